@@ -0,0 +1,58 @@
+// Parser throughput on a large generated source file
+//
+// Written for the standard test harness, like the tokenizer benchmark. Run it
+// as a test of this bench target, with optimizations:
+//
+//   cargo test --release --bench parser -- --nocapture
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use logos::parser::Parser;
+
+/// A program of `units` items: functions with loops, calls, arithmetic and
+/// field access, structs and top-level bindings
+fn synthetic_source(units: usize) -> String {
+    (0..units)
+        .map(|n| {
+            format!(
+                "struct Point{n} {{ x: Int, y: Int }}\nconst LIMIT_{n} = {n}\n\
+                 fn step_{n}(p: Point{n}, ratio: Float) -> Int {{\n    let total = p.x * {n} + p.y - 3\n    \
+                 while total < LIMIT_{n} {{ print(\"step {n}\", total, ratio) }}\n    \
+                 if total > 0 {{ total }} else {{ 0 - total }}\n}}\nlet result_{n} = step_{n}(Point{n} {{ x: 1, y: 2 }}, 0.5)\n",
+                n = n
+            )
+        })
+        .collect()
+}
+
+/// Runs `parse` `rounds` times and returns the mean time per run in seconds
+fn time(rounds: usize, mut parse: impl FnMut()) -> f64 {
+    let started = Instant::now();
+    for _ in 0..rounds {
+        parse();
+    }
+    started.elapsed().as_secs_f64() / rounds as f64
+}
+
+#[test]
+fn parse_large_file() {
+    let source = synthetic_source(5_000);
+    let rounds = 5;
+
+    // One untimed pass checks the source parses and warms the allocator
+    let expected = Parser::new(&source).parse_program().unwrap().statements.len();
+
+    let seconds = time(rounds, || {
+        let program = Parser::new(black_box(&source)).parse_program().unwrap();
+        assert_eq!(program.statements.len(), expected);
+    });
+
+    println!(
+        "parser: {} items from {} bytes in {:.1}ms ({:.1} MB/s)",
+        expected,
+        source.len(),
+        seconds * 1000.0,
+        source.len() as f64 / seconds / 1e6
+    );
+}
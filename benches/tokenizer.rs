@@ -0,0 +1,52 @@
+// Tokenizer throughput on a large generated source file
+//
+// Written for the standard test harness, so it needs no extra dependency or
+// manifest entry. Stable `cargo bench` only runs `#[bench]` functions, so run
+// it as a test of this bench target, with optimizations:
+//
+//   cargo test --release --bench tokenizer -- --nocapture
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use logos::lexer::{Lexer, Token};
+
+/// A program of `units` functions mixing names, keywords, numbers, strings with
+/// and without escapes, ranges, labels, comments and non-ASCII identifiers
+fn synthetic_source(units: usize) -> String {
+    (0..units)
+        .map(|n| {
+            format!(
+                "// unit {n}\nfn step_{n}(x: Int, ratio: Float) -> String {{\n    let total_{n} = x * {n} + 3.25\n    \
+                 for i in 0..{n} {{ print(\"plain {n}\") }}\n    let 名前 = \"tab\\there \\\"quoted\\\"\"\n    \
+                 'outer: while ok {{ break 'outer }}\n    return 'c'\n}}\n",
+                n = n
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn tokenize_large_file() {
+    let source = synthetic_source(20_000);
+    let rounds = 5;
+
+    // One untimed pass warms the allocator and caches
+    let expected = Lexer::new(&source).tokenize();
+    assert!(!expected.iter().any(|token| matches!(token, Token::Error(_))));
+
+    let started = Instant::now();
+    for _ in 0..rounds {
+        let tokens = Lexer::new(black_box(&source)).tokenize();
+        assert_eq!(tokens.len(), expected.len());
+    }
+    let seconds = started.elapsed().as_secs_f64() / rounds as f64;
+
+    println!(
+        "tokenizer: {} tokens from {} bytes in {:.1}ms ({:.1} MB/s)",
+        expected.len(),
+        source.len(),
+        seconds * 1000.0,
+        source.len() as f64 / seconds / 1e6
+    );
+}
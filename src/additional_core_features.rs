@@ -6,6 +6,7 @@ use crate::ast::*;
 /// Advanced type system features
 pub mod type_system {
     use crate::ast::*;
+    use crate::runtime::{runtime_type_name, value_has_type, Runtime, Value};
     
     /// Dependent types implementation
     pub struct DependentTypeSystem {
@@ -13,6 +14,8 @@ pub mod type_system {
         context: Vec<(String, Type)>,
         /// Type-level computation environment
         type_context: Vec<(String, Type)>,
+        /// What has been worked out so far about the types left to inference
+        unifier: Unifier,
     }
 
     impl DependentTypeSystem {
@@ -21,11 +24,14 @@ pub mod type_system {
             Self {
                 context: Vec::new(),
                 type_context: Vec::new(),
+                unifier: Unifier::new(),
             }
         }
 
-        /// Add a variable to the context with its type
+        /// Add a variable to the context with its type; parts left as `Type::Infer` are
+        /// filled in by how the variable is used later
         pub fn add_to_context(&mut self, name: String, ty: Type) -> Result<(), String> {
+            let ty = self.unifier.instantiate(&ty);
             self.context.push((name.clone(), ty.clone()));
 
             // If this is a type-level variable, also add to type context
@@ -92,6 +98,25 @@ pub mod type_system {
             }
         }
 
+        /// Checks a statement, adding what a `let` or `const` binds to the context
+        ///
+        /// A binding without an annotation takes its value's type; with one, the two are
+        /// unified, so an annotation like `[_]` is completed from the value.
+        pub fn check_statement(&mut self, statement: &Statement) -> Result<(), String> {
+            match statement {
+                Statement::LetBinding { name, type_annotation, value, .. }
+                | Statement::ConstBinding { name, type_annotation, value } => {
+                    let value_type = self.infer_type(value)?;
+                    let declared = type_annotation.clone().unwrap_or(Type::Infer);
+                    let ty = self.unifier.unify(&declared, &value_type)
+                        .map_err(|e| format!("{} for variable '{}'", e, name))?;
+                    self.add_to_context(name.clone(), ty)
+                },
+                Statement::Expression(expr) => self.infer_type(expr).map(|_| ()),
+                _ => Ok(()),
+            }
+        }
+
         /// Check a general type in the current context
         fn check_type_in_context(&mut self, ty: &Type, expected: &[Type]) -> Result<(), String> {
             match ty {
@@ -144,6 +169,16 @@ pub mod type_system {
 
                 Type::Infer => Ok(()),
 
+                // Trait objects are checked against implementations by the type checker
+                Type::Dyn(_) => Ok(()),
+
+                Type::Map(key, value) => {
+                    self.check_type_in_context(key, &[])?;
+                    self.check_type_in_context(value, &[])
+                },
+
+                Type::Set(element) => self.check_type_in_context(element, &[]),
+
                 // Dependent types - already handled in check_dependent_type
                 Type::Pi(_, _) | Type::Sigma(_, _) | Type::Universe(_) | Type::Equality(_, _, _) => {
                     Ok(())
@@ -174,16 +209,16 @@ pub mod type_system {
                 Expression::Nil => Ok(Type::Unit),
 
                 Expression::Identifier(name) => {
-                    // Look up the type in our context
-                    for (var_name, var_type) in &self.context {
+                    // Look up the type in our context, latest binding first so shadowing works
+                    for (var_name, var_type) in self.context.iter().rev() {
                         if var_name == name {
-                            return Ok(var_type.clone());
+                            return Ok(self.unifier.resolve(var_type));
                         }
                     }
                     Err(format!("Unknown variable: {}", name))
                 },
 
-                Expression::BinaryOp(left, op, right) => {
+                Expression::BinaryOp(left, op, right, _) => {
                     let left_ty = self.infer_type(left)?;
                     let right_ty = self.infer_type(right)?;
 
@@ -197,6 +232,8 @@ pub mod type_system {
                                 } else {
                                     Ok(Type::Int)
                                 }
+                            } else if let Some(ty) = self.unify_numeric(&left_ty, &right_ty) {
+                                Ok(ty)
                             } else {
                                 Err(format!(
                                     "Operator {:?} requires numeric operands, found {:?} and {:?}",
@@ -224,30 +261,18 @@ pub mod type_system {
             }
         }
 
-        /// Check if two types are equal
-        fn types_equal(&self, ty1: &Type, ty2: &Type) -> bool {
-            match (ty1, ty2) {
-                (Type::Int, Type::Int) => true,
-                (Type::Float, Type::Float) => true,
-                (Type::Bool, Type::Bool) => true,
-                (Type::String, Type::String) => true,
-                (Type::Unit, Type::Unit) => true,
-                (Type::Array(a), Type::Array(b)) => self.types_equal(a, b),
-                (Type::Tuple(items1), Type::Tuple(items2)) => {
-                    if items1.len() != items2.len() {
-                        false
-                    } else {
-                        items1.iter().zip(items2.iter()).all(|(a, b)| self.types_equal(a, b))
-                    }
-                },
-                (Type::Named(n1), Type::Named(n2)) => n1 == n2,
-                (Type::Generic(g1), Type::Generic(g2)) => g1 == g2,
-                (Type::Option(o1), Type::Option(o2)) => self.types_equal(o1, o2),
-                (Type::Result(ok1, err1), Type::Result(ok2, err2)) => {
-                    self.types_equal(ok1, ok2) && self.types_equal(err1, err2)
-                },
-                (Type::Infer, _) | (_, Type::Infer) => true, // Infer matches anything
-                _ => false,
+        /// Check if two types are equal, or can be made equal by inferring what's still open
+        fn types_equal(&mut self, ty1: &Type, ty2: &Type) -> bool {
+            self.unifier.unify(ty1, ty2).is_ok()
+        }
+
+        /// The type of an arithmetic operation where an operand's type is still being inferred
+        fn unify_numeric(&mut self, left: &Type, right: &Type) -> Option<Type> {
+            let ty = self.unifier.unify(left, right).ok()?;
+            if self.is_numeric_type(&ty) || Unifier::is_variable(&ty) {
+                Some(ty)
+            } else {
+                None
             }
         }
 
@@ -262,6 +287,160 @@ pub mod type_system {
         }
     }
     
+    /// Solves equations between types, Hindley-Milner style
+    ///
+    /// Every `Type::Infer` handed to the unifier becomes a fresh inference variable,
+    /// written as a generic named `?0`, `?1`, ... so it can't clash with a type
+    /// parameter from source. Unifying binds variables in a substitution, and the
+    /// result is the most general type both sides can be.
+    #[derive(Debug, Clone, Default)]
+    pub struct Unifier {
+        substitution: std::collections::HashMap<String, Type>,
+        next_variable: u32,
+    }
+
+    impl Unifier {
+        /// Create a unifier with no variables yet
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// A new inference variable, bound to nothing
+        pub fn fresh(&mut self) -> Type {
+            let variable = Type::Generic(format!("?{}", self.next_variable));
+            self.next_variable += 1;
+            variable
+        }
+
+        /// Whether a type is an inference variable
+        pub fn is_variable(ty: &Type) -> bool {
+            matches!(ty, Type::Generic(name) if name.starts_with('?'))
+        }
+
+        /// Makes `a` and `b` the same type, or explains why they can't be
+        ///
+        /// A failed unification leaves the substitution as it was.
+        pub fn unify(&mut self, a: &Type, b: &Type) -> Result<Type, String> {
+            let a = self.instantiate(a);
+            let b = self.instantiate(b);
+            let saved = self.substitution.clone();
+            match self.unify_variables(&a, &b) {
+                Ok(()) => Ok(self.resolve(&a)),
+                Err(e) => {
+                    self.substitution = saved;
+                    Err(e)
+                },
+            }
+        }
+
+        /// Applies everything learned so far; variables still unbound stay as they are
+        pub fn resolve(&self, ty: &Type) -> Type {
+            self.map_parts(ty, &mut |ty| match ty {
+                Type::Generic(name) if Unifier::is_variable(ty) => self.substitution.get(name).map(|bound| self.resolve(bound)),
+                _ => None,
+            })
+        }
+
+        /// Replaces each `Type::Infer` in a type with its own fresh variable
+        pub(crate) fn instantiate(&mut self, ty: &Type) -> Type {
+            let mut instantiated = ty.clone();
+            self.fill_holes(&mut instantiated);
+            instantiated
+        }
+
+        fn fill_holes(&mut self, ty: &mut Type) {
+            match ty {
+                Type::Infer => *ty = self.fresh(),
+                Type::Array(inner) | Type::Option(inner) | Type::Set(inner) | Type::Channel(inner) | Type::Linear(inner) => self.fill_holes(inner),
+                Type::Result(first, second) | Type::Map(first, second) => {
+                    self.fill_holes(first);
+                    self.fill_holes(second);
+                },
+                Type::Tuple(items) => items.iter_mut().for_each(|item| self.fill_holes(item)),
+                Type::Function(params, ret) => {
+                    params.iter_mut().for_each(|param| self.fill_holes(param));
+                    self.fill_holes(ret);
+                },
+                _ => {},
+            }
+        }
+
+        /// Rebuilds a type, letting `replace` substitute any part of it
+        fn map_parts(&self, ty: &Type, replace: &mut dyn FnMut(&Type) -> Option<Type>) -> Type {
+            if let Some(replaced) = replace(ty) {
+                return replaced;
+            }
+            let mut part = |ty: &Type| Box::new(self.map_parts(ty, replace));
+            match ty {
+                Type::Array(inner) => Type::Array(part(inner)),
+                Type::Option(inner) => Type::Option(part(inner)),
+                Type::Set(inner) => Type::Set(part(inner)),
+                Type::Channel(inner) => Type::Channel(part(inner)),
+                Type::Linear(inner) => Type::Linear(part(inner)),
+                Type::Result(ok, err) => Type::Result(part(ok), part(err)),
+                Type::Map(key, value) => Type::Map(part(key), part(value)),
+                Type::Tuple(items) => Type::Tuple(items.iter().map(|item| *part(item)).collect()),
+                Type::Function(params, ret) => Type::Function(params.iter().map(|param| *part(param)).collect(), part(ret)),
+                other => other.clone(),
+            }
+        }
+
+        /// Follows a bound variable to what it stands for, one level at a time
+        fn shallow(&self, ty: &Type) -> Type {
+            let mut ty = ty.clone();
+            while let Type::Generic(name) = &ty {
+                match self.substitution.get(name) {
+                    Some(bound) => ty = bound.clone(),
+                    None => break,
+                }
+            }
+            ty
+        }
+
+        fn unify_variables(&mut self, a: &Type, b: &Type) -> Result<(), String> {
+            let (a, b) = (self.shallow(a), self.shallow(b));
+            match (&a, &b) {
+                (Type::Generic(x), Type::Generic(y)) if x == y => Ok(()),
+                (Type::Generic(variable), other) | (other, Type::Generic(variable)) if variable.starts_with('?') => {
+                    let other = self.resolve(other);
+                    if self.occurs(variable, &other) {
+                        return Err(format!("Infinite type: {} occurs in {}", variable, other));
+                    }
+                    self.substitution.insert(variable.clone(), other);
+                    Ok(())
+                },
+                (Type::Array(x), Type::Array(y))
+                | (Type::Option(x), Type::Option(y))
+                | (Type::Set(x), Type::Set(y))
+                | (Type::Channel(x), Type::Channel(y))
+                | (Type::Linear(x), Type::Linear(y)) => self.unify_variables(x, y),
+                (Type::Result(x1, x2), Type::Result(y1, y2)) | (Type::Map(x1, x2), Type::Map(y1, y2)) => {
+                    self.unify_variables(x1, y1)?;
+                    self.unify_variables(x2, y2)
+                },
+                (Type::Tuple(xs), Type::Tuple(ys)) if xs.len() == ys.len() => {
+                    xs.iter().zip(ys).try_for_each(|(x, y)| self.unify_variables(x, y))
+                },
+                (Type::Function(xs, x), Type::Function(ys, y)) if xs.len() == ys.len() => {
+                    xs.iter().zip(ys).try_for_each(|(x, y)| self.unify_variables(x, y))?;
+                    self.unify_variables(x, y)
+                },
+                (x, y) if x == y => Ok(()),
+                (x, y) => Err(format!("Type mismatch: {} is not {}", self.resolve(x), self.resolve(y))),
+            }
+        }
+
+        /// Whether a variable appears inside a type, which would make binding it infinite
+        fn occurs(&self, variable: &str, ty: &Type) -> bool {
+            let mut found = false;
+            self.map_parts(ty, &mut |part| {
+                found |= matches!(part, Type::Generic(name) if name == variable);
+                None
+            });
+            found
+        }
+    }
+
     /// Linear types implementation
     pub struct LinearTypeSystem {
         /// Resource usage tracking
@@ -321,7 +500,7 @@ pub mod type_system {
                     }
                     Ok(())
                 },
-                Expression::BinaryOp(left, _, right) => {
+                Expression::BinaryOp(left, _, right, _) => {
                     self.check_linear_usage(left)?;
                     self.check_linear_usage(right)?;
                     Ok(())
@@ -330,12 +509,33 @@ pub mod type_system {
                     self.check_linear_usage(operand)?;
                     Ok(())
                 },
-                Expression::Call(_, args) => {
+                Expression::Call(_, args) | Expression::Array(args) | Expression::Tuple(args) => {
+                    for arg in args {
+                        self.check_linear_usage(arg)?;
+                    }
+                    Ok(())
+                },
+                Expression::MethodCall(receiver, _, args) => {
+                    // Calling a method hands the receiver over, like passing it to a function
+                    self.check_linear_usage(receiver)?;
                     for arg in args {
                         self.check_linear_usage(arg)?;
                     }
                     Ok(())
                 },
+                Expression::Struct(_, fields) => {
+                    for (_, value) in fields {
+                        self.check_linear_usage(value)?;
+                    }
+                    Ok(())
+                },
+                Expression::FieldAccess(inner, _) | Expression::Propagate(inner) => self.check_linear_usage(inner),
+                Expression::Block(statements) | Expression::BlockExpr(statements) => {
+                    for stmt in statements {
+                        self.check_linear_statement(stmt)?;
+                    }
+                    Ok(())
+                },
                 Expression::If(condition, then_branch, else_branch) => {
                     self.check_linear_usage(condition)?;
 
@@ -344,9 +544,11 @@ pub mod type_system {
                     let mut then_checker = LinearTypeSystem::new();
                     let mut else_checker = LinearTypeSystem::new();
 
-                    // Copy linear vars to both checkers
+                    // Both branches start from what has been used so far
                     then_checker.linear_vars = self.linear_vars.clone();
                     else_checker.linear_vars = self.linear_vars.clone();
+                    then_checker.resource_usage = self.resource_usage.clone();
+                    else_checker.resource_usage = self.resource_usage.clone();
 
                     // Check then branch
                     for stmt in then_branch {
@@ -373,6 +575,7 @@ pub mod type_system {
                     for (pattern, guard, body) in arms {
                         let mut arm_checker = LinearTypeSystem::new();
                         arm_checker.linear_vars = self.linear_vars.clone();
+                        arm_checker.resource_usage = self.resource_usage.clone();
 
                         // Bind variables from pattern
                         self.bind_pattern_variables(pattern, &mut arm_checker)?;
@@ -407,7 +610,7 @@ pub mod type_system {
         }
 
         /// Check linear usage in a statement
-        fn check_linear_statement(&mut self, stmt: &Statement) -> Result<(), String> {
+        pub fn check_linear_statement(&mut self, stmt: &Statement) -> Result<(), String> {
             match stmt {
                 Statement::Expression(expr) => self.check_linear_usage(expr),
                 Statement::LetBinding { name, value, type_annotation, .. } => {
@@ -545,6 +748,12 @@ pub mod type_system {
                 let then_status = then_checker.resource_usage.get(var_name);
                 let else_status = else_checker.resource_usage.get(var_name);
 
+                // Neither branch touched it
+                let before = self.resource_usage.get(var_name);
+                if then_status == before && else_status == before {
+                    continue;
+                }
+
                 match (then_status, else_status) {
                     // If consumed in both branches, that's an error (double consumption)
                     (Some(ResourceStatus::Consumed), Some(ResourceStatus::Consumed)) => {
@@ -604,15 +813,21 @@ pub mod type_system {
     }
     
     /// Algebraic effect system implementation
+    ///
+    /// Handlers nest lexically: each `push_frame` opens a scope whose handlers shadow
+    /// those of the frames around it, and an effect resolves to the innermost handler
+    /// for it, so the answer never depends on hash order.
     pub struct EffectSystem {
-        /// Active effects in the current scope
-        active_effects: std::collections::HashSet<String>,
-        /// Effect handlers
+        /// Active effects in the current scope, kept sorted so listings are stable
+        active_effects: std::collections::BTreeSet<String>,
+        /// Effect handlers installed in the current (innermost) frame
         handlers: std::collections::HashMap<String, EffectHandler>,
-        /// Effect stack for nested effect handling
+        /// Enclosing frames, outermost first
         effect_stack: Vec<EffectFrame>,
-        /// Effect typing context
-        effect_context: std::collections::HashMap<String, EffectSignature>,
+        /// Effect typing context: the operations of each effect
+        effect_context: std::collections::HashMap<String, Vec<EffectSignature>>,
+        /// Effects each function declares, which a call to it performs
+        function_effects: std::collections::HashMap<String, Vec<String>>,
     }
 
     #[derive(Debug, Clone)]
@@ -625,7 +840,7 @@ pub mod type_system {
     #[derive(Debug, Clone)]
     pub struct EffectFrame {
         /// Effects available in this frame
-        available_effects: std::collections::HashSet<String>,
+        available_effects: std::collections::BTreeSet<String>,
         /// Handlers in this frame
         handlers: std::collections::HashMap<String, EffectHandler>,
     }
@@ -642,10 +857,11 @@ pub mod type_system {
         /// Create a new effect system
         pub fn new() -> Self {
             Self {
-                active_effects: std::collections::HashSet::new(),
+                active_effects: std::collections::BTreeSet::new(),
                 handlers: std::collections::HashMap::new(),
                 effect_stack: vec![],
                 effect_context: std::collections::HashMap::new(),
+                function_effects: std::collections::HashMap::new(),
             }
         }
 
@@ -657,7 +873,8 @@ pub mod type_system {
             };
             self.effect_stack.push(current_frame);
 
-            // Clear current effects and handlers for the new frame
+            // The new frame starts empty; the saved frame's handlers stay reachable
+            // through `resolve_handler` until they are shadowed
             self.active_effects.clear();
             self.handlers.clear();
         }
@@ -673,9 +890,28 @@ pub mod type_system {
             }
         }
 
-        /// Register an effect signature
+        /// Register an effect signature, adding an operation to the effect
         pub fn register_effect_signature(&mut self, effect_name: String, signature: EffectSignature) {
-            self.effect_context.insert(effect_name, signature);
+            let operations = self.effect_context.entry(effect_name).or_default();
+            operations.retain(|existing| existing.operation_name != signature.operation_name);
+            operations.push(signature);
+        }
+
+        /// Records the effects a function declares, so calls to it count as performing them
+        pub fn declare_function_effects(&mut self, function_name: String, effects: Vec<String>) {
+            self.function_effects.insert(function_name, effects);
+        }
+
+        /// The effects performed by calling `name`: the effect it is an operation of, or
+        /// the effects the function declares
+        fn effects_of_call(&self, name: &str) -> Vec<String> {
+            let mut effects: Vec<String> = self.effect_context.iter()
+                .filter(|(_, operations)| operations.iter().any(|op| op.operation_name == name))
+                .map(|(effect, _)| effect.clone())
+                .collect();
+            effects.sort();
+            effects.extend(self.function_effects.get(name).into_iter().flatten().cloned());
+            effects
         }
 
         /// Register an effect handler
@@ -683,14 +919,21 @@ pub mod type_system {
             self.handlers.insert(effect_name, handler);
         }
 
-        /// Check if an effect is handled
+        /// Finds the handler for an effect, searching from the innermost frame outwards
+        pub fn resolve_handler(&self, effect_name: &str) -> Option<&EffectHandler> {
+            self.handlers.get(effect_name).or_else(|| {
+                self.effect_stack.iter().rev().find_map(|frame| frame.handlers.get(effect_name))
+            })
+        }
+
+        /// Check if an effect is handled in this frame or any enclosing one
         pub fn is_effect_handled(&self, effect_name: &str) -> bool {
-            self.handlers.contains_key(effect_name)
+            self.resolve_handler(effect_name).is_some()
         }
 
-        /// Process an effect operation
+        /// Process an effect operation with the innermost handler for its effect
         pub fn process_operation(&mut self, effect_name: &str, op_name: &str) -> Result<(), String> {
-            if let Some(handler) = self.handlers.get(effect_name) {
+            if let Some(handler) = self.resolve_handler(effect_name) {
                 if handler.operations.contains_key(op_name) {
                     Ok(())
                 } else {
@@ -701,33 +944,63 @@ pub mod type_system {
             }
         }
 
-        /// Perform an effect operation
-        pub fn perform(&mut self, effect_name: &str, op_name: &str, args: &[Expression]) -> Result<Expression, String> {
+        /// Perform an effect operation by running the innermost handler's implementation of it
+        ///
+        /// The arguments are evaluated in `runtime`, and the handler's operation runs with the
+        /// performing code's bindings in scope. Its result is the value the `perform` resumes
+        /// with; each perform resumes exactly once, so a handler that keeps state between
+        /// operations (a `State` effect's current value, a logger's buffer) holds it in a `Cell`.
+        pub fn perform(&self, runtime: &mut Runtime, effect_name: &str, op_name: &str, args: &[Expression]) -> Result<Value, String> {
             // Check if the effect is active
             if !self.active_effects.contains(effect_name) {
                 return Err(format!("Effect '{}' is not active in current scope", effect_name));
             }
 
             // Check if the operation is valid for this effect
-            if let Some(signature) = self.effect_context.get(effect_name) {
-                if signature.operation_name != op_name {
-                    return Err(format!("Operation '{}' is not valid for effect '{}'", op_name, effect_name));
-                }
+            let operations = self.effect_context.get(effect_name)
+                .ok_or_else(|| format!("Unknown effect: '{}'", effect_name))?;
+            let signature = operations.iter().find(|op| op.operation_name == op_name)
+                .ok_or_else(|| format!("Operation '{}' is not valid for effect '{}'", op_name, effect_name))?;
 
-                // Check argument types match signature
-                if args.len() != signature.parameter_types.len() {
+            // Check argument types match signature
+            if args.len() != signature.parameter_types.len() {
+                return Err(format!(
+                    "Effect operation '{}' expects {} arguments, got {}",
+                    op_name, signature.parameter_types.len(), args.len()
+                ));
+            }
+
+            let handler = self.resolve_handler(effect_name)
+                .ok_or_else(|| format!("No handler registered for effect {}", effect_name))?;
+            let operation = handler.operations.get(op_name)
+                .ok_or_else(|| format!("Operation {} not handled for effect {}", op_name, effect_name))?;
+
+            let values = args.iter()
+                .map(|arg| runtime.eval_expression(arg))
+                .collect::<Result<Vec<_>, _>>()?;
+            for (value, expected) in values.iter().zip(&signature.parameter_types) {
+                if !value_has_type(value, expected) {
                     return Err(format!(
-                        "Effect operation '{}' expects {} arguments, got {}",
-                        op_name, signature.parameter_types.len(), args.len()
+                        "Effect operation '{}.{}' expects {}, got {}",
+                        effect_name, op_name, expected, runtime_type_name(value)
                     ));
                 }
+            }
 
-                // In a real implementation, we would type-check the arguments against the signature
-                // For now, we'll just return a placeholder expression
-                Ok(Expression::Nil)
-            } else {
-                Err(format!("Unknown effect: '{}'", effect_name))
+            let implementation = Value::Function(
+                operation.name.clone(),
+                operation.parameters.clone(),
+                operation.body.clone(),
+                runtime.env.clone(),
+            );
+            let result = runtime.call_function(implementation, values)?;
+            if !value_has_type(&result, &signature.return_type) {
+                return Err(format!(
+                    "Handler for '{}.{}' returned {} where {} was expected",
+                    effect_name, op_name, runtime_type_name(&result), signature.return_type
+                ));
             }
+            Ok(result)
         }
 
         /// Activate an effect in the current scope
@@ -740,7 +1013,7 @@ pub mod type_system {
             self.active_effects.remove(effect_name);
         }
 
-        /// Get all active effects
+        /// Get all active effects, in name order
         pub fn get_active_effects(&self) -> Vec<String> {
             self.active_effects.iter().cloned().collect()
         }
@@ -751,13 +1024,28 @@ pub mod type_system {
 
             match expr {
                 Expression::Call(name, args) => {
-                    // Check if this is a call to an effect operation
-                    // This would require looking up in the effect context
+                    effects_found.extend(self.effects_of_call(name));
                     for arg in args {
                         effects_found.extend(self.check_effects(arg)?);
                     }
                 },
-                Expression::BinaryOp(left, _, right) => {
+                Expression::MethodCall(receiver, _, args) => {
+                    effects_found.extend(self.check_effects(receiver)?);
+                    for arg in args {
+                        effects_found.extend(self.check_effects(arg)?);
+                    }
+                },
+                Expression::Array(items) | Expression::Tuple(items) => {
+                    for item in items {
+                        effects_found.extend(self.check_effects(item)?);
+                    }
+                },
+                Expression::Block(statements) | Expression::BlockExpr(statements) => {
+                    for stmt in statements {
+                        effects_found.extend(self.check_effects_in_statement(stmt)?);
+                    }
+                },
+                Expression::BinaryOp(left, _, right, _) => {
                     effects_found.extend(self.check_effects(left)?);
                     effects_found.extend(self.check_effects(right)?);
                 },
@@ -796,7 +1084,7 @@ pub mod type_system {
         }
 
         /// Check effects in a statement
-        fn check_effects_in_statement(&self, stmt: &Statement) -> Result<Vec<String>, String> {
+        pub fn check_effects_in_statement(&self, stmt: &Statement) -> Result<Vec<String>, String> {
             let mut effects_found = Vec::new();
 
             match stmt {
@@ -808,9 +1096,15 @@ pub mod type_system {
                         effects_found.extend(self.check_effects_in_statement(stmt)?);
                     }
                 },
-                Statement::LetBinding { value, .. } => {
+                Statement::LetBinding { value, .. } | Statement::ConstBinding { value, .. } => {
                     effects_found.extend(self.check_effects(value)?);
                 },
+                Statement::While { condition, body, .. } => {
+                    effects_found.extend(self.check_effects(condition)?);
+                    for stmt in body {
+                        effects_found.extend(self.check_effects_in_statement(stmt)?);
+                    }
+                },
                 Statement::Return(expr) => {
                     if let Some(return_expr) = expr {
                         effects_found.extend(self.check_effects(return_expr)?);
@@ -827,6 +1121,7 @@ pub mod type_system {
 /// Pattern matching enhancements
 pub mod pattern_matching {
     use crate::ast::*;
+    use crate::runtime::floats_equal;
 
     /// Advanced pattern matching with guards and or-patterns
     pub struct AdvancedPatternMatcher {
@@ -887,7 +1182,7 @@ pub mod pattern_matching {
         pub fn match_pattern(&mut self, value: &Value, pattern: &Pattern) -> Result<bool, String> {
             match (value, pattern) {
                 (Value::Integer(v), Pattern::Literal(Expression::Integer(p))) => Ok(v == p),
-                (Value::Float(v), Pattern::Literal(Expression::Float(p))) => Ok((v - p).abs() < f64::EPSILON),
+                (Value::Float(v), Pattern::Literal(Expression::Float(p))) => Ok(floats_equal(*v, *p)),
                 (Value::String(v), Pattern::Literal(Expression::String(p))) => Ok(v == p),
                 (Value::Boolean(v), Pattern::Literal(Expression::Boolean(p))) => Ok(v == p),
                 (Value::Unit, Pattern::Literal(Expression::Nil)) => Ok(true),
@@ -966,7 +1261,7 @@ pub mod pattern_matching {
                     }
                 },
                 // Range patterns: e.g., 1..=5
-                (Value::Integer(v), Pattern::Literal(Expression::BinaryOp(left, BinaryOp::Range, right))) => {
+                (Value::Integer(v), Pattern::Literal(Expression::BinaryOp(left, BinaryOp::Range, right, _))) => {
                     if let (Expression::Integer(start), Expression::Integer(end)) = (left.as_ref(), right.as_ref()) {
                         Ok(v >= start && v <= end)
                     } else {
@@ -986,7 +1281,7 @@ pub mod pattern_matching {
         fn compare_values(&self, value: &Value, expr: &Expression) -> Result<bool, String> {
             match (value, expr) {
                 (Value::Integer(v), Expression::Integer(p)) => Ok(v == p),
-                (Value::Float(v), Expression::Float(p)) => Ok((v - p).abs() < f64::EPSILON),
+                (Value::Float(v), Expression::Float(p)) => Ok(floats_equal(*v, *p)),
                 (Value::String(v), Expression::String(p)) => Ok(v == p),
                 (Value::Boolean(v), Expression::Boolean(p)) => Ok(v == p),
                 (Value::Unit, Expression::Nil) => Ok(true),
@@ -998,7 +1293,7 @@ pub mod pattern_matching {
         fn evaluate_guard(&self, expr: &Expression) -> Result<bool, String> {
             // This is a simplified guard evaluator that works with the bound variables
             match expr {
-                Expression::BinaryOp(left, op, right) => {
+                Expression::BinaryOp(left, op, right, _) => {
                     let left_val = self.eval_expression(left)?;
                     let right_val = self.eval_expression(right)?;
 
@@ -1015,13 +1310,15 @@ pub mod pattern_matching {
                             }
                         },
                         (Value::Float(l), Value::Float(r)) => {
+                            // Same rules as the runtime: NaN equals nothing and can't be ordered
+                            let ordering = || l.partial_cmp(&r).ok_or_else(|| "Cannot order NaN".to_string());
                             match op {
-                                BinaryOp::Eq => Ok((l - r).abs() < f64::EPSILON),
-                                BinaryOp::Ne => Ok((l - r).abs() >= f64::EPSILON),
-                                BinaryOp::Lt => Ok(l < r),
-                                BinaryOp::Gt => Ok(l > r),
-                                BinaryOp::Le => Ok(l <= r),
-                                BinaryOp::Ge => Ok(l >= r),
+                                BinaryOp::Eq => Ok(floats_equal(l, r)),
+                                BinaryOp::Ne => Ok(!floats_equal(l, r)),
+                                BinaryOp::Lt => Ok(ordering()?.is_lt()),
+                                BinaryOp::Gt => Ok(ordering()?.is_gt()),
+                                BinaryOp::Le => Ok(ordering()?.is_le()),
+                                BinaryOp::Ge => Ok(ordering()?.is_ge()),
                                 _ => Err(format!("Unsupported operation {:?} for floats", op)),
                             }
                         },
@@ -1084,7 +1381,7 @@ pub mod pattern_matching {
                         None => Err(format!("Unknown variable: {}", name)),
                     }
                 },
-                Expression::BinaryOp(left, op, right) => {
+                Expression::BinaryOp(left, op, right, _) => {
                     let left_val = self.eval_expression(left)?;
                     let right_val = self.eval_expression(right)?;
 
@@ -1641,11 +1938,14 @@ pub mod concurrency {
     use std::sync::mpsc;
     use std::thread;
     use std::collections::HashMap;
-    use std::time::Duration;
+    use std::time::{Duration, Instant};
     use std::sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}};
     use std::future::Future;
     use std::pin::Pin;
 
+    /// How long `ActorSystem::stop_actor` waits for an actor's thread to exit
+    pub const DEFAULT_STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
     /// Actor system implementation
     pub struct ActorSystem {
         /// Registry of actors
@@ -1842,32 +2142,84 @@ pub mod concurrency {
             }
         }
 
-        /// Stop an actor gracefully
+        /// Stop an actor gracefully, giving it `DEFAULT_STOP_TIMEOUT` to finish
         pub fn stop_actor(&mut self, id: &str) -> Result<(), String> {
-            if let Some(handle) = self.actors.get_mut(id) {
-                // Set the stop flag
-                handle.should_stop.store(true, Ordering::Relaxed);
-
-                // Update actor state
-                self.actor_states.insert(id.to_string(), ActorState::Stopping);
-
-                if let Some(thread_handle) = handle.thread_handle.take() {
-                    // Wait for the thread to finish (with timeout)
-                    // Note: join_timeout is not available in stable Rust, so we'll use a different approach
-                    // We'll send a termination message and then join without timeout
-                    // In a real implementation, we'd have a more sophisticated timeout mechanism
-                    let _ = thread_handle.join().map_err(|_| "Actor thread panicked".to_string());
-
-                    // Remove from registry
-                    self.actors.remove(id);
-                    self.message_queues.remove(id);
+            self.stop_actor_within(id, DEFAULT_STOP_TIMEOUT)
+        }
+
+        /// Stop an actor, waiting at most `timeout` for its thread to exit
+        ///
+        /// An actor still busy in `handle_message` when the timeout runs out is
+        /// marked `Failed` and its thread is detached rather than joined.
+        pub fn stop_actor_within(&mut self, id: &str, timeout: Duration) -> Result<(), String> {
+            let stopped = self.signal_stop(id)?;
+            self.await_stop(id, stopped, Instant::now() + timeout, timeout)
+        }
+
+        /// Stop every actor at once, all sharing a single `timeout`
+        ///
+        /// Each actor ends up `Stopped` or, if it overran the timeout, `Failed`.
+        /// The error names every actor that timed out.
+        pub fn stop_all(&mut self, timeout: Duration) -> Result<(), String> {
+            let mut ids: Vec<String> = self.actors.keys().cloned().collect();
+            ids.sort();
+
+            let mut pending = Vec::new();
+            for id in ids {
+                if let Ok(stopped) = self.signal_stop(&id) {
+                    pending.push((id, stopped));
+                }
+            }
+
+            let deadline = Instant::now() + timeout;
+            let timed_out: Vec<String> = pending
+                .into_iter()
+                .filter_map(|(id, stopped)| self.await_stop(&id, stopped, deadline, timeout).err().map(|_| id))
+                .collect();
+
+            if timed_out.is_empty() {
+                Ok(())
+            } else {
+                Err(format!("Actors did not stop within {:?}: {}", timeout, timed_out.join(", ")))
+            }
+        }
+
+        /// Raises the stop flag, closes the mailbox and hands the thread to a
+        /// watchdog, which reports on the returned channel once the thread exits
+        fn signal_stop(&mut self, id: &str) -> Result<mpsc::Receiver<()>, String> {
+            let handle = self.actors.get_mut(id).ok_or_else(|| format!("Actor {} not found", id))?;
+            let thread_handle = handle.thread_handle.take().ok_or_else(|| "Actor thread already stopped".to_string())?;
+            handle.should_stop.store(true, Ordering::Relaxed);
+
+            // Dropping the only sender wakes an idle actor with `Disconnected`
+            self.message_queues.remove(id);
+            self.actor_states.insert(id.to_string(), ActorState::Stopping);
+
+            let (done, stopped) = mpsc::channel();
+            thread::spawn(move || {
+                // A panicking actor has stopped all the same
+                let _ = thread_handle.join();
+                let _ = done.send(());
+            });
+            Ok(stopped)
+        }
+
+        /// Waits until `deadline` for the watchdog of a signalled actor
+        fn await_stop(&mut self, id: &str, stopped: mpsc::Receiver<()>, deadline: Instant, timeout: Duration) -> Result<(), String> {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            self.actors.remove(id);
+
+            match stopped.recv_timeout(remaining) {
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    let reason = format!("did not stop within {:?}", timeout);
+                    eprintln!("Actor {} {}; abandoning its thread", id, reason);
+                    self.actor_states.insert(id.to_string(), ActorState::Failed(reason.clone()));
+                    Err(format!("Actor {} {}", id, reason))
+                }
+                _ => {
                     self.actor_states.insert(id.to_string(), ActorState::Stopped);
                     Ok(())
-                } else {
-                    Err("Actor thread already stopped".to_string())
                 }
-            } else {
-                Err(format!("Actor {} not found", id))
             }
         }
 
@@ -2050,11 +2402,47 @@ pub mod trait_system_enhancements {
                 return Err(format!("Trait '{}' not found", impl_def.trait_name));
             }
 
+            // Every supertrait has to be implemented for the type first
+            let missing: Vec<String> = self.traits[&impl_def.trait_name].super_traits.iter()
+                .filter(|super_trait| !self.implements_trait(&impl_def.for_type, super_trait))
+                .map(|super_trait| format!("'{}'", super_trait))
+                .collect();
+            if !missing.is_empty() {
+                return Err(format!(
+                    "Type '{}' must implement supertrait{} {} before implementing '{}'",
+                    impl_def.for_type,
+                    if missing.len() == 1 { "" } else { "s" },
+                    missing.join(", "),
+                    impl_def.trait_name
+                ));
+            }
+
+            // Associated types without a default have to be given, and only declared ones may be
+            let trait_def = &self.traits[&impl_def.trait_name];
+            if let Some((name, _)) = impl_def.associated_types.iter()
+                .find(|(name, _)| !trait_def.associated_types.iter().any(|declared| declared.name == *name)) {
+                return Err(format!("Associated type '{}' is not a member of trait '{}'", name, impl_def.trait_name));
+            }
+            let missing: Vec<String> = trait_def.associated_types.iter()
+                .filter(|declared| declared.default.is_none())
+                .filter(|declared| !impl_def.associated_types.iter().any(|(name, _)| *name == declared.name))
+                .map(|declared| format!("'{}'", declared.name))
+                .collect();
+            if !missing.is_empty() {
+                return Err(format!(
+                    "Implementation of '{}' for '{}' is missing associated type{} {}",
+                    impl_def.trait_name,
+                    impl_def.for_type,
+                    if missing.len() == 1 { "" } else { "s" },
+                    missing.join(", ")
+                ));
+            }
+
             // Check coherence - no overlapping implementations
-            if self.coherence_checker.has_overlapping_impl(&impl_def, &self.implementations) {
+            if let Some(existing) = self.coherence_checker.find_overlapping_impl(&impl_def, &self.implementations) {
                 return Err(format!(
-                    "Overlapping implementation for trait '{}' on type '{}'",
-                    impl_def.trait_name, impl_def.for_type
+                    "Conflicting implementations of trait '{}': `{}` overlaps `{}`",
+                    impl_def.trait_name, describe_impl(&impl_def), describe_impl(existing)
                 ));
             }
 
@@ -2068,7 +2456,25 @@ pub mod trait_system_enhancements {
         }
 
         /// Resolve a method call on a type implementing a trait
+        ///
+        /// Methods of the trait's supertraits are visible too, searched after the trait's own.
         pub fn resolve_trait_method(&self, type_name: &str, trait_name: &str, method_name: &str) -> Option<&FunctionDef> {
+            self.resolve_with_supertraits(type_name, trait_name, method_name, &mut Vec::new())
+        }
+
+        /// `resolve_trait_method`, skipping traits already searched so supertrait cycles end
+        fn resolve_with_supertraits(
+            &self,
+            type_name: &str,
+            trait_name: &str,
+            method_name: &str,
+            searched: &mut Vec<String>,
+        ) -> Option<&FunctionDef> {
+            if searched.iter().any(|name| name == trait_name) {
+                return None;
+            }
+            searched.push(trait_name.to_string());
+
             if let Some(implementations) = self.implementations.get(trait_name) {
                 for implementation in implementations {
                     if implementation.for_type == type_name {
@@ -2079,12 +2485,36 @@ pub mod trait_system_enhancements {
                 }
             }
 
-            // If not found in implementation, check default methods in trait
-            if let Some(trait_def) = self.traits.get(trait_name) {
-                trait_def.provided_methods.iter().find(|m| m.name == method_name)
-            } else {
-                None
-            }
+            // If not found in implementation, check default methods in trait, then its supertraits
+            let trait_def = self.traits.get(trait_name)?;
+            trait_def.provided_methods.iter().find(|m| m.name == method_name).or_else(|| {
+                trait_def.super_traits.iter()
+                    .find_map(|super_trait| self.resolve_with_supertraits(type_name, super_trait, method_name, searched))
+            })
+        }
+
+        /// The concrete type `type_name`'s implementation of `trait_name` binds an associated type to
+        ///
+        /// This is what `Self::Item` means inside `impl Iterator for Counter`. An impl that leaves the
+        /// type out gets the trait's default.
+        pub fn resolve_associated_type(&self, type_name: &str, trait_name: &str, assoc_name: &str) -> Result<Type, String> {
+            let trait_def = self.traits.get(trait_name)
+                .ok_or_else(|| format!("Trait '{}' not found", trait_name))?;
+            let declared = trait_def.associated_types.iter()
+                .find(|declared| declared.name == assoc_name)
+                .ok_or_else(|| format!("Trait '{}' has no associated type '{}'", trait_name, assoc_name))?;
+            let implementation = self.implementations.get(trait_name)
+                .and_then(|implementations| implementations.iter().find(|imp| imp.for_type == type_name))
+                .ok_or_else(|| format!("Type '{}' does not implement trait '{}'", type_name, trait_name))?;
+
+            implementation.associated_types.iter()
+                .find(|(name, _)| name == assoc_name)
+                .map(|(_, ty)| ty.clone())
+                .or_else(|| declared.default.clone())
+                .ok_or_else(|| format!(
+                    "Implementation of '{}' for '{}' doesn't bind associated type '{}'",
+                    trait_name, type_name, assoc_name
+                ))
         }
 
         /// Check if a type implements a specific trait
@@ -2113,15 +2543,138 @@ pub mod trait_system_enhancements {
     impl CoherenceChecker {
         /// Check if an implementation overlaps with existing ones
         pub fn has_overlapping_impl(&self, new_impl: &EnhancedImplDef, existing_impls: &HashMap<String, Vec<EnhancedImplDef>>) -> bool {
-            if let Some(exiting_for_trait) = existing_impls.get(&new_impl.trait_name) {
-                for existing_impl in exiting_for_trait {
-                    // Simple overlap check: same type implementing same trait
-                    if existing_impl.for_type == new_impl.for_type {
-                        return true;
+            self.find_overlapping_impl(new_impl, existing_impls).is_some()
+        }
+
+        /// The first existing implementation of the same trait that some type could get from both
+        ///
+        /// An impl's type parameters stand for any type, so `impl<U> T for Vec<U>` overlaps
+        /// `impl T for Vec<Int>`, while `Vec<Int>` and `Vec<String>` are disjoint.
+        pub fn find_overlapping_impl<'a>(
+            &self,
+            new_impl: &EnhancedImplDef,
+            existing_impls: &'a HashMap<String, Vec<EnhancedImplDef>>,
+        ) -> Option<&'a EnhancedImplDef> {
+            let new_shape = TypeShape::of_impl(new_impl, "new");
+            existing_impls.get(&new_impl.trait_name)?.iter().find(|existing_impl| {
+                let mut substitution = HashMap::new();
+                new_shape.unify(&TypeShape::of_impl(existing_impl, "existing"), &mut substitution)
+            })
+        }
+    }
+
+    /// How an implementation reads in source, e.g. `impl<U> Show for Vec<U>`
+    fn describe_impl(impl_def: &EnhancedImplDef) -> String {
+        if impl_def.type_params.is_empty() {
+            format!("impl {} for {}", impl_def.trait_name, impl_def.for_type)
+        } else {
+            format!("impl<{}> {} for {}", impl_def.type_params.join(", "), impl_def.trait_name, impl_def.for_type)
+        }
+    }
+
+    /// The structure of an impl's target type, with its type parameters left open
+    #[derive(Debug, Clone, PartialEq)]
+    enum TypeShape {
+        Param(String),
+        Concrete(String, Vec<TypeShape>),
+    }
+
+    impl TypeShape {
+        /// The target of `impl_def`; parameters are tagged with `side` so two impls' `T`s stay apart
+        ///
+        /// Text that doesn't parse is compared as a whole, the way every target used to be.
+        fn of_impl(impl_def: &EnhancedImplDef, side: &str) -> TypeShape {
+            let mut chars = impl_def.for_type.chars().peekable();
+            let params: Vec<&str> = impl_def.type_params.iter().map(String::as_str).collect();
+            match Self::parse(&mut chars, &params, side) {
+                Some(shape) if chars.all(char::is_whitespace) => shape,
+                _ => TypeShape::Concrete(impl_def.for_type.trim().to_string(), Vec::new()),
+            }
+        }
+
+        /// Parses `Name`, `Name<A, B>`, `[A]` or `(A, B)`
+        fn parse(chars: &mut std::iter::Peekable<std::str::Chars>, params: &[&str], side: &str) -> Option<TypeShape> {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            match chars.peek()? {
+                '[' => {
+                    chars.next();
+                    let items = Self::parse_list(chars, params, side, ']')?;
+                    Some(TypeShape::Concrete("[]".to_string(), items))
+                },
+                '(' => {
+                    chars.next();
+                    let items = Self::parse_list(chars, params, side, ')')?;
+                    Some(TypeShape::Concrete("()".to_string(), items))
+                },
+                _ => {
+                    let mut name = String::new();
+                    while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_' || *c == ':') {
+                        name.push(c);
                     }
+                    if name.is_empty() {
+                        return None;
+                    }
+                    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                    if chars.next_if_eq(&'<').is_some() {
+                        let args = Self::parse_list(chars, params, side, '>')?;
+                        Some(TypeShape::Concrete(name, args))
+                    } else if params.contains(&name.as_str()) {
+                        Some(TypeShape::Param(format!("{}:{}", side, name)))
+                    } else {
+                        Some(TypeShape::Concrete(name, Vec::new()))
+                    }
+                },
+            }
+        }
+
+        /// Parses comma-separated shapes up to and including `close`
+        fn parse_list(chars: &mut std::iter::Peekable<std::str::Chars>, params: &[&str], side: &str, close: char) -> Option<Vec<TypeShape>> {
+            let mut items = Vec::new();
+            loop {
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                if chars.next_if_eq(&close).is_some() {
+                    return Some(items);
+                }
+                if !items.is_empty() {
+                    chars.next_if_eq(&',')?;
                 }
+                items.push(Self::parse(chars, params, side)?);
+            }
+        }
+
+        /// Follows bound parameters until reaching an unbound one or a concrete type
+        fn resolve<'a>(&'a self, substitution: &'a HashMap<String, TypeShape>) -> &'a TypeShape {
+            match self {
+                TypeShape::Param(name) => substitution.get(name).map_or(self, |bound| bound.resolve(substitution)),
+                concrete => concrete,
+            }
+        }
+
+        fn mentions(&self, param: &str, substitution: &HashMap<String, TypeShape>) -> bool {
+            match self.resolve(substitution) {
+                TypeShape::Param(name) => name == param,
+                TypeShape::Concrete(_, args) => args.iter().any(|arg| arg.mentions(param, substitution)),
+            }
+        }
+
+        /// Whether some assignment of the parameters makes both shapes the same type
+        fn unify(&self, other: &TypeShape, substitution: &mut HashMap<String, TypeShape>) -> bool {
+            let (a, b) = (self.resolve(substitution).clone(), other.resolve(substitution).clone());
+            match (a, b) {
+                (TypeShape::Param(x), TypeShape::Param(y)) if x == y => true,
+                (TypeShape::Param(param), shape) | (shape, TypeShape::Param(param)) => {
+                    if shape.mentions(&param, substitution) {
+                        return false;
+                    }
+                    substitution.insert(param, shape);
+                    true
+                },
+                (TypeShape::Concrete(a_name, a_args), TypeShape::Concrete(b_name, b_args)) => {
+                    a_name == b_name
+                        && a_args.len() == b_args.len()
+                        && a_args.iter().zip(&b_args).all(|(a_arg, b_arg)| a_arg.unify(b_arg, substitution))
+                },
             }
-            false
         }
     }
 }
@@ -2129,6 +2682,7 @@ pub mod trait_system_enhancements {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::runtime::Value;
 
     #[test]
     fn test_dependent_type_system_creation() {
@@ -2192,6 +2746,46 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_unify_fills_in_inferred_parts() {
+        let mut unifier = type_system::Unifier::new();
+        let array = |ty| Type::Array(Box::new(ty));
+        assert_eq!(unifier.unify(&array(Type::Infer), &array(Type::Int)), Ok(array(Type::Int)));
+
+        // Holes on both sides are filled from each other
+        let result = |ok, err| Type::Result(Box::new(ok), Box::new(err));
+        assert_eq!(unifier.unify(&result(Type::Infer, Type::String), &result(Type::Int, Type::Infer)), Ok(result(Type::Int, Type::String)));
+
+        // A variable bound once stays bound, so it can't then be something else
+        let variable = unifier.fresh();
+        assert_eq!(unifier.unify(&variable, &Type::Int), Ok(Type::Int));
+        assert_eq!(unifier.unify(&array(variable.clone()), &array(Type::String)), Err("Type mismatch: Int is not String".to_string()));
+        assert_eq!(unifier.resolve(&variable), Type::Int);
+    }
+
+    #[test]
+    fn test_unify_rejects_infinite_types() {
+        let mut unifier = type_system::Unifier::new();
+        let variable = unifier.fresh();
+        let err = unifier.unify(&variable, &Type::Array(Box::new(variable.clone()))).unwrap_err();
+        assert_eq!(err, "Infinite type: ?0 occurs in [?0]");
+        // The failed attempt bound nothing
+        assert_eq!(unifier.resolve(&variable), variable);
+    }
+
+    #[test]
+    fn test_inferred_bindings_keep_their_type() {
+        let check = |source: &str| {
+            let program = crate::parser::Parser::new(source).parse_program().unwrap();
+            let mut dep_type_sys = type_system::DependentTypeSystem::new();
+            program.statements.iter().try_for_each(|statement| dep_type_sys.check_statement(statement))
+        };
+        assert!(check("let x = 3\nx + 1").is_ok());
+        assert!(check("let x = 3\nx + \"s\"").unwrap_err().contains("requires numeric operands"));
+        assert!(check("let x = 3\nx == \"s\"").unwrap_err().starts_with("Cannot compare"));
+        assert!(check("let x = 3\nlet x = \"s\"\nx == \"t\"").is_ok());
+    }
+
     #[test]
     fn test_linear_type_system() {
         let mut linear_sys = type_system::LinearTypeSystem::new();
@@ -2209,26 +2803,138 @@ mod tests {
         assert!(linear_sys.validate_consumption().is_ok()); // Now properly consumed
     }
 
+    #[test]
+    fn test_nested_handlers_resolve_to_the_innermost() {
+        let handler = |return_type: Type| type_system::EffectHandler {
+            operations: std::collections::HashMap::new(),
+            return_type,
+        };
+        let mut effect_sys = type_system::EffectSystem::new();
+        effect_sys.register_handler("State".to_string(), handler(Type::Int));
+
+        // An inner frame without its own handler still sees the outer one
+        effect_sys.push_frame();
+        assert_eq!(effect_sys.resolve_handler("State").map(|h| &h.return_type), Some(&Type::Int));
+
+        effect_sys.register_handler("State".to_string(), handler(Type::String));
+        effect_sys.push_frame();
+        assert_eq!(effect_sys.resolve_handler("State").map(|h| &h.return_type), Some(&Type::String));
+        assert!(!effect_sys.is_effect_handled("Console"));
+
+        effect_sys.pop_frame().unwrap();
+        effect_sys.pop_frame().unwrap();
+        assert_eq!(effect_sys.resolve_handler("State").map(|h| &h.return_type), Some(&Type::Int));
+    }
+
+    #[test]
+    fn test_active_effects_are_listed_in_name_order() {
+        let mut effect_sys = type_system::EffectSystem::new();
+        for name in ["State", "IO", "Async", "Exception"] {
+            effect_sys.activate_effect(name.to_string());
+        }
+        effect_sys.deactivate_effect("Async");
+        assert_eq!(effect_sys.get_active_effects(), vec!["Exception", "IO", "State"]);
+    }
+
+    /// Runs `source` in `runtime` and builds a handler from the functions it defines
+    fn handler_from(runtime: &mut crate::runtime::Runtime, source: &str) -> type_system::EffectHandler {
+        let program = crate::parser::Parser::new(source).parse_program().unwrap();
+        runtime.eval_program(&program).unwrap();
+        let operations = program.statements.iter()
+            .filter_map(|statement| match statement {
+                Statement::Function(def) => Some((def.name.clone(), def.clone())),
+                _ => None,
+            })
+            .collect();
+        type_system::EffectHandler { operations, return_type: Type::Unit }
+    }
+
+    fn operation(name: &str, parameter_types: Vec<Type>, return_type: Type) -> type_system::EffectSignature {
+        type_system::EffectSignature { operation_name: name.to_string(), parameter_types, return_type }
+    }
+
     #[test]
     fn test_effect_system_operations() {
         let mut effect_sys = type_system::EffectSystem::new();
+        let mut runtime = crate::runtime::Runtime::new();
 
         // Test effect activation
         effect_sys.activate_effect("IO".to_string());
         assert!(effect_sys.get_active_effects().contains(&"IO".to_string()));
 
         // Test effect signature registration
-        let signature = type_system::EffectSignature {
-            operation_name: "read".to_string(),
-            parameter_types: vec![Type::String],
-            return_type: Type::String,
-        };
-        effect_sys.register_effect_signature("read_file".to_string(), signature);
+        effect_sys.register_effect_signature("IO".to_string(), operation("read", vec![Type::String], Type::String));
 
-        // Test performing an operation
+        // Performing needs a handler for the effect
         let args = vec![Expression::String("test.txt".to_string())];
-        let result = effect_sys.perform("read_file", "read", &args);
-        assert!(result.is_ok());
+        let err = effect_sys.perform(&mut runtime, "IO", "read", &args).unwrap_err();
+        assert_eq!(err, "No handler registered for effect IO");
+
+        let handler = handler_from(&mut runtime, "fn read(path: String) -> String { \"contents of \" + path }");
+        effect_sys.register_handler("IO".to_string(), handler);
+        let result = effect_sys.perform(&mut runtime, "IO", "read", &args).unwrap();
+        assert_eq!(result, Value::String("contents of test.txt".to_string()));
+
+        let err = effect_sys.perform(&mut runtime, "IO", "read", &[Expression::Integer(1)]).unwrap_err();
+        assert_eq!(err, "Effect operation 'IO.read' expects String, got Int");
+    }
+
+    #[test]
+    fn test_logger_handler_collects_performed_messages() {
+        let mut effect_sys = type_system::EffectSystem::new();
+        let mut runtime = crate::runtime::Runtime::new();
+        effect_sys.register_effect_signature("Logger".to_string(), operation("log", vec![Type::String], Type::Unit));
+        let handler = handler_from(&mut runtime, "let buffer = Cell::new(\"\")\n\
+                                                  let worker = \"worker\"\n\
+                                                  fn log(message: String) {\n\
+                                                      buffer.set(buffer.get() + message + \"\\n\")\n\
+                                                  }");
+        effect_sys.register_handler("Logger".to_string(), handler);
+        effect_sys.activate_effect("Logger".to_string());
+
+        let messages = [
+            Expression::String("starting".to_string()),
+            Expression::binary(
+                Expression::Identifier("worker".to_string()),
+                BinaryOp::Add,
+                Expression::String(" ready".to_string()),
+            ),
+        ];
+        for message in messages {
+            let resumed = effect_sys.perform(&mut runtime, "Logger", "log", &[message]).unwrap();
+            assert_eq!(resumed, Value::Unit);
+        }
+
+        let buffer = runtime.env.get("buffer").unwrap();
+        assert_eq!(buffer.to_string(), "Cell(\"starting\nworker ready\n\")");
+    }
+
+    #[test]
+    fn test_state_handler_resumes_with_the_current_value() {
+        let mut effect_sys = type_system::EffectSystem::new();
+        let mut runtime = crate::runtime::Runtime::new();
+        effect_sys.register_effect_signature("State".to_string(), operation("get", vec![], Type::Int));
+        effect_sys.register_effect_signature("State".to_string(), operation("set", vec![Type::Int], Type::Unit));
+        let handler = handler_from(&mut runtime, "let state = Cell::new(0)\n\
+                                                  fn get() -> Int { state.get() }\n\
+                                                  fn set(value: Int) { state.set(value) }");
+        effect_sys.register_handler("State".to_string(), handler);
+        effect_sys.activate_effect("State".to_string());
+
+        assert_eq!(effect_sys.perform(&mut runtime, "State", "get", &[]).unwrap(), Value::Integer(0));
+        effect_sys.perform(&mut runtime, "State", "set", &[Expression::Integer(41)]).unwrap();
+        assert_eq!(effect_sys.perform(&mut runtime, "State", "get", &[]).unwrap(), Value::Integer(41));
+
+        // An inner frame's handler shadows the outer one until the frame is popped
+        effect_sys.push_frame();
+        effect_sys.activate_effect("State".to_string());
+        let inner = handler_from(&mut runtime, "fn get() -> Int { 7 }");
+        effect_sys.register_handler("State".to_string(), inner);
+        assert_eq!(effect_sys.perform(&mut runtime, "State", "get", &[]).unwrap(), Value::Integer(7));
+        let err = effect_sys.perform(&mut runtime, "State", "set", &[Expression::Integer(1)]).unwrap_err();
+        assert_eq!(err, "Operation set not handled for effect State");
+        effect_sys.pop_frame().unwrap();
+        assert_eq!(effect_sys.perform(&mut runtime, "State", "get", &[]).unwrap(), Value::Integer(41));
     }
 
     #[test]
@@ -2262,6 +2968,26 @@ mod tests {
         assert!(matcher.match_pattern(&value, &pattern).unwrap());
     }
 
+    #[test]
+    fn test_nan_in_patterns_and_guards() {
+        use pattern_matching::*;
+
+        let mut matcher = AdvancedPatternMatcher::new();
+        let nan = Value::Float(f64::NAN);
+        let inf = Value::Float(f64::INFINITY);
+        assert!(!matcher.match_pattern(&nan, &Pattern::Literal(Expression::Float(f64::NAN))).unwrap());
+        assert!(matcher.match_pattern(&inf, &Pattern::Literal(Expression::Float(f64::INFINITY))).unwrap());
+
+        let x = || Box::new(Expression::Identifier("x".to_string()));
+        let binding = Pattern::Identifier("x".to_string());
+        let unequal = Expression::BinaryOp(x(), BinaryOp::Ne, x(), ParsedAt::default());
+        assert!(matcher.match_pattern_with_guard(&nan, &binding, Some(&unequal)).unwrap());
+        assert!(!matcher.match_pattern_with_guard(&inf, &binding, Some(&unequal)).unwrap());
+
+        let below_one = Expression::BinaryOp(x(), BinaryOp::Lt, Box::new(Expression::Float(1.0)), ParsedAt::default());
+        assert_eq!(matcher.match_pattern_with_guard(&nan, &binding, Some(&below_one)).unwrap_err(), "Cannot order NaN");
+    }
+
     #[test]
     fn test_memory_management() {
         use memory_management::*;
@@ -2323,6 +3049,79 @@ mod tests {
         assert!(actor_system.stop_actor(&actor_id).is_ok());
     }
 
+    /// Reports each message it receives, then stays busy for `busy_for`
+    struct BusyActor {
+        busy_for: std::time::Duration,
+        received: std::sync::mpsc::Sender<()>,
+    }
+
+    impl concurrency::ActorBehavior for BusyActor {
+        fn handle_message(&mut self, _msg: concurrency::Message) -> Result<Option<concurrency::Message>, String> {
+            let _ = self.received.send(());
+            std::thread::sleep(self.busy_for);
+            Ok(None)
+        }
+    }
+
+    fn ping() -> concurrency::Message {
+        concurrency::Message {
+            from: "test".to_string(),
+            content: "ping".to_string(),
+            timestamp: 0,
+            priority: concurrency::MessagePriority::Normal,
+            correlation_id: None,
+        }
+    }
+
+    #[test]
+    fn test_stop_actor_times_out_on_a_blocked_actor() {
+        use concurrency::*;
+        use std::time::{Duration, Instant};
+
+        let mut actor_system = ActorSystem::new();
+        let (received, handling) = std::sync::mpsc::channel();
+        let actor = BusyActor { busy_for: Duration::from_secs(10), received };
+        actor_system.create_actor("stuck".to_string(), actor).unwrap();
+        actor_system.send_message("stuck", ping()).unwrap();
+        handling.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        let started = Instant::now();
+        let err = actor_system.stop_actor_within("stuck", Duration::from_millis(100)).unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(err.contains("did not stop within 100ms"), "{}", err);
+        assert!(matches!(actor_system.get_actor_state("stuck"), Some(ActorState::Failed(_))));
+        assert!(actor_system.send_message("stuck", ping()).is_err());
+    }
+
+    #[test]
+    fn test_stop_all_stops_idle_actors_and_reports_blocked_ones() {
+        use concurrency::*;
+        use std::time::{Duration, Instant};
+
+        let mut actor_system = ActorSystem::new();
+        let (received, handling) = std::sync::mpsc::channel();
+        let idle = BusyActor { busy_for: Duration::ZERO, received: received.clone() };
+        let stuck = BusyActor { busy_for: Duration::from_secs(10), received };
+        actor_system.create_actor("idle".to_string(), idle).unwrap();
+        actor_system.create_actor("stuck".to_string(), stuck).unwrap();
+        actor_system.send_message("stuck", ping()).unwrap();
+        handling.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        let started = Instant::now();
+        let err = actor_system.stop_all(Duration::from_millis(500)).unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(err, "Actors did not stop within 500ms: stuck");
+        assert_eq!(actor_system.get_actor_state("idle"), Some(&ActorState::Stopped));
+        assert!(matches!(actor_system.get_actor_state("stuck"), Some(ActorState::Failed(_))));
+
+        // A well-behaved actor stops well before the timeout
+        actor_system.create_actor("quick".to_string(), BusyActor { busy_for: Duration::ZERO, received: std::sync::mpsc::channel().0 }).unwrap();
+        let started = Instant::now();
+        actor_system.stop_all(Duration::from_secs(5)).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(actor_system.get_actor_state("quick"), Some(&ActorState::Stopped));
+    }
+
     #[test]
     fn test_enhanced_trait_resolver() {
         use trait_system_enhancements::*;
@@ -2343,6 +3142,8 @@ mod tests {
                     is_public: true,
                     is_awaitable: false,
                     effect_annotations: vec![],
+                    generic_params: vec![],
+                    attributes: vec![],
                 }
             ],
             provided_methods: vec![],
@@ -2354,4 +3155,153 @@ mod tests {
         assert!(resolver.register_trait(trait_def).is_ok());
         assert!(resolver.traits.contains_key("Display"));
     }
+
+    fn trait_method(name: &str) -> FunctionDef {
+        FunctionDef {
+            name: name.to_string(),
+            parameters: vec![],
+            return_type: Some(Type::Bool),
+            body: vec![],
+            is_async: false,
+            is_public: true,
+            is_awaitable: false,
+            effect_annotations: vec![],
+            generic_params: vec![],
+            attributes: vec![],
+        }
+    }
+
+    fn enhanced_trait(name: &str, provided: &[&str], super_traits: &[&str]) -> trait_system_enhancements::EnhancedTraitDef {
+        trait_system_enhancements::EnhancedTraitDef {
+            name: name.to_string(),
+            type_params: vec![],
+            required_methods: vec![],
+            provided_methods: provided.iter().map(|m| trait_method(m)).collect(),
+            associated_types: vec![],
+            super_traits: super_traits.iter().map(|t| t.to_string()).collect(),
+            trait_constraints: vec![],
+        }
+    }
+
+    fn enhanced_impl(trait_name: &str, for_type: &str) -> trait_system_enhancements::EnhancedImplDef {
+        trait_system_enhancements::EnhancedImplDef {
+            trait_name: trait_name.to_string(),
+            for_type: for_type.to_string(),
+            type_params: vec![],
+            methods: vec![],
+            associated_types: vec![],
+            trait_constraints: vec![],
+        }
+    }
+
+    #[test]
+    fn test_implementing_ord_without_eq_is_rejected() {
+        let mut resolver = trait_system_enhancements::EnhancedTraitResolver::new();
+        resolver.register_trait(enhanced_trait("PartialEq", &[], &[])).unwrap();
+        resolver.register_trait(enhanced_trait("Eq", &[], &["PartialEq"])).unwrap();
+        resolver.register_trait(enhanced_trait("PartialOrd", &[], &["PartialEq"])).unwrap();
+        resolver.register_trait(enhanced_trait("Ord", &[], &["Eq", "PartialOrd"])).unwrap();
+
+        let err = resolver.register_implementation(enhanced_impl("Ord", "Point")).unwrap_err();
+        assert_eq!(err, "Type 'Point' must implement supertraits 'Eq', 'PartialOrd' before implementing 'Ord'");
+
+        resolver.register_implementation(enhanced_impl("PartialEq", "Point")).unwrap();
+        resolver.register_implementation(enhanced_impl("PartialOrd", "Point")).unwrap();
+        let err = resolver.register_implementation(enhanced_impl("Ord", "Point")).unwrap_err();
+        assert_eq!(err, "Type 'Point' must implement supertrait 'Eq' before implementing 'Ord'");
+        assert!(!resolver.implements_trait("Point", "Ord"));
+
+        resolver.register_implementation(enhanced_impl("Eq", "Point")).unwrap();
+        assert!(resolver.register_implementation(enhanced_impl("Ord", "Point")).is_ok());
+    }
+
+    fn generic_impl(trait_name: &str, type_params: &[&str], for_type: &str) -> trait_system_enhancements::EnhancedImplDef {
+        trait_system_enhancements::EnhancedImplDef {
+            type_params: type_params.iter().map(|p| p.to_string()).collect(),
+            ..enhanced_impl(trait_name, for_type)
+        }
+    }
+
+    #[test]
+    fn test_blanket_impl_overlaps_concrete_impl() {
+        let mut resolver = trait_system_enhancements::EnhancedTraitResolver::new();
+        resolver.register_trait(enhanced_trait("Show", &[], &[])).unwrap();
+        resolver.register_implementation(enhanced_impl("Show", "Vec<Int>")).unwrap();
+
+        let err = resolver.register_implementation(generic_impl("Show", &["U"], "Vec<U>")).unwrap_err();
+        assert_eq!(err, "Conflicting implementations of trait 'Show': `impl<U> Show for Vec<U>` overlaps `impl Show for Vec<Int>`");
+
+        // A fully blanket impl overlaps everything, and the order they arrive in doesn't matter
+        let mut resolver = trait_system_enhancements::EnhancedTraitResolver::new();
+        resolver.register_trait(enhanced_trait("Show", &[], &[])).unwrap();
+        resolver.register_implementation(generic_impl("Show", &["T"], "T")).unwrap();
+        let err = resolver.register_implementation(enhanced_impl("Show", "Map<String, [Int]>")).unwrap_err();
+        assert_eq!(err, "Conflicting implementations of trait 'Show': `impl Show for Map<String, [Int]>` overlaps `impl<T> Show for T`");
+    }
+
+    #[test]
+    fn test_disjoint_impls_are_allowed() {
+        let mut resolver = trait_system_enhancements::EnhancedTraitResolver::new();
+        resolver.register_trait(enhanced_trait("Show", &[], &[])).unwrap();
+        resolver.register_trait(enhanced_trait("Debug", &[], &[])).unwrap();
+
+        resolver.register_implementation(enhanced_impl("Show", "Vec<Int>")).unwrap();
+        resolver.register_implementation(enhanced_impl("Show", "Vec<String>")).unwrap();
+        resolver.register_implementation(generic_impl("Show", &["T"], "Pair<T, T>")).unwrap();
+        // Pair<T, T> can't be Pair<Int, String>, and a different trait never conflicts
+        resolver.register_implementation(enhanced_impl("Show", "Pair<Int, String>")).unwrap();
+        resolver.register_implementation(generic_impl("Debug", &["U"], "Vec<U>")).unwrap();
+
+        let err = resolver.register_implementation(enhanced_impl("Show", "Pair<Bool, Bool>")).unwrap_err();
+        assert!(err.contains("`impl<T> Show for Pair<T, T>`"), "{}", err);
+        assert!(resolver.register_implementation(enhanced_impl("Show", "Vec<Int>")).is_err());
+    }
+
+    #[test]
+    fn test_iterator_item_resolves_per_implementation() {
+        use trait_system_enhancements::*;
+
+        let mut resolver = EnhancedTraitResolver::new();
+        let assoc = |name: &str, default: Option<Type>| AssociatedTypeDef { name: name.to_string(), bounds: vec![], default };
+        resolver.register_trait(EnhancedTraitDef {
+            associated_types: vec![assoc("Item", None), assoc("Size", Some(Type::Int))],
+            ..enhanced_trait("Iterator", &[], &[])
+        }).unwrap();
+
+        let with_types = |for_type: &str, types: &[(&str, Type)]| EnhancedImplDef {
+            associated_types: types.iter().map(|(name, ty)| (name.to_string(), ty.clone())).collect(),
+            ..enhanced_impl("Iterator", for_type)
+        };
+        resolver.register_implementation(with_types("Counter", &[("Item", Type::Int)])).unwrap();
+        resolver.register_implementation(with_types("Words", &[("Item", Type::String), ("Size", Type::Float)])).unwrap();
+
+        assert_eq!(resolver.resolve_associated_type("Counter", "Iterator", "Item"), Ok(Type::Int));
+        assert_eq!(resolver.resolve_associated_type("Words", "Iterator", "Item"), Ok(Type::String));
+        // Size has a default that Words overrides
+        assert_eq!(resolver.resolve_associated_type("Counter", "Iterator", "Size"), Ok(Type::Int));
+        assert_eq!(resolver.resolve_associated_type("Words", "Iterator", "Size"), Ok(Type::Float));
+
+        assert_eq!(resolver.resolve_associated_type("Lines", "Iterator", "Item").unwrap_err(),
+                   "Type 'Lines' does not implement trait 'Iterator'");
+        assert_eq!(resolver.resolve_associated_type("Counter", "Iterator", "Key").unwrap_err(),
+                   "Trait 'Iterator' has no associated type 'Key'");
+
+        let err = resolver.register_implementation(with_types("Lines", &[])).unwrap_err();
+        assert_eq!(err, "Implementation of 'Iterator' for 'Lines' is missing associated type 'Item'");
+        let err = resolver.register_implementation(with_types("Lines", &[("Item", Type::String), ("Key", Type::Int)])).unwrap_err();
+        assert_eq!(err, "Associated type 'Key' is not a member of trait 'Iterator'");
+    }
+
+    #[test]
+    fn test_supertrait_methods_are_visible_on_the_subtrait() {
+        let mut resolver = trait_system_enhancements::EnhancedTraitResolver::new();
+        resolver.register_trait(enhanced_trait("PartialEq", &["ne"], &["Ord"])).unwrap();
+        resolver.register_trait(enhanced_trait("Ord", &["max"], &["PartialEq"])).unwrap();
+        resolver.register_trait(enhanced_trait("Hash", &["hash"], &[])).unwrap();
+
+        // The supertrait cycle above can't be implemented, but looking methods up through it still ends
+        assert_eq!(resolver.resolve_trait_method("Point", "Ord", "ne").map(|m| m.name.as_str()), Some("ne"));
+        assert_eq!(resolver.resolve_trait_method("Point", "PartialEq", "max").map(|m| m.name.as_str()), Some("max"));
+        assert!(resolver.resolve_trait_method("Point", "Ord", "hash").is_none());
+    }
 }
\ No newline at end of file
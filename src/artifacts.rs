@@ -0,0 +1,297 @@
+// Logos Build Artifacts
+// This module knows where the toolchain writes generated files (build output,
+// incremental cache, language sync scaffolding) and how to remove them safely.
+//
+// Builds write under a target directory split by profile, like Cargo's:
+//
+//   target/debug/<name>              compiled output
+//   target/debug/ir/<name>.<ext>     bytecode and IR dumps from --emit or --target
+//   target/debug/incremental/        incremental build cache
+//
+// `--release` selects target/release instead, and `--out-dir` replaces target/.
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Directory that holds compiled output, relative to the project root
+pub const TARGET_DIR: &str = "target";
+
+/// Directory that held the incremental build cache before it moved under the target
+/// directory; `clean` still removes it from older projects
+pub const CACHE_DIR: &str = ".logos-cache";
+
+/// Directory that holds files generated by `logos sync`, relative to the project root
+pub const SYNC_DIR: &str = "logos";
+
+/// Build profile, which picks the subdirectory of the target directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    Debug,
+    Release,
+}
+
+impl Profile {
+    /// The profile a build uses, given whether `--release` was passed
+    pub fn from_release(release: bool) -> Self {
+        if release { Profile::Release } else { Profile::Debug }
+    }
+
+    /// Name of the profile's directory under the target directory
+    pub fn dir_name(&self) -> &'static str {
+        match self {
+            Profile::Debug => "debug",
+            Profile::Release => "release",
+        }
+    }
+}
+
+/// Where one build writes its outputs
+#[derive(Debug, Clone)]
+pub struct TargetLayout {
+    base: PathBuf,     // The target directory, or the --out-dir override
+    profile: Profile,
+}
+
+impl TargetLayout {
+    /// Lays out a build under `project_dir/target`, or under `out_dir` when given
+    pub fn new(project_dir: &Path, out_dir: Option<&Path>, profile: Profile) -> Self {
+        let base = match out_dir {
+            Some(dir) => dir.to_path_buf(),
+            None => project_dir.join(TARGET_DIR),
+        };
+        TargetLayout { base, profile }
+    }
+
+    /// Directory holding everything this profile produces
+    pub fn profile_dir(&self) -> PathBuf {
+        self.base.join(self.profile.dir_name())
+    }
+
+    /// Path of the compiled output called `name`
+    pub fn output_path(&self, name: &str) -> PathBuf {
+        self.profile_dir().join(name)
+    }
+
+    /// Path of an intermediate dump, such as `name.bc` for bytecode
+    pub fn dump_path(&self, name: &str, extension: &str) -> PathBuf {
+        self.profile_dir().join("ir").join(format!("{}.{}", name, extension))
+    }
+
+    /// Directory for the incremental build cache
+    pub fn cache_dir(&self) -> PathBuf {
+        self.profile_dir().join("incremental")
+    }
+
+    /// Writes `contents` to `path`, creating the directories above it
+    pub fn write(&self, path: &Path, contents: &str) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Could not create directory '{}': {}", parent.display(), e))?;
+        }
+        fs::write(path, contents)
+            .map_err(|e| format!("Could not write output file '{}': {}", path.display(), e))
+    }
+}
+
+/// Options controlling what `clean` removes
+#[derive(Debug, Clone, Default)]
+pub struct CleanOptions {
+    pub include_sync: bool,       // Also remove the generated sync directory
+    pub dry_run: bool,            // Report what would be removed without touching the filesystem
+    pub out_dir: Option<PathBuf>, // The --out-dir builds wrote to, relative to the project and inside it
+}
+
+/// Summary of a clean run
+#[derive(Debug, Clone, Default)]
+pub struct CleanReport {
+    pub removed: Vec<PathBuf>,  // Paths that were removed (or would be, in dry-run mode)
+    pub skipped: Vec<PathBuf>,  // Candidate paths that did not exist
+}
+
+/// Removes build artifacts from a project directory
+///
+/// With `options.out_dir`, the profile directories a build writes there are removed
+/// too. The rest of that directory is left alone, since it may hold other files. The
+/// out_dir must be a relative path that stays inside the project: nothing outside
+/// `project_dir` is ever removed.
+///
+/// # Arguments
+/// * `project_dir` - Root of the project whose artifacts should be removed
+/// * `options` - Which artifacts to remove and whether to actually delete them
+///
+/// # Returns
+/// * `Ok(CleanReport)` describing what was removed
+/// * `Err` if the out_dir or a candidate resolves outside the project, or a candidate cannot be deleted
+pub fn clean(project_dir: &Path, options: &CleanOptions) -> Result<CleanReport, String> {
+    let root = project_dir.canonicalize()
+        .map_err(|e| format!("Could not resolve project directory '{}': {}", project_dir.display(), e))?;
+
+    let mut candidates = vec![root.join(TARGET_DIR), root.join(CACHE_DIR)];
+    if options.include_sync {
+        candidates.push(root.join(SYNC_DIR));
+    }
+    if let Some(out_dir) = &options.out_dir {
+        // Checked before joining, since joining an absolute path replaces the root
+        let escapes = out_dir.components().any(|part| !matches!(part, Component::Normal(_) | Component::CurDir));
+        if escapes {
+            return Err(format!(
+                "Refusing to clean --out-dir '{}': it must be a relative path inside the project",
+                out_dir.display()
+            ));
+        }
+        for profile in [Profile::Debug, Profile::Release] {
+            candidates.push(root.join(out_dir).join(profile.dir_name()));
+        }
+    }
+
+    let mut report = CleanReport::default();
+
+    for candidate in candidates {
+        if !candidate.exists() {
+            report.skipped.push(candidate);
+            continue;
+        }
+
+        // Resolve symlinks so a link pointing elsewhere can't trick us into deleting it
+        let resolved = candidate.canonicalize()
+            .map_err(|e| format!("Could not resolve '{}': {}", candidate.display(), e))?;
+        if !resolved.starts_with(&root) || resolved == root {
+            return Err(format!(
+                "Refusing to remove '{}': it resolves outside '{}'",
+                candidate.display(),
+                root.display()
+            ));
+        }
+
+        if !options.dry_run {
+            let result = if resolved.is_dir() {
+                fs::remove_dir_all(&resolved)
+            } else {
+                fs::remove_file(&resolved)
+            };
+            result.map_err(|e| format!("Could not remove '{}': {}", resolved.display(), e))?;
+        }
+
+        report.removed.push(candidate);
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_project() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join(TARGET_DIR).join("debug")).unwrap();
+        fs::write(dir.path().join(TARGET_DIR).join("debug").join("main.bc"), "bytecode").unwrap();
+        fs::create_dir_all(dir.path().join(CACHE_DIR)).unwrap();
+        fs::create_dir_all(dir.path().join(SYNC_DIR)).unwrap();
+        fs::write(dir.path().join(SYNC_DIR).join("python_config.toml"), "").unwrap();
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("src").join("main.logos"), "fn main() {}").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_clean_removes_artifacts() {
+        let project = make_project();
+        let report = clean(project.path(), &CleanOptions::default()).unwrap();
+
+        assert_eq!(report.removed.len(), 2);
+        assert!(!project.path().join(TARGET_DIR).exists());
+        assert!(!project.path().join(CACHE_DIR).exists());
+        // Sync files and sources are left alone without --sync
+        assert!(project.path().join(SYNC_DIR).exists());
+        assert!(project.path().join("src").join("main.logos").exists());
+
+        let options = CleanOptions { include_sync: true, ..CleanOptions::default() };
+        let report = clean(project.path(), &options).unwrap();
+        assert_eq!(report.removed.len(), 1);
+        assert!(!project.path().join(SYNC_DIR).exists());
+    }
+
+    #[test]
+    fn test_clean_dry_run_keeps_files() {
+        let project = make_project();
+        let options = CleanOptions { include_sync: true, dry_run: true, ..CleanOptions::default() };
+        let report = clean(project.path(), &options).unwrap();
+
+        assert_eq!(report.removed.len(), 3);
+        assert!(project.path().join(TARGET_DIR).exists());
+        assert!(project.path().join(CACHE_DIR).exists());
+        assert!(project.path().join(SYNC_DIR).exists());
+    }
+
+    #[test]
+    fn test_build_outputs_go_to_profile_directory() {
+        let project = tempfile::tempdir().unwrap();
+
+        let debug = TargetLayout::new(project.path(), None, Profile::from_release(false));
+        debug.write(&debug.output_path("main"), "debug build").unwrap();
+        debug.write(&debug.dump_path("main", "bc"), "bytecode").unwrap();
+        let release = TargetLayout::new(project.path(), None, Profile::from_release(true));
+        release.write(&release.output_path("main"), "release build").unwrap();
+
+        let target = project.path().join(TARGET_DIR);
+        assert_eq!(fs::read_to_string(target.join("debug").join("main")).unwrap(), "debug build");
+        assert_eq!(fs::read_to_string(target.join("debug").join("ir").join("main.bc")).unwrap(), "bytecode");
+        assert_eq!(fs::read_to_string(target.join("release").join("main")).unwrap(), "release build");
+        assert_eq!(release.cache_dir(), target.join("release").join("incremental"));
+
+        // --out-dir replaces target/ but keeps the profile split
+        let out = project.path().join("dist");
+        let custom = TargetLayout::new(project.path(), Some(&out), Profile::Release);
+        assert_eq!(custom.output_path("main"), out.join("release").join("main"));
+    }
+
+    #[test]
+    fn test_clean_removes_out_dir_builds() {
+        let project = make_project();
+        let out = project.path().join("dist");
+        let layout = TargetLayout::new(project.path(), Some(&out), Profile::Release);
+        layout.write(&layout.output_path("main"), "release build").unwrap();
+        fs::write(out.join("notes.txt"), "not a build artifact").unwrap();
+
+        let options = CleanOptions { out_dir: Some(PathBuf::from("dist")), ..CleanOptions::default() };
+        let report = clean(project.path(), &options).unwrap();
+
+        let root = project.path().canonicalize().unwrap();
+        assert!(report.removed.contains(&root.join("dist").join("release")), "{:?}", report);
+        assert!(!out.join("release").exists());
+        assert!(out.join("notes.txt").exists());
+        assert!(!project.path().join(TARGET_DIR).exists());
+    }
+
+    #[test]
+    fn test_clean_refuses_out_dir_outside_project() {
+        let project = make_project();
+        let elsewhere = tempfile::tempdir().unwrap();
+        let layout = TargetLayout::new(project.path(), Some(elsewhere.path()), Profile::Release);
+        layout.write(&layout.output_path("main"), "release build").unwrap();
+        let escaping = Path::new("..").join(elsewhere.path().file_name().unwrap());
+
+        for out_dir in [elsewhere.path().to_path_buf(), escaping, PathBuf::from("/")] {
+            let options = CleanOptions { out_dir: Some(out_dir.clone()), ..CleanOptions::default() };
+            let err = clean(project.path(), &options).unwrap_err();
+            assert!(err.contains("must be a relative path inside the project"), "{}", err);
+        }
+
+        // Nothing was removed, inside the project or out
+        assert!(elsewhere.path().join("release").join("main").exists());
+        assert!(project.path().join(TARGET_DIR).exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_clean_refuses_symlink_outside_project() {
+        let project = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        std::os::unix::fs::symlink(outside.path(), project.path().join(TARGET_DIR)).unwrap();
+
+        let result = clean(project.path(), &CleanOptions::default());
+        assert!(result.is_err());
+        assert!(outside.path().exists());
+    }
+}
@@ -3,6 +3,7 @@
 // Each AST node corresponds to a syntactic construct in the language.
 
 use std::collections::HashMap;
+use crate::format_spec::FormatSpec;
 
 /// Represents the entire program as a sequence of statements
 #[derive(Debug, Clone)]
@@ -39,12 +40,75 @@ pub enum Statement {
     Actor(ActorDef),         // Actor definition
     Effect(EffectDef),       // Effect definition
     Return(Option<Expression>), // Return statement with optional value
-    Break,                   // Break statement
-    Continue,                // Continue statement
+
+    /// While loop, optionally labeled: 'label: while condition { body }
+    While {
+        label: Option<Label>,             // Label that break/continue can name to target this loop
+        condition: Expression,            // Checked before each iteration
+        body: Vec<Statement>,             // The loop body
+    },
+
+    Defer(Box<Statement>),   // Statement run when the enclosing block or function exits: defer close(file)
+
+    /// Assertion: assert(condition, message?) or assert_eq(actual, expected, message?)
+    Assert {
+        actual: Expression,               // The condition, or the actual value for assert_eq
+        expected: Option<Expression>,     // The expected value, for assert_eq
+        message: Option<Expression>,      // Extra context reported when the assertion fails
+        location: SourceLocation,         // Where the assertion is, for failure reports
+    },
+
+    Break(Option<Label>),    // Break statement, optionally naming the loop to exit
+    Continue(Option<Label>), // Continue statement, optionally naming the loop to continue
     Block(Vec<Statement>),   // Block of statements { ... }
     MacroDefinition(MacroDef), // Macro definition
     Enum(EnumDef),          // Enum definition
     TypeAlias(TypeAliasDef), // Type alias definition
+    Extern(ExternDef),       // Foreign function declaration: extern "c" fn c_sqrt(x: Float) -> Float from "libm"
+}
+
+/// Name of a loop label, without its leading quote ('outer is "outer")
+pub type Label = String;
+
+/// A position in the source code; lines and columns start at 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// Where a node was parsed from, for runtime error reports; nodes built by later
+/// passes have no location
+///
+/// Locations never make two nodes unequal, so the same code compares equal however
+/// it was laid out and wherever it appears.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ParsedAt(pub Option<SourceLocation>);
+
+impl PartialEq for ParsedAt {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+/// The `Debug` form of an AST node with every `SourceLocation` in it left out
+///
+/// Code that differs only in where it was parsed renders the same, so the result
+/// identifies an item however far it moved in the file.
+pub fn debug_without_locations(node: &impl fmt::Debug) -> String {
+    let debug = format!("{:?}", node);
+    let mut parts = debug.split("SourceLocation { line: ");
+    let mut shape = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        shape.push_str(part.split_once('}').map_or(part, |(_, rest)| rest));
+    }
+    shape
 }
 
 /// Represents different kinds of expressions in the language
@@ -54,21 +118,26 @@ pub enum Expression {
     Integer(i64),           // Integer literal (e.g., 42)
     Float(f64),             // Floating-point literal (e.g., 3.14)
     String(String),         // String literal (e.g., "hello")
+    Bytes(Vec<u8>),         // Byte string literal (e.g., b"\x00ok")
     Boolean(bool),          // Boolean literal (true/false)
     Nil,                    // Nil/null value
     Char(char),             // Character literal (e.g., 'a')
     Array(Vec<Expression>), // Array literal (e.g., [1, 2, 3])
+    ArrayRepeat(Box<Expression>, Box<Expression>), // Repeated array literal (e.g., [0; 16]) - value, length
     Tuple(Vec<Expression>), // Tuple literal (e.g., (1, "hello", true))
     Struct(String, Vec<(String, Expression)>), // Struct instantiation (e.g., Point { x: 5, y: 10 })
     Lambda(Vec<Parameter>, Vec<Statement>), // Anonymous function (e.g., |x, y| { x + y })
 
     // Variable and operation expressions
     Identifier(String),     // Variable identifier (e.g., x, func_name)
-    BinaryOp(Box<Expression>, BinaryOp, Box<Expression>), // Binary operation (e.g., a + b)
+    BinaryOp(Box<Expression>, BinaryOp, Box<Expression>, ParsedAt), // Binary operation (e.g., a + b), and where its operator is
     UnaryOp(UnaryOp, Box<Expression>),                  // Unary operation (e.g., -x, !flag)
     Call(String, Vec<Expression>),                      // Function call (e.g., func(arg1, arg2))
     MethodCall(Box<Expression>, String, Vec<Expression>), // Method call (e.g., obj.method(args))
     FieldAccess(Box<Expression>, String),               // Field access (e.g., obj.field)
+    Propagate(Box<Expression>),                         // Error propagation (e.g., read_file(path)?)
+    Index(Box<Expression>, Box<Expression>),            // Indexing (e.g., items[0])
+    Slice(Box<Expression>, Box<Expression>, Box<Expression>), // Slicing with an exclusive end (e.g., bytes[1..4])
 
 
     // Actor messaging expressions for concurrent programming
@@ -78,9 +147,10 @@ pub enum Expression {
 
     // Control flow expressions
     If(Box<Expression>, Vec<Statement>, Vec<Statement>), // condition, then, else
-    Match(Box<Expression>, Vec<(Pattern, Option<Box<Expression>>, Vec<Statement>)>), // expr, (pattern, guard, body)
+    Match(Box<Expression>, Vec<MatchArm>), // expr, (pattern, guard, body)
     BlockExpr(Vec<Statement>),                          // Expression block
     Block(Vec<Statement>),                              // Block expression
+    TryCatch(Vec<Statement>, String, Vec<Statement>),   // try { body } catch (error) { handler }
 
     // Multi-language integration expressions
     MultiLangCall(String, String),                      // @lang{code} - call code in another language
@@ -114,6 +184,51 @@ pub enum Expression {
     MacroInvocation(String, Vec<Expression>),          // Macro call: macro_name!(args...)
 }
 
+/// `Nil` is what `std::mem::take` leaves behind when a pass moves a child out of a node
+impl Default for Expression {
+    fn default() -> Self {
+        Expression::Nil
+    }
+}
+
+/// Expressions are dropped without recursing down long chains
+///
+/// Operator chains such as `1 + 1 + ... + 1` and postfix chains such as `a.b.c...` are
+/// parsed in loops, so the parser's nesting limit doesn't bound how deep they get, and
+/// `else if` ladders may run longer than it as well. The derived drop glue would recurse
+/// once per link; instead the links are detached here and dropped one after another.
+impl Drop for Expression {
+    fn drop(&mut self) {
+        let mut pending = Vec::new();
+        detach_chain_links(self, &mut pending);
+        while let Some(mut expression) = pending.pop() {
+            detach_chain_links(&mut expression, &mut pending);
+        }
+    }
+}
+
+/// Moves the children of `expression` that can start an arbitrarily long chain into `pending`
+fn detach_chain_links(expression: &mut Expression, pending: &mut Vec<Expression>) {
+    match expression {
+        Expression::BinaryOp(left, _, right, _) => {
+            pending.push(std::mem::take(&mut **left));
+            pending.push(std::mem::take(&mut **right));
+        }
+        Expression::MethodCall(receiver, _, _)
+        | Expression::FieldAccess(receiver, _)
+        | Expression::Propagate(receiver)
+        | Expression::Index(receiver, _) => pending.push(std::mem::take(&mut **receiver)),
+        Expression::If(_, _, else_branch) => {
+            for statement in std::mem::take(else_branch) {
+                if let Statement::Expression(inner) = statement {
+                    pending.push(inner);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
 /// Represents different types in the language
 use std::fmt;
 
@@ -148,6 +263,9 @@ pub enum Type {
     },
 
     Named(String),                        // Named type (user-defined types)
+    Dyn(String),                          // Trait object type: dyn Trait
+    Map(Box<Type>, Box<Type>),            // Hash map type: Map<K, V>
+    Set(Box<Type>),                       // Hash set type: Set<T>
     Option(Box<Type>),                    // Optional type: Option<T>
     Result(Box<Type>, Box<Type>),         // Result type: Result<T, E>
     Infer,                                // For type inference
@@ -188,6 +306,9 @@ impl fmt::Display for Type {
             Type::Option(t) => write!(f, "Option<{}>", t),
             Type::Result(ok, err) => write!(f, "Result<{}, {}>", ok, err),
             Type::Infer => write!(f, "_"),
+            Type::Dyn(trait_name) => write!(f, "dyn {}", trait_name),
+            Type::Map(key, value) => write!(f, "Map<{}, {}>", key, value),
+            Type::Set(element) => write!(f, "Set<{}>", element),
         }
     }
 }
@@ -196,7 +317,7 @@ impl fmt::Display for Type {
 #[derive(Debug, Clone, PartialEq)]
 pub enum StringPart {
     Literal(String),                       // Plain text part of the string
-    Interpolated(Box<Expression>),         // Expression part of the string: ${expr}
+    Interpolated(Box<Expression>, Option<FormatSpec>), // Expression part of the string: ${expr} or ${expr:spec}
 }
 
 /// Represents binary operators
@@ -231,6 +352,46 @@ pub struct FunctionDef {
     pub is_awaitable: bool,               // For functions that can be awaited
     pub effect_annotations: Vec<EffectAnnotation>, // Annotations for structured concurrency
     pub generic_params: Vec<GenericParam>, // Generic type parameters with bounds
+    pub attributes: Vec<Attribute>,       // Attributes such as #[test] or #[inline]
+}
+
+impl FunctionDef {
+    /// The effects named in the function's `with` clause, in the order written
+    pub fn declared_effects(&self) -> Vec<&str> {
+        self.effect_annotations.iter()
+            .filter_map(|annotation| match annotation {
+                EffectAnnotation::Performs(effect) => Some(effect.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Represents an attribute attached to an item: #[name] or #[name(args)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attribute {
+    pub name: String,                     // Attribute name (e.g., test, inline, cfg)
+    pub args: Vec<AttributeArg>,          // Arguments inside the parentheses, if any
+}
+
+/// Represents one argument of an attribute
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttributeArg {
+    Word(String),                         // Bare word: #[derive(Debug)]
+    KeyValue(String, String),             // Key and value: #[cfg(feature = "json")]
+    Nested(String, Vec<AttributeArg>),    // Nested list: #[cfg(not(test))]
+}
+
+impl Attribute {
+    /// Checks whether a bare word argument is present, e.g. `always` in #[inline(always)]
+    pub fn has_word(&self, word: &str) -> bool {
+        self.args.iter().any(|arg| matches!(arg, AttributeArg::Word(w) if w == word))
+    }
+}
+
+/// Finds the first attribute with the given name
+pub fn find_attribute<'a>(attributes: &'a [Attribute], name: &str) -> Option<&'a Attribute> {
+    attributes.iter().find(|attr| attr.name == name)
 }
 
 /// Represents a generic parameter with optional trait bounds
@@ -252,6 +413,7 @@ pub enum EffectAnnotation {
     CPUBound,                             // CPU bound function
     Blocking,                             // Blocking function
     NonBlocking,                          // Non-blocking function
+    Performs(String),                     // Effect the function may perform: `with Log`
 }
 
 /// Represents an async block
@@ -309,6 +471,8 @@ pub enum ChannelOperation {
     Send { channel: Box<Expression>, value: Box<Expression> },  // Send operation
     Receive { channel: Box<Expression> },                       // Receive operation
     Close { channel: Box<Expression> },                         // Close operation
+    Default,                                                    // Taken when no other arm is ready: default => ...
+    Timeout { millis: Box<Expression> },                        // Taken once the wait passes this many ms: timeout(100) => ...
 }
 
 /// Represents a function parameter
@@ -354,6 +518,7 @@ pub struct ClassDef {
     pub is_abstract: bool,                // Whether the class is abstract
     pub generics: Vec<GenericParam>,      // Generic type parameters with bounds
     pub interfaces: Vec<String>,          // Interfaces this class implements
+    pub attributes: Vec<Attribute>,       // Attributes attached to the class
     pub constructors: Vec<ConstructorDef>, // Constructor methods
     pub destructors: Vec<DestructorDef>,   // Destructor methods
 }
@@ -401,6 +566,8 @@ pub struct TraitDef {
     pub methods: Vec<FunctionDef>,        // Method signatures in the trait
     pub associated_types: Vec<AssociatedTypeDef>, // Associated types
     pub super_traits: Vec<String>,        // Super traits (inheritance)
+    pub attributes: Vec<Attribute>,       // Attributes attached to the trait
+    pub is_public: bool,                  // Whether the trait is declared `pub trait`
 }
 
 /// Associated type definition within a trait
@@ -419,6 +586,7 @@ pub struct ImplDef {
     pub type_params: Vec<String>,         // Generic type parameters
     pub methods: Vec<FunctionDef>,        // Implemented methods
     pub associated_types: Vec<(String, Type)>, // Implemented associated types
+    pub attributes: Vec<Attribute>,       // Attributes attached to the impl block
 }
 
 /// Represents an actor definition
@@ -452,6 +620,7 @@ pub struct EnumDef {
     pub variants: Vec<VariantDef>,        // Enum variants
     pub access_modifier: AccessModifier,  // Access level (public, private, etc.)
     pub generics: Vec<GenericParam>,      // Generic type parameters with bounds
+    pub attributes: Vec<Attribute>,       // Attributes attached to the enum
 }
 
 /// Represents an enum variant (can have associated data)
@@ -478,6 +647,16 @@ pub struct TypeAliasDef {
     pub generics: Vec<GenericParam>,      // Generic type parameters with bounds
 }
 
+/// Represents a function implemented in another language and called through the FFI
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternDef {
+    pub abi: String,                      // Language the function is written in: "c", "rust", "python", ...
+    pub name: String,                     // Name the function is called by in Logos
+    pub parameters: Vec<Parameter>,       // Typed parameters; their types decide how arguments are passed
+    pub return_type: Option<Type>,        // Return type, or None for Unit
+    pub library: String,                  // Library the function is loaded from
+}
+
 /// Represents different pattern types for pattern matching
 #[derive(Debug, Clone, PartialEq)]
 pub enum Pattern {
@@ -494,6 +673,9 @@ pub enum Pattern {
     Guard(Box<Pattern>, Box<Expression>), // Pattern with guard: pattern if condition
 }
 
+/// One arm of a match: its pattern, optional guard and body
+pub type MatchArm = (Pattern, Option<Box<Expression>>, Vec<Statement>);
+
 
 // Helper functions for creating AST nodes
 impl Program {
@@ -543,7 +725,7 @@ impl Expression {
 
     /// Creates a binary operation expression
     pub fn binary(left: Expression, op: BinaryOp, right: Expression) -> Self {
-        Expression::BinaryOp(Box::new(left), op, Box::new(right))
+        Expression::BinaryOp(Box::new(left), op, Box::new(right), ParsedAt::default())
     }
 
     /// Creates a function call expression
@@ -633,6 +815,721 @@ impl UnaryOp {
     }
 }
 
+// Writing an AST back out as source
+
+/// Writes a program back out as Logos source, one statement per line
+///
+/// Parsing the result gives back the same tree for everything the parser can read.
+/// Constructs it has no syntax for yet (lambdas, lifetimes, `future`/`join`-style
+/// task operations, dependent types, range patterns) are written in the notation the
+/// AST documents for them, so the text still says what the tree holds. Effect
+/// annotations other than declared effects have no notation and are left out, as are
+/// source locations. A plain string containing `${` is written as a concatenation,
+/// since the parser reads any `${` in a literal as a placeholder.
+pub fn unparse(program: &Program) -> String {
+    if program.statements.is_empty() {
+        return String::new();
+    }
+    statements_text(&program.statements, 0) + "\n"
+}
+
+/// Binding strength of an expression, loosest first, following the parser's levels
+const LOOSEST: u8 = 0;     // `await expr` and lambdas take a whole expression
+const ASSIGN: u8 = 1;
+const PIPE: u8 = 2;
+const RANGE: u8 = 3;
+const OR: u8 = 4;
+const AND: u8 = 5;
+const EQUALITY: u8 = 6;
+const COMPARISON: u8 = 7;  // Also `ch <- value`, whose left side is a whole term
+const TERM: u8 = 8;
+const FACTOR: u8 = 9;
+const POWER: u8 = 10;
+const UNARY: u8 = 11;
+const POSTFIX: u8 = 12;
+const PRIMARY: u8 = 13;
+
+fn indent(level: usize) -> String {
+    "    ".repeat(level)
+}
+
+/// Statements one per line at `level`, with `;` where the next line would otherwise
+/// continue the previous one, as `(a, b)` would become a call or `-x` a subtraction
+fn statements_text(statements: &[Statement], level: usize) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    for statement in statements {
+        let text = format!("{}{}", indent(level), statement_text(statement, level));
+        if let Some(previous) = lines.last_mut() {
+            let continues = text.trim_start().starts_with(['(', '[', '{', '-', '*', '!', '<', '.', '?', '&', '\'']);
+            if continues && !previous.ends_with(';') {
+                previous.push(';');
+            }
+        }
+        lines.push(text);
+    }
+    lines.join("\n")
+}
+
+/// A braced block, or `{}` when it is empty
+fn block_text(statements: &[Statement], level: usize) -> String {
+    if statements.is_empty() {
+        return "{}".to_string();
+    }
+    format!("{{\n{}\n{}}}", statements_text(statements, level + 1), indent(level))
+}
+
+/// Members of a class, trait, impl, actor or effect body, one per line
+fn members_text(members: &[String], level: usize) -> String {
+    if members.is_empty() {
+        return "{}".to_string();
+    }
+    let lines: Vec<String> = members.iter().map(|member| format!("{}{}", indent(level + 1), member)).collect();
+    format!("{{\n{}\n{}}}", lines.join("\n"), indent(level))
+}
+
+fn statement_text(statement: &Statement, level: usize) -> String {
+    match statement {
+        Statement::Expression(expr) => {
+            let text = expression_text(expr, level, LOOSEST);
+            // Only an if or match itself may start with its keyword, or it'd be read as that statement
+            let first_word: String = text.chars().take_while(|&c| crate::lexer::is_identifier_continue(c)).collect();
+            let misread = ((text.starts_with("if ") || text.starts_with("match ")) && !matches!(expr, Expression::If(..) | Expression::Match(..)))
+                || text.starts_with('{')
+                || first_word == "assert"
+                || first_word == "assert_eq";
+            if misread { format!("({})", text) } else { text }
+        },
+        Statement::LetBinding { mutable, name, type_annotation, value, ownership_modifier, lifetime_annotation } => {
+            let mut text = format!("{} {}", if *mutable { "mut" } else { "let" }, name);
+            if let Some(lifetime) = lifetime_annotation {
+                text.push_str(&format!(" '{}", lifetime));
+            }
+            if let Some(ty) = type_annotation {
+                text.push_str(&format!(": {}", type_text(ty)));
+            }
+            match ownership_modifier {
+                Some(OwnershipModifier::Borrowed) => text.push_str(" &"),
+                Some(OwnershipModifier::MutablyBorrowed) => text.push_str(" &mut"),
+                Some(OwnershipModifier::Shared) => text.push_str(" *"),
+                _ => {},
+            }
+            format!("{} = {}", text, expression_text(value, level, LOOSEST))
+        },
+        Statement::ConstBinding { name, type_annotation, value } => {
+            let annotation = type_annotation.as_ref().map(|ty| format!(": {}", type_text(ty))).unwrap_or_default();
+            format!("const {}{} = {}", name, annotation, expression_text(value, level, LOOSEST))
+        },
+        Statement::Function(def) => function_text(def, level),
+        Statement::Class(def) => class_text(def, level),
+        Statement::Trait(def) => {
+            let mut head = format!("{}{}trait {}", attributes_text(&def.attributes, level), if def.is_public { "pub " } else { "" }, def.name);
+            if !def.type_params.is_empty() {
+                head.push_str(&format!("<{}>", def.type_params.join(", ")));
+            }
+            if !def.super_traits.is_empty() {
+                head.push_str(&format!(": {}", def.super_traits.join(" + ")));
+            }
+            let mut members: Vec<String> = def.associated_types.iter()
+                .map(|assoc| {
+                    let bounds = if assoc.bounds.is_empty() { String::new() } else { format!(": {}", assoc.bounds.join(" + ")) };
+                    let default = assoc.default.as_ref().map(|ty| format!(" = {}", type_text(ty))).unwrap_or_default();
+                    format!("type {}{}{};", assoc.name, bounds, default)
+                })
+                .collect();
+            members.extend(def.methods.iter().map(|method| function_text(method, level + 1)));
+            format!("{} {}", head, members_text(&members, level))
+        },
+        Statement::Implementation(def) => {
+            let params = if def.type_params.is_empty() { String::new() } else { format!("<{}>", def.type_params.join(", ")) };
+            let head = format!("{}impl{} {} for {}", attributes_text(&def.attributes, level), params, def.trait_name, def.for_type);
+            let mut members: Vec<String> = def.associated_types.iter()
+                .map(|(name, ty)| format!("type {} = {};", name, type_text(ty)))
+                .collect();
+            members.extend(def.methods.iter().map(|method| function_text(method, level + 1)));
+            format!("{} {}", head, members_text(&members, level))
+        },
+        Statement::Actor(def) => {
+            let mut members: Vec<String> = def.state.iter().map(|(name, ty)| format!("{}: {};", name, type_text(ty))).collect();
+            members.extend(def.handlers.iter().map(|handler| function_text(handler, level + 1)));
+            format!("actor {} {}", def.name, members_text(&members, level))
+        },
+        Statement::Effect(def) => {
+            let members: Vec<String> = def.operations.iter().map(|operation| function_text(operation, level + 1)).collect();
+            format!("effect {} {}", def.name, members_text(&members, level))
+        },
+        Statement::Return(Some(expr)) => format!("return {}", expression_text(expr, level, LOOSEST)),
+        Statement::Return(None) => "return;".to_string(),
+        Statement::While { label, condition, body } => {
+            let label = label.as_ref().map(|label| format!("'{}: ", label)).unwrap_or_default();
+            format!("{}while {} {}", label, condition_text(condition, level), block_text(body, level))
+        },
+        Statement::Defer(inner) => format!("defer {}", statement_text(inner, level)),
+        Statement::Assert { actual, expected, message, .. } => {
+            let name = if expected.is_some() { "assert_eq" } else { "assert" };
+            let args: Vec<&Expression> = std::iter::once(actual).chain(expected).chain(message).collect();
+            format!("{}({})", name, list_text(&args, level))
+        },
+        Statement::Break(label) => format!("break{}", label.as_ref().map(|label| format!(" '{}", label)).unwrap_or_default()),
+        Statement::Continue(label) => format!("continue{}", label.as_ref().map(|label| format!(" '{}", label)).unwrap_or_default()),
+        Statement::Block(statements) => block_text(statements, level),
+        Statement::MacroDefinition(def) => {
+            format!("macro {}({}) {}", def.name, def.parameters.join(", "), block_text(&def.body, level))
+        },
+        Statement::Enum(def) => {
+            let public = if def.access_modifier == AccessModifier::Public { "pub " } else { "" };
+            let head = format!("{}{}enum {}{}", attributes_text(&def.attributes, level), public, def.name, generics_text(&def.generics));
+            let variants: Vec<String> = def.variants.iter()
+                .map(|variant| {
+                    let data = match &variant.data {
+                        None | Some(VariantData::Unit) => String::new(),
+                        Some(VariantData::Tuple(types)) => {
+                            format!("({})", types.iter().map(type_text).collect::<Vec<_>>().join(", "))
+                        },
+                        Some(VariantData::Struct(fields)) if fields.is_empty() => " {}".to_string(),
+                        Some(VariantData::Struct(fields)) => {
+                            let fields: Vec<String> = fields.iter().map(|field| format!("{}: {}", field.name, type_text(&field.type_annotation))).collect();
+                            format!(" {{ {} }}", fields.join(", "))
+                        },
+                    };
+                    format!("{}{},", variant.name, data)
+                })
+                .collect();
+            format!("{} {}", head, members_text(&variants, level))
+        },
+        Statement::TypeAlias(def) => {
+            let public = if def.access_modifier == AccessModifier::Public { "pub " } else { "" };
+            format!("{}type {}{} = {}", public, def.name, generics_text(&def.generics), type_text(&def.aliased_type))
+        },
+        Statement::Extern(def) => {
+            let returns = def.return_type.as_ref().map(|ty| format!(" -> {}", type_text(ty))).unwrap_or_default();
+            format!("extern {} fn {}({}){} from {}", quote(&def.abi), def.name, parameters_text(&def.parameters, level), returns, quote(&def.library))
+        },
+    }
+}
+
+/// `#[attr]` lines above an item
+fn attributes_text(attributes: &[Attribute], level: usize) -> String {
+    attributes.iter()
+        .map(|attribute| {
+            let args = if attribute.args.is_empty() { String::new() } else { format!("({})", attribute_args(&attribute.args)) };
+            format!("#[{}{}]\n{}", attribute.name, args, indent(level))
+        })
+        .collect()
+}
+
+pub(crate) fn attribute_args(args: &[AttributeArg]) -> String {
+    let word = |word: &str| if is_identifier(word) || word.parse::<i64>().is_ok() { word.to_string() } else { quote(word) };
+    args.iter()
+        .map(|arg| match arg {
+            AttributeArg::Word(text) => word(text),
+            AttributeArg::KeyValue(key, value) => format!("{} = {}", word(key), quote(value)),
+            AttributeArg::Nested(name, inner) => format!("{}({})", word(name), attribute_args(inner)),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn function_text(def: &FunctionDef, level: usize) -> String {
+    let returns = def.return_type.as_ref().map(|ty| format!(" -> {}", type_text(ty))).unwrap_or_default();
+    format!(
+        "{}{}{}fn {}{}({}){} {}",
+        attributes_text(&def.attributes, level),
+        if def.is_public { "pub " } else { "" },
+        if def.is_async { "async " } else { "" },
+        def.name,
+        generics_text(&def.generic_params),
+        parameters_text(&def.parameters, level),
+        returns + &effects_text(def),
+        block_text(&def.body, level),
+    )
+}
+
+/// A function's `with` clause, naming the effects it declares
+pub(crate) fn effects_text(def: &FunctionDef) -> String {
+    let effects = def.declared_effects();
+    if effects.is_empty() { String::new() } else { format!(" with {}", effects.join(", ")) }
+}
+
+fn parameters_text(parameters: &[Parameter], level: usize) -> String {
+    parameters.iter()
+        .map(|param| {
+            let ownership = match param.ownership_modifier {
+                Some(OwnershipModifier::Borrowed) => "&",
+                Some(OwnershipModifier::MutablyBorrowed) => "&mut ",
+                Some(OwnershipModifier::Shared) => "*",
+                _ => "",
+            };
+            let mutability = if param.mutability == Some(Mutability::Mutable) { "mut " } else { "" };
+            let lifetime = param.lifetime_annotation.as_ref().map(|lifetime| format!(" '{}", lifetime)).unwrap_or_default();
+            let default = param.default_value.as_ref().map(|value| format!(" = {}", expression_text(value, level, LOOSEST))).unwrap_or_default();
+            format!("{}{}{}{}: {}{}", ownership, mutability, param.name, lifetime, type_text(&param.type_annotation), default)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// `<T: Bound + Other = Default, U>`, or nothing when there are no parameters
+fn generics_text(generics: &[GenericParam]) -> String {
+    if generics.is_empty() {
+        return String::new();
+    }
+    let params: Vec<String> = generics.iter()
+        .map(|param| {
+            let mut text = param.name.clone();
+            if !param.bounds.is_empty() {
+                text.push_str(&format!(": {}", param.bounds.join(" + ")));
+            }
+            if let Some(default) = &param.default_type {
+                text.push_str(&format!(" = {}", type_text(default)));
+            }
+            text
+        })
+        .collect();
+    format!("<{}>", params.join(", "))
+}
+
+/// A class, or a struct when it is what the parser makes of one: public, immutable fields and nothing else
+fn class_text(def: &ClassDef, level: usize) -> String {
+    let plain_fields = def.fields.iter().all(|field| {
+        field.access_modifier == AccessModifier::Public && !field.is_mutable && !field.is_static && field.default_value.is_none()
+    });
+    let is_struct = plain_fields && def.methods.is_empty() && def.constructors.is_empty() && def.destructors.is_empty()
+        && def.parent.is_none() && !def.is_abstract && def.interfaces.is_empty() && def.access_modifier == AccessModifier::Public
+        && def.generics.iter().all(|param| param.bounds.is_empty() && param.default_type.is_none());
+
+    let attributes = attributes_text(&def.attributes, level);
+    if is_struct {
+        let fields: Vec<String> = def.fields.iter().map(|field| format!("{}: {},", field.name, type_text(&field.type_annotation))).collect();
+        return format!("{}struct {}{} {}", attributes, def.name, generics_text(&def.generics), members_text(&fields, level));
+    }
+
+    let mut head = format!(
+        "{}{}{}class {}{}",
+        attributes,
+        if def.access_modifier == AccessModifier::Public { "pub " } else { "" },
+        if def.is_abstract { "abstract " } else { "" },
+        def.name,
+        generics_text(&def.generics),
+    );
+    if let Some(parent) = &def.parent {
+        head.push_str(&format!(" : {}", parent));
+    }
+    if !def.interfaces.is_empty() {
+        head.push_str(&format!(" impl {}", def.interfaces.join(", ")));
+    }
+
+    let mut members: Vec<String> = def.fields.iter()
+        .map(|field| {
+            let default = field.default_value.as_ref().map(|value| format!(" = {}", expression_text(value, level + 1, LOOSEST))).unwrap_or_default();
+            format!(
+                "{}{}{} {}: {}{};",
+                if field.access_modifier == AccessModifier::Public { "pub " } else { "" },
+                if field.is_static { "static " } else { "" },
+                if field.is_mutable { "mut" } else { "let" },
+                field.name,
+                type_text(&field.type_annotation),
+                default,
+            )
+        })
+        .collect();
+    // The parser sorts methods named `new` and `drop` into constructors and destructors
+    members.extend(def.constructors.iter().map(|constructor| {
+        format!("fn new({}) {}", parameters_text(&constructor.parameters, level + 1), block_text(&constructor.body, level + 1))
+    }));
+    members.extend(def.destructors.iter().map(|destructor| format!("fn drop() {}", block_text(&destructor.body, level + 1))));
+    members.extend(def.methods.iter().map(|method| function_text(method, level + 1)));
+    format!("{} {}", head, members_text(&members, level))
+}
+
+/// A type as it is written in annotations
+fn type_text(ty: &Type) -> String {
+    match ty {
+        Type::Int | Type::Float | Type::Bool | Type::String | Type::Unit | Type::Universe(_) | Type::Infer => ty.to_string(),
+        Type::Named(name) | Type::Generic(name) => name.clone(),
+        Type::GenericWithBounds { name, bounds } if bounds.is_empty() => name.clone(),
+        Type::GenericWithBounds { name, bounds } => format!("{}: {}", name, bounds.join(" + ")),
+        Type::Dyn(name) => format!("dyn {}", name),
+        Type::Array(inner) => format!("[{}]", type_text(inner)),
+        Type::Tuple(types) if types.len() == 1 => format!("({},)", type_text(&types[0])),
+        Type::Tuple(types) => format!("({})", types.iter().map(type_text).collect::<Vec<_>>().join(", ")),
+        Type::Function(params, ret) => {
+            format!("({}) -> {}", params.iter().map(type_text).collect::<Vec<_>>().join(", "), type_text(ret))
+        },
+        Type::Channel(inner) => format!("Channel<{}>", type_text(inner)),
+        Type::Option(inner) => format!("Option<{}>", type_text(inner)),
+        Type::Set(inner) => format!("Set<{}>", type_text(inner)),
+        Type::Map(key, value) => format!("Map<{}, {}>", type_text(key), type_text(value)),
+        Type::Result(ok, err) => format!("Result<{}, {}>", type_text(ok), type_text(err)),
+        Type::Pi(param, ret) => format!("({}: {}) -> {}", param.name, type_text(&param.type_annotation), type_text(ret)),
+        Type::Sigma(param, snd) => format!("({}: {}, {})", param.name, type_text(&param.type_annotation), type_text(snd)),
+        Type::Equality(ty, left, right) => {
+            format!("{} =_{} {}", expression_text(left, 0, PRIMARY), type_text(ty), expression_text(right, 0, PRIMARY))
+        },
+        Type::Linear(inner) => format!("!{}", type_text(inner)),
+    }
+}
+
+/// How tightly an expression binds, so the printer knows where parentheses are needed
+fn precedence(expr: &Expression) -> u8 {
+    match expr {
+        Expression::Await(_) | Expression::Lambda(..) | Expression::LambdaSimple(..) | Expression::DestructureAssignment(..) => LOOSEST,
+        Expression::Call(name, args) if assignment_target(name, args).is_some() => ASSIGN,
+        Expression::Pipeline(..) | Expression::BackPipeline(..) => PIPE,
+        Expression::BinaryOp(_, op, _, _) => binary_operator(op).1,
+        Expression::ChannelSend(..) => COMPARISON,
+        Expression::UnaryOp(..) | Expression::ChannelReceive(_) => UNARY,
+        // A negative literal is written with a leading minus
+        Expression::Integer(value) if *value < 0 => UNARY,
+        Expression::Float(value) if value.is_finite() && value.is_sign_negative() => UNARY,
+        Expression::Call(..) | Expression::MacroInvocation(..) | Expression::MethodCall(..) | Expression::FieldAccess(..)
+            | Expression::Propagate(_) | Expression::Index(..) | Expression::Slice(..) => POSTFIX,
+        _ => PRIMARY,
+    }
+}
+
+fn binary_operator(op: &BinaryOp) -> (&'static str, u8) {
+    match op {
+        BinaryOp::PipeForward => ("|>", PIPE),
+        BinaryOp::PipeBackward => ("<|", PIPE),
+        BinaryOp::Range => ("..", RANGE),
+        BinaryOp::Or => ("||", OR),
+        BinaryOp::And => ("&&", AND),
+        BinaryOp::Eq => ("==", EQUALITY),
+        BinaryOp::Ne => ("!=", EQUALITY),
+        BinaryOp::Lt => ("<", COMPARISON),
+        BinaryOp::Gt => (">", COMPARISON),
+        BinaryOp::Le => ("<=", COMPARISON),
+        BinaryOp::Ge => (">=", COMPARISON),
+        BinaryOp::Spaceship => ("<>", COMPARISON),
+        BinaryOp::Add => ("+", TERM),
+        BinaryOp::Sub => ("-", TERM),
+        BinaryOp::Mul => ("*", FACTOR),
+        BinaryOp::Div => ("/", FACTOR),
+        BinaryOp::Mod => ("%", FACTOR),
+        BinaryOp::Power => ("^", POWER),
+    }
+}
+
+/// The parser reads `x = value` as a call to `assign` with the target's name as a string
+fn assignment_target<'e>(name: &str, args: &'e [Expression]) -> Option<(&'e str, &'e Expression)> {
+    match args {
+        [Expression::String(target), value] if name == "assign" && target.split("::").all(is_identifier) => Some((target, value)),
+        _ => None,
+    }
+}
+
+/// An expression, parenthesized if it binds more loosely than `min`
+fn expression_text(expr: &Expression, level: usize, min: u8) -> String {
+    if precedence(expr) < min {
+        return format!("({})", expression_text(expr, level, LOOSEST));
+    }
+
+    match expr {
+        // The lexer reads the digits before applying the minus, and i64::MIN's digits don't fit
+        Expression::Integer(i64::MIN) => "(-9223372036854775807 - 1)".to_string(),
+        Expression::Integer(value) => value.to_string(),
+        Expression::Float(value) => float_text(*value),
+        Expression::String(value) if value.contains("${") => {
+            // End each piece before the `{`, so no literal holds a whole `${`
+            let pieces: Vec<String> = value.split("${").map(str::to_string).collect();
+            let last = pieces.len() - 1;
+            let literals: Vec<String> = pieces.iter().enumerate()
+                .map(|(i, piece)| {
+                    let open = if i > 0 { "{" } else { "" };
+                    let dollar = if i < last { "$" } else { "" };
+                    quote(&format!("{}{}{}", open, piece, dollar))
+                })
+                .collect();
+            format!("({})", literals.join(" + "))
+        },
+        Expression::String(value) => quote(value),
+        Expression::InterpolatedString(parts) => interpolated_text(parts),
+        Expression::Bytes(bytes) => format!("b\"{}\"", crate::lexer::escape_bytes(bytes)),
+        Expression::Boolean(value) => value.to_string(),
+        Expression::Nil => "nil".to_string(),
+        Expression::Char(value) => format!("'{}'", escape_char(*value)),
+        Expression::Identifier(name) => name.clone(),
+        Expression::Array(items) => format!("[{}]", list_text(items, level)),
+        Expression::ArrayRepeat(value, length) => {
+            format!("[{}; {}]", expression_text(value, level, LOOSEST), expression_text(length, level, LOOSEST))
+        },
+        Expression::Tuple(items) if items.len() == 1 => format!("({},)", expression_text(&items[0], level, LOOSEST)),
+        Expression::Tuple(items) => format!("({})", list_text(items, level)),
+        Expression::Struct(name, fields) if fields.is_empty() => format!("{} {{}}", name),
+        Expression::Struct(name, fields) => {
+            let fields: Vec<String> = fields.iter()
+                .map(|(field, value)| format!("{}: {}", field, expression_text(value, level, LOOSEST)))
+                .collect();
+            format!("{} {{ {} }}", name, fields.join(", "))
+        },
+        Expression::Lambda(params, body) => format!("|{}| {}", parameters_text(params, level), block_text(body, level)),
+        Expression::LambdaSimple(params, body) => format!("|{}| {}", params.join(", "), expression_text(body, level, LOOSEST)),
+        Expression::BinaryOp(left, op, right, _) => {
+            let (symbol, prec) = binary_operator(op);
+            // Range and power group to the right, everything else to the left; the
+            // left side of `^` can't be a unary operation, which would take the `^` in
+            let (left_min, right_min) = match op {
+                BinaryOp::Range => (prec + 1, prec),
+                BinaryOp::Power => (POSTFIX, prec),
+                _ => (prec, prec + 1),
+            };
+            format!("{} {} {}", expression_text(left, level, left_min), symbol, expression_text(right, level, right_min))
+        },
+        Expression::UnaryOp(op, operand) => {
+            let symbol = match op {
+                UnaryOp::Neg => "-",
+                UnaryOp::Not => "!",
+                UnaryOp::Ref => "&",
+                UnaryOp::Deref => "*",
+            };
+            prefixed(symbol, expression_text(operand, level, POWER))
+        },
+        Expression::Call(name, args) => match assignment_target(name, args) {
+            Some((target, value)) => format!("{} = {}", target, expression_text(value, level, ASSIGN)),
+            None => format!("{}({})", name, list_text(args, level)),
+        },
+        Expression::MacroInvocation(name, args) => format!("{}!({})", name, list_text(args, level)),
+        Expression::MethodCall(receiver, method, args) => {
+            format!("{}.{}({})", receiver_text(receiver, level), method, list_text(args, level))
+        },
+        Expression::FieldAccess(target, field) => format!("{}.{}", receiver_text(target, level), field),
+        Expression::Propagate(inner) => format!("{}?", receiver_text(inner, level)),
+        Expression::Index(target, index) => format!("{}[{}]", receiver_text(target, level), expression_text(index, level, LOOSEST)),
+        Expression::Slice(target, start, end) => format!(
+            "{}[{}..{}]",
+            receiver_text(target, level),
+            expression_text(start, level, RANGE + 1),
+            expression_text(end, level, RANGE),
+        ),
+        Expression::Spawn(name, args) => format!("spawn {}({})", name, list_text(args, level)),
+        Expression::Send(actor, message) => {
+            format!("send({}, {})", expression_text(actor, level, LOOSEST), expression_text(message, level, LOOSEST))
+        },
+        Expression::Receive => "receive".to_string(),
+        Expression::If(condition, then_branch, else_branch) => if_text(condition, then_branch, else_branch, level),
+        Expression::Match(scrutinee, arms) => {
+            let arms: Vec<String> = arms.iter()
+                .map(|(pattern, guard, body)| {
+                    let guard = guard.as_ref().map(|guard| format!(" if {}", expression_text(guard, level + 1, LOOSEST))).unwrap_or_default();
+                    format!("{}{} => {}", pattern_text(pattern), guard, arm_body_text(body, level + 1))
+                })
+                .collect();
+            format!("match {} {}", condition_text(scrutinee, level), members_text(&arms, level))
+        },
+        Expression::Select(arms) => {
+            let arms: Vec<String> = arms.iter()
+                .map(|arm| {
+                    let operation = match &arm.channel_operation {
+                        ChannelOperation::Send { channel, value } => {
+                            format!("{}.send({})", receiver_text(channel, level + 1), expression_text(value, level + 1, LOOSEST))
+                        },
+                        ChannelOperation::Receive { channel } => format!("{}.recv()", receiver_text(channel, level + 1)),
+                        ChannelOperation::Close { channel } => format!("{}.close()", receiver_text(channel, level + 1)),
+                        ChannelOperation::Default => "default".to_string(),
+                        ChannelOperation::Timeout { millis } => format!("timeout({})", expression_text(millis, level + 1, LOOSEST)),
+                    };
+                    let binding = arm.pattern.as_ref().map(|pattern| format!("{} = ", pattern_text(pattern))).unwrap_or_default();
+                    format!("{}{} => {}", binding, operation, arm_body_text(&arm.body, level + 1))
+                })
+                .collect();
+            format!("select {}", members_text(&arms, level))
+        },
+        Expression::BlockExpr(statements) | Expression::Block(statements) => block_text(statements, level),
+        Expression::TryCatch(body, name, handler) => {
+            format!("try {} catch ({}) {}", block_text(body, level), name, block_text(handler, level))
+        },
+        Expression::MultiLangCall(lang, code) => format!("@{}{{{}}}", lang, code),
+        Expression::MultiLangImport(_, resource, _) => format!("@import({})", quote(resource)),
+        Expression::MultiLangIndex(_, resource) => format!("@index({})", quote(resource)),
+        Expression::Pipeline(value, functions) => {
+            let mut text = expression_text(value, level, PIPE);
+            for function in functions {
+                text.push_str(&format!(" |> {}", expression_text(function, level, PIPE + 1)));
+            }
+            text
+        },
+        Expression::BackPipeline(value, functions) => {
+            let mut parts: Vec<String> = functions.iter().rev().map(|function| expression_text(function, level, PIPE + 1)).collect();
+            parts.push(expression_text(value, level, PIPE + 1));
+            parts.join(" <| ")
+        },
+        Expression::DestructureAssignment(pattern, value, body) => {
+            format!("let {} = {} in {}", pattern_text(pattern), expression_text(value, level, LOOSEST), statement_text(body, level))
+        },
+        Expression::ChannelCreate(element) => format!("chan {}", type_text(element)),
+        Expression::ChannelSend(channel, value) => {
+            format!("{} <- {}", expression_text(channel, level, TERM), expression_text(value, level, FACTOR))
+        },
+        Expression::ChannelReceive(channel) => prefixed("<-", expression_text(channel, level, POWER)),
+        Expression::ChannelClose(channel) => format!("close({})", expression_text(channel, level, LOOSEST)),
+        Expression::AsyncBlock(statements) => format!("async {}", block_text(statements, level)),
+        Expression::Await(inner) => format!("await {}", expression_text(inner, level, LOOSEST)),
+        Expression::Future(inner) => format!("future({})", expression_text(inner, level, LOOSEST)),
+        Expression::SpawnTask(inner) => format!("spawn_task({})", expression_text(inner, level, LOOSEST)),
+        Expression::Join(inner) => format!("join({})", expression_text(inner, level, LOOSEST)),
+        Expression::Race(futures) => format!("race({})", list_text(futures, level)),
+        Expression::Timeout(inner, duration) => {
+            format!("timeout({}, {})", expression_text(inner, level, LOOSEST), expression_text(duration, level, LOOSEST))
+        },
+    }
+}
+
+/// A prefix operator and its operand, kept apart where they'd lex as something else (`- -x`)
+fn prefixed(symbol: &str, operand: String) -> String {
+    let space = if operand.starts_with(['-', '*', '!', '&']) { " " } else { "" };
+    format!("{}{}{}", symbol, space, operand)
+}
+
+/// Comma-separated expressions, as in an argument list
+fn list_text<E: std::borrow::Borrow<Expression>>(items: &[E], level: usize) -> String {
+    items.iter().map(|item| expression_text(item.borrow(), level, LOOSEST)).collect::<Vec<_>>().join(", ")
+}
+
+/// The expression before `.`, `[` or `?`
+fn receiver_text(expr: &Expression, level: usize) -> String {
+    // `1.abs()` would lex as the float `1.`
+    if matches!(expr, Expression::Integer(_) | Expression::Float(_)) {
+        return format!("({})", expression_text(expr, level, LOOSEST));
+    }
+    expression_text(expr, level, POSTFIX)
+}
+
+/// An if or while condition or match scrutinee, parenthesized where the brace after
+/// it could be read as part of it, as in `if Ready {}`
+fn condition_text(expr: &Expression, level: usize) -> String {
+    let text = expression_text(expr, level, LOOSEST);
+    let trailing: String = text.chars().rev().take_while(|&c| crate::lexer::is_identifier_continue(c)).collect();
+    let ends_with_type_name = trailing.chars().next_back().map_or(false, char::is_uppercase);
+    if ends_with_type_name || text.contains('{') {
+        format!("({})", text)
+    } else {
+        text
+    }
+}
+
+fn if_text(condition: &Expression, then_branch: &[Statement], else_branch: &[Statement], level: usize) -> String {
+    let mut text = format!("if {} {}", condition_text(condition, level), block_text(then_branch, level));
+    match else_branch {
+        [] => {},
+        [Statement::Expression(Expression::If(condition, then_branch, else_branch))] => {
+            text.push_str(" else ");
+            text.push_str(&if_text(condition, then_branch, else_branch, level));
+        },
+        statements => {
+            text.push_str(" else ");
+            text.push_str(&block_text(statements, level));
+        },
+    }
+    text
+}
+
+/// The body of a match or select arm: a single expression followed by a comma, or a block
+fn arm_body_text(body: &[Statement], level: usize) -> String {
+    match body {
+        [Statement::Expression(value)] if !matches!(value, Expression::Block(_) | Expression::BlockExpr(_)) => {
+            let text = expression_text(value, level, LOOSEST);
+            // A leading brace would be read as the arm's block
+            if text.starts_with('{') { format!("({}),", text) } else { format!("{},", text) }
+        },
+        statements => block_text(statements, level),
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&pattern_text(self))
+    }
+}
+
+fn pattern_text(pattern: &Pattern) -> String {
+    let list = |patterns: &[Pattern]| patterns.iter().map(pattern_text).collect::<Vec<_>>().join(", ");
+    match pattern {
+        Pattern::Identifier(name) => name.clone(),
+        Pattern::Literal(literal) => expression_text(literal, 0, LOOSEST),
+        Pattern::Wildcard => "_".to_string(),
+        Pattern::Tuple(patterns) => format!("({})", list(patterns)),
+        Pattern::Array(patterns) => format!("[{}]", list(patterns)),
+        Pattern::Struct(name, fields) if fields.is_empty() => format!("{} {{}}", name),
+        Pattern::Struct(name, fields) => {
+            let fields: Vec<String> = fields.iter().map(|(field, pattern)| format!("{}: {}", field, pattern_text(pattern))).collect();
+            format!("{} {{ {} }}", name, fields.join(", "))
+        },
+        Pattern::Or(left, right) => format!("{} | {}", pattern_text(left), pattern_text(right)),
+        Pattern::Enum(name, variant, None) => format!("{}::{}", name, variant),
+        Pattern::Enum(name, variant, Some(patterns)) => format!("{}::{}({})", name, variant, list(patterns)),
+        Pattern::Range(low, high) => format!("{}..{}", low, high),
+        Pattern::Irrefutable(inner) => pattern_text(inner),
+        Pattern::Guard(inner, condition) => format!("{} if {}", pattern_text(inner), expression_text(condition, 0, LOOSEST)),
+    }
+}
+
+/// The text of an interpolated string, whose placeholders hold expressions as source
+fn interpolated_text(parts: &[StringPart]) -> String {
+    let mut text = String::new();
+    for part in parts {
+        match part {
+            StringPart::Literal(literal) => text.push_str(literal),
+            StringPart::Interpolated(expr, spec) => {
+                text.push_str("${");
+                text.push_str(&expression_text(expr, 0, LOOSEST));
+                if let Some(spec) = spec {
+                    text.push_str(&format!(":{}", spec));
+                }
+                text.push('}');
+            },
+        }
+    }
+    quote(&text)
+}
+
+/// A float literal that reads back as the same value; there are no literals for
+/// infinities and NaN, so those are written as the divisions that produce them
+fn float_text(value: f64) -> String {
+    if value.is_nan() {
+        return "(0.0 / 0.0)".to_string();
+    }
+    if value.is_infinite() {
+        return if value > 0.0 { "(1.0 / 0.0)" } else { "(-1.0 / 0.0)" }.to_string();
+    }
+    // Display never uses an exponent and prints the shortest digits that round-trip
+    let text = value.to_string();
+    if text.contains('.') { text } else { format!("{}.0", text) }
+}
+
+pub(crate) fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars.next().map_or(false, crate::lexer::is_identifier_start) && chars.all(crate::lexer::is_identifier_continue)
+}
+
+/// A string literal holding `text`
+pub(crate) fn quote(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+pub(crate) fn escape_char(c: char) -> String {
+    match c {
+        '\'' => "\\'".to_string(),
+        '\\' => "\\\\".to_string(),
+        '\n' => "\\n".to_string(),
+        '\t' => "\\t".to_string(),
+        '\r' => "\\r".to_string(),
+        c => c.to_string(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -645,6 +1542,8 @@ mod tests {
                 name: "x".to_string(),
                 type_annotation: Some(Type::int()),
                 value: Expression::int(42),
+                ownership_modifier: None,
+                lifetime_annotation: None,
             },
             Statement::expr(Expression::call(
                 "print".to_string(),
@@ -664,5 +1563,140 @@ mod tests {
             _ => panic!("Expected LetBinding"),
         }
     }
+
+    /// The parsed statements, minus the source locations assertions and operators record
+    fn shape(source: &str) -> String {
+        let statements = crate::parser::Parser::new(source).parse_program().unwrap().statements;
+        debug_without_locations(&statements)
+    }
+
+    #[test]
+    fn test_unparsed_programs_parse_back_the_same() {
+        let snippets = r#"
+            #[inline(always)]
+            pub fn area(&shape: Shape, scale: Float) -> Float { return shape.width * scale }
+            fn nothing() { return; }
+            let total: Map<String, [Option<Int>]> = build(1, -2.5, 100000000000000000000.0, 0.1)
+            mut count = 0; const LIMIT: Int = 10
+            count = count + 1
+            let flags = !a && (b || c) && -x ^ 2 == (-x) ^ 2 && a - (b - c) == a - b - c
+            let cmp = a <> b; let r = 1..n + 1; let s = xs[i + 1..j]; let e = xs[0][1]
+            let piped = data |> parse |> check; let p = (a |> f)..(b |> g)
+            let t = (1,); let u = (); let v = (a, "b", 'c', '\n', b"\x00ok", nil, true)
+            let text = "tab\t\"quoted\" ${n + 1:>8.2} and ${items.len()} ${label(\"x\")}"
+            let point = Point { x: 1, y: Point { x: 2, y: 3 } }; let empty = Unit {}
+            if Point { x: 1 }.x > 0 { print("yes") } else if ready { print("no") } else {}
+            let kind = match value {
+                0 | 1 => "small",
+                Point { x: 0, y: _ } if strict => { log("origin"); "origin" }
+                (a, [b, c]) => "nested",
+                "text" => { { inner() } },
+                _ => -1,
+            }
+            'outer: while i < 10 { if i == 3 { continue 'outer }; break 'outer }
+            while (Done) {}
+            defer close_file(f)
+            assert(total > 0, "positive"); assert_eq(total, 3)
+            { let scoped = 1; -scoped }
+            ({ 1 } + 2);
+            (if a { 1 } else { 2 }) + 3
+            struct Pair<T> { left: T, right: T }
+            class pub Animal : Base impl Speak, Walk {
+                mut legs: Int = 4;
+                let name: String;
+                pub let id: Int;
+                fn new(name: String) { legs = 4 }
+                fn drop() { print("bye") }
+                #[inline]
+                fn speak(&mut me: Animal) -> String { me.name }
+            }
+            trait Speak { fn speak() -> String { "" } }
+            impl Speak for Dog { fn speak() -> String { "woof" } }
+            actor Counter { count: Int; fn increment() { count = count + 1 } }
+            effect Log { fn log(message: String) {} }
+            macro twice(e) { e; e }
+            let m = max!(1, 2)
+            #[derive(Debug)]
+            pub enum Shape<T: Num + Copy> { Circle(Float), Rect { w: T, h: T }, Empty }
+            type pub Grid<T> = Map<T, [dyn Cell]>
+            extern "c" fn c_sqrt(x: Float) -> Float from "libm"
+            let value = try { risky()?.unwrap() } catch (err) { 0 }
+            let ch = chan Int; ch <- 1 * 2; let got = <-ch; let neg = - -1
+            let picked = select { job = jobs.recv() => run(job), out.send(1) => {}, timeout(100) => idle(), }
+            let actor_ref = spawn Counter(0); send(actor_ref, "increment")
+            let fut = async { await fetch(url) }
+            let imported = @import("https://github.com/logos/lib"); let indexed = @index("docs")
+            let calls = (1).abs() + (2.5).floor() + xs.len()
+            let r: Result<Int, String> = Ok(1); let c: Channel<Int> = chan Int
+        "#;
+        let mut sources = vec![("snippets".to_string(), snippets.to_string())];
+        let examples = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+        for entry in std::fs::read_dir(examples).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().map_or(false, |ext| ext == "logos") {
+                sources.push((path.display().to_string(), std::fs::read_to_string(&path).unwrap()));
+            }
+        }
+
+        for (name, source) in &sources {
+            // Examples using syntax the parser doesn't accept have no tree to print
+            let program = match crate::parser::Parser::new(source).parse_program() {
+                Ok(program) => program,
+                Err(err) => {
+                    assert_ne!(name, "snippets", "the snippets should parse: {}", err.message);
+                    continue;
+                },
+            };
+            // Embedded foreign code is held as the parser glued its tokens together, which
+            // isn't the code as written, so it doesn't read back the same
+            if shape(source).contains("MultiLangCall") {
+                continue;
+            }
+            let printed = unparse(&program);
+            assert!(crate::parser::Parser::new(&printed).parse_program().is_ok(), "unparsing {} gave invalid source:\n{}", name, printed);
+            assert_eq!(shape(&printed), shape(source), "unparsing {} changed its meaning:\n{}", name, printed);
+            let reparsed = crate::parser::Parser::new(&printed).parse_program().unwrap();
+            assert_eq!(unparse(&reparsed), printed, "unparsing {} again changed it", name);
+        }
+    }
+
+    #[test]
+    fn test_constructs_without_syntax_use_the_documented_notation() {
+        let x = || Box::new(Expression::Identifier("x".to_string()));
+        let param = Parameter {
+            name: "x".to_string(),
+            type_annotation: Type::Tuple(vec![Type::Int, Type::Linear(Box::new(Type::String))]),
+            ownership_modifier: None,
+            lifetime_annotation: Some("a".to_string()),
+            default_value: None,
+            mutability: None,
+        };
+        let program = Program::new(vec![
+            Statement::expr(Expression::Lambda(vec![param], vec![Statement::expr(Expression::Future(x()))])),
+            Statement::expr(Expression::Match(x(), vec![
+                (Pattern::Enum("Shape".to_string(), "Circle".to_string(), Some(vec![Pattern::Wildcard])), None, vec![Statement::expr(Expression::int(1))]),
+                (Pattern::Guard(Box::new(Pattern::Range(0, 9)), x()), None, vec![]),
+            ])),
+            Statement::expr(Expression::binary(Expression::float(f64::NAN), BinaryOp::Mul, Expression::float(-0.5))),
+            Statement::expr(Expression::string("costs ${price}".to_string())),
+        ]);
+
+        assert_eq!(
+            unparse(&program),
+            "|x 'a: (Int, !String)| {\n    future(x)\n}\nmatch x {\n    Shape::Circle(_) => 1,\n    0..9 if x => {}\n};\n\
+             (0.0 / 0.0) * -0.5;\n(\"costs $\" + \"{price}\")\n"
+        );
+
+        // The concatenation still holds the text the string did, rather than a placeholder
+        let concatenation = crate::parser::Parser::new("\"costs $\" + \"{price}\"").parse_program().unwrap();
+        assert_eq!(
+            concatenation.statements,
+            vec![Statement::expr(Expression::binary(
+                Expression::string("costs $".to_string()),
+                BinaryOp::Add,
+                Expression::string("{price}".to_string()),
+            ))]
+        );
+    }
 }
 // Additional definitions for missing expression types
@@ -0,0 +1,369 @@
+// Logos Item Attributes
+// Gives meaning to the `#[...]` attributes the parser attaches to declarations:
+// `#[cfg(...)]` removes items that are not configured in, `#[test]` marks functions
+// for the test runner, `#[inline]` is a hint read by the function inliner, and
+// `#[allow(missing_docs)]` exempts a public item from `logos doc --check`.
+// Attributes the compiler doesn't recognise produce warnings rather than errors.
+
+use crate::ast::*;
+use std::collections::HashSet;
+
+/// Attribute names the compiler understands; anything else is warned about and ignored
+pub const KNOWN_ATTRIBUTES: &[&str] = &["allow", "automatically_derived", "cfg", "derive", "inline", "test"];
+
+/// The set of configuration options `#[cfg(...)]` predicates are evaluated against
+#[derive(Debug, Clone, Default)]
+pub struct CfgSet {
+    flags: HashSet<String>,
+    values: HashSet<(String, String)>,
+}
+
+impl CfgSet {
+    /// Creates an empty configuration, as used by a plain `logos run`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates the configuration used when running tests, with the `test` flag set
+    pub fn for_tests() -> Self {
+        Self::new().with_flag("test")
+    }
+
+    /// Enables a bare flag such as `test`
+    pub fn with_flag(mut self, flag: &str) -> Self {
+        self.flags.insert(flag.to_string());
+        self
+    }
+
+    /// Enables a key-value option such as `feature = "json"`
+    pub fn with_value(mut self, key: &str, value: &str) -> Self {
+        self.values.insert((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Evaluates a single cfg predicate
+    pub fn eval(&self, predicate: &AttributeArg) -> Result<bool, String> {
+        match predicate {
+            AttributeArg::Word(flag) => Ok(self.flags.contains(flag)),
+            AttributeArg::KeyValue(key, value) => Ok(self.values.contains(&(key.clone(), value.clone()))),
+            AttributeArg::Nested(op, args) => match op.as_str() {
+                "not" => {
+                    if args.len() != 1 {
+                        return Err(format!("cfg(not(...)) takes exactly one predicate, found {}", args.len()));
+                    }
+                    Ok(!self.eval(&args[0])?)
+                },
+                "all" => {
+                    for arg in args {
+                        if !self.eval(arg)? {
+                            return Ok(false);
+                        }
+                    }
+                    Ok(true)
+                },
+                "any" => {
+                    for arg in args {
+                        if self.eval(arg)? {
+                            return Ok(true);
+                        }
+                    }
+                    Ok(false)
+                },
+                other => Err(format!("Unknown cfg predicate: {}", other)),
+            },
+        }
+    }
+
+    /// Returns whether an item with these attributes is configured in
+    ///
+    /// Every `#[cfg(...)]` on the item must hold; an item without one is always active.
+    pub fn is_active(&self, attributes: &[Attribute]) -> Result<bool, String> {
+        for attribute in attributes.iter().filter(|attr| attr.name == "cfg") {
+            if attribute.args.len() != 1 {
+                return Err(format!("#[cfg] takes exactly one predicate, found {}", attribute.args.len()));
+            }
+            if !self.eval(&attribute.args[0])? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Returns the attributes attached to a declaration, or an empty slice for other statements
+pub fn item_attributes(statement: &Statement) -> &[Attribute] {
+    match statement {
+        Statement::Function(def) => &def.attributes,
+        Statement::Class(def) => &def.attributes,
+        Statement::Trait(def) => &def.attributes,
+        Statement::Implementation(def) => &def.attributes,
+        Statement::Enum(def) => &def.attributes,
+        _ => &[],
+    }
+}
+
+/// Removes the items and methods whose `#[cfg(...)]` doesn't hold under `cfg`
+pub fn strip_cfg(program: Program, cfg: &CfgSet) -> Result<Program, String> {
+    let mut statements = Vec::with_capacity(program.statements.len());
+
+    for statement in program.statements {
+        statements.extend(strip_cfg_statement(statement, cfg)?);
+    }
+
+    Ok(Program { statements })
+}
+
+/// Applies `strip_cfg` to one top-level statement, returning `None` if it is configured out
+pub fn strip_cfg_statement(statement: Statement, cfg: &CfgSet) -> Result<Option<Statement>, String> {
+    if !cfg.is_active(item_attributes(&statement))? {
+        return Ok(None);
+    }
+
+    Ok(Some(match statement {
+        Statement::Class(mut def) => {
+            def.methods = strip_methods(def.methods, cfg)?;
+            Statement::Class(def)
+        },
+        Statement::Trait(mut def) => {
+            def.methods = strip_methods(def.methods, cfg)?;
+            Statement::Trait(def)
+        },
+        Statement::Implementation(mut def) => {
+            def.methods = strip_methods(def.methods, cfg)?;
+            Statement::Implementation(def)
+        },
+        other => other,
+    }))
+}
+
+/// Filters a method list by each method's own `#[cfg(...)]`
+fn strip_methods(methods: Vec<FunctionDef>, cfg: &CfgSet) -> Result<Vec<FunctionDef>, String> {
+    let mut kept = Vec::with_capacity(methods.len());
+    for method in methods {
+        if cfg.is_active(&method.attributes)? {
+            kept.push(method);
+        }
+    }
+    Ok(kept)
+}
+
+/// Returns the top-level functions marked `#[test]`, in source order
+pub fn find_tests(program: &Program) -> Vec<&FunctionDef> {
+    program.statements.iter()
+        .filter_map(|statement| match statement {
+            Statement::Function(def) if find_attribute(&def.attributes, "test").is_some() => Some(def),
+            _ => None,
+        })
+        .collect()
+}
+
+/// How strongly a function asks to be inlined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InlineHint {
+    Default, // No #[inline]; the inliner decides by size
+    Hint,    // #[inline]
+    Always,  // #[inline(always)]
+    Never,   // #[inline(never)]
+}
+
+/// Reads the `#[inline]` attribute of a function
+pub fn inline_hint(def: &FunctionDef) -> InlineHint {
+    match find_attribute(&def.attributes, "inline") {
+        None => InlineHint::Default,
+        Some(attr) if attr.has_word("always") => InlineHint::Always,
+        Some(attr) if attr.has_word("never") => InlineHint::Never,
+        Some(_) => InlineHint::Hint,
+    }
+}
+
+/// Collects a warning for each unknown attribute on the given top-level statements
+pub fn check_attributes<'s>(statements: impl IntoIterator<Item = &'s Statement>) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    for statement in statements {
+        let (item, methods): (&str, &[FunctionDef]) = match statement {
+            Statement::Function(def) => (&def.name, &[]),
+            Statement::Class(def) => (&def.name, &def.methods),
+            Statement::Trait(def) => (&def.name, &def.methods),
+            Statement::Implementation(def) => (&def.for_type, &def.methods),
+            Statement::Enum(def) => (&def.name, &[]),
+            _ => continue,
+        };
+
+        warn_unknown(item_attributes(statement), item, &mut warnings);
+        for method in methods {
+            warn_unknown(&method.attributes, &method.name, &mut warnings);
+        }
+    }
+
+    warnings
+}
+
+fn warn_unknown(attributes: &[Attribute], item: &str, warnings: &mut Vec<String>) {
+    for attribute in attributes {
+        if !KNOWN_ATTRIBUTES.contains(&attribute.name.as_str()) {
+            warnings.push(format!("warning: unknown attribute '#[{}]' on '{}' is ignored", attribute.name, item));
+        }
+    }
+}
+
+/// The result of running one `#[test]` function
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestOutcome {
+    pub name: String,
+    pub error: Option<String>, // None when the test passed
+}
+
+impl TestOutcome {
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Runs every `#[test]` function in `source` whose name contains `filter`
+///
+/// The program is configured with `CfgSet::for_tests`, so `#[cfg(test)]` helpers are
+/// available. A test fails if calling it returns an error.
+pub fn run_tests(source: &str, filter: Option<&str>) -> Result<Vec<TestOutcome>, String> {
+    let mut parser = crate::parser::Parser::new(source);
+    let program = parser.parse_program()?;
+    let program = strip_cfg(program, &CfgSet::for_tests())?;
+    let program = crate::derive::expand_derives(program)?;
+
+    let tests: Vec<String> = find_tests(&program).into_iter()
+        .filter(|def| filter.map_or(true, |f| def.name.contains(f)))
+        .map(|def| {
+            if def.parameters.is_empty() {
+                Ok(def.name.clone())
+            } else {
+                Err(format!("Test function '{}' must not take parameters", def.name))
+            }
+        })
+        .collect::<Result<_, _>>()?;
+
+    let mut runtime = crate::runtime::Runtime::new();
+    runtime.eval_program(&program)?;
+
+    let mut outcomes = Vec::with_capacity(tests.len());
+    for name in tests {
+        let func = runtime.env.get(&name)
+            .ok_or_else(|| format!("Test function '{}' is not defined", name))?;
+        let error = runtime.call_function(func, Vec::new()).err();
+        outcomes.push(TestOutcome { name, error });
+    }
+
+    Ok(outcomes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn parse(source: &str) -> Program {
+        Parser::new(source).parse_program().unwrap()
+    }
+
+    fn function_names(program: &Program) -> Vec<&str> {
+        program.statements.iter()
+            .filter_map(|s| match s {
+                Statement::Function(def) => Some(def.name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_cfg_predicates() {
+        let cfg = CfgSet::for_tests().with_value("feature", "json");
+        let program = parse(r#"
+            #[cfg(test)] fn only_tests() {}
+            #[cfg(not(test))] fn not_tests() {}
+            #[cfg(feature = "json")] fn json() {}
+            #[cfg(any(feature = "xml", all(test, feature = "json")))] fn combined() {}
+            fn always() {}
+        "#);
+
+        let stripped = strip_cfg(program.clone(), &cfg).unwrap();
+        assert_eq!(function_names(&stripped), vec!["only_tests", "json", "combined", "always"]);
+
+        let stripped = strip_cfg(program, &CfgSet::new()).unwrap();
+        assert_eq!(function_names(&stripped), vec!["not_tests", "always"]);
+    }
+
+    #[test]
+    fn test_unknown_cfg_predicate_is_an_error() {
+        let program = parse("#[cfg(maybe(test))] fn f() {}");
+        assert!(strip_cfg(program, &CfgSet::new()).is_err());
+    }
+
+    #[test]
+    fn test_unknown_attributes_warn() {
+        let program = parse("#[test] #[frobnicate] fn f() {}");
+        let warnings = check_attributes(&program.statements);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("#[frobnicate]"));
+    }
+
+    #[test]
+    fn test_check_prepares_the_program_like_run() {
+        // A configured-out item is not checked, and derive errors are reported
+        let source = "#[cfg(test)]\nfn broken() -> Int { 1 - \"text\" }\nlet x = 1";
+        assert!(crate::check_syntax_and_types(source).is_ok());
+        assert!(crate::parser::Parser::new(source).parse_program().is_ok_and(|program| crate::type_checker::check_types(&program).is_err()));
+
+        let err = crate::check_syntax_and_types("#[derive(Ord)]\nstruct P { x: Int }").unwrap_err();
+        assert!(err.to_string().contains("Cannot derive 'Ord'"), "{}", err);
+    }
+
+    #[test]
+    fn test_inline_hints() {
+        let program = parse("#[inline] fn a() {} #[inline(always)] fn b() {} #[inline(never)] fn c() {} fn d() {}");
+        let hints: Vec<InlineHint> = program.statements.iter()
+            .filter_map(|s| match s {
+                Statement::Function(def) => Some(inline_hint(def)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(hints, vec![InlineHint::Hint, InlineHint::Always, InlineHint::Never, InlineHint::Default]);
+    }
+
+    #[test]
+    fn test_run_tests_reports_failures() {
+        let source = r#"
+            #[cfg(test)]
+            fn helper() { 1 }
+
+            #[test]
+            fn passes() { helper() }
+
+            #[test]
+            fn fails() { undefined_function() }
+
+            fn not_a_test() {}
+        "#;
+
+        let outcomes = run_tests(source, None).unwrap();
+        assert_eq!(outcomes.len(), 2);
+        assert!(outcomes[0].passed());
+        assert_eq!(outcomes[1].name, "fails");
+        assert!(!outcomes[1].passed());
+
+        let filtered = run_tests(source, Some("pass")).unwrap();
+        assert_eq!(filtered.len(), 1);
+    }
+
+    #[test]
+    fn test_failing_assert_is_a_located_test_failure() {
+        let source = "#[test]\nfn arithmetic() {\n    let total = 1 + 2\n    assert_eq(total, 4, \"totals match\")\n}\n\
+                      #[test]\nfn still_runs() { assert(true) }";
+
+        let outcomes = run_tests(source, None).unwrap();
+        assert_eq!(outcomes.len(), 2);
+        assert_eq!(
+            outcomes[0].error.as_deref(),
+            Some("Assertion failed at line 4, column 5: totals match: expected 4, actual 3")
+        );
+        assert!(outcomes[1].passed());
+    }
+}
@@ -0,0 +1,202 @@
+// Logos Build Hashes
+// A content address for what `logos build` produces, printed with
+// `logos build --print-build-hash` for build caches and reproducibility audits.
+// The hash covers the artifact and everything that went into it:
+//
+//   source        the file being built
+//   compiler      the compiler's version
+//   dependencies  every file reached through @import, named relative to the source
+//   passes        the optimization passes run, in order
+//   output        the code generator and the form it writes
+//   artifact      the generated code itself
+//
+// Nothing machine-specific is hashed: no timestamps, no absolute paths. Building
+// the same inputs again, on any machine or from any checkout directory, gives the
+// same hash. FNV-1a is used rather than std's hasher, whose algorithm may change
+// between Rust releases.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::build_plan::BuildSettings;
+use crate::watch::imported_files;
+
+/// The compiler version recorded in every build hash
+pub const COMPILER_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// 64-bit FNV-1a over `bytes`
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Everything besides the artifact that decides what a build produces
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildInputs {
+    pub source: u64,
+    pub compiler: String,
+    /// Imported files (relative to the source's directory) and their content hashes, sorted
+    pub dependencies: Vec<(String, u64)>,
+    pub passes: Vec<&'static str>,
+    pub output: String,
+}
+
+impl BuildInputs {
+    /// Gathers the inputs of building `source`, read from `file`, with `settings`
+    ///
+    /// Imports are followed transitively; one that can't be read fails the hash.
+    pub fn collect(file: &Path, source: &str, settings: &BuildSettings) -> Result<Self, String> {
+        let root = file.parent().unwrap_or_else(|| Path::new(""));
+        let mut seen: Vec<PathBuf> = Vec::new();
+        let mut dependencies = Vec::new();
+        let mut pending = imported_files(source, root);
+
+        while let Some(path) = pending.pop() {
+            if seen.contains(&path) {
+                continue;
+            }
+            let contents = std::fs::read_to_string(&path)
+                .map_err(|e| format!("Could not read import '{}': {}", path.display(), e))?;
+            let dir = path.parent().unwrap_or(root);
+            pending.extend(imported_files(&contents, dir));
+            dependencies.push((relative_name(&path, root), fnv1a(contents.as_bytes())));
+            seen.push(path);
+        }
+        dependencies.sort();
+
+        let output = match (settings.target.as_deref(), settings.emit_bytecode) {
+            (Some("llvm"), _) => "llvm ir".to_string(),
+            (Some(target), true) => format!("{} bytecode disassembly", target),
+            (Some(target), false) => format!("{} bytecode", target),
+            (None, true) => "bytecode disassembly".to_string(),
+            (None, false) => "bytecode".to_string(),
+        };
+
+        Ok(Self {
+            source: fnv1a(source.as_bytes()),
+            compiler: COMPILER_VERSION.to_string(),
+            dependencies,
+            passes: settings.pass_manager().names(),
+            output,
+        })
+    }
+}
+
+/// `path` as written relative to `root`, with `/` separators on every platform
+fn relative_name(path: &Path, root: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    relative.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The hash of one build: its inputs plus the artifact they produced
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildHash {
+    pub inputs: BuildInputs,
+    pub artifact: u64,
+}
+
+impl BuildHash {
+    pub fn new(inputs: BuildInputs, artifact: &str) -> Self {
+        Self { inputs, artifact: fnv1a(artifact.as_bytes()) }
+    }
+
+    /// The hashed record, one input per line
+    pub fn manifest(&self) -> String {
+        let mut lines = vec![
+            format!("source {:016x}", self.inputs.source),
+            format!("compiler logos {}", self.inputs.compiler),
+        ];
+        for (name, hash) in &self.inputs.dependencies {
+            lines.push(format!("dependency {} {:016x}", name, hash));
+        }
+        let passes = if self.inputs.passes.is_empty() { "none".to_string() } else { self.inputs.passes.join(",") };
+        lines.push(format!("passes {}", passes));
+        lines.push(format!("output {}", self.inputs.output));
+        lines.push(format!("artifact {:016x}", self.artifact));
+        lines.join("\n")
+    }
+
+    /// The build's hash, over its manifest
+    pub fn digest(&self) -> u64 {
+        fnv1a(self.manifest().as_bytes())
+    }
+}
+
+impl fmt::Display for BuildHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Build hash: {:016x}", self.digest())?;
+        for line in self.manifest().lines() {
+            write!(f, "\n  {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode_generator::BytecodeGenerator;
+    use crate::parser::Parser;
+
+    const MAIN: &str = "@import(\"lib/math.logos\")\nfn main() { print(2 * 21) }";
+    const MATH: &str = "fn double(x: Int) -> Int { x * 2 }";
+
+    /// Builds `main.logos` in `dir` the way `logos build` does and hashes the result
+    fn build(dir: &Path, settings: &BuildSettings) -> BuildHash {
+        let file = dir.join("main.logos");
+        let source = std::fs::read_to_string(&file).unwrap();
+        let mut program = Parser::new(&source).parse_program().unwrap();
+        settings.pass_manager().run(&mut program);
+        let artifact = format!("{:?}", BytecodeGenerator::new().generate_program(&program));
+        BuildHash::new(BuildInputs::collect(&file, &source, settings).unwrap(), &artifact)
+    }
+
+    fn project(main: &str, math: &str) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("lib")).unwrap();
+        std::fs::write(dir.path().join("main.logos"), main).unwrap();
+        std::fs::write(dir.path().join("lib/math.logos"), math).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_identical_inputs_hash_identically_from_any_directory() {
+        let settings = BuildSettings { opt_level: 2, ..BuildSettings::default() };
+        let first = project(MAIN, MATH);
+        let second = project(MAIN, MATH);
+
+        let hash = build(first.path(), &settings);
+        assert_eq!(build(first.path(), &settings), hash);
+        assert_eq!(build(second.path(), &settings).digest(), hash.digest());
+        assert_eq!(hash.inputs.dependencies, vec![("lib/math.logos".to_string(), fnv1a(MATH.as_bytes()))]);
+        assert!(!hash.to_string().contains(&first.path().display().to_string()), "{}", hash);
+    }
+
+    #[test]
+    fn test_each_input_changes_the_hash() {
+        let settings = BuildSettings { opt_level: 2, ..BuildSettings::default() };
+        let base = build(project(MAIN, MATH).path(), &settings).digest();
+
+        let edited_dependency = project(MAIN, "fn double(x: Int) -> Int { x + x }");
+        assert_ne!(build(edited_dependency.path(), &settings).digest(), base);
+
+        let edited_source = project(&MAIN.replace("21", "20"), MATH);
+        assert_ne!(build(edited_source.path(), &settings).digest(), base);
+
+        let unoptimized = BuildSettings { opt_level: 0, ..BuildSettings::default() };
+        assert_ne!(build(project(MAIN, MATH).path(), &unoptimized).digest(), base);
+    }
+
+    #[test]
+    fn test_unreadable_import_is_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("main.logos");
+        let err = BuildInputs::collect(&file, MAIN, &BuildSettings::default()).unwrap_err();
+        assert!(err.starts_with("Could not read import '"), "{}", err);
+        assert!(err.contains("math.logos"), "{}", err);
+    }
+}
@@ -0,0 +1,172 @@
+// Logos Build Plan
+// The stages `logos build` runs, described without running them. `logos build
+// --plan` prints this list so the pipeline can be inspected before anything is
+// parsed, compiled or written:
+//
+//   detect multilang  ->  analyze (multilang only)  ->  parse  ->  type-check
+//     ->  optimize (release, -O1 and up, or --passes)  ->  codegen  ->  write output
+//     ->  cache bytecode (not multilang)
+//
+// The build itself asks `BuildSettings` the same questions, so the plan and what
+// actually runs stay in step.
+
+use crate::optimizer::{OptLevel, PassManager};
+use std::fmt;
+use std::path::Path;
+
+/// The options of one build that decide which stages run and what they produce
+#[derive(Debug, Clone, Default)]
+pub struct BuildSettings {
+    pub release: bool,
+    pub opt_level: u8,
+    /// Passes chosen with `--passes`, which replace the level's preset
+    pub passes: Option<PassManager>,
+    pub target: Option<String>,
+    pub emit_bytecode: bool,
+}
+
+impl BuildSettings {
+    /// The optimizations the build runs; a release build gets the most there are
+    pub fn level(&self) -> OptLevel {
+        if self.release {
+            OptLevel::O2
+        } else {
+            OptLevel::from_flag(self.opt_level)
+        }
+    }
+
+    /// The optimization passes the build runs, in order
+    pub fn pass_manager(&self) -> PassManager {
+        self.passes.clone().unwrap_or_else(|| PassManager::for_level(self.level()))
+    }
+
+    /// Whether the build runs the optimizer over the AST
+    pub fn optimizes(&self) -> bool {
+        !self.pass_manager().is_empty()
+    }
+
+    /// Whether code generation produces LLVM IR rather than bytecode
+    pub fn targets_llvm(&self) -> bool {
+        self.target.as_deref() == Some("llvm")
+    }
+}
+
+/// One step of a build: what it reads and what it hands to the next
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stage {
+    pub name: &'static str,
+    pub input: String,
+    pub output: String,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.name, self.input, self.output)
+    }
+}
+
+/// Whether `source` embeds code in other languages, which the build analyzes first
+pub fn needs_multilang_analysis(source: &str) -> bool {
+    crate::contains_multilang_annotations(source)
+}
+
+/// Lists the stages building `source` (read from `file`) would run, in order
+pub fn plan(file: &str, source: &str, settings: &BuildSettings, output_path: &Path) -> Vec<Stage> {
+    let stage = |name, input: &str, output: String| Stage { name, input: input.to_string(), output };
+    let multilang = needs_multilang_analysis(source);
+
+    let mut stages = vec![stage(
+        "detect multilang",
+        &format!("{} ({} bytes)", file, source.len()),
+        if multilang {
+            "@language{...} annotations found".to_string()
+        } else {
+            "no annotations, Rust-only processing".to_string()
+        },
+    )];
+    if multilang {
+        stages.push(stage("analyze", "source", "Go and Python analysis report".to_string()));
+    }
+    stages.push(stage("parse", "source", "AST with #[cfg] applied and derives and macros expanded".to_string()));
+    stages.push(stage("type-check", "AST", "checked AST".to_string()));
+    if settings.optimizes() {
+        let level = if settings.passes.is_some() {
+            "--passes".to_string()
+        } else if settings.release {
+            "release".to_string()
+        } else {
+            format!("-O{}", settings.opt_level)
+        };
+        let passes = settings.pass_manager().descriptions().join(", ");
+        stages.push(stage("optimize", "checked AST", format!("optimized AST ({}: {})", level, passes)));
+    }
+
+    let ast = if settings.optimizes() { "optimized AST" } else { "checked AST" };
+    let code = if settings.targets_llvm() {
+        "LLVM IR (needs the llvm-codegen feature)"
+    } else if settings.emit_bytecode {
+        "bytecode disassembly"
+    } else {
+        "bytecode instructions"
+    };
+    stages.push(stage("codegen", ast, code.to_string()));
+    stages.push(stage("write output", code, output_path.display().to_string()));
+    if !multilang {
+        let cache = crate::codegen::cache_path(Path::new(file));
+        stages.push(stage("cache bytecode", "source", format!("{} (if it all compiles to bytecode)", cache.display())));
+    }
+    stages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(stages: &[Stage]) -> Vec<&'static str> {
+        stages.iter().map(|stage| stage.name).collect()
+    }
+
+    #[test]
+    fn test_plain_and_multilang_plans() {
+        let settings = BuildSettings { opt_level: 2, ..BuildSettings::default() };
+        let output = Path::new("target/debug/app");
+
+        let plain = plan("app.logos", "fn main() { print(1) }", &settings, output);
+        assert_eq!(
+            names(&plain),
+            vec!["detect multilang", "parse", "type-check", "optimize", "codegen", "write output", "cache bytecode"]
+        );
+        assert_eq!(plain[0].to_string(), "detect multilang: app.logos (22 bytes) -> no annotations, Rust-only processing");
+        assert_eq!(plain[5].output, "target/debug/app");
+        assert_eq!(plain[6].output, "app.logosc (if it all compiles to bytecode)");
+
+        let multilang = plan("app.logos", "let x = @python{1 + 1}", &settings, output);
+        assert_eq!(
+            names(&multilang),
+            vec!["detect multilang", "analyze", "parse", "type-check", "optimize", "codegen", "write output"]
+        );
+    }
+
+    #[test]
+    fn test_settings_shape_the_plan() {
+        let output = Path::new("target/debug/ir/app.bc");
+        let settings = BuildSettings { opt_level: 0, emit_bytecode: true, ..BuildSettings::default() };
+        let stages = plan("app.logos", "1", &settings, output);
+        assert!(!names(&stages).contains(&"optimize"));
+        assert_eq!(stages[3].to_string(), "codegen: checked AST -> bytecode disassembly");
+
+        let settings = BuildSettings { opt_level: 1, ..BuildSettings::default() };
+        let stages = plan("app.logos", "1", &settings, output);
+        assert_eq!(stages[3].to_string(), "optimize: checked AST -> optimized AST (-O1: constant folding)");
+
+        let release = BuildSettings { release: true, target: Some("llvm".to_string()), ..BuildSettings::default() };
+        let stages = plan("app.logos", "1", &release, output);
+        assert_eq!(stages[3].output, "optimized AST (release: constant folding, dead code elimination)");
+        assert!(stages[4].output.starts_with("LLVM IR"));
+
+        // --passes replaces the level's preset, even at -O0
+        let chosen = BuildSettings { passes: Some(PassManager::parse("fold").unwrap()), ..BuildSettings::default() };
+        let stages = plan("app.logos", "1", &chosen, output);
+        assert_eq!(stages[3].output, "optimized AST (--passes: constant folding)");
+    }
+}
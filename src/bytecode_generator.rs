@@ -2,12 +2,13 @@
 //! This module converts the AST to bytecode instructions for the virtual machine
 
 use crate::ast::*;
+use std::collections::HashMap;
 
 /// Represents a single bytecode instruction
 #[derive(Debug, Clone, PartialEq)]
 pub enum Instruction {
     // Constants
-    LoadConstant(Constant),
+    LoadConstant(usize),   // Push the constant-pool entry at this index
 
     // Variables
     LoadVar(String),
@@ -28,6 +29,7 @@ pub enum Instruction {
     And,
     Or,
     Not,
+    Neg,
     
     // Control Flow
     Jump(usize),           // Unconditional jump to instruction index
@@ -42,9 +44,16 @@ pub enum Instruction {
     // Stack operations
     Pop,
     Dup,
+
+    // Scopes: bindings made between the two are dropped at `ExitScope`
+    EnterScope,
+    ExitScope,
     
     // Print
     Print,
+
+    // Source spans
+    Located(SourceLocation), // Where the next instruction was parsed from, for its runtime errors
 }
 
 /// Constants that can be stored in the constant pool
@@ -57,14 +66,41 @@ pub enum Constant {
     Unit,
 }
 
+/// Key used to intern constants; floats are compared by bit pattern so that
+/// `0.0` and `-0.0` stay distinct entries
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum ConstantKey {
+    Integer(i64),
+    Float(u64),
+    String(String),
+    Boolean(bool),
+    Unit,
+}
+
+impl From<&Constant> for ConstantKey {
+    fn from(constant: &Constant) -> Self {
+        match constant {
+            Constant::Integer(i) => ConstantKey::Integer(*i),
+            Constant::Float(f) => ConstantKey::Float(f.to_bits()),
+            Constant::String(s) => ConstantKey::String(s.clone()),
+            Constant::Boolean(b) => ConstantKey::Boolean(*b),
+            Constant::Unit => ConstantKey::Unit,
+        }
+    }
+}
+
 /// Bytecode generator that converts AST to bytecode instructions
 pub struct BytecodeGenerator {
     /// Current instruction list
     instructions: Vec<Instruction>,
     /// Constant pool
     constants: Vec<Constant>,
+    /// Index of each constant already in the pool, so identical literals share an entry
+    constant_indices: HashMap<ConstantKey, usize>,
     /// Temporary variable counter
     temp_counter: usize,
+    /// Constructs met that have no bytecode yet; each compiled to a unit value
+    unsupported: Vec<String>,
 }
 
 impl BytecodeGenerator {
@@ -73,19 +109,94 @@ impl BytecodeGenerator {
         Self {
             instructions: Vec::new(),
             constants: Vec::new(),
+            constant_indices: HashMap::new(),
             temp_counter: 0,
+            unsupported: Vec::new(),
         }
     }
 
     /// Generates bytecode for a program
+    ///
+    /// A program ending in an expression leaves that expression's value on the stack,
+    /// as `Runtime::eval_program` returns it.
     pub fn generate_program(&mut self, program: &Program) -> Vec<Instruction> {
-        for statement in &program.statements {
-            self.generate_statement(statement);
+        if let Some((last, rest)) = program.statements.split_last() {
+            for statement in rest {
+                self.generate_statement(statement);
+            }
+            match last {
+                Statement::Expression(expr) => self.generate_expression(expr),
+                statement => self.generate_statement(statement),
+            }
         }
         
         self.instructions.clone()
     }
 
+    /// Returns the constant pool referenced by `LoadConstant` instructions
+    pub fn constants(&self) -> &[Constant] {
+        &self.constants
+    }
+
+    /// The kinds of statement and expression the generator met but can't compile yet,
+    /// such as `MethodCall`; empty when the bytecode covers the whole program
+    pub fn unsupported(&self) -> &[String] {
+        &self.unsupported
+    }
+
+    /// Generates `statements` so they leave exactly one value on the stack: that of
+    /// the final expression, or unit when they end with anything else
+    fn generate_valued(&mut self, statements: &[Statement]) {
+        match statements.split_last() {
+            Some((Statement::Expression(expr), rest)) => {
+                for stmt in rest {
+                    self.generate_statement(stmt);
+                }
+                self.generate_expression(expr);
+            },
+            _ => {
+                for stmt in statements {
+                    self.generate_statement(stmt);
+                }
+                self.load_unit();
+            },
+        }
+    }
+
+    /// Pushes a jump whose target is filled in by `patch_jump`, returning its offset
+    fn emit_jump(&mut self, jump: fn(usize) -> Instruction) -> usize {
+        self.instructions.push(jump(0));
+        self.instructions.len() - 1
+    }
+
+    /// Points the jump at `offset` to the next instruction to be generated
+    fn patch_jump(&mut self, offset: usize) {
+        let target = self.instructions.len();
+        self.instructions[offset] = match self.instructions[offset] {
+            Instruction::JumpIfFalse(_) => Instruction::JumpIfFalse(target),
+            Instruction::JumpIfTrue(_) => Instruction::JumpIfTrue(target),
+            _ => Instruction::Jump(target),
+        };
+    }
+
+    fn load_unit(&mut self) {
+        let const_idx = self.add_constant(Constant::Unit);
+        self.instructions.push(Instruction::LoadConstant(const_idx));
+    }
+
+    fn load_boolean(&mut self, value: bool) {
+        let const_idx = self.add_constant(Constant::Boolean(value));
+        self.instructions.push(Instruction::LoadConstant(const_idx));
+    }
+
+    /// Records a construct with no bytecode yet; `node` is its `Debug` form
+    fn record_unsupported(&mut self, node: String) {
+        let kind = node.split(|c: char| !c.is_alphanumeric() && c != '_').next().unwrap_or_default().to_string();
+        if !self.unsupported.contains(&kind) {
+            self.unsupported.push(kind);
+        }
+    }
+
     /// Generates bytecode for a statement
     fn generate_statement(&mut self, statement: &Statement) {
         match statement {
@@ -94,18 +205,27 @@ impl BytecodeGenerator {
                 // Pop the result since expressions as statements don't return anything
                 self.instructions.push(Instruction::Pop);
             },
-            Statement::LetBinding { mutable: _, name, type_annotation: _, value, ownership_modifier: _, lifetime_annotation: _ } => {
+            Statement::LetBinding { mutable: _, name, type_annotation, value, ownership_modifier: _, lifetime_annotation: _ } => {
+                // `dyn Trait` annotations wrap the value in a trait object at runtime
+                if type_annotation.as_ref().is_some_and(mentions_dyn) {
+                    self.record_unsupported("DynAnnotation".to_string());
+                }
                 // Generate code for the value
                 self.generate_expression(value);
                 // Store it in the variable
                 self.instructions.push(Instruction::StoreVar(name.clone()));
             },
+            Statement::ConstBinding { name, type_annotation: _, value } => {
+                self.generate_expression(value);
+                self.instructions.push(Instruction::StoreVar(name.clone()));
+            },
             Statement::Function(func_def) => {
-                // Generate bytecode for the function body
-                let mut func_generator = BytecodeGenerator::new();
-                for stmt in &func_def.body {
-                    func_generator.generate_statement(stmt);
-                }
+                // Generate bytecode for the function body, sharing this generator's constant pool.
+                // The body returns its final expression's value if it doesn't return earlier.
+                let outer_instructions = std::mem::take(&mut self.instructions);
+                self.generate_valued(&func_def.body);
+                self.instructions.push(Instruction::Return);
+                let body_instructions = std::mem::replace(&mut self.instructions, outer_instructions);
                 
                 let param_names: Vec<String> = func_def.parameters.iter()
                     .map(|param| param.name.clone())
@@ -114,25 +234,37 @@ impl BytecodeGenerator {
                 self.instructions.push(Instruction::DefineFunction(
                     func_def.name.clone(),
                     param_names,
-                    func_generator.instructions,
+                    body_instructions,
                 ));
             },
             Statement::Return(expr) => {
-                if let Some(return_expr) = expr {
-                    self.generate_expression(return_expr);
+                match expr {
+                    Some(return_expr) => self.generate_expression(return_expr),
+                    None => self.load_unit(),
                 }
                 self.instructions.push(Instruction::Return);
             },
             Statement::Block(statements) => {
+                self.instructions.push(Instruction::EnterScope);
                 for stmt in statements {
                     self.generate_statement(stmt);
                 }
+                self.instructions.push(Instruction::ExitScope);
+            },
+            Statement::While { label: _, condition, body } => {
+                let start = self.instructions.len();
+                self.generate_expression(condition);
+                let exit = self.emit_jump(Instruction::JumpIfFalse);
+                self.instructions.push(Instruction::EnterScope);
+                for stmt in body {
+                    self.generate_statement(stmt);
+                }
+                self.instructions.push(Instruction::ExitScope);
+                self.instructions.push(Instruction::Jump(start));
+                self.patch_jump(exit);
             },
             // Handle other statement types as needed
-            _ => {
-                // For now, we'll just add a comment for unhandled statements
-                // In a full implementation, we'd handle all statement types
-            }
+            other => self.record_unsupported(format!("{:?}", other)),
         }
     }
 
@@ -141,32 +273,52 @@ impl BytecodeGenerator {
         match expr {
             Expression::Integer(value) => {
                 let const_idx = self.add_constant(Constant::Integer(*value));
-                self.instructions.push(Instruction::LoadConstant(self.constants[const_idx].clone()));
+                self.instructions.push(Instruction::LoadConstant(const_idx));
             },
             Expression::Float(value) => {
                 let const_idx = self.add_constant(Constant::Float(*value));
-                self.instructions.push(Instruction::LoadConstant(self.constants[const_idx].clone()));
+                self.instructions.push(Instruction::LoadConstant(const_idx));
             },
             Expression::String(value) => {
                 let const_idx = self.add_constant(Constant::String(value.clone()));
-                self.instructions.push(Instruction::LoadConstant(self.constants[const_idx].clone()));
+                self.instructions.push(Instruction::LoadConstant(const_idx));
             },
             Expression::Boolean(value) => {
                 let const_idx = self.add_constant(Constant::Boolean(*value));
-                self.instructions.push(Instruction::LoadConstant(self.constants[const_idx].clone()));
+                self.instructions.push(Instruction::LoadConstant(const_idx));
             },
             Expression::Nil => {
                 let const_idx = self.add_constant(Constant::Unit);
-                self.instructions.push(Instruction::LoadConstant(self.constants[const_idx].clone()));
+                self.instructions.push(Instruction::LoadConstant(const_idx));
             },
             Expression::Identifier(name) => {
                 self.instructions.push(Instruction::LoadVar(name.clone()));
             },
-            Expression::BinaryOp(left, op, right) => {
+            Expression::BinaryOp(left, op @ (BinaryOp::And | BinaryOp::Or), right, _) => {
+                // Short-circuit: jump to the deciding result as soon as an operand settles it
+                let decides: fn(usize) -> Instruction =
+                    if *op == BinaryOp::And { Instruction::JumpIfFalse } else { Instruction::JumpIfTrue };
+                self.generate_expression(left);
+                let left_decides = self.emit_jump(decides);
+                self.generate_expression(right);
+                let right_decides = self.emit_jump(decides);
+                self.load_boolean(*op == BinaryOp::And);
+                let to_end = self.emit_jump(Instruction::Jump);
+                self.patch_jump(left_decides);
+                self.patch_jump(right_decides);
+                self.load_boolean(*op == BinaryOp::Or);
+                self.patch_jump(to_end);
+            },
+            Expression::BinaryOp(left, op, right, parsed_at) => {
                 // Generate code for left operand
                 self.generate_expression(left);
                 // Generate code for right operand
                 self.generate_expression(right);
+
+                // Division and modulo report where they failed, as when running the source
+                if let (BinaryOp::Div | BinaryOp::Mod, Some(location)) = (op, parsed_at.0) {
+                    self.instructions.push(Instruction::Located(location));
+                }
                 
                 // Generate operation instruction
                 match op {
@@ -174,6 +326,7 @@ impl BytecodeGenerator {
                     BinaryOp::Sub => self.instructions.push(Instruction::Sub),
                     BinaryOp::Mul => self.instructions.push(Instruction::Mul),
                     BinaryOp::Div => self.instructions.push(Instruction::Div),
+                    BinaryOp::Mod => self.instructions.push(Instruction::Mod),
                     BinaryOp::Eq => self.instructions.push(Instruction::Eq),
                     BinaryOp::Ne => self.instructions.push(Instruction::Ne),
                     BinaryOp::Lt => self.instructions.push(Instruction::Lt),
@@ -183,13 +336,45 @@ impl BytecodeGenerator {
                     BinaryOp::And => self.instructions.push(Instruction::And),
                     BinaryOp::Or => self.instructions.push(Instruction::Or),
                     // Add more operations as needed
-                    _ => {
-                        // For now, we'll just push a unit value for unhandled operations
-                        let const_idx = self.add_constant(Constant::Unit);
-                        self.instructions.push(Instruction::LoadConstant(self.constants[const_idx].clone()));
+                    other => {
+                        // Discard the operands and stand in a unit value for the result
+                        self.record_unsupported(format!("{:?}", other));
+                        self.instructions.push(Instruction::Pop);
+                        self.instructions.push(Instruction::Pop);
+                        self.load_unit();
                     }
                 }
             },
+            Expression::UnaryOp(op, operand) => {
+                self.generate_expression(operand);
+                match op {
+                    UnaryOp::Not => self.instructions.push(Instruction::Not),
+                    UnaryOp::Neg => self.instructions.push(Instruction::Neg),
+                    other => {
+                        self.record_unsupported(format!("{:?}", other));
+                        self.instructions.push(Instruction::Pop);
+                        self.load_unit();
+                    }
+                }
+            },
+            Expression::If(condition, then_branch, else_branch) => {
+                self.generate_expression(condition);
+                let to_else = self.emit_jump(Instruction::JumpIfFalse);
+                self.instructions.push(Instruction::EnterScope);
+                self.generate_valued(then_branch);
+                self.instructions.push(Instruction::ExitScope);
+                let to_end = self.emit_jump(Instruction::Jump);
+                self.patch_jump(to_else);
+                self.instructions.push(Instruction::EnterScope);
+                self.generate_valued(else_branch);
+                self.instructions.push(Instruction::ExitScope);
+                self.patch_jump(to_end);
+            },
+            Expression::Block(statements) => {
+                self.instructions.push(Instruction::EnterScope);
+                self.generate_valued(statements);
+                self.instructions.push(Instruction::ExitScope);
+            },
             Expression::Call(name, args) => {
                 // Generate code for each argument
                 for arg in args {
@@ -200,17 +385,209 @@ impl BytecodeGenerator {
                 self.instructions.push(Instruction::Call(name.clone(), args.len()));
             },
             // Handle other expression types as needed
-            _ => {
-                // For now, we'll just push a unit value for unhandled expressions
-                let const_idx = self.add_constant(Constant::Unit);
-                self.instructions.push(Instruction::LoadConstant(self.constants[const_idx].clone()));
+            other => {
+                // Stand in a unit value so the stack keeps its shape
+                self.record_unsupported(format!("{:?}", other));
+                self.load_unit();
             }
         }
     }
 
     /// Adds a constant to the constant pool and returns its index
+    ///
+    /// Identical constants are interned: adding a value that is already in the
+    /// pool returns the existing index instead of growing the pool.
     fn add_constant(&mut self, constant: Constant) -> usize {
+        let key = ConstantKey::from(&constant);
+        if let Some(&idx) = self.constant_indices.get(&key) {
+            return idx;
+        }
+
         self.constants.push(constant);
-        self.constants.len() - 1
+        let idx = self.constants.len() - 1;
+        self.constant_indices.insert(key, idx);
+        idx
     }
-}
\ No newline at end of file
+}
+
+/// Whether a type annotation needs a `dyn Trait` coercion when a value is bound to it
+fn mentions_dyn(ty: &Type) -> bool {
+    match ty {
+        Type::Dyn(_) => true,
+        Type::Array(inner) => mentions_dyn(inner),
+        _ => false,
+    }
+}
+
+/// Renders a bytecode listing in a human-readable form
+///
+/// Each line shows the instruction offset, opcode and operands. Offsets that are
+/// the target of a jump get a label (`L0:`, `L1:`, ...) and jumps refer to those
+/// labels, so basic blocks are easy to pick out. Function bodies are listed
+/// indented beneath their `DEFINE_FN` with their own offsets.
+pub fn disassemble(program: &[Instruction]) -> String {
+    let mut out = String::new();
+    disassemble_into(program, &[], 0, &mut out);
+    out
+}
+
+/// Like `disassemble`, but prefixes the listing with the constant pool and
+/// annotates each `LOAD_CONST` with the value it loads
+pub fn disassemble_with_constants(program: &[Instruction], constants: &[Constant]) -> String {
+    let mut out = String::new();
+    if !constants.is_empty() {
+        out.push_str("constants:\n");
+        for (idx, constant) in constants.iter().enumerate() {
+            out.push_str(&format!("    #{}  {}\n", idx, format_constant(constant)));
+        }
+        out.push_str("code:\n");
+    }
+    disassemble_into(program, constants, 0, &mut out);
+    out
+}
+
+fn disassemble_into(program: &[Instruction], constants: &[Constant], depth: usize, out: &mut String) {
+    // Collect jump targets so each one can be given a stable label, in offset order
+    let mut targets: Vec<usize> = program.iter()
+        .filter_map(|instr| match instr {
+            Instruction::Jump(target)
+            | Instruction::JumpIfTrue(target)
+            | Instruction::JumpIfFalse(target) => Some(*target),
+            _ => None,
+        })
+        .collect();
+    targets.sort_unstable();
+    targets.dedup();
+
+    let label = |target: usize| -> String {
+        match targets.binary_search(&target) {
+            Ok(idx) => format!("L{}", idx),
+            Err(_) => format!("{:04}", target),
+        }
+    };
+    let indent = "    ".repeat(depth);
+
+    for (offset, instr) in program.iter().enumerate() {
+        if targets.binary_search(&offset).is_ok() {
+            out.push_str(&format!("{}{}:\n", indent, label(offset)));
+        }
+
+        let text = match instr {
+            Instruction::LoadConstant(idx) => match constants.get(*idx) {
+                Some(constant) => format!("LOAD_CONST #{}  ; {}", idx, format_constant(constant)),
+                None => format!("LOAD_CONST #{}", idx),
+            },
+            Instruction::LoadVar(name) => format!("LOAD_VAR {}", name),
+            Instruction::StoreVar(name) => format!("STORE_VAR {}", name),
+            Instruction::Add => "ADD".to_string(),
+            Instruction::Sub => "SUB".to_string(),
+            Instruction::Mul => "MUL".to_string(),
+            Instruction::Div => "DIV".to_string(),
+            Instruction::Mod => "MOD".to_string(),
+            Instruction::Eq => "EQ".to_string(),
+            Instruction::Ne => "NE".to_string(),
+            Instruction::Lt => "LT".to_string(),
+            Instruction::Gt => "GT".to_string(),
+            Instruction::Le => "LE".to_string(),
+            Instruction::Ge => "GE".to_string(),
+            Instruction::And => "AND".to_string(),
+            Instruction::Or => "OR".to_string(),
+            Instruction::Not => "NOT".to_string(),
+            Instruction::Neg => "NEG".to_string(),
+            Instruction::Jump(target) => format!("JUMP {}", label(*target)),
+            Instruction::JumpIfTrue(target) => format!("JUMP_IF_TRUE {}", label(*target)),
+            Instruction::JumpIfFalse(target) => format!("JUMP_IF_FALSE {}", label(*target)),
+            Instruction::Call(name, argc) => format!("CALL {} {}", name, argc),
+            Instruction::Return => "RETURN".to_string(),
+            Instruction::DefineFunction(name, params, _) => format!("DEFINE_FN {}({})", name, params.join(", ")),
+            Instruction::Pop => "POP".to_string(),
+            Instruction::Dup => "DUP".to_string(),
+            Instruction::EnterScope => "ENTER_SCOPE".to_string(),
+            Instruction::ExitScope => "EXIT_SCOPE".to_string(),
+            Instruction::Print => "PRINT".to_string(),
+            Instruction::Located(location) => format!("LOCATED {}:{}", location.line, location.column),
+        };
+        out.push_str(&format!("{}{:04}  {}\n", indent, offset, text));
+
+        if let Instruction::DefineFunction(_, _, body) = instr {
+            disassemble_into(body, constants, depth + 1, out);
+        }
+    }
+}
+
+fn format_constant(constant: &Constant) -> String {
+    match constant {
+        Constant::Integer(i) => i.to_string(),
+        Constant::Float(f) => format!("{:?}", f),
+        Constant::String(s) => format!("{:?}", s),
+        Constant::Boolean(b) => b.to_string(),
+        Constant::Unit => "()".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_disassemble_small_program() {
+        let program = Parser::new("let x = 1 + 2").parse_program().unwrap();
+        let instructions = BytecodeGenerator::new().generate_program(&program);
+
+        let expected = "\
+0000  LOAD_CONST #0
+0001  LOAD_CONST #1
+0002  ADD
+0003  STORE_VAR x
+";
+        assert_eq!(disassemble(&instructions), expected);
+    }
+
+    #[test]
+    fn test_disassemble_labels_jump_targets() {
+        let instructions = vec![
+            Instruction::LoadVar("flag".to_string()),
+            Instruction::JumpIfFalse(4),
+            Instruction::LoadConstant(0),
+            Instruction::Jump(5),
+            Instruction::LoadConstant(1),
+            Instruction::Print,
+        ];
+
+        let constants = vec![
+            Constant::String("yes".to_string()),
+            Constant::String("no".to_string()),
+        ];
+
+        let expected = "\
+constants:
+    #0  \"yes\"
+    #1  \"no\"
+code:
+0000  LOAD_VAR flag
+0001  JUMP_IF_FALSE L0
+0002  LOAD_CONST #0  ; \"yes\"
+0003  JUMP L1
+L0:
+0004  LOAD_CONST #1  ; \"no\"
+L1:
+0005  PRINT
+";
+        assert_eq!(disassemble_with_constants(&instructions, &constants), expected);
+    }
+
+    #[test]
+    fn test_repeated_literals_share_constant() {
+        let source = "let a = \"hi\"\nlet b = \"hi\"\nlet c = \"hi\"";
+        let program = Parser::new(source).parse_program().unwrap();
+        let mut generator = BytecodeGenerator::new();
+        let instructions = generator.generate_program(&program);
+
+        assert_eq!(generator.constants(), &[Constant::String("hi".to_string())]);
+        let loads = instructions.iter()
+            .filter(|instr| **instr == Instruction::LoadConstant(0))
+            .count();
+        assert_eq!(loads, 3);
+    }
+}
@@ -395,7 +395,7 @@ impl VM {
     fn value_to_string(&self, value: &Value) -> String {
         match value {
             Value::Integer(i) => i.to_string(),
-            Value::Float(f) => f.to_string(),
+            Value::Float(f) => crate::runtime::format_float(*f),
             Value::String(s) => s.clone(),
             Value::Boolean(b) => b.to_string(),
             Value::Unit => "()".to_string(),
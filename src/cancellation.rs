@@ -0,0 +1,235 @@
+// Logos Cancellation Support
+// This module lets an embedding application (an editor, a language server) abandon
+// long-running compiler work. A `CancellationToken` is checked between phases, and
+// subprocesses started through `run_command` are killed as soon as it is cancelled.
+
+use std::fmt;
+use std::io::{self, Read};
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// How often a running subprocess is polled for completion or cancellation
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A cheaply clonable flag shared between the caller and the work it may cancel
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Creates a token that has not been cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests cancellation; every clone of this token observes it
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns true once `cancel` has been called on any clone
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Returns `Err(Cancelled)` if cancellation has been requested
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Checks an optional token, for entry points where cancellation is opt-in
+pub fn check(token: Option<&CancellationToken>) -> Result<(), Cancelled> {
+    match token {
+        Some(token) => token.check(),
+        None => Ok(()),
+    }
+}
+
+/// Error returned when an operation stops because its token was cancelled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Operation cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Returns true if a boxed error is a cancellation
+pub fn is_cancelled_error(error: &(dyn std::error::Error + 'static)) -> bool {
+    error.downcast_ref::<Cancelled>().is_some()
+}
+
+/// Runs a command to completion, killing it if `token` is cancelled meanwhile
+///
+/// The token is checked before spawning, so a cancelled token never starts a
+/// process. Output is captured as with `Command::output`.
+pub fn run_command(command: &mut Command, token: Option<&CancellationToken>) -> Result<Output, Box<dyn std::error::Error>> {
+    check(token)?;
+
+    let token = match token {
+        Some(token) => token,
+        None => return Ok(command.output()?),
+    };
+
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Drain both pipes while we poll; a child that fills a pipe buffer would
+    // otherwise block on write and never exit
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+
+    loop {
+        if token.is_cancelled() {
+            // The process may already have exited; either way it's gone afterwards
+            let _ = child.kill();
+            let _ = child.wait();
+            // A grandchild may still hold the pipes open, so the readers are detached
+            // rather than joined; they finish on their own once the last writer goes
+            return Err(Box::new(Cancelled));
+        }
+
+        if let Some(status) = child.try_wait()? {
+            // The child is gone but a process it started may keep writing, so keep
+            // watching the token until both pipes are closed
+            while !(stdout.is_finished() && stderr.is_finished()) {
+                token.check()?;
+                thread::sleep(POLL_INTERVAL);
+            }
+            return Ok(Output {
+                status,
+                stdout: collect(stdout)?,
+                stderr: collect(stderr)?,
+            });
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Reads a child's pipe to the end on its own thread
+fn drain<R: Read + Send + 'static>(pipe: Option<R>) -> thread::JoinHandle<io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut buffer = Vec::new();
+        if let Some(mut pipe) = pipe {
+            pipe.read_to_end(&mut buffer)?;
+        }
+        Ok(buffer)
+    })
+}
+
+/// Waits for a `drain` thread and returns what it read
+fn collect(reader: thread::JoinHandle<io::Result<Vec<u8>>>) -> io::Result<Vec<u8>> {
+    reader
+        .join()
+        .unwrap_or_else(|_| Err(io::Error::other("output reader panicked")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_is_shared_between_clones() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(token.check().is_ok());
+
+        clone.cancel();
+        assert!(token.is_cancelled());
+        assert_eq!(token.check(), Err(Cancelled));
+    }
+
+    #[test]
+    fn test_cancelled_token_does_not_spawn() {
+        let token = CancellationToken::new();
+        token.cancel();
+
+        // Spawning this would fail with "not found"; getting Cancelled proves we never tried
+        let mut command = Command::new("logos-test-no-such-program");
+        let err = run_command(&mut command, Some(&token)).unwrap_err();
+        assert!(is_cancelled_error(err.as_ref()));
+    }
+
+    #[test]
+    fn test_cancelled_compile_returns_cancelled() {
+        let token = CancellationToken::new();
+        token.cancel();
+
+        let err = crate::compile_cancellable("fn main() { print(1) }", Some(&token)).unwrap_err();
+        assert!(is_cancelled_error(err.as_ref()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_cancel_kills_running_process() {
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            canceller.cancel();
+        });
+
+        let started = std::time::Instant::now();
+        let mut command = Command::new("sleep");
+        command.arg("30");
+        let err = run_command(&mut command, Some(&token)).unwrap_err();
+
+        handle.join().unwrap();
+        assert!(is_cancelled_error(err.as_ref()));
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_cancel_does_not_wait_for_grandchildren_holding_the_pipes() {
+        // The backgrounded sleep inherits stdout and stderr and outlives both the
+        // shell (on the exit path) and the kill (on the cancel path)
+        for script in ["sleep 30 & sleep 30", "sleep 30 & exit 0"] {
+            let token = CancellationToken::new();
+            let canceller = token.clone();
+            let handle = thread::spawn(move || {
+                thread::sleep(Duration::from_millis(200));
+                canceller.cancel();
+            });
+
+            let started = std::time::Instant::now();
+            let mut command = Command::new("sh");
+            command.args(["-c", script]);
+            let err = run_command(&mut command, Some(&token)).unwrap_err();
+
+            handle.join().unwrap();
+            assert!(is_cancelled_error(err.as_ref()), "{}", script);
+            assert!(started.elapsed() < Duration::from_secs(10), "{}", script);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_output_larger_than_pipe_buffer_does_not_hang() {
+        let token = CancellationToken::new();
+
+        // Well past the usual 64 KiB pipe buffer, on both streams
+        let mut command = Command::new("sh");
+        command.args(["-c", "head -c 300000 /dev/zero; head -c 200000 /dev/zero >&2"]);
+        let output = run_command(&mut command, Some(&token)).unwrap();
+
+        assert!(output.status.success());
+        assert_eq!(output.stdout.len(), 300_000);
+        assert_eq!(output.stderr.len(), 200_000);
+    }
+}
@@ -1,7 +1,14 @@
 // Logos Programming Language Code Generator
 // This module transforms the AST into executable code or intermediate representation.
+//
+// `compile_to_bytecode` produces the form `logos build` caches next to the source
+// as `app.logosc`, and `logos run` executes that cache instead of re-parsing while
+// the source's modification time still matches the one recorded in it.
 
 use crate::ast::*;
+use crate::bytecode_generator::{BytecodeGenerator, Constant, Instruction};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
 
 /// Code generator for converting AST to target code
 pub struct CodeGen {
@@ -132,7 +139,7 @@ impl CodeGen {
             Expression::Boolean(val) => val.to_string(),
             Expression::Nil => "nil".to_string(),
             Expression::Identifier(name) => name.clone(),
-            Expression::BinaryOp(left, op, right) => {
+            Expression::BinaryOp(left, op, right, _) => {
                 let left_str = self.generate_expression(left);
                 let right_str = self.generate_expression(right);
                 let op_str = self.binary_op_to_string(op);
@@ -270,6 +277,9 @@ impl CodeGen {
             Type::Option(inner) => format!("Option<{}>", self.type_to_string(inner)),
             Type::Result(ok, err) => format!("Result<{}, {}>", self.type_to_string(ok), self.type_to_string(err)),
             Type::Infer => "auto".to_string(),
+            Type::Dyn(trait_name) => format!("dyn {}", trait_name),
+            Type::Map(key, value) => format!("HashMap<{}, {}>", self.type_to_string(key), self.type_to_string(value)),
+            Type::Set(element) => format!("HashSet<{}>", self.type_to_string(element)),
             // Handle other type variants as needed
             _ => "unknown".to_string(),
         }
@@ -294,4 +304,332 @@ impl CodeGen {
 pub fn generate_code(program: &Program) -> String {
     let mut codegen = CodeGen::new();
     codegen.generate_program(program)
-}
\ No newline at end of file
+}
+/// A compiled program: instructions, the constants they load, and anything left out
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Bytecode {
+    pub instructions: Vec<Instruction>,
+    pub constants: Vec<Constant>,
+    /// Kinds of construct the program uses that have no bytecode yet
+    pub unsupported: Vec<String>,
+}
+
+impl Bytecode {
+    /// Whether every construct in the program was compiled, so running the bytecode
+    /// behaves like running the source
+    pub fn is_complete(&self) -> bool {
+        self.unsupported.is_empty()
+    }
+
+    /// Writes the bytecode as text, one constant or instruction per line
+    ///
+    /// Strings are hex-encoded and floats stored by bit pattern, so `from_text`
+    /// reads back exactly what was written.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for constant in &self.constants {
+            out.push_str(&match constant {
+                Constant::Integer(i) => format!("const int {}\n", i),
+                Constant::Float(f) => format!("const float {:x}\n", f.to_bits()),
+                Constant::String(s) => format!("const str {}\n", hex_encode(s.as_bytes())),
+                Constant::Boolean(b) => format!("const bool {}\n", b),
+                Constant::Unit => "const unit\n".to_string(),
+            });
+        }
+        out.push_str("code\n");
+        write_instructions(&self.instructions, &mut out);
+        out
+    }
+
+    /// Reads bytecode written by `to_text`
+    pub fn from_text(text: &str) -> Result<Bytecode, String> {
+        let mut lines = text.lines().enumerate().map(|(index, line)| (index + 1, line));
+        let mut constants = Vec::new();
+        loop {
+            let (number, line) = lines.next().ok_or("bytecode has no 'code' section")?;
+            if line == "code" {
+                break;
+            }
+            let parts: Vec<&str> = line.split(' ').collect();
+            let constant = match parts.as_slice() {
+                ["const", "int", value] => value.parse().ok().map(Constant::Integer),
+                ["const", "float", bits] => u64::from_str_radix(bits, 16).ok().map(|bits| Constant::Float(f64::from_bits(bits))),
+                ["const", "str", hex] => hex_decode(hex).and_then(|bytes| String::from_utf8(bytes).ok()).map(Constant::String),
+                ["const", "bool", value] => value.parse().ok().map(Constant::Boolean),
+                ["const", "unit"] => Some(Constant::Unit),
+                _ => None,
+            };
+            constants.push(constant.ok_or_else(|| format!("line {}: malformed constant '{}'", number, line))?);
+        }
+
+        let instructions = read_instructions(&mut lines, false)?;
+        Ok(Bytecode { instructions, constants, unsupported: Vec::new() })
+    }
+}
+
+/// Compiles a program to bytecode that `Runtime::eval_bytecode` can run
+///
+/// Constructs without a bytecode form yet are listed in `unsupported`; the result
+/// is only worth caching when `is_complete` holds.
+pub fn compile_to_bytecode(program: &Program) -> Bytecode {
+    // The VM has no generic functions, so each instantiation is compiled as its own copy
+    let program = match crate::monomorphize::monomorphize(program, &[]) {
+        Ok(program) => program,
+        Err(e) => return Bytecode { instructions: Vec::new(), constants: Vec::new(), unsupported: vec![e] },
+    };
+    let mut generator = BytecodeGenerator::new();
+    let instructions = generator.generate_program(&program);
+    Bytecode {
+        instructions,
+        constants: generator.constants().to_vec(),
+        unsupported: generator.unsupported().to_vec(),
+    }
+}
+
+/// First word of every cache file, followed by the format version and the source's mtime
+const CACHE_MAGIC: &str = "logosc";
+const CACHE_VERSION: u32 = 2;
+
+/// Where the bytecode cache for `source` lives: beside it, with a `.logosc` extension
+pub fn cache_path(source: &Path) -> PathBuf {
+    source.with_extension("logosc")
+}
+
+/// The modification time of `source` in nanoseconds since the Unix epoch
+fn source_mtime(source: &Path) -> Result<u128, String> {
+    let modified = std::fs::metadata(source)
+        .and_then(|metadata| metadata.modified())
+        .map_err(|e| format!("Could not read the modification time of '{}': {}", source.display(), e))?;
+    Ok(modified.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_nanos()))
+}
+
+/// Writes `bytecode` as the cache for `source`, recording the source's current mtime
+pub fn write_cache(source: &Path, bytecode: &Bytecode) -> Result<PathBuf, String> {
+    if !bytecode.is_complete() {
+        return Err(format!("Not caching bytecode for '{}': it uses {} with no bytecode form yet",
+                           source.display(), bytecode.unsupported.join(", ")));
+    }
+    let path = cache_path(source);
+    let text = format!("{} {} {}\n{}", CACHE_MAGIC, CACHE_VERSION, source_mtime(source)?, bytecode.to_text());
+    std::fs::write(&path, text).map_err(|e| format!("Could not write '{}': {}", path.display(), e))?;
+    Ok(path)
+}
+
+/// Loads the cache for `source` if there is one written for its current contents
+///
+/// A cache is stale once the source's mtime differs from the one recorded when it
+/// was written. Stale, unreadable and older-format caches are all ignored, and the
+/// caller falls back to compiling the source.
+pub fn load_cache(source: &Path) -> Option<Bytecode> {
+    let text = std::fs::read_to_string(cache_path(source)).ok()?;
+    let (header, body) = text.split_once('\n')?;
+    let expected = format!("{} {} {}", CACHE_MAGIC, CACHE_VERSION, source_mtime(source).ok()?);
+    if header != expected {
+        return None;
+    }
+    Bytecode::from_text(body).ok()
+}
+
+fn write_instructions(instructions: &[Instruction], out: &mut String) {
+    for instruction in instructions {
+        let line = match instruction {
+            Instruction::LoadConstant(index) => format!("LOAD_CONST {}", index),
+            Instruction::LoadVar(name) => format!("LOAD_VAR {}", name),
+            Instruction::StoreVar(name) => format!("STORE_VAR {}", name),
+            Instruction::Jump(target) => format!("JUMP {}", target),
+            Instruction::JumpIfTrue(target) => format!("JUMP_IF_TRUE {}", target),
+            Instruction::JumpIfFalse(target) => format!("JUMP_IF_FALSE {}", target),
+            Instruction::Call(name, argc) => format!("CALL {} {}", name, argc),
+            Instruction::Located(location) => format!("LOCATED {} {}", location.line, location.column),
+            Instruction::DefineFunction(name, params, body) => {
+                let mut words = vec!["DEFINE_FN", name.as_str()];
+                words.extend(params.iter().map(String::as_str));
+                out.push_str(&words.join(" "));
+                out.push('\n');
+                write_instructions(body, out);
+                "END_FN".to_string()
+            },
+            simple => simple_opcode(simple).unwrap_or_default().to_string(),
+        };
+        out.push_str(&line);
+        out.push('\n');
+    }
+}
+
+/// Reads instructions up to the end of the text, or up to `END_FN` for a function body
+fn read_instructions<'t>(lines: &mut impl Iterator<Item = (usize, &'t str)>, in_function: bool) -> Result<Vec<Instruction>, String> {
+    let mut instructions = Vec::new();
+    while let Some((number, line)) = lines.next() {
+        let words: Vec<&str> = line.split(' ').collect();
+        let instruction = match words.as_slice() {
+            ["END_FN"] if in_function => return Ok(instructions),
+            ["LOAD_CONST", index] => index.parse().ok().map(Instruction::LoadConstant),
+            ["LOAD_VAR", name] => Some(Instruction::LoadVar(name.to_string())),
+            ["STORE_VAR", name] => Some(Instruction::StoreVar(name.to_string())),
+            ["JUMP", target] => target.parse().ok().map(Instruction::Jump),
+            ["JUMP_IF_TRUE", target] => target.parse().ok().map(Instruction::JumpIfTrue),
+            ["JUMP_IF_FALSE", target] => target.parse().ok().map(Instruction::JumpIfFalse),
+            ["CALL", name, argc] => argc.parse().ok().map(|argc| Instruction::Call(name.to_string(), argc)),
+            ["LOCATED", line, column] => match (line.parse(), column.parse()) {
+                (Ok(line), Ok(column)) => Some(Instruction::Located(SourceLocation { line, column })),
+                _ => None,
+            },
+            ["DEFINE_FN", name, params @ ..] => {
+                let body = read_instructions(lines, true)?;
+                Some(Instruction::DefineFunction(name.to_string(), params.iter().map(|p| p.to_string()).collect(), body))
+            },
+            [opcode] => SIMPLE_INSTRUCTIONS.iter().find(|candidate| simple_opcode(candidate) == Some(opcode)).cloned(),
+            _ => None,
+        };
+        instructions.push(instruction.ok_or_else(|| format!("line {}: malformed instruction '{}'", number, line))?);
+    }
+    if in_function {
+        return Err("bytecode ends inside a function body".to_string());
+    }
+    Ok(instructions)
+}
+
+/// Instructions without operands, looked up by opcode when reading
+const SIMPLE_INSTRUCTIONS: [Instruction; 21] = [
+    Instruction::Add, Instruction::Sub, Instruction::Mul, Instruction::Div, Instruction::Mod,
+    Instruction::Eq, Instruction::Ne, Instruction::Lt, Instruction::Gt, Instruction::Le, Instruction::Ge,
+    Instruction::And, Instruction::Or, Instruction::Not, Instruction::Neg,
+    Instruction::Return, Instruction::Pop, Instruction::Dup, Instruction::Print,
+    Instruction::EnterScope, Instruction::ExitScope,
+];
+
+fn simple_opcode(instruction: &Instruction) -> Option<&'static str> {
+    Some(match instruction {
+        Instruction::Add => "ADD",
+        Instruction::Sub => "SUB",
+        Instruction::Mul => "MUL",
+        Instruction::Div => "DIV",
+        Instruction::Mod => "MOD",
+        Instruction::Eq => "EQ",
+        Instruction::Ne => "NE",
+        Instruction::Lt => "LT",
+        Instruction::Gt => "GT",
+        Instruction::Le => "LE",
+        Instruction::Ge => "GE",
+        Instruction::And => "AND",
+        Instruction::Or => "OR",
+        Instruction::Not => "NOT",
+        Instruction::Neg => "NEG",
+        Instruction::Return => "RETURN",
+        Instruction::Pop => "POP",
+        Instruction::Dup => "DUP",
+        Instruction::Print => "PRINT",
+        Instruction::EnterScope => "ENTER_SCOPE",
+        Instruction::ExitScope => "EXIT_SCOPE",
+        _ => return None,
+    })
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn hex_decode(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::runtime::{capture_output, Runtime, Value};
+    use std::time::{Duration, SystemTime};
+
+    const PROGRAM: &str = "fn fact(n: Int) -> Int {\n if n <= 1 { 1 } else { n * fact(n - 1) }\n}\n\
+                           let label = \"5! is\"\nprint(label, fact(5))\nlet x = 2\n\
+                           if x > 1 { let x = 10\n print(x) }\nfact(3) + x";
+
+    fn compile(source: &str) -> Bytecode {
+        compile_to_bytecode(&Parser::new(source).parse_program().unwrap())
+    }
+
+    #[test]
+    fn test_bytecode_runs_like_the_source() {
+        let program = Parser::new(PROGRAM).parse_program().unwrap();
+        let bytecode = compile_to_bytecode(&program);
+        assert!(bytecode.is_complete(), "{:?}", bytecode.unsupported);
+
+        let (from_source, source_output) = capture_output(|| Runtime::new().eval_program(&program));
+        let (from_bytecode, bytecode_output) = capture_output(|| Runtime::new().eval_bytecode(&bytecode));
+        assert_eq!(from_bytecode, Ok(Value::Integer(8)));
+        assert_eq!(from_bytecode, from_source);
+        assert_eq!(bytecode_output, "5! is 120\n10\n");
+        assert_eq!(bytecode_output, source_output);
+    }
+
+    #[test]
+    fn test_logical_operators_short_circuit() {
+        let source = "fn loud(b: Bool) -> Bool {\n print(b)\n b\n}\nlet a = false && loud(true)\n\
+                      let b = true || loud(false)\nlet c = true && loud(false)\nprint(a, b, c)\nc || 1 < 2";
+        let program = Parser::new(source).parse_program().unwrap();
+        let bytecode = compile_to_bytecode(&program);
+        assert!(bytecode.is_complete(), "{:?}", bytecode.unsupported);
+
+        let (from_source, source_output) = capture_output(|| Runtime::new().eval_program(&program));
+        let (from_bytecode, bytecode_output) = capture_output(|| Runtime::new().eval_bytecode(&bytecode));
+        assert_eq!(from_bytecode, Ok(Value::Boolean(true)));
+        assert_eq!(from_bytecode, from_source);
+        assert_eq!(bytecode_output, "false\nfalse true false\n");
+        assert_eq!(bytecode_output, source_output);
+    }
+
+    #[test]
+    fn test_text_form_round_trips() {
+        let bytecode = compile(&format!("{}\nlet s = \"two words\\nand a line\"\nlet f = -0.1", PROGRAM));
+        let text = bytecode.to_text();
+        assert_eq!(Bytecode::from_text(&text), Ok(bytecode));
+
+        let err = Bytecode::from_text("const int 1\ncode\nLOAD_CONST 0\nJUMP_SOMEWHERE\n").unwrap_err();
+        assert_eq!(err, "line 4: malformed instruction 'JUMP_SOMEWHERE'");
+    }
+
+    #[test]
+    fn test_unsupported_constructs_are_reported() {
+        let bytecode = compile("let xs = [1, 2]\nlet n = xs.len()\nn");
+        assert_eq!(bytecode.unsupported, vec!["Array", "MethodCall"]);
+        assert!(write_cache(Path::new("unused.logos"), &bytecode).unwrap_err().contains("Array, MethodCall"));
+    }
+
+    #[test]
+    fn test_cache_is_invalidated_when_the_source_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("app.logos");
+        std::fs::write(&source, PROGRAM).unwrap();
+        assert_eq!(load_cache(&source), None);
+
+        let bytecode = compile(PROGRAM);
+        assert_eq!(write_cache(&source, &bytecode).unwrap(), dir.path().join("app.logosc"));
+        assert_eq!(load_cache(&source), Some(bytecode));
+
+        // Any change to the source's mtime, even to an earlier time, makes the cache stale
+        let file = std::fs::File::options().write(true).open(&source).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1)).unwrap();
+        assert_eq!(load_cache(&source), None);
+    }
+
+    #[test]
+    fn test_cached_runs_report_error_locations() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("app.logos");
+        for program in ["fn ratio(a: Int, b: Int) -> Int {\n  a / b\n}\nlet x = 10\nratio(x, 0)", "let n = 0\n7 % n"] {
+            std::fs::write(&source, program).unwrap();
+            let parsed = Parser::new(program).parse_program().unwrap();
+
+            // Once from the source, as on the first run, then from the cache `logos build` wrote
+            let cold = Runtime::new().eval_program(&parsed).unwrap_err();
+            write_cache(&source, &compile_to_bytecode(&parsed)).unwrap();
+            let cached = Runtime::new().eval_bytecode(&load_cache(&source).unwrap()).unwrap_err();
+
+            assert!(cold.contains(" at line 2, column "), "{}", cold);
+            assert_eq!(cached, cold);
+        }
+    }
+}
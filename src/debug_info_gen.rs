@@ -182,7 +182,7 @@ impl DebugInfoGenerator {
                 // Record usage of the variable
                 self.record_variable_usage(name, self.current_line, 0); // Column 0 for now
             },
-            Expression::BinaryOp(left, _, right) => {
+            Expression::BinaryOp(left, _, right, _) => {
                 self.generate_debug_info_for_expression(left);
                 self.generate_debug_info_for_expression(right);
             },
@@ -1,16 +1,85 @@
-fn main() {
-    use crate::lexer::{Lexer, Token};
+// Logos Token Debugging
+// The token stream the lexer produces for a piece of source, with where each token
+// came from. The parser only ever sees tokens one at a time; this lists them all, so
+// a misread can be traced to the lexer or ruled out:
+//
+//   let x = 1   ->   1:1 Let   1:5 Identifier("x")   1:7 Assign   1:9 Integer(1)
+//
+// `dump` is what `logos check --dump-tokens` and the shell's `:tokens` print; pasting
+// it into a bug report shows exactly what the lexer made of the input.
 
-    let input = "@python{print(\"hello\");}";
-    let mut lexer = Lexer::new(input);
+use crate::lexer::{Lexer, Token};
+use std::ops::Range;
 
-    println!("Input: {}", input);
-    println!("Tokens:");
+/// A token and the place in the source it was read from
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub line: usize,
+    pub column: usize,
+    /// Byte offsets of the token's text in the source
+    pub span: Range<usize>,
+}
+
+/// Lexes all of `source`, ending with the `Eof` token
+pub fn tokenize_with_spans(source: &str) -> Vec<SpannedToken> {
+    let mut lexer = Lexer::new(source);
+    let mut tokens = Vec::new();
     loop {
         let token = lexer.next_token();
-        println!("  {:?}", token);
-        if matches!(token, Token::Eof) {
-            break;
+        let (line, column) = lexer.token_position();
+        let done = matches!(token, Token::Eof);
+        tokens.push(SpannedToken { token, line, column, span: lexer.token_span() });
+        if done {
+            return tokens;
         }
     }
-}
\ No newline at end of file
+}
+
+/// One line per token: where it starts, its byte span, its kind and the text it was read from
+///
+/// ```text
+/// 1:5 4..5 Identifier "x"
+/// ```
+pub fn dump(source: &str) -> String {
+    let mut out = String::new();
+    for spanned in tokenize_with_spans(source) {
+        let lexeme = source.get(spanned.span.clone()).unwrap_or("");
+        out.push_str(&format!(
+            "{}:{} {}..{} {} {:?}\n",
+            spanned.line, spanned.column, spanned.span.start, spanned.span.end, kind(&spanned.token), lexeme
+        ));
+    }
+    out
+}
+
+/// The token's variant name, without the value it carries
+fn kind(token: &Token) -> String {
+    let debug = format!("{:?}", token);
+    match debug.find(['(', ' ', '{']) {
+        Some(end) => debug[..end].to_string(),
+        None => debug,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dump_lists_every_token_with_its_span() {
+        let dump = dump("let x = 42\nprint(\"hi\")");
+        let expected = [
+            r#"1:1 0..3 Let "let""#,
+            r#"1:5 4..5 Identifier "x""#,
+            r#"1:7 6..7 Assign "=""#,
+            r#"1:9 8..10 Integer "42""#,
+            r#"2:1 11..16 Identifier "print""#,
+            r#"2:6 16..17 LeftParen "(""#,
+            r#"2:7 17..21 String "\"hi\"""#,
+            r#"2:11 21..22 RightParen ")""#,
+            r#"2:12 22..22 Eof """#,
+        ];
+        assert_eq!(dump.lines().collect::<Vec<_>>(), expected);
+    }
+}
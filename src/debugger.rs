@@ -447,6 +447,7 @@ impl Debugger {
             Value::Integer(i) => i.to_string(),
             Value::Float(f) => f.to_string(),
             Value::String(s) => format!("\"{}\"", s),
+            Value::Bytes(bytes) => format!("b\"{}\"", crate::lexer::escape_bytes(bytes)),
             Value::Boolean(b) => b.to_string(),
             Value::Unit => "()".to_string(),
             Value::Function(name, _, _, _) => format!("<function {}>", name),
@@ -459,16 +460,29 @@ impl Debugger {
                 format!("[{}]", item_strs.join(", "))
             },
             Value::Struct(name, fields) => {
-                let field_strs: Vec<String> = fields.iter()
+                let mut sorted: Vec<_> = fields.iter().collect();
+                sorted.sort_by(|a, b| a.0.cmp(b.0));
+                let field_strs: Vec<String> = sorted.into_iter()
                     .map(|(k, v)| format!("{}: {}", k, self.format_value(v)))
                     .collect();
                 format!("{} {{ {} }}", name, field_strs.join(", "))
             },
             Value::Char(c) => format!("'{}'", c),
             Value::BuiltinFunction(_) => "<builtin function>".to_string(),
+            Value::ForeignFunction(_) => value.to_string(),
             Value::Future(boxed_value) => format!("<future: {}>", self.format_value(boxed_value)),
             Value::Task(boxed_value) => format!("<task: {}>", self.format_value(boxed_value)),
             Value::Closure(_, _, _) => "<closure>".to_string(),
+            Value::Box(inner) => format!("Box({})", self.format_value(inner)),
+            Value::Rc(inner) => format!("Rc({})", self.format_value(inner)),
+            Value::Cell(_) | Value::RefCell(_) | Value::Borrow(_) | Value::Map(_) | Value::Set(_) | Value::Channel(_) => {
+                value.to_string()
+            },
+            Value::Option(Some(inner)) => format!("Some({})", self.format_value(inner)),
+            Value::Option(None) => "None".to_string(),
+            Value::Result(Ok(inner)) => format!("Ok({})", self.format_value(inner)),
+            Value::Result(Err(error)) => format!("Err({})", self.format_value(error)),
+            Value::TraitObject(trait_name, inner, _) => format!("<dyn {}: {}>", trait_name, self.format_value(inner)),
         }
     }
 
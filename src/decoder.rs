@@ -436,7 +436,7 @@ impl LogosDecoder {
             Boolean(b) => if *b { "true".to_string() } else { "false".to_string() },
             Nil => "nil".to_string(),
             Identifier(name) => name.clone(),
-            BinaryOp(left, op, right) => {
+            BinaryOp(left, op, right, _) => {
                 format!("({} {} {})", Self::expression_to_code(left), Self::op_to_code(op), Self::expression_to_code(right))
             },
             Call(name, args) => {
@@ -0,0 +1,321 @@
+// Logos Derive Expansion
+// Expands `#[derive(...)]` on struct and enum declarations into ordinary `impl` blocks,
+// placed right after the declaration. Because the result is plain AST, the trait
+// resolver, type checker and runtime treat derived implementations exactly like
+// hand-written ones. Supported traits are Eq, Display, Clone and Hash.
+
+use crate::ast::*;
+use std::collections::{HashMap, HashSet};
+
+/// Traits that `#[derive(...)]` can generate
+pub const DERIVABLE_TRAITS: &[&str] = &["Eq", "Display", "Clone", "Hash"];
+
+/// Attribute placed on generated impl blocks
+pub const AUTOMATICALLY_DERIVED: &str = "automatically_derived";
+
+/// Returns the traits requested by the `#[derive(...)]` attributes of an item, in order
+pub fn derive_list(attributes: &[Attribute]) -> Result<Vec<String>, String> {
+    let mut traits = Vec::new();
+
+    for attribute in attributes.iter().filter(|attr| attr.name == "derive") {
+        for arg in &attribute.args {
+            match arg {
+                AttributeArg::Word(name) if DERIVABLE_TRAITS.contains(&name.as_str()) => {
+                    if !traits.contains(name) {
+                        traits.push(name.clone());
+                    }
+                },
+                AttributeArg::Word(name) => {
+                    return Err(format!("Cannot derive '{}': derivable traits are {}", name, DERIVABLE_TRAITS.join(", ")));
+                },
+                _ => return Err("#[derive] expects a list of trait names, e.g. #[derive(Eq, Clone)]".to_string()),
+            }
+        }
+    }
+
+    Ok(traits)
+}
+
+/// Adds an impl block for every derived trait in the program
+///
+/// A struct field whose type is another user-defined type must implement the trait
+/// being derived, either by deriving it too or through a hand-written impl.
+pub fn expand_derives(program: Program) -> Result<Program, String> {
+    let items = program.statements.into_iter().map(|statement| (statement, 0, 0)).collect();
+    let statements = expand_derives_located(items)?.into_iter().map(|(statement, _, _)| statement).collect();
+    Ok(Program { statements })
+}
+
+/// `expand_derives` over statements from `Parser::parse_program_with_lines`
+///
+/// Each generated impl takes the lines of the type it was derived for.
+pub fn expand_derives_located(items: Vec<(Statement, usize, usize)>) -> Result<Vec<(Statement, usize, usize)>, String> {
+    let mut user_types = HashSet::new();
+    let mut implemented: HashMap<String, HashSet<String>> = HashMap::new();
+
+    for (statement, _, _) in &items {
+        let (type_name, attributes) = match statement {
+            Statement::Class(def) => (&def.name, &def.attributes),
+            Statement::Enum(def) => (&def.name, &def.attributes),
+            Statement::Implementation(def) => {
+                implemented.entry(def.for_type.clone()).or_default().insert(def.trait_name.clone());
+                continue;
+            },
+            _ => continue,
+        };
+
+        user_types.insert(type_name.clone());
+        implemented.entry(type_name.clone()).or_default().extend(derive_list(attributes)?);
+    }
+
+    let mut expanded = Vec::with_capacity(items.len());
+    for (statement, start, end) in items {
+        let derived = match &statement {
+            Statement::Class(def) => derive_list(&def.attributes)?
+                .iter()
+                .map(|trait_name| derive_for_struct(def, trait_name, &user_types, &implemented))
+                .collect::<Result<Vec<_>, _>>()?,
+            Statement::Enum(def) => derive_list(&def.attributes)?
+                .iter()
+                .map(|trait_name| derived_impl(&def.name, trait_name, Vec::new()))
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        expanded.push((statement, start, end));
+        expanded.extend(derived.into_iter().map(|def| (Statement::Implementation(def), start, end)));
+    }
+
+    Ok(expanded)
+}
+
+/// Generates the impl of one trait for a struct
+fn derive_for_struct(
+    def: &ClassDef,
+    trait_name: &str,
+    user_types: &HashSet<String>,
+    implemented: &HashMap<String, HashSet<String>>,
+) -> Result<ImplDef, String> {
+    for field in &def.fields {
+        if let Type::Named(field_type) = &field.type_annotation {
+            let implements = implemented.get(field_type).map_or(false, |traits| traits.contains(trait_name));
+            if user_types.contains(field_type) && !implements {
+                return Err(format!(
+                    "Cannot derive {} for '{}': field '{}' has type '{}', which does not implement {}",
+                    trait_name, def.name, field.name, field_type, trait_name
+                ));
+            }
+        }
+    }
+
+    let methods = match trait_name {
+        "Eq" => vec![derive_eq(def)],
+        "Display" => vec![derive_display(def, user_types)],
+        "Clone" => vec![derive_clone(def)],
+        "Hash" => {
+            // The runtime hashes struct keys field by field, so the impl only has to
+            // record that the type is hashable
+            if let Some(field) = def.fields.iter().find(|field| !is_hashable_field(&field.type_annotation)) {
+                return Err(format!(
+                    "Cannot derive Hash for '{}': field '{}' has type '{}', which is not hashable",
+                    def.name, field.name, field.type_annotation
+                ));
+            }
+            Vec::new()
+        },
+        other => return Err(format!("Cannot derive '{}'", other)),
+    };
+
+    Ok(derived_impl(&def.name, trait_name, methods))
+}
+
+/// Floats and functions can't be hashed; user-defined field types are checked separately
+fn is_hashable_field(ty: &Type) -> bool {
+    match ty {
+        Type::Float | Type::Function(_, _) | Type::Map(_, _) | Type::Set(_) => false,
+        Type::Array(inner) => is_hashable_field(inner),
+        Type::Tuple(types) => types.iter().all(is_hashable_field),
+        _ => true,
+    }
+}
+
+/// Wraps generated methods in an impl block marked #[automatically_derived]
+///
+/// Enums don't have runtime values yet, so for them the impl carries no methods and
+/// only records that the trait is implemented.
+fn derived_impl(type_name: &str, trait_name: &str, methods: Vec<FunctionDef>) -> ImplDef {
+    ImplDef {
+        trait_name: trait_name.to_string(),
+        for_type: type_name.to_string(),
+        type_params: Vec::new(),
+        methods,
+        associated_types: Vec::new(),
+        attributes: vec![Attribute { name: AUTOMATICALLY_DERIVED.to_string(), args: Vec::new() }],
+    }
+}
+
+/// fn eq(self: T, other: T) -> Bool { self.a == other.a && self.b == other.b }
+fn derive_eq(def: &ClassDef) -> FunctionDef {
+    let body = def.fields.iter()
+        .map(|field| Expression::BinaryOp(
+            Box::new(field_of("self", &field.name)),
+            BinaryOp::Eq,
+            Box::new(field_of("other", &field.name)),
+            ParsedAt::default(),
+        ))
+        .reduce(|acc, cmp| Expression::BinaryOp(Box::new(acc), BinaryOp::And, Box::new(cmp), ParsedAt::default()))
+        .unwrap_or(Expression::Boolean(true));
+
+    method("eq", vec![self_param(&def.name), param("other", &def.name)], Type::Bool, body)
+}
+
+/// fn to_string(self: T) -> String, rendering `T { a: 1, b: 2 }` in declaration order
+fn derive_display(def: &ClassDef, user_types: &HashSet<String>) -> FunctionDef {
+    let mut parts = Vec::new();
+
+    if def.fields.is_empty() {
+        parts.push(Expression::String(def.name.clone()));
+    } else {
+        for (i, field) in def.fields.iter().enumerate() {
+            let prefix = if i == 0 { format!("{} {{ ", def.name) } else { ", ".to_string() };
+            parts.push(Expression::String(format!("{}{}: ", prefix, field.name)));
+
+            // Nested user types render through their own Display impl
+            let value = field_of("self", &field.name);
+            parts.push(match &field.type_annotation {
+                Type::Named(ty) if user_types.contains(ty) => Expression::MethodCall(Box::new(value), "to_string".to_string(), Vec::new()),
+                _ => Expression::Call("str".to_string(), vec![value]),
+            });
+        }
+        parts.push(Expression::String(" }".to_string()));
+    }
+
+    let body = parts.into_iter()
+        .reduce(|acc, part| Expression::BinaryOp(Box::new(acc), BinaryOp::Add, Box::new(part), ParsedAt::default()))
+        .unwrap_or_else(|| Expression::String(String::new()));
+
+    method("to_string", vec![self_param(&def.name)], Type::String, body)
+}
+
+/// fn clone(self: T) -> T { T { a: self.a.clone(), b: self.b.clone() } }
+fn derive_clone(def: &ClassDef) -> FunctionDef {
+    let fields = def.fields.iter()
+        .map(|field| {
+            let cloned = Expression::MethodCall(Box::new(field_of("self", &field.name)), "clone".to_string(), Vec::new());
+            (field.name.clone(), cloned)
+        })
+        .collect();
+
+    method("clone", vec![self_param(&def.name)], Type::Named(def.name.clone()), Expression::Struct(def.name.clone(), fields))
+}
+
+fn field_of(variable: &str, field: &str) -> Expression {
+    Expression::FieldAccess(Box::new(Expression::Identifier(variable.to_string())), field.to_string())
+}
+
+fn self_param(type_name: &str) -> Parameter {
+    param("self", type_name)
+}
+
+fn param(name: &str, type_name: &str) -> Parameter {
+    Parameter {
+        name: name.to_string(),
+        type_annotation: Type::Named(type_name.to_string()),
+        ownership_modifier: None,
+        lifetime_annotation: None,
+        default_value: None,
+        mutability: None,
+    }
+}
+
+fn method(name: &str, parameters: Vec<Parameter>, return_type: Type, body: Expression) -> FunctionDef {
+    FunctionDef {
+        name: name.to_string(),
+        parameters,
+        return_type: Some(return_type),
+        body: vec![Statement::Expression(body)],
+        is_async: false,
+        is_public: true,
+        is_awaitable: false,
+        effect_annotations: Vec::new(),
+        generic_params: Vec::new(),
+        attributes: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::runtime::{Runtime, Value};
+
+    fn run(source: &str) -> Result<Value, String> {
+        let program = Parser::new(source).parse_program()?;
+        let program = expand_derives(program)?;
+        Runtime::new().eval_program(&program)
+    }
+
+    const POINT: &str = r#"
+        #[derive(Eq, Display, Clone)]
+        struct Point { x: Int, y: Int }
+    "#;
+
+    #[test]
+    fn test_derived_eq_compares_fields() {
+        let equal = run(&format!("{}\nPoint {{ x: 1, y: 2 }} == Point {{ x: 1, y: 2 }}", POINT)).unwrap();
+        assert_eq!(equal, Value::Boolean(true));
+
+        let different = run(&format!("{}\nPoint {{ x: 1, y: 2 }} == Point {{ x: 1, y: 3 }}", POINT)).unwrap();
+        assert_eq!(different, Value::Boolean(false));
+    }
+
+    #[test]
+    fn test_derived_display_prints_fields_in_order() {
+        let source = format!("{}\nlet p = Point {{ x: 1, y: 2 }}\np.to_string()", POINT);
+        assert_eq!(run(&source).unwrap(), Value::String("Point { x: 1, y: 2 }".to_string()));
+    }
+
+    #[test]
+    fn test_derived_clone_copies_fields() {
+        let source = format!("{}\nlet p = Point {{ x: 1, y: 2 }}\nlet q = p.clone()\nq == p", POINT);
+        assert_eq!(run(&source).unwrap(), Value::Boolean(true));
+    }
+
+    #[test]
+    fn test_nested_display_uses_field_impl() {
+        let source = format!(
+            "{}\n#[derive(Display)]\nstruct Line {{ start: Point, end: Point }}\nlet l = Line {{ start: Point {{ x: 0, y: 0 }}, end: Point {{ x: 1, y: 1 }} }}\nl.to_string()",
+            POINT
+        );
+        assert_eq!(
+            run(&source).unwrap(),
+            Value::String("Line { start: Point { x: 0, y: 0 }, end: Point { x: 1, y: 1 } }".to_string())
+        );
+    }
+
+    #[test]
+    fn test_eq_requires_derive() {
+        let err = run("struct Plain { x: Int }\nPlain { x: 1 } == Plain { x: 1 }").unwrap_err();
+        assert!(err.contains("does not implement Eq"));
+    }
+
+    #[test]
+    fn test_derive_checks_field_types() {
+        let program = Parser::new("struct Inner { x: Int }\n#[derive(Eq)]\nstruct Outer { inner: Inner }").parse_program().unwrap();
+        let err = expand_derives(program).unwrap_err();
+        assert!(err.contains("field 'inner' has type 'Inner'"));
+    }
+
+    #[test]
+    fn test_derived_impls_pass_type_checking() {
+        let program = expand_derives(Parser::new(POINT).parse_program().unwrap()).unwrap();
+        assert_eq!(program.statements.len(), 4);
+        assert!(crate::type_checker::check_types(&program).is_ok());
+    }
+
+    #[test]
+    fn test_unknown_derive_is_an_error() {
+        let program = Parser::new("#[derive(Ord)]\nstruct P { x: Int }").parse_program().unwrap();
+        assert!(expand_derives(program).unwrap_err().contains("Cannot derive 'Ord'"));
+    }
+}
@@ -0,0 +1,98 @@
+// Logos Documentation Check
+// `logos doc --check` fails when a public item has no doc comment. An item is
+// public when its declaration starts with `pub`, and documented when a `///`
+// line sits directly above it (above its attributes, if it has any):
+//
+//   /// Adds two numbers
+//   #[inline]
+//   pub fn add(a: Int, b: Int) -> Int { a + b }
+//
+// `#[allow(missing_docs)]` exempts an item. Only top-level functions, structs,
+// classes, enums, traits and type aliases are checked.
+
+use std::fmt;
+
+use crate::ast::*;
+use crate::lexer::declares_public;
+use crate::parser::Parser;
+
+/// A public item without a doc comment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingDoc {
+    pub kind: &'static str,
+    pub name: String,
+    pub line: usize,
+}
+
+impl fmt::Display for MissingDoc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: public {} `{}` has no doc comment", self.line, self.kind, self.name)
+    }
+}
+
+/// Lists the public top-level items in `source` that have no doc comment, in source order
+pub fn missing_docs(source: &str) -> Result<Vec<MissingDoc>, String> {
+    let items = Parser::new(source).parse_program_with_lines()?;
+    let lines: Vec<&str> = source.lines().collect();
+
+    let mut missing = Vec::new();
+    for (statement, start, end) in &items {
+        let (kind, name, attributes): (&'static str, &String, &[Attribute]) = match statement {
+            Statement::Function(def) => ("function", &def.name, &def.attributes),
+            Statement::Class(def) => ("type", &def.name, &def.attributes),
+            Statement::Enum(def) => ("enum", &def.name, &def.attributes),
+            Statement::Trait(def) => ("trait", &def.name, &def.attributes),
+            Statement::TypeAlias(def) => ("type alias", &def.name, &[]),
+            _ => continue,
+        };
+
+        let text = lines[start - 1..*end].join("\n");
+        if !declares_public(&text) || allows_missing_docs(attributes) || has_doc_comment(&lines, *start) {
+            continue;
+        }
+        missing.push(MissingDoc { kind, name: name.clone(), line: *start });
+    }
+    Ok(missing)
+}
+
+fn allows_missing_docs(attributes: &[Attribute]) -> bool {
+    attributes.iter().any(|attribute| attribute.name == "allow" && attribute.has_word("missing_docs"))
+}
+
+/// Whether the line above `line` (1-based) is a `///` doc comment
+fn has_doc_comment(lines: &[&str], line: usize) -> bool {
+    line >= 2 && lines[line - 2].trim_start().starts_with("///")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_undocumented_public_items_are_reported() {
+        let source = "pub fn area(w: Int, h: Int) -> Int { w * h }\n\n\
+                      fn helper() -> Int { 1 }\n\n\
+                      #[inline]\n\
+                      pub fn twice(x: Int) -> Int { x * 2 }\n\
+                      pub trait Shape {\n    fn sides(self: Shape) -> Int {}\n}\n";
+        let missing = missing_docs(source).unwrap();
+        assert_eq!(
+            missing.iter().map(|item| item.to_string()).collect::<Vec<_>>(),
+            vec![
+                "line 1: public function `area` has no doc comment",
+                "line 5: public function `twice` has no doc comment",
+                "line 7: public trait `Shape` has no doc comment",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_documented_and_allowed_items_pass() {
+        let source = "/// Area of a rectangle\npub fn area(w: Int, h: Int) -> Int { w * h }\n\n\
+                      /// Doubles its input\n#[inline]\npub fn twice(x: Int) -> Int { x * 2 }\n\n\
+                      #[allow(missing_docs)]\npub fn internal_detail() -> Int { 0 }\n\n\
+                      // A plain comment is not documentation, but this item is private\n\
+                      fn helper() -> Int { 1 }\n";
+        assert_eq!(missing_docs(source).unwrap(), vec![]);
+    }
+}
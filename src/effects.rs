@@ -1,6 +1,8 @@
 // Complete effect system implementation for Logos programming language
 // This implements algebraic effects with handlers
 
+use crate::additional_core_features::type_system;
+use crate::ast::{FunctionDef, Statement, Type};
 use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
@@ -275,6 +277,16 @@ impl EffectSet {
     }
 }
 
+/// Lists the effects by name in sorted order, so diagnostics don't depend on the order
+/// the effects were encountered in
+impl fmt::Display for EffectSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut names: Vec<String> = self.effects.iter().map(Effect::name).collect();
+        names.sort();
+        write!(f, "{{{}}}", names.join(", "))
+    }
+}
+
 impl Default for EffectSet {
     fn default() -> Self {
         Self::new()
@@ -379,6 +391,48 @@ impl EffectSystem {
     }
 }
 
+/// An effect resolver that knows the effects a program declares and what each of its
+/// functions declares it performs
+pub fn effect_resolver(statements: &[Statement]) -> type_system::EffectSystem {
+    let mut resolver = type_system::EffectSystem::new();
+    for statement in statements {
+        match statement {
+            Statement::Effect(effect) => {
+                for operation in &effect.operations {
+                    resolver.register_effect_signature(effect.name.clone(), type_system::EffectSignature {
+                        operation_name: operation.name.clone(),
+                        parameter_types: operation.parameters.iter().map(|param| param.type_annotation.clone()).collect(),
+                        return_type: operation.return_type.clone().unwrap_or(Type::Unit),
+                    });
+                }
+            },
+            Statement::Function(func) => {
+                let declared = func.declared_effects().into_iter().map(str::to_string).collect();
+                resolver.declare_function_effects(func.name.clone(), declared);
+            },
+            _ => {},
+        }
+    }
+    resolver
+}
+
+/// Checks that a function declares every effect its body performs
+///
+/// Calling an effect's operation performs that effect, and calling a function performs
+/// whatever that function declares, so undeclared effects can't hide behind a call.
+/// An effect with a handler installed in `resolver` is dealt with there and needn't be declared.
+pub fn check_function_effects(func: &FunctionDef, resolver: &type_system::EffectSystem) -> Result<(), String> {
+    let declared = func.declared_effects();
+    for statement in &func.body {
+        for effect in resolver.check_effects_in_statement(statement)? {
+            if !declared.contains(&effect.as_str()) && !resolver.is_effect_handled(&effect) {
+                return Err(format!("function '{}' performs effect '{}' but does not declare it", func.name, effect));
+            }
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -406,6 +460,40 @@ mod tests {
         set.insert(Effect::IO);
         assert!(set.contains(&Effect::IO));
         assert!(!set.contains(&Effect::Exception));
+
+        // Rendered in name order, whatever order the effects were added in
+        set.insert(Effect::State("Int".to_string()));
+        set.insert(Effect::Exception);
+        assert_eq!(set.to_string(), "{Exception, IO, State<Int>}");
+    }
+
+    #[test]
+    fn test_functions_declare_the_effects_they_perform() {
+        let program = crate::parser::Parser::new(
+            "effect Log { fn log(message: String) {} }\n\
+             fn greet(name: String) with Log { log(name) }\n\
+             fn welcome() { greet(\"you\") }\n\
+             fn quiet() -> Int { 1 + 2 }",
+        ).parse_program().unwrap();
+        let resolver = effect_resolver(&program.statements);
+        let function = |index: usize| match &program.statements[index] {
+            Statement::Function(func) => func.clone(),
+            _ => panic!("expected a function"),
+        };
+
+        assert!(check_function_effects(&function(1), &resolver).is_ok());
+        assert!(check_function_effects(&function(3), &resolver).is_ok());
+        // Calling a function that declares an effect performs it too
+        assert_eq!(
+            check_function_effects(&function(2), &resolver),
+            Err("function 'welcome' performs effect 'Log' but does not declare it".to_string())
+        );
+
+        // Unless a handler deals with it
+        let mut handled = effect_resolver(&program.statements);
+        let handler = type_system::EffectHandler { operations: HashMap::new(), return_type: Type::Unit };
+        handled.register_handler("Log".to_string(), handler);
+        assert!(check_function_effects(&function(2), &handled).is_ok());
     }
 
     #[test]
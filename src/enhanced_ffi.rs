@@ -302,23 +302,9 @@ impl FFIManager {
         cache.insert(cache_key, result);
     }
 
-    /// Make a C function call
+    /// Make a C function call by loading the library and calling the symbol directly
     fn call_c_function(&self, call: &FFICall) -> Result<FFIValue, String> {
-        // In a real implementation, this would use libloading or similar to call C functions
-        // For now, we'll simulate the call
-        println!("Calling C function: {} from {}", call.function_name, call.library_path);
-
-        // Simulate returning a value based on the return type
-        Ok(match call.return_type {
-            FFIType::Int => FFIValue::Int(42),
-            FFIType::Float => FFIValue::Float(3.14),
-            FFIType::Double => FFIValue::Double(2.718281828),
-            FFIType::Bool => FFIValue::Bool(true),
-            FFIType::Char => FFIValue::Char(b'C'),
-            FFIType::String => FFIValue::String("C function result".to_string()),
-            FFIType::Void => FFIValue::Void,
-            _ => FFIValue::Int(0), // Default for other types
-        })
+        native_c::call(call)
     }
 
     /// Make a Rust function call
@@ -576,6 +562,133 @@ impl InterLanguageCallOptimizer {
     }
 }
 
+/// Calls into C libraries through the platform's dynamic loader. Only signatures
+/// whose arguments and result are all `Double` or all `Int` can be called, since
+/// each one needs its own function pointer type; that covers most of libm and
+/// the integer helpers of libc.
+#[cfg(unix)]
+mod native_c {
+    use super::{FFICall, FFIType, FFIValue};
+    use std::ffi::{c_void, CString};
+    use std::os::raw::{c_char, c_int};
+
+    const RTLD_NOW: c_int = 2;
+
+    #[cfg_attr(target_os = "linux", link(name = "dl"))]
+    extern "C" {
+        fn dlopen(filename: *const c_char, flag: c_int) -> *mut c_void;
+        fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    }
+
+    /// The file names tried for a library written as `libm` or `m`
+    fn library_candidates(library: &str) -> Vec<String> {
+        vec![
+            library.to_string(),
+            format!("{}.so", library),
+            format!("{}.so.6", library),
+            format!("lib{}.so", library),
+            format!("lib{}.so.6", library),
+            format!("{}.dylib", library),
+            format!("lib{}.dylib", library),
+        ]
+    }
+
+    /// Finds `name` in `library`. A Logos declaration may prefix the C name with
+    /// `c_` (`c_sqrt` for `sqrt`) so it doesn't shadow a builtin of the same name.
+    fn lookup(library: &str, name: &str) -> Result<*mut c_void, String> {
+        // Libraries stay loaded for the life of the process, as the symbols may be called again
+        let handle = library_candidates(library)
+            .into_iter()
+            .filter_map(|candidate| CString::new(candidate).ok())
+            .map(|candidate| unsafe { dlopen(candidate.as_ptr(), RTLD_NOW) })
+            .find(|handle| !handle.is_null())
+            .ok_or_else(|| format!("Could not load C library '{}'", library))?;
+
+        let names = std::iter::once(name).chain(name.strip_prefix("c_"));
+        for symbol in names {
+            let symbol = CString::new(symbol).map_err(|_| format!("Invalid C function name '{}'", name))?;
+            let address = unsafe { dlsym(handle, symbol.as_ptr()) };
+            if !address.is_null() {
+                return Ok(address);
+            }
+        }
+        Err(format!("C library '{}' has no function '{}'", library, name))
+    }
+
+    pub fn call(call: &FFICall) -> Result<FFIValue, String> {
+        let doubles: Option<Vec<f64>> = call.parameters.iter()
+            .map(|value| if let FFIValue::Double(d) = value { Some(*d) } else { None })
+            .collect();
+        let ints: Option<Vec<i64>> = call.parameters.iter()
+            .map(|value| if let FFIValue::Int(i) = value { Some(*i) } else { None })
+            .collect();
+        let unsupported = || format!(
+            "C function '{}' has an unsupported signature: up to three arguments, all Int or all Float, returning the same type",
+            call.function_name
+        );
+
+        match (&call.return_type, doubles, ints) {
+            (FFIType::Double, Some(args), _) if args.len() <= 3 => {
+                let address = lookup(&call.library_path, &call.function_name)?;
+                // SAFETY: the declaration promises the symbol takes `args.len()` doubles
+                // and returns a double; a wrong declaration is the caller's to answer for,
+                // as with any extern block
+                let result = unsafe {
+                    match args[..] {
+                        [] => std::mem::transmute::<*mut c_void, extern "C" fn() -> f64>(address)(),
+                        [a] => std::mem::transmute::<*mut c_void, extern "C" fn(f64) -> f64>(address)(a),
+                        [a, b] => std::mem::transmute::<*mut c_void, extern "C" fn(f64, f64) -> f64>(address)(a, b),
+                        [a, b, c] => {
+                            std::mem::transmute::<*mut c_void, extern "C" fn(f64, f64, f64) -> f64>(address)(a, b, c)
+                        },
+                        _ => unreachable!(),
+                    }
+                };
+                Ok(FFIValue::Double(result))
+            },
+            (FFIType::Int, _, Some(args)) if args.len() <= 3 => {
+                let address = lookup(&call.library_path, &call.function_name)?;
+                // SAFETY: as above, with 64-bit integers in place of doubles
+                let result = unsafe {
+                    match args[..] {
+                        [] => std::mem::transmute::<*mut c_void, extern "C" fn() -> i64>(address)(),
+                        [a] => std::mem::transmute::<*mut c_void, extern "C" fn(i64) -> i64>(address)(a),
+                        [a, b] => std::mem::transmute::<*mut c_void, extern "C" fn(i64, i64) -> i64>(address)(a, b),
+                        [a, b, c] => {
+                            std::mem::transmute::<*mut c_void, extern "C" fn(i64, i64, i64) -> i64>(address)(a, b, c)
+                        },
+                        _ => unreachable!(),
+                    }
+                };
+                Ok(FFIValue::Int(result))
+            },
+            _ => Err(unsupported()),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod native_c {
+    use super::{FFICall, FFIValue};
+
+    pub fn call(call: &FFICall) -> Result<FFIValue, String> {
+        Err(format!("Can't call C function '{}': C calls need a Unix dynamic loader", call.function_name))
+    }
+}
+
+/// The FFI type a Logos value of type `ty` crosses the boundary as, if it can cross at all
+pub fn ffi_type_of(ty: &crate::ast::Type) -> Option<FFIType> {
+    use crate::ast::Type;
+    match ty {
+        Type::Int => Some(FFIType::Int),
+        Type::Float => Some(FFIType::Double),
+        Type::Bool => Some(FFIType::Bool),
+        Type::String => Some(FFIType::String),
+        Type::Unit => Some(FFIType::Void),
+        _ => None,
+    }
+}
+
 /// Global FFI manager instance
 use lazy_static::lazy_static;
 
@@ -592,6 +705,14 @@ pub fn make_ffi_call(call: FFICall) -> Result<FFIValue, String> {
     optimizer.optimize_call(&call, &manager)
 }
 
+/// Call a function declared with `extern` in Logos source. The optimizer's result
+/// cache is skipped: a foreign function may have side effects, and nothing about
+/// its name says whether it does.
+pub fn call_extern(call: FFICall) -> Result<FFIValue, String> {
+    let manager = GLOBAL_FFI_MANAGER.lock().unwrap();
+    manager.call_foreign_function(call)
+}
+
 /// Load a foreign library through the global FFI manager
 pub fn load_foreign_library(name: &str, path: &str) -> Result<(), String> {
     let mut manager = GLOBAL_FFI_MANAGER.lock().unwrap();
@@ -630,7 +751,7 @@ mod tests {
     #[test]
     fn test_ffi_call_types() {
         let call = FFICall {
-            language: FFICallType::C,
+            language: FFICallType::Rust,
             function_name: "test_func".to_string(),
             library_path: "/path/to/lib.so".to_string(),
             parameters: vec![],
@@ -644,4 +765,26 @@ mod tests {
         let result = manager.call_foreign_function(call);
         assert!(result.is_ok());
     }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_c_calls_report_what_went_wrong() {
+        let call = |library: &str, name: &str, parameters, return_type| FFICall {
+            language: FFICallType::C,
+            function_name: name.to_string(),
+            library_path: library.to_string(),
+            parameters,
+            return_type,
+            safety_level: FFISafetyLevel::Unsafe,
+            timeout: None,
+        };
+        let manager = FFIManager::new();
+
+        let missing = manager.call_foreign_function(call("no_such_library", "f", vec![], FFIType::Int));
+        assert_eq!(missing.unwrap_err(), "Could not load C library 'no_such_library'");
+
+        let mixed = vec![FFIValue::Int(1), FFIValue::Double(2.0)];
+        let unsupported = manager.call_foreign_function(call("libm", "pow", mixed, FFIType::Double));
+        assert!(unsupported.unwrap_err().contains("unsupported signature"));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,167 @@
+// Logos Diagnostic Explanations
+// Long-form descriptions of the compiler's diagnostic codes, printed by
+// `logos explain <code>` in the spirit of `rustc --explain`. Each entry gives the
+// meaning of the code, a minimal program that triggers it, and how to fix it.
+
+/// The long-form explanation of one diagnostic code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Explanation {
+    pub code: &'static str,
+    pub title: &'static str,
+    pub message: &'static str, // Start of the error message this code is reported with
+    pub description: &'static str,
+    pub example: &'static str,
+    pub fix: &'static str,
+}
+
+/// Every diagnostic code the compiler reports, in code order
+pub const EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        code: "E0001",
+        title: "undefined variable",
+        message: "Undefined variable",
+        description: "A name was used that is not bound by a `let`, a function parameter or a \
+                      declaration visible at that point. Bindings are only visible after the \
+                      statement that introduces them and inside the block they were declared in.",
+        example: "fn main() {\n    print(total)\n}",
+        fix: "Declare the variable before using it, or correct the spelling:\n\nfn main() {\n    let total = 0\n    print(total)\n}",
+    },
+    Explanation {
+        code: "E0002",
+        title: "type mismatch",
+        message: "Type mismatch",
+        description: "A value's type doesn't match the type annotation of the variable or \
+                      constant it is assigned to.",
+        example: "let count: Int = \"three\"",
+        fix: "Change the value or the annotation so they agree:\n\nlet count: Int = 3",
+    },
+    Explanation {
+        code: "E0003",
+        title: "division by zero",
+        message: "Division by zero",
+        description: "An integer or float was divided by zero at runtime. Logos reports this as \
+                      an error instead of producing infinity or crashing.",
+        example: "let items = 0\nprint(10 / items)",
+        fix: "Check the divisor before dividing:\n\nlet items = 0\nif items != 0 { print(10 / items) }",
+    },
+    Explanation {
+        code: "E0004",
+        title: "type does not implement a trait",
+        message: "does not implement trait",
+        description: "A value was used as a `dyn Trait` object, or a trait method was called on \
+                      it, but there is no `impl Trait for Type` for its type.",
+        example: "trait Drawable { fn draw(self: Int) -> String }\nlet shape: dyn Drawable = 7",
+        fix: "Implement the trait for the type:\n\nimpl Drawable for Int {\n    fn draw(self: Int) -> String { \"int\" }\n}",
+    },
+    Explanation {
+        code: "E0005",
+        title: "struct compared without Eq",
+        message: "does not implement Eq",
+        description: "Two struct values were compared with `==` or `!=`, but the struct doesn't \
+                      implement Eq. Structs are only comparable when they opt in.",
+        example: "struct Point { x: Int, y: Int }\nPoint { x: 1, y: 2 } == Point { x: 1, y: 2 }",
+        fix: "Derive Eq on the struct:\n\n#[derive(Eq)]\nstruct Point { x: Int, y: Int }",
+    },
+    Explanation {
+        code: "E0006",
+        title: "unhashable map key",
+        message: "cannot be used as a map key",
+        description: "A Map or Set key must be hashable and comparable. Floats and functions are \
+                      never hashable, and structs must implement both Eq and Hash.",
+        example: "let prices: Map<Float, String> = Map::new()",
+        fix: "Use a hashable key type, or derive Eq and Hash on the struct used as the key:\n\n\
+              #[derive(Eq, Hash)]\nstruct Point { x: Int, y: Int }\nlet names: Map<Point, String> = Map::new()",
+    },
+    Explanation {
+        code: "E0007",
+        title: "allocation exceeds limit",
+        message: "allocation of",
+        description: "An array allocation asked for more elements than the runtime allows. The \
+                      size is checked before any memory is allocated, and elements of nested \
+                      arrays count towards it.",
+        example: "let grid = [0; 10000000000]",
+        fix: "Allocate less, or raise the limit when embedding the runtime with \
+              `Runtime::set_max_allocation`.",
+    },
+    Explanation {
+        code: "E0008",
+        title: "RefCell already borrowed",
+        message: "RefCell already borrowed",
+        description: "A RefCell was borrowed mutably while another borrow of it was still alive. \
+                      RefCell enforces at runtime that a mutable borrow is exclusive.",
+        example: "let cell = RefCell::new(1)\nlet a = cell.borrow_mut()\nlet b = cell.borrow_mut()",
+        fix: "Let the first borrow go out of scope before borrowing again, or read through a \
+              shared `borrow()` instead.",
+    },
+];
+
+/// Finds the explanation of a code, accepting `E0001`, `e0001` or `0001`
+pub fn lookup(code: &str) -> Option<&'static Explanation> {
+    let code = code.trim();
+    let digits = code.strip_prefix('E').or_else(|| code.strip_prefix('e')).unwrap_or(code);
+    EXPLANATIONS.iter().find(|explanation| &explanation.code[1..] == digits)
+}
+
+/// Finds the code an error message is reported with, if it has one
+pub fn code_for_message(message: &str) -> Option<&'static str> {
+    EXPLANATIONS.iter()
+        .find(|explanation| message.contains(explanation.message))
+        .map(|explanation| explanation.code)
+}
+
+/// Renders an explanation the way `logos explain` prints it
+pub fn render(explanation: &Explanation) -> String {
+    format!(
+        "{}: {}\n\n{}\n\nErroneous code example:\n\n{}\n\nHow to fix it:\n\n{}\n",
+        explanation.code, explanation.title, explanation.description, indent(explanation.example), explanation.fix
+    )
+}
+
+/// Returns the rendered explanation of a code, or an error listing the known codes
+pub fn explain(code: &str) -> Result<String, String> {
+    match lookup(code) {
+        Some(explanation) => Ok(render(explanation)),
+        None => {
+            let known: Vec<&str> = EXPLANATIONS.iter().map(|explanation| explanation.code).collect();
+            Err(format!("'{}' is not a Logos diagnostic code; known codes are {}", code.trim(), known.join(", ")))
+        },
+    }
+}
+
+fn indent(text: &str) -> String {
+    text.lines().map(|line| format!("    {}", line)).collect::<Vec<_>>().join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_explain_known_code() {
+        let text = explain("E0005").unwrap();
+        assert!(text.starts_with("E0005: struct compared without Eq"));
+        assert!(text.contains("Erroneous code example:"));
+        assert!(text.contains("#[derive(Eq)]"));
+
+        // The prefix is optional and case doesn't matter
+        assert_eq!(explain("e0005").unwrap(), text);
+        assert_eq!(explain("0005").unwrap(), text);
+    }
+
+    #[test]
+    fn test_explain_unknown_code() {
+        let err = explain("E9999").unwrap_err();
+        assert!(err.contains("'E9999' is not a Logos diagnostic code"));
+        assert!(err.contains("E0001"));
+    }
+
+    #[test]
+    fn test_codes_are_unique_and_match_messages() {
+        for (i, explanation) in EXPLANATIONS.iter().enumerate() {
+            assert_eq!(explanation.code, format!("E{:04}", i + 1));
+        }
+        assert_eq!(code_for_message("Undefined variable: x"), Some("E0001"));
+        assert_eq!(code_for_message("allocation of 10 elements exceeds limit of 5"), Some("E0007"));
+        assert_eq!(code_for_message("something else"), None);
+    }
+}
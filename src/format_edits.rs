@@ -0,0 +1,616 @@
+// Logos Formatter Edits
+// Describes what `logos fmt` would change as a list of text edits instead of a whole
+// new file, so editors can preview them or apply them one at a time. Edits are found
+// by diffing the original source against the formatter's output line by line: each
+// run of differing lines becomes one edit replacing those lines of the original.
+//
+// Spans are byte offsets into the original source, with the end exclusive, alongside
+// the 1-based line numbers they cover. Edits are sorted and never overlap, so they can
+// be applied back to front without adjusting offsets.
+//
+// `layout` is what `logos fmt` falls back to for items the formatter can't re-emit
+// from the AST (see formatter.rs). It works on lines rather than the AST, so it can't
+// lose comments or change what a program means:
+//
+//   - bracket groups, struct literals and method chains split across lines are
+//     joined back up, dropping the trailing comma before the closing bracket
+//   - lines longer than the maximum width are broken, at the dots of a method chain
+//     or else after the commas of their outermost `(...)`, `[...]` or struct literal,
+//     adding a trailing comma after the last item so appending one is a one-line diff
+//   - every line is re-indented by bracket depth, with chain continuations one deeper
+//
+// Joining first means the result depends only on the code, not on how it was wrapped
+// before, so formatting twice gives the same output.
+
+use serde::Serialize;
+
+/// A replacement of part of the original source
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TextEdit {
+    pub start: usize,      // byte offset of the first replaced byte
+    pub end: usize,        // byte offset just past the last replaced byte
+    pub start_line: usize, // 1-based line the span starts on
+    pub end_line: usize,   // 1-based line the span ends on; start_line - 1 for a pure insertion
+    pub replacement: String,
+}
+
+/// Finds the edits that turn `original` into `formatted`
+pub fn diff(original: &str, formatted: &str) -> Vec<TextEdit> {
+    let old: Vec<&str> = original.split_inclusive('\n').collect();
+    let new: Vec<&str> = formatted.split_inclusive('\n').collect();
+
+    // Byte offset at which each original line starts, plus the end of the source
+    let mut offsets = Vec::with_capacity(old.len() + 1);
+    let mut offset = 0;
+    for line in &old {
+        offsets.push(offset);
+        offset += line.len();
+    }
+    offsets.push(offset);
+
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    for (next_i, next_j) in common_lines(&old, &new).into_iter().chain([(old.len(), new.len())]) {
+        if next_i > i || next_j > j {
+            edits.push(TextEdit {
+                start: offsets[i],
+                end: offsets[next_i],
+                start_line: i + 1,
+                end_line: next_i,
+                replacement: new[j..next_j].concat(),
+            });
+        }
+        i = next_i + 1;
+        j = next_j + 1;
+    }
+    edits
+}
+
+/// Applies edits produced by `diff` to the source they were computed against
+pub fn apply(original: &str, edits: &[TextEdit]) -> String {
+    let mut result = original.to_string();
+    for edit in edits.iter().rev() {
+        result.replace_range(edit.start..edit.end, &edit.replacement);
+    }
+    result
+}
+
+/// Renders edits as the JSON object `logos fmt --format=json` prints
+///
+/// `applied` says whether the edits were written to the file or only reported.
+pub fn to_json(file: &str, edits: &[TextEdit], applied: bool) -> String {
+    let report = EditReport { file, applied, edit_count: edits.len(), edits };
+    serde_json::to_string(&report).expect("an edit report always serializes")
+}
+
+/// The JSON object `to_json` renders
+#[derive(Serialize)]
+struct EditReport<'a> {
+    file: &'a str,
+    applied: bool,
+    edit_count: usize,
+    edits: &'a [TextEdit],
+}
+
+/// Lays source out within `max_width` columns, indenting with `indent`
+pub fn layout(source: &str, indent: &str, max_width: usize) -> String {
+    let joined = reindent(&join_lines(source), indent);
+    let mut wrapped = Vec::new();
+    let mut in_string = false;
+    for line in joined.lines() {
+        let (ends_in_string, _) = scan_code(line, in_string, |_, _| {});
+        if in_string || ends_in_string {
+            wrapped.push(line.to_string());
+        } else {
+            wrap_line(line, indent, max_width, &mut wrapped);
+        }
+        in_string = ends_in_string;
+    }
+    reindent(&wrapped.join("\n"), indent)
+}
+
+/// Re-indents source by bracket nesting, one `indent` deeper inside each line that
+/// leaves a `{`, `(` or `[` open
+///
+/// Lines starting a method call with `.` get one extra level. Trailing whitespace is
+/// trimmed, blank lines lose their indentation and the result ends with a single
+/// newline. Lines that continue a string literal are left as they are.
+pub fn reindent(source: &str, indent: &str) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut open_levels: Vec<usize> = Vec::new(); // level of the line each open bracket is on
+    let mut in_string = false;
+
+    for line in source.lines() {
+        let inner = open_levels.last().map_or(0, |level| level + 1);
+        let mut level = inner;
+        if in_string {
+            out.push_str(line);
+        } else {
+            let trimmed = line.trim();
+            let closers = trimmed.chars().take_while(|c| matches!(c, '}' | ')' | ']')).count();
+            level = match closers {
+                0 => inner + is_chain_continuation(trimmed) as usize,
+                n if n <= open_levels.len() => open_levels[open_levels.len() - n],
+                _ => 0,
+            };
+            if !trimmed.is_empty() {
+                out.push_str(&indent.repeat(level));
+                out.push_str(trimmed);
+            }
+        }
+        out.push('\n');
+
+        (in_string, _) = scan_code(line, in_string, |_, c| match c {
+            '{' | '(' | '[' => open_levels.push(level),
+            '}' | ')' | ']' => { open_levels.pop(); },
+            _ => {},
+        });
+    }
+
+    // Strip trailing whitespace left by the last line or trailing blank lines
+    let content_end = out.trim_end().len();
+    out.truncate(content_end);
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out
+}
+
+/// Joins `(...)` and `[...]` groups and struct literals spanning several lines, and
+/// method chains, onto one line
+///
+/// A group is left alone if it holds a comment, a multi-line string or a block, since
+/// those can't be put on one line safely.
+fn join_lines(source: &str) -> String {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut out = Vec::with_capacity(lines.len());
+    let mut in_string = false;
+    let mut i = 0;
+
+    while i < lines.len() {
+        let mut line = lines[i].to_string();
+        i += 1;
+        if in_string {
+            (in_string, _) = scan_code(&line, true, |_, _| {});
+            out.push(line);
+            continue;
+        }
+
+        loop {
+            let mut open = Vec::new();
+            let (ends_in_string, comment) = scan_code(&line, false, |i, c| match c {
+                '{' | '(' | '[' => open.push(i),
+                '}' | ')' | ']' => { open.pop(); },
+                _ => {},
+            });
+            if ends_in_string || comment || i >= lines.len() {
+                break;
+            }
+            if open.is_empty() {
+                if !is_chain_continuation(lines[i].trim()) {
+                    break;
+                }
+                join_piece(&mut line, lines[i]);
+                i += 1;
+                continue;
+            }
+            let blocks = open.iter().any(|&brace| {
+                let after = &line[brace + 1..];
+                let after = if after.trim().is_empty() { lines[i] } else { after };
+                line.as_bytes()[brace] == b'{' && !opens_struct_literal(&line, brace, after)
+            });
+            if blocks {
+                break;
+            }
+            match group_end(&lines[i..], open.len()) {
+                Some(count) => {
+                    for next in &lines[i..i + count] {
+                        join_piece(&mut line, next);
+                    }
+                    i += count;
+                },
+                None => break,
+            }
+        }
+
+        (in_string, _) = scan_code(&line, false, |_, _| {});
+        out.push(line);
+    }
+    out.join("\n")
+}
+
+/// How many of `lines` it takes to close `open` brackets, if they can be joined
+fn group_end(lines: &[&str], open: usize) -> Option<usize> {
+    let mut depth = open;
+    for (count, line) in lines.iter().enumerate() {
+        let mut blocked = false;
+        let (ends_in_string, comment) = scan_code(line, false, |_, c| {
+            if depth == 0 {
+                return;
+            }
+            match c {
+                '(' | '[' => depth += 1,
+                ')' | ']' | '}' => depth -= 1,
+                '{' => blocked = true,
+                _ => {},
+            }
+        });
+        if blocked || ends_in_string || comment {
+            return None;
+        }
+        if depth == 0 {
+            return Some(count + 1);
+        }
+    }
+    None
+}
+
+/// Appends the next line of a group or chain to `line` with canonical spacing
+///
+/// A trailing comma before the closing bracket is dropped, as it would be on one line.
+fn join_piece(line: &mut String, next: &str) {
+    let next = next.trim();
+    if next.is_empty() {
+        return;
+    }
+    line.truncate(line.trim_end().len());
+    if next.starts_with([')', ']', '}']) {
+        if line.ends_with(',') {
+            line.pop();
+        }
+        if next.starts_with('}') && !line.ends_with('{') {
+            line.push(' ');
+        }
+    } else if !line.ends_with(['(', '[']) && !is_chain_continuation(next) {
+        line.push(' ');
+    }
+    line.push_str(next);
+}
+
+/// Breaks `line` into lines no wider than `max_width` where it can, appending them to `out`
+///
+/// Indentation of the pieces is approximate; `layout` re-indents afterwards.
+fn wrap_line(line: &str, indent: &str, max_width: usize, out: &mut Vec<String>) {
+    if width(line) <= max_width {
+        out.push(line.to_string());
+        return;
+    }
+    let leading = &line[..line.len() - line.trim_start().len()];
+    let continued = format!("{}{}", leading, indent);
+
+    // Method call dots outside any bracket, and every group closed on this line
+    let bytes = line.as_bytes();
+    let mut chain_dots = Vec::new();
+    let mut groups = Vec::new(); // (depth, open, close, has a top-level comma)
+    let mut stack: Vec<(usize, bool)> = Vec::new();
+    scan_code(line, false, |i, c| match c {
+        '.' if stack.is_empty() && i > 0 && bytes[i - 1] != b'.' && starts_method_call(&line[i + 1..]) => chain_dots.push(i),
+        '(' | '[' | '{' => stack.push((i, false)),
+        ')' | ']' | '}' => {
+            if let Some((open, comma)) = stack.pop() {
+                let braces = (c == '}', bytes[open] == b'{');
+                if braces == (false, false) || (braces == (true, true) && opens_struct_literal(line, open, &line[open + 1..])) {
+                    groups.push((stack.len(), open, i, comma));
+                }
+            }
+        },
+        ',' => {
+            if let Some(top) = stack.last_mut() {
+                top.1 = true;
+            }
+        },
+        _ => {},
+    });
+
+    // A chain of two or more method calls breaks before each call
+    if chain_dots.len() >= 2 {
+        wrap_line(&line[..chain_dots[0]], indent, max_width, out);
+        for (k, &dot) in chain_dots.iter().enumerate() {
+            let end = chain_dots.get(k + 1).copied().unwrap_or(line.len());
+            wrap_line(&format!("{}{}", continued, &line[dot..end]), indent, max_width, out);
+        }
+        return;
+    }
+
+    // Otherwise break the outermost group, preferring one with several items
+    let shallowest = groups.iter().map(|g| g.0).min();
+    let group = groups.iter()
+        .filter(|g| Some(g.0) == shallowest && !line[g.1 + 1..g.2].trim().is_empty())
+        .min_by_key(|g| (!g.3, g.1));
+    let (_, open, close, comma) = match group {
+        Some(&group) => group,
+        None => {
+            out.push(line.to_string());
+            return;
+        },
+    };
+
+    // Lists take a trailing comma; a lone item in `(...)` or `[...]` might be a
+    // parenthesized expression, an index or a repeated array, which can't
+    let trailing = comma || bytes[open] == b'{' || (bytes[open] == b'(' && follows_callee(&line[..open]));
+    wrap_line(&line[..=open], indent, max_width, out);
+    let items = split_items(&line[open + 1..close]);
+    let last = items.len() - 1;
+    for (k, item) in items.iter().enumerate() {
+        let comma = if k < last || trailing { "," } else { "" };
+        wrap_line(&format!("{}{}{}", continued, item.trim(), comma), indent, max_width, out);
+    }
+    wrap_line(&format!("{}{}", leading, &line[close..]), indent, max_width, out);
+}
+
+/// Splits the inside of a bracket group at its top-level commas
+fn split_items(inner: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    scan_code(inner, false, |i, c| match c {
+        '(' | '[' | '{' => depth += 1,
+        ')' | ']' | '}' => depth = depth.saturating_sub(1),
+        ',' if depth == 0 => {
+            items.push(&inner[start..i]);
+            start = i + 1;
+        },
+        _ => {},
+    });
+    let rest = &inner[start..];
+    if !rest.trim().is_empty() || items.is_empty() {
+        items.push(rest);
+    }
+    items
+}
+
+/// Whether the `{` at `brace` opens a struct literal such as `Point { x: 1 }`, given
+/// the code that follows it
+fn opens_struct_literal(line: &str, brace: usize, after: &str) -> bool {
+    use crate::lexer::is_identifier_continue;
+    let before = line[..brace].trim_end();
+    let name_start = before.char_indices()
+        .rev()
+        .take_while(|&(_, c)| is_identifier_continue(c))
+        .last()
+        .map_or(before.len(), |(i, _)| i);
+    let name = &before[name_start..];
+    let keyword = before[..name_start].split_whitespace().last();
+
+    let after = after.trim_start();
+    let field_len = after.find(|c: char| !is_identifier_continue(c)).unwrap_or(after.len());
+    let starts_field = field_len > 0 && after[field_len..].starts_with(':') && !after[field_len..].starts_with("::");
+
+    name.starts_with(char::is_uppercase)
+        && !matches!(keyword, Some("struct" | "enum" | "class" | "trait" | "impl" | "for" | "effect" | "actor"))
+        && (starts_field || after.starts_with('}'))
+}
+
+/// Whether the code before a `(` ends in something that can be called, so the parens
+/// hold arguments or parameters
+fn follows_callee(before: &str) -> bool {
+    // `@import(...)` and the other `@` directives don't accept one
+    if before.trim_end_matches(crate::lexer::is_identifier_continue).ends_with('@') {
+        return false;
+    }
+    before.chars().last().map_or(false, |c| crate::lexer::is_identifier_continue(c) || matches!(c, ')' | ']' | '!' | '>' | '?'))
+}
+
+/// Whether a trimmed line continues a method chain, as in `.map(f)`
+fn is_chain_continuation(trimmed: &str) -> bool {
+    trimmed.strip_prefix('.').map_or(false, starts_method_call)
+}
+
+/// Whether text starts with `name(`, the part of a method call after its dot
+fn starts_method_call(rest: &str) -> bool {
+    let name_len = rest.find(|c: char| !crate::lexer::is_identifier_continue(c)).unwrap_or(rest.len());
+    name_len > 0
+        && rest.chars().next().map_or(false, crate::lexer::is_identifier_start)
+        && rest[name_len..].starts_with('(')
+}
+
+/// Display width of a line, counting a tab as four columns
+fn width(line: &str) -> usize {
+    line.chars().map(|c| if c == '\t' { 4 } else { 1 }).sum()
+}
+
+/// Whether any line of `source` has a `//` comment outside a string
+pub(crate) fn has_comment(source: &str) -> bool {
+    let mut in_string = false;
+    for line in source.lines() {
+        let (ends_in_string, comment) = scan_code(line, in_string, |_, _| {});
+        if comment {
+            return true;
+        }
+        in_string = ends_in_string;
+    }
+    false
+}
+
+/// Calls `visit` with the byte offset of every character of `line` that is code,
+/// not part of a string or character literal or a comment
+///
+/// `in_string` says whether the line starts inside a string. Returns whether it ends
+/// inside one, and whether it has a comment.
+pub(crate) fn scan_code(line: &str, mut in_string: bool, mut visit: impl FnMut(usize, char)) -> (bool, bool) {
+    let mut chars = line.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' if in_string => { chars.next(); },
+            '"' => in_string = !in_string,
+            _ if in_string => {},
+            '/' if chars.peek().map(|&(_, next)| next) == Some('/') => return (false, true),
+            '\'' => {
+                // A character literal, or else the quote of a loop label
+                let mut rest = line[i + 1..].chars();
+                let literal_len = match (rest.next(), rest.next(), rest.next()) {
+                    (Some('\\'), Some(_), Some('\'')) => 3,
+                    (Some(ch), Some('\''), _) if ch != '\\' => 2,
+                    _ => 0,
+                };
+                for _ in 0..literal_len {
+                    chars.next();
+                }
+                if literal_len == 0 {
+                    visit(i, c);
+                }
+            },
+            _ => visit(i, c),
+        }
+    }
+    (in_string, false)
+}
+
+/// Pairs of indices of lines that both sides share, in order, as a longest common subsequence
+fn common_lines(old: &[&str], new: &[&str]) -> Vec<(usize, usize)> {
+    // lengths[i][j] is the length of the LCS of old[i..] and new[j..]
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edits_for_misindented_input() {
+        let original = "fn main() {\nlet x = 1\n      print(x)\n}\n";
+        let formatted = "fn main() {\n    let x = 1\n    print(x)\n}\n";
+        let edits = diff(original, formatted);
+        assert_eq!(edits, vec![TextEdit {
+            start: 12,
+            end: 37,
+            start_line: 2,
+            end_line: 3,
+            replacement: "    let x = 1\n    print(x)\n".to_string(),
+        }]);
+        assert_eq!(apply(original, &edits), formatted);
+        assert_eq!(
+            to_json("main.logos", &edits, false),
+            "{\"file\":\"main.logos\",\"applied\":false,\"edit_count\":1,\"edits\":[{\"start\":12,\"end\":37,\
+             \"start_line\":2,\"end_line\":3,\"replacement\":\"    let x = 1\\n    print(x)\\n\"}]}"
+        );
+    }
+
+    #[test]
+    fn test_reindent_by_bracket_depth() {
+        let source = "fn main() {\nlet s = \"{ not a block\"   \n  // a { comment\nif x {\n        f(1,\n2)\n}\n\n      }\n\n\n";
+        assert_eq!(
+            reindent(source, "    "),
+            "fn main() {\n    let s = \"{ not a block\"\n    // a { comment\n    if x {\n        f(1,\n            2)\n    }\n\n}\n"
+        );
+        assert_eq!(reindent(&reindent(source, "\t"), "\t"), reindent(source, "\t"));
+        assert_eq!(reindent("", "    "), "");
+    }
+
+    fn assert_layout(source: &str, max_width: usize, expected: &str) {
+        let formatted = layout(source, "    ", max_width);
+        assert_eq!(formatted, expected);
+        assert_eq!(layout(&formatted, "    ", max_width), formatted, "formatting is not idempotent");
+    }
+
+    #[test]
+    fn test_long_call_is_wrapped() {
+        assert_layout(
+            "fn main() {\n    print(format_total(order.items, tax_rate), customer_name(order), shipping_label(address))\n}\n",
+            60,
+            "fn main() {\n    print(\n        format_total(order.items, tax_rate),\n        customer_name(order),\n        shipping_label(address),\n    )\n}\n",
+        );
+    }
+
+    #[test]
+    fn test_short_call_stays_inline() {
+        assert_layout("print(add(1, 2), 3)\n", 60, "print(add(1, 2), 3)\n");
+
+        // A call wrapped by hand is joined back up once it fits
+        assert_layout("print(\n    add(1, 2),\n    3,\n)\n", 60, "print(add(1, 2), 3)\n");
+    }
+
+    #[test]
+    fn test_long_signatures_chains_and_arrays() {
+        assert_layout(
+            "fn transfer(source: Account, destination: Account, amount: Int) -> Result<Int, String> {\n    return Ok(amount)\n}\n",
+            60,
+            "fn transfer(\n    source: Account,\n    destination: Account,\n    amount: Int,\n) -> Result<Int, String> {\n    return Ok(amount)\n}\n",
+        );
+        assert_layout(
+            "let names = people.filter(is_adult).map(full_name).sort_by(last_name)\n",
+            40,
+            "let names = people\n    .filter(is_adult)\n    .map(full_name)\n    .sort_by(last_name)\n",
+        );
+        assert_layout(
+            "let primes = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47]\n",
+            40,
+            "let primes = [\n    2,\n    3,\n    5,\n    7,\n    11,\n    13,\n    17,\n    19,\n    23,\n    29,\n    31,\n    37,\n    41,\n    43,\n    47,\n]\n",
+        );
+    }
+
+    #[test]
+    fn test_trailing_commas_follow_wrapping() {
+        // Added when a call, struct literal or array is wrapped...
+        assert_layout(
+            "let origin = Point { x: compute_offset(base), y: compute_offset(height), z: 0 }\n",
+            50,
+            "let origin = Point {\n    x: compute_offset(base),\n    y: compute_offset(height),\n    z: 0,\n}\n",
+        );
+        assert_layout(
+            "total(inventory_value(warehouse))\n",
+            20,
+            "total(\n    inventory_value(\n        warehouse,\n    ),\n)\n",
+        );
+
+        // ...but not to a lone parenthesized expression or index
+        assert_layout(
+            "let area = (width_in_meters + margin) * height\n",
+            30,
+            "let area = (\n    width_in_meters + margin\n) * height\n",
+        );
+
+        // ...and removed when the literal collapses back onto one line
+        assert_layout("let p = Point {\n    x: 1,\n    y: 2,\n}\n", 50, "let p = Point { x: 1, y: 2 }\n");
+        assert_layout("let xs = [\n    1,\n    2,\n]\n", 50, "let xs = [1, 2]\n");
+
+        // Struct definitions and blocks are never joined
+        let definition = "struct Point {\n    x: Int,\n    y: Int\n}\n";
+        assert_layout(definition, 50, definition);
+    }
+
+    #[test]
+    fn test_groups_with_comments_are_not_joined() {
+        let source = "print(\n    1, // first\n    2\n)\n";
+        assert_layout(source, 60, source);
+    }
+
+    #[test]
+    fn test_separate_edits_insertions_and_no_changes() {
+        let original = "a\n  b\nc\n  d\ne";
+        let formatted = "a\nb\nc\nd\ne\n";
+        let edits = diff(original, formatted);
+        let lines: Vec<(usize, usize)> = edits.iter().map(|e| (e.start_line, e.end_line)).collect();
+        assert_eq!(lines, vec![(2, 2), (4, 5)]);
+        assert_eq!(apply(original, &edits), formatted);
+
+        let inserted = diff("a\nc\n", "a\nb\nc\n");
+        assert_eq!(inserted, vec![TextEdit { start: 2, end: 2, start_line: 2, end_line: 1, replacement: "b\n".to_string() }]);
+
+        assert!(diff(formatted, formatted).is_empty());
+    }
+}
@@ -0,0 +1,252 @@
+// Logos Format Specifiers
+// Parses and applies the `:spec` suffix of string interpolation placeholders, as in
+// "${price:.2}" or "${id:05}". The grammar is the part of Rust's format specs that
+// makes sense for Logos values:
+//
+//     [[fill]align][0][width][.precision]
+//
+// where align is `<` (left), `>` (right) or `^` (center). Numbers are right-aligned by
+// default and everything else left-aligned, and zero-padding goes between a number's
+// sign and its digits. Precision rounds floats, widening integers first, and truncates
+// strings.
+
+use std::fmt;
+
+use crate::runtime::{runtime_type_name, Value};
+
+/// Where a value sits within its field when it is narrower than the width
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,   // <
+    Right,  // >
+    Center, // ^
+}
+
+/// A parsed `[[fill]align][0][width][.precision]` specifier
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatSpec {
+    pub fill: char,
+    pub align: Option<Align>, // None uses the value's default alignment
+    pub zero_pad: bool,
+    pub width: Option<usize>,
+    pub precision: Option<usize>,
+}
+
+impl FormatSpec {
+    /// Parses the text after the `:` of a placeholder
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let invalid = |reason: String| format!("Invalid format spec '{}': {}", spec, reason);
+        let chars: Vec<char> = spec.chars().collect();
+        let mut i = 0;
+
+        // A fill character is only recognised in front of an alignment
+        let mut fill = ' ';
+        let mut align = None;
+        if let Some(a) = chars.get(1).and_then(|&c| align_of(c)) {
+            fill = chars[0];
+            align = Some(a);
+            i = 2;
+        } else if let Some(a) = chars.first().and_then(|&c| align_of(c)) {
+            align = Some(a);
+            i = 1;
+        }
+
+        let zero_pad = chars.get(i) == Some(&'0');
+        if zero_pad {
+            i += 1;
+        }
+
+        let width = digits(&chars, &mut i).map_err(invalid)?;
+        let precision = if chars.get(i) == Some(&'.') {
+            i += 1;
+            match digits(&chars, &mut i).map_err(invalid)? {
+                Some(precision) => Some(precision),
+                None => return Err(invalid("expected digits after '.'".to_string())),
+            }
+        } else {
+            None
+        };
+
+        if let Some(c) = chars.get(i) {
+            return Err(invalid(format!("unexpected '{}'", c)));
+        }
+
+        Ok(FormatSpec { fill, align, zero_pad, width, precision })
+    }
+
+    /// Formats a value according to the spec
+    pub fn apply(&self, value: &Value) -> Result<String, String> {
+        let (text, numeric) = match (value, self.precision) {
+            (Value::Float(f), Some(precision)) => (format!("{:.*}", precision, f), true),
+            (Value::Integer(n), Some(precision)) => (format!("{:.*}", precision, *n as f64), true),
+            (Value::Float(_) | Value::Integer(_), None) => (value.to_string(), true),
+            (Value::String(s), Some(precision)) => (s.chars().take(precision).collect(), false),
+            (Value::String(s), None) => (s.clone(), false),
+            (other, Some(_)) => {
+                return Err(format!("Format spec '{}' sets a precision, which can't be applied to {}", self, runtime_type_name(other)));
+            },
+            (other, None) => (other.to_string(), false),
+        };
+
+        if self.zero_pad && !numeric {
+            return Err(format!("Format spec '{}' zero-pads, which only applies to numbers, not {}", self, runtime_type_name(value)));
+        }
+
+        let padding = match self.width {
+            Some(width) if width > text.chars().count() => width - text.chars().count(),
+            _ => return Ok(text),
+        };
+
+        if self.zero_pad {
+            let (sign, digits) = match text.strip_prefix('-') {
+                Some(digits) => ("-", digits),
+                None => ("", text.as_str()),
+            };
+            return Ok(format!("{}{}{}", sign, "0".repeat(padding), digits));
+        }
+
+        let align = self.align.unwrap_or(if numeric { Align::Right } else { Align::Left });
+        let (before, after) = match align {
+            Align::Left => (0, padding),
+            Align::Right => (padding, 0),
+            Align::Center => (padding / 2, padding - padding / 2),
+        };
+        let fill = self.fill.to_string();
+        Ok(format!("{}{}{}", fill.repeat(before), text, fill.repeat(after)))
+    }
+}
+
+impl fmt::Display for FormatSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(align) = self.align {
+            if self.fill != ' ' {
+                write!(f, "{}", self.fill)?;
+            }
+            let symbol = match align {
+                Align::Left => '<',
+                Align::Right => '>',
+                Align::Center => '^',
+            };
+            write!(f, "{}", symbol)?;
+        }
+        if self.zero_pad {
+            write!(f, "0")?;
+        }
+        if let Some(width) = self.width {
+            write!(f, "{}", width)?;
+        }
+        if let Some(precision) = self.precision {
+            write!(f, ".{}", precision)?;
+        }
+        Ok(())
+    }
+}
+
+/// Splits the inside of a `${...}` placeholder into its expression and its spec, if any
+///
+/// The spec starts at the last `:` outside brackets and string literals, so paths like
+/// `Map::new()` and struct literals stay part of the expression.
+pub fn split_placeholder(placeholder: &str) -> (&str, Option<&str>) {
+    let bytes = placeholder.as_bytes();
+    let mut depth = 0i32;
+    let mut in_string = false;
+    let mut split = None;
+
+    for (i, &b) in bytes.iter().enumerate() {
+        match b {
+            b'"' => in_string = !in_string,
+            _ if in_string => {},
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' => depth -= 1,
+            b':' if depth == 0 => {
+                let doubled = bytes.get(i + 1) == Some(&b':') || (i > 0 && bytes[i - 1] == b':');
+                if !doubled {
+                    split = Some(i);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    match split {
+        Some(i) => (&placeholder[..i], Some(&placeholder[i + 1..])),
+        None => (placeholder, None),
+    }
+}
+
+fn align_of(c: char) -> Option<Align> {
+    match c {
+        '<' => Some(Align::Left),
+        '>' => Some(Align::Right),
+        '^' => Some(Align::Center),
+        _ => None,
+    }
+}
+
+/// Reads a run of decimal digits starting at `*i`, if there is one
+fn digits(chars: &[char], i: &mut usize) -> Result<Option<usize>, String> {
+    let start = *i;
+    while chars.get(*i).map_or(false, char::is_ascii_digit) {
+        *i += 1;
+    }
+    if *i == start {
+        return Ok(None);
+    }
+    let text: String = chars[start..*i].iter().collect();
+    text.parse().map(Some).map_err(|_| format!("{} is too large", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(spec: &str, value: Value) -> String {
+        FormatSpec::parse(spec).unwrap().apply(&value).unwrap()
+    }
+
+    #[test]
+    fn test_float_precision() {
+        assert_eq!(format(".2", Value::Float(3.14159)), "3.14");
+        assert_eq!(format("0.2", Value::Float(2.5)), "2.50");
+        assert_eq!(format(".1", Value::Integer(7)), "7.0");
+        assert_eq!(format("8.3", Value::Float(-1.5)), "  -1.500");
+    }
+
+    #[test]
+    fn test_integer_zero_padding() {
+        assert_eq!(format("05", Value::Integer(42)), "00042");
+        assert_eq!(format("05", Value::Integer(-42)), "-0042");
+        assert_eq!(format("03", Value::Integer(12345)), "12345");
+    }
+
+    #[test]
+    fn test_alignment() {
+        let name = || Value::String("ab".to_string());
+        assert_eq!(format("5", name()), "ab   ");
+        assert_eq!(format(">5", name()), "   ab");
+        assert_eq!(format("^6", name()), "  ab  ");
+        assert_eq!(format("*<5", name()), "ab***");
+        assert_eq!(format("5", Value::Integer(7)), "    7");
+        assert_eq!(format("<5", Value::Integer(7)), "7    ");
+    }
+
+    #[test]
+    fn test_invalid_specs() {
+        assert!(FormatSpec::parse(".").unwrap_err().contains("expected digits after '.'"));
+        assert!(FormatSpec::parse("5x").unwrap_err().contains("unexpected 'x'"));
+        assert!(FormatSpec::parse("99999999999999999999999").unwrap_err().contains("is too large"));
+
+        let err = FormatSpec::parse("05").unwrap().apply(&Value::Boolean(true)).unwrap_err();
+        assert!(err.contains("only applies to numbers"), "{}", err);
+    }
+
+    #[test]
+    fn test_spec_round_trips_and_splits() {
+        for spec in ["05", ".2", "*^10.3", "<4"] {
+            assert_eq!(FormatSpec::parse(spec).unwrap().to_string(), spec);
+        }
+        assert_eq!(split_placeholder("price:.2"), ("price", Some(".2")));
+        assert_eq!(split_placeholder("Map::new()"), ("Map::new()", None));
+        assert_eq!(split_placeholder("Point { x: 1 }.x:3"), ("Point { x: 1 }.x", Some("3")));
+    }
+}
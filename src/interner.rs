@@ -0,0 +1,109 @@
+// Logos Identifier Interner
+// Maps each distinct identifier to a small integer `Symbol`, so the checker's
+// scope tables hash and compare a `u32` instead of a heap string. Every name is
+// stored once and can be resolved back for diagnostics:
+//
+//   interner.intern("count")  ->  Symbol(7)  ->  "count" in an error message
+//
+// Each type check owns its interner, shared by the nested scopes of that check, so
+// the table is freed with the checker and lookups take no lock. A symbol only means
+// something to the interner that made it.
+//
+// Only the type checker interns, as it binds and looks up names. The parser
+// still hands out identifiers as `String`s in the AST, and the runtime, linter
+// and codegen compare those strings directly; moving the AST itself to symbols
+// is not done here.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// An interned identifier; two symbols are equal exactly when their names are
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol(u32);
+
+/// A table of distinct names, each stored once and numbered in interning order
+#[derive(Debug, Default)]
+pub struct Interner {
+    symbols: HashMap<Rc<str>, Symbol>,
+    names: Vec<Rc<str>>,
+}
+
+impl Interner {
+    /// Creates an empty interner
+    pub fn new() -> Self {
+        Interner::default()
+    }
+
+    /// Returns the symbol for `name`, copying it into the table only the first time it is seen
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(&symbol) = self.symbols.get(name) {
+            return symbol;
+        }
+        let symbol = Symbol(self.names.len() as u32);
+        let name: Rc<str> = Rc::from(name);
+        self.names.push(name.clone());
+        self.symbols.insert(name, symbol);
+        symbol
+    }
+
+    /// The symbol for `name` if it has been interned, without adding it
+    pub fn get(&self, name: &str) -> Option<Symbol> {
+        self.symbols.get(name).copied()
+    }
+
+    /// The name a symbol stands for
+    pub fn resolve(&self, symbol: Symbol) -> &str {
+        &self.names[symbol.0 as usize]
+    }
+
+    /// How many distinct names have been interned
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Whether nothing has been interned yet
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+impl Symbol {
+    /// The raw index of the symbol
+    pub fn as_u32(self) -> u32 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repeated_names_are_stored_once() {
+        let mut interner = Interner::new();
+        let names = ["total", "count", "total", "i", "count", "total"];
+        let symbols: Vec<Symbol> = (0..1000).map(|i| interner.intern(names[i % names.len()])).collect();
+
+        // A thousand lookups of three names keep three copies of the text
+        assert_eq!(interner.len(), 3);
+        assert_eq!(symbols[0], symbols[2]);
+        assert_ne!(symbols[0], symbols[1]);
+        assert_eq!(interner.get("i"), Some(symbols[3]));
+        assert_eq!(interner.get("missing"), None);
+        assert_eq!(interner.len(), 3);
+    }
+
+    #[test]
+    fn test_symbols_resolve_back_to_their_names() {
+        let mut interner = Interner::new();
+        let point = interner.intern("Point");
+        let unicode = interner.intern("größe");
+        assert_eq!(interner.resolve(point), "Point");
+        assert_eq!(interner.resolve(unicode), "größe");
+
+        // Interners are independent: each numbers its own names from zero
+        let mut other = Interner::new();
+        assert_eq!(other.intern("größe").as_u32(), 0);
+        assert_eq!(unicode.as_u32(), 1);
+    }
+}
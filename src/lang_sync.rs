@@ -0,0 +1,792 @@
+// Logos Language Synchronization
+// This module implements `logos sync <language>`: it scaffolds the files a project
+// needs to interoperate with another language ecosystem under the `logos/` directory.
+// Every supported language is described by an entry in `LANGUAGES`, so adding a
+// language means adding a table entry rather than another copy of the sync logic.
+//
+// Paths in the generated `<language>_sync.toml` are written relative to the project
+// root and resolved when read, so a synced project keeps working after it is moved
+// or checked out somewhere else.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::artifacts::SYNC_DIR;
+
+/// How a language's native dependency manifest is laid out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DependencyFormat {
+    Requirements,  // pip requirements.txt: one `name>=version` per line
+    PackageJson,   // npm package.json "dependencies" object
+    GoMod,         // go.mod `require` block
+    CsProj,        // MSBuild <PackageReference> items
+}
+
+/// Static description of a language that `logos sync` supports
+#[derive(Debug)]
+pub struct LanguageSpec {
+    pub name: &'static str,                            // Canonical name used for file names
+    pub aliases: &'static [&'static str],              // Other names accepted on the command line
+    pub display_name: &'static str,                    // Name shown to users
+    pub import_style: &'static str,
+    pub export_style: &'static str,
+    pub integration: &'static str,                     // Contents of `<name>_integration.logos`
+    pub target_subdirs: &'static [&'static str],       // Extra directories created under the target dir
+    pub dependency_file: &'static str,                 // Native dependency manifest, relative to `logos/`
+    pub dependency_format: DependencyFormat,
+    pub default_dependencies: &'static [(&'static str, &'static str)],  // (package, version) seeded on first sync
+    pub install_command: &'static [&'static str],      // Installs the dependency manifest, run from `logos/`
+}
+
+/// Languages supported by `logos sync`
+pub const LANGUAGES: &[LanguageSpec] = &[
+    LanguageSpec {
+        name: "python",
+        aliases: &["py"],
+        display_name: "Python",
+        import_style: "from_python",
+        export_style: "to_python",
+        integration: r#"// Python Integration Module for Logos
+// Auto-generated file for Python interop
+
+fn call_python_function(module: String, func: String, args: Array) -> Any {
+    // Implementation for calling Python functions from Logos
+    external_call("python", module, func, args)
+}
+
+fn import_python_module(module: String) -> Object {
+    // Import a Python module into Logos
+    external_import("python", module)
+}
+
+fn export_to_python(data: Any) -> String {
+    // Export Logos data to Python-compatible format
+    serialize_for_python(data)
+}
+"#,
+        target_subdirs: &[],
+        dependency_file: "python_requirements.txt",
+        dependency_format: DependencyFormat::Requirements,
+        default_dependencies: &[("numpy", "1.21.0"), ("requests", "2.25.0"), ("pydantic", "1.8.0")],
+        install_command: &["pip", "install", "-r", "python_requirements.txt"],
+    },
+    LanguageSpec {
+        name: "csharp",
+        aliases: &["cs", "c#"],
+        display_name: "C#",
+        import_style: "dll_import",
+        export_style: "interop",
+        integration: r#"// C# Integration Module for Logos
+// Auto-generated file for C# interop with header support
+
+fn call_csharp_method(assembly: String, class: String, method: String, args: Array) -> Any {
+    // Implementation for calling C# methods from Logos
+    external_call("csharp", assembly, class, method, args)
+}
+
+fn import_csharp_assembly(assembly: String) -> Object {
+    // Import a C# assembly into Logos
+    external_import("csharp", assembly)
+}
+
+fn load_csharp_header(header_path: String) -> Bool {
+    // Load C# header/definition file for interop
+    load_header("csharp", header_path)
+}
+
+fn export_to_csharp(data: Any) -> String {
+    // Export Logos data to C#-compatible format
+    serialize_for_csharp(data)
+}
+"#,
+        target_subdirs: &["headers"],
+        dependency_file: "logos_csharp_interop.csproj",
+        dependency_format: DependencyFormat::CsProj,
+        default_dependencies: &[("Newtonsoft.Json", "13.0.1"), ("System.Text.Json", "6.0.0")],
+        install_command: &["dotnet", "restore", "logos_csharp_interop.csproj"],
+    },
+    LanguageSpec {
+        name: "javascript",
+        aliases: &["js"],
+        display_name: "JavaScript",
+        import_style: "require_import",
+        export_style: "module_export",
+        integration: r#"// JavaScript Integration Module for Logos
+// Auto-generated file for JS interop
+
+fn call_js_function(module: String, func: String, args: Array) -> Any {
+    // Implementation for calling JavaScript functions from Logos
+    external_call("javascript", module, func, args)
+}
+
+fn import_js_module(module: String) -> Object {
+    // Import a JavaScript module into Logos
+    external_import("javascript", module)
+}
+
+fn export_to_js(data: Any) -> String {
+    // Export Logos data to JavaScript-compatible format
+    serialize_for_js(data)
+}
+"#,
+        target_subdirs: &[],
+        dependency_file: "package.json",
+        dependency_format: DependencyFormat::PackageJson,
+        default_dependencies: &[("node-fetch", "^2.6.0"), ("lodash", "^4.17.0")],
+        install_command: &["npm", "install"],
+    },
+    LanguageSpec {
+        name: "go",
+        aliases: &["golang"],
+        display_name: "Go",
+        import_style: "cgo_import",
+        export_style: "cgo_export",
+        integration: r#"// Go Integration Module for Logos
+// Auto-generated file for Go interop
+
+fn call_go_function(pkg: String, func: String, args: Array) -> Any {
+    // Implementation for calling Go functions from Logos
+    external_call("go", pkg, func, args)
+}
+
+fn import_go_package(pkg: String) -> Object {
+    // Import a Go package into Logos
+    external_import("go", pkg)
+}
+
+fn export_to_go(data: Any) -> String {
+    // Export Logos data to Go-compatible format
+    serialize_for_go(data)
+}
+"#,
+        target_subdirs: &[],
+        dependency_file: "go.mod",
+        dependency_format: DependencyFormat::GoMod,
+        default_dependencies: &[("github.com/gorilla/websocket", "v1.5.0"), ("golang.org/x/tools", "v0.1.0")],
+        install_command: &["go", "mod", "tidy"],
+    },
+];
+
+/// Looks up a language by its name or one of its aliases (case-insensitive)
+pub fn find_language(name: &str) -> Option<&'static LanguageSpec> {
+    let name = name.to_lowercase();
+    LANGUAGES.iter().find(|spec| spec.name == name || spec.aliases.contains(&name.as_str()))
+}
+
+/// Options for a sync run
+#[derive(Debug, Clone, Default)]
+pub struct SyncOptions {
+    pub bidirectional: bool,
+    pub install_deps: bool,
+    pub force: bool,                  // Regenerate config and integration files even if they exist
+    pub dry_run: bool,                // Report what would change without writing anything
+    pub target_dir: Option<PathBuf>,  // Overrides `logos/<language>_target`; relative to the project root
+}
+
+/// What a sync run did (or would do) to a single file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileAction {
+    Created,
+    Updated,
+    Unchanged,
+}
+
+/// Result of installing a language's dependencies
+#[derive(Debug, Clone)]
+pub struct InstallOutcome {
+    pub command: String,   // The command line, for display
+    pub executed: bool,    // False in dry-run mode
+    pub output: String,    // Combined stdout and stderr of the command
+}
+
+/// Summary of a sync run
+#[derive(Debug, Clone, Default)]
+pub struct SyncReport {
+    pub files: Vec<(PathBuf, FileAction)>,
+    pub install: Option<InstallOutcome>,  // Present when dependency installation was requested
+}
+
+impl SyncReport {
+    /// Returns the action recorded for a path, if the sync touched it
+    pub fn action_for(&self, path: &Path) -> Option<FileAction> {
+        self.files.iter().find(|(p, _)| p == path).map(|(_, action)| *action)
+    }
+}
+
+/// Synchronizes a project with another language
+///
+/// Generated config and integration files are only written when missing (or
+/// with `force`). The dependency manifest is merged: default dependencies are
+/// added if absent, and anything the user has added or changed is kept.
+///
+/// # Arguments
+/// * `project_dir` - Root of the Logos project
+/// * `language` - Language name or alias, e.g. `python` or `js`
+/// * `options` - Sync options
+pub fn sync_language(project_dir: &Path, language: &str, options: &SyncOptions) -> Result<SyncReport, String> {
+    let spec = find_language(language).ok_or_else(|| {
+        let supported: Vec<&str> = LANGUAGES.iter().map(|spec| spec.name).collect();
+        format!("Unsupported language: {}. Supported languages: {}", language, supported.join(", "))
+    })?;
+
+    let logos_dir = project_dir.join(SYNC_DIR);
+    let target_dir = match &options.target_dir {
+        Some(dir) => project_dir.join(dir),
+        None => logos_dir.join(format!("{}_target", spec.name)),
+    };
+
+    let mut report = SyncReport::default();
+    let mut writer = SyncWriter { dry_run: options.dry_run, report: &mut report };
+
+    writer.ensure_dir(&logos_dir)?;
+    writer.ensure_dir(&target_dir)?;
+    for subdir in spec.target_subdirs {
+        writer.ensure_dir(&target_dir.join(subdir))?;
+    }
+
+    let config_path = logos_dir.join(format!("{}_sync.toml", spec.name));
+    writer.write_generated(&config_path, &render_sync_config(spec, project_dir, &target_dir, options), options.force)?;
+
+    let integration_path = logos_dir.join(format!("{}_integration.logos", spec.name));
+    writer.write_generated(&integration_path, spec.integration, options.force)?;
+
+    let deps_path = logos_dir.join(spec.dependency_file);
+    let existing = if deps_path.exists() {
+        Some(fs::read_to_string(&deps_path)
+            .map_err(|e| format!("Could not read {}: {}", deps_path.display(), e))?)
+    } else {
+        None
+    };
+    let merged = merge_dependencies(spec.dependency_format, existing.as_deref(), spec.default_dependencies);
+    writer.write_merged(&deps_path, existing.as_deref(), &merged)?;
+
+    if options.install_deps {
+        report.install = Some(install_dependencies(spec, &logos_dir, options.dry_run)?);
+    }
+
+    Ok(report)
+}
+
+/// Reads a language's synced target directory from its config, resolved against `project_dir`
+///
+/// Configs written before paths were stored relative to the project root hold
+/// absolute paths, which are returned unchanged.
+pub fn sync_target_dir(project_dir: &Path, language: &str) -> Result<PathBuf, String> {
+    let spec = find_language(language).ok_or_else(|| format!("Unsupported language: {}", language))?;
+    let config_path = project_dir.join(SYNC_DIR).join(format!("{}_sync.toml", spec.name));
+    let content = fs::read_to_string(&config_path)
+        .map_err(|e| format!("Could not read {}: {}", config_path.display(), e))?;
+    let config = toml::from_str::<toml::Table>(&content)
+        .map_err(|e| format!("Could not parse {}: {}", config_path.display(), e))?;
+
+    let target = config.get("paths")
+        .and_then(|paths| paths.get("target"))
+        .and_then(|target| target.as_str())
+        .ok_or_else(|| format!("{} has no [paths] target", config_path.display()))?;
+    Ok(resolve_config_path(project_dir, target))
+}
+
+/// Resolves a path from a sync config against the project root
+pub fn resolve_config_path(project_dir: &Path, path: &str) -> PathBuf {
+    project_dir.join(path)
+}
+
+/// Renders `path` for a sync config: relative to the project root with `/` separators,
+/// or as given when it lies outside the project
+fn config_path(project_dir: &Path, path: &Path) -> String {
+    match path.strip_prefix(project_dir) {
+        Ok(relative) => relative.components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+        Err(_) => path.display().to_string(),
+    }
+}
+
+/// Runs a language's dependency installer (`pip install -r`, `npm install`, ...)
+///
+/// In dry-run mode the command is only reported. Otherwise the toolchain must
+/// be on `PATH`; the command's output is captured and a non-zero exit status is
+/// returned as an error that includes that output.
+pub fn install_dependencies(spec: &LanguageSpec, logos_dir: &Path, dry_run: bool) -> Result<InstallOutcome, String> {
+    let command_line = spec.install_command.join(" ");
+    if dry_run {
+        return Ok(InstallOutcome { command: command_line, executed: false, output: String::new() });
+    }
+
+    let (program, args) = spec.install_command.split_first()
+        .ok_or_else(|| format!("No install command configured for {}", spec.display_name))?;
+    let program_path = find_on_path(program).ok_or_else(|| format!(
+        "Cannot install {} dependencies: '{}' was not found on PATH",
+        spec.display_name, program
+    ))?;
+
+    let output = Command::new(program_path)
+        .args(args)
+        .current_dir(logos_dir)
+        .output()
+        .map_err(|e| format!("Could not run '{}': {}", command_line, e))?;
+
+    let mut combined = String::from_utf8_lossy(&output.stdout).into_owned();
+    combined.push_str(&String::from_utf8_lossy(&output.stderr));
+
+    if !output.status.success() {
+        return Err(format!("'{}' failed ({}):\n{}", command_line, output.status, combined.trim_end()));
+    }
+
+    Ok(InstallOutcome { command: command_line, executed: true, output: combined })
+}
+
+/// Finds an executable by name in the directories listed in `PATH`
+fn find_on_path(program: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    let candidates: Vec<String> = if cfg!(windows) {
+        vec![format!("{}.exe", program), format!("{}.cmd", program), program.to_string()]
+    } else {
+        vec![program.to_string()]
+    };
+
+    std::env::split_paths(&path)
+        .flat_map(|dir| candidates.iter().map(move |name| dir.join(name)))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Applies file changes for a sync run and records them in the report
+struct SyncWriter<'r> {
+    dry_run: bool,
+    report: &'r mut SyncReport,
+}
+
+impl<'r> SyncWriter<'r> {
+    fn ensure_dir(&mut self, dir: &Path) -> Result<(), String> {
+        if !dir.exists() && !self.dry_run {
+            fs::create_dir_all(dir).map_err(|e| format!("Could not create {}: {}", dir.display(), e))?;
+        }
+        Ok(())
+    }
+
+    /// Writes a generated file unless it already exists and `overwrite` is false
+    fn write_generated(&mut self, path: &Path, content: &str, overwrite: bool) -> Result<(), String> {
+        let action = if !path.exists() {
+            FileAction::Created
+        } else if overwrite {
+            FileAction::Updated
+        } else {
+            FileAction::Unchanged
+        };
+        self.apply(path, content, action)
+    }
+
+    /// Writes merged content, only touching the file if the merge changed it
+    fn write_merged(&mut self, path: &Path, existing: Option<&str>, merged: &str) -> Result<(), String> {
+        let action = match existing {
+            None => FileAction::Created,
+            Some(existing) if existing == merged => FileAction::Unchanged,
+            Some(_) => FileAction::Updated,
+        };
+        self.apply(path, merged, action)
+    }
+
+    fn apply(&mut self, path: &Path, content: &str, action: FileAction) -> Result<(), String> {
+        if action != FileAction::Unchanged && !self.dry_run {
+            fs::write(path, content).map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+        }
+        self.report.files.push((path.to_path_buf(), action));
+        Ok(())
+    }
+}
+
+fn render_sync_config(spec: &LanguageSpec, project_dir: &Path, target_dir: &Path, options: &SyncOptions) -> String {
+    let mut config = format!(r#"# Paths are relative to the project root
+[sync]
+language = "{}"
+enabled = true
+version = "0.1.0"
+bidirectional = {}
+install_dependencies = {}
+
+[paths]
+source = "src"
+target = "{}"
+"#,
+        spec.name,
+        options.bidirectional,
+        options.install_deps,
+        config_path(project_dir, target_dir)
+    );
+    for subdir in spec.target_subdirs {
+        let dir = config_path(project_dir, &target_dir.join(subdir));
+        config.push_str(&format!("{}_dir = \"{}\"\n", subdir.trim_end_matches('s'), dir));
+    }
+    config.push_str(&format!(r#"
+[integration]
+import_style = "{}"
+export_style = "{}"
+"#, spec.import_style, spec.export_style));
+    config
+}
+
+/// Adds any default dependency missing from `existing`, leaving everything else as written
+///
+/// With no existing manifest, a fresh one is rendered from the defaults.
+pub fn merge_dependencies(format: DependencyFormat, existing: Option<&str>, defaults: &[(&str, &str)]) -> String {
+    let existing = match existing {
+        Some(content) => content,
+        None => return render_dependencies(format, defaults),
+    };
+
+    let present = declared_dependencies(format, existing);
+    let missing: Vec<(&str, &str)> = defaults.iter()
+        .copied()
+        .filter(|(name, _)| !present.iter().any(|p| p == name))
+        .collect();
+    if missing.is_empty() {
+        return existing.to_string();
+    }
+
+    match format {
+        DependencyFormat::Requirements => {
+            let mut merged = existing.to_string();
+            if !merged.is_empty() && !merged.ends_with('\n') {
+                merged.push('\n');
+            }
+            for (name, version) in missing {
+                merged.push_str(&format!("{}>={}\n", name, version));
+            }
+            merged
+        },
+        DependencyFormat::PackageJson => {
+            let entries: Vec<String> = missing.iter()
+                .map(|(name, version)| format!("\"{}\": \"{}\"", name, version))
+                .collect();
+            insert_json_dependencies(existing, &entries)
+        },
+        DependencyFormat::GoMod => {
+            let lines: String = missing.iter()
+                .map(|(name, version)| format!("    {} {}\n", name, version))
+                .collect();
+            match find_go_require_block_end(existing) {
+                Some(close) => format!("{}{}{}", &existing[..close], lines, &existing[close..]),
+                None => {
+                    let separator = if existing.ends_with('\n') { "\n" } else { "\n\n" };
+                    format!("{}{}require (\n{})\n", existing, separator, lines)
+                },
+            }
+        },
+        DependencyFormat::CsProj => {
+            let lines: String = missing.iter()
+                .map(|(name, version)| format!("    <PackageReference Include=\"{}\" Version=\"{}\" />\n", name, version))
+                .collect();
+            match find_package_reference_group_end(existing) {
+                Some(close) => format!("{}{}{}", &existing[..close], lines, &existing[close..]),
+                None => match existing.rfind("</Project>") {
+                    Some(close) => format!("{}  <ItemGroup>\n{}  </ItemGroup>\n\n{}", &existing[..close], lines, &existing[close..]),
+                    None => format!("{}\n<ItemGroup>\n{}</ItemGroup>\n", existing, lines),
+                },
+            }
+        },
+    }
+}
+
+/// Renders a fresh dependency manifest listing `deps`
+fn render_dependencies(format: DependencyFormat, deps: &[(&str, &str)]) -> String {
+    match format {
+        DependencyFormat::Requirements => {
+            let mut out = String::from("# Auto-generated requirements file for Python interop\n# Generated by Logos multi-language sync\n\n");
+            for (name, version) in deps {
+                out.push_str(&format!("{}>={}\n", name, version));
+            }
+            out
+        },
+        DependencyFormat::PackageJson => {
+            let entries: Vec<String> = deps.iter()
+                .map(|(name, version)| format!("    \"{}\": \"{}\"", name, version))
+                .collect();
+            format!(r#"{{
+  "name": "logos-js-interop",
+  "version": "0.1.0",
+  "description": "Auto-generated package for Logos-JavaScript interop",
+  "main": "index.js",
+  "keywords": ["logos", "interop"],
+  "license": "MIT",
+  "dependencies": {{
+{}
+  }}
+}}
+"#, entries.join(",\n"))
+        },
+        DependencyFormat::GoMod => {
+            let lines: String = deps.iter()
+                .map(|(name, version)| format!("    {} {}\n", name, version))
+                .collect();
+            format!("module logos-go-interop\n\ngo 1.19\n\nrequire (\n{})\n", lines)
+        },
+        DependencyFormat::CsProj => {
+            let lines: String = deps.iter()
+                .map(|(name, version)| format!("    <PackageReference Include=\"{}\" Version=\"{}\" />\n", name, version))
+                .collect();
+            format!(r#"<!-- Auto-generated .csproj for Logos-C# interop -->
+<!-- Generated by Logos multi-language sync -->
+
+<Project Sdk="Microsoft.NET.Sdk">
+
+  <PropertyGroup>
+    <TargetFramework>net6.0</TargetFramework>
+    <ImplicitUsings>enable</ImplicitUsings>
+    <Nullable>enable</Nullable>
+  </PropertyGroup>
+
+  <ItemGroup>
+{}  </ItemGroup>
+
+</Project>
+"#, lines)
+        },
+    }
+}
+
+/// Lists the package names declared in a dependency manifest
+pub fn declared_dependencies(format: DependencyFormat, content: &str) -> Vec<String> {
+    match format {
+        DependencyFormat::Requirements => content.lines()
+            .map(|line| line.split('#').next().unwrap_or("").trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('-'))
+            .map(|line| {
+                let end = line.find(|c: char| "=<>!~[; ".contains(c)).unwrap_or(line.len());
+                line[..end].to_string()
+            })
+            .collect(),
+        DependencyFormat::PackageJson => match json_dependencies_span(content) {
+            Some((open, close)) => content[open..close]
+                .split(',')
+                .filter_map(|entry| {
+                    let key = entry.split(':').next()?.trim();
+                    key.strip_prefix('"')?.strip_suffix('"').map(|k| k.to_string())
+                })
+                .collect(),
+            None => Vec::new(),
+        },
+        DependencyFormat::GoMod => {
+            let mut names = Vec::new();
+            let mut in_block = false;
+            for line in content.lines() {
+                let line = line.split("//").next().unwrap_or("").trim();
+                if in_block {
+                    if line == ")" {
+                        in_block = false;
+                    } else if let Some(name) = line.split_whitespace().next() {
+                        names.push(name.to_string());
+                    }
+                } else if line == "require (" || line == "require(" {
+                    in_block = true;
+                } else if let Some(rest) = line.strip_prefix("require ") {
+                    if let Some(name) = rest.split_whitespace().next() {
+                        names.push(name.to_string());
+                    }
+                }
+            }
+            names
+        },
+        DependencyFormat::CsProj => content.match_indices("<PackageReference")
+            .filter_map(|(idx, _)| {
+                let rest = &content[idx..];
+                let start = rest.find("Include=\"")? + "Include=\"".len();
+                let end = rest[start..].find('"')?;
+                Some(rest[start..start + end].to_string())
+            })
+            .collect(),
+    }
+}
+
+/// Byte range of the body of the top-level `"dependencies": { ... }` object
+fn json_dependencies_span(content: &str) -> Option<(usize, usize)> {
+    let key = content.find("\"dependencies\"")?;
+    let open = key + content[key..].find('{')? + 1;
+    let close = open + content[open..].find('}')?;
+    Some((open, close))
+}
+
+fn insert_json_dependencies(content: &str, entries: &[String]) -> String {
+    match json_dependencies_span(content) {
+        Some((open, close)) => {
+            let body = content[open..close].trim_end();
+            let separator = if body.trim().is_empty() { "" } else { "," };
+            let added: Vec<String> = entries.iter().map(|e| format!("    {}", e)).collect();
+            format!("{}{}\n{}\n  {}", &content[..open + body.len()], separator, added.join(",\n"), &content[close..])
+        },
+        None => {
+            // No dependencies object yet: add one just before the final closing brace
+            match content.rfind('}') {
+                Some(end) => {
+                    let head = content[..end].trim_end();
+                    let separator = if head.ends_with('{') { "" } else { "," };
+                    let added: Vec<String> = entries.iter().map(|e| format!("    {}", e)).collect();
+                    format!("{}{}\n  \"dependencies\": {{\n{}\n  }}\n{}", head, separator, added.join(",\n"), &content[end..])
+                },
+                None => content.to_string(),
+            }
+        },
+    }
+}
+
+/// Byte offset of the start of the `)` line closing the first `require (` block in a go.mod
+fn find_go_require_block_end(content: &str) -> Option<usize> {
+    let start = content.find("require (")?;
+    let mut offset = start;
+    for line in content[start..].split_inclusive('\n') {
+        if line.trim() == ")" {
+            return Some(offset);
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// Byte offset of the start of the `</ItemGroup>` line closing the group that holds PackageReferences
+fn find_package_reference_group_end(content: &str) -> Option<usize> {
+    let reference = content.find("<PackageReference")?;
+    let close = reference + content[reference..].find("</ItemGroup>")?;
+    Some(content[..close].rfind('\n').map(|nl| nl + 1).unwrap_or(close))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_language_by_alias() {
+        assert_eq!(find_language("js").map(|spec| spec.name), Some("javascript"));
+        assert_eq!(find_language("Python").map(|spec| spec.name), Some("python"));
+        assert!(find_language("cobol").is_none());
+    }
+
+    #[test]
+    fn test_resync_preserves_user_dependencies() {
+        let project = tempfile::tempdir().unwrap();
+        let options = SyncOptions::default();
+
+        sync_language(project.path(), "python", &options).unwrap();
+        let requirements = project.path().join(SYNC_DIR).join("python_requirements.txt");
+        let config = project.path().join(SYNC_DIR).join("python_sync.toml");
+
+        // The user pins an extra package and tweaks the generated config
+        let mut content = fs::read_to_string(&requirements).unwrap();
+        content.push_str("flask==2.0.1\n");
+        fs::write(&requirements, &content).unwrap();
+        fs::write(&config, "[sync]\nlanguage = \"python\"\n# edited\n").unwrap();
+
+        let report = sync_language(project.path(), "python", &options).unwrap();
+
+        let resynced = fs::read_to_string(&requirements).unwrap();
+        assert!(resynced.contains("flask==2.0.1"));
+        assert_eq!(resynced.matches("numpy").count(), 1);
+        assert!(fs::read_to_string(&config).unwrap().contains("# edited"));
+        assert_eq!(report.action_for(&requirements), Some(FileAction::Unchanged));
+    }
+
+    #[test]
+    fn test_sync_csharp_creates_expected_files() {
+        let project = tempfile::tempdir().unwrap();
+        let report = sync_language(project.path(), "csharp", &SyncOptions::default()).unwrap();
+
+        let logos_dir = project.path().join(SYNC_DIR);
+        let expected = [
+            logos_dir.join("csharp_sync.toml"),
+            logos_dir.join("csharp_integration.logos"),
+            logos_dir.join("logos_csharp_interop.csproj"),
+        ];
+        for path in &expected {
+            assert!(path.is_file(), "missing {}", path.display());
+            assert_eq!(report.action_for(path), Some(FileAction::Created));
+        }
+        // Each file is produced exactly once by the single C# path
+        assert_eq!(report.files.len(), expected.len());
+        assert!(logos_dir.join("csharp_target").join("headers").is_dir());
+
+        let config = fs::read_to_string(&expected[0]).unwrap();
+        assert!(config.contains("language = \"csharp\""));
+        assert!(config.contains("import_style = \"dll_import\""));
+        assert!(config.contains("header_dir = "));
+
+        let csproj = fs::read_to_string(&expected[2]).unwrap();
+        assert_eq!(
+            declared_dependencies(DependencyFormat::CsProj, &csproj),
+            vec!["Newtonsoft.Json", "System.Text.Json"]
+        );
+    }
+
+    #[test]
+    fn test_sync_config_survives_moving_the_project() {
+        let workspace = tempfile::tempdir().unwrap();
+        let original = workspace.path().join("original");
+        fs::create_dir_all(&original).unwrap();
+        sync_language(&original, "csharp", &SyncOptions::default()).unwrap();
+
+        let config = fs::read_to_string(original.join(SYNC_DIR).join("csharp_sync.toml")).unwrap();
+        assert!(config.contains("target = \"logos/csharp_target\""), "{}", config);
+        assert!(config.contains("header_dir = \"logos/csharp_target/headers\""), "{}", config);
+        assert!(!config.contains(&workspace.path().display().to_string()));
+
+        let moved = workspace.path().join("moved");
+        fs::rename(&original, &moved).unwrap();
+        let target = sync_target_dir(&moved, "csharp").unwrap();
+        assert_eq!(target, moved.join(SYNC_DIR).join("csharp_target"));
+        assert!(target.join("headers").is_dir());
+    }
+
+    #[test]
+    fn test_install_dry_run_reports_command_without_running() {
+        for spec in LANGUAGES {
+            let dir = tempfile::tempdir().unwrap();
+            let outcome = install_dependencies(spec, dir.path(), true).unwrap();
+
+            assert!(!outcome.executed);
+            assert_eq!(outcome.command, spec.install_command.join(" "));
+            // Nothing was run, so the directory is still empty
+            assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+        }
+    }
+
+    #[test]
+    fn test_sync_dry_run_with_install_deps() {
+        let project = tempfile::tempdir().unwrap();
+        let options = SyncOptions { install_deps: true, dry_run: true, ..SyncOptions::default() };
+        let report = sync_language(project.path(), "js", &options).unwrap();
+
+        let install = report.install.unwrap();
+        assert_eq!(install.command, "npm install");
+        assert!(!install.executed);
+        assert!(!project.path().join(SYNC_DIR).exists());
+    }
+
+    #[test]
+    fn test_merge_restores_removed_defaults_without_duplicates() {
+        let defaults = [("left-pad", "^1.0.0"), ("lodash", "^4.17.0")];
+        let existing = "{\n  \"name\": \"app\",\n  \"dependencies\": {\n    \"lodash\": \"^4.0.0\",\n    \"express\": \"^4.18.0\"\n  }\n}\n";
+        let merged = merge_dependencies(DependencyFormat::PackageJson, Some(existing), &defaults);
+
+        let mut names = declared_dependencies(DependencyFormat::PackageJson, &merged);
+        names.sort();
+        assert_eq!(names, vec!["express", "left-pad", "lodash"]);
+        // The user's pinned lodash version wins over the default
+        assert!(merged.contains("\"lodash\": \"^4.0.0\""));
+    }
+
+    #[test]
+    fn test_merge_go_mod_and_csproj() {
+        let go_mod = "module app\n\ngo 1.21\n\nrequire (\n    example.com/mine v1.0.0\n)\n";
+        let merged = merge_dependencies(DependencyFormat::GoMod, Some(go_mod), &[("golang.org/x/tools", "v0.1.0")]);
+        assert_eq!(
+            declared_dependencies(DependencyFormat::GoMod, &merged),
+            vec!["example.com/mine", "golang.org/x/tools"]
+        );
+
+        let csproj = render_dependencies(DependencyFormat::CsProj, &[("Mine", "1.0.0")]);
+        let merged = merge_dependencies(DependencyFormat::CsProj, Some(&csproj), &[("Newtonsoft.Json", "13.0.1")]);
+        assert_eq!(
+            declared_dependencies(DependencyFormat::CsProj, &merged),
+            vec!["Mine", "Newtonsoft.Json"]
+        );
+    }
+}
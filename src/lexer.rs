@@ -4,6 +4,38 @@
 
 use std::iter::Peekable;
 use std::str::Chars;
+use unicode_normalization::UnicodeNormalization;
+use unicode_xid::UnicodeXID;
+
+/// Whether a character can start an identifier: Unicode XID_Start, or an underscore
+pub fn is_identifier_start(ch: char) -> bool {
+    ch == '_' || UnicodeXID::is_xid_start(ch)
+}
+
+/// Whether a character can continue an identifier: Unicode XID_Continue
+///
+/// This includes digits and combining marks, which may follow a start character but
+/// can't begin an identifier themselves.
+pub fn is_identifier_continue(ch: char) -> bool {
+    UnicodeXID::is_xid_continue(ch)
+}
+
+/// Renders bytes as the inside of a `b"..."` literal that lexes back to the same bytes
+pub fn escape_bytes(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len());
+    for &byte in bytes {
+        match byte {
+            b'\n' => out.push_str("\\n"),
+            b'\t' => out.push_str("\\t"),
+            b'\r' => out.push_str("\\r"),
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            0x20..=0x7e => out.push(byte as char),
+            _ => out.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+    out
+}
 
 /// Represents a single token in the Logos programming language
 #[derive(Debug, Clone, PartialEq)]
@@ -14,15 +46,16 @@ pub enum Token {
     Float(f64),          // Floating-point numbers (e.g., 3.14, -2.5)
     String(String),      // String literals (e.g., "hello", 'world')
     Char(char),          // Character literals (e.g., 'a', '\n')
+    ByteString(Vec<u8>), // Byte string literals (e.g., b"GET", b"\x00\xff")
     Boolean(bool),       // Boolean values (true, false)
 
     // Keyword tokens - reserved words with special meaning
     Fn, Let, Mut, Const, If, Elif, Else, While, For, In,
     Return, Match, Enum, Struct, Class, Trait, Impl, Pub, Type,
     True, False, Nil, Async, Await, Try, Catch, Finally,
-    Actor, Spawn, Send, Receive, Effect, Perform, With, Chan, Close,
+    Actor, Spawn, Send, Receive, Effect, Perform, With, Chan, Close, Select,
     Abstract, Private, Protected, Static, Implements,
-    Macro,
+    Macro, Defer, Extern,
 
     // Operator tokens - symbols that perform operations
     Plus, Minus, Multiply, Divide, Modulo, Power,           // Arithmetic operators
@@ -31,11 +64,13 @@ pub enum Token {
     PipeForward, PipeBackward, Spaceship,                   // Special operators
     Range,                                                  // Range operator: ..
     LeftArrow,                                              // Channel operator: <-
+    Question,                                               // Error propagation: ?
 
     // Delimiter tokens - symbols that separate or group code
     LeftParen, RightParen, LeftBrace, RightBrace,    // Parentheses and braces: ( ) { }
     LeftBracket, RightBracket,                        // Brackets: [ ]
     Comma, Dot, Colon,                               // Punctuation: , . :
+    DoubleColon,                                     // Path separator: ::
     Semicolon, Arrow, FatArrow, Underscore,          // Other delimiters: ; -> => _
     
     // Additional operator tokens
@@ -49,23 +84,31 @@ pub enum Token {
 
     // Special tokens
     At,        // @ symbol for multi-language calls
+    Hash,      // # symbol that starts an attribute: #[...]
     Import,    // import keyword
     Index,     // index keyword
     Break,     // break keyword
     Continue,  // continue keyword
+    Label(String), // Loop label: 'outer
     Eof,       // End of file marker
     LessThan,  // '<' character
     GreaterThan, // '>' character
+    Error(String), // Malformed input the lexer could not turn into a token
 }
 
 /// The Lexer struct processes source code and converts it into tokens
+#[derive(Clone)]
 pub struct Lexer<'a> {
     input: &'a str,              // The source code to tokenize
     chars: Peekable<Chars<'a>>,  // Iterator over characters with peek capability
     current_char: Option<char>,  // The current character being processed
-    position: usize,             // Current position in the input string
+    position: usize,             // Byte offset of the current character in the input string
     line: usize,                 // Current line number (for error reporting)
     column: usize,               // Current column number (for error reporting)
+    token_line: usize,           // Line where the most recently returned token starts
+    token_column: usize,         // Column where the most recently returned token starts
+    token_offset: usize,         // Byte offset where the most recently returned token starts
+    previous_end_line: usize,    // Line where the token before the most recently returned one ends
 }
 
 impl<'a> Lexer<'a> {
@@ -88,6 +131,10 @@ impl<'a> Lexer<'a> {
             position: 0,
             line: 1,
             column: 1,
+            token_line: 1,
+            token_column: 1,
+            token_offset: 0,
+            previous_end_line: 1,
         }
     }
 
@@ -100,9 +147,10 @@ impl<'a> Lexer<'a> {
 
         loop {
             let token = self.next_token();
-            tokens.push(token.clone());
+            let done = token == Token::Eof;
+            tokens.push(token);
 
-            if matches!(token, Token::Eof) {
+            if done {
                 break;
             }
         }
@@ -115,160 +163,202 @@ impl<'a> Lexer<'a> {
     /// # Returns
     /// The next token in the input stream
     pub fn next_token(&mut self) -> Token {
-        self.skip_whitespace();
+        // Nothing has been read since the previous token, so this is still where it ends
+        self.previous_end_line = self.line;
 
-        if let Some(ch) = self.current_char {
-            let token = match ch {
-                // Multi-character tokens: advance in match, main function advances again
-                '=' => {
-                    if self.peek_char() == Some('=') {
-                        self.advance();  // Move to second '='
-                        Token::Equal     // Main advance() will move past second '=', so both chars consumed
-                    } else if self.peek_char() == Some('>') {
-                        self.advance();  // Move to '>'
-                        Token::FatArrow  // Main advance() will move past '>', so both chars consumed
-                    } else {
-                        Token::Assign    // Main advance() will move past single '=', so one char consumed
-                    }
-                },
-                '!' => {
-                    if self.peek_char() == Some('=') {
-                        self.advance();  // Move to '='
-                        Token::NotEqual  // Main advance() will move past '=', so both chars consumed
-                    } else {
-                        Token::Not       // Main advance() will move past single '!', so one char consumed
-                    }
-                },
-                '<' => {
-                    if self.peek_char() == Some('=') {
-                        self.advance();  // Move to '='
-                        Token::LessEqual // Main advance() will move past '=', so both chars consumed
-                    } else if self.peek_char() == Some('>') {
-                        self.advance();  // Move to '>'
-                        Token::Spaceship // Main advance() will move past '>', so both chars consumed
-                    } else if self.peek_char() == Some('-') {
-                        self.advance();  // Move to '-'
-                        Token::LeftArrow // Main advance() will move past '-', so both chars consumed
-                    } else {
-                        Token::Less      // Main advance() will move past single '<', so one char consumed
-                    }
-                },
-                '>' => {
-                    if self.peek_char() == Some('=') {
-                        self.advance();  // Move to '='
-                        Token::GreaterEqual // Main advance() will move past '=', so both chars consumed
-                    } else {
-                        Token::Greater   // Main advance() will move past single '>', so one char consumed
-                    }
-                },
-                '&' => {
-                    if self.peek_char() == Some('&') {
-                        self.advance();  // Move to second '&'
-                        Token::And       // Main advance() will move past second '&', so both chars consumed
-                    } else {
-                        Token::Ampersand // Main advance() will move past single '&', so one char consumed
-                    }
-                },
-                '|' => {
-                    if self.peek_char() == Some('|') {
-                        self.advance();  // Move to second '|'
-                        Token::Or        // Main advance() will move past second '|', so both chars consumed
-                    } else if self.peek_char() == Some('>') {
-                        self.advance();  // Move to '>'
-                        Token::PipeForward // Main advance() will move past '>', so both chars consumed
-                    } else {
-                        Token::Pipe      // Main advance() will move past single '|', so one char consumed
-                    }
-                },
-                '+' => {
-                    if self.peek_char() == Some('=') {
-                        self.advance();  // Move to '='
-                        Token::PlusAssign // Main advance() will move past '=', so both chars consumed
-                    } else {
-                        Token::Plus      // Main advance() will move past single '+', so one char consumed
-                    }
-                },
-                '-' => {
-                    if self.peek_char() == Some('>') {
-                        self.advance();  // Move to '>'
-                        Token::Arrow     // Main advance() will move past '>', so both chars consumed
-                    } else if self.peek_char() == Some('=') {
-                        self.advance();  // Move to '='
-                        Token::MinusAssign // Main advance() will move past '=', so both chars consumed
-                    } else {
-                        Token::Minus     // Main advance() will move past single '-', so one char consumed
-                    }
-                },
-                // Single character tokens
-                '(' => Token::LeftParen,
-                ')' => Token::RightParen,
-                '{' => Token::LeftBrace,
-                '}' => Token::RightBrace,
-                '[' => Token::LeftBracket,
-                ']' => Token::RightBracket,
-                ',' => Token::Comma,
-                '.' => {
-                    if self.peek_char() == Some('.') {
-                        self.advance();  // Move to second '.'
-                        Token::Range     // Main advance() will move past second '.', so both chars consumed
-                    } else {
-                        Token::Dot       // Main advance() will move past single '.', so one char consumed
-                    }
-                },
-                ':' => Token::Colon,
-                ';' => Token::Semicolon,
-                '_' => Token::Underscore,
-                '*' => Token::Multiply,
-                '/' => {
-                    if self.peek_char() == Some('/') {
-                        // Handle single-line comment: skip until newline
-                        self.advance(); // consume the second '/'
-                        while let Some(ch) = self.current_char {
-                            if ch == '\n' {
-                                break; // stop at newline, don't consume it
+        // Comments and unknown characters are skipped by looping rather than recursing,
+        // so long runs of them can't exhaust the stack
+        loop {
+            self.skip_whitespace();
+            self.token_line = self.line;
+            self.token_column = self.column;
+            self.token_offset = self.position;
+
+            if let Some(ch) = self.current_char {
+                let token = match ch {
+                    // Multi-character tokens: advance in match, main function advances again
+                    '=' => {
+                        if self.peek_char() == Some('=') {
+                            self.advance();  // Move to second '='
+                            Token::Equal     // Main advance() will move past second '=', so both chars consumed
+                        } else if self.peek_char() == Some('>') {
+                            self.advance();  // Move to '>'
+                            Token::FatArrow  // Main advance() will move past '>', so both chars consumed
+                        } else {
+                            Token::Assign    // Main advance() will move past single '=', so one char consumed
+                        }
+                    },
+                    '!' => {
+                        if self.peek_char() == Some('=') {
+                            self.advance();  // Move to '='
+                            Token::NotEqual  // Main advance() will move past '=', so both chars consumed
+                        } else {
+                            Token::Not       // Main advance() will move past single '!', so one char consumed
+                        }
+                    },
+                    '<' => {
+                        if self.peek_char() == Some('=') {
+                            self.advance();  // Move to '='
+                            Token::LessEqual // Main advance() will move past '=', so both chars consumed
+                        } else if self.peek_char() == Some('>') {
+                            self.advance();  // Move to '>'
+                            Token::Spaceship // Main advance() will move past '>', so both chars consumed
+                        } else if self.peek_char() == Some('-') {
+                            self.advance();  // Move to '-'
+                            Token::LeftArrow // Main advance() will move past '-', so both chars consumed
+                        } else {
+                            Token::Less      // Main advance() will move past single '<', so one char consumed
+                        }
+                    },
+                    '>' => {
+                        if self.peek_char() == Some('=') {
+                            self.advance();  // Move to '='
+                            Token::GreaterEqual // Main advance() will move past '=', so both chars consumed
+                        } else {
+                            Token::Greater   // Main advance() will move past single '>', so one char consumed
+                        }
+                    },
+                    '&' => {
+                        if self.peek_char() == Some('&') {
+                            self.advance();  // Move to second '&'
+                            Token::And       // Main advance() will move past second '&', so both chars consumed
+                        } else {
+                            Token::Ampersand // Main advance() will move past single '&', so one char consumed
+                        }
+                    },
+                    '|' => {
+                        if self.peek_char() == Some('|') {
+                            self.advance();  // Move to second '|'
+                            Token::Or        // Main advance() will move past second '|', so both chars consumed
+                        } else if self.peek_char() == Some('>') {
+                            self.advance();  // Move to '>'
+                            Token::PipeForward // Main advance() will move past '>', so both chars consumed
+                        } else {
+                            Token::Pipe      // Main advance() will move past single '|', so one char consumed
+                        }
+                    },
+                    '+' => {
+                        if self.peek_char() == Some('=') {
+                            self.advance();  // Move to '='
+                            Token::PlusAssign // Main advance() will move past '=', so both chars consumed
+                        } else {
+                            Token::Plus      // Main advance() will move past single '+', so one char consumed
+                        }
+                    },
+                    '-' => {
+                        if self.peek_char() == Some('>') {
+                            self.advance();  // Move to '>'
+                            Token::Arrow     // Main advance() will move past '>', so both chars consumed
+                        } else if self.peek_char() == Some('=') {
+                            self.advance();  // Move to '='
+                            Token::MinusAssign // Main advance() will move past '=', so both chars consumed
+                        } else {
+                            Token::Minus     // Main advance() will move past single '-', so one char consumed
+                        }
+                    },
+                    // Single character tokens
+                    '(' => Token::LeftParen,
+                    ')' => Token::RightParen,
+                    '{' => Token::LeftBrace,
+                    '}' => Token::RightBrace,
+                    '[' => Token::LeftBracket,
+                    ']' => Token::RightBracket,
+                    ',' => Token::Comma,
+                    '.' => {
+                        if self.peek_char() == Some('.') {
+                            self.advance();  // Move to second '.'
+                            Token::Range     // Main advance() will move past second '.', so both chars consumed
+                        } else {
+                            Token::Dot       // Main advance() will move past single '.', so one char consumed
+                        }
+                    },
+                    ':' => {
+                        if self.peek_char() == Some(':') {
+                            self.advance();      // Move to second ':'
+                            Token::DoubleColon   // Main advance() will move past second ':'
+                        } else {
+                            Token::Colon
+                        }
+                    },
+                    ';' => Token::Semicolon,
+                    '?' => Token::Question,
+                    '#' => Token::Hash,
+                    '_' => Token::Underscore,
+                    '*' => Token::Multiply,
+                    '/' => {
+                        if self.peek_char() == Some('/') {
+                            // Handle single-line comment: skip until newline
+                            self.advance(); // consume the second '/'
+                            while let Some(ch) = self.current_char {
+                                if ch == '\n' {
+                                    break; // stop at newline, don't consume it
+                                }
+                                self.advance();
                             }
-                            self.advance();
+                            // After skipping comment, get the next token
+                            continue;
+                        } else {
+                            Token::Divide
                         }
-                        // After skipping comment, get the next token
-                        return self.next_token();
-                    } else {
-                        Token::Divide
-                    }
-                },
-                '%' => Token::Modulo,
-                '^' => Token::Power,
+                    },
+                    '%' => Token::Modulo,
+                    '^' => Token::Power,
 
-                // Tokens that return early (they handle their own advancement)
-                '"' => return self.read_string(),
-                '\'' => return self.read_char(),
-
-                '@' => {
-                    self.advance();
-                    return self.read_multilang_call();
-                },
+                    // Tokens that return early (they handle their own advancement)
+                    'b' if self.peek_char() == Some('"') => return self.read_byte_string(),
+                    '"' => return self.read_string(),
+                    '\'' => return self.read_char(),
 
-                // Other characters
-                _ => {
-                    if ch.is_ascii_digit() {
-                        return self.read_number();
-                    } else if ch.is_alphabetic() || ch == '_' {
-                        return self.read_identifier_or_keyword();
-                    } else {
-                        // Skip unknown character
+                    '@' => {
                         self.advance();
-                        return self.next_token();
+                        return self.read_multilang_call();
+                    },
+
+                    // Other characters
+                    _ => {
+                        if ch.is_ascii_digit() {
+                            return self.read_number();
+                        } else if is_identifier_start(ch) {
+                            return self.read_identifier_or_keyword();
+                        } else if is_identifier_continue(ch) {
+                            // A combining mark or similar with nothing to attach to
+                            let rest = self.read_identifier_chars();
+                            return Token::Error(format!(
+                                "Invalid identifier '{}': identifiers cannot start with U+{:04X}",
+                                rest, ch as u32
+                            ));
+                        } else {
+                            // Skip unknown character
+                            self.advance();
+                            continue;
+                        }
                     }
-                }
-            };
+                };
 
-            // Advance past the current token (for tokens that didn't return early)
-            self.advance();
-            token
-        } else {
-            Token::Eof
+                // Advance past the current token (for tokens that didn't return early)
+                self.advance();
+                return token;
+            } else {
+                return Token::Eof;
+            }
         }
     }
 
+    /// Returns the line and column where the most recently returned token starts
+    pub fn token_position(&self) -> (usize, usize) {
+        (self.token_line, self.token_column)
+    }
+
+    /// Returns the bytes of the input the most recently returned token was read from
+    pub fn token_span(&self) -> std::ops::Range<usize> {
+        self.token_offset..self.position
+    }
+
+    /// Returns the line where the token before the most recently returned one ends
+    pub fn previous_end_line(&self) -> usize {
+        self.previous_end_line
+    }
+
     /// Advances the lexer to the next character in the input stream
     /// Updates position, line, and column counters accordingly
     fn advance(&mut self) {
@@ -279,9 +369,9 @@ impl<'a> Lexer<'a> {
             } else {
                 self.column += 1;
             }
+            self.position += ch.len_utf8();
         }
 
-        self.position += 1;
         // Get the next character from the stream
         self.current_char = self.chars.next();
     }
@@ -310,11 +400,10 @@ impl<'a> Lexer<'a> {
     /// # Returns
     /// A Token::Integer or Token::Float containing the parsed number
     fn read_number(&mut self) -> Token {
-        let mut number_str = String::new();
+        let start = self.position;
 
         while let Some(ch) = self.current_char {
             if ch.is_ascii_digit() {
-                number_str.push(ch);
                 self.advance();
             } else if ch == '.' {
                 // Check if the next character after this dot is also a dot (range operator "..")
@@ -324,7 +413,6 @@ impl<'a> Lexer<'a> {
                     break;
                 } else {
                     // This is a decimal point in a float, add it and continue
-                    number_str.push(ch);
                     self.advance();
                 }
             } else {
@@ -332,6 +420,14 @@ impl<'a> Lexer<'a> {
             }
         }
 
+        let mut number_str = &self.input[start..self.position];
+
+        // A number running straight into letters is an identifier that starts with a digit
+        if self.current_char.map_or(false, is_identifier_start) {
+            let identifier = format!("{}{}", number_str, self.read_identifier_chars());
+            return Token::Error(format!("Invalid identifier '{}': identifiers cannot start with a digit", identifier));
+        }
+
         // At this point, we have collected the number string
         // If it contains a '.', it should be parsed as a float
         // But we need to be careful about cases like "3.14." which would be invalid
@@ -339,7 +435,7 @@ impl<'a> Lexer<'a> {
         if number_str.ends_with('.') && number_str.len() > 1 {
             // Remove the trailing dot - this means we had a number like "3." which should be "3" (integer)
             // and the dot should be parsed separately
-            number_str.pop();
+            number_str = &number_str[..number_str.len() - 1];
             if number_str.is_empty() {
                 // This shouldn't happen if numbers start with digits
                 return Token::Dot;
@@ -350,13 +446,13 @@ impl<'a> Lexer<'a> {
             if let Ok(value) = number_str.parse::<f64>() {
                 Token::Float(value)
             } else {
-                panic!("Invalid float literal: {}", number_str);
+                Token::Error(format!("Invalid float literal '{}'", number_str))
             }
         } else {
             if let Ok(value) = number_str.parse::<i64>() {
                 Token::Integer(value)
             } else {
-                panic!("Invalid integer literal: {}", number_str);
+                Token::Error(format!("Integer literal '{}' out of range", number_str))
             }
         }
     }
@@ -367,17 +463,25 @@ impl<'a> Lexer<'a> {
     /// # Returns
     /// A Token::String containing the parsed string content
     fn read_string(&mut self) -> Token {
-        let quote = self.current_char.unwrap(); // Store the opening quote
+        let quote = match self.current_char {
+            Some(ch) => ch, // Store the opening quote
+            None => return Token::Eof,
+        };
         self.advance(); // Skip opening quote
 
+        // Text between escapes is copied a run at a time, so a string without
+        // escapes is a single copy out of the input
         let mut string_content = String::new();
+        let mut run_start = self.position;
 
         while let Some(ch) = self.current_char {
             if ch == quote {
+                string_content.push_str(&self.input[run_start..self.position]);
                 self.advance(); // Skip closing quote
-                break;
+                return Token::String(string_content);
             } else if ch == '\\' {
                 // Handle escape sequences
+                string_content.push_str(&self.input[run_start..self.position]);
                 self.advance(); // Skip backslash
                 if let Some(escaped_char) = self.current_char {
                     match escaped_char {
@@ -391,23 +495,83 @@ impl<'a> Lexer<'a> {
                     }
                     self.advance();
                 }
+                run_start = self.position;
             } else {
-                string_content.push(ch);
                 self.advance();
             }
         }
 
+        // Unterminated: everything up to the end of the input
+        string_content.push_str(&self.input[run_start..self.position]);
         Token::String(string_content)
     }
 
+    /// Reads a byte string token such as `b"\x89PNG"` from the input stream
+    ///
+    /// Byte strings hold raw bytes rather than text, so only ASCII characters may
+    /// appear literally; other bytes are written as `\xNN` escapes.
+    fn read_byte_string(&mut self) -> Token {
+        self.advance(); // Skip b
+        self.advance(); // Skip opening quote
+
+        let mut bytes = Vec::new();
+        while let Some(ch) = self.current_char {
+            self.advance();
+            match ch {
+                '"' => return Token::ByteString(bytes),
+                '\\' => {
+                    let escaped = match self.current_char {
+                        Some(escaped) => escaped,
+                        None => break,
+                    };
+                    self.advance();
+                    match escaped {
+                        'n' => bytes.push(b'\n'),
+                        't' => bytes.push(b'\t'),
+                        'r' => bytes.push(b'\r'),
+                        '0' => bytes.push(0),
+                        'x' => {
+                            let digits: String = (0..2).filter_map(|_| {
+                                let digit = self.current_char.filter(char::is_ascii_hexdigit)?;
+                                self.advance();
+                                Some(digit)
+                            }).collect();
+                            match u8::from_str_radix(&digits, 16) {
+                                Ok(byte) if digits.len() == 2 => bytes.push(byte),
+                                _ => return Token::Error(format!(
+                                    "Invalid escape '\\x{}' in byte string: expected two hex digits", digits
+                                )),
+                            }
+                        },
+                        other if other.is_ascii() => bytes.push(other as u8),
+                        other => return Token::Error(format!("Non-ASCII character '{}' in byte string", other)),
+                    }
+                },
+                ch if ch.is_ascii() => bytes.push(ch as u8),
+                ch => return Token::Error(format!(
+                    "Non-ASCII character '{}' in byte string; write it as \\x escapes", ch
+                )),
+            }
+        }
+
+        Token::Error("Unterminated byte string".to_string())
+    }
+
     /// Reads a character token from the input stream
-    /// Handles escape sequences within character literals
+    /// Handles escape sequences within character literals, and loop labels like 'outer
     ///
     /// # Returns
-    /// A Token::Char containing the parsed character content
+    /// A Token::Char containing the parsed character content, or a Token::Label
     fn read_char(&mut self) -> Token {
         self.advance(); // Skip opening quote
 
+        // A quote before a name that isn't closed right after its first character is a loop label
+        if let Some(ch) = self.current_char {
+            if is_identifier_start(ch) && self.peek_char() != Some('\'') {
+                return Token::Label(self.read_identifier_chars());
+            }
+        }
+
         let mut char_content = String::new();
 
         while let Some(ch) = self.current_char {
@@ -450,18 +614,15 @@ impl<'a> Lexer<'a> {
     /// # Returns
     /// A Token::Identifier if it's a user-defined name, or the appropriate keyword token
     fn read_identifier_or_keyword(&mut self) -> Token {
-        let mut identifier = String::new();
+        let identifier = self.identifier_slice();
 
-        while let Some(ch) = self.current_char {
-            if ch.is_alphanumeric() || ch == '_' {
-                identifier.push(ch);
-                self.advance();
-            } else {
-                break;
-            }
+        // Normalize to NFC so that precomposed and combining-mark spellings of the same
+        // name are the same identifier
+        if !identifier.is_ascii() {
+            return Token::Identifier(identifier.nfc().collect());
         }
 
-        match identifier.as_str() {
+        match identifier {
             "fn" => Token::Fn,
             "let" => Token::Let,
             "mut" => Token::Mut,
@@ -498,6 +659,8 @@ impl<'a> Lexer<'a> {
             "with" => Token::With,
             "chan" => Token::Chan,
             "close" => Token::Close,
+            "select" => Token::Select,
+            "defer" => Token::Defer,
             "break" => Token::Break,
             "continue" => Token::Continue,
             "import" => Token::Import,
@@ -508,10 +671,25 @@ impl<'a> Lexer<'a> {
             "static" => Token::Static,
             "implements" => Token::Implements,
             "macro" => Token::Macro,
-            _ => Token::Identifier(identifier),
+            "extern" => Token::Extern,
+            _ => Token::Identifier(identifier.to_string()),
         }
     }
 
+    /// Consumes a run of identifier characters (XID_Continue) and returns it
+    fn read_identifier_chars(&mut self) -> String {
+        self.identifier_slice().to_string()
+    }
+
+    /// Consumes a run of identifier characters, borrowing them from the input
+    fn identifier_slice(&mut self) -> &'a str {
+        let start = self.position;
+        while matches!(self.current_char, Some(ch) if is_identifier_continue(ch)) {
+            self.advance();
+        }
+        &self.input[start..self.position]
+    }
+
     /// Reads a multi-language call token (indicated by @ symbol)
     /// 
     /// # Returns
@@ -524,6 +702,37 @@ impl<'a> Lexer<'a> {
     }
 }
 
+/// Every reserved word `read_identifier_or_keyword` turns into its own token
+pub const KEYWORDS: &[&str] = &[
+    "fn", "let", "mut", "const", "if", "elif", "else", "while", "for", "in", "return", "match",
+    "enum", "struct", "class", "trait", "type", "impl", "pub", "true", "false", "nil", "async",
+    "await", "try", "catch", "finally", "actor", "spawn", "send", "receive", "effect", "perform",
+    "with", "chan", "close", "select", "defer", "break", "continue", "import", "index", "abstract",
+    "private", "protected", "static", "implements", "macro", "extern",
+];
+
+/// Whether an item's source starts with the `pub` keyword once any `#[...]` attributes are skipped
+pub fn declares_public(item_source: &str) -> bool {
+    let mut lexer = Lexer::new(item_source);
+    loop {
+        match lexer.next_token() {
+            Token::Hash => {
+                let mut depth = 0;
+                loop {
+                    match lexer.next_token() {
+                        Token::LeftBracket => depth += 1,
+                        Token::RightBracket if depth <= 1 => break,
+                        Token::RightBracket => depth -= 1,
+                        Token::Eof => return false,
+                        _ => {},
+                    }
+                }
+            },
+            token => return token == Token::Pub,
+        }
+    }
+}
+
 /// Public function to tokenize input source code
 /// 
 /// # Arguments
@@ -542,6 +751,14 @@ pub fn tokenize(input: &str) -> Result<Vec<Token>, Box<dyn std::error::Error>> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_keyword_list_matches_the_lexer() {
+        for keyword in KEYWORDS {
+            let token = Lexer::new(keyword).next_token();
+            assert!(!matches!(token, Token::Identifier(_)), "{} lexes as an identifier", keyword);
+        }
+    }
+
     #[test]
     fn test_single_char_tokens() {
         let input = "=+-*/%(){}[];,.!";
@@ -588,6 +805,34 @@ mod tests {
         assert_eq!(lexer.next_token(), Token::Eof);
     }
 
+    #[test]
+    fn test_unicode_identifiers() {
+        let mut lexer = Lexer::new("let π = 3\nlet 名前 = π");
+        assert_eq!(lexer.next_token(), Token::Let);
+        assert_eq!(lexer.next_token(), Token::Identifier("π".to_string()));
+        assert_eq!(lexer.next_token(), Token::Assign);
+        assert_eq!(lexer.next_token(), Token::Integer(3));
+        assert_eq!(lexer.next_token(), Token::Let);
+        assert_eq!(lexer.next_token(), Token::Identifier("名前".to_string()));
+        assert_eq!(lexer.next_token(), Token::Assign);
+        assert_eq!(lexer.next_token(), Token::Identifier("π".to_string()));
+
+        // "e" + combining acute accent and the precomposed "é" are the same name
+        let mut decomposed = Lexer::new("cafe\u{301}");
+        let mut precomposed = Lexer::new("caf\u{e9}");
+        assert_eq!(decomposed.next_token(), precomposed.next_token());
+    }
+
+    #[test]
+    fn test_invalid_identifier_starts_are_rejected() {
+        let mut lexer = Lexer::new("\u{301}abc");
+        assert!(matches!(lexer.next_token(), Token::Error(message) if message.contains("cannot start with U+0301")));
+
+        let mut lexer = Lexer::new("1abc");
+        assert!(matches!(lexer.next_token(), Token::Error(message) if message.contains("'1abc'")));
+        assert_eq!(lexer.next_token(), Token::Eof);
+    }
+
     #[test]
     fn test_numbers() {
         let input = "42 3.14 0";
@@ -609,6 +854,21 @@ mod tests {
         assert_eq!(lexer.next_token(), Token::Eof);
     }
 
+    #[test]
+    fn test_byte_strings() {
+        let input = r#"b"GET \x00\xFF\n" b bytes"#;
+        let mut lexer = Lexer::new(input);
+
+        assert_eq!(lexer.next_token(), Token::ByteString(b"GET \x00\xff\n".to_vec()));
+        // A plain `b` is still an identifier
+        assert_eq!(lexer.next_token(), Token::Identifier("b".to_string()));
+        assert_eq!(lexer.next_token(), Token::Identifier("bytes".to_string()));
+
+        assert!(matches!(Lexer::new(r#"b"\xZZ""#).next_token(), Token::Error(e) if e.contains("expected two hex digits")));
+        assert!(matches!(Lexer::new("b\"caf\u{e9}\"").next_token(), Token::Error(e) if e.contains("Non-ASCII")));
+        assert_eq!(escape_bytes(b"GET \x00\xff\n\""), r#"GET \x00\xff\n\""#);
+    }
+
     #[test]
     fn test_multilang_calls() {
         let input = "@rust{let x = 42;}";
@@ -629,4 +889,44 @@ mod tests {
         // The rest of the tokens depend on how the embedded code is tokenized
         // This is what matters for the multilang functionality
     }
+
+    /// One function's worth of source, numbered `n`, touching every borrowed-token path
+    fn synthetic_unit(n: usize) -> String {
+        format!(
+            "// unit {n}\nfn step_{n}(x: Int, ratio: Float) -> String {{\n    let total_{n} = x * {n} + 3.25\n    \
+             for i in 0..{n} {{ print(\"plain {n}\") }}\n    let 名前 = \"tab\\there \\\"quoted\\\"\"\n    \
+             'outer: while ok {{ break 'outer }}\n    return 'c'\n}}\n",
+            n = n
+        )
+    }
+
+    #[test]
+    fn test_large_inputs_tokenize_like_their_parts() {
+        let units: Vec<String> = (0..500).map(synthetic_unit).collect();
+        let whole = Lexer::new(&units.concat()).tokenize();
+
+        let mut expected = Vec::new();
+        for unit in &units {
+            let mut tokens = Lexer::new(unit).tokenize();
+            assert_eq!(tokens.pop(), Some(Token::Eof));
+            expected.extend(tokens);
+        }
+        expected.push(Token::Eof);
+        assert_eq!(whole, expected);
+
+        let tokens = Lexer::new(&units[7]).tokenize();
+        for token in [
+            Token::Identifier("step_7".to_string()),
+            Token::Float(3.25),
+            Token::Range,
+            Token::String("plain 7".to_string()),
+            Token::Identifier("名前".to_string()),
+            Token::String("tab\there \"quoted\"".to_string()),
+            Token::Label("outer".to_string()),
+            Token::Char('c'),
+        ] {
+            assert!(tokens.contains(&token), "missing {:?} in {:?}", token, tokens);
+        }
+        assert!(!tokens.iter().any(|token| matches!(token, Token::Error(_))), "{:?}", tokens);
+    }
 }
\ No newline at end of file
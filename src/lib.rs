@@ -66,6 +66,50 @@ pub mod debug_info_gen;
 pub mod repl;
 /// Networking module providing HTTP, WebSocket, and other networking capabilities
 pub mod networking;
+/// Build artifact locations and cleanup
+pub mod artifacts;
+/// Project scaffolding rules and templates used by `logos init`
+pub mod project;
+/// Multi-language synchronization used by `logos sync`
+pub mod lang_sync;
+/// Cancellation tokens for long-running compiler operations
+pub mod cancellation;
+/// Item attributes: #[cfg] stripping, #[test] discovery and #[inline] hints
+pub mod attributes;
+/// Expansion of #[derive(Eq, Display, Clone)] into impl blocks
+pub mod derive;
+/// Long-form explanations of diagnostic codes, printed by `logos explain`
+pub mod explain;
+/// Delta-debugging minimization of failing `#[test]` programs
+pub mod minimize;
+/// Call-stack sampling profiler with folded-stack (flamegraph) output
+pub mod profiler;
+/// File watching with debouncing for `--watch`
+pub mod watch;
+/// Captured, JSON-serialisable results for `logos run --output=json`
+pub mod run_report;
+/// Width, fill, alignment and precision specifiers for `${expr:spec}` interpolation
+pub mod format_spec;
+/// Compile-time limits on macro expansion depth, type recursion and AST size
+pub mod limits;
+/// Line layout for `logos fmt`, and the edits between source and formatted output
+pub mod format_edits;
+/// AST-based source formatter behind `logos fmt`
+pub mod formatter;
+/// Warnings reported by `logos check --lint`, starting with indentation checks
+pub mod linter;
+/// Suggestions reported by `logos check --perf-hints` for code that does needless work
+pub mod perf_hints;
+/// The stages `logos build` runs, as printed by `logos build --plan`
+pub mod build_plan;
+/// Content hashes of build outputs and their inputs, for `logos build --print-build-hash`
+pub mod build_hash;
+/// Identifier interning: names become `Symbol`s the type checker hashes as integers
+pub mod interner;
+/// Reports public items without doc comments, for `logos doc --check`
+pub mod doc_check;
+/// Specializes generic functions into one copy per set of type arguments
+pub mod monomorphize;
 pub mod memory_management {
     pub mod ownership;
     pub mod safety_without_gc;
@@ -78,7 +122,7 @@ pub mod memory_management {
 use std::process::Command;
 
 /// Checks if the source code contains multi-language annotations like @python{...}, @go{...}, etc.
-fn contains_multilang_annotations(source: &str) -> bool {
+pub(crate) fn contains_multilang_annotations(source: &str) -> bool {
     // Look for the pattern @language{...} where language is a known language
     let multilang_patterns = [
         "@python{",
@@ -144,26 +188,68 @@ pub struct AnalysisResult {
 /// * `Ok(())` if execution was successful
 /// * `Err` with error details if execution failed
 pub fn execute(source: &str) -> Result<(), Box<dyn std::error::Error>> {
+    execute_with_limits(source, &limits::CompileLimits::default())
+}
+
+/// Executes Logos source code, aborting before it runs if macro expansion or the
+/// program's size passes `limits`
+pub fn execute_with_limits(source: &str, limits: &limits::CompileLimits) -> Result<(), Box<dyn std::error::Error>> {
+    execute_with_runtime(source, crate::runtime::Runtime::new(), limits)
+}
+
+/// Executes Logos source code while `profiler` samples its call stack
+///
+/// Once this returns, the profiler holds the samples; see `Profiler::write_folded`.
+pub fn execute_profiled(source: &str, profiler: profiler::SharedProfiler, limits: &limits::CompileLimits) -> Result<(), Box<dyn std::error::Error>> {
+    let mut runtime = crate::runtime::Runtime::new();
+    runtime.set_profiler(profiler);
+    execute_with_runtime(source, runtime, limits)
+}
+
+/// Executes Logos source code on a new thread with a stack of `stack_size` bytes
+///
+/// The runtime's call depth limit is scaled to the stack, so a recursion-heavy
+/// program can be given more room and still fails with a recursion error, not a
+/// stack overflow, when it runs out. `profiler` samples the call stack as in
+/// `execute_profiled`. The other `execute` functions run on the calling thread,
+/// which `runtime::capture_output` relies on.
+pub fn execute_with_stack_size(
+    source: &str,
+    limits: &limits::CompileLimits,
+    stack_size: usize,
+    profiler: Option<profiler::SharedProfiler>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    crate::runtime::with_stack_size(stack_size, || {
+        let mut runtime = crate::runtime::Runtime::new();
+        runtime.set_max_call_depth(crate::runtime::max_call_depth_for(stack_size));
+        if let Some(profiler) = profiler {
+            runtime.set_profiler(profiler);
+        }
+        // Errors aren't Send, so they cross back to this thread as text
+        execute_with_runtime(source, runtime, limits).map_err(|e| e.to_string())
+    })?;
+    Ok(())
+}
+
+fn execute_with_runtime(source: &str, mut runtime: crate::runtime::Runtime, limits: &limits::CompileLimits) -> Result<(), Box<dyn std::error::Error>> {
     // Check if the source contains multi-language annotations (@python{}, @go{}, etc.)
     let has_multilang_annotations = contains_multilang_annotations(source);
 
     if has_multilang_annotations {
-        // If multi-language annotations are present, use multi-language processing
-        println!("Multi-language annotations detected, using multi-language processing...");
+        // If multi-language annotations are present, use multi-language processing.
+        // Status goes to stderr so stdout carries only the program's own output.
+        eprintln!("Multi-language annotations detected, using multi-language processing...");
         let analysis = analyze_with_multilang(source, MultiLangSupport::Both)?;
-        println!("Execution analysis: {}", analysis.combined_result);
+        eprintln!("Execution analysis: {}", analysis.combined_result);
     } else {
         // For efficiency, skip multi-language analysis if no annotations are present
-        println!("No multi-language annotations detected, using Rust-only processing...");
+        eprintln!("No multi-language annotations detected, using Rust-only processing...");
     }
 
-    // Parse the source code into an AST
-    let mut parser = parser::Parser::new(source);
-    let ast = parser.parse_program()?;
+    let ast = prepare_program(source, limits)?;
 
     // Execute the AST using the runtime
-    let mut runtime = crate::runtime::Runtime::new();
-    runtime.eval_program(&ast)?;
+    let script_value = runtime.eval_program(&ast)?;
 
     // Import the Value from runtime to shadow the one from type_system
     use crate::runtime::Value;
@@ -176,15 +262,86 @@ pub fn execute(source: &str) -> Result<(), Box<dyn std::error::Error>> {
             // Create a new environment for the main function call
             let mut main_runtime = crate::runtime::Runtime::new();
             main_runtime.env = closure_env; // Use the closure environment
+            main_runtime.set_max_call_depth(runtime.max_call_depth());
+
+            // When profiling, main is the root frame of every sample taken inside it
+            let profiler = runtime.profiler();
+            if let Some(profiler) = &profiler {
+                main_runtime.set_profiler(profiler.clone());
+                profiler.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).enter("main");
+            }
 
             // Evaluate the main function body
-            for stmt in &body {
-                main_runtime.eval_statement(stmt)?;
+            let result = main_runtime.eval_body(&body);
+
+            if let Some(profiler) = &profiler {
+                profiler.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).exit();
             }
+            result?;
         }
+    } else if matches!(ast.statements.last(), Some(Statement::Expression(_))) && script_value != RuntimeValue::Unit {
+        // A script without main shows the value it ends with, as the REPL would
+        crate::runtime::print_value(&script_value);
     }
 
+    Ok(())
+}
 
+/// Parses `source` into the program `logos run` executes: `#[cfg]`-disabled items
+/// dropped, derives and macros expanded
+///
+/// Warnings about unknown attributes are printed to stderr.
+pub fn prepare_program(source: &str, limits: &limits::CompileLimits) -> Result<Program, Box<dyn std::error::Error>> {
+    let statements = parser::Parser::new(source).parse_program_with_lines()?;
+    let expanded = prepare_statements(statements, limits)?;
+    Ok(Program { statements: expanded.into_iter().map(|item| item.statement).collect() })
+}
+
+/// The steps of `prepare_program` after parsing, keeping each statement's line for diagnostics
+fn prepare_statements(
+    statements: Vec<(Statement, usize, usize)>,
+    limits: &limits::CompileLimits,
+) -> Result<Vec<macros::ExpandedStatement>, Box<dyn std::error::Error>> {
+    // Drop items configured out with #[cfg(...)] and report attributes we don't know
+    let cfg = attributes::CfgSet::new();
+    let mut kept = Vec::with_capacity(statements.len());
+    for (statement, start, end) in statements {
+        if let Some(statement) = attributes::strip_cfg_statement(statement, &cfg)? {
+            kept.push((statement, start, end));
+        }
+    }
+    for warning in attributes::check_attributes(kept.iter().map(|(statement, _, _)| statement)) {
+        eprintln!("{}", warning);
+    }
+
+    let kept = derive::expand_derives_located(kept)?;
+    Ok(macros::preprocess_macros_located(&kept, limits)?)
+}
+
+/// Runs bytecode cached by `logos build` on a new thread with a stack of `stack_size` bytes
+///
+/// Behaves like `execute_with_stack_size` on the source it was compiled from: a
+/// `main` function is called once the top level has run, and a script without one
+/// shows the value it ends with.
+pub fn execute_bytecode_with_stack_size(
+    bytecode: &codegen::Bytecode,
+    stack_size: usize,
+    profiler: Option<profiler::SharedProfiler>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    crate::runtime::with_stack_size(stack_size, || {
+        let mut runtime = crate::runtime::Runtime::new();
+        runtime.set_max_call_depth(crate::runtime::max_call_depth_for(stack_size));
+        if let Some(profiler) = profiler {
+            runtime.set_profiler(profiler);
+        }
+        let script_value = runtime.eval_bytecode(bytecode)?;
+        if runtime.has_bytecode_function("main") {
+            runtime.call_by_name("main", Vec::new())?;
+        } else if script_value != crate::runtime::Value::Unit {
+            crate::runtime::print_value(&script_value);
+        }
+        Ok::<(), String>(())
+    })?;
     Ok(())
 }
 
@@ -197,6 +354,16 @@ pub fn execute(source: &str) -> Result<(), Box<dyn std::error::Error>> {
 /// * `Ok(String)` containing the compiled code if successful
 /// * `Err` with error details if compilation failed
 pub fn compile(source: &str) -> Result<String, Box<dyn std::error::Error>> {
+    compile_cancellable(source, None)
+}
+
+/// Compiles Logos source code, stopping early if `token` is cancelled
+///
+/// The token is checked between phases (analysis, parsing, code generation,
+/// optimization); a cancelled compile returns a `cancellation::Cancelled` error.
+pub fn compile_cancellable(source: &str, token: Option<&cancellation::CancellationToken>) -> Result<String, Box<dyn std::error::Error>> {
+    cancellation::check(token)?;
+
     // Check if the source contains multi-language annotations (@python{}, @go{}, etc.)
     let has_multilang_annotations = contains_multilang_annotations(source);
 
@@ -212,14 +379,19 @@ pub fn compile(source: &str) -> Result<String, Box<dyn std::error::Error>> {
         MultiLangSupport::RustOnly
     };
 
-    let analysis = analyze_with_multilang(source, support_level.clone())?;
+    let analysis = analyze_with_multilang_cancellable(source, support_level.clone(), token)?;
     if has_multilang_annotations {
         println!("Compilation analysis: {}", analysis.combined_result);
     }
 
+    cancellation::check(token)?;
     let mut parser = parser::Parser::new(source);
     let ast = parser.parse_program()?;
+
+    cancellation::check(token)?;
     let code = generate_code_with_multilang(&ast, support_level.clone())?;
+
+    cancellation::check(token)?;
     let optimized_code = optimize_with_multilang(&code, support_level)?;
 
     Ok(optimized_code)
@@ -235,6 +407,13 @@ pub fn compile(source: &str) -> Result<String, Box<dyn std::error::Error>> {
 /// * `Ok(AnalysisResult)` containing the analysis results if successful
 /// * `Err` with error details if analysis failed
 pub fn analyze_with_multilang(source: &str, support: MultiLangSupport) -> Result<AnalysisResult, Box<dyn std::error::Error>> {
+    analyze_with_multilang_cancellable(source, support, None)
+}
+
+/// Performs multi-language analysis, checking `token` before each decoder call
+pub fn analyze_with_multilang_cancellable(source: &str, support: MultiLangSupport, token: Option<&cancellation::CancellationToken>) -> Result<AnalysisResult, Box<dyn std::error::Error>> {
+    cancellation::check(token)?;
+
     let mut go_result = None;
     let mut python_result = None;
     let mut combined_result = String::new();
@@ -251,6 +430,7 @@ pub fn analyze_with_multilang(source: &str, support: MultiLangSupport) -> Result
         },
         MultiLangSupport::Both => {
             go_result = Some(decoder::LogosDecoder::parse_with_go(source)?);
+            cancellation::check(token)?;
             python_result = Some(decoder::LogosDecoder::parse_with_python(source).map_err(|e| format!("Python error: {:?}", e))?);
 
             combined_result = format!(
@@ -259,7 +439,9 @@ pub fn analyze_with_multilang(source: &str, support: MultiLangSupport) -> Result
                 python_result.as_ref().unwrap()
             );
 
+            cancellation::check(token)?;
             let go_analysis = decoder::LogosDecoder::analyze_with_go(source)?;
+            cancellation::check(token)?;
             let python_analysis = decoder::LogosDecoder::analyze_with_python(source).map_err(|e| format!("Python error: {:?}", e))?;
 
             combined_result.push_str(&format!("\nGo Performance: {}\nPython Performance: {}", go_analysis, python_analysis));
@@ -330,7 +512,7 @@ pub fn optimize_with_multilang(source: &str, support: MultiLangSupport) -> Resul
 /// * `Ok(String)` containing the generated code if successful
 /// * `Err` with error details if code generation failed
 pub fn generate_code_with_multilang(ast: &Program, support: MultiLangSupport) -> Result<String, Box<dyn std::error::Error>> {
-    let basic_code = generate_code(&format!("{:?}", ast))?;
+    let basic_code = generate_code(&ast::unparse(ast))?;
 
     match support {
         MultiLangSupport::Go => {
@@ -419,19 +601,44 @@ pub fn generate_code(program: &str) -> Result<String, Box<dyn std::error::Error>
 /// * `Ok(())` if syntax and type checking was successful
 /// * `Err` with error details if checking failed
 pub fn check_syntax_and_types(source: &str) -> Result<(), Box<dyn std::error::Error>> {
+    check_syntax_and_types_with_limits(source, &limits::CompileLimits::default())
+}
+
+/// Checks syntax and types, expanding macros first, within the given compile-time limits
+pub fn check_syntax_and_types_with_limits(source: &str, limits: &limits::CompileLimits) -> Result<(), Box<dyn std::error::Error>> {
     // First perform lexical analysis
     let tokens = lexer::tokenize(source)?;
 
-    // Then parse the tokens
-    let mut parser = parser::Parser::new(source);
-    let program = parser.parse_program()?;
+    // Then parse the tokens; on failure, parse again collecting every syntax error
+    let statements = match parser::Parser::new(source).parse_program_with_lines() {
+        Ok(statements) => statements,
+        Err(first) => {
+            let (_, errors) = parser::Parser::new(source).parse_program_recovering();
+            if errors.is_empty() {
+                return Err(first.into());
+            }
+            let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            return Err(errors.join("\n").into());
+        },
+    };
+    // Prepared as for `logos run`; statements keep their lines through expansion, so a
+    // type error in expanded code can name the macro and the line it was invoked on
+    let statements = prepare_statements(statements, limits)?;
 
     // Finally, perform type checking
-    type_checker::check_types(&program)?;
+    type_checker::check_expanded_with_limits(&statements, limits)?;
 
     Ok(())
 }
 
+/// Formats Logos source code the way `logos fmt` does
+///
+/// This is the entry point for tools embedding Logos, such as editors; it needs no
+/// external interpreter. Fails if the source doesn't parse.
+pub fn format_source(source: &str, options: &formatter::FormatOptions) -> Result<String, String> {
+    formatter::format(source, options)
+}
+
 /// Transpiles Logos code to Python
 ///
 /// # Arguments
@@ -556,6 +763,11 @@ pub mod logos_lang {
     pub use crate::decoder;
     pub use crate::decoder::LogosDecoder;
     pub use crate::execute;
+    pub use crate::execute_with_limits;
+    pub use crate::execute_profiled;
+    pub use crate::execute_with_stack_size;
+    pub use crate::execute_bytecode_with_stack_size;
+    pub use crate::prepare_program;
     pub use crate::compile;
     pub use crate::analyze_with_multilang;
     pub use crate::optimize_with_multilang;
@@ -564,6 +776,8 @@ pub mod logos_lang {
     pub use crate::optimize;
     pub use crate::generate_code;
     pub use crate::check_syntax_and_types;
+    pub use crate::check_syntax_and_types_with_limits;
+    pub use crate::format_source;
     #[cfg(feature = "python")]
     pub use crate::transpile_to_python;
     #[cfg(feature = "python")]
@@ -851,6 +1065,11 @@ pub mod multi_lang_loader {
         /// * `Ok(())` if loading was successful
         /// * `Err` with error details if loading failed
         pub fn load_go_library(&mut self, name: &str, path: &str) -> Result<(), String> {
+            self.load_go_library_cancellable(name, path, None)
+        }
+
+        /// Loads a Go library, killing the `go build` subprocess if `token` is cancelled
+        pub fn load_go_library_cancellable(&mut self, name: &str, path: &str, token: Option<&crate::cancellation::CancellationToken>) -> Result<(), String> {
             // Check if Go is available
             if !crate::is_go_available() {
                 return Err("Go is not available in the system".to_string());
@@ -862,9 +1081,7 @@ pub mod multi_lang_loader {
             }
 
             // Attempt to build the Go library
-            let output = Command::new("go")
-                .args(&["build", path])
-                .output()
+            let output = crate::cancellation::run_command(Command::new("go").args(["build", path]), token)
                 .map_err(|e| format!("Failed to execute Go build: {}", e))?;
 
             if !output.status.success() {
@@ -0,0 +1,196 @@
+// Logos Compile-Time Limits
+// Bounds on the work the front end may do before a program runs. Macros can expand
+// into further invocations of themselves, and type aliases can name themselves, so
+// without a bound a buggy or hostile program would make `logos run` or `logos check`
+// loop or exhaust memory. Each limit aborts with a diagnostic naming it instead:
+//
+//   - macro expansion depth: invocations produced by expanding other invocations
+//   - type depth: levels a type alias may expand through
+//   - AST node count: size of the program once every macro has been expanded
+//
+// The defaults are far beyond what hand-written programs need; the CLI can raise or
+// lower them with --macro-depth, --type-depth and --max-ast-nodes.
+
+use crate::ast::*;
+
+/// Default number of nested macro expansions
+pub const DEFAULT_MACRO_DEPTH: usize = 64;
+
+/// Default number of levels a type alias may expand through
+pub const DEFAULT_TYPE_DEPTH: usize = 128;
+
+/// Default number of AST nodes a program may have after macro expansion
+pub const DEFAULT_MAX_AST_NODES: usize = 1_000_000;
+
+/// Limits on compile-time work, checked during macro expansion and type checking
+///
+/// `type_depth` bounds alias expansion only. How deeply expressions nest is the
+/// parser's concern, so a long `a + b + ...` chain or else-if chain is never
+/// rejected for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompileLimits {
+    pub macro_depth: usize,
+    pub type_depth: usize,
+    pub max_ast_nodes: usize,
+}
+
+impl Default for CompileLimits {
+    fn default() -> Self {
+        CompileLimits {
+            macro_depth: DEFAULT_MACRO_DEPTH,
+            type_depth: DEFAULT_TYPE_DEPTH,
+            max_ast_nodes: DEFAULT_MAX_AST_NODES,
+        }
+    }
+}
+
+impl CompileLimits {
+    /// Fails if `nodes` exceeds the AST node limit
+    pub fn check_node_count(&self, nodes: usize) -> Result<(), String> {
+        if nodes > self.max_ast_nodes {
+            return Err(format!(
+                "AST node limit reached: the program has more than {} nodes after macro expansion",
+                self.max_ast_nodes
+            ));
+        }
+        Ok(())
+    }
+
+    /// Fails if the program has more nodes than the AST node limit
+    ///
+    /// Counting stops as soon as the limit is passed, so this is cheap on runaway input.
+    pub fn check_program(&self, program: &Program) -> Result<(), String> {
+        self.check_statements(&program.statements)
+    }
+
+    /// Checks a program given as its top-level statements, as `check_program` does
+    pub fn check_statements<'a>(&self, statements: impl IntoIterator<Item = &'a Statement>) -> Result<(), String> {
+        let nodes = count_nodes(statements.into_iter().map(Node::Statement).collect(), self.max_ast_nodes);
+        self.check_node_count(nodes)
+    }
+}
+
+/// Counts the nodes of an expression, as `check_program` would
+pub fn expression_nodes(expr: &Expression) -> usize {
+    count_nodes(vec![Node::Expression(expr)], usize::MAX)
+}
+
+/// A node still to be counted
+enum Node<'a> {
+    Statement(&'a Statement),
+    Expression(&'a Expression),
+}
+
+/// Counts the nodes reachable from `pending`, stopping once there are more than `limit`
+///
+/// The tree is walked with an explicit stack rather than recursion: a long chain like
+/// `1 + 1 + ... + 1` nests one level per operator, and must not overflow the stack.
+fn count_nodes(mut pending: Vec<Node<'_>>, limit: usize) -> usize {
+    let mut nodes = 0;
+
+    while let Some(node) = pending.pop() {
+        nodes += 1;
+        if nodes > limit {
+            break;
+        }
+        match node {
+            Node::Statement(statement) => push_statement_children(statement, &mut pending),
+            Node::Expression(expr) => push_expression_children(expr, &mut pending),
+        }
+    }
+
+    nodes
+}
+
+fn push_statements<'a>(statements: &'a [Statement], pending: &mut Vec<Node<'a>>) {
+    pending.extend(statements.iter().map(Node::Statement));
+}
+
+fn push_statement_children<'a>(statement: &'a Statement, pending: &mut Vec<Node<'a>>) {
+    match statement {
+        Statement::Expression(expr) | Statement::Return(Some(expr)) => pending.push(Node::Expression(expr)),
+        Statement::LetBinding { value, .. } | Statement::ConstBinding { value, .. } => pending.push(Node::Expression(value)),
+        Statement::Function(func_def) => push_statements(&func_def.body, pending),
+        Statement::While { condition, body, .. } => {
+            pending.push(Node::Expression(condition));
+            push_statements(body, pending);
+        },
+        Statement::Defer(inner) => pending.push(Node::Statement(inner)),
+        Statement::Assert { actual, expected, message, .. } => {
+            pending.push(Node::Expression(actual));
+            pending.extend(expected.iter().chain(message).map(Node::Expression));
+        },
+        Statement::Block(statements) => push_statements(statements, pending),
+        Statement::Implementation(impl_def) => {
+            impl_def.methods.iter().for_each(|method| push_statements(&method.body, pending));
+        },
+        _ => {},
+    }
+}
+
+fn push_expression_children<'a>(expr: &'a Expression, pending: &mut Vec<Node<'a>>) {
+    match expr {
+        Expression::BinaryOp(left, _, right, _) | Expression::ArrayRepeat(left, right) | Expression::Index(left, right) => {
+            pending.push(Node::Expression(left));
+            pending.push(Node::Expression(right));
+        },
+        Expression::Slice(target, start, end) => {
+            pending.extend([target, start, end].map(|expr| Node::Expression(expr)));
+        },
+        Expression::UnaryOp(_, operand) | Expression::FieldAccess(operand, _) | Expression::Propagate(operand) => {
+            pending.push(Node::Expression(operand));
+        },
+        Expression::Call(_, args) | Expression::MacroInvocation(_, args)
+            | Expression::Array(args) | Expression::Tuple(args) => {
+            pending.extend(args.iter().map(Node::Expression));
+        },
+        Expression::MethodCall(receiver, _, args) => {
+            pending.push(Node::Expression(receiver));
+            pending.extend(args.iter().map(Node::Expression));
+        },
+        Expression::Struct(_, fields) => pending.extend(fields.iter().map(|(_, value)| Node::Expression(value))),
+        Expression::If(condition, then_branch, else_branch) => {
+            pending.push(Node::Expression(condition));
+            push_statements(then_branch, pending);
+            push_statements(else_branch, pending);
+        },
+        Expression::Block(statements) | Expression::BlockExpr(statements) | Expression::Lambda(_, statements) => {
+            push_statements(statements, pending);
+        },
+        Expression::TryCatch(body, _, handler) => {
+            push_statements(body, pending);
+            push_statements(handler, pending);
+        },
+        Expression::InterpolatedString(parts) => pending.extend(parts.iter().filter_map(|part| match part {
+            StringPart::Interpolated(expr, _) => Some(Node::Expression(expr)),
+            _ => None,
+        })),
+        _ => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_program_size_limits() {
+        let program = Parser::new("let x = (1 + 2) * 3\nprint(x)").parse_program().unwrap();
+        assert!(CompileLimits::default().check_program(&program).is_ok());
+
+        let tight = CompileLimits { max_ast_nodes: 5, ..CompileLimits::default() };
+        let err = tight.check_program(&program).unwrap_err();
+        assert_eq!(err, "AST node limit reached: the program has more than 5 nodes after macro expansion");
+
+        // The type depth doesn't limit how deeply expressions nest
+        let chain = format!("let x = 1{}", " + 1".repeat(140));
+        let program = Parser::new(&chain).parse_program().unwrap();
+        let shallow = CompileLimits { type_depth: 3, ..CompileLimits::default() };
+        assert!(shallow.check_program(&program).is_ok());
+        match &program.statements[..] {
+            [Statement::LetBinding { value, .. }] => assert_eq!(expression_nodes(value), 281),
+            other => panic!("expected one let binding, got {:?}", other),
+        }
+    }
+}
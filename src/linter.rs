@@ -0,0 +1,586 @@
+// Logos Linter
+// Warnings `logos check --lint` reports about code that is valid but likely to cause
+// trouble. Unlike errors they don't stop a program from running; each has a stable
+// code so it can be looked up or filtered.
+//
+// Indentation is checked line by line, on the source text rather than the AST:
+//
+//   - W0001: a line indented with both tabs and spaces, which looks different in
+//     every editor with a different tab width
+//   - W0002: a line indented with spaces whose count isn't a multiple of the indent
+//     size, usually a stray space that hides the real nesting
+//
+// Lines continuing a multi-line string are skipped, since their leading whitespace
+// is part of the string.
+//
+// The remaining lints walk the AST, tracking `let` bindings scope by scope:
+//
+//   - W0003: a `let` inside a function or block whose value is never read
+//   - W0004: a `let` reusing the name of a binding that is still in scope
+//   - W0005: statements after a `return`, `break` or `continue` in the same block
+//   - W0006: an `if` whose branch has no statements
+//
+// The AST carries no positions, so these are reported at the first line of the
+// top-level item they're in. Each lint has a severity, and the warning level picks
+// which severities are reported: high reports everything, low only the lints that
+// almost always point at a bug.
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::ast::{ChannelOperation, Expression, FunctionDef, Parameter, Pattern, Program, SelectArm, Statement, StringPart};
+use crate::format_edits::scan_code;
+use crate::parser::Parser;
+
+/// Indentation mixing tabs and spaces
+pub const MIXED_INDENTATION: &str = "W0001";
+
+/// Space indentation that isn't a whole number of levels
+pub const MISALIGNED_INDENTATION: &str = "W0002";
+
+/// A `let` binding that is never read
+pub const UNUSED_VARIABLE: &str = "W0003";
+
+/// A `let` binding hiding another binding of the same name
+pub const SHADOWED_VARIABLE: &str = "W0004";
+
+/// Statements that can never run
+pub const UNREACHABLE_CODE: &str = "W0005";
+
+/// An `if` branch with nothing in it
+pub const EMPTY_IF_BRANCH: &str = "W0006";
+
+/// How serious a lint is, and how strictly `logos check --lint` reports them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WarningLevel {
+    Low,
+    Medium,
+    High,
+}
+
+impl WarningLevel {
+    /// Parses a level name as given on the command line
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "low" => Ok(WarningLevel::Low),
+            "medium" => Ok(WarningLevel::Medium),
+            "high" => Ok(WarningLevel::High),
+            _ => Err(format!("Unknown warning level '{}': expected low, medium or high", name)),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WarningLevel::Low => "low",
+            WarningLevel::Medium => "medium",
+            WarningLevel::High => "high",
+        }
+    }
+
+    /// Whether a lint of the given severity is reported at this level
+    pub fn reports(self, severity: WarningLevel) -> bool {
+        match self {
+            WarningLevel::High => true,
+            WarningLevel::Medium => severity >= WarningLevel::Medium,
+            WarningLevel::Low => severity == WarningLevel::High,
+        }
+    }
+}
+
+/// A problem found by the linter
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LintWarning {
+    pub code: &'static str,
+    pub severity: WarningLevel,
+    pub line: usize,    // 1-based, or 0 when the position isn't known
+    pub column: usize,  // 1-based column of the first character the warning is about
+    pub message: String,
+}
+
+impl fmt::Display for LintWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            return write!(f, "warning[{}]: {}", self.code, self.message);
+        }
+        write!(f, "{}:{}: warning[{}]: {}", self.line, self.column, self.code, self.message)
+    }
+}
+
+/// The JSON object `logos check --lint --format=json` prints for one file
+#[derive(Serialize)]
+struct LintReport<'a> {
+    file: &'a str,
+    warning_count: usize,
+    warnings: &'a [LintWarning],
+}
+
+/// Renders the warnings for one file as a JSON object
+pub fn to_json(file: &str, warnings: &[LintWarning]) -> String {
+    let report = LintReport { file, warning_count: warnings.len(), warnings };
+    serde_json::to_string(&report).expect("a lint report always serializes")
+}
+
+/// Checks the indentation of every line against `indent_size` spaces per level
+pub fn check_indentation(source: &str, indent_size: usize) -> Vec<LintWarning> {
+    let mut warnings = Vec::new();
+    let mut in_string = false;
+    for (index, line) in source.lines().enumerate() {
+        let starts_in_string = in_string;
+        (in_string, _) = scan_code(line, in_string, |_, _| {});
+
+        let content = line.trim_start_matches([' ', '\t']);
+        if starts_in_string || content.is_empty() {
+            continue;
+        }
+
+        let indentation = &line[..line.len() - content.len()];
+        let tabs = indentation.contains('\t');
+        let spaces = indentation.matches(' ').count();
+        if tabs && spaces > 0 {
+            // Point at the first character that breaks with the line's leading style
+            let first = indentation.chars().next().unwrap_or(' ');
+            let switch = indentation.find(|c| c != first).unwrap_or(0);
+            warnings.push(LintWarning {
+                code: MIXED_INDENTATION,
+                severity: WarningLevel::Medium,
+                line: index + 1,
+                column: switch + 1,
+                message: "indentation mixes tabs and spaces".to_string(),
+            });
+        } else if !tabs && indent_size > 0 && spaces % indent_size != 0 {
+            warnings.push(LintWarning {
+                code: MISALIGNED_INDENTATION,
+                severity: WarningLevel::Medium,
+                line: index + 1,
+                column: spaces + 1,
+                message: format!("indented by {} spaces, which is not a multiple of {}", spaces, indent_size),
+            });
+        }
+    }
+    warnings
+}
+
+/// Lints a parsed program
+///
+/// Without the source there are no line numbers, so every warning has line 0; use
+/// `lint_source` to have warnings placed at the item they're in.
+pub fn lint_program(program: &Program, level: WarningLevel) -> Vec<LintWarning> {
+    let mut linter = Linter::new(level);
+    linter.program(program.statements.iter().map(|statement| (statement, 0)));
+    linter.warnings
+}
+
+/// Parses and lints source code, placing each warning at the first line of its
+/// top-level item
+pub fn lint_source(source: &str, level: WarningLevel) -> Result<Vec<LintWarning>, String> {
+    let items = Parser::new(source).parse_program_with_lines()?;
+    let mut linter = Linter::new(level);
+    linter.program(items.iter().map(|(statement, start, _)| (statement, *start)));
+    Ok(linter.warnings)
+}
+
+/// A `let` binding in scope, and whether anything has read it yet
+struct Binding {
+    name: String,
+    line: usize,
+    used: bool,
+}
+
+struct Linter {
+    level: WarningLevel,
+    line: usize, // Where the top-level item being linted starts
+    scopes: Vec<Vec<Binding>>,
+    warnings: Vec<LintWarning>,
+}
+
+impl Linter {
+    fn new(level: WarningLevel) -> Self {
+        Linter { level, line: 0, scopes: Vec::new(), warnings: Vec::new() }
+    }
+
+    fn warn(&mut self, code: &'static str, severity: WarningLevel, line: usize, message: String) {
+        if self.level.reports(severity) {
+            let column = if line == 0 { 0 } else { 1 };
+            self.warnings.push(LintWarning { code, severity, line, column, message });
+        }
+    }
+
+    fn program<'p>(&mut self, items: impl Iterator<Item = (&'p Statement, usize)>) {
+        // Top-level bindings may be read by importers, so only their shadowing is checked
+        self.scopes.push(Vec::new());
+        let mut statements = Vec::new();
+        for (statement, line) in items {
+            self.line = line;
+            self.statement(statement);
+            statements.push(statement);
+        }
+        self.unreachable(&statements);
+        self.scopes.pop();
+    }
+
+    /// Lints statements in a scope of their own, reporting its unused bindings at the end
+    fn block(&mut self, statements: &[Statement]) {
+        self.scoped(Vec::new(), |linter| linter.statements(statements));
+    }
+
+    fn scoped(&mut self, names: Vec<String>, body: impl FnOnce(&mut Self)) {
+        let line = self.line;
+        self.scopes.push(names.into_iter().map(|name| Binding { name, line, used: true }).collect());
+        body(self);
+        for binding in self.scopes.pop().unwrap_or_default() {
+            if !binding.used {
+                self.warn(
+                    UNUSED_VARIABLE,
+                    WarningLevel::Medium,
+                    binding.line,
+                    format!("variable `{}` is never used", binding.name),
+                );
+            }
+        }
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+        self.unreachable(&statements.iter().collect::<Vec<_>>());
+    }
+
+    fn unreachable(&mut self, statements: &[&Statement]) {
+        let exit = statements.iter().position(|statement| {
+            matches!(statement, Statement::Return(_) | Statement::Break(_) | Statement::Continue(_))
+        });
+        if let Some(index) = exit.filter(|index| index + 1 < statements.len()) {
+            let keyword = match statements[index] {
+                Statement::Return(_) => "return",
+                Statement::Break(_) => "break",
+                _ => "continue",
+            };
+            self.warn(UNREACHABLE_CODE, WarningLevel::High, self.line, format!("unreachable code after `{}`", keyword));
+        }
+    }
+
+    fn declare(&mut self, name: &str) {
+        if self.scopes.iter().flatten().any(|binding| binding.name == name) {
+            self.warn(
+                SHADOWED_VARIABLE,
+                WarningLevel::Low,
+                self.line,
+                format!("`{}` shadows an earlier binding of the same name", name),
+            );
+        }
+        let line = self.line;
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(Binding { name: name.to_string(), line, used: false });
+        }
+    }
+
+    fn use_name(&mut self, name: &str) {
+        if let Some(binding) = self.scopes.iter_mut().rev().flat_map(|scope| scope.iter_mut().rev()).find(|binding| binding.name == name) {
+            binding.used = true;
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Expression(expression) => self.expression(expression),
+            Statement::LetBinding { name, value, .. } => {
+                self.expression(value);
+                self.declare(name);
+            },
+            Statement::ConstBinding { value, .. } => self.expression(value),
+            Statement::Function(function) => self.function(function),
+            Statement::Class(class) => {
+                for method in &class.methods {
+                    self.function(method);
+                }
+                for constructor in &class.constructors {
+                    self.body(&constructor.parameters, &constructor.body);
+                }
+                for destructor in &class.destructors {
+                    self.block(&destructor.body);
+                }
+            },
+            Statement::Trait(definition) => definition.methods.iter().for_each(|method| self.function(method)),
+            Statement::Implementation(definition) => definition.methods.iter().for_each(|method| self.function(method)),
+            Statement::Actor(actor) => actor.handlers.iter().for_each(|handler| self.function(handler)),
+            Statement::Effect(effect) => effect.operations.iter().for_each(|operation| self.function(operation)),
+            Statement::Return(value) => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            },
+            Statement::While { condition, body, .. } => {
+                self.expression(condition);
+                self.block(body);
+            },
+            Statement::Defer(statement) => self.statement(statement),
+            Statement::Assert { actual, expected, message, .. } => {
+                self.expression(actual);
+                expected.iter().chain(message).for_each(|expression| self.expression(expression));
+            },
+            Statement::Block(statements) => self.block(statements),
+            // Macro bodies are templates, and their names only mean something once expanded
+            Statement::MacroDefinition(_)
+            | Statement::Break(_)
+            | Statement::Continue(_)
+            | Statement::Enum(_)
+            | Statement::TypeAlias(_)
+            | Statement::Extern(_) => {},
+        }
+    }
+
+    fn function(&mut self, function: &FunctionDef) {
+        self.body(&function.parameters, &function.body);
+    }
+
+    fn body(&mut self, parameters: &[Parameter], body: &[Statement]) {
+        for parameter in parameters {
+            if let Some(default) = &parameter.default_value {
+                self.expression(default);
+            }
+        }
+        let names = parameters.iter().map(|parameter| parameter.name.clone()).collect();
+        self.scoped(names, |linter| linter.statements(body));
+    }
+
+    fn expressions(&mut self, expressions: &[Expression]) {
+        for expression in expressions {
+            self.expression(expression);
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Identifier(name) => self.use_name(name),
+            Expression::Call(name, arguments) => {
+                self.use_name(name);
+                self.expressions(arguments);
+            },
+            Expression::Array(items) | Expression::Tuple(items) | Expression::Race(items) => self.expressions(items),
+            Expression::MacroInvocation(_, arguments) | Expression::Spawn(_, arguments) => self.expressions(arguments),
+            Expression::Struct(_, fields) => fields.iter().for_each(|(_, value)| self.expression(value)),
+            Expression::ArrayRepeat(first, second)
+            | Expression::BinaryOp(first, _, second, _)
+            | Expression::Index(first, second)
+            | Expression::Send(first, second)
+            | Expression::ChannelSend(first, second)
+            | Expression::Timeout(first, second) => {
+                self.expression(first);
+                self.expression(second);
+            },
+            Expression::Slice(value, start, end) => {
+                self.expression(value);
+                self.expression(start);
+                self.expression(end);
+            },
+            Expression::UnaryOp(_, value)
+            | Expression::FieldAccess(value, _)
+            | Expression::Propagate(value)
+            | Expression::ChannelReceive(value)
+            | Expression::ChannelClose(value)
+            | Expression::Await(value)
+            | Expression::Future(value)
+            | Expression::SpawnTask(value)
+            | Expression::Join(value) => self.expression(value),
+            Expression::MethodCall(receiver, _, arguments) => {
+                self.expression(receiver);
+                self.expressions(arguments);
+            },
+            Expression::Pipeline(value, stages) | Expression::BackPipeline(value, stages) => {
+                self.expression(value);
+                self.expressions(stages);
+            },
+            Expression::Lambda(parameters, body) => self.body(parameters, body),
+            Expression::LambdaSimple(parameters, body) => self.scoped(parameters.clone(), |linter| linter.expression(body)),
+            Expression::If(condition, then_branch, else_branch) => {
+                self.expression(condition);
+                if then_branch.is_empty() {
+                    self.warn(EMPTY_IF_BRANCH, WarningLevel::Medium, self.line, "`if` branch is empty".to_string());
+                }
+                self.block(then_branch);
+                self.block(else_branch);
+            },
+            Expression::Match(subject, arms) => {
+                self.expression(subject);
+                for (pattern, guard, body) in arms {
+                    self.scoped(pattern_names(pattern), |linter| {
+                        guard.iter().for_each(|guard| linter.expression(guard));
+                        linter.pattern(pattern);
+                        linter.block(body);
+                    });
+                }
+            },
+            Expression::BlockExpr(statements) | Expression::Block(statements) | Expression::AsyncBlock(statements) => {
+                self.block(statements)
+            },
+            Expression::TryCatch(body, error, handler) => {
+                self.block(body);
+                self.scoped(vec![error.clone()], |linter| linter.block(handler));
+            },
+            Expression::DestructureAssignment(pattern, value, body) => {
+                self.expression(value);
+                self.scoped(pattern_names(pattern), |linter| linter.statement(body));
+            },
+            Expression::InterpolatedString(parts) => {
+                for part in parts {
+                    if let StringPart::Interpolated(value, _) = part {
+                        self.expression(value);
+                    }
+                }
+            },
+            Expression::Select(arms) => arms.iter().for_each(|arm| self.select_arm(arm)),
+            Expression::Integer(_)
+            | Expression::Float(_)
+            | Expression::String(_)
+            | Expression::Bytes(_)
+            | Expression::Boolean(_)
+            | Expression::Nil
+            | Expression::Char(_)
+            | Expression::Receive
+            | Expression::MultiLangCall(..)
+            | Expression::MultiLangImport(..)
+            | Expression::MultiLangIndex(..)
+            | Expression::ChannelCreate(_) => {},
+        }
+    }
+
+    /// Reads the expressions a pattern contains, such as guards
+    fn pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Literal(value) => self.expression(value),
+            Pattern::Tuple(items) | Pattern::Array(items) => items.iter().for_each(|item| self.pattern(item)),
+            Pattern::Struct(_, fields) => fields.iter().for_each(|(_, field)| self.pattern(field)),
+            Pattern::Or(left, right) => {
+                self.pattern(left);
+                self.pattern(right);
+            },
+            Pattern::Enum(_, _, Some(items)) => items.iter().for_each(|item| self.pattern(item)),
+            Pattern::Irrefutable(inner) => self.pattern(inner),
+            Pattern::Guard(inner, guard) => {
+                self.pattern(inner);
+                self.expression(guard);
+            },
+            Pattern::Identifier(_) | Pattern::Wildcard | Pattern::Range(..) | Pattern::Enum(_, _, None) => {},
+        }
+    }
+
+    fn select_arm(&mut self, arm: &SelectArm) {
+        match &arm.channel_operation {
+            ChannelOperation::Send { channel, value } => {
+                self.expression(channel);
+                self.expression(value);
+            },
+            ChannelOperation::Receive { channel }
+            | ChannelOperation::Close { channel }
+            | ChannelOperation::Timeout { millis: channel } => self.expression(channel),
+            ChannelOperation::Default => {},
+        }
+        let names = arm.pattern.as_ref().map(pattern_names).unwrap_or_default();
+        self.scoped(names, |linter| linter.block(&arm.body));
+    }
+}
+
+/// The names a pattern binds
+pub(crate) fn pattern_names(pattern: &Pattern) -> Vec<String> {
+    match pattern {
+        Pattern::Identifier(name) => vec![name.clone()],
+        Pattern::Tuple(items) | Pattern::Array(items) | Pattern::Enum(_, _, Some(items)) => {
+            items.iter().flat_map(pattern_names).collect()
+        },
+        Pattern::Struct(_, fields) => fields.iter().flat_map(|(_, field)| pattern_names(field)).collect(),
+        Pattern::Or(left, _) => pattern_names(left),
+        Pattern::Irrefutable(inner) | Pattern::Guard(inner, _) => pattern_names(inner),
+        Pattern::Literal(_) | Pattern::Wildcard | Pattern::Range(..) | Pattern::Enum(_, _, None) => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mixed_tabs_and_spaces() {
+        let source = "fn main() {\n\tlet a = 1\n    let b = 2\n\t    let c = 3\n  \tlet d = 4\n}\n";
+        let warnings = check_indentation(source, 4);
+        assert_eq!(warnings.len(), 2);
+        assert_eq!((warnings[0].code, warnings[0].line, warnings[0].column), (MIXED_INDENTATION, 4, 2));
+        assert_eq!((warnings[1].code, warnings[1].line, warnings[1].column), (MIXED_INDENTATION, 5, 3));
+        assert_eq!(warnings[0].to_string(), "4:2: warning[W0001]: indentation mixes tabs and spaces");
+    }
+
+    #[test]
+    fn test_misaligned_indentation() {
+        let source = "fn main() {\n    let a = 1\n     let b = 2\n  let c = \"first\n   second\"\n\n}\n";
+        let warnings = check_indentation(source, 4);
+        assert_eq!(warnings.len(), 2);
+        assert_eq!((warnings[0].code, warnings[0].line, warnings[0].column), (MISALIGNED_INDENTATION, 3, 6));
+        assert_eq!(warnings[0].message, "indented by 5 spaces, which is not a multiple of 4");
+        assert_eq!(warnings[1].line, 4);
+
+        // Two-space indentation is fine when that is the configured size
+        assert!(check_indentation("if x {\n  y()\n}\n", 2).is_empty());
+    }
+
+    fn codes(warnings: &[LintWarning]) -> Vec<&'static str> {
+        warnings.iter().map(|warning| warning.code).collect()
+    }
+
+    #[test]
+    fn test_lint_program_finds_each_kind_of_problem() {
+        let source = "\
+let total = 0
+fn f(n: Int) -> Int {
+    let unused = 1
+    let total = n * 2
+    if total > 10 {
+    }
+    return total
+    print(n)
+}
+";
+        let warnings = lint_source(source, WarningLevel::High).unwrap();
+        assert_eq!(codes(&warnings), vec![SHADOWED_VARIABLE, EMPTY_IF_BRANCH, UNREACHABLE_CODE, UNUSED_VARIABLE]);
+        assert!(warnings.iter().all(|warning| warning.line == 2));
+        assert!(warnings[3].message.contains("`unused`"));
+
+        // Without source lines the same warnings come out unplaced
+        let program = Parser::new(source).parse_program().unwrap();
+        let warnings = lint_program(&program, WarningLevel::High);
+        assert_eq!(warnings.len(), 4);
+        assert_eq!(warnings[2].to_string(), "warning[W0005]: unreachable code after `return`");
+    }
+
+    #[test]
+    fn test_uses_in_nested_code_count() {
+        let source = "\
+fn f(items: [Int]) -> Int {
+    let scale = 3
+    let offset = 1
+    let label = \"x\"
+    while offset < 10 {
+        print(\"${label}\")
+    }
+    match items {
+        [first, _] => first * scale + offset,
+        _ => 0,
+    }
+}
+";
+        assert!(lint_source(source, WarningLevel::High).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_warning_level_filters_by_severity() {
+        let source = "fn f() {\n    let a = 1\n    let a = 2\n    return a\n    a\n}\n";
+        let found = |level| codes(&lint_source(source, level).unwrap());
+        assert_eq!(found(WarningLevel::High), vec![SHADOWED_VARIABLE, UNREACHABLE_CODE, UNUSED_VARIABLE]);
+        assert_eq!(found(WarningLevel::Medium), vec![UNREACHABLE_CODE, UNUSED_VARIABLE]);
+        assert_eq!(found(WarningLevel::Low), vec![UNREACHABLE_CODE]);
+
+        assert_eq!(WarningLevel::parse("medium"), Ok(WarningLevel::Medium));
+        assert!(WarningLevel::parse("strict").is_err());
+    }
+}
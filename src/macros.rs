@@ -3,6 +3,7 @@
 
 use std::collections::HashMap;
 use crate::ast::*;
+use crate::limits::CompileLimits;
 
 /// Represents a macro definition with its expansion rules
 #[derive(Debug, Clone)]
@@ -115,10 +116,10 @@ impl MacroSystem {
                     Ok(Expression::Identifier(name.clone()))
                 }
             },
-            Expression::BinaryOp(left, op, right) => {
+            Expression::BinaryOp(left, op, right, location) => {
                 let new_left = Box::new(self.substitute_expr_parameters(left, substitutions)?);
                 let new_right = Box::new(self.substitute_expr_parameters(right, substitutions)?);
-                Ok(Expression::BinaryOp(new_left, op.clone(), new_right))
+                Ok(Expression::BinaryOp(new_left, op.clone(), new_right, *location))
             },
             Expression::Call(func_name, args) => {
                 let mut new_args = Vec::new();
@@ -127,6 +128,13 @@ impl MacroSystem {
                 }
                 Ok(Expression::Call(func_name.clone(), new_args))
             },
+            Expression::MacroInvocation(macro_name, args) => {
+                let mut new_args = Vec::new();
+                for arg in args {
+                    new_args.push(self.substitute_expr_parameters(arg, substitutions)?);
+                }
+                Ok(Expression::MacroInvocation(macro_name.clone(), new_args))
+            },
             // For now, we'll handle other expression types by returning them unchanged
             // In a full implementation, we'd recursively substitute in all expression types
             _ => Ok(expr.clone()),
@@ -141,84 +149,186 @@ impl MacroSystem {
 
 /// Preprocesses a program by expanding all macro invocations
 pub fn preprocess_macros(program: &Program) -> Result<Program, String> {
+    preprocess_macros_with_limits(program, &CompileLimits::default())
+}
+
+/// Expands all macro invocations, failing once expansion passes `limits`
+///
+/// Invocations produced by an expansion are expanded in turn, up to `macro_depth`
+/// levels, and the expanded program may hold at most `max_ast_nodes` nodes.
+pub fn preprocess_macros_with_limits(program: &Program, limits: &CompileLimits) -> Result<Program, String> {
+    let items: Vec<(&Statement, usize)> = program.statements.iter().map(|stmt| (stmt, 0)).collect();
+    let expanded = expand_items(&items, limits)?;
+    Ok(Program {
+        statements: expanded.into_iter().map(|item| item.statement).collect(),
+    })
+}
+
+/// A top-level statement after expansion, with the line it was written on and the macros expanded into it
+#[derive(Debug, Clone)]
+pub struct ExpandedStatement {
+    pub statement: Statement,
+    pub line: usize,
+    /// Macros expanded while producing the statement, outermost first, without repeats
+    pub expansions: Vec<String>,
+}
+
+impl ExpandedStatement {
+    /// Adds the expansion context to an error found in this statement
+    ///
+    /// Expanded code has no source position of its own, so the error is tied to the
+    /// invocation that produced it:
+    ///
+    /// ```text
+    /// Operator Sub requires numeric operands, found Int and String
+    ///   in expansion of macro `double`
+    ///   in expansion of macro `quad` (invoked at line 3)
+    /// ```
+    pub fn annotate(&self, error: String) -> String {
+        let (outermost, nested) = match self.expansions.split_first() {
+            Some(split) => split,
+            None => return error,
+        };
+        let mut message = error;
+        for name in nested.iter().rev() {
+            message.push_str(&format!("\n  in expansion of macro `{}`", name));
+        }
+        message.push_str(&format!("\n  in expansion of macro `{}` (invoked at line {})", outermost, self.line));
+        message
+    }
+}
+
+/// Expands macros in statements parsed with `Parser::parse_program_with_lines`, keeping
+/// each statement's line and the macros expanded into it for diagnostics
+pub fn preprocess_macros_located(
+    statements: &[(Statement, usize, usize)],
+    limits: &CompileLimits,
+) -> Result<Vec<ExpandedStatement>, String> {
+    let items: Vec<(&Statement, usize)> = statements.iter().map(|(stmt, line, _)| (stmt, *line)).collect();
+    expand_items(&items, limits)
+}
+
+fn expand_items(items: &[(&Statement, usize)], limits: &CompileLimits) -> Result<Vec<ExpandedStatement>, String> {
     let mut macro_system = MacroSystem::new();
     
     // First, collect all macro definitions
-    for stmt in &program.statements {
+    for (stmt, _) in items {
         if let Statement::MacroDefinition(macro_def) = stmt {
             macro_system.register_macro(macro_def)?;
         }
     }
     
     // Then, expand the program by replacing macro invocations with their expansions
+    let mut expansion = Expansion { macros: &macro_system, limits: *limits, nodes: 0, expanded: Vec::new() };
     let mut expanded_statements = Vec::new();
-    for stmt in &program.statements {
+    for (stmt, line) in items {
         // Skip macro definitions as they've been processed
         if matches!(stmt, Statement::MacroDefinition(_)) {
             continue;
         }
         
         // Expand any macro invocations in the statement
-        let expanded_stmt = expand_macros_in_statement(stmt, &macro_system)?;
-        expanded_statements.push(expanded_stmt);
+        let expanded_stmt = expansion.statement(stmt)?;
+        expanded_statements.push(ExpandedStatement {
+            statement: expanded_stmt,
+            line: *line,
+            expansions: std::mem::take(&mut expansion.expanded),
+        });
     }
     
-    Ok(Program {
-        statements: expanded_statements,
-    })
+    limits.check_statements(expanded_statements.iter().map(|item| &item.statement))?;
+    Ok(expanded_statements)
 }
 
-/// Recursively expands macro invocations in a statement
-fn expand_macros_in_statement(stmt: &Statement, macro_system: &MacroSystem) -> Result<Statement, String> {
-    match stmt {
-        Statement::Expression(expr) => {
-            let expanded_expr = expand_macros_in_expression(expr, macro_system)?;
-            Ok(Statement::Expression(expanded_expr))
-        },
-        Statement::Block(statements) => {
-            let mut expanded_block = Vec::new();
-            for stmt in statements {
-                expanded_block.push(expand_macros_in_statement(stmt, macro_system)?);
-            }
-            Ok(Statement::Block(expanded_block))
-        },
-        // For now, return other statement types unchanged
-        _ => Ok(stmt.clone()),
-    }
+/// Macro expansion in progress, with the nodes expansions have produced so far
+struct Expansion<'a> {
+    macros: &'a MacroSystem,
+    limits: CompileLimits,
+    nodes: usize,
+    expanded: Vec<String>, // Macros expanded into the current top-level statement
 }
 
-/// Recursively expands macro invocations in an expression
-fn expand_macros_in_expression(expr: &Expression, macro_system: &MacroSystem) -> Result<Expression, String> {
-    match expr {
-        Expression::MacroInvocation(name, args) => {
-            // Expand the macro and return the first expression from the expansion
-            // In a more sophisticated implementation, we might need to handle multiple expressions
-            let expanded_statements = macro_system.expand_macro(name, args)?;
-            
-            // Find the first expression in the expanded statements
-            for stmt in expanded_statements {
-                if let Statement::Expression(e) = stmt {
-                    return Ok(e);
+impl Expansion<'_> {
+    /// Recursively expands macro invocations in a statement
+    fn statement(&mut self, stmt: &Statement) -> Result<Statement, String> {
+        match stmt {
+            Statement::Expression(expr) => {
+                let expanded_expr = self.expression(expr, 0)?;
+                Ok(Statement::Expression(expanded_expr))
+            },
+            Statement::LetBinding { mutable, name, type_annotation, value, ownership_modifier, lifetime_annotation } => {
+                Ok(Statement::LetBinding {
+                    mutable: *mutable,
+                    name: name.clone(),
+                    type_annotation: type_annotation.clone(),
+                    value: self.expression(value, 0)?,
+                    ownership_modifier: ownership_modifier.clone(),
+                    lifetime_annotation: lifetime_annotation.clone(),
+                })
+            },
+            Statement::Block(statements) => {
+                let mut expanded_block = Vec::new();
+                for stmt in statements {
+                    expanded_block.push(self.statement(stmt)?);
+                }
+                Ok(Statement::Block(expanded_block))
+            },
+            // For now, return other statement types unchanged
+            _ => Ok(stmt.clone()),
+        }
+    }
+
+    /// Recursively expands macro invocations in an expression
+    ///
+    /// `depth` counts the expansions the expression came out of.
+    fn expression(&mut self, expr: &Expression, depth: usize) -> Result<Expression, String> {
+        match expr {
+            Expression::MacroInvocation(name, args) => {
+                if depth >= self.limits.macro_depth {
+                    return Err(format!(
+                        "macro recursion limit reached while expanding '{}!' (limit is {} levels)",
+                        name, self.limits.macro_depth
+                    ));
+                }
+
+                if !self.expanded.contains(name) {
+                    self.expanded.push(name.clone());
+                }
+
+                // Expand the macro and use the first expression from the expansion
+                // In a more sophisticated implementation, we might need to handle multiple expressions
+                let expanded_statements = self.macros.expand_macro(name, args)?;
+                let expanded = expanded_statements.into_iter()
+                    .find_map(|stmt| match stmt {
+                        Statement::Expression(e) => Some(e),
+                        _ => None,
+                    })
+                    // If no expression is found, return a unit value
+                    .unwrap_or(Expression::Nil);
+
+                // Count what each expansion produces, so a macro that grows its input
+                // stops at the node limit rather than when memory runs out
+                self.nodes += crate::limits::expression_nodes(&expanded);
+                self.limits.check_node_count(self.nodes)?;
+
+                // The expansion may itself invoke macros, including this one
+                self.expression(&expanded, depth + 1)
+            },
+            Expression::BinaryOp(left, op, right, location) => {
+                let new_left = Box::new(self.expression(left, depth)?);
+                let new_right = Box::new(self.expression(right, depth)?);
+                Ok(Expression::BinaryOp(new_left, op.clone(), new_right, *location))
+            },
+            Expression::Call(func_name, args) => {
+                let mut new_args = Vec::new();
+                for arg in args {
+                    new_args.push(self.expression(arg, depth)?);
                 }
-            }
-            
-            // If no expression is found, return a unit value
-            Ok(Expression::Nil)
-        },
-        Expression::BinaryOp(left, op, right) => {
-            let new_left = Box::new(expand_macros_in_expression(left, macro_system)?);
-            let new_right = Box::new(expand_macros_in_expression(right, macro_system)?);
-            Ok(Expression::BinaryOp(new_left, op.clone(), new_right))
-        },
-        Expression::Call(func_name, args) => {
-            let mut new_args = Vec::new();
-            for arg in args {
-                new_args.push(expand_macros_in_expression(arg, macro_system)?);
-            }
-            Ok(Expression::Call(func_name.clone(), new_args))
-        },
-        // For now, return other expression types unchanged
-        _ => Ok(expr.clone()),
+                Ok(Expression::Call(func_name.clone(), new_args))
+            },
+            // For now, return other expression types unchanged
+            _ => Ok(expr.clone()),
+        }
     }
 }
 
@@ -240,4 +350,51 @@ mod tests {
         assert!(macro_system.register_macro(&macro_def).is_ok());
         assert!(macro_system.has_macro("test_macro"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_self_expanding_macro_hits_depth_limit() {
+        let program = crate::parser::Parser::new("macro forever(x) { forever!(x) }\nforever!(1)")
+            .parse_program()
+            .unwrap();
+        let err = preprocess_macros(&program).unwrap_err();
+        assert_eq!(err, "macro recursion limit reached while expanding 'forever!' (limit is 64 levels)");
+
+        // A macro that doubles its argument on every step runs into the node limit first
+        let program = crate::parser::Parser::new("macro grow(x) { grow!(x + x) }\ngrow!(1)")
+            .parse_program()
+            .unwrap();
+        let limits = CompileLimits { max_ast_nodes: 1000, ..CompileLimits::default() };
+        let err = preprocess_macros_with_limits(&program, &limits).unwrap_err();
+        assert!(err.starts_with("AST node limit reached"), "{}", err);
+    }
+
+    #[test]
+    fn test_nested_macro_expansion() {
+        let program = crate::parser::Parser::new("macro double(x) { x + x }\nmacro quad(x) { double!(double!(x)) }\nquad!(1)")
+            .parse_program()
+            .unwrap();
+        let expanded = preprocess_macros(&program).unwrap();
+        assert_eq!(expanded.statements.len(), 1);
+        assert_eq!(crate::limits::expression_nodes(match &expanded.statements[0] {
+            Statement::Expression(expr) => expr,
+            other => panic!("expected an expression, got {:?}", other),
+        }), 7);
+    }
+
+    #[test]
+    fn test_type_error_in_expansion_names_macro_and_call_site() {
+        let source = "macro shout(x) { x - \"!\" }\nmacro twice(x) { shout!(x) }\n\nlet n = 1\nlet loud = twice!(n)";
+        let statements = crate::parser::Parser::new(source).parse_program_with_lines().unwrap();
+        let expanded = preprocess_macros_located(&statements, &CompileLimits::default()).unwrap();
+        assert_eq!(expanded.len(), 2);
+        assert_eq!(expanded[1].line, 5);
+        assert_eq!(expanded[1].expansions, vec!["twice", "shout"]);
+
+        let err = crate::type_checker::check_expanded_with_limits(&expanded, &CompileLimits::default()).unwrap_err();
+        let lines: Vec<&str> = err.lines().collect();
+        assert_eq!(lines.len(), 3, "{}", err);
+        assert_eq!(lines[0], "Operator Sub requires numeric operands, found Int and String");
+        assert_eq!(lines[1], "  in expansion of macro `shout`");
+        assert_eq!(lines[2], "  in expansion of macro `twice` (invoked at line 5)");
+    }
+}
@@ -15,6 +15,22 @@ fn get_memory_usage() -> u64 {
     0
 }
 
+/// Recursively collects .logos files, skipping hidden directories and build output
+fn find_logos_files(dir: &Path, files: &mut Vec<std::path::PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        if path.is_dir() {
+            if !name.starts_with('.') && name != "target" {
+                find_logos_files(&path, files)?;
+            }
+        } else if path.extension().map_or(false, |ext| ext == "logos") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
 #[derive(Parser)]
 #[clap(name = "logos", about = "Logos Programming Language Compiler")]
 struct Cli {
@@ -22,6 +38,32 @@ struct Cli {
     command: Commands,
 }
 
+/// Compile-time limits shared by `run` and `check`
+#[derive(clap::Args)]
+struct LimitArgs {
+    /// How many levels deep macro invocations may expand into further invocations
+    #[clap(long, default_value_t = limits::DEFAULT_MACRO_DEPTH)]
+    macro_depth: usize,
+
+    /// How many levels deep type aliases may expand
+    #[clap(long, default_value_t = limits::DEFAULT_TYPE_DEPTH)]
+    type_depth: usize,
+
+    /// Largest number of AST nodes a program may have after macro expansion
+    #[clap(long, default_value_t = limits::DEFAULT_MAX_AST_NODES)]
+    max_ast_nodes: usize,
+}
+
+impl LimitArgs {
+    fn compile_limits(&self) -> limits::CompileLimits {
+        limits::CompileLimits {
+            macro_depth: self.macro_depth,
+            type_depth: self.type_depth,
+            max_ast_nodes: self.max_ast_nodes,
+        }
+    }
+}
+
 #[derive(clap::Subcommand)]
 enum Commands {
     /// Run a Logos program
@@ -37,6 +79,10 @@ enum Commands {
         #[clap(short, long)]
         profile: bool,
         
+        /// Where --profile writes folded call stacks, for flamegraph.pl or inferno
+        #[clap(long, default_value = profiler::DEFAULT_OUTPUT)]
+        profile_output: String,
+        
         /// Enable verbose output
         #[clap(short, long)]
         verbose: bool,
@@ -48,6 +94,31 @@ enum Commands {
         /// Enable memory profiling
         #[clap(long)]
         memory_profile: bool,
+        
+        /// Re-run whenever the file or a file it imports changes
+        #[clap(long)]
+        watch: bool,
+        
+        /// Result format: text, or json for a single machine-readable object on stdout
+        #[clap(long, default_value = "text")]
+        output: String,
+
+        /// Stack size in MiB for the thread the program runs on; raise it for deeply
+        /// recursive programs
+        #[clap(long, default_value_t = runtime::DEFAULT_STACK_SIZE / (1024 * 1024))]
+        stack_size: usize,
+
+        /// Directory the program's relative file paths resolve against (default: the
+        /// directory the file is in)
+        #[clap(long)]
+        cwd: Option<String>,
+
+        #[clap(flatten)]
+        limits: LimitArgs,
+
+        /// Arguments for the program, given after `--`; it reads them with env::args()
+        #[clap(last = true)]
+        args: Vec<String>,
     },
     
     /// Compile a Logos program to executable
@@ -55,18 +126,27 @@ enum Commands {
         /// The Logos file to compile
         file: String,
         
-        /// Output filename
-        #[clap(short, long, default_value = "output")]
-        output: String,
+        /// Output filename, placed in the profile directory (defaults to the source file's name)
+        #[clap(short, long)]
+        output: Option<String>,
         
-        /// Build in release mode
+        /// Build in release mode, writing to target/release instead of target/debug
         #[clap(long)]
         release: bool,
         
+        /// Directory to use in place of target/ for build artifacts
+        #[clap(long)]
+        out_dir: Option<String>,
+        
         /// Optimization level (0-3)
         #[clap(short = 'O', long, default_value = "2")]
         opt_level: u8,
         
+        /// Optimization passes to run in place of the level's preset, in order (fold, dce,
+        /// inline, cse), e.g. --passes=fold,dce
+        #[clap(long)]
+        passes: Option<String>,
+        
         /// Target architecture
         #[clap(long)]
         target: Option<String>,
@@ -75,6 +155,24 @@ enum Commands {
         #[clap(long)]
         debug_symbols: bool,
         
+        /// Emit an intermediate form instead of the default output (supported: bytecode)
+        #[clap(long)]
+        emit: Option<String>,
+        
+        /// Print the stages the build would run, with what each reads and produces, and stop
+        #[clap(long)]
+        plan: bool,
+
+        /// After building, print a hash of the output and of the inputs that produced it
+        /// (source, compiler version, imports, optimization passes)
+        #[clap(long)]
+        print_build_hash: bool,
+
+        /// Directory target/ and a relative --out-dir are placed under (default: the
+        /// current directory)
+        #[clap(long)]
+        cwd: Option<String>,
+        
         /// Enable verbose output
         #[clap(short, long)]
         verbose: bool,
@@ -93,6 +191,10 @@ enum Commands {
         #[clap(long)]
         lib: bool,
         
+        /// Project type: binary, library, web or wasm
+        #[clap(long = "type")]
+        project_type: Option<String>,
+        
         /// Enable verbose output
         #[clap(short, long)]
         verbose: bool,
@@ -103,9 +205,41 @@ enum Commands {
         /// The Logos file to check
         file: String,
         
+        /// Re-check whenever the file or a file it imports changes
+        #[clap(long)]
+        watch: bool,
+        
+        /// Also report lint warnings, such as inconsistent indentation
+        #[clap(long)]
+        lint: bool,
+        
+        /// Spaces per indentation level, used by --lint
+        #[clap(long, default_value = "4")]
+        indent_size: usize,
+        
+        /// Which lints --lint reports: low (likely bugs only), medium or high (everything);
+        /// at high, any high-severity warning fails the check
+        #[clap(long, default_value = "medium")]
+        warning_level: String,
+        
+        /// How --lint prints warnings: human, compact (one line each) or json
+        #[clap(long, default_value = "human")]
+        format: String,
+        
+        /// Also suggest performance improvements, such as hoisting len() out of loop conditions
+        #[clap(long)]
+        perf_hints: bool,
+        
+        /// Print every token the lexer reads, with its span, before checking
+        #[clap(long)]
+        dump_tokens: bool,
+        
         /// Enable verbose output
         #[clap(short, long)]
         verbose: bool,
+
+        #[clap(flatten)]
+        limits: LimitArgs,
     },
     
     /// Format Logos source code
@@ -117,6 +251,30 @@ enum Commands {
         #[clap(long)]
         check: bool,
         
+        /// Report format: text, or json listing the edits (with --check) or how many were applied
+        #[clap(long, default_value = "text")]
+        format: String,
+        
+        /// Lines longer than this are wrapped at call arguments, array items and method chains
+        #[clap(long, default_value = "100")]
+        max_width: usize,
+        
+        /// Indent with spaces or tabs
+        #[clap(long, default_value = "spaces")]
+        indent_style: String,
+        
+        /// Spaces per indentation level, and the width a tab counts for
+        #[clap(long, default_value = "4")]
+        indent_size: usize,
+        
+        /// End lines with unix (\n) or windows (\r\n) line endings, or auto to keep the file's own
+        #[clap(long, default_value = "unix")]
+        line_ending: String,
+        
+        /// Group and sort the @import statements at the top of the file
+        #[clap(long)]
+        sort_imports: bool,
+        
         /// Enable verbose output
         #[clap(short, long)]
         verbose: bool,
@@ -148,6 +306,10 @@ enum Commands {
         #[clap(long)]
         only_failed: bool,
         
+        /// Shrink the failing test in this file to a minimal failing program (--filter picks the test)
+        #[clap(long)]
+        minimize: Option<String>,
+        
         /// Enable verbose output
         #[clap(short, long)]
         verbose: bool,
@@ -162,6 +324,10 @@ enum Commands {
         #[clap(long)]
         open: bool,
         
+        /// Fail if a public item has no doc comment, instead of generating documentation
+        #[clap(long)]
+        check: bool,
+        
         /// Enable verbose output
         #[clap(short, long)]
         verbose: bool,
@@ -175,6 +341,7 @@ enum Commands {
     },
     
     /// Synchronize with another programming language
+    #[clap(alias = "add-lang")]
     Sync {
         /// Language to synchronize with
         language: String,
@@ -203,7 +370,7 @@ enum Commands {
         #[clap(long)]
         config: Option<String>,
         
-        /// Target directory for generated files
+        /// Target directory for generated files, relative to the project root
         #[clap(long)]
         target_dir: Option<String>,
         
@@ -281,11 +448,34 @@ enum Commands {
     
     /// Clean build artifacts
     Clean {
+        /// Also remove the generated logos/ sync files
+        #[clap(long)]
+        sync: bool,
+        
+        /// Show what would be removed without deleting anything
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Also remove the builds written to this --out-dir, which must be inside the project
+        #[clap(long)]
+        out_dir: Option<String>,
+
+        /// Project directory to clean, which --out-dir is relative to (default: the
+        /// current directory)
+        #[clap(long)]
+        cwd: Option<String>,
+        
         /// Enable verbose output
         #[clap(short, long)]
         verbose: bool,
     },
     
+    /// Explain a diagnostic code in detail, e.g. `logos explain E0001`
+    Explain {
+        /// The diagnostic code to explain
+        code: String,
+    },
+    
     /// Check the system for potential issues
     Doctor {
         /// Enable verbose output
@@ -305,7 +495,26 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::Run { file, debug, profile, verbose, timeout: _, memory_profile } => {
+        Commands::Run { file, debug, profile, profile_output, verbose, timeout: _, memory_profile, watch, output, stack_size, cwd, limits, args } => {
+            let stack_size = stack_size.checked_mul(1024 * 1024)
+                .filter(|&bytes| bytes > 0)
+                .ok_or_else(|| format!("Invalid --stack-size {}: expected a positive number of MiB", stack_size))?;
+            let limits = limits.compile_limits();
+            std_lib::file_io::use_script(Some(Path::new(&file)), cwd.as_deref().map(Path::new))?;
+            std_lib::env::set_args(args);
+            if output == "json" {
+                let source_code = std::fs::read_to_string(&file)
+                    .map_err(|e| -> Box<dyn std::error::Error> { format!("Could not read file '{}': {}", file, e).into() })?;
+                let report = run_report::run_captured(&source_code);
+                println!("{}", report.to_json());
+                if !report.success {
+                    std::process::exit(1);
+                }
+                return Ok(());
+            } else if output != "text" {
+                return Err(format!("Unknown output format '{}': expected text or json", output).into());
+            }
+
             if verbose {
                 println!("Running Logos file: {}", file);
                 if debug {
@@ -319,6 +528,18 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
             }
 
+            if watch {
+                watch::watch_file(Path::new(&file), |source| {
+                    let _ = console::Term::stdout().clear_screen();
+                    println!("Running {} (watching for changes, Ctrl-C to stop)\n", file);
+                    match logos_lang::execute_with_limits(source, &limits) {
+                        Ok(_) => println!("\n{}", console::style("Program executed successfully").green()),
+                        Err(e) => eprintln!("\nExecution error: {}", e),
+                    }
+                })?;
+                return Ok(());
+            }
+
             // Read the source file
             let source_code = std::fs::read_to_string(&file)
                 .map_err(|e| -> Box<dyn std::error::Error> { format!("Could not read file '{}': {}", file, e).into() })?;
@@ -341,8 +562,24 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 0
             };
 
-            // Execute using the library
-            match logos_lang::execute(&source_code) {
+            // Execute using the library, sampling the call stack when profiling
+            let profiler = if profile {
+                Some(profiler::Profiler::shared(profiler::DEFAULT_SAMPLE_INTERVAL))
+            } else {
+                None
+            };
+            // A bytecode cache written by `logos build` stands in for the source until the source changes
+            let result = match codegen::load_cache(Path::new(&file)) {
+                Some(bytecode) => {
+                    if verbose {
+                        println!("Using bytecode cache: {}", codegen::cache_path(Path::new(&file)).display());
+                    }
+                    logos_lang::execute_bytecode_with_stack_size(&bytecode, stack_size, profiler.clone())
+                },
+                None => logos_lang::execute_with_stack_size(&source_code, &limits, stack_size, profiler.clone()),
+            };
+
+            match result {
                 Ok(_) => {
                     if profile {
                         if let Some(start) = start_time {
@@ -350,6 +587,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                             println!("Execution completed in: {:?}", duration);
                         }
                     }
+                    if let Some(profiler) = &profiler {
+                        let profiler = profiler.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                        profiler.write_folded(Path::new(&profile_output))?;
+                        println!("Wrote {} call-stack samples to {} (render with flamegraph.pl or inferno-flamegraph)",
+                                 profiler.sample_count(), profile_output);
+                    }
                     if memory_profile {
                         let final_memory = get_memory_usage();
                         let memory_used = final_memory - initial_memory;
@@ -371,88 +614,164 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             return Ok(());
         },
         
-        Commands::Build { file, output, release, opt_level, target, debug_symbols, verbose } => {
+        Commands::Build { file, output, release, out_dir, opt_level, passes, target, debug_symbols, emit, plan, print_build_hash, cwd, verbose, .. } => {
+            let base = match cwd {
+                Some(dir) => std::path::absolute(&dir)?,
+                None => env::current_dir()?,
+            };
+            let layout = artifacts::TargetLayout::new(
+                &base,
+                out_dir.as_deref().map(|dir| base.join(dir)).as_deref(),
+                artifacts::Profile::from_release(release),
+            );
+            let name = match output {
+                Some(name) => name,
+                None => Path::new(&file).file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| "output".to_string()),
+            };
             if verbose {
-                println!("Building Logos file: {} -> {}", file, output);
+                println!("Building Logos file: {} -> {}", file, layout.profile_dir().display());
             }
 
+            let emit_bytecode = match emit.as_deref() {
+                None => false,
+                Some("bytecode") => true,
+                Some(other) => return Err(format!("Unsupported --emit kind '{}' (expected: bytecode)", other).into()),
+            };
+            let passes = passes.as_deref().map(crate::optimizer::PassManager::parse).transpose()?;
+            let settings = build_plan::BuildSettings { release, opt_level, passes, target: target.clone(), emit_bytecode };
+
+            // Dumps go beside the compiled output, in the profile's ir/ directory
+            let output_path = if emit_bytecode {
+                layout.dump_path(&name, "bc")
+            } else if settings.targets_llvm() {
+                layout.dump_path(&name, "ll")
+            } else {
+                layout.output_path(&name)
+            };
+
             // Read the source file
             let source_code = std::fs::read_to_string(&file)
                 .map_err(|e| -> Box<dyn std::error::Error> { format!("Could not read file '{}': {}", file, e).into() })?;
 
+            if plan {
+                println!("Build plan for {}:", file);
+                for (number, stage) in build_plan::plan(&file, &source_code, &settings, &output_path).iter().enumerate() {
+                    println!("  {}. {}", number + 1, stage);
+                }
+                return Ok(());
+            }
+
             // For now, just print the source code since the modules don't exist yet
             println!("Source code length: {} characters", source_code.len());
             if verbose {
                 println!("Optimization level: {}", opt_level);
+                let pass_names = settings.pass_manager().names();
+                println!("Optimization passes: {}", if pass_names.is_empty() { "none".to_string() } else { pass_names.join(", ") });
                 println!("Release mode: {}", if release { "yes" } else { "no" });
                 println!("Debug symbols: {}", if debug_symbols { "included" } else { "excluded" });
             }
 
-            // Parse the source code
-            let mut parser = crate::parser::Parser::new(&source_code);
-            let program = parser.parse_program()
-                .map_err(|e| format!("Parse error: {}", e))?;
+            // Embedded code in other languages is analyzed as it is for `logos run`
+            if build_plan::needs_multilang_analysis(&source_code) {
+                let analysis = logos_lang::analyze_with_multilang(&source_code, MultiLangSupport::Both)?;
+                if verbose {
+                    println!("Multilang analysis: {}", analysis.combined_result);
+                }
+            }
+
+            // Parsed and expanded as for `logos run`: #[cfg] applied, derives and macros expanded
+            let prepared = logos_lang::prepare_program(&source_code, &limits::CompileLimits::default())?;
 
             // Type check the program
             let mut type_checker = crate::type_checker::TypeChecker::new();
-            if let Err(e) = type_checker.check_program(&program) {
+            if let Err(e) = type_checker.check_program(&prepared) {
                 return Err(format!("Type error: {}", e).into());
             }
 
-            // Optimize the program if in release mode or optimization level > 1
-            let optimized_program = if release || opt_level > 1 {
-                let mut optimizer = crate::optimizer::Optimizer::new();
-                optimizer.optimize_program(program)
-            } else {
-                program
-            };
+            // Backends see one concrete copy of each generic function per instantiation
+            let program = crate::monomorphize::monomorphize(&prepared, &[])
+                .map_err(|e| format!("Type error: {}", e))?;
 
-            // Generate code based on target
-            let generated_code = if let Some(target_str) = &target {
-                if target_str == "llvm" {
-                    // Generate LLVM IR
-                    #[cfg(feature = "llvm-codegen")]
-                    {
-                        use inkwell::context::Context;
-                        let context = Context::create();
-                        let mut codegen = crate::llvm_code_gen::LLVMCodeGen::new(&context);
-                        codegen.generate_program(&optimized_program)
-                    }
-                    #[cfg(not(feature = "llvm-codegen"))]
-                    {
-                        return Err("LLVM code generation not enabled (compile with --features llvm-codegen)".into());
-                    }
-                } else {
-                    // Generate bytecode for the virtual machine
-                    let mut bytecode_gen = crate::bytecode_generator::BytecodeGenerator::new();
-                    let instructions = bytecode_gen.generate_program(&optimized_program);
+            // Optimize with the passes --passes names, or else the preset for the level:
+            // folding at -O1, dead code elimination too at -O2 and in release mode
+            let mut optimized_program = program;
+            settings.pass_manager().run(&mut optimized_program);
 
-                    // Convert instructions to a string representation for the output file
-                    format!("{:?}", instructions)
+            // Generate code based on target
+            let generated_code = if settings.targets_llvm() {
+                // Generate LLVM IR
+                #[cfg(feature = "llvm-codegen")]
+                {
+                    use inkwell::context::Context;
+                    let context = Context::create();
+                    let mut codegen = crate::llvm_code_gen::LLVMCodeGen::new(&context);
+                    codegen.generate_program(&optimized_program)
+                }
+                #[cfg(not(feature = "llvm-codegen"))]
+                {
+                    return Err("LLVM code generation not enabled (compile with --features llvm-codegen)".into());
                 }
             } else {
-                // Default to bytecode if no target specified
+                // Generate bytecode for the virtual machine, the default target
                 let mut bytecode_gen = crate::bytecode_generator::BytecodeGenerator::new();
                 let instructions = bytecode_gen.generate_program(&optimized_program);
 
-                // Convert instructions to a string representation for the output file
-                format!("{:?}", instructions)
+                // --emit bytecode writes a readable listing; otherwise the output is the
+                // serialized form, constant pool included, that Bytecode::from_text reads back
+                if emit_bytecode {
+                    crate::bytecode_generator::disassemble_with_constants(&instructions, bytecode_gen.constants())
+                } else {
+                    codegen::Bytecode {
+                        instructions,
+                        constants: bytecode_gen.constants().to_vec(),
+                        unsupported: bytecode_gen.unsupported().to_vec(),
+                    }.to_text()
+                }
             };
 
-            // Write the generated code to the output file
-            std::fs::write(&output, generated_code)
-                .map_err(|e| -> Box<dyn std::error::Error> { format!("Could not write output file '{}': {}", output, e).into() })?;
+            layout.write(&output_path, &generated_code)?;
+
+            if print_build_hash {
+                let inputs = build_hash::BuildInputs::collect(Path::new(&file), &source_code, &settings)?;
+                println!("{}", build_hash::BuildHash::new(inputs, &generated_code));
+            }
+
+            // Cache bytecode beside the source for `logos run`. It is compiled from the program
+            // as run sees it, with macros expanded; programs using constructs without a bytecode
+            // form yet, or embedding other languages, keep running from source.
+            if !build_plan::needs_multilang_analysis(&source_code) {
+                let bytecode = codegen::compile_to_bytecode(&prepared);
+                if bytecode.is_complete() {
+                    let cache = codegen::write_cache(Path::new(&file), &bytecode)?;
+                    if verbose {
+                        println!("Wrote bytecode cache: {}", cache.display());
+                    }
+                } else if verbose {
+                    println!("No bytecode cache: {} not compiled to bytecode yet", bytecode.unsupported.join(", "));
+                }
+            }
 
             if verbose {
-                println!("Successfully built {} -> {}", file, output);
+                println!("Successfully built {} -> {}", file, output_path.display());
             }
             return Ok(());
         },
         
-        Commands::Init { name, bin, lib, verbose } => {
+        Commands::Init { name, bin, lib, project_type, verbose } => {
             if verbose {
                 println!("Initializing new Logos project: {}", name);
             }
+
+            // Validate before touching the filesystem
+            project::validate_project_name(&name)?;
+            let project_type = match (project_type, bin, lib) {
+                (Some(ty), false, false) => project::ProjectType::parse(&ty)?,
+                (None, false, true) => project::ProjectType::Library,
+                (None, _, false) => project::ProjectType::Binary,
+                _ => return Err("Only one of --bin, --lib and --type may be given".into()),
+            };
             
             // Create project directory
             let project_path = std::path::Path::new(&name);
@@ -463,9 +782,6 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             std::fs::create_dir_all(project_path.join("src"))
                 .map_err(|e| -> Box<dyn std::error::Error> { format!("Could not create project directory: {}", e).into() })?;
 
-            // Determine project type
-            let project_type = if lib { "library" } else { "binary" };
-
             if verbose {
                 println!("Initializing {} project: {}", project_type, name);
             }
@@ -486,27 +802,10 @@ logos-lang = {{ path = "../.." }}
             std::fs::write(project_path.join("Cargo.toml"), cargo_toml_content)
                 .map_err(|e| -> Box<dyn std::error::Error> { format!("Could not create Cargo.toml: {}", e).into() })?;
 
-            // Create main logos file if it's a binary project
-            if !lib {
-                let main_content = r#"// Main function for your Logos program
-fn main() {
-    print("Hello, Logos!")
-}
-"#;
-
-                std::fs::write(project_path.join("src").join("main.logos"), main_content)
-                    .map_err(|e| -> Box<dyn std::error::Error> { format!("Could not create main.logos: {}", e).into() })?;
-            } else {
-                // Create lib logos file if it's a library project
-                let lib_content = r#"// Library module for your Logos project
-fn greet(name: String) -> String {
-    "Hello, " + name + "!"
-}
-"#;
-
-                std::fs::write(project_path.join("src").join("lib.logos"), lib_content)
-                    .map_err(|e| -> Box<dyn std::error::Error> { format!("Could not create lib.logos: {}", e).into() })?;
-            }
+            // Create the entry file for the project type
+            let entry_file = project_type.entry_file();
+            std::fs::write(project_path.join("src").join(entry_file), project_type.entry_template())
+                .map_err(|e| -> Box<dyn std::error::Error> { format!("Could not create {}: {}", entry_file, e).into() })?;
 
             // Create .gitignore
             let gitignore_content = r#"# Logos build artifacts
@@ -536,16 +835,32 @@ Thumbs.db
                 println!("  ├── Cargo.toml");
                 println!("  ├── .gitignore");
                 println!("  └── src/");
-                if !lib {
-                    println!("      └── main.logos");
-                } else {
-                    println!("      └── lib.logos");
-                }
+                println!("      └── {}", project_type.entry_file());
             }
             return Ok(());
         },
         
-        Commands::Check { file, verbose } => {
+        Commands::Check { file, watch, lint, indent_size, warning_level, format, perf_hints, dump_tokens, verbose, limits } => {
+            let limits = limits.compile_limits();
+            let warning_level = linter::WarningLevel::parse(&warning_level)?;
+            if !["human", "compact", "json"].contains(&format.as_str()) {
+                return Err(format!("Unknown format '{}': expected human, compact or json", format).into());
+            }
+            if watch {
+                watch::watch_file(Path::new(&file), |source| {
+                    let _ = console::Term::stdout().clear_screen();
+                    println!("Checking {} (watching for changes, Ctrl-C to stop)\n", file);
+                    if dump_tokens {
+                        println!("{}", debug_tokens::dump(source));
+                    }
+                    match check_syntax_and_types_with_limits(source, &limits) {
+                        Ok(()) => println!("{}", console::style(format!("File {} checked successfully", file)).green()),
+                        Err(e) => eprintln!("{}: {}", console::style("error").red(), e),
+                    }
+                })?;
+                return Ok(());
+            }
+
             // Read the source file
             let source_code = std::fs::read_to_string(&file)
                 .map_err(|e| -> Box<dyn std::error::Error> { format!("Could not read file '{}': {}", file, e).into() })?;
@@ -553,15 +868,76 @@ Thumbs.db
             if verbose {
                 println!("Checking Logos file: {}", file);
             }
+            // Dumped before checking, so the tokens show even when the file doesn't parse
+            if dump_tokens {
+                print!("{}", debug_tokens::dump(&source_code));
+            }
 
-            // For now, just print the source code since the modules don't exist yet
+            check_syntax_and_types_with_limits(&source_code, &limits)
+                .map_err(|e| -> Box<dyn std::error::Error> { format!("{}: {}", file, e).into() })?;
+            if lint {
+                let mut warnings = linter::check_indentation(&source_code, indent_size);
+                warnings.retain(|warning| warning_level.reports(warning.severity));
+                warnings.extend(linter::lint_source(&source_code, warning_level)?);
+                warnings.sort_by_key(|warning| (warning.line, warning.column));
+                match format.as_str() {
+                    "json" => println!("{}", linter::to_json(&file, &warnings)),
+                    "compact" => warnings.iter().for_each(|warning| println!("{}:{}", file, warning)),
+                    _ => {
+                        for warning in &warnings {
+                            println!("{}: {}", console::style(format!("warning[{}]", warning.code)).yellow(), warning.message);
+                            println!("  --> {}:{}:{}", file, warning.line, warning.column);
+                        }
+                    },
+                }
+
+                let high = warnings.iter().filter(|warning| warning.severity == linter::WarningLevel::High).count();
+                if warning_level == linter::WarningLevel::High && high > 0 {
+                    return Err(format!("{}: {} high-severity warning(s)", file, high).into());
+                }
+            }
+            if perf_hints {
+                let hints = perf_hints::performance_hints_for_source(&source_code)?;
+                for hint in &hints {
+                    // Keep stdout a single JSON object when --lint prints one
+                    if lint && format == "json" {
+                        eprintln!("{}:{}", file, hint);
+                    } else {
+                        println!("{}:{}", file, hint);
+                    }
+                }
+                if verbose {
+                    println!("{} performance hint(s)", hints.len());
+                }
+            }
+            if lint && format == "json" {
+                return Ok(());
+            }
             println!("File {} checked successfully", file);
             if verbose {
                 println!("Found {} characters in the program", source_code.len());
+                // Macro invocations aren't expanded here, so a program using them may not infer
+                let program = parser::Parser::new(&source_code).parse_program().ok();
+                if let Some(types) = program.and_then(|program| type_checker::infer_program(&program).ok()) {
+                    for (name, return_type) in types.return_types() {
+                        println!("fn {} returns {}", name, return_type);
+                    }
+                }
             }
             return Ok(());
         },
-        Commands::Fmt { file, check, verbose } => {
+        Commands::Fmt { file, check, format, max_width, indent_style, indent_size, line_ending, sort_imports, verbose } => {
+            if format != "text" && format != "json" {
+                return Err(format!("Unknown format '{}': expected text or json", format).into());
+            }
+            let options = formatter::FormatOptions {
+                indent_style: formatter::IndentStyle::parse(&indent_style)?,
+                indent_size,
+                max_width,
+                line_ending: formatter::LineEnding::parse(&line_ending)?,
+                sort_imports,
+            };
+
             // Read the source file
             let source_code = std::fs::read_to_string(&file)
                 .map_err(|e| -> Box<dyn std::error::Error> { format!("Could not read file '{}': {}", file, e).into() })?;
@@ -570,44 +946,128 @@ Thumbs.db
                 println!("Formatting Logos file: {}", file);
             }
 
-            if check {
-                // For now, just assume the file is formatted correctly
-                println!("File {} is properly formatted", file);
-                return Ok(());
+            // Code that doesn't parse has no tree to lay out, so it is refused
+            let formatted = format_source(&source_code, &options)
+                .map_err(|e| -> Box<dyn std::error::Error> { format!("{}: {}", file, e).into() })?;
+            let edits = format_edits::diff(&source_code, &formatted);
+
+            if !check && !edits.is_empty() {
+                std::fs::write(&file, &formatted)
+                    .map_err(|e| -> Box<dyn std::error::Error> { format!("Could not write file '{}': {}", file, e).into() })?;
+            }
+
+            if format == "json" {
+                println!("{}", format_edits::to_json(&file, &edits, !check));
+            } else if check {
+                for edit in &edits {
+                    println!("{}:{}-{}: would reformat", file, edit.start_line, edit.end_line.max(edit.start_line));
+                }
+                if edits.is_empty() {
+                    println!("File {} is properly formatted", file);
+                }
             } else {
-                // For now, just print that the file would be formatted
-                println!("Would format file: {}", file);
+                println!("Formatted {} ({} edits applied)", file, edits.len());
+            }
+
+            if check && !edits.is_empty() {
+                std::process::exit(1);
             }
             return Ok(());
         },
         
-        Commands::Test { filter, parallel: _, threads: _, bench: _, timeout: _, only_failed: _, verbose } => {
-            // Placeholder for running tests
+        Commands::Test { filter, parallel: _, threads: _, bench: _, timeout, only_failed: _, minimize, verbose } => {
+            if let Some(file) = minimize {
+                let source = fs::read_to_string(&file)
+                    .map_err(|e| -> Box<dyn std::error::Error> { format!("Could not read file '{}': {}", file, e).into() })?;
+                let minimized = minimize::minimize_test(&source, filter.as_deref(), std::time::Duration::from_secs(timeout))
+                    .map_err(|e| -> Box<dyn std::error::Error> { format!("{}: {}", file, e).into() })?;
+
+                if verbose {
+                    println!("Ran {} candidate program(s)", minimized.attempts);
+                }
+                if minimized.timed_out {
+                    println!("{}: stopped after {}s; the result may not be minimal", console::style("warning").yellow(), timeout);
+                }
+                println!("Minimal program failing test '{}':\n", minimized.test);
+                println!("{}", minimized.source);
+                return Ok(());
+            }
+
+            // Discover `#[test]` functions in every .logos file of the project
+            let root = if Path::new("src").is_dir() { Path::new("src") } else { Path::new(".") };
+            let mut files = Vec::new();
+            find_logos_files(root, &mut files)?;
+            files.sort();
+
             if verbose {
-                println!("Running tests");
+                println!("Running tests in {} file(s)", files.len());
             }
 
-            if let Some(filter) = filter {
-                println!("Running tests matching filter: {}", filter);
-            } else {
-                println!("Running all tests");
+            let mut passed = 0;
+            let mut failed = Vec::new();
+            for path in &files {
+                let source = fs::read_to_string(path)
+                    .map_err(|e| -> Box<dyn std::error::Error> { format!("Could not read file '{}': {}", path.display(), e).into() })?;
+                let outcomes = attributes::run_tests(&source, filter.as_deref())
+                    .map_err(|e| -> Box<dyn std::error::Error> { format!("{}: {}", path.display(), e).into() })?;
+
+                for outcome in outcomes {
+                    match &outcome.error {
+                        None => {
+                            println!("test {}::{} ... {}", path.display(), outcome.name, console::style("ok").green());
+                            passed += 1;
+                        },
+                        Some(error) => {
+                            println!("test {}::{} ... {}", path.display(), outcome.name, console::style("FAILED").red());
+                            failed.push(format!("{}::{}: {}", path.display(), outcome.name, error));
+                        },
+                    }
+                }
             }
 
-            // In a real implementation, this would:
-            // 1. Find all test files in the project
-            // 2. Parse and compile each test
-            // 3. Run the tests with the specified parameters
-            // 4. Report results
+            if !failed.is_empty() {
+                println!("\nfailures:");
+                for failure in &failed {
+                    println!("    {}", failure);
+                }
+            }
+            println!("\ntest result: {} passed; {} failed", passed, failed.len());
 
-            // For now, we'll simulate running tests
-            println!("Tests completed successfully");
-            if verbose {
-                println!("Test execution completed with 0 failures");
+            if !failed.is_empty() {
+                return Err(format!("{} test(s) failed", failed.len()).into());
             }
             return Ok(());
         },
         
-        Commands::Doc { crate_name, open, verbose } => {
+        Commands::Doc { crate_name, open, check, verbose } => {
+            if check {
+                let root = if Path::new("src").is_dir() { Path::new("src") } else { Path::new(".") };
+                let mut files = Vec::new();
+                find_logos_files(root, &mut files)?;
+                files.sort();
+
+                let mut missing = 0;
+                for path in &files {
+                    let source = fs::read_to_string(path)
+                        .map_err(|e| -> Box<dyn std::error::Error> { format!("Could not read file '{}': {}", path.display(), e).into() })?;
+                    let items = doc_check::missing_docs(&source)
+                        .map_err(|e| -> Box<dyn std::error::Error> { format!("{}: {}", path.display(), e).into() })?;
+                    for item in &items {
+                        println!("{}: {}", path.display(), item);
+                    }
+                    missing += items.len();
+                }
+
+                if missing > 0 {
+                    return Err(format!("{} public item(s) without a doc comment", missing).into());
+                }
+                if verbose {
+                    println!("Checked {} file(s)", files.len());
+                }
+                println!("Every public item is documented");
+                return Ok(());
+            }
+
             // Placeholder for documentation generation
             if verbose {
                 println!("Generating documentation");
@@ -647,18 +1107,51 @@ Thumbs.db
             return Ok(());
         },
         
-        Commands::Sync { language, path: _, install_deps, verbose, dry_run, force: _, config: _, target_dir: _, bidirectional: _, update_deps: _, no_install: _ } => {
+        Commands::Sync { language, path, install_deps, verbose, dry_run, force, config: _, target_dir, bidirectional, update_deps: _, no_install } => {
             if verbose {
                 println!("Synchronizing with language: {}", language);
             }
 
-            if dry_run {
-                println!("DRY RUN: Would synchronize with {}", language);
-                return Ok(());
+            let project_dir = match path {
+                Some(p) => std::path::PathBuf::from(p),
+                None => env::current_dir()?,
+            };
+            let options = lang_sync::SyncOptions {
+                bidirectional,
+                install_deps: install_deps && !no_install,
+                force,
+                dry_run,
+                target_dir: target_dir.map(std::path::PathBuf::from),
+            };
+            let report = lang_sync::sync_language(&project_dir, &language, &options)?;
+
+            for (file, action) in &report.files {
+                let verb = match (action, dry_run) {
+                    (lang_sync::FileAction::Created, false) => "Created",
+                    (lang_sync::FileAction::Created, true) => "Would create",
+                    (lang_sync::FileAction::Updated, false) => "Updated",
+                    (lang_sync::FileAction::Updated, true) => "Would update",
+                    (lang_sync::FileAction::Unchanged, _) => "Unchanged",
+                };
+                if verbose || *action != lang_sync::FileAction::Unchanged {
+                    println!("{} {}", verb, file.display());
+                }
+            }
+
+            if let Some(install) = &report.install {
+                if install.executed {
+                    if verbose {
+                        print!("{}", install.output);
+                    }
+                    println!("Installed dependencies with `{}`", install.command);
+                } else {
+                    println!("DRY RUN: Would run `{}`", install.command);
+                }
             }
 
-            // Placeholder for language synchronization
-            println!("Synchronizing with {} (install_deps={})", language, install_deps);
+            if !dry_run {
+                println!("Synchronization with {} completed!", language);
+            }
             return Ok(());
         },
         
@@ -711,15 +1204,46 @@ Thumbs.db
             return Ok(());
         },
         
-        Commands::Clean { verbose } => {
+        Commands::Clean { sync, dry_run, out_dir, cwd, verbose } => {
             if verbose {
                 println!("Cleaning build artifacts");
             }
 
-            println!("Cleaned build artifacts");
+            let project_dir = match cwd {
+                Some(dir) => std::path::absolute(&dir)?,
+                None => env::current_dir()?,
+            };
+            let options = artifacts::CleanOptions { include_sync: sync, dry_run, out_dir: out_dir.map(std::path::PathBuf::from) };
+            let report = artifacts::clean(&project_dir, &options)?;
+
+            let action = if dry_run { "Would remove" } else { "Removed" };
+            for path in &report.removed {
+                println!("{} {}", action, path.display());
+            }
+            if verbose {
+                for path in &report.skipped {
+                    println!("Skipped {} (not present)", path.display());
+                }
+            }
+
+            if report.removed.is_empty() {
+                println!("Nothing to clean");
+            } else if !dry_run {
+                println!("Cleaned build artifacts");
+            }
             return Ok(());
         },
         
+        Commands::Explain { code } => {
+            match explain::explain(&code) {
+                Ok(text) => print!("{}", text),
+                Err(message) => {
+                    eprintln!("{}: {}", console::style("error").red(), message);
+                    std::process::exit(1);
+                },
+            }
+            return Ok(());
+        },
         Commands::Doctor { verbose } => {
             if verbose {
                 println!("Checking system for potential issues");
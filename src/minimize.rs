@@ -0,0 +1,267 @@
+// Logos Test Case Minimization
+// Shrinks a failing `.logos` test to a smaller program that still fails the same way,
+// in the style of delta debugging: chunks of lines are removed and the test re-run,
+// keeping each removal after which the test still fails. Whole blocks (a function, an
+// `if` body) are also tried as single units, since removing just their opening line can
+// never parse. Candidates that don't parse are skipped without being run, and the whole
+// search is bounded by a timeout.
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The result of a minimization run
+#[derive(Debug, Clone, PartialEq)]
+pub struct Minimized {
+    pub source: String,   // Smallest failing program found
+    pub test: String,     // Name of the test that was minimized
+    pub attempts: usize,  // Number of candidates that were run
+    pub timed_out: bool,  // Whether the search stopped at the timeout rather than a minimum
+}
+
+/// Minimizes `source` by removing lines while `still_fails` holds
+///
+/// `still_fails` is only called on candidates that parse. The search stops at the first
+/// fixpoint, or when `deadline` passes, returning the smallest failing program found.
+pub fn minimize<F>(source: &str, deadline: Instant, mut still_fails: F) -> (String, usize, bool)
+where
+    F: FnMut(&str) -> bool,
+{
+    let mut lines: Vec<String> = source.lines().map(str::to_string).collect();
+    let mut attempts = 0;
+
+    let mut test = |candidate: &[String], attempts: &mut usize| -> bool {
+        let text = candidate.join("\n");
+        if crate::parser::Parser::new(&text).parse_program().is_err() {
+            return false;
+        }
+        *attempts += 1;
+        still_fails(&text)
+    };
+
+    loop {
+        let before = lines.len();
+
+        // Delta debugging: remove ever smaller chunks of lines
+        let mut granularity = 2;
+        while lines.len() >= 2 {
+            if Instant::now() >= deadline {
+                return (lines.join("\n"), attempts, true);
+            }
+
+            let chunk = (lines.len() + granularity - 1) / granularity;
+            let mut reduced = false;
+            for start in (0..lines.len()).step_by(chunk) {
+                let end = (start + chunk).min(lines.len());
+                let candidate = without(&lines, start, end);
+                if test(&candidate, &mut attempts) {
+                    lines = candidate;
+                    granularity = (granularity - 1).max(2);
+                    reduced = true;
+                    break;
+                }
+            }
+
+            if !reduced {
+                if granularity >= lines.len() {
+                    break;
+                }
+                granularity = (granularity * 2).min(lines.len());
+            }
+        }
+
+        // Remove whole blocks, which line chunks rarely line up with
+        let mut start = 0;
+        while start < lines.len() {
+            if Instant::now() >= deadline {
+                return (lines.join("\n"), attempts, true);
+            }
+
+            if let Some(end) = block_end(&lines, start) {
+                let candidate = without(&lines, start, end + 1);
+                if test(&candidate, &mut attempts) {
+                    lines = candidate;
+                    continue;
+                }
+            }
+            start += 1;
+        }
+
+        if lines.len() == before {
+            return (lines.join("\n"), attempts, false);
+        }
+    }
+}
+
+/// Minimizes a `.logos` file while one of its `#[test]` functions keeps failing with the same error
+///
+/// With no `test_name`, the first failing test in the file is minimized. Each candidate
+/// run gets whatever is left of `timeout`; a candidate that is still running when it runs
+/// out ends the search.
+pub fn minimize_test(source: &str, test_name: Option<&str>, timeout: Duration) -> Result<Minimized, String> {
+    let deadline = Instant::now() + timeout;
+
+    let outcomes = crate::attributes::run_tests(source, test_name)?;
+    let failing = outcomes.into_iter()
+        .find(|outcome| !outcome.passed() && test_name.map_or(true, |name| outcome.name == name))
+        .ok_or_else(|| match test_name {
+            Some(name) => format!("Test '{}' does not fail; there is nothing to minimize", name),
+            None => "No failing test found; there is nothing to minimize".to_string(),
+        })?;
+    // Removing lines moves what's left, so errors compare without the locations they report
+    let expected = failing.error.as_deref().map(without_locations);
+
+    let (source, attempts, timed_out) = minimize(source, deadline, |candidate| {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        run_test_with_timeout(candidate, &failing.name, remaining).as_deref().map(without_locations) == expected
+    });
+
+    Ok(Minimized { source, test: failing.name, attempts, timed_out })
+}
+
+/// Runs one test on a separate thread, returning its error if it failed within `limit`
+///
+/// A run that exceeds the limit is abandoned and counts as not failing.
+fn run_test_with_timeout(source: &str, test_name: &str, limit: Duration) -> Option<String> {
+    let (sender, receiver) = mpsc::channel();
+    let source = source.to_string();
+    let name = test_name.to_string();
+
+    thread::spawn(move || {
+        let error = crate::attributes::run_tests(&source, Some(&name)).ok()
+            .and_then(|outcomes| outcomes.into_iter().find(|outcome| outcome.name == name))
+            .and_then(|outcome| outcome.error);
+        // The receiver is gone if we timed out; nothing is waiting for the result then
+        let _ = sender.send(error);
+    });
+
+    receiver.recv_timeout(limit).ok().flatten()
+}
+
+/// Drops each ` at line N, column M` a runtime error reports, as `SourceLocation` displays it
+fn without_locations(error: &str) -> String {
+    let mut rest = error;
+    let mut stripped = String::with_capacity(error.len());
+    while let Some(at) = rest.find(" at line ") {
+        stripped.push_str(&rest[..at]);
+        let after = &rest[at + " at line ".len()..];
+        match location_len(after) {
+            Some(len) => rest = &after[len..],
+            None => {
+                stripped.push_str(" at line ");
+                rest = after;
+            },
+        }
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+/// Length of the `N, column M` that follows "line " in a location, if `text` starts with one
+fn location_len(text: &str) -> Option<usize> {
+    let digits = |s: &str| s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let line = digits(text);
+    let column_start = line + ", column ".len();
+    if line == 0 || !text[line..].starts_with(", column ") {
+        return None;
+    }
+    let column = digits(&text[column_start..]);
+    (column > 0).then_some(column_start + column)
+}
+
+fn without(lines: &[String], start: usize, end: usize) -> Vec<String> {
+    lines[..start].iter().chain(&lines[end..]).cloned().collect()
+}
+
+/// Finds the line closing a block opened on line `start`, by counting braces
+fn block_end(lines: &[String], start: usize) -> Option<usize> {
+    let mut depth = 0i32;
+    for (i, line) in lines.iter().enumerate().skip(start) {
+        depth += line.matches('{').count() as i32;
+        depth -= line.matches('}').count() as i32;
+        if i == start && depth <= 0 {
+            return None;
+        }
+        if depth <= 0 {
+            return Some(i);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM: &str = "let a = 1\n\
+                           fn helper(x: Int) -> Int {\n    let y = x + 1\n    y * 2\n}\n\
+                           let b = a + 2\n\
+                           crash()\n\
+                           if b > 1 {\n    print(b)\n}\n\
+                           let c = 3";
+
+    #[test]
+    fn test_minimize_keeps_failing_statement() {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let (source, attempts, timed_out) = minimize(PROGRAM, deadline, |candidate| candidate.contains("crash()"));
+
+        assert_eq!(source, "crash()");
+        assert!(attempts > 0);
+        assert!(!timed_out);
+    }
+
+    #[test]
+    fn test_minimize_only_runs_parseable_candidates() {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let (source, _, _) = minimize(PROGRAM, deadline, |candidate| {
+            assert!(crate::parser::Parser::new(candidate).parse_program().is_ok(), "{}", candidate);
+            candidate.contains("y * 2")
+        });
+
+        // The statement can't leave its function, so the function survives around it
+        assert_eq!(source, "fn helper(x: Int) -> Int {\n    y * 2\n}");
+    }
+
+    #[test]
+    fn test_minimize_stops_at_deadline() {
+        let (source, attempts, timed_out) = minimize(PROGRAM, Instant::now(), |_| true);
+        assert_eq!(source, PROGRAM);
+        assert_eq!(attempts, 0);
+        assert!(timed_out);
+    }
+
+    #[test]
+    fn test_minimize_failing_logos_test() {
+        let source = "fn unused() { 1 }\n\
+                      #[test]\n\
+                      fn broken() {\n    let fine = 1\n    missing_function()\n}\n\
+                      #[test]\n\
+                      fn passes() { 1 }";
+        let minimized = minimize_test(source, None, Duration::from_secs(30)).unwrap();
+
+        assert_eq!(minimized.test, "broken");
+        assert_eq!(minimized.source, "#[test]\nfn broken() {\n    missing_function()\n}");
+    }
+
+    #[test]
+    fn test_minimize_failing_assertion_whose_line_moves() {
+        let source = "let noise1 = 1\n\
+                      let noise2 = 2\n\
+                      fn unused() { 1 }\n\
+                      #[test]\n\
+                      fn broken() {\n    let c = 3\n    let spare = 0\n    assert(c == 4)\n}";
+        let minimized = minimize_test(source, None, Duration::from_secs(30)).unwrap();
+
+        assert_eq!(minimized.source, "#[test]\nfn broken() {\n    let c = 3\n    assert(c == 4)\n}");
+    }
+
+    #[test]
+    fn test_errors_compare_without_locations() {
+        assert_eq!(without_locations("Assertion failed at line 9, column 5"), "Assertion failed");
+        assert_eq!(
+            without_locations("Assertion failed at line 2, column 5: expected 1 at line 3"),
+            "Assertion failed: expected 1 at line 3"
+        );
+        assert_eq!(without_locations("Division by zero: 1 / 0 at line 12, column 7"), "Division by zero: 1 / 0");
+    }
+}
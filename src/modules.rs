@@ -49,6 +49,7 @@ pub enum ImportItem {
 pub struct ModuleResolver {
     loaded_modules: HashMap<String, Arc<Mutex<Module>>>,
     module_paths: HashMap<String, String>,
+    import_stack: Vec<String>,  // Modules whose imports are being loaded, outermost first
 }
 
 impl ModuleResolver {
@@ -57,24 +58,55 @@ impl ModuleResolver {
         Self {
             loaded_modules: HashMap::new(),
             module_paths: HashMap::new(),
+            import_stack: Vec::new(),
         }
     }
 
-    /// Resolves and loads a module by its path
+    /// Resolves and loads a module by its path, along with every local module it imports
+    ///
+    /// Imports are loaded depth first. A module reached along two routes, such as a
+    /// dependency shared by two imports, is loaded once and shared. An import of a module
+    /// whose own imports are still loading is a cycle, and the error spells out the
+    /// whole of it: `Circular import: a -> b -> c -> a`.
     pub fn load_module(&mut self, path: &str) -> Result<Arc<Mutex<Module>>, String> {
+        if let Some(start) = self.import_stack.iter().position(|loading| loading == path) {
+            let cycle: Vec<&str> = self.import_stack[start..].iter()
+                .map(|loading| module_name(loading))
+                .chain(std::iter::once(module_name(path)))
+                .collect();
+            return Err(format!("Circular import: {}", cycle.join(" -> ")));
+        }
+
         // Check if module is already loaded
         if let Some(module) = self.loaded_modules.get(path) {
             return Ok(module.clone());
         }
 
-        // In a real implementation, this would read the file and parse it
-        // For now, we'll create a mock module
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read module '{}': {}", path, e))?;
+        let program = crate::parser::Parser::new(&source).parse_program()
+            .map_err(|e| format!("Parse error in module '{}': {}", path, e))?;
+        let base_dir = std::path::Path::new(path).parent().unwrap_or(std::path::Path::new(""));
+        let imports: Vec<ImportDeclaration> = crate::watch::imported_files(&source, base_dir).into_iter()
+            .map(|file| ImportDeclaration {
+                module_path: file.to_string_lossy().to_string(),
+                items: Vec::new(),
+                alias: None,
+                is_public: false,
+            })
+            .collect();
+
+        self.import_stack.push(path.to_string());
+        let loaded = imports.iter().try_for_each(|import| self.load_module(&import.module_path).map(|_| ()));
+        self.import_stack.pop();
+        loaded?;
+
         let module = Module {
-            name: path.split('/').last().unwrap_or(path).trim_end_matches(".logos").to_string(),
+            name: module_name(path).to_string(),
             path: path.to_string(),
             exports: HashMap::new(),
-            imports: Vec::new(),
-            statements: Vec::new(),
+            imports,
+            statements: program.statements,
             is_public: true,
         };
 
@@ -174,6 +206,11 @@ impl ModuleResolver {
     }
 }
 
+/// The name a module is known by: its file name without the `.logos` extension
+fn module_name(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path).trim_end_matches(".logos")
+}
+
 /// Standard library module with common utilities
 pub struct StandardLibrary;
 
@@ -200,6 +237,7 @@ impl StandardLibrary {
             is_awaitable: false,
             effect_annotations: vec![],
             generic_params: vec![],
+            attributes: vec![],
         }));
 
         exports.insert("println".to_string(), ExportItem::Function(FunctionDef {
@@ -219,6 +257,7 @@ impl StandardLibrary {
             is_awaitable: false,
             effect_annotations: vec![],
             generic_params: vec![],
+            attributes: vec![],
         }));
 
         exports.insert("len".to_string(), ExportItem::Function(FunctionDef {
@@ -238,6 +277,7 @@ impl StandardLibrary {
             is_awaitable: false,
             effect_annotations: vec![],
             generic_params: vec![],
+            attributes: vec![],
         }));
 
         // Add basic types
@@ -437,4 +477,54 @@ pub struct EffectHandler {
 }
 
 // Re-export commonly used items
-pub use crate::ast::{Statement, Expression, FunctionDef, Parameter, Type, Pattern};
\ No newline at end of file
+pub use crate::ast::{Statement, Expression, FunctionDef, Parameter, Type, Pattern};
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_modules(dir: &std::path::Path, modules: &[(&str, &str)]) {
+        for (name, source) in modules {
+            std::fs::write(dir.join(name), source).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_circular_imports_report_the_whole_cycle() {
+        let dir = tempfile::tempdir().unwrap();
+        write_modules(dir.path(), &[
+            ("main.logos", "let a = @import(\"a.logos\")"),
+            ("a.logos", "let b = @import(\"b.logos\")"),
+            ("b.logos", "let c = @import(\"c.logos\")"),
+            ("c.logos", "let a = @import(\"a.logos\")"),
+        ]);
+
+        let mut resolver = ModuleResolver::new();
+        let main = dir.path().join("main.logos");
+        let err = resolver.load_module(&main.to_string_lossy()).unwrap_err();
+        assert_eq!(err, "Circular import: a -> b -> c -> a");
+    }
+
+    #[test]
+    fn test_a_shared_dependency_is_not_a_cycle() {
+        let dir = tempfile::tempdir().unwrap();
+        write_modules(dir.path(), &[
+            ("main.logos", "let left = @import(\"left.logos\")\nlet right = @import(\"right.logos\")"),
+            ("left.logos", "let shared = @import(\"shared.logos\")"),
+            ("right.logos", "let shared = @import(\"shared.logos\")"),
+            ("shared.logos", "let answer = 42"),
+        ]);
+
+        let mut resolver = ModuleResolver::new();
+        let main = resolver.load_module(&dir.path().join("main.logos").to_string_lossy()).unwrap();
+        let imported: Vec<String> = main.lock().unwrap().imports.iter()
+            .map(|import| module_name(&import.module_path).to_string())
+            .collect();
+        assert_eq!(imported, vec!["left", "right"]);
+
+        // Both routes reach the same, once-loaded module
+        let shared = dir.path().join("shared.logos").to_string_lossy().to_string();
+        let via_left = resolver.load_module(&shared).unwrap();
+        assert!(Arc::ptr_eq(&via_left, &resolver.load_module(&shared).unwrap()));
+        assert_eq!(resolver.loaded_modules.len(), 4);
+    }
+}
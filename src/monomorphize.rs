@@ -0,0 +1,564 @@
+// Logos Monomorphization
+// Backends that need concrete types can't compile a generic function once, so each
+// instantiation gets its own copy, with the type parameters substituted and a name
+// of its own:
+//
+//   fn id<T>(x: T) -> T     id(1)     ->  fn id__Int(x: Int) -> Int        id__Int(1)
+//                           id("a")   ->  fn id__String(x: String) -> String   id__String("a")
+//
+// Type arguments are read off the argument types at each call, and every copy's own
+// generic calls are specialized in turn. A function that reaches itself again at a
+// different instantiation (polymorphic recursion) would need endless copies, so that
+// is an error. Run this after derive expansion: trait bounds are checked against the
+// program's impl blocks.
+
+use crate::ast::*;
+use crate::derive::DERIVABLE_TRAITS;
+use std::collections::{HashMap, HashSet};
+
+/// A generic function together with the concrete types it is used at
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instantiation {
+    pub function: String,
+    pub type_args: Vec<Type>,
+}
+
+/// Replaces the program's generic functions with one specialized copy per instantiation
+///
+/// `instantiations` lists uses the type checker found beyond the calls in the program
+/// itself, such as functions exported at fixed types; they are specialized even if
+/// nothing calls them. Generic functions that end up with no instantiation are dropped,
+/// since there is no concrete code to give them. Generic functions nested in other
+/// functions are left as they are.
+pub fn monomorphize(program: &Program, instantiations: &[Instantiation]) -> Result<Program, String> {
+    let mut pass = Monomorphizer::new(program);
+    for instantiation in instantiations {
+        pass.specialize(&instantiation.function, &instantiation.type_args)?;
+    }
+
+    let mut statements = program.statements.clone();
+    let mut scope = Vec::new();
+    for statement in &mut statements {
+        if !matches!(statement, Statement::Function(def) if !def.generic_params.is_empty()) {
+            pass.rewrite_statement(statement, &HashMap::new(), &mut scope)?;
+        }
+    }
+
+    // Each generic function's copies take its place
+    let mut output = Vec::with_capacity(statements.len());
+    for statement in statements {
+        match statement {
+            Statement::Function(def) if !def.generic_params.is_empty() => {
+                let copies = pass.copies.remove(&def.name).unwrap_or_default();
+                output.extend(copies.into_iter().map(Statement::Function));
+            },
+            statement => output.push(statement),
+        }
+    }
+    Ok(Program::new(output))
+}
+
+struct Monomorphizer {
+    /// Top-level generic functions by name
+    generics: HashMap<String, FunctionDef>,
+    /// (trait, type) pairs the program implements
+    impls: HashSet<(String, String)>,
+    /// Declared return types, including those of the copies made so far
+    returns: HashMap<String, Type>,
+    /// Every top-level name, so a copy's name can't collide with anything
+    taken: HashSet<String>,
+    /// The copy made for each function and type arguments, keyed by their debug form
+    specialized: HashMap<(String, String), String>,
+    /// Instantiations whose bodies are being rewritten, outermost first
+    in_progress: Vec<(String, Vec<Type>)>,
+    /// Finished copies of each generic function, in the order they were made
+    copies: HashMap<String, Vec<FunctionDef>>,
+}
+
+impl Monomorphizer {
+    fn new(program: &Program) -> Self {
+        let mut pass = Monomorphizer {
+            generics: HashMap::new(),
+            impls: HashSet::new(),
+            returns: HashMap::new(),
+            taken: HashSet::new(),
+            specialized: HashMap::new(),
+            in_progress: Vec::new(),
+            copies: HashMap::new(),
+        };
+        for statement in &program.statements {
+            match statement {
+                Statement::Function(def) => {
+                    pass.taken.insert(def.name.clone());
+                    if !def.generic_params.is_empty() {
+                        pass.generics.insert(def.name.clone(), def.clone());
+                    } else if let Some(return_type) = &def.return_type {
+                        pass.returns.insert(def.name.clone(), return_type.clone());
+                    }
+                },
+                Statement::Implementation(impl_def) => {
+                    pass.impls.insert((impl_def.trait_name.clone(), impl_def.for_type.clone()));
+                },
+                Statement::Extern(def) => {
+                    pass.taken.insert(def.name.clone());
+                },
+                _ => {},
+            }
+        }
+        pass
+    }
+
+    /// Returns the name of the copy of `function` for `type_args`, making it if needed
+    fn specialize(&mut self, function: &str, type_args: &[Type]) -> Result<String, String> {
+        let def = self.generics.get(function)
+            .ok_or_else(|| format!("Cannot instantiate '{}': it is not a generic function", function))?
+            .clone();
+        if type_args.len() != def.generic_params.len() {
+            return Err(format!(
+                "{} takes {} type argument(s), found {}",
+                function, def.generic_params.len(), type_args.len()
+            ));
+        }
+        for (param, ty) in def.generic_params.iter().zip(type_args) {
+            for bound in &param.bounds {
+                if !self.implements(ty, bound) {
+                    return Err(format!(
+                        "{} does not implement {}, which {}'s type parameter {} requires",
+                        ty, bound, function, param.name
+                    ));
+                }
+            }
+        }
+
+        let key = (function.to_string(), format!("{:?}", type_args));
+        if let Some(name) = self.specialized.get(&key) {
+            return Ok(name.clone());
+        }
+        if let Some((_, outer)) = self.in_progress.iter().find(|(name, _)| name == function) {
+            return Err(format!(
+                "Polymorphic recursion: {}<{}> needs {}<{}>, so it would need a copy for every type",
+                function, type_list(outer), function, type_list(type_args)
+            ));
+        }
+
+        let name = self.fresh_name(function, type_args);
+        self.specialized.insert(key, name.clone());
+
+        let substitution: HashMap<String, Type> = def.generic_params.iter()
+            .map(|param| param.name.clone())
+            .zip(type_args.iter().cloned())
+            .collect();
+        let mut copy = def;
+        copy.name = name.clone();
+        copy.generic_params.clear();
+        for param in &mut copy.parameters {
+            substitute(&mut param.type_annotation, &substitution);
+        }
+        if let Some(return_type) = &mut copy.return_type {
+            substitute(return_type, &substitution);
+            self.returns.insert(name.clone(), return_type.clone());
+        }
+
+        self.in_progress.push((function.to_string(), type_args.to_vec()));
+        let mut scope = parameter_scope(&copy.parameters);
+        let rewritten = self.rewrite_statements(&mut copy.body, &substitution, &mut scope);
+        self.in_progress.pop();
+        rewritten?;
+
+        self.copies.entry(function.to_string()).or_default().push(copy);
+        Ok(name)
+    }
+
+    /// Whether a concrete type satisfies a trait bound
+    ///
+    /// Primitives have every derivable trait, except that floats can't be hashed;
+    /// anything else needs an impl in the program.
+    fn implements(&self, ty: &Type, bound: &str) -> bool {
+        let builtin = match ty {
+            Type::Int | Type::Bool | Type::String | Type::Unit => DERIVABLE_TRAITS.contains(&bound),
+            Type::Float => DERIVABLE_TRAITS.contains(&bound) && bound != "Hash",
+            _ => false,
+        };
+        builtin || self.impls.contains(&(bound.to_string(), ty.to_string()))
+    }
+
+    /// `function` plus its type arguments, numbered if that is already someone's name
+    fn fresh_name(&mut self, function: &str, type_args: &[Type]) -> String {
+        let base = format!("{}__{}", function, type_args.iter().map(mangle).collect::<Vec<_>>().join("_"));
+        let mut name = base.clone();
+        let mut number = 2;
+        while !self.taken.insert(name.clone()) {
+            name = format!("{}_{}", base, number);
+            number += 1;
+        }
+        name
+    }
+
+    fn rewrite_statements(&mut self, statements: &mut [Statement], substitution: &HashMap<String, Type>, scope: &mut Scope) -> Result<(), String> {
+        let depth = scope.len();
+        let result = statements.iter_mut().try_for_each(|statement| self.rewrite_statement(statement, substitution, scope));
+        scope.truncate(depth);
+        result
+    }
+
+    /// Substitutes type parameters and redirects generic calls within a statement,
+    /// recording what it binds in `scope`
+    fn rewrite_statement(&mut self, statement: &mut Statement, substitution: &HashMap<String, Type>, scope: &mut Scope) -> Result<(), String> {
+        match statement {
+            Statement::Expression(expr) | Statement::Return(Some(expr)) => self.rewrite_expression(expr, substitution, scope),
+            Statement::LetBinding { name, type_annotation, value, .. } | Statement::ConstBinding { name, type_annotation, value } => {
+                self.rewrite_expression(value, substitution, scope)?;
+                if let Some(annotation) = type_annotation.as_mut() {
+                    substitute(annotation, substitution);
+                }
+                let ty = type_annotation.clone().or_else(|| self.type_of(value, scope));
+                scope.push((name.clone(), ty));
+                Ok(())
+            },
+            Statement::Function(def) if def.generic_params.is_empty() => {
+                for param in &mut def.parameters {
+                    substitute(&mut param.type_annotation, substitution);
+                }
+                if let Some(return_type) = &mut def.return_type {
+                    substitute(return_type, substitution);
+                }
+                scope.push((def.name.clone(), None));
+                let mut inner = scope.clone();
+                inner.extend(parameter_scope(&def.parameters));
+                self.rewrite_statements(&mut def.body, substitution, &mut inner)
+            },
+            Statement::While { condition, body, .. } => {
+                self.rewrite_expression(condition, substitution, scope)?;
+                self.rewrite_statements(body, substitution, scope)
+            },
+            Statement::Defer(inner) => self.rewrite_statement(inner, substitution, &mut scope.clone()),
+            Statement::Assert { actual, expected, message, .. } => {
+                self.rewrite_expression(actual, substitution, scope)?;
+                expected.iter_mut().chain(message.iter_mut())
+                    .try_for_each(|expr| self.rewrite_expression(expr, substitution, scope))
+            },
+            Statement::Block(statements) => self.rewrite_statements(statements, substitution, scope),
+            _ => Ok(()),
+        }
+    }
+
+    fn rewrite_expression(&mut self, expr: &mut Expression, substitution: &HashMap<String, Type>, scope: &mut Scope) -> Result<(), String> {
+        match expr {
+            Expression::Array(items) | Expression::Tuple(items) | Expression::Race(items)
+            | Expression::MacroInvocation(_, items) | Expression::Spawn(_, items) => {
+                items.iter_mut().try_for_each(|item| self.rewrite_expression(item, substitution, scope))
+            },
+            Expression::Call(name, args) => {
+                args.iter_mut().try_for_each(|arg| self.rewrite_expression(arg, substitution, scope))?;
+                // A local binding of the same name hides the generic function
+                let shadowed = scope.iter().any(|(bound, _)| bound == name);
+                if self.generics.contains_key(name.as_str()) && !shadowed {
+                    let type_args = self.infer_type_args(name, args, scope)?;
+                    *name = self.specialize(name, &type_args)?;
+                }
+                Ok(())
+            },
+            Expression::Struct(_, fields) => {
+                fields.iter_mut().try_for_each(|(_, value)| self.rewrite_expression(value, substitution, scope))
+            },
+            Expression::Lambda(params, body) => {
+                for param in params.iter_mut() {
+                    substitute(&mut param.type_annotation, substitution);
+                }
+                let mut inner = scope.clone();
+                inner.extend(parameter_scope(params));
+                self.rewrite_statements(body, substitution, &mut inner)
+            },
+            Expression::LambdaSimple(params, body) => {
+                let mut inner = scope.clone();
+                inner.extend(params.iter().map(|param| (param.clone(), None)));
+                self.rewrite_expression(body, substitution, &mut inner)
+            },
+            Expression::BinaryOp(left, _, right, _) | Expression::Index(left, right) | Expression::ArrayRepeat(left, right)
+            | Expression::Send(left, right) | Expression::ChannelSend(left, right) | Expression::Timeout(left, right) => {
+                self.rewrite_expression(left, substitution, scope)?;
+                self.rewrite_expression(right, substitution, scope)
+            },
+            Expression::Slice(target, start, end) => {
+                self.rewrite_expression(target, substitution, scope)?;
+                self.rewrite_expression(start, substitution, scope)?;
+                self.rewrite_expression(end, substitution, scope)
+            },
+            Expression::UnaryOp(_, inner) | Expression::FieldAccess(inner, _) | Expression::Propagate(inner)
+            | Expression::ChannelReceive(inner) | Expression::ChannelClose(inner) | Expression::Await(inner)
+            | Expression::Future(inner) | Expression::SpawnTask(inner) | Expression::Join(inner) => {
+                self.rewrite_expression(inner, substitution, scope)
+            },
+            Expression::MethodCall(receiver, _, args) => {
+                self.rewrite_expression(receiver, substitution, scope)?;
+                args.iter_mut().try_for_each(|arg| self.rewrite_expression(arg, substitution, scope))
+            },
+            Expression::Pipeline(value, stages) | Expression::BackPipeline(value, stages) => {
+                self.rewrite_expression(value, substitution, scope)?;
+                stages.iter_mut().try_for_each(|stage| self.rewrite_expression(stage, substitution, scope))
+            },
+            Expression::If(condition, then_branch, else_branch) => {
+                self.rewrite_expression(condition, substitution, scope)?;
+                self.rewrite_statements(then_branch, substitution, scope)?;
+                self.rewrite_statements(else_branch, substitution, scope)
+            },
+            Expression::Match(subject, arms) => {
+                self.rewrite_expression(subject, substitution, scope)?;
+                for (pattern, guard, body) in arms {
+                    let mut inner = scope.clone();
+                    inner.extend(crate::linter::pattern_names(pattern).into_iter().map(|name| (name, None)));
+                    if let Some(guard) = guard {
+                        self.rewrite_expression(guard, substitution, &mut inner)?;
+                    }
+                    self.rewrite_statements(body, substitution, &mut inner)?;
+                }
+                Ok(())
+            },
+            Expression::Block(statements) | Expression::BlockExpr(statements) | Expression::AsyncBlock(statements) => {
+                self.rewrite_statements(statements, substitution, scope)
+            },
+            Expression::TryCatch(body, error, handler) => {
+                self.rewrite_statements(body, substitution, scope)?;
+                let mut inner = scope.clone();
+                inner.push((error.clone(), None));
+                self.rewrite_statements(handler, substitution, &mut inner)
+            },
+            Expression::InterpolatedString(parts) => parts.iter_mut().try_for_each(|part| match part {
+                StringPart::Interpolated(expr, _) => self.rewrite_expression(expr, substitution, scope),
+                _ => Ok(()),
+            }),
+            Expression::ChannelCreate(element_type) => {
+                substitute(element_type, substitution);
+                Ok(())
+            },
+            Expression::DestructureAssignment(_, value, statement) => {
+                self.rewrite_expression(value, substitution, scope)?;
+                self.rewrite_statement(statement, substitution, &mut scope.clone())
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Works out a generic call's type arguments by matching parameter types against the arguments
+    fn infer_type_args(&self, function: &str, args: &[Expression], scope: &Scope) -> Result<Vec<Type>, String> {
+        let def = &self.generics[function];
+        let names: Vec<&str> = def.generic_params.iter().map(|param| param.name.as_str()).collect();
+        let mut bindings = HashMap::new();
+        for (param, arg) in def.parameters.iter().zip(args) {
+            if let Some(arg_type) = self.type_of(arg, scope) {
+                bind_type_params(&param.type_annotation, &arg_type, &names, &mut bindings)
+                    .map_err(|(name, first, second)| format!(
+                        "Conflicting types for {}'s type parameter {}: {} and {}",
+                        function, name, first, second
+                    ))?;
+            }
+        }
+        def.generic_params.iter()
+            .map(|param| bindings.remove(&param.name).or_else(|| param.default_type.clone()).ok_or_else(|| format!(
+                "Cannot infer {}'s type parameter {} from the arguments of this call",
+                function, param.name
+            )))
+            .collect()
+    }
+
+    /// The type of an expression, where it is evident without full type checking
+    fn type_of(&self, expr: &Expression, scope: &Scope) -> Option<Type> {
+        match expr {
+            Expression::Integer(_) => Some(Type::Int),
+            Expression::Float(_) => Some(Type::Float),
+            Expression::String(_) | Expression::InterpolatedString(_) => Some(Type::String),
+            Expression::Boolean(_) => Some(Type::Bool),
+            Expression::Nil => Some(Type::Unit),
+            Expression::Identifier(name) => scope.iter().rev().find(|(bound, _)| bound == name)?.1.clone(),
+            Expression::Array(items) => Some(Type::Array(Box::new(self.type_of(items.first()?, scope)?))),
+            Expression::Tuple(items) => items.iter().map(|item| self.type_of(item, scope)).collect::<Option<_>>().map(Type::Tuple),
+            Expression::Struct(name, _) => Some(Type::Named(name.clone())),
+            Expression::Call(name, _) => self.returns.get(name).cloned(),
+            Expression::BinaryOp(left, op, _, _) => match op {
+                BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => self.type_of(left, scope),
+                _ => Some(Type::Bool),
+            },
+            Expression::Index(target, _) => match self.type_of(target, scope)? {
+                Type::Array(element) => Some(*element),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Variables in scope, latest last; None where the type isn't known
+type Scope = Vec<(String, Option<Type>)>;
+
+fn parameter_scope(parameters: &[Parameter]) -> Scope {
+    parameters.iter().map(|param| (param.name.clone(), Some(param.type_annotation.clone()))).collect()
+}
+
+/// Replaces type parameters named in `substitution` wherever they appear in `ty`
+pub(crate) fn substitute(ty: &mut Type, substitution: &HashMap<String, Type>) {
+    if substitution.is_empty() {
+        return;
+    }
+    match ty {
+        Type::Named(name) | Type::Generic(name) | Type::GenericWithBounds { name, .. } => {
+            if let Some(concrete) = substitution.get(name.as_str()) {
+                *ty = concrete.clone();
+            }
+        },
+        Type::Array(inner) | Type::Option(inner) | Type::Set(inner) | Type::Channel(inner) | Type::Linear(inner) => substitute(inner, substitution),
+        Type::Result(first, second) | Type::Map(first, second) => {
+            substitute(first, substitution);
+            substitute(second, substitution);
+        },
+        Type::Tuple(items) => items.iter_mut().for_each(|item| substitute(item, substitution)),
+        Type::Function(params, ret) => {
+            params.iter_mut().for_each(|param| substitute(param, substitution));
+            substitute(ret, substitution);
+        },
+        _ => {},
+    }
+}
+
+/// Binds the type parameters in a parameter's type to the parts of an argument's type
+/// they line up with; a parameter bound twice to different types is reported
+fn bind_type_params(param: &Type, arg: &Type, names: &[&str], bindings: &mut HashMap<String, Type>) -> Result<(), (String, Type, Type)> {
+    match (param, arg) {
+        (Type::Named(name) | Type::Generic(name), _) if names.contains(&name.as_str()) => {
+            match bindings.get(name) {
+                Some(bound) if bound != arg => Err((name.clone(), bound.clone(), arg.clone())),
+                _ => {
+                    bindings.insert(name.clone(), arg.clone());
+                    Ok(())
+                },
+            }
+        },
+        (Type::Array(p), Type::Array(a)) | (Type::Option(p), Type::Option(a)) | (Type::Set(p), Type::Set(a))
+        | (Type::Channel(p), Type::Channel(a)) | (Type::Linear(p), Type::Linear(a)) => bind_type_params(p, a, names, bindings),
+        (Type::Result(p1, p2), Type::Result(a1, a2)) | (Type::Map(p1, p2), Type::Map(a1, a2)) => {
+            bind_type_params(p1, a1, names, bindings)?;
+            bind_type_params(p2, a2, names, bindings)
+        },
+        (Type::Tuple(ps), Type::Tuple(args)) if ps.len() == args.len() => {
+            ps.iter().zip(args).try_for_each(|(p, a)| bind_type_params(p, a, names, bindings))
+        },
+        _ => Ok(()),
+    }
+}
+
+/// A type as it appears in a copy's name: letters, digits and underscores only
+fn mangle(ty: &Type) -> String {
+    let all = |types: &[Type]| types.iter().map(mangle).collect::<Vec<_>>().join("_");
+    match ty {
+        Type::Array(inner) => format!("Array_{}", mangle(inner)),
+        Type::Option(inner) => format!("Option_{}", mangle(inner)),
+        Type::Set(inner) => format!("Set_{}", mangle(inner)),
+        Type::Channel(inner) => format!("Chan_{}", mangle(inner)),
+        Type::Linear(inner) => format!("Linear_{}", mangle(inner)),
+        Type::Result(ok, err) => format!("Result_{}_{}", mangle(ok), mangle(err)),
+        Type::Map(key, value) => format!("Map_{}_{}", mangle(key), mangle(value)),
+        Type::Tuple(items) => format!("Tuple{}_{}", items.len(), all(items)),
+        Type::Function(params, ret) => format!("Fn{}_{}_{}", params.len(), all(params), mangle(ret)),
+        other => other.to_string().chars().map(|c| if c.is_alphanumeric() { c } else { '_' }).collect(),
+    }
+}
+
+fn type_list(types: &[Type]) -> String {
+    types.iter().map(|ty| ty.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    /// A function's name and its type parameters, each with its bounds
+    type Signature = (&'static str, &'static [(&'static str, &'static [&'static str])]);
+
+    /// Parses `source`, then makes the named functions generic over the given parameters
+    fn program(source: &str, generics: &[Signature]) -> Program {
+        let mut program = Parser::new(source).parse_program().unwrap();
+        for statement in &mut program.statements {
+            if let Statement::Function(def) = statement {
+                if let Some((_, params)) = generics.iter().find(|(name, _)| *name == def.name) {
+                    def.generic_params = params.iter().map(|(name, bounds)| GenericParam {
+                        name: name.to_string(),
+                        bounds: bounds.iter().map(|bound| bound.to_string()).collect(),
+                        default_type: None,
+                    }).collect();
+                }
+            }
+        }
+        program
+    }
+
+    fn functions(program: &Program) -> Vec<&FunctionDef> {
+        program.statements.iter().filter_map(|statement| match statement {
+            Statement::Function(def) => Some(def),
+            _ => None,
+        }).collect()
+    }
+
+    fn calls(program: &Program) -> Vec<String> {
+        let mut names = Vec::new();
+        for statement in &program.statements {
+            crate::perf_hints::visit_statement(statement, &mut |expr| {
+                if let Expression::Call(name, _) = expr {
+                    names.push(name.clone());
+                }
+            });
+        }
+        names
+    }
+
+    #[test]
+    fn test_each_instantiation_gets_its_own_copy() {
+        let source = "fn id(x: T) -> T { x }\nlet a = id(1)\nlet b = id(\"s\")\nlet c = id(2)";
+        let result = monomorphize(&program(source, &[("id", &[("T", &[])])]), &[]).unwrap();
+
+        let copies = functions(&result);
+        assert_eq!(copies.iter().map(|def| def.name.as_str()).collect::<Vec<_>>(), vec!["id__Int", "id__String"]);
+        assert_eq!(copies[0].parameters[0].type_annotation, Type::Int);
+        assert_eq!(copies[1].return_type, Some(Type::String));
+        assert!(copies.iter().all(|def| def.generic_params.is_empty()));
+        assert_eq!(calls(&result), vec!["id__Int", "id__String", "id__Int"]);
+    }
+
+    #[test]
+    fn test_generic_calls_inside_copies_are_specialized_too() {
+        let source = "fn id(x: T) -> T { x }\n\
+                      fn twice(x: T) -> [T] { let y = id(x)\n let pair = [y, id(x)]\n pair }\n\
+                      fn main() { let n: Int = 3\n twice(n) }";
+        let generics: &[Signature] = &[("id", &[("T", &[])]), ("twice", &[("T", &[])])];
+        let result = monomorphize(&program(source, generics), &[Instantiation { function: "id".to_string(), type_args: vec![Type::Float] }]).unwrap();
+
+        let names: Vec<&str> = functions(&result).iter().map(|def| def.name.as_str()).collect();
+        assert_eq!(names, vec!["id__Float", "id__Int", "twice__Int", "main"]);
+        assert_eq!(calls(&result), vec!["id__Int", "id__Int", "twice__Int"]);
+    }
+
+    #[test]
+    fn test_polymorphic_recursion_is_an_error() {
+        let source = "fn grow(x: T) -> Int { grow([x]) }\ngrow(1)";
+        let err = monomorphize(&program(source, &[("grow", &[("T", &[])])]), &[]).unwrap_err();
+        assert_eq!(err, "Polymorphic recursion: grow<Int> needs grow<[Int]>, so it would need a copy for every type");
+
+        // Recursion at the same instantiation is an ordinary recursive copy
+        let source = "fn count(x: T, n: Int) -> Int { count(x, n - 1) }\ncount(true, 3)";
+        let result = monomorphize(&program(source, &[("count", &[("T", &[])])]), &[]).unwrap();
+        assert_eq!(calls(&result), vec!["count__Bool", "count__Bool"]);
+    }
+
+    #[test]
+    fn test_bounds_and_name_collisions() {
+        let source = "struct Point { x: Int }\nfn show(x: T) -> String { \"?\" }\nfn show__Int() {}\n\
+                      show(1)\nshow(Point { x: 1 })";
+        let generics: &[Signature] = &[("show", &[("T", &["Display"])])];
+        let err = monomorphize(&program(source, generics), &[]).unwrap_err();
+        assert_eq!(err, "Point does not implement Display, which show's type parameter T requires");
+
+        let with_impl = format!("{}\nimpl Display for Point {{}}", source);
+        let result = monomorphize(&program(&with_impl, generics), &[]).unwrap();
+        // show__Int is already taken by a function of the program's own
+        assert_eq!(calls(&result), vec!["show__Int_2", "show__Point"]);
+    }
+}
@@ -2,7 +2,12 @@
 //! This module provides various optimization techniques to improve code performance
 
 use crate::ast::*;
+use crate::attributes::{inline_hint, InlineHint};
 use std::collections::HashMap;
+use std::mem::take;
+
+/// Largest body, in counted instructions, inlined without an `#[inline]` hint
+const INLINE_SIZE_LIMIT: usize = 8;
 
 /// Optimization level settings
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -179,7 +184,7 @@ impl Optimizer {
     /// Counts instructions in an expression
     fn count_expression_instructions(&self, expr: &Expression) -> usize {
         match expr {
-            Expression::BinaryOp(left, _, right) => {
+            Expression::BinaryOp(left, _, right, _) => {
                 self.count_expression_instructions(left) +
                 self.count_expression_instructions(right) +
                 1 // The operation itself
@@ -245,6 +250,7 @@ impl Optimizer {
                     is_awaitable: func_def.is_awaitable,
                     effect_annotations: func_def.effect_annotations,
                     generic_params: func_def.generic_params,
+                    attributes: func_def.attributes,
                 })
             },
             // Handle other statement types as needed
@@ -253,26 +259,26 @@ impl Optimizer {
     }
 
     /// Applies constant folding to an expression
-    fn fold_constants_in_expression(&self, expr: Expression, count: &mut usize) -> Expression {
-        match expr {
-            Expression::BinaryOp(left, op, right) => {
-                let left_folded = Box::new(self.fold_constants_in_expression(*left, count));
-                let right_folded = Box::new(self.fold_constants_in_expression(*right, count));
+    fn fold_constants_in_expression(&self, mut expr: Expression, count: &mut usize) -> Expression {
+        match &mut expr {
+            Expression::BinaryOp(left, op, right, location) => {
+                let left_folded = Box::new(self.fold_constants_in_expression(take(&mut **left), count));
+                let right_folded = Box::new(self.fold_constants_in_expression(take(&mut **right), count));
 
                 // Try to fold the operation if both operands are constants
                 if let (Expression::Integer(l_val), Expression::Integer(r_val)) = (left_folded.as_ref(), right_folded.as_ref()) {
-                    if let Some(result) = self.perform_integer_operation(*l_val, &op, *r_val) {
+                    if let Some(result) = self.perform_integer_operation(*l_val, op, *r_val) {
                         *count += 1; // Increment count since we folded a constant
                         return Expression::Integer(result);
                     }
                 } else if let (Expression::Float(l_val), Expression::Float(r_val)) = (left_folded.as_ref(), right_folded.as_ref()) {
-                    if let Some(result) = self.perform_float_operation(*l_val, &op, *r_val) {
+                    if let Some(result) = self.perform_float_operation(*l_val, op, *r_val) {
                         *count += 1; // Increment count since we folded a constant
                         return Expression::Float(result);
                     }
                 }
 
-                Expression::BinaryOp(left_folded, op, right_folded)
+                Expression::BinaryOp(left_folded, op.clone(), right_folded, *location)
             },
             // Handle other expression types as needed
             _ => expr, // For unhandled expressions, return as-is
@@ -354,10 +360,61 @@ impl Optimizer {
     }
 
     /// Applies function inlining
-    fn inline_functions(&self, program: Program) -> Program {
-        // For now, we'll just return the program as-is
-        // In a full implementation, we'd replace function calls with function bodies
-        program
+    ///
+    /// Only functions whose body is a single expression are candidates. Small ones are
+    /// inlined by default; `#[inline]` and `#[inline(always)]` lift the size limit, and
+    /// `#[inline(never)]` opts out. Calls are only rewritten when every argument is a
+    /// literal or a variable, so substituting it for the parameter can't duplicate work.
+    fn inline_functions(&mut self, program: Program) -> Program {
+        let mut candidates = HashMap::new();
+        for stmt in &program.statements {
+            if let Statement::Function(func_def) = stmt {
+                if let Some(body) = self.inline_candidate(func_def) {
+                    let params = func_def.parameters.iter().map(|p| p.name.clone()).collect();
+                    candidates.insert(func_def.name.clone(), (params, body));
+                }
+            }
+        }
+
+        if candidates.is_empty() {
+            return program;
+        }
+
+        let mut inlined = 0;
+        let statements: Vec<Statement> = program.statements
+            .into_iter()
+            .map(|stmt| inline_in_statement(stmt, &candidates, &mut inlined))
+            .collect();
+
+        // Update statistics
+        self.stats.functions_inlined += inlined;
+
+        Program { statements }
+    }
+
+    /// Returns the body expression of a function that may be inlined
+    fn inline_candidate(&self, func_def: &FunctionDef) -> Option<Expression> {
+        let body = match func_def.body.as_slice() {
+            [Statement::Expression(expr)] | [Statement::Return(Some(expr))] => expr,
+            _ => return None,
+        };
+
+        let params: Vec<String> = func_def.parameters.iter().map(|param| param.name.clone()).collect();
+        if !is_inlinable_expression(body, &params) || calls_function(body, &func_def.name) {
+            return None;
+        }
+
+        match inline_hint(func_def) {
+            InlineHint::Never => None,
+            InlineHint::Hint | InlineHint::Always => Some(body.clone()),
+            InlineHint::Default => {
+                if self.count_expression_instructions(body) <= INLINE_SIZE_LIMIT {
+                    Some(body.clone())
+                } else {
+                    None
+                }
+            },
+        }
     }
 
     /// Applies tail call optimization
@@ -402,6 +459,153 @@ impl Default for OptimizationStats {
     }
 }
 
+/// Inline candidates by name: parameter names and the body expression
+type InlineCandidates = HashMap<String, (Vec<String>, Expression)>;
+
+/// Checks that an expression can be substituted into another function's body
+///
+/// Variables other than the function's own parameters are rejected, since the call
+/// site may shadow them.
+fn is_inlinable_expression(expr: &Expression, params: &[String]) -> bool {
+    match expr {
+        Expression::Integer(_) | Expression::Float(_) | Expression::String(_)
+        | Expression::Boolean(_) | Expression::Nil | Expression::Char(_) => true,
+        Expression::Identifier(name) => params.contains(name),
+        Expression::BinaryOp(left, _, right, _) => {
+            is_inlinable_expression(left, params) && is_inlinable_expression(right, params)
+        },
+        Expression::UnaryOp(_, operand) => is_inlinable_expression(operand, params),
+        Expression::Call(_, args) | Expression::Array(args) | Expression::Tuple(args) => {
+            args.iter().all(|arg| is_inlinable_expression(arg, params))
+        },
+        _ => false,
+    }
+}
+
+/// Checks whether an expression calls the named function
+fn calls_function(expr: &Expression, name: &str) -> bool {
+    match expr {
+        Expression::Call(callee, args) => callee == name || args.iter().any(|arg| calls_function(arg, name)),
+        Expression::BinaryOp(left, _, right, _) => calls_function(left, name) || calls_function(right, name),
+        Expression::UnaryOp(_, operand) => calls_function(operand, name),
+        Expression::Array(items) | Expression::Tuple(items) => items.iter().any(|item| calls_function(item, name)),
+        _ => false,
+    }
+}
+
+/// Checks whether an argument can be substituted for a parameter without duplicating work
+fn is_trivial_argument(expr: &Expression) -> bool {
+    matches!(
+        expr,
+        Expression::Integer(_) | Expression::Float(_) | Expression::String(_)
+            | Expression::Boolean(_) | Expression::Nil | Expression::Char(_) | Expression::Identifier(_)
+    )
+}
+
+/// Replaces parameters with their arguments in an inlined body
+fn substitute_parameters(expr: &Expression, bindings: &HashMap<&str, &Expression>) -> Expression {
+    match expr {
+        Expression::Identifier(name) => match bindings.get(name.as_str()) {
+            Some(arg) => (*arg).clone(),
+            None => expr.clone(),
+        },
+        Expression::BinaryOp(left, op, right, location) => Expression::BinaryOp(
+            Box::new(substitute_parameters(left, bindings)),
+            op.clone(),
+            Box::new(substitute_parameters(right, bindings)),
+            *location,
+        ),
+        Expression::UnaryOp(op, operand) => {
+            Expression::UnaryOp(op.clone(), Box::new(substitute_parameters(operand, bindings)))
+        },
+        Expression::Call(name, args) => {
+            Expression::Call(name.clone(), args.iter().map(|arg| substitute_parameters(arg, bindings)).collect())
+        },
+        Expression::Array(items) => {
+            Expression::Array(items.iter().map(|item| substitute_parameters(item, bindings)).collect())
+        },
+        Expression::Tuple(items) => {
+            Expression::Tuple(items.iter().map(|item| substitute_parameters(item, bindings)).collect())
+        },
+        _ => expr.clone(),
+    }
+}
+
+/// Inlines candidate calls within a statement
+fn inline_in_statement(stmt: Statement, candidates: &InlineCandidates, count: &mut usize) -> Statement {
+    match stmt {
+        Statement::Expression(expr) => Statement::Expression(inline_in_expression(expr, candidates, count)),
+        Statement::LetBinding { mutable, name, type_annotation, value, ownership_modifier, lifetime_annotation } => {
+            Statement::LetBinding {
+                mutable,
+                name,
+                type_annotation,
+                value: inline_in_expression(value, candidates, count),
+                ownership_modifier,
+                lifetime_annotation,
+            }
+        },
+        Statement::Return(Some(expr)) => Statement::Return(Some(inline_in_expression(expr, candidates, count))),
+        Statement::Function(mut func_def) => {
+            func_def.body = inline_in_statements(func_def.body, candidates, count);
+            Statement::Function(func_def)
+        },
+        Statement::Block(statements) => Statement::Block(inline_in_statements(statements, candidates, count)),
+        other => other,
+    }
+}
+
+fn inline_in_statements(statements: Vec<Statement>, candidates: &InlineCandidates, count: &mut usize) -> Vec<Statement> {
+    statements.into_iter().map(|stmt| inline_in_statement(stmt, candidates, count)).collect()
+}
+
+/// Inlines candidate calls within an expression
+fn inline_in_expression(mut expr: Expression, candidates: &InlineCandidates, count: &mut usize) -> Expression {
+    match &mut expr {
+        Expression::Call(name, args) => {
+            let args: Vec<Expression> = take(args).into_iter().map(|arg| inline_in_expression(arg, candidates, count)).collect();
+
+            if let Some((params, body)) = candidates.get(name.as_str()) {
+                if params.len() == args.len() && args.iter().all(is_trivial_argument) {
+                    let bindings = params.iter().map(String::as_str).zip(args.iter()).collect();
+                    *count += 1;
+                    return substitute_parameters(body, &bindings);
+                }
+            }
+
+            Expression::Call(take(name), args)
+        },
+        Expression::BinaryOp(left, op, right, location) => Expression::BinaryOp(
+            Box::new(inline_in_expression(take(&mut **left), candidates, count)),
+            op.clone(),
+            Box::new(inline_in_expression(take(&mut **right), candidates, count)),
+            *location,
+        ),
+        Expression::UnaryOp(op, operand) => {
+            Expression::UnaryOp(op.clone(), Box::new(inline_in_expression(take(&mut **operand), candidates, count)))
+        },
+        Expression::MethodCall(receiver, method, args) => Expression::MethodCall(
+            Box::new(inline_in_expression(take(&mut **receiver), candidates, count)),
+            take(method),
+            take(args).into_iter().map(|arg| inline_in_expression(arg, candidates, count)).collect(),
+        ),
+        Expression::Array(items) => {
+            Expression::Array(take(items).into_iter().map(|item| inline_in_expression(item, candidates, count)).collect())
+        },
+        Expression::Tuple(items) => {
+            Expression::Tuple(take(items).into_iter().map(|item| inline_in_expression(item, candidates, count)).collect())
+        },
+        Expression::If(condition, then_branch, else_branch) => Expression::If(
+            Box::new(inline_in_expression(take(&mut **condition), candidates, count)),
+            inline_in_statements(take(then_branch), candidates, count),
+            inline_in_statements(take(else_branch), candidates, count),
+        ),
+        Expression::Block(statements) => Expression::Block(inline_in_statements(take(statements), candidates, count)),
+        Expression::BlockExpr(statements) => Expression::BlockExpr(inline_in_statements(take(statements), candidates, count)),
+        _ => expr,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -423,6 +627,7 @@ mod tests {
             Box::new(Expression::Integer(2)),
             BinaryOp::Add,
             Box::new(Expression::Integer(3)),
+            ParsedAt::default(),
         );
         
         let stmt = Statement::Expression(expr);
@@ -440,4 +645,31 @@ mod tests {
             panic!("Constant folding failed");
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_inlining_honors_inline_attributes() {
+        let source = r#"
+            #[inline] fn double(x: Int) { x * 2 }
+            #[inline(never)] fn triple(x: Int) { x * 3 }
+            let a = double(21)
+            let b = triple(a)
+        "#;
+        let program = crate::parser::Parser::new(source).parse_program().unwrap();
+
+        let mut optimizer = Optimizer::new(OptLevel::Aggressive);
+        let optimized = optimizer.optimize_program(program);
+        assert_eq!(optimizer.stats.functions_inlined, 1);
+
+        match &optimized.statements[2] {
+            Statement::LetBinding { value, .. } => assert_eq!(
+                value,
+                &Expression::BinaryOp(Box::new(Expression::Integer(21)), BinaryOp::Mul, Box::new(Expression::Integer(2)), ParsedAt::default())
+            ),
+            other => panic!("Expected let binding, got {:?}", other),
+        }
+        match &optimized.statements[3] {
+            Statement::LetBinding { value: Expression::Call(name, _), .. } => assert_eq!(name, "triple"),
+            other => panic!("Expected call to triple, got {:?}", other),
+        }
+    }
+}
@@ -3,6 +3,7 @@
 
 use crate::ast::*;
 use std::collections::HashMap;
+use std::mem::take;
 use std::sync::{Arc, RwLock};
 
 /// Optimizer for performing various optimization passes on the AST
@@ -12,8 +13,135 @@ pub struct Optimizer {
     optimization_cache: Arc<RwLock<HashMap<String, Program>>>,
 }
 
+/// How much `optimize` does to a program, as chosen by `-O`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OptLevel {
+    /// `-O0`: the program is left as written
+    O0,
+    /// `-O1`: constant folding
+    O1,
+    /// `-O2`: constant folding, then dead code elimination
+    O2,
+    /// `-O3` and up: also inlining small functions, folding what that exposes, and
+    /// reusing repeated computations
+    O3,
+}
+
+impl OptLevel {
+    /// The level for `-O<level>`; anything above 3 does what `-O3` does
+    pub fn from_flag(level: u8) -> Self {
+        match level {
+            0 => OptLevel::O0,
+            1 => OptLevel::O1,
+            2 => OptLevel::O2,
+            _ => OptLevel::O3,
+        }
+    }
+
+    /// The passes run at this level, in order
+    pub fn passes(self) -> Vec<OptimizationPass> {
+        match self {
+            OptLevel::O0 => vec![],
+            OptLevel::O1 => vec![OptimizationPass::ConstantFolding],
+            OptLevel::O2 => vec![OptimizationPass::ConstantFolding, OptimizationPass::DeadCodeElimination],
+            OptLevel::O3 => vec![
+                OptimizationPass::ConstantFolding,
+                OptimizationPass::FunctionInlining,
+                OptimizationPass::ConstantFolding,
+                OptimizationPass::CommonSubexpressionElimination,
+                OptimizationPass::DeadCodeElimination,
+            ],
+        }
+    }
+}
+
+/// A pass the pass manager can run, with the name `--passes` knows it by
+#[derive(Debug, PartialEq)]
+pub struct NamedPass {
+    pub name: &'static str,
+    pub pass: OptimizationPass,
+    pub description: &'static str,
+}
+
+/// Every pass `--passes` accepts
+pub const NAMED_PASSES: &[NamedPass] = &[
+    NamedPass { name: "fold", pass: OptimizationPass::ConstantFolding, description: "constant folding" },
+    NamedPass { name: "dce", pass: OptimizationPass::DeadCodeElimination, description: "dead code elimination" },
+    NamedPass { name: "inline", pass: OptimizationPass::FunctionInlining, description: "function inlining" },
+    NamedPass {
+        name: "cse",
+        pass: OptimizationPass::CommonSubexpressionElimination,
+        description: "common subexpression elimination",
+    },
+];
+
+/// An ordered list of optimization passes, each run over the whole program before the next
+///
+/// A pass may appear more than once, since one pass can leave work for another:
+/// folding again after inlining finishes off calls whose arguments were constants.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PassManager {
+    passes: Vec<&'static NamedPass>,
+}
+
+impl PassManager {
+    /// The preset pipeline for an `-O` level
+    pub fn for_level(level: OptLevel) -> Self {
+        let passes = level.passes().into_iter()
+            .filter_map(|pass| NAMED_PASSES.iter().find(|named| named.pass == pass))
+            .collect();
+        PassManager { passes }
+    }
+
+    /// Parses a comma-separated list of pass names, as given to `--passes`
+    pub fn parse(list: &str) -> Result<Self, String> {
+        let passes = list.split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                NAMED_PASSES.iter().find(|named| named.name == name).ok_or_else(|| {
+                    let known: Vec<&str> = NAMED_PASSES.iter().map(|named| named.name).collect();
+                    format!("Unknown optimization pass '{}' (expected one of: {})", name, known.join(", "))
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(PassManager { passes })
+    }
+
+    /// Whether running this pipeline leaves the program as it is
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+
+    /// The passes' names, in the order they run
+    pub fn names(&self) -> Vec<&'static str> {
+        self.passes.iter().map(|named| named.name).collect()
+    }
+
+    /// What each pass does, in the order they run
+    pub fn descriptions(&self) -> Vec<&'static str> {
+        self.passes.iter().map(|named| named.description).collect()
+    }
+
+    /// Runs every pass over `program`, in place
+    pub fn run(&self, program: &mut Program) {
+        for named in &self.passes {
+            match &named.pass {
+                // Inlining needs every function's body before it can rewrite any call
+                OptimizationPass::FunctionInlining => {
+                    let mut inliner = crate::optimization_passes::Optimizer::new(crate::optimization_passes::OptLevel::None);
+                    inliner.passes = vec![crate::optimization_passes::Pass::FunctionInlining];
+                    let statements = std::mem::take(&mut program.statements);
+                    *program = inliner.optimize_program(Program { statements });
+                },
+                pass => run_passes(program, vec![pass.clone()]),
+            }
+        }
+    }
+}
+
 /// Different types of optimization passes available
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum OptimizationPass {
     ConstantFolding,                    // Fold constant expressions at compile time
     DeadCodeElimination,               // Remove unreachable code
@@ -50,6 +178,16 @@ impl Optimizer {
         }
     }
 
+    /// Creates an optimizer that runs only `passes`
+    pub fn with_passes(passes: Vec<OptimizationPass>) -> Self {
+        Self { passes, optimization_cache: Arc::new(RwLock::new(HashMap::new())) }
+    }
+
+    /// Whether `pass` is one of the passes this optimizer runs
+    fn runs(&self, pass: OptimizationPass) -> bool {
+        self.passes.contains(&pass)
+    }
+
     /// Adds an optimization pass to the optimizer
     pub fn add_pass(&mut self, pass: OptimizationPass) {
         self.passes.push(pass);
@@ -98,10 +236,7 @@ impl Optimizer {
         // Apply different optimizations based on the pass type
         // For now, we'll just apply the standard statement/expression optimizations
         Program {
-            statements: program.statements
-                .into_iter()
-                .map(|stmt| self.optimize_statement(stmt))
-                .collect(),
+            statements: self.optimize_statements(program.statements),
         }
     }
 
@@ -114,15 +249,13 @@ impl Optimizer {
             name: func_def.name,
             parameters: func_def.parameters,
             return_type: func_def.return_type,
-            body: func_def.body
-                .into_iter()
-                .map(|stmt| self.optimize_statement(stmt))
-                .collect(),
+            body: self.optimize_statements(func_def.body),
             is_async: func_def.is_async,
             is_public: func_def.is_public,
             is_awaitable: func_def.is_awaitable,
             effect_annotations: func_def.effect_annotations,
             generic_params: func_def.generic_params,
+            attributes: func_def.attributes,
         }
     }
 
@@ -130,10 +263,7 @@ impl Optimizer {
     fn apply_loop_optimization(&self, statements: Vec<Statement>) -> Vec<Statement> {
         // In a full implementation, this would optimize loop structures
         // For now, just return the statements with basic optimization
-        statements
-            .into_iter()
-            .map(|stmt| self.optimize_statement(stmt))
-            .collect()
+        self.optimize_statements(statements)
     }
 
     /// Applies closure optimization
@@ -155,10 +285,7 @@ impl Optimizer {
         // In a full implementation, this would optimize register usage
         // For now, return the program unchanged
         Program {
-            statements: program.statements
-                .into_iter()
-                .map(|stmt| self.optimize_statement(stmt))
-                .collect(),
+            statements: self.optimize_statements(program.statements),
         }
     }
 
@@ -176,6 +303,20 @@ impl Optimizer {
         program
     }
 
+    /// Optimizes each statement of a block, then reuses repeated computations and drops
+    /// the statements that can't matter, when those passes are on
+    fn optimize_statements(&self, statements: Vec<Statement>) -> Vec<Statement> {
+        let mut optimized = statements.into_iter().map(|stmt| self.optimize_statement(stmt)).collect();
+        if self.runs(OptimizationPass::CommonSubexpressionElimination) {
+            optimized = eliminate_common_subexpressions(optimized);
+        }
+        if self.runs(OptimizationPass::DeadCodeElimination) {
+            eliminate_dead_statements(optimized)
+        } else {
+            optimized
+        }
+    }
+
     /// Optimizes a statement
     fn optimize_statement(&self, statement: Statement) -> Statement {
         match statement {
@@ -210,8 +351,20 @@ impl Optimizer {
             Statement::Return(expr) => {
                 Statement::Return(expr.map(|e| self.optimize_expression(e)))
             },
-            Statement::Break => Statement::Break,
-            Statement::Continue => Statement::Continue,
+            Statement::While { label, condition, body } => Statement::While {
+                label,
+                condition: self.optimize_expression(condition),
+                body: self.apply_loop_optimization(body),
+            },
+            Statement::Defer(deferred) => Statement::Defer(Box::new(self.optimize_statement(*deferred))),
+            Statement::Assert { actual, expected, message, location } => Statement::Assert {
+                actual: self.optimize_expression(actual),
+                expected: expected.map(|e| self.optimize_expression(e)),
+                message: message.map(|e| self.optimize_expression(e)),
+                location,
+            },
+            Statement::Break(label) => Statement::Break(label),
+            Statement::Continue(label) => Statement::Continue(label),
             Statement::Class(class_def) => {
                 Statement::Class(self.optimize_class(class_def))
             },
@@ -236,6 +389,8 @@ impl Optimizer {
             Statement::TypeAlias(alias_def) => {
                 Statement::TypeAlias(alias_def) // Type aliases don't need optimization
             },
+            Statement::Extern(extern_def) => Statement::Extern(extern_def), // Only a signature, nothing to fold
+
         }
     }
 
@@ -248,6 +403,7 @@ impl Optimizer {
             variants: enum_def.variants,
             access_modifier: enum_def.access_modifier,
             generics: enum_def.generics,
+            attributes: enum_def.attributes,
         }
     }
 
@@ -258,10 +414,7 @@ impl Optimizer {
         MacroDef {
             name: macro_def.name,
             parameters: macro_def.parameters,  // Parameters typically don't need optimization
-            body: macro_def.body
-                .into_iter()
-                .map(|stmt| self.optimize_statement(stmt))
-                .collect(),
+            body: self.optimize_statements(macro_def.body),
             is_hygienic: macro_def.is_hygienic,
         }
     }
@@ -275,15 +428,13 @@ impl Optimizer {
             name: optimized_func.name,
             parameters: optimized_func.parameters, // Parameters typically don't need optimization
             return_type: optimized_func.return_type,
-            body: optimized_func.body
-                .into_iter()
-                .map(|stmt| self.optimize_statement(stmt))
-                .collect(),
+            body: self.optimize_statements(optimized_func.body),
             is_async: optimized_func.is_async,
             is_public: optimized_func.is_public,
             is_awaitable: optimized_func.is_awaitable,
             effect_annotations: optimized_func.effect_annotations,
             generic_params: optimized_func.generic_params,
+            attributes: optimized_func.attributes,
         }
     }
 
@@ -309,6 +460,7 @@ impl Optimizer {
                 .into_iter()
                 .map(|destructor| self.optimize_destructor(destructor))
                 .collect(),
+            attributes: class_def.attributes,
         }
     }
 
@@ -316,10 +468,7 @@ impl Optimizer {
     fn optimize_constructor(&self, constructor: ConstructorDef) -> ConstructorDef {
         ConstructorDef {
             parameters: constructor.parameters,
-            body: constructor.body
-                .into_iter()
-                .map(|stmt| self.optimize_statement(stmt))
-                .collect(),
+            body: self.optimize_statements(constructor.body),
             access_modifier: constructor.access_modifier,
         }
     }
@@ -327,10 +476,7 @@ impl Optimizer {
     /// Optimizes a destructor definition
     fn optimize_destructor(&self, destructor: DestructorDef) -> DestructorDef {
         DestructorDef {
-            body: destructor.body
-                .into_iter()
-                .map(|stmt| self.optimize_statement(stmt))
-                .collect(),
+            body: self.optimize_statements(destructor.body),
             access_modifier: destructor.access_modifier,
         }
     }
@@ -346,6 +492,8 @@ impl Optimizer {
                 .collect(),
             associated_types: trait_def.associated_types,
             super_traits: trait_def.super_traits,
+            attributes: trait_def.attributes,
+            is_public: trait_def.is_public,
         }
     }
 
@@ -360,6 +508,7 @@ impl Optimizer {
                 .map(|method| self.optimize_function(method))
                 .collect(),
             associated_types: impl_def.associated_types,
+            attributes: impl_def.attributes,
         }
     }
 
@@ -387,101 +536,109 @@ impl Optimizer {
     }
 
     /// Optimizes an expression
-    fn optimize_expression(&self, expr: Expression) -> Expression {
-        match expr {
-            Expression::Integer(_) | Expression::Float(_) | Expression::String(_) | 
+    fn optimize_expression(&self, mut expr: Expression) -> Expression {
+        match &mut expr {
+            Expression::Integer(_) | Expression::Float(_) | Expression::String(_) | Expression::Bytes(_) |
             Expression::Boolean(_) | Expression::Nil => expr, // Literals don't need optimization
             
             Expression::Identifier(_) => expr, // Identifiers don't need optimization
             
-            Expression::BinaryOp(left, op, right) => {
-                let optimized_left = Box::new(self.optimize_expression(*left));
-                let optimized_right = Box::new(self.optimize_expression(*right));
-
-                // Perform constant folding if both operands are constants
-                match (&*optimized_left, &*optimized_right, &op) {
-                    (Expression::Integer(a), Expression::Integer(b), BinaryOp::Add) => {
-                        Expression::Integer(a + b)
-                    },
-                    (Expression::Integer(a), Expression::Integer(b), BinaryOp::Sub) => {
-                        Expression::Integer(a - b)
-                    },
-                    (Expression::Integer(a), Expression::Integer(b), BinaryOp::Mul) => {
-                        Expression::Integer(a * b)
-                    },
-                    (Expression::Integer(a), Expression::Integer(b), BinaryOp::Div) if *b != 0 => {
-                        Expression::Integer(a / b)
-                    },
-                    (Expression::Float(a), Expression::Float(b), BinaryOp::Add) => {
-                        Expression::Float(a + b)
-                    },
-                    (Expression::Float(a), Expression::Float(b), BinaryOp::Sub) => {
-                        Expression::Float(a - b)
-                    },
-                    (Expression::Float(a), Expression::Float(b), BinaryOp::Mul) => {
-                        Expression::Float(a * b)
-                    },
-                    (Expression::Float(a), Expression::Float(b), BinaryOp::Div) if *b != 0.0 => {
-                        Expression::Float(a / b)
-                    },
-                    (Expression::Boolean(a), Expression::Boolean(b), BinaryOp::And) => {
-                        Expression::Boolean(*a && *b)
+            Expression::BinaryOp(left, op, right, location) => {
+                let optimized_left = Box::new(self.optimize_expression(take(&mut **left)));
+                let optimized_right = Box::new(self.optimize_expression(take(&mut **right)));
+
+                if !self.runs(OptimizationPass::ConstantFolding) {
+                    return Expression::BinaryOp(optimized_left, op.clone(), optimized_right, *location);
+                }
+
+                // A literal left operand decides `&&` and `||` without the right one
+                match (&*optimized_left, &*op) {
+                    (Expression::Boolean(true), BinaryOp::And) | (Expression::Boolean(false), BinaryOp::Or) => {
+                        return *optimized_right;
                     },
-                    (Expression::Boolean(a), Expression::Boolean(b), BinaryOp::Or) => {
-                        Expression::Boolean(*a || *b)
+                    (Expression::Boolean(false), BinaryOp::And) | (Expression::Boolean(true), BinaryOp::Or) => {
+                        return *optimized_left;
                     },
-                    _ => Expression::BinaryOp(optimized_left, op, optimized_right),
+                    _ => {},
                 }
+
+                fold_binary_op(&optimized_left, op, &optimized_right)
+                    .unwrap_or(Expression::BinaryOp(optimized_left, op.clone(), optimized_right, *location))
             },
             
             Expression::UnaryOp(op, expr) => {
-                let optimized_expr = Box::new(self.optimize_expression(*expr));
+                let optimized_expr = Box::new(self.optimize_expression(take(&mut **expr)));
+
+                if !self.runs(OptimizationPass::ConstantFolding) {
+                    return Expression::UnaryOp(op.clone(), optimized_expr);
+                }
 
                 // Perform constant folding for unary operations on constants
-                match (&*optimized_expr, &op) {
+                match (&*optimized_expr, &*op) {
                     (Expression::Integer(a), UnaryOp::Neg) => Expression::Integer(-a),
                     (Expression::Float(a), UnaryOp::Neg) => Expression::Float(-a),
                     (Expression::Boolean(a), UnaryOp::Not) => Expression::Boolean(!a),
-                    _ => Expression::UnaryOp(op, optimized_expr),
+                    _ => Expression::UnaryOp(op.clone(), optimized_expr),
                 }
             },
             
             Expression::Call(name, args) => {
-                let optimized_args = args
+                let optimized_args = take(args)
                     .into_iter()
                     .map(|arg| self.optimize_expression(arg))
                     .collect();
                 
-                Expression::Call(name, optimized_args)
+                Expression::Call(take(name), optimized_args)
             },
             
             Expression::MethodCall(obj, method, args) => {
-                let optimized_obj = Box::new(self.optimize_expression(*obj));
-                let optimized_args = args
+                let optimized_obj = Box::new(self.optimize_expression(take(&mut **obj)));
+                let optimized_args = take(args)
                     .into_iter()
                     .map(|arg| self.optimize_expression(arg))
                     .collect();
                 
-                Expression::MethodCall(optimized_obj, method, optimized_args)
+                Expression::MethodCall(optimized_obj, take(method), optimized_args)
             },
             
             Expression::FieldAccess(obj, field) => {
-                let optimized_obj = Box::new(self.optimize_expression(*obj));
+                let optimized_obj = Box::new(self.optimize_expression(take(&mut **obj)));
                 
-                Expression::FieldAccess(optimized_obj, field)
+                Expression::FieldAccess(optimized_obj, take(field))
+            },
+
+            Expression::Index(target, index) => Expression::Index(
+                Box::new(self.optimize_expression(take(&mut **target))),
+                Box::new(self.optimize_expression(take(&mut **index))),
+            ),
+
+            Expression::Slice(target, start, end) => Expression::Slice(
+                Box::new(self.optimize_expression(take(&mut **target))),
+                Box::new(self.optimize_expression(take(&mut **start))),
+                Box::new(self.optimize_expression(take(&mut **end))),
+            ),
+
+            Expression::TryCatch(body, name, handler) => Expression::TryCatch(
+                self.optimize_statements(take(body)),
+                take(name),
+                self.optimize_statements(take(handler)),
+            ),
+
+            Expression::Propagate(inner) => {
+                Expression::Propagate(Box::new(self.optimize_expression(take(&mut **inner))))
             },
             
             Expression::If(condition, then_branch, else_branch) => {
-                let optimized_condition = self.optimize_expression(*condition);
-                let optimized_then = then_branch
-                    .into_iter()
-                    .map(|stmt| self.optimize_statement(stmt))
-                    .collect();
-                let optimized_else = else_branch
-                    .into_iter()
-                    .map(|stmt| self.optimize_statement(stmt))
-                    .collect();
-                
+                let optimized_condition = self.optimize_expression(take(&mut **condition));
+                let optimized_then = self.optimize_statements(take(then_branch));
+                let optimized_else = self.optimize_statements(take(else_branch));
+
+                // Only the branch a constant condition picks can run; it keeps its own scope
+                let prunes = self.runs(OptimizationPass::ConstantFolding) || self.runs(OptimizationPass::DeadCodeElimination);
+                if let (Expression::Boolean(taken), true) = (&optimized_condition, prunes) {
+                    return Expression::Block(if *taken { optimized_then } else { optimized_else });
+                }
+
                 Expression::If(
                     Box::new(optimized_condition),
                     optimized_then,
@@ -490,15 +647,12 @@ impl Optimizer {
             },
             
             Expression::Match(expr, arms) => {
-                let optimized_expr = Box::new(self.optimize_expression(*expr));
-                let optimized_arms = arms
+                let optimized_expr = Box::new(self.optimize_expression(take(&mut **expr)));
+                let optimized_arms = take(arms)
                     .into_iter()
                     .map(|(pattern, guard, body)| {
                         let optimized_guard = guard.map(|g| Box::new(self.optimize_expression(*g)));
-                        let optimized_body = body
-                            .into_iter()
-                            .map(|stmt| self.optimize_statement(stmt))
-                            .collect();
+                        let optimized_body = self.optimize_statements(body);
                         (pattern, optimized_guard, optimized_body)
                     })
                     .collect();
@@ -508,26 +662,20 @@ impl Optimizer {
             },
             
             Expression::Lambda(params, body) => {
-                let optimized_body = body
-                    .into_iter()
-                    .map(|stmt| self.optimize_statement(stmt))
-                    .collect();
+                let optimized_body = self.optimize_statements(take(body));
 
                 // Apply closure optimization
-                self.apply_closure_optimization(Expression::Lambda(params, optimized_body))
+                self.apply_closure_optimization(Expression::Lambda(take(params), optimized_body))
             },
             
             Expression::BlockExpr(statements) => {
-                let optimized_statements = statements
-                    .into_iter()
-                    .map(|stmt| self.optimize_statement(stmt))
-                    .collect();
+                let optimized_statements = self.optimize_statements(take(statements));
                 
                 Expression::BlockExpr(optimized_statements)
             },
             
             Expression::Tuple(items) => {
-                let optimized_items = items
+                let optimized_items = take(items)
                     .into_iter()
                     .map(|item| self.optimize_expression(item))
                     .collect();
@@ -538,39 +686,39 @@ impl Optimizer {
             // Multi-language integration expressions
             Expression::MultiLangCall(lang, code) => {
                 // For now, don't optimize multi-language calls
-                Expression::MultiLangCall(lang, code)
+                Expression::MultiLangCall(take(lang), take(code))
             },
             Expression::MultiLangImport(lang, resource, alias) => {
                 // For now, don't optimize multi-language imports
-                Expression::MultiLangImport(lang, resource, alias)
+                Expression::MultiLangImport(take(lang), take(resource), take(alias))
             },
             Expression::MultiLangIndex(lang, resource) => {
                 // For now, don't optimize multi-language indexing
-                Expression::MultiLangIndex(lang, resource)
+                Expression::MultiLangIndex(take(lang), take(resource))
             },
             
             // CSP-style channel operations
             Expression::ChannelCreate(channel_type) => {
-                Expression::ChannelCreate(Box::new(self.optimize_type(*channel_type)))
+                Expression::ChannelCreate(Box::new(self.optimize_type(std::mem::replace(channel_type, Type::Unit))))
             },
             Expression::ChannelSend(channel, value) => {
-                let optimized_channel = Box::new(self.optimize_expression(*channel));
-                let optimized_value = Box::new(self.optimize_expression(*value));
+                let optimized_channel = Box::new(self.optimize_expression(take(&mut **channel)));
+                let optimized_value = Box::new(self.optimize_expression(take(&mut **value)));
                 
                 Expression::ChannelSend(optimized_channel, optimized_value)
             },
             Expression::ChannelReceive(channel) => {
-                let optimized_channel = Box::new(self.optimize_expression(*channel));
+                let optimized_channel = Box::new(self.optimize_expression(take(&mut **channel)));
                 
                 Expression::ChannelReceive(optimized_channel)
             },
             Expression::ChannelClose(channel) => {
-                let optimized_channel = Box::new(self.optimize_expression(*channel));
+                let optimized_channel = Box::new(self.optimize_expression(take(&mut **channel)));
                 
                 Expression::ChannelClose(optimized_channel)
             },
             Expression::Select(select_arms) => {
-                let optimized_arms = select_arms
+                let optimized_arms = take(select_arms)
                     .into_iter()
                     .map(|arm| self.optimize_select_arm(arm))
                     .collect();
@@ -580,35 +728,32 @@ impl Optimizer {
             
             // Async/Await constructs
             Expression::AsyncBlock(statements) => {
-                let optimized_statements = statements
-                    .into_iter()
-                    .map(|stmt| self.optimize_statement(stmt))
-                    .collect();
+                let optimized_statements = self.optimize_statements(take(statements));
                 
                 Expression::AsyncBlock(optimized_statements)
             },
             Expression::Await(expr) => {
-                let optimized_expr = Box::new(self.optimize_expression(*expr));
+                let optimized_expr = Box::new(self.optimize_expression(take(&mut **expr)));
                 
                 Expression::Await(optimized_expr)
             },
             Expression::Future(expr) => {
-                let optimized_expr = Box::new(self.optimize_expression(*expr));
+                let optimized_expr = Box::new(self.optimize_expression(take(&mut **expr)));
                 
                 Expression::Future(optimized_expr)
             },
             Expression::SpawnTask(expr) => {
-                let optimized_expr = Box::new(self.optimize_expression(*expr));
+                let optimized_expr = Box::new(self.optimize_expression(take(&mut **expr)));
                 
                 Expression::SpawnTask(optimized_expr)
             },
             Expression::Join(expr) => {
-                let optimized_expr = Box::new(self.optimize_expression(*expr));
+                let optimized_expr = Box::new(self.optimize_expression(take(&mut **expr)));
                 
                 Expression::Join(optimized_expr)
             },
             Expression::Race(exprs) => {
-                let optimized_exprs = exprs
+                let optimized_exprs = take(exprs)
                     .into_iter()
                     .map(|expr| self.optimize_expression(expr))
                     .collect();
@@ -616,94 +761,95 @@ impl Optimizer {
                 Expression::Race(optimized_exprs)
             },
             Expression::Timeout(expr, duration) => {
-                let optimized_expr = Box::new(self.optimize_expression(*expr));
-                let optimized_duration = Box::new(self.optimize_expression(*duration));
+                let optimized_expr = Box::new(self.optimize_expression(take(&mut **expr)));
+                let optimized_duration = Box::new(self.optimize_expression(take(&mut **duration)));
 
                 Expression::Timeout(optimized_expr, optimized_duration)
             },
 
             // Missing expression types that were causing the error
             Expression::Spawn(actor_name, args) => {
-                let optimized_args = args
+                let optimized_args = take(args)
                     .into_iter()
                     .map(|arg| self.optimize_expression(arg))
                     .collect();
-                Expression::Spawn(actor_name, optimized_args)
+                Expression::Spawn(take(actor_name), optimized_args)
             },
             Expression::Send(actor, message) => {
-                let optimized_actor = Box::new(self.optimize_expression(*actor));
-                let optimized_message = Box::new(self.optimize_expression(*message));
+                let optimized_actor = Box::new(self.optimize_expression(take(&mut **actor)));
+                let optimized_message = Box::new(self.optimize_expression(take(&mut **message)));
                 Expression::Send(optimized_actor, optimized_message)
             },
             Expression::Receive => Expression::Receive,
 
             // Enhanced syntax constructs
             Expression::LambdaSimple(params, body) => {
-                let optimized_body = Box::new(self.optimize_expression(*body));
-                Expression::LambdaSimple(params, optimized_body)
+                let optimized_body = Box::new(self.optimize_expression(take(&mut **body)));
+                Expression::LambdaSimple(take(params), optimized_body)
             },
             Expression::Pipeline(expr, funcs) => {
-                let optimized_expr = Box::new(self.optimize_expression(*expr));
-                let optimized_funcs = funcs
+                let optimized_expr = Box::new(self.optimize_expression(take(&mut **expr)));
+                let optimized_funcs = take(funcs)
                     .into_iter()
                     .map(|func| self.optimize_expression(func))
                     .collect();
                 Expression::Pipeline(optimized_expr, optimized_funcs)
             },
             Expression::BackPipeline(expr, funcs) => {
-                let optimized_expr = Box::new(self.optimize_expression(*expr));
-                let optimized_funcs = funcs
+                let optimized_expr = Box::new(self.optimize_expression(take(&mut **expr)));
+                let optimized_funcs = take(funcs)
                     .into_iter()
                     .map(|func| self.optimize_expression(func))
                     .collect();
                 Expression::BackPipeline(optimized_expr, optimized_funcs)
             },
             Expression::DestructureAssignment(pattern, expr, stmt) => {
-                let optimized_expr = Box::new(self.optimize_expression(*expr));
-                let optimized_stmt = Box::new(self.optimize_statement(*stmt));
-                Expression::DestructureAssignment(pattern, optimized_expr, optimized_stmt)
+                let optimized_expr = Box::new(self.optimize_expression(take(&mut **expr)));
+                let optimized_stmt = Box::new(self.optimize_statement(std::mem::replace(stmt, Statement::Expression(Expression::Nil))));
+                Expression::DestructureAssignment(pattern.clone(), optimized_expr, optimized_stmt)
             },
             Expression::InterpolatedString(parts) => {
-                let optimized_parts = parts
+                let optimized_parts = take(parts)
                     .into_iter()
                     .map(|part| match part {
                         StringPart::Literal(lit) => StringPart::Literal(lit),
-                        StringPart::Interpolated(interp_expr) => {
-                            StringPart::Interpolated(Box::new(self.optimize_expression(*interp_expr)))
+                        StringPart::Interpolated(interp_expr, spec) => {
+                            StringPart::Interpolated(Box::new(self.optimize_expression(*interp_expr)), spec)
                         }
                     })
                     .collect();
                 Expression::InterpolatedString(optimized_parts)
             },
             Expression::MacroInvocation(name, args) => {
-                let optimized_args = args
+                let optimized_args = take(args)
                     .into_iter()
                     .map(|arg| self.optimize_expression(arg))
                     .collect();
-                Expression::MacroInvocation(name, optimized_args)
+                Expression::MacroInvocation(take(name), optimized_args)
             },
 
             // New expression types added
             Expression::Char(_) => expr, // Character literals don't need optimization
             Expression::Array(items) => {
-                let optimized_items = items
+                let optimized_items = take(items)
                     .into_iter()
                     .map(|item| self.optimize_expression(item))
                     .collect();
                 Expression::Array(optimized_items)
             },
+            Expression::ArrayRepeat(value, length) => Expression::ArrayRepeat(
+                Box::new(self.optimize_expression(take(&mut **value))),
+                Box::new(self.optimize_expression(take(&mut **length))),
+            ),
             Expression::Struct(name, fields) => {
-                let optimized_fields = fields
+                let optimized_fields = take(fields)
                     .into_iter()
                     .map(|(field_name, field_expr)| (field_name, self.optimize_expression(field_expr)))
                     .collect();
-                Expression::Struct(name, optimized_fields)
+                Expression::Struct(take(name), optimized_fields)
             },
             Expression::Block(statements) => {
-                let optimized_statements = statements
-                    .into_iter()
-                    .map(|stmt| self.optimize_statement(stmt))
-                    .collect();
+                let optimized_statements = self.optimize_statements(take(statements));
                 Expression::Block(optimized_statements)
             },
         }
@@ -748,10 +894,7 @@ impl Optimizer {
         SelectArm {
             channel_operation: self.optimize_channel_operation(arm.channel_operation),
             pattern: arm.pattern,
-            body: arm.body
-                .into_iter()
-                .map(|stmt| self.optimize_statement(stmt))
-                .collect(),
+            body: self.optimize_statements(arm.body),
         }
     }
 
@@ -768,8 +911,250 @@ impl Optimizer {
             ChannelOperation::Close { channel } => ChannelOperation::Close {
                 channel: Box::new(self.optimize_expression(*channel)),
             },
+            ChannelOperation::Timeout { millis } => ChannelOperation::Timeout {
+                millis: Box::new(self.optimize_expression(*millis)),
+            },
+            ChannelOperation::Default => ChannelOperation::Default,
+        }
+    }
+}
+
+/// Evaluates a binary operation whose operands are both literals
+///
+/// Returns `None` whenever folding could change what the program does at run time:
+/// division or modulo by zero and integer overflow are left for the runtime to report,
+/// and operators without a literal result, such as `^` and `..`, are never folded.
+fn fold_binary_op(left: &Expression, op: &BinaryOp, right: &Expression) -> Option<Expression> {
+    match (left, right) {
+        (Expression::Integer(a), Expression::Integer(b)) => {
+            let (a, b) = (*a, *b);
+            match op {
+                BinaryOp::Add => a.checked_add(b).map(Expression::Integer),
+                BinaryOp::Sub => a.checked_sub(b).map(Expression::Integer),
+                BinaryOp::Mul => a.checked_mul(b).map(Expression::Integer),
+                BinaryOp::Div => a.checked_div(b).map(Expression::Integer),
+                BinaryOp::Mod => a.checked_rem(b).map(Expression::Integer),
+                _ => fold_comparison(a.cmp(&b), op),
+            }
+        },
+        (Expression::Integer(_) | Expression::Float(_), Expression::Integer(_) | Expression::Float(_)) => {
+            // Mixed operands are promoted to Float, as the runtime does
+            let as_float = |expr: &Expression| match expr {
+                Expression::Integer(i) => *i as f64,
+                Expression::Float(f) => *f,
+                _ => unreachable!("operands were matched as numbers"),
+            };
+            let (a, b) = (as_float(left), as_float(right));
+            match op {
+                BinaryOp::Add => Some(Expression::Float(a + b)),
+                BinaryOp::Sub => Some(Expression::Float(a - b)),
+                BinaryOp::Mul => Some(Expression::Float(a * b)),
+                BinaryOp::Div if b != 0.0 => Some(Expression::Float(a / b)),
+                BinaryOp::Mod if b != 0.0 => Some(Expression::Float(a % b)),
+                BinaryOp::Div | BinaryOp::Mod => None,
+                _ => fold_comparison(a.partial_cmp(&b)?, op),
+            }
+        },
+        (Expression::String(a), Expression::String(b)) => match op {
+            BinaryOp::Add => Some(Expression::String(format!("{}{}", a, b))),
+            _ => fold_comparison(a.cmp(b), op),
+        },
+        (Expression::Boolean(a), Expression::Boolean(b)) => match op {
+            BinaryOp::And => Some(Expression::Boolean(*a && *b)),
+            BinaryOp::Or => Some(Expression::Boolean(*a || *b)),
+            BinaryOp::Eq => Some(Expression::Boolean(a == b)),
+            BinaryOp::Ne => Some(Expression::Boolean(a != b)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The literal result of comparing two operands that compare as `ordering`
+fn fold_comparison(ordering: std::cmp::Ordering, op: &BinaryOp) -> Option<Expression> {
+    let result = match op {
+        BinaryOp::Eq => ordering.is_eq(),
+        BinaryOp::Ne => ordering.is_ne(),
+        BinaryOp::Lt => ordering.is_lt(),
+        BinaryOp::Gt => ordering.is_gt(),
+        BinaryOp::Le => ordering.is_le(),
+        BinaryOp::Ge => ordering.is_ge(),
+        _ => return None,
+    };
+    Some(Expression::Boolean(result))
+}
+
+/// Folds constant expressions throughout `program`, in place
+///
+/// Operations on literals become their result (`2 + 3` becomes `5`), a literal left
+/// operand of `&&` or `||` decides the operation (`true && x` becomes `x`), and an
+/// `if` on a literal condition becomes the block of the branch it would take.
+pub fn fold_constants(program: &mut Program) {
+    run_passes(program, vec![OptimizationPass::ConstantFolding]);
+}
+
+/// Removes code that can never run or whose result is never used, in place
+///
+/// Statements after an unconditional `return`, `break` or `continue` are dropped, as
+/// are the branches an `if` on a literal condition can't take and `let` bindings no
+/// other statement of their block refers to. A binding is only dropped when its value
+/// has no side effects: anything that calls a function, or could fail, stays.
+pub fn eliminate_dead_code(program: &mut Program) {
+    run_passes(program, vec![OptimizationPass::DeadCodeElimination]);
+}
+
+/// Runs the passes `level` calls for over `program`, in place
+pub fn optimize(program: &mut Program, level: OptLevel) {
+    PassManager::for_level(level).run(program);
+}
+
+fn run_passes(program: &mut Program, passes: Vec<OptimizationPass>) {
+    let optimizer = Optimizer::with_passes(passes);
+    program.statements = optimizer.optimize_statements(std::mem::take(&mut program.statements));
+}
+
+/// Drops the dead statements of one block whose statements are already optimized
+fn eliminate_dead_statements(mut statements: Vec<Statement>) -> Vec<Statement> {
+    // Nothing after an unconditional jump out of the block runs
+    if let Some(jump) = statements
+        .iter()
+        .position(|stmt| matches!(stmt, Statement::Return(_) | Statement::Break(_) | Statement::Continue(_)))
+    {
+        statements.truncate(jump + 1);
+    }
+
+    // The last statement gives the block its value, so it always stays. Going backwards
+    // lets a chain of unused bindings (`let a = 1`, `let b = a`) go in one sweep.
+    for index in (0..statements.len().saturating_sub(1)).rev() {
+        let removable = match &statements[index] {
+            Statement::Expression(Expression::Block(body)) => body.is_empty(),
+            Statement::LetBinding { name, value, .. } => {
+                is_pure(value)
+                    && !statements.iter().enumerate().any(|(other, stmt)| other != index && statement_uses(stmt, name))
+            },
+            _ => false,
+        };
+        if removable {
+            statements.remove(index);
         }
     }
+    statements
+}
+
+/// Makes each `let` in one block whose value an earlier `let` already computed reuse it
+///
+/// `let b = x * y` after `let a = x * y` becomes `let b = a`, unless something in
+/// between rebinds or assigns `a`, `x` or `y`. Only operations without side effects
+/// are reused; a plain literal or variable is no cheaper to refer to than to repeat.
+fn eliminate_common_subexpressions(mut statements: Vec<Statement>) -> Vec<Statement> {
+    // Each computed value still valid, with the binding that holds it
+    let mut available: Vec<(Expression, String)> = Vec::new();
+    for stmt in &mut statements {
+        match stmt {
+            Statement::LetBinding { name, value, .. } => {
+                if let Some((_, holder)) = available.iter().find(|(computed, _)| computed == value) {
+                    *value = Expression::Identifier(holder.clone());
+                }
+                let rebound = name.clone();
+                available.retain(|(computed, holder)| *holder != rebound && !expression_uses(computed, &rebound));
+                if matches!(value, Expression::BinaryOp(..) | Expression::UnaryOp(..))
+                    && is_pure(value)
+                    && !expression_uses(value, &rebound)
+                {
+                    available.push((value.clone(), rebound));
+                }
+            },
+            // Nothing here looks inside these, so forget everything
+            Statement::Class(_) | Statement::Implementation(_) | Statement::Actor(_) | Statement::MacroDefinition(_) => {
+                available.clear();
+            },
+            other => available.retain(|(computed, holder)| {
+                !statement_assigns(other, holder) && !variables_of(computed).iter().any(|name| statement_assigns(other, name))
+            }),
+        }
+    }
+    statements
+}
+
+/// Whether `expr` might read the variable `name`
+fn expression_uses(expr: &Expression, name: &str) -> bool {
+    statement_uses(&Statement::Expression(expr.clone()), name)
+}
+
+/// The variables an expression reads
+fn variables_of(expr: &Expression) -> Vec<String> {
+    let mut names = Vec::new();
+    crate::perf_hints::visit_statement(&Statement::Expression(expr.clone()), &mut |expression| {
+        if let Expression::Identifier(name) = expression {
+            names.push(name.clone());
+        }
+    });
+    names
+}
+
+/// Whether `stmt` might assign the variable `name`, with `assign` or code this can't see into
+fn statement_assigns(stmt: &Statement, name: &str) -> bool {
+    let mut assigns = false;
+    crate::perf_hints::visit_statement(stmt, &mut |expression| {
+        assigns |= match expression {
+            Expression::Call(function, arguments) if function == "assign" => {
+                matches!(arguments.first(), Some(Expression::String(target)) if target == name)
+            },
+            Expression::MultiLangCall(_, code) => code.contains(name),
+            Expression::MacroInvocation(..) => true,
+            _ => false,
+        }
+    });
+    assigns
+}
+
+/// Whether evaluating `expr` can have no effect beyond producing its value
+///
+/// Calls of any kind might print, mutate or fail, and so might division, indexing and
+/// the rest of what isn't listed here.
+fn is_pure(expr: &Expression) -> bool {
+    match expr {
+        Expression::Integer(_) | Expression::Float(_) | Expression::String(_) | Expression::Bytes(_)
+        | Expression::Boolean(_) | Expression::Char(_) | Expression::Nil | Expression::Identifier(_)
+        | Expression::Lambda(..) | Expression::LambdaSimple(..) => true,
+        Expression::Array(items) | Expression::Tuple(items) => items.iter().all(is_pure),
+        Expression::Struct(_, fields) => fields.iter().all(|(_, value)| is_pure(value)),
+        Expression::UnaryOp(UnaryOp::Neg | UnaryOp::Not, operand) => is_pure(operand),
+        Expression::BinaryOp(left, op, right, _) => {
+            !matches!(op, BinaryOp::Div | BinaryOp::Mod | BinaryOp::PipeForward | BinaryOp::PipeBackward)
+                && is_pure(left)
+                && is_pure(right)
+        },
+        _ => false,
+    }
+}
+
+/// Whether `stmt` might read or assign the variable `name`
+fn statement_uses(stmt: &Statement, name: &str) -> bool {
+    match stmt {
+        // Nothing here looks inside these, so assume they do
+        Statement::Class(_)
+        | Statement::Implementation(_)
+        | Statement::Actor(_)
+        | Statement::Trait(_)
+        | Statement::Effect(_)
+        | Statement::MacroDefinition(_) => true,
+        _ => {
+            let mut used = false;
+            crate::perf_hints::visit_statement(stmt, &mut |expression| {
+                used |= match expression {
+                    Expression::Identifier(identifier) => identifier == name,
+                    Expression::Call(function, arguments) if function == "assign" => {
+                        matches!(arguments.first(), Some(Expression::String(target)) if target == name)
+                    },
+                    Expression::MultiLangCall(_, code) => code.contains(name),
+                    Expression::MacroInvocation(..) => true,
+                    _ => false,
+                }
+            });
+            used
+        },
+    }
 }
 
 /// Performs constant folding optimization on an expression
@@ -782,4 +1167,187 @@ pub fn constant_fold(expr: Expression) -> Expression {
 pub fn optimize_program(program: Program) -> Program {
     let optimizer = Optimizer::new();
     optimizer.optimize_program(program)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn folded(source: &str) -> Vec<Statement> {
+        let mut program = Parser::new(source).parse_program().unwrap();
+        fold_constants(&mut program);
+        program.statements
+    }
+
+    fn folded_value(source: &str) -> Expression {
+        match folded(source).remove(0) {
+            Statement::LetBinding { value, .. } => value,
+            Statement::Expression(expr) => expr,
+            other => panic!("Expected a binding or expression, got {:?}", other),
+        }
+    }
+
+    fn binary(left: Expression, op: BinaryOp, right: Expression) -> Expression {
+        Expression::BinaryOp(Box::new(left), op, Box::new(right), ParsedAt::default())
+    }
+
+    #[test]
+    fn test_literal_operations_fold_to_their_result() {
+        assert_eq!(folded_value("let a = 2 + 3"), Expression::Integer(5));
+        assert_eq!(folded_value("let a = (1 + 2) * 4 - 10 % 4"), Expression::Integer(10));
+        assert_eq!(folded_value("let a = 1 + 0.5"), Expression::Float(1.5));
+        assert_eq!(folded_value("let a = \"log\" + \"os\""), Expression::String("logos".to_string()));
+        assert_eq!(folded_value("let a = 3 < 4 && \"b\" >= \"a\""), Expression::Boolean(true));
+
+        // Only the literal part of a larger expression folds
+        let identifier = |name: &str| Expression::Identifier(name.to_string());
+        assert_eq!(folded_value("let a = x * (6 / 2)"), binary(identifier("x"), BinaryOp::Mul, Expression::Integer(3)));
+    }
+
+    #[test]
+    fn test_runtime_errors_are_left_unfolded() {
+        assert_eq!(folded_value("let a = x / 0"), binary(Expression::Identifier("x".to_string()), BinaryOp::Div, Expression::Integer(0)));
+        assert_eq!(folded_value("let a = 7 / 0"), binary(Expression::Integer(7), BinaryOp::Div, Expression::Integer(0)));
+        assert_eq!(folded_value("let a = 7 % 0"), binary(Expression::Integer(7), BinaryOp::Mod, Expression::Integer(0)));
+        assert_eq!(folded_value("let a = 1.0 / 0"), binary(Expression::Float(1.0), BinaryOp::Div, Expression::Integer(0)));
+        assert_eq!(
+            folded_value("let a = 9223372036854775807 + 1"),
+            binary(Expression::Integer(i64::MAX), BinaryOp::Add, Expression::Integer(1))
+        );
+    }
+
+    #[test]
+    fn test_literal_conditions_pick_a_branch() {
+        let flag = Expression::Identifier("flag".to_string());
+        assert_eq!(folded_value("let a = true && flag"), flag);
+        assert_eq!(folded_value("let a = false || flag"), flag);
+        assert_eq!(folded_value("let a = false && flag"), Expression::Boolean(false));
+        assert_eq!(folded_value("let a = flag && true"), binary(flag.clone(), BinaryOp::And, Expression::Boolean(true)));
+
+        assert_eq!(
+            folded_value("if 1 < 2 { print(1 + 1) } else { print(0) }"),
+            Expression::Block(vec![Statement::Expression(Expression::Call("print".to_string(), vec![Expression::Integer(2)]))])
+        );
+        assert_eq!(folded_value("if false { print(1) }"), Expression::Block(vec![]));
+
+        // Function bodies are folded too
+        match &folded("fn f() -> Int { if true { 40 + 2 } else { 0 } }")[0] {
+            Statement::Function(function) => assert_eq!(
+                function.body,
+                vec![Statement::Expression(Expression::Block(vec![Statement::Expression(Expression::Integer(42))]))]
+            ),
+            other => panic!("Expected function, got {:?}", other),
+        }
+    }
+
+    fn function_body(source: &str) -> Vec<Statement> {
+        let mut program = Parser::new(source).parse_program().unwrap();
+        eliminate_dead_code(&mut program);
+        match program.statements.remove(0) {
+            Statement::Function(function) => function.body,
+            other => panic!("Expected function, got {:?}", other),
+        }
+    }
+
+    fn parsed(source: &str) -> Vec<Statement> {
+        Parser::new(source).parse_program().unwrap().statements
+    }
+
+    #[test]
+    fn test_code_after_a_jump_is_removed() {
+        assert_eq!(function_body("fn f() -> Int {\n return 1\n print(2)\n 3\n}"), parsed("return 1"));
+
+        let program = "fn f() {\n while true {\n  break\n  print(1)\n }\n print(2)\n}";
+        assert_eq!(function_body(program), parsed("while true {\n break\n}\nprint(2)"));
+    }
+
+    #[test]
+    fn test_unused_bindings_go_but_side_effects_stay() {
+        let body = function_body(
+            "fn f(x: Int) -> Int {\n let unused = x * 2\n let logged = print(x)\n let shown = \"${x}\"\n\
+             let ratio = 10 / x\n let first = [x, 1]\n let second = first\n let used = x + 1\n used\n}",
+        );
+        assert_eq!(body, parsed("let logged = print(x)\nlet shown = \"${x}\"\nlet ratio = 10 / x\nlet used = x + 1\nused"));
+
+        // A function can refer to a binding made after it; the final statement is the block's value
+        let mut program = Parser::new("fn show() { print(total) }\nlet total = 5\nlet last = 1").parse_program().unwrap();
+        eliminate_dead_code(&mut program);
+        assert_eq!(program.statements, parsed("fn show() { print(total) }\nlet total = 5\nlet last = 1"));
+    }
+
+    #[test]
+    fn test_untaken_branches_are_pruned() {
+        let mut program = Parser::new("if false { print(1) }\nif false { print(2) } else { print(3) }\nprint(4)")
+            .parse_program()
+            .unwrap();
+        eliminate_dead_code(&mut program);
+        let print = |n: i64| Statement::Expression(Expression::Call("print".to_string(), vec![Expression::Integer(n)]));
+        assert_eq!(program.statements, vec![Statement::Expression(Expression::Block(vec![print(3)])), print(4)]);
+    }
+
+    #[test]
+    fn test_opt_levels_choose_the_passes() {
+        let source = "let a = 2 * 3\nlet b = a\nprint(2 * 3)";
+        let optimized = |level: OptLevel| {
+            let mut program = Parser::new(source).parse_program().unwrap();
+            optimize(&mut program, level);
+            program.statements
+        };
+
+        assert_eq!(optimized(OptLevel::O0), parsed(source));
+        assert_eq!(optimized(OptLevel::O1), parsed("let a = 6\nlet b = a\nprint(6)"));
+        assert_eq!(optimized(OptLevel::O2), parsed("print(6)"));
+        assert_eq!(OptLevel::from_flag(3), OptLevel::O3);
+        assert_eq!(OptLevel::from_flag(9), OptLevel::O3);
+    }
+
+    fn with_passes(list: &str, source: &str) -> Vec<Statement> {
+        let mut program = Parser::new(source).parse_program().unwrap();
+        PassManager::parse(list).unwrap().run(&mut program);
+        program.statements
+    }
+
+    #[test]
+    fn test_passes_run_only_as_named() {
+        let source = "let a = 2 * 3\nlet b = a\nprint(2 * 3)";
+
+        // Folding alone leaves the unused binding for dead code elimination
+        assert_eq!(with_passes("fold", source), parsed("let a = 6\nlet b = a\nprint(6)"));
+        assert_eq!(with_passes("dce", source), parsed("print(2 * 3)"));
+        assert_eq!(with_passes("fold,dce", source), parsed("print(6)"));
+        assert_eq!(with_passes("", source), parsed(source));
+
+        let err = PassManager::parse("fold,unroll").unwrap_err();
+        assert_eq!(err, "Unknown optimization pass 'unroll' (expected one of: fold, dce, inline, cse)");
+        assert_eq!(PassManager::parse(" fold , dce ").unwrap().names(), vec!["fold", "dce"]);
+        assert_eq!(PassManager::for_level(OptLevel::O3).names(), vec!["fold", "inline", "fold", "cse", "dce"]);
+        assert!(PassManager::for_level(OptLevel::O0).is_empty());
+    }
+
+    #[test]
+    fn test_inlining_then_folding_evaluates_constant_calls() {
+        let source = "fn square(x: Int) -> Int { x * x }\nprint(square(3))";
+        let statements = with_passes("inline,fold", source);
+        assert_eq!(statements[1], parsed("print(9)")[0]);
+        assert_eq!(with_passes("fold,inline", source)[1], parsed("print(3 * 3)")[0]);
+    }
+
+    #[test]
+    fn test_repeated_computations_are_reused() {
+        assert_eq!(
+            with_passes("cse", "let a = x * y\nlet b = x * y\nprint(a + b)"),
+            parsed("let a = x * y\nlet b = a\nprint(a + b)")
+        );
+
+        // Rebinding an operand, or the binding that held the value, means computing it again
+        let rebound = "let a = x * y\nlet x = 2\nlet b = x * y";
+        assert_eq!(with_passes("cse", rebound), parsed(rebound));
+        let assigned = "let a = x * y\nassign(\"a\", 0)\nlet b = x * y";
+        assert_eq!(with_passes("cse", assigned), parsed(assigned));
+
+        // Calls might have side effects, so each one still runs
+        let calls = "let a = f(1) + 1\nlet b = f(1) + 1";
+        assert_eq!(with_passes("cse", calls), parsed(calls));
+    }
+}
@@ -1,12 +1,13 @@
 //! Package manager for the Logos programming language
 //! Handles multi-language dependencies and integrates with other language package managers
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::Path;
 use std::process::Command;
 use std::sync::{Arc, Mutex};
 use lazy_static::lazy_static;
+use serde::Deserialize;
 
 /// Represents different programming languages that can be integrated with Logos
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -153,42 +154,9 @@ impl PackageManager {
         self.parse_package(&content)
     }
     
-    /// Parses a package specification from TOML content
+    /// Parses a package specification from TOML content, read through the typed `Manifest`
     fn parse_package(&self, content: &str) -> Result<Package, String> {
-        // In a real implementation, this would parse TOML
-        // For now, we'll return a placeholder
-        Ok(Package {
-            name: "example".to_string(),
-            version: "0.1.0".to_string(),
-            description: "An example package".to_string(),
-            authors: vec!["Logos Team".to_string()],
-            license: "MIT".to_string(),
-            repository: Some("https://github.com/logos/example".to_string()),
-            homepage: Some("https://logos-lang.org".to_string()),
-            keywords: vec!["example".to_string(), "logos".to_string()],
-            categories: vec!["development".to_string()],
-            dependencies: HashMap::new(),
-            build_settings: BuildSettings {
-                build_script: Some("build.sh".to_string()),
-                test_script: Some("test.sh".to_string()),
-                install_script: Some("install.sh".to_string()),
-                target_architectures: vec!["x86_64".to_string(), "aarch64".to_string()],
-                optimization_levels: vec!["O2".to_string()],
-                features: vec!["default".to_string()],
-                exclude_files: vec![],
-                include_files: vec!["src/**/*".to_string()],
-            },
-            multilang_integration: MultiLangIntegration {
-                supported_languages: vec!["go".to_string(), "python".to_string(), "rust".to_string()],
-                language_specific_deps: HashMap::new(),
-                cross_language_bindings: vec![],
-                cached_binaries: HashMap::new(),
-            },
-            binaries: HashMap::new(),
-            source_files: vec!["src/lib.logos".to_string()],
-            checksum: "sha256-placeholder".to_string(),
-            cached: false,
-        })
+        Manifest::parse(content).map(Package::from)
     }
 
     /// Downloads and caches a package
@@ -201,7 +169,8 @@ impl PackageManager {
         let cache_path = format!("{}/{}-{}.lpkg", self.cache_dir, package_name, version);
         
         // Create a placeholder file
-        std::fs::write(&cache_path, format!("Placeholder for {} v{}", package_name, version))
+        let description = format!("Placeholder for {} v{}", package_name, version);
+        std::fs::write(&cache_path, LogosPackageFormat::create_spec(package_name, version, &description))
             .map_err(|e| format!("Could not create cached package: {}", e))?;
         
         Ok(cache_path)
@@ -551,26 +520,237 @@ license = "MIT"
 "#, name, version, description)
     }
 
-    /// Validates a package specification
-    pub fn validate_spec(spec: &str) -> Result<(), String> {
-        // In a real implementation, this would validate the TOML structure
-        // For now, we'll just check if it contains basic required fields
-        if !spec.contains("[package]") {
-            return Err("Missing [package] section".to_string());
+    /// Validates a package specification, returning it as a typed `Manifest`
+    pub fn validate_spec(spec: &str) -> Result<Manifest, String> {
+        Manifest::parse(spec)
+    }
+}
+
+/// File name of the project manifest
+pub const MANIFEST_FILE: &str = "logos.toml";
+
+/// Editions a manifest may declare
+pub const KNOWN_EDITIONS: &[&str] = &["2021", "2024"];
+
+/// Typed view of a project's `logos.toml`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Manifest {
+    pub package: ManifestPackage,
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, ManifestDependency>,
+    #[serde(default)]
+    pub features: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    pub multilang: ManifestMultiLang,
+}
+
+/// The `[package]` section of a manifest
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ManifestPackage {
+    pub name: String,
+    pub version: String,
+    #[serde(default = "default_edition")]
+    pub edition: String,
+    #[serde(default)]
+    pub authors: Vec<String>,
+    pub description: Option<String>,
+    pub license: Option<String>,
+}
+
+/// A single `[dependencies]` entry: either `name = "1.0"` or an inline table
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum ManifestDependency {
+    Version(String),
+    Detailed {
+        version: Option<String>,
+        path: Option<String>,
+        git: Option<String>,
+        language: Option<String>,
+        #[serde(default)]
+        features: Vec<String>,
+    },
+}
+
+/// The `[multilang]` section of a manifest
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct ManifestMultiLang {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub sync_languages: Vec<String>,
+}
+
+fn default_edition() -> String {
+    "2024".to_string()
+}
+
+impl Manifest {
+    /// Parses and validates manifest content
+    ///
+    /// Errors name the offending key, e.g. `package.version: ...`
+    pub fn parse(content: &str) -> Result<Manifest, String> {
+        let manifest: Manifest = toml::from_str(content)
+            .map_err(|e| format!("Invalid {}: {}", MANIFEST_FILE, e))?;
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    /// Loads `logos.toml` from a project directory
+    pub fn load(project_dir: &Path) -> Result<Manifest, String> {
+        let path = project_dir.join(MANIFEST_FILE);
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        Manifest::parse(&content)
+    }
+
+    /// Checks the semantic rules that deserialization alone can't express
+    pub fn validate(&self) -> Result<(), String> {
+        if self.package.name.trim().is_empty() {
+            return Err("package.name: must not be empty".to_string());
         }
-        
-        if !spec.contains("name =") {
-            return Err("Missing package name".to_string());
+
+        if !is_valid_semver(&self.package.version) {
+            return Err(format!(
+                "package.version: '{}' is not a valid semantic version (expected MAJOR.MINOR.PATCH)",
+                self.package.version
+            ));
         }
-        
-        if !spec.contains("version =") {
-            return Err("Missing package version".to_string());
+
+        if !KNOWN_EDITIONS.contains(&self.package.edition.as_str()) {
+            return Err(format!(
+                "package.edition: unknown edition '{}' (expected one of: {})",
+                self.package.edition,
+                KNOWN_EDITIONS.join(", ")
+            ));
         }
-        
+
+        for (name, dependency) in &self.dependencies {
+            match dependency {
+                ManifestDependency::Version(version) if version.trim().is_empty() => {
+                    return Err(format!("dependencies.{}: version must not be empty", name));
+                },
+                ManifestDependency::Detailed { version: None, path: None, git: None, .. } => {
+                    return Err(format!("dependencies.{}: must specify one of version, path or git", name));
+                },
+                _ => {},
+            }
+        }
+
+        for (feature, enables) in &self.features {
+            for entry in enables {
+                if !self.features.contains_key(entry) && !self.dependencies.contains_key(entry) {
+                    return Err(format!(
+                        "features.{}: '{}' is neither a feature nor a dependency",
+                        feature, entry
+                    ));
+                }
+            }
+        }
+
         Ok(())
     }
 }
 
+impl From<Manifest> for Package {
+    fn from(manifest: Manifest) -> Package {
+        let dependencies = manifest.dependencies.into_iter()
+            .map(|(name, dependency)| {
+                let spec = DependencySpec::from_manifest(&name, dependency);
+                (name, spec)
+            })
+            .collect();
+
+        Package {
+            name: manifest.package.name,
+            version: manifest.package.version,
+            description: manifest.package.description.unwrap_or_default(),
+            authors: manifest.package.authors,
+            license: manifest.package.license.unwrap_or_default(),
+            repository: None,
+            homepage: None,
+            keywords: Vec::new(),
+            categories: Vec::new(),
+            dependencies,
+            build_settings: BuildSettings {
+                build_script: None,
+                test_script: None,
+                install_script: None,
+                target_architectures: Vec::new(),
+                optimization_levels: Vec::new(),
+                features: manifest.features.into_keys().collect(),
+                exclude_files: Vec::new(),
+                include_files: Vec::new(),
+            },
+            multilang_integration: MultiLangIntegration {
+                supported_languages: manifest.multilang.sync_languages,
+                language_specific_deps: HashMap::new(),
+                cross_language_bindings: Vec::new(),
+                cached_binaries: HashMap::new(),
+            },
+            binaries: HashMap::new(),
+            source_files: Vec::new(),
+            checksum: String::new(),
+            cached: false,
+        }
+    }
+}
+
+impl DependencySpec {
+    /// Converts a `[dependencies]` entry; entries without a `language` are Logos packages
+    ///
+    /// A path or git source stands in for the version where there is none, as validation
+    /// guarantees one of the three.
+    fn from_manifest(name: &str, dependency: ManifestDependency) -> DependencySpec {
+        let (language, version) = match dependency {
+            ManifestDependency::Version(version) => (None, version),
+            ManifestDependency::Detailed { version, path, git, language, .. } => {
+                (language, version.or(path).or(git).unwrap_or_default())
+            },
+        };
+        let package = name.to_string();
+
+        match language.as_deref() {
+            None | Some("logos") => DependencySpec::Logos { version, registry: None },
+            Some("go") => DependencySpec::Go { package, version },
+            Some("python") => DependencySpec::Python { package, version },
+            Some("rust") => DependencySpec::Rust { crate_name: package, version },
+            Some("javascript") | Some("js") => DependencySpec::JavaScript { package, version },
+            Some("c") => DependencySpec::C { library: package, version: Some(version) },
+            Some("cpp") => DependencySpec::CPP { library: package, version: Some(version) },
+            Some("java") => DependencySpec::Java { artifact: package, version },
+            Some(other) => DependencySpec::Other { language: other.to_string(), spec: version },
+        }
+    }
+}
+
+/// Checks that a version string is `MAJOR.MINOR.PATCH` with optional
+/// `-prerelease` and `+build` suffixes, per semver 2.0
+fn is_valid_semver(version: &str) -> bool {
+    let (version, build) = match version.split_once('+') {
+        Some((version, build)) => (version, Some(build)),
+        None => (version, None),
+    };
+    let (core, prerelease) = match version.split_once('-') {
+        Some((core, prerelease)) => (core, Some(prerelease)),
+        None => (version, None),
+    };
+
+    let numbers: Vec<&str> = core.split('.').collect();
+    let core_ok = numbers.len() == 3 && numbers.iter().all(|n| {
+        !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()) && (n.len() == 1 || !n.starts_with('0'))
+    });
+
+    let identifiers_ok = |part: Option<&str>| match part {
+        None => true,
+        Some(part) => part.split('.').all(|id| {
+            !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        }),
+    };
+
+    core_ok && identifiers_ok(prerelease) && identifiers_ok(build)
+}
+
 /// Global package manager instance
 lazy_static! {
     pub static ref GLOBAL_PKG_MANAGER: Arc<Mutex<PackageManager>> = 
@@ -658,6 +838,32 @@ mod tests {
         let spec = LogosPackageFormat::create_spec("test", "0.1.0", "A test package");
         assert!(spec.contains("[package]"));
         assert!(spec.contains("name = \"test\""));
+
+        let manifest = LogosPackageFormat::validate_spec(&spec).unwrap();
+        assert_eq!(manifest.package.description.as_deref(), Some("A test package"));
+        let err = LogosPackageFormat::validate_spec(&spec.replace("0.1.0", "0.1")).unwrap_err();
+        assert!(err.starts_with("package.version:"), "{}", err);
+    }
+
+    #[test]
+    fn test_load_package_reads_the_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(MANIFEST_FILE);
+        let manifest = VALID_MANIFEST.replace("[features]", "numpy = { version = \"1.26\", language = \"python\" }\n\n[features]");
+        fs::write(&path, manifest).unwrap();
+
+        let mut manager = PackageManager::new().unwrap();
+        let package = manager.load_package(path.to_str().unwrap()).unwrap();
+        assert_eq!((package.name.as_str(), package.version.as_str()), ("demo", "0.1.0-alpha.1"));
+        assert_eq!(package.multilang_integration.supported_languages, vec!["python".to_string()]);
+
+        let mut resolved = manager.resolve_dependencies(&package).unwrap();
+        resolved.sort();
+        assert_eq!(resolved, vec!["logos:http:1.2", "logos:local_utils:../utils", "python:numpy:1.26"]);
+
+        // The typed manifest's errors come through, naming the key
+        fs::write(&path, VALID_MANIFEST.replace("edition = \"2024\"", "edition = \"1999\"")).unwrap();
+        assert!(manager.load_package(path.to_str().unwrap()).unwrap_err().starts_with("package.edition:"));
     }
     
     #[test]
@@ -665,4 +871,74 @@ mod tests {
         let manager = PackageManager::new().unwrap();
         assert!(!manager.is_package_cached("non-existent-package"));
     }
-}
\ No newline at end of file
+
+    const VALID_MANIFEST: &str = r#"
+[package]
+name = "demo"
+version = "0.1.0-alpha.1"
+edition = "2024"
+authors = ["Ada <ada@example.com>"]
+
+[dependencies]
+http = "1.2"
+local_utils = { path = "../utils" }
+
+[features]
+default = ["fast"]
+fast = ["http"]
+
+[multilang]
+enabled = true
+sync_languages = ["python"]
+"#;
+
+    fn manifest_error(from: &str, to: &str) -> String {
+        Manifest::parse(&VALID_MANIFEST.replace(from, to)).unwrap_err()
+    }
+
+    #[test]
+    fn test_manifest_parses_valid_file() {
+        let manifest = Manifest::parse(VALID_MANIFEST).unwrap();
+        assert_eq!(manifest.package.name, "demo");
+        assert_eq!(manifest.package.edition, "2024");
+        assert_eq!(manifest.dependencies["http"], ManifestDependency::Version("1.2".to_string()));
+        assert_eq!(manifest.features["default"], vec!["fast".to_string()]);
+        assert!(manifest.multilang.enabled);
+        assert_eq!(manifest.multilang.sync_languages, vec!["python".to_string()]);
+    }
+
+    #[test]
+    fn test_manifest_rejects_empty_name() {
+        let err = manifest_error(r#"name = "demo""#, r#"name = "  ""#);
+        assert!(err.starts_with("package.name:"), "{}", err);
+    }
+
+    #[test]
+    fn test_manifest_rejects_invalid_version() {
+        for bad in ["1.0", "01.2.3", "1.2.3-", "v1.2.3", "1.2.x"] {
+            let err = manifest_error(r#"version = "0.1.0-alpha.1""#, &format!(r#"version = "{}""#, bad));
+            assert!(err.starts_with("package.version:"), "{}: {}", bad, err);
+        }
+    }
+
+    #[test]
+    fn test_manifest_rejects_unknown_edition() {
+        let err = manifest_error(r#"edition = "2024""#, r#"edition = "2030""#);
+        assert!(err.starts_with("package.edition:"), "{}", err);
+    }
+
+    #[test]
+    fn test_manifest_rejects_bad_dependency_and_feature() {
+        let err = manifest_error(r#"http = "1.2""#, r#"http = """#);
+        assert!(err.starts_with("dependencies.http:"), "{}", err);
+
+        let err = manifest_error(r#"fast = ["http"]"#, r#"fast = ["missing"]"#);
+        assert!(err.starts_with("features.fast:"), "{}", err);
+    }
+
+    #[test]
+    fn test_manifest_reports_missing_key() {
+        let err = manifest_error(r#"version = "0.1.0-alpha.1""#, "");
+        assert!(err.contains("version"), "{}", err);
+    }
+}
@@ -5,11 +5,115 @@
 
 use crate::lexer::{Lexer, Token};
 use crate::ast::*;
+use crate::format_spec::{split_placeholder, FormatSpec};
+use std::fmt;
+
+/// Maximum nesting depth of statements, expressions, types and patterns
+///
+/// In a debug build one level can take about 30 KiB of stack (a nested `match` or
+/// struct literal), so this keeps the deepest input allowed well inside the 2 MiB a
+/// spawned thread gets by default.
+pub const MAX_NESTING_DEPTH: usize = 48;
+
+/// Maximum number of `else if` links in one chain
+///
+/// Every link nests the next `if` inside the previous else branch, but costs the
+/// parser only a couple of small frames, so chains are bounded separately and may run
+/// well past `MAX_NESTING_DEPTH`.
+pub const MAX_ELSE_IF_CHAIN: usize = 128;
+
+/// Maximum number of alternatives in one or-pattern
+///
+/// `a | b | ...` is parsed in a loop but builds a nested `Pattern::Or` per `|`, which
+/// the checker, the runtime and drop all walk recursively.
+pub const MAX_OR_PATTERN_ALTERNATIVES: usize = 128;
+
+/// A syntax error and where in the source it was found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub message: String,
+    pub line: usize,      // 1-based, like the column
+    pub column: usize,
+    pub snippet: String,  // The source line the error is on
+}
+
+impl ParseError {
+    /// Creates an error at `location`, quoting its line of `source`
+    pub fn new(message: String, location: SourceLocation, source: &str) -> Self {
+        let snippet = source.lines().nth(location.line.saturating_sub(1)).unwrap_or("");
+        ParseError { message, line: location.line, column: location.column, snippet: snippet.to_string() }
+    }
+}
+
+/// Renders the error with its line of source and a caret under the column, e.g.
+///
+/// ```text
+/// 3:12: Expected ':' after field name
+///   |
+/// 3 | struct P { x Int }
+///   |              ^
+/// ```
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let gutter = " ".repeat(self.line.to_string().len());
+        // Tabs before the column are kept so the caret lines up however they are shown
+        let pad: String = self.snippet.chars()
+            .take(self.column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        writeln!(f, "{}:{}: {}", self.line, self.column, self.message)?;
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", self.line, self.snippet)?;
+        write!(f, "{} | {}^", gutter, pad)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<ParseError> for String {
+    fn from(error: ParseError) -> String {
+        error.to_string()
+    }
+}
+
+/// Languages an `extern` declaration may name, matching the FFI's call types
+pub const EXTERN_ABIS: &[&str] = &["c", "rust", "go", "python", "javascript", "java", "wasm"];
 
 /// The Parser struct processes tokens and converts them into AST nodes
 pub struct Parser<'a> {
+    source: &'a str,         // The text being parsed, quoted in errors
     lexer: Lexer<'a>,        // The lexer that provides tokens
     current_token: Token,    // The current token being processed
+    location: SourceLocation, // Where the current token starts
+    depth: usize,            // Current nesting depth, bounded by MAX_NESTING_DEPTH
+    else_ifs: usize,         // `else if` links open right now, bounded by MAX_ELSE_IF_CHAIN
+}
+
+/// Iterator over top-level items, returned by `Parser::parse_items_lazy`
+pub struct LazyItems<'p, 'a> {
+    parser: &'p mut Parser<'a>,
+    failed: bool,  // Set once an error has been yielded; parsing does not resume after it
+}
+
+impl<'p, 'a> Iterator for LazyItems<'p, 'a> {
+    type Item = Result<Statement, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        // Semicolons between items are skipped, as in parse_program
+        while matches!(self.parser.current_token, Token::Semicolon) {
+            self.parser.advance(); // consume ;
+        }
+        if matches!(self.parser.current_token, Token::Eof) {
+            return None;
+        }
+
+        let item = self.parser.parse_statement().map_err(|message| self.parser.error(message));
+        self.failed = item.is_err();
+        Some(item)
+    }
 }
 
 impl<'a> Parser<'a> {
@@ -23,30 +127,158 @@ impl<'a> Parser<'a> {
     pub fn new(input: &'a str) -> Self {
         let mut lexer = Lexer::new(input);
         let current_token = lexer.next_token();
+        let (line, column) = lexer.token_position();
 
         Self {
+            source: input,
             lexer,
             current_token,
+            location: SourceLocation { line, column },
+            depth: 0,
+            else_ifs: 0,
         }
     }
 
     /// Parses the entire input program into an AST
     /// 
     /// # Returns
-    /// A Result containing the Program AST node if successful, or an error string
-    pub fn parse_program(&mut self) -> Result<Program, String> {
+    /// A Result containing the Program AST node if successful, or an error located at
+    /// the token where parsing stopped
+    pub fn parse_program(&mut self) -> Result<Program, ParseError> {
         let mut statements = Vec::new();
 
         while !matches!(self.current_token, Token::Eof) {
-            statements.push(self.parse_statement()?);
+            if matches!(self.current_token, Token::Semicolon) {
+                self.advance(); // consume ;
+                continue;
+            }
+            statements.push(self.parse_statement().map_err(|message| self.error(message))?);
         }
 
         Ok(Program { statements })
     }
 
+    /// Parses the entire program, continuing past syntax errors
+    ///
+    /// After an error, tokens are skipped up to the next statement boundary and parsing
+    /// resumes there, so a single pass reports every error instead of only the first.
+    /// The program holds the statements that did parse.
+    pub fn parse_program_recovering(&mut self) -> (Program, Vec<ParseError>) {
+        let mut statements = Vec::new();
+        let mut errors = Vec::new();
+
+        while !matches!(self.current_token, Token::Eof) {
+            if matches!(self.current_token, Token::Semicolon) {
+                self.advance(); // consume ;
+                continue;
+            }
+            let start = self.location;
+            match self.parse_statement() {
+                Ok(statement) => statements.push(statement),
+                Err(message) => {
+                    errors.push(self.error(message));
+                    self.synchronize(start);
+                },
+            }
+        }
+
+        (Program { statements }, errors)
+    }
+
+    /// Parses the program, recording the lines each top-level statement spans
+    ///
+    /// Lines start at 1 and both ends are inclusive. The AST keeps no comments, so tools
+    /// that rewrite a program from its statements use these to find the text between them.
+    pub fn parse_program_with_lines(&mut self) -> Result<Vec<(Statement, usize, usize)>, ParseError> {
+        let mut statements = Vec::new();
+
+        while !matches!(self.current_token, Token::Eof) {
+            if matches!(self.current_token, Token::Semicolon) {
+                self.advance(); // consume ;
+                continue;
+            }
+            let start = self.location.line;
+            let statement = self.parse_statement().map_err(|message| self.error(message))?;
+            // The lexer has read one token past the statement, so the one before that is its last
+            statements.push((statement, start, self.lexer.previous_end_line()));
+        }
+
+        Ok(statements)
+    }
+
+    /// Parses the input one top-level item at a time
+    ///
+    /// Unlike `parse_program`, nothing is retained between items: each call to
+    /// `next` parses and hands over a single top-level statement, so tools that
+    /// only need signatures can process and drop items as they go. The iterator
+    /// ends at end of input, or after yielding the first parse error.
+    pub fn parse_items_lazy(&mut self) -> LazyItems<'_, 'a> {
+        LazyItems { parser: self, failed: false }
+    }
+
+    /// Runs `parse` one nesting level deeper, failing once `MAX_NESTING_DEPTH` is reached
+    ///
+    /// The parser is recursive descent, so deeply nested input such as `((((...` or
+    /// `----...1` would otherwise overflow the stack instead of producing an error.
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T, String>) -> Result<T, String> {
+        if self.depth >= MAX_NESTING_DEPTH {
+            return Err(format!("Nesting too deep (limit is {} levels)", MAX_NESTING_DEPTH));
+        }
+
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Runs `parse` for one more `else if` link, failing once `MAX_ELSE_IF_CHAIN` is reached
+    ///
+    /// Like `nested`, but against the chain's own limit rather than the nesting depth.
+    fn chained<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T, String>) -> Result<T, String> {
+        if self.else_ifs >= MAX_ELSE_IF_CHAIN {
+            return Err(format!("else if chain too long (limit is {} links)", MAX_ELSE_IF_CHAIN));
+        }
+
+        self.else_ifs += 1;
+        let result = parse(self);
+        self.else_ifs -= 1;
+        result
+    }
+
     /// Advances the parser to the next token from the lexer
     fn advance(&mut self) {
         self.current_token = self.lexer.next_token();
+        let (line, column) = self.lexer.token_position();
+        self.location = SourceLocation { line, column };
+    }
+
+    /// Skips tokens after an error until the next statement can start: just past a `;`
+    /// or `}`, or at a keyword that begins a statement
+    ///
+    /// If the failed statement started at `start` and consumed nothing, one token is
+    /// skipped regardless, so parsing can't fail at the same place forever.
+    fn synchronize(&mut self, start: SourceLocation) {
+        let mut moved = self.location != start;
+        loop {
+            match self.current_token {
+                Token::Eof => return,
+                Token::Semicolon | Token::RightBrace => {
+                    self.advance();
+                    return;
+                },
+                Token::Fn | Token::Let | Token::Mut | Token::Const | Token::Struct | Token::Class | Token::Enum
+                | Token::Trait | Token::Impl | Token::Type | Token::While | Token::If | Token::Match | Token::Return
+                | Token::Hash if moved => return,
+                _ => {},
+            }
+            self.advance();
+            moved = true;
+        }
+    }
+
+    /// Locates an error message at the current token, where parsing stopped
+    fn error(&self, message: String) -> ParseError {
+        ParseError::new(message, self.location, self.source)
     }
 
     /// Gets a reference to the current token
@@ -62,6 +294,11 @@ impl<'a> Parser<'a> {
     /// # Returns
     /// A Result containing the Statement AST node if successful, or an error string
     pub fn parse_statement(&mut self) -> Result<Statement, String> {
+        self.nested(Self::parse_statement_kind)
+    }
+
+    /// Dispatches on the current token to parse the statement it starts
+    fn parse_statement_kind(&mut self) -> Result<Statement, String> {
         match self.current_token() {
             Token::Let | Token::Mut => self.parse_variable_declaration(),
             Token::Const => self.parse_const_declaration(),
@@ -70,19 +307,42 @@ impl<'a> Parser<'a> {
             Token::Match => self.parse_match_statement(),
             Token::Actor => self.parse_actor(),
             Token::Effect => self.parse_effect(),
+            Token::Struct => self.parse_struct(),
             Token::Class => self.parse_class(),
             Token::Trait => self.parse_trait(),
             Token::Impl => self.parse_impl(),
             Token::Macro => self.parse_macro(),
             Token::Enum => self.parse_enum(),
             Token::Type => self.parse_type_alias(),
+            Token::Extern => self.parse_extern(),
+            Token::Hash => self.parse_attributed_item(),
+            Token::Pub => self.parse_public_item(),
+            Token::While => self.parse_while_statement(None),
+            Token::Label(label) => {
+                let label = label.clone();
+                self.advance(); // consume label
+                if !matches!(self.current_token(), Token::Colon) {
+                    return Err(format!("Expected ':' after loop label '{}", label));
+                }
+                self.advance(); // consume :
+                if !matches!(self.current_token(), Token::While) {
+                    return Err(format!("Expected a loop after label '{}", label));
+                }
+                self.parse_while_statement(Some(label))
+            },
+            Token::Identifier(name) if name == "assert" || name == "assert_eq" => self.parse_assert(),
+            Token::Defer => {
+                self.advance(); // consume defer
+                let deferred = self.parse_statement()?;
+                Ok(Statement::Defer(Box::new(deferred)))
+            },
             Token::Break => {
                 self.advance(); // consume break
-                Ok(Statement::Break)
+                Ok(Statement::Break(self.parse_loop_label()))
             },
             Token::Continue => {
                 self.advance(); // consume continue
-                Ok(Statement::Continue)
+                Ok(Statement::Continue(self.parse_loop_label()))
             },
             Token::Return => {
                 self.advance(); // consume return
@@ -104,6 +364,144 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parses an item preceded by attributes: #[attr] #[attr(args)] item
+    ///
+    /// Attributes are attached to the item's definition; they are only allowed on
+    /// `fn`, `class`, `trait`, `impl` and `enum` items.
+    fn parse_attributed_item(&mut self) -> Result<Statement, String> {
+        let mut attributes = Vec::new();
+        while matches!(self.current_token(), Token::Hash) {
+            attributes.push(self.parse_attribute()?);
+        }
+
+        let mut item = match self.current_token() {
+            Token::Fn | Token::Struct | Token::Class | Token::Trait | Token::Impl | Token::Enum | Token::Pub => self.parse_statement()?,
+            other => return Err(format!("Attributes must be followed by an item (fn, struct, class, trait, impl or enum), got {:?}", other)),
+        };
+
+        match &mut item {
+            Statement::Function(def) => def.attributes = attributes,
+            Statement::Class(def) => def.attributes = attributes,
+            Statement::Trait(def) => def.attributes = attributes,
+            Statement::Implementation(def) => def.attributes = attributes,
+            Statement::Enum(def) => def.attributes = attributes,
+            _ => return Err("Attributes are not supported on this item".to_string()),
+        }
+
+        Ok(item)
+    }
+
+    /// Parses an item declared public: pub fn, pub struct, pub class, pub trait, pub enum or pub type
+    fn parse_public_item(&mut self) -> Result<Statement, String> {
+        self.advance(); // consume pub
+
+        let mut item = match self.current_token() {
+            Token::Fn | Token::Struct | Token::Class | Token::Trait | Token::Enum | Token::Type => self.parse_statement_kind()?,
+            other => return Err(format!("`pub` must be followed by fn, struct, class, trait, enum or type, got {:?}", other)),
+        };
+
+        match &mut item {
+            Statement::Function(def) => def.is_public = true,
+            Statement::Class(def) => def.access_modifier = AccessModifier::Public,
+            Statement::Trait(def) => def.is_public = true,
+            Statement::Enum(def) => def.access_modifier = AccessModifier::Public,
+            Statement::TypeAlias(def) => def.access_modifier = AccessModifier::Public,
+            _ => return Err("`pub` is not supported on this item".to_string()),
+        }
+
+        Ok(item)
+    }
+
+    /// Parses a method inside a class, trait or impl body, with any attributes before it
+    fn parse_method_def(&mut self) -> Result<FunctionDef, String> {
+        let mut attributes = Vec::new();
+        while matches!(self.current_token(), Token::Hash) {
+            attributes.push(self.parse_attribute()?);
+        }
+
+        if !matches!(self.current_token(), Token::Fn) {
+            return Err(format!("Attributes must be followed by a method, got {:?}", self.current_token()));
+        }
+
+        let mut method = self.parse_function_def()?;
+        method.attributes = attributes;
+        Ok(method)
+    }
+
+    /// Parses a single attribute: #[name] or #[name(arg, key = "value", nested(arg))]
+    fn parse_attribute(&mut self) -> Result<Attribute, String> {
+        self.advance(); // consume #
+
+        if !matches!(self.current_token(), Token::LeftBracket) {
+            return Err("Expected '[' after '#'".to_string());
+        }
+        self.advance(); // consume [
+
+        let name = match self.current_token().clone() {
+            Token::Identifier(name) => {
+                self.advance(); // consume name
+                name
+            }
+            _ => return Err("Expected attribute name".to_string()),
+        };
+
+        let args = if matches!(self.current_token(), Token::LeftParen) {
+            self.parse_attribute_args()?
+        } else {
+            Vec::new()
+        };
+
+        if !matches!(self.current_token(), Token::RightBracket) {
+            return Err(format!("Expected ']' to close attribute '{}'", name));
+        }
+        self.advance(); // consume ]
+
+        Ok(Attribute { name, args })
+    }
+
+    /// Parses a parenthesized attribute argument list, including the parentheses
+    fn parse_attribute_args(&mut self) -> Result<Vec<AttributeArg>, String> {
+        self.advance(); // consume (
+
+        let mut args = Vec::new();
+        while !matches!(self.current_token(), Token::RightParen) {
+            let word = match self.current_token().clone() {
+                Token::Identifier(word) => word,
+                Token::String(text) => text,
+                Token::Integer(value) => value.to_string(),
+                other => return Err(format!("Unexpected {:?} in attribute arguments", other)),
+            };
+            self.advance(); // consume word
+
+            let arg = match self.current_token() {
+                Token::Assign => {
+                    self.advance(); // consume =
+                    let value = match self.current_token().clone() {
+                        Token::String(value) | Token::Identifier(value) => value,
+                        Token::Integer(value) => value.to_string(),
+                        Token::True => "true".to_string(),
+                        Token::False => "false".to_string(),
+                        other => return Err(format!("Expected value after '{} =', got {:?}", word, other)),
+                    };
+                    self.advance(); // consume value
+                    AttributeArg::KeyValue(word, value)
+                }
+                Token::LeftParen => AttributeArg::Nested(word, self.parse_attribute_args()?),
+                _ => AttributeArg::Word(word),
+            };
+            args.push(arg);
+
+            match self.current_token() {
+                Token::Comma => self.advance(), // consume ,
+                Token::RightParen => {}
+                other => return Err(format!("Expected ',' or ')' in attribute arguments, got {:?}", other)),
+            }
+        }
+        self.advance(); // consume )
+
+        Ok(args)
+    }
+
     /// Parses an expression (the right-hand side of assignments, function arguments, etc.)
     ///
     /// # Returns
@@ -112,6 +510,23 @@ impl<'a> Parser<'a> {
         self.parse_assignment()
     }
 
+    /// Decides whether `{` after `name` opens a struct literal rather than a block
+    ///
+    /// Only capitalised names qualify, and the brace must be followed by `}` or by
+    /// `field:`, so `if ready { ... }` and `match value { ... }` are unaffected.
+    fn starts_struct_literal(&self, name: &str) -> bool {
+        if !name.chars().next().map_or(false, char::is_uppercase) {
+            return false;
+        }
+
+        let mut lookahead = self.lexer.clone();
+        match lookahead.next_token() {
+            Token::RightBrace => true,
+            Token::Identifier(_) => matches!(lookahead.next_token(), Token::Colon),
+            _ => false,
+        }
+    }
+
     /// Parses a struct literal expression
     ///
     /// # Returns
@@ -139,6 +554,9 @@ impl<'a> Parser<'a> {
                 // Parse additional fields
                 while matches!(self.current_token(), Token::Comma) {
                     self.advance(); // consume ,
+                    if matches!(self.current_token(), Token::RightBrace) {
+                        break; // trailing comma
+                    }
 
                     if let Token::Identifier(field_name) = self.current_token().clone() {
                         self.advance(); // consume field name
@@ -175,11 +593,11 @@ impl<'a> Parser<'a> {
 
         if matches!(self.current_token(), Token::Assign) {
             self.advance(); // consume =
-            let right = self.parse_assignment()?;
+            let right = self.nested(Self::parse_assignment)?; // right associative
             // For now, treat as a function call to an assignment function
-            if let Expression::Identifier(name) = left {
+            if let Expression::Identifier(name) = &mut left {
                 Ok(Expression::Call("assign".to_string(), vec![
-                    Expression::String(name),
+                    Expression::String(std::mem::take(name)),
                     right
                 ]))
             } else {
@@ -200,21 +618,25 @@ impl<'a> Parser<'a> {
         loop {
             match self.current_token() {
                 Token::PipeForward => {
+                    let location = self.location;
                     self.advance(); // consume |>
                     let right = self.parse_range()?;
                     left = Expression::BinaryOp(
                         Box::new(left),
                         BinaryOp::PipeForward,
-                        Box::new(right)
+                        Box::new(right),
+                        ParsedAt(Some(location))
                     );
                 }
                 Token::PipeBackward => {
+                    let location = self.location;
                     self.advance(); // consume <|
                     let right = self.parse_range()?;
                     left = Expression::BinaryOp(
                         Box::new(left),
                         BinaryOp::PipeBackward,
-                        Box::new(right)
+                        Box::new(right),
+                        ParsedAt(Some(location))
                     );
                 }
                 _ => break,
@@ -229,17 +651,52 @@ impl<'a> Parser<'a> {
     /// # Returns
     /// A Result containing the Expression AST node if successful, or an error string
     fn parse_range(&mut self) -> Result<Expression, String> {
-        let mut left = self.parse_equality()?;
+        let left = self.parse_logical_or()?;
 
         if matches!(self.current_token(), Token::Range) {
+            let location = self.location;
             self.advance(); // consume ..
-            let right = self.parse_range()?; // right associative
-            Ok(Expression::BinaryOp(Box::new(left), BinaryOp::Range, Box::new(right)))
+            let right = self.nested(Self::parse_range)?; // right associative
+            Ok(Expression::BinaryOp(Box::new(left), BinaryOp::Range, Box::new(right), ParsedAt(Some(location))))
         } else {
             Ok(left)
         }
     }
 
+    /// Parses logical or expressions (left || right)
+    /// 
+    /// # Returns
+    /// A Result containing the Expression AST node if successful, or an error string
+    fn parse_logical_or(&mut self) -> Result<Expression, String> {
+        let mut left = self.parse_logical_and()?;
+
+        while matches!(self.current_token(), Token::Or) {
+            let location = self.location;
+            self.advance(); // consume ||
+            let right = self.parse_logical_and()?;
+            left = Expression::BinaryOp(Box::new(left), BinaryOp::Or, Box::new(right), ParsedAt(Some(location)));
+        }
+
+        Ok(left)
+    }
+
+    /// Parses logical and expressions (left && right), which bind tighter than ||
+    /// 
+    /// # Returns
+    /// A Result containing the Expression AST node if successful, or an error string
+    fn parse_logical_and(&mut self) -> Result<Expression, String> {
+        let mut left = self.parse_equality()?;
+
+        while matches!(self.current_token(), Token::And) {
+            let location = self.location;
+            self.advance(); // consume &&
+            let right = self.parse_equality()?;
+            left = Expression::BinaryOp(Box::new(left), BinaryOp::And, Box::new(right), ParsedAt(Some(location)));
+        }
+
+        Ok(left)
+    }
+
     /// Parses equality expressions (==, !=)
     /// 
     /// # Returns
@@ -254,9 +711,10 @@ impl<'a> Parser<'a> {
                 _ => break,
             };
 
+            let location = self.location;
             self.advance(); // consume operator
             let right = self.parse_comparison()?;
-            left = Expression::BinaryOp(Box::new(left), op, Box::new(right));
+            left = Expression::BinaryOp(Box::new(left), op, Box::new(right), ParsedAt(Some(location)));
         }
 
         Ok(left)
@@ -279,9 +737,10 @@ impl<'a> Parser<'a> {
                 _ => break,
             };
 
+            let location = self.location;
             self.advance(); // consume operator
             let right = self.parse_term()?;
-            left = Expression::BinaryOp(Box::new(left), op, Box::new(right));
+            left = Expression::BinaryOp(Box::new(left), op, Box::new(right), ParsedAt(Some(location)));
         }
 
         Ok(left)
@@ -307,9 +766,10 @@ impl<'a> Parser<'a> {
                 _ => break,
             };
 
+            let location = self.location;
             self.advance(); // consume operator
             let right = self.parse_factor()?;
-            left = Expression::BinaryOp(Box::new(left), op, Box::new(right));
+            left = Expression::BinaryOp(Box::new(left), op, Box::new(right), ParsedAt(Some(location)));
         }
 
         Ok(left)
@@ -330,9 +790,10 @@ impl<'a> Parser<'a> {
                 _ => break,
             };
 
+            let location = self.location;
             self.advance(); // consume operator
             let right = self.parse_exponentiation()?;
-            left = Expression::BinaryOp(Box::new(left), op, Box::new(right));
+            left = Expression::BinaryOp(Box::new(left), op, Box::new(right), ParsedAt(Some(location)));
         }
 
         Ok(left)
@@ -343,12 +804,20 @@ impl<'a> Parser<'a> {
     /// # Returns
     /// A Result containing the Expression AST node if successful, or an error string
     fn parse_exponentiation(&mut self) -> Result<Expression, String> {
+        // Every level of expression nesting (parentheses, unary chains, `^` chains)
+        // passes through here, so this is where the nesting limit is enforced
+        self.nested(Self::parse_exponentiation_operand)
+    }
+
+    /// Parses `unary (^ exponentiation)?`; see `parse_exponentiation`
+    fn parse_exponentiation_operand(&mut self) -> Result<Expression, String> {
         let left = self.parse_unary()?;
 
         if matches!(self.current_token(), Token::Power) {
+            let location = self.location;
             self.advance(); // consume ^
             let right = self.parse_exponentiation()?; // right associative
-            Ok(Expression::BinaryOp(Box::new(left), BinaryOp::Power, Box::new(right)))
+            Ok(Expression::BinaryOp(Box::new(left), BinaryOp::Power, Box::new(right), ParsedAt(Some(location))))
         } else {
             Ok(left)
         }
@@ -370,6 +839,12 @@ impl<'a> Parser<'a> {
                 let expr = self.parse_exponentiation()?;
                 Ok(Expression::UnaryOp(UnaryOp::Not, Box::new(expr)))
             }
+            Token::Multiply => {
+                // Handle dereference: *expression
+                self.advance();
+                let expr = self.parse_exponentiation()?;
+                Ok(Expression::UnaryOp(UnaryOp::Deref, Box::new(expr)))
+            }
             Token::LeftArrow => {
                 // Handle channel receive: <-expression
                 self.advance();
@@ -389,19 +864,27 @@ impl<'a> Parser<'a> {
 
         loop {
             match self.current_token() {
-                Token::LeftParen => {
+                // A `(` that starts a new line begins the next statement, not a call
+                Token::LeftParen if self.location.line == self.lexer.previous_end_line() => {
                     self.advance(); // consume (
                     let args = self.parse_arguments()?;
-                    expr = match expr {
+                    expr = match &mut expr {
                         Expression::Identifier(name) => {
-                            Expression::Call(name, args)
+                            Expression::Call(std::mem::take(name), args)
                         },
                         _ => return Err("Expected function name".to_string()),
                     };
                 }
                 Token::Dot => {
                     self.advance(); // consume .
-                    if let Token::Identifier(method_name) = self.current_token().clone() {
+                    // `send` and `close` are keywords, but after a dot they name channel methods
+                    let member = match self.current_token().clone() {
+                        Token::Identifier(name) => Some(name),
+                        Token::Send => Some("send".to_string()),
+                        Token::Close => Some("close".to_string()),
+                        _ => None,
+                    };
+                    if let Some(method_name) = member {
                         self.advance(); // consume method name
                         if matches!(self.current_token(), Token::LeftParen) {
                             self.advance(); // consume (
@@ -421,6 +904,24 @@ impl<'a> Parser<'a> {
                         return Err("Expected method or field name".to_string());
                     }
                 }
+                Token::Question => {
+                    self.advance(); // consume ?
+                    expr = Expression::Propagate(Box::new(expr));
+                }
+                Token::LeftBracket => {
+                    self.advance(); // consume [
+                    let mut index = self.parse_expression()?;
+                    if !matches!(self.current_token(), Token::RightBracket) {
+                        return Err(format!("Expected ']' after index, got {:?}", self.current_token()));
+                    }
+                    self.advance(); // consume ]
+                    expr = match &mut index {
+                        Expression::BinaryOp(start, BinaryOp::Range, end, _) => {
+                            Expression::Slice(Box::new(expr), std::mem::take(start), std::mem::take(end))
+                        }
+                        _ => Expression::Index(Box::new(expr), Box::new(index)),
+                    };
+                }
                 _ => break,
             }
         }
@@ -444,6 +945,9 @@ impl<'a> Parser<'a> {
 
         while matches!(self.current_token(), Token::Comma) {
             self.advance(); // consume ,
+            if matches!(self.current_token(), Token::RightParen) {
+                break; // trailing comma
+            }
             args.push(self.parse_expression()?);
         }
 
@@ -470,8 +974,18 @@ impl<'a> Parser<'a> {
                 Ok(Expression::Float(value))
             }
             Token::String(value) => {
+                let location = self.location;
+                self.advance();
+                if value.contains("${") {
+                    Self::parse_interpolated_string(&value)
+                        .map_err(|e| format!("{} in string at {}", e, location))
+                } else {
+                    Ok(Expression::String(value))
+                }
+            }
+            Token::ByteString(bytes) => {
                 self.advance();
-                Ok(Expression::String(value))
+                Ok(Expression::Bytes(bytes))
             }
             Token::True => {
                 self.advance();
@@ -491,7 +1005,36 @@ impl<'a> Parser<'a> {
             }
             Token::Identifier(name) => {
                 self.advance();
-                Ok(Expression::Identifier(name))
+
+                // Join a path such as `Box::new` into a single qualified name
+                let mut path = name;
+                while matches!(self.current_token(), Token::DoubleColon) {
+                    self.advance(); // consume ::
+                    match self.current_token().clone() {
+                        Token::Identifier(segment) => {
+                            self.advance();
+                            path.push_str("::");
+                            path.push_str(&segment);
+                        }
+                        _ => return Err(format!("Expected identifier after '{}::'", path)),
+                    }
+                }
+
+                if matches!(self.current_token(), Token::LeftBrace) && self.starts_struct_literal(&path) {
+                    return self.parse_struct_literal(path);
+                }
+
+                // Macro invocation: name!(args); `!` followed by anything else is left alone
+                if matches!(self.current_token(), Token::Not)
+                    && matches!(self.lexer.clone().next_token(), Token::LeftParen)
+                {
+                    self.advance(); // consume !
+                    self.advance(); // consume (
+                    let args = self.parse_arguments()?;
+                    return Ok(Expression::MacroInvocation(path, args));
+                }
+
+                Ok(Expression::Identifier(path))
             }
             Token::LeftParen => {
                 self.advance(); // consume (
@@ -511,6 +1054,9 @@ impl<'a> Parser<'a> {
 
                     while matches!(self.current_token(), Token::Comma) {
                         self.advance(); // consume ,
+                        if matches!(self.current_token(), Token::RightParen) {
+                            break; // trailing comma
+                        }
                         elements.push(self.parse_expression()?);
                     }
 
@@ -529,6 +1075,10 @@ impl<'a> Parser<'a> {
                     Ok(first_expr)
                 }
             }
+            Token::Try => self.parse_try(),
+            Token::If => self.parse_if_expression(),
+            Token::Match => self.parse_match_expression(),
+            Token::Select => self.parse_select_expression(),
             Token::LeftBracket => {
                 // Parse array literal
                 self.advance(); // consume [
@@ -537,8 +1087,23 @@ impl<'a> Parser<'a> {
                 if !matches!(self.current_token(), Token::RightBracket) {
                     elements.push(self.parse_expression()?);
 
+                    // Repeated array: [value; length]
+                    if matches!(self.current_token(), Token::Semicolon) {
+                        self.advance(); // consume ;
+                        let length = self.parse_expression()?;
+                        if !matches!(self.current_token(), Token::RightBracket) {
+                            return Err("Expected ']'".to_string());
+                        }
+                        self.advance(); // consume ]
+                        let value = elements.pop().expect("value was just parsed");
+                        return Ok(Expression::ArrayRepeat(Box::new(value), Box::new(length)));
+                    }
+
                     while matches!(self.current_token(), Token::Comma) {
                         self.advance(); // consume ,
+                        if matches!(self.current_token(), Token::RightBracket) {
+                            break; // trailing comma
+                        }
                         elements.push(self.parse_expression()?);
                     }
                 }
@@ -551,25 +1116,8 @@ impl<'a> Parser<'a> {
                 Ok(Expression::Array(elements))
             }
             Token::LeftBrace => {
-                // Parse block or struct literal
-                // Check if this is preceded by an identifier to determine if it's a struct literal
-                // For now, we'll assume it's a block if not preceded by an identifier that looks like a struct name
-
-                // We need to peek ahead to see if there's a struct name before the brace
-                // Since we can't peek easily, we'll assume it's a block for now
-                // In a more sophisticated parser, we'd track if the previous token was a struct name
-                self.advance(); // consume {
-                let mut statements = Vec::new();
-
-                while !matches!(self.current_token(), Token::RightBrace) && !matches!(self.current_token(), Token::Eof) {
-                    statements.push(self.parse_statement()?);
-                }
-
-                if !matches!(self.current_token(), Token::RightBrace) {
-                    return Err("Expected '}'".to_string());
-                }
-                self.advance(); // consume }
-
+                // A block expression; struct literals are recognised after their name
+                let statements = self.parse_block()?;
                 Ok(Expression::Block(statements))
             }
             Token::Async => {
@@ -1108,6 +1656,7 @@ impl<'a> Parser<'a> {
                 let channel_type = self.parse_type()?;
                 Ok(Expression::ChannelCreate(Box::new(channel_type)))
             }
+            Token::Error(message) => Err(message),
             _ => Err(format!("Unexpected token: {:?}", self.current_token())),
         }
     }
@@ -1257,6 +1806,9 @@ impl<'a> Parser<'a> {
             return Err("Expected function name".to_string());
         };
 
+        // Type parameters, if any: fn id<T>(x: T) -> T
+        let generic_params = self.parse_generic_params()?;
+
         if !matches!(self.current_token(), Token::LeftParen) {
             return Err("Expected '('".to_string());
         }
@@ -1272,6 +1824,8 @@ impl<'a> Parser<'a> {
             None
         };
 
+        let effect_annotations = self.parse_effect_clause()?;
+
         let is_async = false; // Simplified for now
 
         if !matches!(self.current_token(), Token::LeftBrace) {
@@ -1288,11 +1842,94 @@ impl<'a> Parser<'a> {
             is_async,
             is_public: false, // Default to private
             is_awaitable: false,  // Default to not awaitable
-            effect_annotations: vec![], // Default to no effect annotations
-            generic_params: vec![], // Default to no generic parameters
+            effect_annotations,
+            generic_params,
+            attributes: vec![], // Attached by parse_attributed_item
         }))
     }
 
+    /// Parses the effects a function declares after its signature, if any: `with Log, Io`
+    fn parse_effect_clause(&mut self) -> Result<Vec<EffectAnnotation>, String> {
+        let mut effects = Vec::new();
+        if !matches!(self.current_token(), Token::With) {
+            return Ok(effects);
+        }
+        loop {
+            self.advance(); // consume `with` or ,
+            match self.current_token().clone() {
+                Token::Identifier(effect) => {
+                    self.advance();
+                    effects.push(EffectAnnotation::Performs(effect));
+                }
+                _ => return Err("Expected an effect name after 'with'".to_string()),
+            }
+            if !matches!(self.current_token(), Token::Comma) {
+                return Ok(effects);
+            }
+        }
+    }
+
+    /// Parses a foreign function declaration: extern "c" fn c_sqrt(x: Float) -> Float from "libm"
+    ///
+    /// The declaration has a signature but no body; calls go through the FFI to the
+    /// named library, converting arguments and the result by the declared types.
+    fn parse_extern(&mut self) -> Result<Statement, String> {
+        self.advance(); // consume extern
+
+        let abi = match self.current_token().clone() {
+            Token::String(abi) => {
+                self.advance(); // consume the ABI string
+                abi
+            },
+            other => return Err(format!("Expected the language of an extern function, such as \"c\", got {:?}", other)),
+        };
+        if !EXTERN_ABIS.contains(&abi.as_str()) {
+            return Err(format!("Unknown extern language \"{}\": expected one of {}", abi, EXTERN_ABIS.join(", ")));
+        }
+
+        if !matches!(self.current_token(), Token::Fn) {
+            return Err("Expected 'fn' after the extern language".to_string());
+        }
+        self.advance(); // consume fn
+
+        let name = if let Token::Identifier(name) = self.current_token().clone() {
+            self.advance(); // consume name
+            name
+        } else {
+            return Err("Expected extern function name".to_string());
+        };
+
+        if !matches!(self.current_token(), Token::LeftParen) {
+            return Err("Expected '('".to_string());
+        }
+        self.advance(); // consume (
+        let parameters = self.parse_parameters()?;
+        if let Some(param) = parameters.iter().find(|param| param.default_value.is_some()) {
+            return Err(format!("Extern function parameter '{}' can't have a default value", param.name));
+        }
+
+        let return_type = if matches!(self.current_token(), Token::Arrow) {
+            self.advance(); // consume ->
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+
+        if !matches!(self.current_token(), Token::Identifier(word) if word == "from") {
+            return Err(format!("Expected 'from \"library\"' after the signature of extern fn {}", name));
+        }
+        self.advance(); // consume from
+        let library = match self.current_token().clone() {
+            Token::String(library) => {
+                self.advance(); // consume the library name
+                library
+            },
+            other => return Err(format!("Expected a library name string after 'from', got {:?}", other)),
+        };
+
+        Ok(Statement::Extern(ExternDef { abi, name, parameters, return_type, library }))
+    }
+
     /// Parses function parameters: (param1: Type, param2: Type, ...)
     /// 
     /// # Returns
@@ -1309,6 +1946,9 @@ impl<'a> Parser<'a> {
 
         while matches!(self.current_token(), Token::Comma) {
             self.advance(); // consume ,
+            if matches!(self.current_token(), Token::RightParen) {
+                break; // trailing comma
+            }
             params.push(self.parse_parameter()?);
         }
 
@@ -1386,6 +2026,11 @@ impl<'a> Parser<'a> {
     /// # Returns
     /// A Result containing the Type AST node if successful, or an error string
     fn parse_type(&mut self) -> Result<Type, String> {
+        self.nested(Self::parse_type_kind)
+    }
+
+    /// Parses a single type; see `parse_type`
+    fn parse_type_kind(&mut self) -> Result<Type, String> {
         match self.current_token().clone() {
             Token::Identifier(name) => {
                 self.advance();
@@ -1395,9 +2040,25 @@ impl<'a> Parser<'a> {
                     "Bool" => Ok(Type::Bool),
                     "String" => Ok(Type::String),
                     "Unit" => Ok(Type::Unit),
+                    "Map" | "Set" | "Option" | "Result" | "Channel" if matches!(self.current_token(), Token::Less) => self.parse_collection_type(&name),
+                    "dyn" => {
+                        // Trait object type: dyn Trait
+                        match self.current_token().clone() {
+                            Token::Identifier(trait_name) => {
+                                self.advance();
+                                Ok(Type::Dyn(trait_name))
+                            }
+                            _ => Err("Expected trait name after 'dyn'".to_string()),
+                        }
+                    }
                     _ => Ok(Type::Named(name)),
                 }
             }
+            Token::Not => {
+                // Linear type: !File, a value that must be used exactly once
+                self.advance(); // consume !
+                Ok(Type::Linear(Box::new(self.parse_type()?)))
+            }
             Token::LeftBracket => {
                 // Array type: [Int], [String], etc.
                 self.advance(); // consume [
@@ -1412,19 +2073,136 @@ impl<'a> Parser<'a> {
         }
     }
 
-    /// Parses a block of statements: { statement1; statement2; ... }
-    /// 
-    /// # Returns
-    /// A Result containing a vector of Statement AST nodes if successful, or an error string
-    fn parse_block(&mut self) -> Result<Vec<Statement>, String> {
-        if !matches!(self.current_token(), Token::LeftBrace) {
-            return Err("Expected '{'".to_string());
-        }
-        self.advance(); // consume {
+    /// Splits a string literal into text and `${expr}` / `${expr:spec}` placeholders
+    fn parse_interpolated_string(text: &str) -> Result<Expression, String> {
+        let mut parts = Vec::new();
+        let mut rest = text;
+
+        while let Some(start) = rest.find("${") {
+            if start > 0 {
+                parts.push(StringPart::Literal(rest[..start].to_string()));
+            }
+            rest = &rest[start + 2..];
+
+            // Find the `}` closing the placeholder, skipping braces nested in the expression
+            let mut depth = 0;
+            let mut in_string = false;
+            let end = rest.char_indices()
+                .find(|&(_, c)| match c {
+                    '"' => { in_string = !in_string; false },
+                    _ if in_string => false,
+                    '{' => { depth += 1; false },
+                    '}' if depth == 0 => true,
+                    '}' => { depth -= 1; false },
+                    _ => false,
+                })
+                .map(|(i, _)| i)
+                .ok_or_else(|| "Unterminated '${' placeholder".to_string())?;
+
+            let (source, spec) = split_placeholder(&rest[..end]);
+            if source.trim().is_empty() {
+                return Err("Empty '${}' placeholder".to_string());
+            }
+            let mut parser = Parser::new(source);
+            let expr = parser.parse_expression()?;
+            if !matches!(parser.current_token(), Token::Eof) {
+                return Err(format!("Unexpected {:?} in placeholder '${{{}}}'", parser.current_token(), source));
+            }
+            let spec = spec.map(FormatSpec::parse).transpose()?;
+
+            parts.push(StringPart::Interpolated(Box::new(expr), spec));
+            rest = &rest[end + 1..];
+        }
+
+        if !rest.is_empty() {
+            parts.push(StringPart::Literal(rest.to_string()));
+        }
+        Ok(Expression::InterpolatedString(parts))
+    }
+
+    /// Parses a try expression: try { body } catch (error) { handler }
+    ///
+    /// The parentheses around the error variable are optional: `catch error { ... }` works too.
+    fn parse_try(&mut self) -> Result<Expression, String> {
+        self.advance(); // consume try
+        let body = self.parse_block()?;
+
+        if !matches!(self.current_token(), Token::Catch) {
+            return Err("Expected 'catch' after try block".to_string());
+        }
+        self.advance(); // consume catch
+
+        let parenthesized = matches!(self.current_token(), Token::LeftParen);
+        if parenthesized {
+            self.advance(); // consume (
+        }
+        let name = match self.current_token().clone() {
+            Token::Identifier(name) => {
+                self.advance(); // consume error variable
+                name
+            },
+            other => return Err(format!("Expected error variable after 'catch', got {:?}", other)),
+        };
+        if parenthesized {
+            if !matches!(self.current_token(), Token::RightParen) {
+                return Err("Expected ')' after catch variable".to_string());
+            }
+            self.advance(); // consume )
+        }
+
+        let handler = self.parse_block()?;
+        Ok(Expression::TryCatch(body, name, handler))
+    }
+
+    /// Parses the type arguments of a generic built-in type: Map<K, V>, Set<T>, Option<T>, Result<T, E> or Channel<T>
+    fn parse_collection_type(&mut self, name: &str) -> Result<Type, String> {
+        self.advance(); // consume <
+
+        let first = self.parse_type()?;
+        let ty = if name == "Map" || name == "Result" {
+            if !matches!(self.current_token(), Token::Comma) {
+                return Err(format!("Expected ',' between the two type arguments of {}", name));
+            }
+            self.advance(); // consume ,
+            let second = self.parse_type()?;
+            if name == "Map" {
+                Type::Map(Box::new(first), Box::new(second))
+            } else {
+                Type::Result(Box::new(first), Box::new(second))
+            }
+        } else if name == "Option" {
+            Type::Option(Box::new(first))
+        } else if name == "Channel" {
+            Type::Channel(Box::new(first))
+        } else {
+            Type::Set(Box::new(first))
+        };
+
+        if !matches!(self.current_token(), Token::Greater) {
+            return Err(format!("Expected '>' to close {} type", name));
+        }
+        self.advance(); // consume >
+
+        Ok(ty)
+    }
+
+    /// 
+    /// # Returns
+    /// A Result containing a vector of Statement AST nodes if successful, or an error string
+    fn parse_block(&mut self) -> Result<Vec<Statement>, String> {
+        if !matches!(self.current_token(), Token::LeftBrace) {
+            return Err("Expected '{'".to_string());
+        }
+        self.advance(); // consume {
 
         let mut statements = Vec::new();
 
         while !matches!(self.current_token(), Token::RightBrace) && !matches!(self.current_token(), Token::Eof) {
+            // Statements may be separated by `;` as well as by line breaks
+            if matches!(self.current_token(), Token::Semicolon) {
+                self.advance(); // consume ;
+                continue;
+            }
             statements.push(self.parse_statement()?);
         }
 
@@ -1436,6 +2214,51 @@ impl<'a> Parser<'a> {
         Ok(statements)
     }
 
+    /// Parses a while loop: while condition { body }
+    fn parse_while_statement(&mut self, label: Option<Label>) -> Result<Statement, String> {
+        self.advance(); // consume while
+        let condition = self.parse_expression()?;
+        let body = self.parse_block()?;
+        Ok(Statement::While { label, condition, body })
+    }
+
+    /// Parses an assertion: assert(condition, message?) or assert_eq(actual, expected, message?)
+    fn parse_assert(&mut self) -> Result<Statement, String> {
+        let location = self.location;
+        let is_eq = matches!(self.current_token(), Token::Identifier(name) if name == "assert_eq");
+        let name = if is_eq { "assert_eq" } else { "assert" };
+        self.advance(); // consume assert or assert_eq
+
+        if !matches!(self.current_token(), Token::LeftParen) {
+            return Err(format!("Expected '(' after {}", name));
+        }
+        self.advance(); // consume (
+        let mut args = self.parse_arguments()?.into_iter();
+
+        let (required, described) = if is_eq { (2, "an actual and an expected value") } else { (1, "a condition") };
+        if args.len() < required || args.len() > required + 1 {
+            return Err(format!(
+                "{}() expects {} and an optional message, got {} arguments at {}",
+                name, described, args.len(), location
+            ));
+        }
+
+        let actual = args.next().expect("argument count was checked");
+        let expected = if is_eq { args.next() } else { None };
+        Ok(Statement::Assert { actual, expected, message: args.next(), location })
+    }
+
+    /// Parses the optional label after break or continue
+    fn parse_loop_label(&mut self) -> Option<Label> {
+        match self.current_token().clone() {
+            Token::Label(label) => {
+                self.advance(); // consume label
+                Some(label)
+            },
+            _ => None,
+        }
+    }
+
     /// Parses a block statement: { statement1; statement2; ... }
     /// 
     /// # Returns
@@ -1450,6 +2273,13 @@ impl<'a> Parser<'a> {
     /// # Returns
     /// A Result containing the Statement AST node if successful, or an error string
     fn parse_if_statement(&mut self) -> Result<Statement, String> {
+        Ok(Statement::Expression(self.parse_if_expression()?))
+    }
+
+    /// Parses an if expression, whose value is that of the branch taken
+    ///
+    /// `else if` chains nest: the else branch holds the inner if as its only statement.
+    fn parse_if_expression(&mut self) -> Result<Expression, String> {
         self.advance(); // consume if
 
         let condition = self.parse_expression()?;
@@ -1458,16 +2288,20 @@ impl<'a> Parser<'a> {
 
         let else_branch = if matches!(self.current_token(), Token::Else) {
             self.advance(); // consume else
-            self.parse_block()?
+            if matches!(self.current_token(), Token::If) {
+                vec![Statement::Expression(self.chained(Self::parse_if_expression)?)]
+            } else {
+                self.parse_block()?
+            }
         } else {
             Vec::new()
         };
 
-        Ok(Statement::Expression(Expression::If(
+        Ok(Expression::If(
             Box::new(condition),
             then_branch,
             else_branch
-        )))
+        ))
     }
 
     /// Parses a match statement: match expr { pattern => block, ... }
@@ -1475,6 +2309,14 @@ impl<'a> Parser<'a> {
     /// # Returns
     /// A Result containing the Statement AST node if successful, or an error string
     fn parse_match_statement(&mut self) -> Result<Statement, String> {
+        Ok(Statement::Expression(self.parse_match_expression()?))
+    }
+
+    /// Parses a match expression, whose value is that of the arm taken
+    ///
+    /// An arm's body is either a block or a single expression; arms may be separated
+    /// by commas, with an optional one after the last.
+    fn parse_match_expression(&mut self) -> Result<Expression, String> {
         self.advance(); // consume match
 
         let expr = self.parse_expression()?;
@@ -1502,9 +2344,17 @@ impl<'a> Parser<'a> {
             }
             self.advance(); // consume =>
 
-            let body = self.parse_block()?;
+            let body = if matches!(self.current_token(), Token::LeftBrace) {
+                self.parse_block()?
+            } else {
+                vec![Statement::Expression(self.parse_expression()?)]
+            };
 
             arms.push((pattern, guard, body));
+
+            if matches!(self.current_token(), Token::Comma) {
+                self.advance(); // consume ,
+            }
         }
 
         if !matches!(self.current_token(), Token::RightBrace) {
@@ -1512,10 +2362,119 @@ impl<'a> Parser<'a> {
         }
         self.advance(); // consume }
 
-        Ok(Statement::Expression(Expression::Match(
+        Ok(Expression::Match(
             Box::new(expr),
             arms
-        )))
+        ))
+    }
+
+    /// Parses a select expression, whose value is that of the arm taken
+    ///
+    /// Each arm is a channel operation, `default` or `timeout(ms)`, then `=>` and a
+    /// block or single expression; a receive can bind the value it takes:
+    ///
+    /// ```text
+    /// select {
+    ///     job = jobs.recv() => run(job),
+    ///     results.send(0) => {},
+    ///     timeout(100) => print("idle"),
+    /// }
+    /// ```
+    fn parse_select_expression(&mut self) -> Result<Expression, String> {
+        self.advance(); // consume select
+
+        if !matches!(self.current_token(), Token::LeftBrace) {
+            return Err("Expected '{' after 'select'".to_string());
+        }
+        self.advance(); // consume {
+
+        let mut arms: Vec<SelectArm> = Vec::new();
+        while !matches!(self.current_token(), Token::RightBrace | Token::Eof) {
+            let mut lookahead = self.lexer.clone();
+            let next = lookahead.next_token();
+            let (channel_operation, pattern) = match (self.current_token().clone(), next) {
+                (Token::Identifier(word), Token::FatArrow) if word == "default" => {
+                    self.advance(); // consume default
+                    (ChannelOperation::Default, None)
+                }
+                (Token::Identifier(word), Token::LeftParen) if word == "timeout" => {
+                    self.advance(); // consume timeout
+                    self.advance(); // consume (
+                    let millis = self.parse_expression()?;
+                    if !matches!(self.current_token(), Token::RightParen) {
+                        return Err("Expected ')' after the select timeout".to_string());
+                    }
+                    self.advance(); // consume )
+                    (ChannelOperation::Timeout { millis: Box::new(millis) }, None)
+                }
+                (Token::Identifier(name), Token::Assign) => {
+                    self.advance(); // consume name
+                    self.advance(); // consume =
+                    match self.parse_select_operation()? {
+                        operation @ ChannelOperation::Receive { .. } => (operation, Some(Pattern::Identifier(name))),
+                        _ => return Err(format!("Only a receive can bind '{}' in a select arm", name)),
+                    }
+                }
+                _ => (self.parse_select_operation()?, None),
+            };
+
+            // At most one arm may say what happens when no channel is ready
+            let fallback = |op: &ChannelOperation| match op {
+                ChannelOperation::Default => Some("default"),
+                ChannelOperation::Timeout { .. } => Some("timeout"),
+                _ => None,
+            };
+            if let (Some(kind), Some(earlier)) = (fallback(&channel_operation), arms.iter().find_map(|arm| fallback(&arm.channel_operation))) {
+                return Err(if kind == earlier {
+                    format!("A select can have only one {} arm", kind)
+                } else {
+                    "A select can't have both a default and a timeout arm: default is taken before any wait".to_string()
+                });
+            }
+
+            if !matches!(self.current_token(), Token::FatArrow) {
+                return Err("Expected '=>' after the select arm's operation".to_string());
+            }
+            self.advance(); // consume =>
+
+            let body = if matches!(self.current_token(), Token::LeftBrace) {
+                self.parse_block()?
+            } else {
+                vec![Statement::Expression(self.parse_expression()?)]
+            };
+            arms.push(SelectArm { channel_operation, pattern, body });
+
+            if matches!(self.current_token(), Token::Comma) {
+                self.advance(); // consume ,
+            }
+        }
+
+        if !matches!(self.current_token(), Token::RightBrace) {
+            return Err("Expected '}' to close the select".to_string());
+        }
+        self.advance(); // consume }
+
+        Ok(Expression::Select(arms))
+    }
+
+    /// Parses the channel operation a select arm waits on: `ch.recv()`, `<-ch`, `ch.send(value)` or `ch.close()`
+    fn parse_select_operation(&mut self) -> Result<ChannelOperation, String> {
+        use std::mem::take;
+
+        match &mut self.parse_expression()? {
+            Expression::MethodCall(channel, method, args) if method == "recv" && args.is_empty() => {
+                Ok(ChannelOperation::Receive { channel: take(channel) })
+            }
+            Expression::ChannelReceive(channel) => Ok(ChannelOperation::Receive { channel: take(channel) }),
+            Expression::MethodCall(channel, method, args) if method == "send" && args.len() == 1 => {
+                Ok(ChannelOperation::Send { channel: take(channel), value: Box::new(args.remove(0)) })
+            }
+            Expression::ChannelSend(channel, value) => Ok(ChannelOperation::Send { channel: take(channel), value: take(value) }),
+            Expression::MethodCall(channel, method, args) if method == "close" && args.is_empty() => {
+                Ok(ChannelOperation::Close { channel: take(channel) })
+            }
+            _ => Err("Expected a channel operation in select arm: ch.recv(), ch.send(value), default or timeout(ms)".to_string()),
+        }
     }
 
     /// Parses a pattern for match expressions
@@ -1523,7 +2482,7 @@ impl<'a> Parser<'a> {
     /// # Returns
     /// A Result containing the Pattern AST node if successful, or an error string
     fn parse_pattern(&mut self) -> Result<Pattern, String> {
-        self.parse_or_pattern()
+        self.nested(Self::parse_or_pattern)
     }
 
     /// Parses OR patterns (pattern1 | pattern2)
@@ -1534,7 +2493,12 @@ impl<'a> Parser<'a> {
         let mut left = self.parse_basic_pattern()?;
 
         // Look for | (or) patterns
+        let mut alternatives = 1;
         while matches!(self.current_token(), Token::Pipe) {
+            if alternatives == MAX_OR_PATTERN_ALTERNATIVES {
+                return Err(format!("Or-pattern too long (limit is {} alternatives)", MAX_OR_PATTERN_ALTERNATIVES));
+            }
+            alternatives += 1;
             self.advance(); // consume |
             let right = self.parse_basic_pattern()?;
             left = Pattern::Or(Box::new(left), Box::new(right));
@@ -1549,10 +2513,16 @@ impl<'a> Parser<'a> {
     /// A Result containing the Pattern AST node if successful, or an error string
     fn parse_basic_pattern(&mut self) -> Result<Pattern, String> {
         match self.current_token().clone() {
+            Token::Underscore => {
+                self.advance();
+                Ok(Pattern::Wildcard)
+            }
             Token::Identifier(name) => {
                 self.advance();
                 if name == "_" {
                     Ok(Pattern::Wildcard)
+                } else if matches!(self.current_token(), Token::DoubleColon) {
+                    self.parse_enum_pattern(name)
                 } else {
                     // Check if this is a struct pattern: Name { field: pattern, ... }
                     if matches!(self.current_token(), Token::LeftBrace) {
@@ -1573,7 +2543,7 @@ impl<'a> Parser<'a> {
                             }
                             self.advance(); // consume :
 
-                            let field_pattern = self.parse_or_pattern()?; // Use or pattern for nested or
+                            let field_pattern = self.parse_pattern()?;
                             fields.push((field_name, field_pattern));
 
                             while matches!(self.current_token(), Token::Comma) {
@@ -1590,7 +2560,7 @@ impl<'a> Parser<'a> {
                                 }
                                 self.advance(); // consume :
 
-                                let field_pattern = self.parse_or_pattern()?; // Use or pattern for nested or
+                                let field_pattern = self.parse_pattern()?;
                                 fields.push((field_name, field_pattern));
                             }
                         }
@@ -1610,6 +2580,11 @@ impl<'a> Parser<'a> {
                 self.advance();
                 Ok(Pattern::Literal(Expression::Integer(value)))
             }
+            Token::Float(value) => {
+                // Float literals match by ==, so a NaN never matches one
+                self.advance();
+                Ok(Pattern::Literal(Expression::Float(value)))
+            }
             Token::String(value) => {
                 self.advance();
                 Ok(Pattern::Literal(Expression::String(value)))
@@ -1628,11 +2603,11 @@ impl<'a> Parser<'a> {
                 let mut patterns = Vec::new();
 
                 if !matches!(self.current_token(), Token::RightParen) {
-                    patterns.push(self.parse_or_pattern()?); // Use or pattern for nested or
+                    patterns.push(self.parse_pattern()?);
 
                     while matches!(self.current_token(), Token::Comma) {
                         self.advance(); // consume ,
-                        patterns.push(self.parse_or_pattern()?); // Use or pattern for nested or
+                        patterns.push(self.parse_pattern()?);
                     }
                 }
 
@@ -1649,11 +2624,11 @@ impl<'a> Parser<'a> {
                 let mut patterns = Vec::new();
 
                 if !matches!(self.current_token(), Token::RightBracket) {
-                    patterns.push(self.parse_or_pattern()?); // Use or pattern for nested or
+                    patterns.push(self.parse_pattern()?);
 
                     while matches!(self.current_token(), Token::Comma) {
                         self.advance(); // consume ,
-                        patterns.push(self.parse_or_pattern()?); // Use or pattern for nested or
+                        patterns.push(self.parse_pattern()?);
                     }
                 }
 
@@ -1668,6 +2643,35 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parses the rest of an enum variant pattern once its enum's name has been read:
+    /// `Shape::Empty`, or `Shape::Circle(r)` for a variant with data
+    fn parse_enum_pattern(&mut self, enum_name: String) -> Result<Pattern, String> {
+        self.advance(); // consume ::
+        let variant = match self.current_token().clone() {
+            Token::Identifier(variant) => variant,
+            _ => return Err(format!("Expected a variant name after '{}::'", enum_name)),
+        };
+        self.advance();
+
+        if !matches!(self.current_token(), Token::LeftParen) {
+            return Ok(Pattern::Enum(enum_name, variant, None));
+        }
+        self.advance(); // consume (
+        let mut patterns = Vec::new();
+        while !matches!(self.current_token(), Token::RightParen) {
+            patterns.push(self.parse_pattern()?);
+            if !matches!(self.current_token(), Token::Comma) {
+                break;
+            }
+            self.advance(); // consume ,
+        }
+        if !matches!(self.current_token(), Token::RightParen) {
+            return Err("Expected ')'".to_string());
+        }
+        self.advance(); // consume )
+        Ok(Pattern::Enum(enum_name, variant, Some(patterns)))
+    }
+
     /// Parses an actor definition: actor Name { state_fields, handler_functions }
     /// 
     /// # Returns
@@ -1746,6 +2750,9 @@ impl<'a> Parser<'a> {
             return Err("Expected function name".to_string());
         };
 
+        // Type parameters, if any: fn id<T>(x: T) -> T
+        let generic_params = self.parse_generic_params()?;
+
         if !matches!(self.current_token(), Token::LeftParen) {
             return Err("Expected '('".to_string());
         }
@@ -1761,6 +2768,8 @@ impl<'a> Parser<'a> {
             None
         };
 
+        let effect_annotations = self.parse_effect_clause()?;
+
         let is_async = false; // Simplified for now
 
         if !matches!(self.current_token(), Token::LeftBrace) {
@@ -1777,8 +2786,9 @@ impl<'a> Parser<'a> {
             is_async,
             is_public: false, // Default to private
             is_awaitable: false,  // Default to not awaitable
-            effect_annotations: vec![], // Default to no effect annotations
-            generic_params: vec![], // Default to no generic parameters
+            effect_annotations,
+            generic_params,
+            attributes: vec![], // Attached by parse_attributed_item
         })
     }
 
@@ -1823,6 +2833,81 @@ impl<'a> Parser<'a> {
         }))
     }
 
+    /// Parses a struct declaration: struct Name { field: Type, ... }
+    ///
+    /// A struct is represented as a class with only public fields, so it shares the
+    /// class handling of later phases.
+    fn parse_struct(&mut self) -> Result<Statement, String> {
+        self.advance(); // consume struct
+
+        let name = if let Token::Identifier(name) = self.current_token().clone() {
+            self.advance(); // consume name
+            name
+        } else {
+            return Err("Expected struct name".to_string());
+        };
+
+        let generics = if matches!(self.current_token(), Token::Less) {
+            self.parse_generics()?
+        } else {
+            Vec::new()
+        };
+
+        if !matches!(self.current_token(), Token::LeftBrace) {
+            return Err("Expected '{' after struct name".to_string());
+        }
+        self.advance(); // consume {
+
+        let mut fields = Vec::new();
+        while !matches!(self.current_token(), Token::RightBrace | Token::Eof) {
+            let field_name = if let Token::Identifier(name) = self.current_token().clone() {
+                self.advance(); // consume field name
+                name
+            } else {
+                return Err(format!("Expected field name in struct {}", name));
+            };
+
+            if !matches!(self.current_token(), Token::Colon) {
+                return Err("Expected ':' after field name".to_string());
+            }
+            self.advance(); // consume :
+
+            fields.push(FieldDef {
+                name: field_name,
+                type_annotation: self.parse_type()?,
+                access_modifier: AccessModifier::Public,
+                is_mutable: false,
+                is_static: false,
+                default_value: None,
+            });
+
+            if matches!(self.current_token(), Token::Comma) {
+                self.advance(); // consume ,
+            } else {
+                break;
+            }
+        }
+
+        if !matches!(self.current_token(), Token::RightBrace) {
+            return Err("Expected '}'".to_string());
+        }
+        self.advance(); // consume }
+
+        Ok(Statement::Class(ClassDef {
+            name,
+            fields,
+            methods: Vec::new(),
+            parent: None,
+            access_modifier: AccessModifier::Public,
+            is_abstract: false,
+            generics,
+            interfaces: Vec::new(),
+            attributes: Vec::new(), // Attached by parse_attributed_item
+            constructors: Vec::new(),
+            destructors: Vec::new(),
+        }))
+    }
+
     /// Parses a class definition: class Name { fields, methods }
     /// 
     /// # Returns
@@ -1888,9 +2973,9 @@ impl<'a> Parser<'a> {
 
         // Parse class members
         while !matches!(self.current_token(), Token::RightBrace) && !matches!(self.current_token(), Token::Eof) {
-            if matches!(self.current_token(), Token::Fn) {
+            if matches!(self.current_token(), Token::Fn | Token::Hash) {
                 // Parse method
-                let method = self.parse_function_def()?;
+                let method = self.parse_method_def()?;
 
                 // Check if it's a constructor or destructor
                 if method.name == "new" || method.name == "__init__" {
@@ -1907,7 +2992,7 @@ impl<'a> Parser<'a> {
                 } else {
                     methods.push(method);
                 }
-            } else if matches!(self.current_token(), Token::Let) || matches!(self.current_token(), Token::Mut) {
+            } else if matches!(self.current_token(), Token::Let | Token::Mut | Token::Pub) {
                 // Parse field
                 let field = self.parse_field_def()?;
                 fields.push(field);
@@ -1932,6 +3017,7 @@ impl<'a> Parser<'a> {
             interfaces,
             constructors,
             destructors,
+            attributes: Vec::new(),
         }))
     }
 
@@ -1948,10 +3034,10 @@ impl<'a> Parser<'a> {
             AccessModifier::Private  // Default to private
         };
 
-        // Check for mutability
+        // Check for mutability: `mut name`, or `let name` for a field that can't change
         let is_mutable = matches!(self.current_token(), Token::Mut);
-        if is_mutable {
-            self.advance(); // consume mut
+        if is_mutable || matches!(self.current_token(), Token::Let) {
+            self.advance(); // consume mut or let
         }
 
         // Set default value for static (since we removed the Static token)
@@ -2080,9 +3166,9 @@ impl<'a> Parser<'a> {
 
         // Parse trait methods (signatures only)
         while !matches!(self.current_token(), Token::RightBrace) && !matches!(self.current_token(), Token::Eof) {
-            if matches!(self.current_token(), Token::Fn) {
+            if matches!(self.current_token(), Token::Fn | Token::Hash) {
                 // Parse method signature (without implementation)
-                let method_def = self.parse_function_def()?;
+                let method_def = self.parse_method_def()?;
                 methods.push(method_def);
             } else {
                 return Err("Expected function declaration in trait".to_string());
@@ -2100,6 +3186,8 @@ impl<'a> Parser<'a> {
             methods,
             associated_types: Vec::new(),
             super_traits: Vec::new(),
+            attributes: Vec::new(),
+            is_public: false, // Set by parse_public_item
         }))
     }
 
@@ -2138,9 +3226,9 @@ impl<'a> Parser<'a> {
 
         // Parse implementation methods
         while !matches!(self.current_token(), Token::RightBrace) && !matches!(self.current_token(), Token::Eof) {
-            if matches!(self.current_token(), Token::Fn) {
+            if matches!(self.current_token(), Token::Fn | Token::Hash) {
                 // Parse method implementation
-                let method_def = self.parse_function_def()?;
+                let method_def = self.parse_method_def()?;
                 methods.push(method_def);
             } else {
                 return Err("Expected function declaration in impl block".to_string());
@@ -2158,6 +3246,7 @@ impl<'a> Parser<'a> {
             type_params: Vec::new(),
             methods,
             associated_types: Vec::new(),
+            attributes: Vec::new(),
         }))
     }
 
@@ -2209,9 +3298,13 @@ impl<'a> Parser<'a> {
         }
         self.advance(); // consume '{'
 
-        // Parse macro body statements
+        // Parse macro body statements, which like a block's may be separated by `;`
         let mut body = Vec::new();
         while !matches!(self.current_token(), Token::RightBrace) && !matches!(self.current_token(), Token::Eof) {
+            if matches!(self.current_token(), Token::Semicolon) {
+                self.advance(); // consume ;
+                continue;
+            }
             body.push(self.parse_statement()?);
         }
 
@@ -2380,6 +3473,7 @@ impl<'a> Parser<'a> {
             variants,
             access_modifier,
             generics,
+            attributes: Vec::new(),
         }))
     }
 
@@ -2556,7 +3650,7 @@ mod tests {
 
         assert!(result.is_ok());
 
-        match result.unwrap() {
+        match &result.unwrap() {
             Expression::Call(name, args) => {
                 assert_eq!(name, "print");
                 assert_eq!(args.len(), 1);
@@ -2568,4 +3662,553 @@ mod tests {
             _ => panic!("Expected function call"),
         }
     }
-}
\ No newline at end of file
+
+    /// Parses `input` and reports whether it panicked
+    fn parses_without_panic(input: &str) -> bool {
+        let input = input.to_string();
+        std::panic::catch_unwind(move || {
+            let _ = Parser::new(&input).parse_program();
+        }).is_ok()
+    }
+
+    #[test]
+    fn test_malformed_inputs_never_panic() {
+        let mut corpus: Vec<String> = [
+            "", "(", ")", "{", "}", "[", "]", "fn", "fn (", "fn main(", "fn main() {",
+            "let", "let =", "let x =", "let x: = 1", "let x: [", "let x: [Int",
+            "match", "match x {", "match x { 1 =>", "match x { (1, =>",
+            "if", "if x {", "if x { } else", "else", "@", "@python", "@python{", "@import(",
+            "\"unterminated", "'", "'\\", "\\", "1..", "..", "1.2.3", "0.", ".5",
+            "99999999999999999999999999", "1e400", "x = = 1", "<-", "<- <-", "a |> |>",
+            "struct", "enum E {", "trait T {", "impl", "impl T for", "class C {", "actor A {",
+            "effect E {", "macro m(", "type T =", "const", "return return", "spawn", "send(",
+            "chan", "chan [", "async", "await", "\u{0}\u{1}\u{7f}", "\u{feff}fn main() {}",
+            "日本語 = 1", "fn ��() {}", "$$$$", "#!#!#!", "/* unterminated", "// comment only",
+        ].iter().map(|s| s.to_string()).collect();
+
+        // Deep nesting must be rejected with an error rather than overflowing the stack
+        corpus.push("(".repeat(10_000));
+        corpus.push(format!("{}1{}", "(".repeat(10_000), ")".repeat(10_000)));
+        corpus.push(format!("{}1", "-".repeat(10_000)));
+        corpus.push(format!("{}1", "!".repeat(10_000)));
+        corpus.push(format!("1{}", "^1".repeat(10_000)));
+        corpus.push(format!("1{}", "..1".repeat(5_000)));
+        corpus.push(format!("if c {{ 1 }}{}", " else if c { 1 }".repeat(2_000)));
+        corpus.push("{".repeat(10_000));
+        corpus.push(format!("let x: {}Int", "[".repeat(10_000)));
+        corpus.push(format!("match x {{ {}a{} => 1 }}", "(".repeat(2_000), ")".repeat(2_000)));
+        corpus.push(format!("match x {{ {}a{} => 1 }}", "E::V([".repeat(2_000), "])".repeat(2_000)));
+        corpus.push(format!("{}1", "x = ".repeat(20_000)));
+        corpus.push("$".repeat(100_000));
+        corpus.push("// c\n".repeat(100_000));
+
+        // Long chains parse, and the deep trees they build must drop without overflowing
+        corpus.push(format!("let x = 1{}", " + 1".repeat(100_000)));
+        corpus.push(format!("let x = a{}", ".b".repeat(100_000)));
+        corpus.push(format!("match x {{ a{} => 1 }}", " | a".repeat(100_000)));
+
+        // Cheap deterministic byte soup built from the token alphabet
+        let alphabet: Vec<char> = "(){}[]<>=!-+*/%^&|.,:;@'\"\\_ \nfnletmatchif0123456789".chars().collect();
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        for _ in 0..200 {
+            let mut input = String::new();
+            for _ in 0..64 {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                input.push(alphabet[(seed % alphabet.len() as u64) as usize]);
+            }
+            corpus.push(input);
+        }
+
+        // Run on a thread with the stack size the CLI's main thread gets by default
+        let handle = std::thread::Builder::new()
+            .stack_size(8 * 1024 * 1024)
+            .spawn(move || {
+                corpus.iter()
+                    .filter(|input| !parses_without_panic(input))
+                    .map(|input| input.chars().take(40).collect::<String>())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap();
+
+        let panicked = handle.join().unwrap();
+        assert!(panicked.is_empty(), "parser panicked on: {:?}", panicked);
+    }
+
+    #[test]
+    fn test_logical_operators_bind_looser_than_comparisons() {
+        let program = Parser::new("a || b && c == d").parse_program().unwrap();
+        let name = |n: &str| Box::new(Expression::Identifier(n.to_string()));
+        let and = Expression::BinaryOp(
+            name("b"),
+            BinaryOp::And,
+            Box::new(Expression::BinaryOp(name("c"), BinaryOp::Eq, name("d"), ParsedAt::default())),
+            ParsedAt::default(),
+        );
+        assert_eq!(
+            program.statements[0],
+            Statement::Expression(Expression::BinaryOp(name("a"), BinaryOp::Or, Box::new(and), ParsedAt::default()))
+        );
+    }
+
+    #[test]
+    fn test_select_allows_one_fallback_arm() {
+        let program = Parser::new("select { v = a.recv() => v, b.send(1) => 0, default => -1 }").parse_program().unwrap();
+        match &program.statements[0] {
+            Statement::Expression(Expression::Select(arms)) => {
+                assert_eq!(arms.len(), 3);
+                assert_eq!(arms[0].pattern, Some(Pattern::Identifier("v".to_string())));
+                assert_eq!(arms[2].channel_operation, ChannelOperation::Default);
+            },
+            other => panic!("Expected select, got {:?}", other),
+        }
+
+        let err = Parser::new("select { default => 1, default => 2 }").parse_program().unwrap_err();
+        assert!(err.message.contains("only one default arm"), "{}", err);
+        let err = Parser::new("select { default => 1, timeout(5) => 2 }").parse_program().unwrap_err();
+        assert!(err.message.contains("both a default and a timeout"), "{}", err);
+        let err = Parser::new("select { v = f() => v }").parse_program().unwrap_err();
+        assert!(err.message.contains("Expected a channel operation"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_errors_carry_their_location() {
+        let err = Parser::new("let a = 1\nstruct P { x Int }").parse_program().unwrap_err();
+        assert_eq!((err.line, err.column), (2, 14));
+        assert_eq!(err.snippet, "struct P { x Int }");
+        assert_eq!(err.to_string(), format!("2:14: {}\n  |\n2 | struct P {{ x Int }}\n  |              ^", err.message));
+
+        // The caret keeps tabs so it lines up under tab-indented code
+        let err = Parser::new("fn f() {\n\tlet = 1\n}").parse_program().unwrap_err();
+        assert!(err.to_string().ends_with("2 | \tlet = 1\n  | \t    ^"), "{}", err);
+
+        // Lexer errors point at the start of the bad token and don't repeat a location
+        let err = Parser::new("let a = 1\n\nlet b = 1e300").parse_program().unwrap_err();
+        assert_eq!((err.line, err.column), (3, 9));
+        assert_eq!(err.message, "Invalid identifier '1e300': identifiers cannot start with a digit");
+    }
+
+    #[test]
+    fn test_recovering_parse_reports_every_error() {
+        let source = "let a = 1\nlet = 2\nfn f( { 3 }\nlet b = a +\nlet c = 4";
+        let (program, errors) = Parser::new(source).parse_program_recovering();
+        let lines: Vec<usize> = errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, vec![2, 3, 5]);
+        let names: Vec<&str> = program.statements.iter()
+            .filter_map(|s| match s { Statement::LetBinding { name, .. } => Some(name.as_str()), _ => None })
+            .collect();
+        assert_eq!(names, vec!["a", "c"]);
+
+        // Running out of input mid-statement is one error, not a loop
+        let (_, errors) = Parser::new("fn f() {\n  let x = (").parse_program_recovering();
+        assert_eq!(errors.len(), 1);
+        let (program, errors) = Parser::new("let ok = 1").parse_program_recovering();
+        assert!(errors.is_empty() && program.statements.len() == 1);
+    }
+
+    #[test]
+    fn test_deep_nesting_is_an_error() {
+        // Runs on the default test thread: the limit has to trip before the stack runs out
+        let nest = |open: &str, close: &str| {
+            format!("{}{}1{}", "let a = ", open.repeat(MAX_NESTING_DEPTH + 1), close.repeat(MAX_NESTING_DEPTH + 1))
+        };
+        for input in [
+            nest("(", ")"),
+            nest("[", "]"),
+            nest("match x { _ => ", " }"),
+            nest("P { x: ", " }"),
+            nest("if c { ", " } else { 0 }"),
+            nest("1..", ""),
+            nest("x = ", ""),
+            format!("match x {{ {}a{} => 1 }}", "(".repeat(MAX_NESTING_DEPTH + 1), ")".repeat(MAX_NESTING_DEPTH + 1)),
+            format!("match x {{ {}a{} => 1 }}", "E::V(".repeat(MAX_NESTING_DEPTH + 1), ")".repeat(MAX_NESTING_DEPTH + 1)),
+        ] {
+            let result = Parser::new(&input).parse_program();
+            assert!(result.unwrap_err().message.contains("Nesting too deep"), "{}", input);
+        }
+
+        // An else if chain has its own, longer limit
+        let chain = |links: usize| format!("let a = if c {{ 1 }}{} else {{ 0 }}", " else if c { 1 }".repeat(links));
+        assert!(Parser::new(&chain(MAX_ELSE_IF_CHAIN)).parse_program().is_ok());
+        let err = Parser::new(&chain(MAX_ELSE_IF_CHAIN + 1)).parse_program().unwrap_err();
+        assert!(err.message.contains("else if chain too long"), "{}", err.message);
+
+        // So does an or-pattern
+        let alternatives = |count: usize| format!("match x {{ {} => 1 }}", vec!["1"; count].join(" | "));
+        assert!(Parser::new(&alternatives(MAX_OR_PATTERN_ALTERNATIVES)).parse_program().is_ok());
+        let err = Parser::new(&alternatives(MAX_OR_PATTERN_ALTERNATIVES + 1)).parse_program().unwrap_err();
+        assert!(err.message.contains("Or-pattern too long"), "{}", err.message);
+
+        // Each parenthesis is a level, and so are the statement and the innermost operand
+        let deepest = MAX_NESTING_DEPTH - 2;
+        let input = format!("{}1{}", "(".repeat(deepest), ")".repeat(deepest));
+        assert!(Parser::new(&input).parse_program().is_ok());
+    }
+
+    #[test]
+    fn test_long_chains_drop_without_recursing() {
+        // Also on the default test thread: dropping these trees must not recurse per link
+        for input in [
+            format!("let x = 1{}", " * 2 + 1".repeat(50_000)),
+            format!("let x = items{}", ".next()?[0].value".repeat(20_000)),
+        ] {
+            let program = Parser::new(&input).parse_program().unwrap();
+            drop(program);
+        }
+    }
+
+    #[test]
+    fn test_parse_items_lazy_matches_parse_program() {
+        let input = r#"
+        const LIMIT = 10
+        fn add(a: Int, b: Int) -> Int {
+            a + b
+        }
+        let total = add(1, 2)
+        fn main() {
+            print(total)
+        }
+        "#;
+
+        let program = Parser::new(input).parse_program().unwrap();
+        let mut parser = Parser::new(input);
+        let items: Vec<Statement> = parser.parse_items_lazy()
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        assert_eq!(items.len(), 4);
+        assert_eq!(items, program.statements);
+    }
+
+    #[test]
+    fn test_parse_items_lazy_skips_semicolons() {
+        let input = "let a = 1; let b = 2;; fn f() {};";
+        let program = Parser::new(input).parse_program().unwrap();
+        let items: Vec<Statement> = Parser::new(input).parse_items_lazy().collect::<Result<_, _>>().unwrap();
+
+        assert_eq!(items.len(), 3);
+        assert_eq!(items, program.statements);
+    }
+
+    #[test]
+    fn test_parse_items_lazy_stops_after_error() {
+        let mut parser = Parser::new("let x = 1\nlet = 2\nlet y = 3");
+        let items: Vec<_> = parser.parse_items_lazy().collect();
+
+        assert_eq!(items.len(), 2);
+        assert!(items[0].is_ok());
+        let error = items[1].as_ref().unwrap_err();
+        assert_eq!((error.line, error.column), (2, 5));
+        assert_eq!(error.snippet, "let = 2");
+    }
+
+    #[test]
+    fn test_parse_attributes_without_args() {
+        let program = Parser::new("#[test]\n#[inline]\nfn check() {}").parse_program().unwrap();
+        match &program.statements[0] {
+            Statement::Function(def) => {
+                let names: Vec<&str> = def.attributes.iter().map(|a| a.name.as_str()).collect();
+                assert_eq!(names, vec!["test", "inline"]);
+                assert!(def.attributes.iter().all(|a| a.args.is_empty()));
+            },
+            other => panic!("Expected function, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_attributes_with_args() {
+        let input = r#"
+        #[cfg(feature = "json")]
+        #[inline(always)]
+        fn encode() {}
+        #[cfg(not(test))]
+        fn release_only() {}
+        "#;
+        let program = Parser::new(input).parse_program().unwrap();
+
+        let attributes: Vec<&Vec<Attribute>> = program.statements.iter()
+            .filter_map(|s| match s {
+                Statement::Function(def) => Some(&def.attributes),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(attributes[0][0], Attribute {
+            name: "cfg".to_string(),
+            args: vec![AttributeArg::KeyValue("feature".to_string(), "json".to_string())],
+        });
+        assert!(attributes[0][1].has_word("always"));
+        assert_eq!(attributes[1][0].args, vec![
+            AttributeArg::Nested("not".to_string(), vec![AttributeArg::Word("test".to_string())]),
+        ]);
+    }
+
+    #[test]
+    fn test_attribute_must_precede_an_item() {
+        assert!(Parser::new("#[test]\nlet x = 1").parse_program().is_err());
+    }
+
+    #[test]
+    fn test_parse_struct_declaration_and_literal() {
+        let input = "#[derive(Eq)]\nstruct Point { x: Int, y: Int }\nlet p = Point { x: 1, y: 2 }\nif ready { p }";
+        let program = Parser::new(input).parse_program().unwrap();
+
+        match &program.statements[0] {
+            Statement::Class(def) => {
+                assert_eq!(def.name, "Point");
+                assert_eq!(def.fields.iter().map(|f| f.name.as_str()).collect::<Vec<_>>(), vec!["x", "y"]);
+                assert!(find_attribute(&def.attributes, "derive").is_some());
+            },
+            other => panic!("Expected struct declaration, got {:?}", other),
+        }
+        match &program.statements[1] {
+            Statement::LetBinding { value, .. } => assert_eq!(value, &Expression::Struct(
+                "Point".to_string(),
+                vec![("x".to_string(), Expression::Integer(1)), ("y".to_string(), Expression::Integer(2))],
+            )),
+            other => panic!("Expected let binding, got {:?}", other),
+        }
+        // A lowercase name before a brace is still a condition followed by a block
+        assert!(matches!(&program.statements[2], Statement::Expression(Expression::If(cond, _, _))
+            if **cond == Expression::Identifier("ready".to_string())));
+    }
+
+    #[test]
+    fn test_pub_marks_items_public() {
+        let program = Parser::new("pub fn f() -> Int { 1 }\n#[inline]\npub fn g() -> Int { 2 }\npub enum E { A }\nfn h() -> Int { 3 }")
+            .parse_program()
+            .unwrap();
+        assert!(matches!(&program.statements[0], Statement::Function(def) if def.is_public));
+        assert!(matches!(&program.statements[1], Statement::Function(def) if def.is_public && def.attributes.len() == 1));
+        assert!(matches!(&program.statements[2], Statement::Enum(def) if def.access_modifier == AccessModifier::Public));
+        assert!(matches!(&program.statements[3], Statement::Function(def) if !def.is_public));
+        assert!(Parser::new("pub let x = 1").parse_program().is_err());
+    }
+
+    #[test]
+    fn test_parse_array_repeat() {
+        let program = Parser::new("let zeros = [0; 16]").parse_program().unwrap();
+        match &program.statements[0] {
+            Statement::LetBinding { value, .. } => assert_eq!(value, &Expression::ArrayRepeat(
+                Box::new(Expression::Integer(0)),
+                Box::new(Expression::Integer(16)),
+            )),
+            other => panic!("Expected let binding, got {:?}", other),
+        }
+        assert!(Parser::new("[0; 16, 1]").parse_program().is_err());
+    }
+
+    #[test]
+    fn test_parse_labeled_loops() {
+        let program = Parser::new("'outer: while true { while false { break 'outer\n continue } }").parse_program().unwrap();
+        match &program.statements[0] {
+            Statement::While { label, condition, body } => {
+                assert_eq!(label.as_deref(), Some("outer"));
+                assert_eq!(condition, &Expression::Boolean(true));
+                match &body[0] {
+                    Statement::While { label: None, body, .. } => {
+                        assert_eq!(body, &vec![Statement::Break(Some("outer".to_string())), Statement::Continue(None)]);
+                    },
+                    other => panic!("Expected inner loop, got {:?}", other),
+                }
+            },
+            other => panic!("Expected while loop, got {:?}", other),
+        }
+
+        // Char literals still lex as chars
+        assert!(matches!(Parser::new("'a'").parse_program().unwrap().statements[0],
+            Statement::Expression(Expression::Char('a'))));
+        assert!(Parser::new("'outer: print(1)").parse_program().is_err());
+    }
+
+    #[test]
+    fn test_parse_defer() {
+        let program = Parser::new("fn f() { defer print(1)\n 2 }").parse_program().unwrap();
+        match &program.statements[0] {
+            Statement::Function(func) => assert_eq!(func.body[0], Statement::Defer(Box::new(
+                Statement::Expression(Expression::Call("print".to_string(), vec![Expression::Integer(1)])),
+            ))),
+            other => panic!("Expected function, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_interpolation_with_format_spec() {
+        let program = Parser::new("\"total: ${price * 2:>8.2}!\"").parse_program().unwrap();
+        let parts = match &program.statements[0] {
+            Statement::Expression(Expression::InterpolatedString(parts)) => parts.clone(),
+            other => panic!("Expected interpolated string, got {:?}", other),
+        };
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[0], StringPart::Literal("total: ".to_string()));
+        match &parts[1] {
+            StringPart::Interpolated(expr, Some(spec)) => {
+                assert!(matches!(**expr, Expression::BinaryOp(_, BinaryOp::Mul, _, _)), "{:?}", expr);
+                assert_eq!(spec.to_string(), ">8.2");
+            },
+            other => panic!("Expected placeholder with spec, got {:?}", other),
+        }
+
+        let err = Parser::new("\"${x:5q}\"").parse_program().unwrap_err();
+        assert!(err.message.contains("Invalid format spec '5q'"), "{}", err);
+        assert!(Parser::new("\"${x\"").parse_program().unwrap_err().message.contains("Unterminated"));
+    }
+
+    #[test]
+    fn test_parse_try_catch() {
+        let expected = Expression::TryCatch(
+            vec![Statement::Expression(Expression::Call("risky".to_string(), vec![]))],
+            "err".to_string(),
+            vec![Statement::Expression(Expression::Identifier("err".to_string()))],
+        );
+        let program = Parser::new("let r = try { risky() } catch (err) { err }").parse_program().unwrap();
+        match &program.statements[0] {
+            Statement::LetBinding { value, .. } => assert_eq!(value, &expected),
+            other => panic!("Expected let binding, got {:?}", other),
+        }
+
+        // Parentheses around the error variable are optional
+        let program = Parser::new("try { risky() } catch err { err }").parse_program().unwrap();
+        assert_eq!(program.statements[0], Statement::Expression(expected));
+
+        assert!(Parser::new("try { risky() }").parse_program().is_err());
+    }
+
+    #[test]
+    fn test_parse_block_expression() {
+        let program = Parser::new("let x = { let a = 1; a + 2 }").parse_program().unwrap();
+        match &program.statements[..] {
+            [Statement::LetBinding { value: Expression::Block(statements), .. }] => {
+                assert!(matches!(statements[0], Statement::LetBinding { .. }));
+                assert!(matches!(statements[1], Statement::Expression(Expression::BinaryOp(_, BinaryOp::Add, _, _))));
+                assert_eq!(statements.len(), 2);
+            },
+            other => panic!("expected a let bound to a block, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_if_expression() {
+        let program = Parser::new("let max = if a > b { a } else { b }\nlet sign = if n < 0 { -1 } else if n == 0 { 0 } else { 1 }")
+            .parse_program()
+            .unwrap();
+        match &program.statements[..] {
+            [Statement::LetBinding { value: Expression::If(_, then_branch, else_branch), .. }, Statement::LetBinding { value: Expression::If(_, _, chained), .. }] => {
+                assert_eq!(then_branch, &vec![Statement::Expression(Expression::Identifier("a".to_string()))]);
+                assert_eq!(else_branch, &vec![Statement::Expression(Expression::Identifier("b".to_string()))]);
+                assert!(matches!(&chained[..], [Statement::Expression(Expression::If(..))]), "{:?}", chained);
+            },
+            other => panic!("expected two lets bound to ifs, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_match_expression() {
+        let program = Parser::new("let label = match n { 0 => \"zero\", _ => { \"other\" }, }")
+            .parse_program()
+            .unwrap();
+        match &program.statements[..] {
+            [Statement::LetBinding { value: Expression::Match(_, arms), .. }] => {
+                assert_eq!(arms.len(), 2);
+                assert_eq!(arms[0].2, vec![Statement::Expression(Expression::String("zero".to_string()))]);
+                assert_eq!(arms[1].0, Pattern::Wildcard);
+                assert_eq!(arms[1].2, vec![Statement::Expression(Expression::String("other".to_string()))]);
+            },
+            other => panic!("expected a let bound to a match, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_trailing_commas() {
+        let parse = |source: &str| Parser::new(source).parse_program().unwrap().statements;
+        assert_eq!(parse("f(1, 2,)"), parse("f(1, 2)"));
+        assert_eq!(parse("[1, 2,]"), parse("[1, 2]"));
+        assert_eq!(parse("(1, 2,)"), parse("(1, 2)"));
+        assert_eq!(parse("Point { x: 1, y: 2, }"), parse("Point { x: 1, y: 2 }"));
+        assert_eq!(parse("fn add(a: Int, b: Int,) -> Int { a + b }"), parse("fn add(a: Int, b: Int) -> Int { a + b }"));
+
+        // A comma alone is still not an argument list
+        assert!(Parser::new("f(,)").parse_program().is_err());
+    }
+
+    #[test]
+    fn test_parse_index_and_slice() {
+        let program = Parser::new("data[i + 1]\ndata[1..n]\nb\"\\x00A\"[0]").parse_program().unwrap();
+        let data = || Box::new(Expression::Identifier("data".to_string()));
+        assert_eq!(program.statements, vec![
+            Statement::Expression(Expression::Index(data(), Box::new(Expression::BinaryOp(
+                Box::new(Expression::Identifier("i".to_string())),
+                BinaryOp::Add,
+                Box::new(Expression::Integer(1)),
+                ParsedAt::default(),
+            )))),
+            Statement::Expression(Expression::Slice(
+                data(),
+                Box::new(Expression::Integer(1)),
+                Box::new(Expression::Identifier("n".to_string())),
+            )),
+            Statement::Expression(Expression::Index(
+                Box::new(Expression::Bytes(vec![0, b'A'])),
+                Box::new(Expression::Integer(0)),
+            )),
+        ]);
+    }
+
+    #[test]
+    fn test_parse_propagate() {
+        let program = Parser::new("fn total() -> Result<Int, String> { let n = parse(\"1\")?.value\n Ok(n) }")
+            .parse_program()
+            .unwrap();
+        match &program.statements[0] {
+            Statement::Function(func) => {
+                assert_eq!(func.return_type, Some(Type::Result(Box::new(Type::Int), Box::new(Type::String))));
+                match &func.body[0] {
+                    Statement::LetBinding { value, .. } => assert_eq!(value, &Expression::FieldAccess(
+                        Box::new(Expression::Propagate(Box::new(Expression::Call(
+                            "parse".to_string(),
+                            vec![Expression::String("1".to_string())],
+                        )))),
+                        "value".to_string(),
+                    )),
+                    other => panic!("Expected let binding, got {:?}", other),
+                }
+            },
+            other => panic!("Expected function, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_paren_on_a_new_line_starts_a_statement() {
+        let program = Parser::new("let n = len(xs)\n(n, xs)").parse_program().unwrap();
+        assert_eq!(program.statements.len(), 2);
+        assert!(matches!(&program.statements[0], Statement::LetBinding { value: Expression::Call(name, _), .. } if name == "len"));
+        assert!(matches!(&program.statements[1], Statement::Expression(Expression::Tuple(items)) if items.len() == 2));
+
+        // On the same line it is still a call, and arguments may span lines
+        let program = Parser::new("f (1,\n 2)").parse_program().unwrap();
+        assert!(matches!(&program.statements[0], Statement::Expression(Expression::Call(_, args)) if args.len() == 2));
+    }
+
+    #[test]
+    fn test_parse_extern_declaration() {
+        let program = Parser::new("extern \"c\" fn c_sqrt(x: Float) -> Float from \"libm\"\nextern \"c\" fn abort() from \"libc\"")
+            .parse_program()
+            .unwrap();
+        match &program.statements[0] {
+            Statement::Extern(def) => {
+                assert_eq!((def.abi.as_str(), def.name.as_str(), def.library.as_str()), ("c", "c_sqrt", "libm"));
+                assert_eq!(def.parameters.len(), 1);
+                assert_eq!(def.parameters[0].type_annotation, Type::Float);
+                assert_eq!(def.return_type, Some(Type::Float));
+            },
+            other => panic!("Expected extern declaration, got {:?}", other),
+        }
+        assert!(matches!(&program.statements[1], Statement::Extern(def) if def.return_type.is_none()));
+
+        let err = Parser::new("extern \"cobol\" fn f() from \"lib\"").parse_program().unwrap_err();
+        assert!(err.message.contains("Unknown extern language \"cobol\""), "{}", err);
+        let err = Parser::new("extern \"c\" fn f(x: Int) -> Int").parse_program().unwrap_err();
+        assert!(err.message.contains("Expected 'from \"library\"'"), "{}", err);
+    }
+}
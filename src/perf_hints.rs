@@ -0,0 +1,538 @@
+// Logos Performance Hints
+// Suggestions `logos check --perf-hints` makes about code that is correct but does
+// more work than it needs to. Each pattern is found by walking the AST:
+//
+//   - len() in a `while` condition over a collection the loop never changes, which
+//     recomputes the same length before every iteration
+//   - a string grown with `+` inside a loop, which copies everything built so far on
+//     each pass; collecting the pieces in an array and joining them once is linear
+//   - match arms binding values they never use, or rebinding the whole matched
+//     value to a new name; every binding copies what it binds
+//
+// The AST keeps no positions, so a hint's span is the lines of the top-level item it
+// was found in.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::ast::{Expression, FunctionDef, Pattern, Program, Statement, StringPart};
+use crate::parser::Parser;
+
+/// The patterns a hint can point out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HintKind {
+    LenInLoopCondition,
+    StringConcatInLoop,
+    CopyingPatternBinding,
+}
+
+/// A suggestion for making code faster
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PerfHint {
+    pub kind: HintKind,
+    pub message: String,
+    pub span: Option<(usize, usize)>, // First and last line (1-based, inclusive), when known
+}
+
+impl fmt::Display for PerfHint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.span {
+            Some((start, end)) if start == end => write!(f, "{}: hint: {}", start, self.message),
+            Some((start, end)) => write!(f, "{}-{}: hint: {}", start, end, self.message),
+            None => write!(f, "hint: {}", self.message),
+        }
+    }
+}
+
+/// Finds performance hints in a parsed program; without the source they have no span
+pub fn performance_hints(program: &Program) -> Vec<PerfHint> {
+    let mut finder = HintFinder::default();
+    for statement in &program.statements {
+        finder.statement(statement);
+    }
+    finder.hints
+}
+
+/// Parses source code and finds its performance hints, each spanning the top-level
+/// item it was found in
+pub fn performance_hints_for_source(source: &str) -> Result<Vec<PerfHint>, String> {
+    let items = Parser::new(source).parse_program_with_lines()?;
+    let mut finder = HintFinder::default();
+    for (statement, start, end) in &items {
+        finder.span = Some((*start, *end));
+        finder.statement(statement);
+    }
+    Ok(finder.hints)
+}
+
+#[derive(Default)]
+struct HintFinder {
+    span: Option<(usize, usize)>,
+    loop_depth: usize,
+    strings: HashSet<String>, // Variables bound to a string, as far as their initializers show
+    hints: Vec<PerfHint>,
+}
+
+impl HintFinder {
+    fn hint(&mut self, kind: HintKind, message: String) {
+        self.hints.push(PerfHint { kind, message, span: self.span });
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Expression(expression) => self.expression(expression),
+            Statement::LetBinding { name, value, .. } => {
+                self.expression(value);
+                if is_string(value, &self.strings) {
+                    self.strings.insert(name.clone());
+                } else {
+                    self.strings.remove(name);
+                }
+            },
+            Statement::ConstBinding { value, .. } => self.expression(value),
+            Statement::Function(function) => self.function(function),
+            Statement::Class(class) => {
+                class.methods.iter().for_each(|method| self.function(method));
+                class.constructors.iter().for_each(|constructor| self.statements(&constructor.body));
+                class.destructors.iter().for_each(|destructor| self.statements(&destructor.body));
+            },
+            Statement::Trait(definition) => definition.methods.iter().for_each(|method| self.function(method)),
+            Statement::Implementation(definition) => definition.methods.iter().for_each(|method| self.function(method)),
+            Statement::Actor(actor) => actor.handlers.iter().for_each(|handler| self.function(handler)),
+            Statement::Return(Some(value)) => self.expression(value),
+            Statement::While { condition, body, .. } => {
+                self.loop_condition(condition, body);
+                self.expression(condition);
+                self.loop_depth += 1;
+                self.statements(body);
+                self.loop_depth -= 1;
+            },
+            Statement::Defer(statement) => self.statement(statement),
+            Statement::Assert { actual, expected, message, .. } => {
+                self.expression(actual);
+                expected.iter().chain(message).for_each(|expression| self.expression(expression));
+            },
+            Statement::Block(statements) => self.statements(statements),
+            _ => {},
+        }
+    }
+
+    fn function(&mut self, function: &FunctionDef) {
+        // A function body runs in a fresh scope, outside any loop around its definition
+        let outer = (std::mem::take(&mut self.strings), self.loop_depth);
+        self.loop_depth = 0;
+        for parameter in &function.parameters {
+            if matches!(&parameter.type_annotation, crate::ast::Type::String) {
+                self.strings.insert(parameter.name.clone());
+            }
+        }
+        self.statements(&function.body);
+        (self.strings, self.loop_depth) = outer;
+    }
+
+    /// Looks for lengths a loop condition recomputes even though they can't change
+    fn loop_condition(&mut self, condition: &Expression, body: &[Statement]) {
+        let mut collections = Vec::new();
+        length_calls(condition, &mut collections);
+        for collection in collections {
+            if !body.iter().any(|statement| statement_changes(statement, &collection)) {
+                self.hint(
+                    HintKind::LenInLoopCondition,
+                    format!(
+                        "`len({})` is recomputed before every iteration, but `{}` doesn't change in the loop; store the length in a variable before it",
+                        collection, collection
+                    ),
+                );
+            }
+        }
+    }
+
+    fn expressions(&mut self, expressions: &[Expression]) {
+        for expression in expressions {
+            self.expression(expression);
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Call(name, arguments) => {
+                // `x = value` parses as assign("x", value)
+                if let (true, [Expression::String(target), value]) = (name == "assign", arguments.as_slice()) {
+                    if self.loop_depth > 0 && appends_to(value, target) && (self.strings.contains(target) || is_string(value, &self.strings)) {
+                        self.hint(
+                            HintKind::StringConcatInLoop,
+                            format!(
+                                "`{}` is grown with `+` inside a loop, copying the whole string each time; collect the pieces in an array and join them once after the loop",
+                                target
+                            ),
+                        );
+                    }
+                }
+                self.expressions(arguments);
+            },
+            Expression::Match(subject, arms) => {
+                self.expression(subject);
+                for (pattern, guard, body) in arms {
+                    self.match_arm(subject, pattern, guard.as_deref(), body);
+                    guard.iter().for_each(|guard| self.expression(guard));
+                    self.statements(body);
+                }
+            },
+            Expression::Array(items) | Expression::Tuple(items) | Expression::Race(items) => self.expressions(items),
+            Expression::MacroInvocation(_, arguments) | Expression::Spawn(_, arguments) => self.expressions(arguments),
+            Expression::Struct(_, fields) => fields.iter().for_each(|(_, value)| self.expression(value)),
+            Expression::ArrayRepeat(first, second)
+            | Expression::BinaryOp(first, _, second, _)
+            | Expression::Index(first, second)
+            | Expression::Send(first, second)
+            | Expression::ChannelSend(first, second)
+            | Expression::Timeout(first, second) => {
+                self.expression(first);
+                self.expression(second);
+            },
+            Expression::Slice(value, start, end) => {
+                self.expression(value);
+                self.expression(start);
+                self.expression(end);
+            },
+            Expression::UnaryOp(_, value)
+            | Expression::FieldAccess(value, _)
+            | Expression::Propagate(value)
+            | Expression::ChannelReceive(value)
+            | Expression::ChannelClose(value)
+            | Expression::Await(value)
+            | Expression::Future(value)
+            | Expression::SpawnTask(value)
+            | Expression::Join(value)
+            | Expression::LambdaSimple(_, value) => self.expression(value),
+            Expression::MethodCall(receiver, _, arguments) => {
+                self.expression(receiver);
+                self.expressions(arguments);
+            },
+            Expression::Pipeline(value, stages) | Expression::BackPipeline(value, stages) => {
+                self.expression(value);
+                self.expressions(stages);
+            },
+            Expression::If(condition, then_branch, else_branch) => {
+                self.expression(condition);
+                self.statements(then_branch);
+                self.statements(else_branch);
+            },
+            Expression::Lambda(_, body)
+            | Expression::BlockExpr(body)
+            | Expression::Block(body)
+            | Expression::AsyncBlock(body) => self.statements(body),
+            Expression::TryCatch(body, _, handler) => {
+                self.statements(body);
+                self.statements(handler);
+            },
+            Expression::DestructureAssignment(_, value, body) => {
+                self.expression(value);
+                self.statement(body);
+            },
+            Expression::InterpolatedString(parts) => {
+                for part in parts {
+                    if let StringPart::Interpolated(value, _) = part {
+                        self.expression(value);
+                    }
+                }
+            },
+            Expression::Select(arms) => arms.iter().for_each(|arm| self.statements(&arm.body)),
+            _ => {},
+        }
+    }
+
+    /// Points out bindings in a match arm that copy values for nothing
+    fn match_arm(&mut self, subject: &Expression, pattern: &Pattern, guard: Option<&Expression>, body: &[Statement]) {
+        if let (Pattern::Identifier(name), Expression::Identifier(original)) = (pattern, subject) {
+            if is_binding(name) {
+                self.hint(
+                    HintKind::CopyingPatternBinding,
+                    format!("the arm binding `{}` copies all of `{}`; use `{}` itself, or `_` if the arm doesn't need it", name, original, original),
+                );
+                return;
+            }
+        }
+
+        let mut bound = Vec::new();
+        pattern_bindings(pattern, &mut bound);
+        for name in bound {
+            let used = guard.map_or(false, |guard| expression_mentions(guard, &name))
+                || body.iter().any(|statement| statement_mentions(statement, &name));
+            if !used {
+                self.hint(
+                    HintKind::CopyingPatternBinding,
+                    format!("`{}` is copied out of the matched value but never used; match it with `_` instead", name),
+                );
+            }
+        }
+    }
+}
+
+/// Whether a pattern name binds a variable rather than naming a unit variant like `None`
+fn is_binding(name: &str) -> bool {
+    name.chars().next().map_or(false, |first| first.is_lowercase() || first == '_') && name != "_"
+}
+
+fn pattern_bindings(pattern: &Pattern, names: &mut Vec<String>) {
+    match pattern {
+        Pattern::Identifier(name) if is_binding(name) => names.push(name.clone()),
+        Pattern::Tuple(items) | Pattern::Array(items) | Pattern::Enum(_, _, Some(items)) => {
+            items.iter().for_each(|item| pattern_bindings(item, names))
+        },
+        Pattern::Struct(_, fields) => fields.iter().for_each(|(_, field)| pattern_bindings(field, names)),
+        Pattern::Irrefutable(inner) | Pattern::Guard(inner, _) => pattern_bindings(inner, names),
+        // The two sides of an or-pattern bind the same names
+        Pattern::Or(left, _) => pattern_bindings(left, names),
+        _ => {},
+    }
+}
+
+/// Whether an expression is known to produce a string
+fn is_string(expression: &Expression, strings: &HashSet<String>) -> bool {
+    match expression {
+        Expression::String(_) | Expression::InterpolatedString(_) => true,
+        Expression::Identifier(name) => strings.contains(name),
+        Expression::Call(name, _) => name == "str",
+        Expression::BinaryOp(left, crate::ast::BinaryOp::Add, right, _) => is_string(left, strings) || is_string(right, strings),
+        _ => false,
+    }
+}
+
+/// Whether `value` is `target + ...`, extending what `target` already holds
+fn appends_to(value: &Expression, target: &str) -> bool {
+    match value {
+        Expression::BinaryOp(left, crate::ast::BinaryOp::Add, _, _) => {
+            matches!(&**left, Expression::Identifier(name) if name == target) || appends_to(left, target)
+        },
+        _ => false,
+    }
+}
+
+/// Collects the collections whose length an expression computes with len()
+fn length_calls(expression: &Expression, collections: &mut Vec<String>) {
+    match expression {
+        Expression::Call(name, arguments) if name == "len" || name == "vec_len" => {
+            if let [Expression::Identifier(collection)] = arguments.as_slice() {
+                collections.push(collection.clone());
+            }
+        },
+        Expression::MethodCall(receiver, method, arguments) if method == "len" && arguments.is_empty() => {
+            if let Expression::Identifier(collection) = &**receiver {
+                collections.push(collection.clone());
+            }
+        },
+        Expression::BinaryOp(left, _, right, _) => {
+            length_calls(left, collections);
+            length_calls(right, collections);
+        },
+        Expression::UnaryOp(_, value) => length_calls(value, collections),
+        _ => {},
+    }
+}
+
+/// Methods that change the length of the collection they're called on
+const RESIZING_METHODS: &[&str] = &["push", "pop", "insert", "remove", "clear", "append", "extend", "truncate"];
+
+/// Whether a statement may change `name`: by assigning it, or by a resizing method
+fn statement_changes(statement: &Statement, name: &str) -> bool {
+    let mut changes = false;
+    visit_statement(statement, &mut |expression| match expression {
+        Expression::Call(function, arguments) if function == "assign" => {
+            changes |= matches!(arguments.first(), Some(Expression::String(target)) if target == name);
+        },
+        Expression::MethodCall(receiver, method, _) if RESIZING_METHODS.contains(&method.as_str()) => {
+            changes |= matches!(&**receiver, Expression::Identifier(target) if target == name);
+        },
+        _ => {},
+    });
+    changes
+}
+
+fn statement_mentions(statement: &Statement, name: &str) -> bool {
+    let mut found = false;
+    visit_statement(statement, &mut |expression| found |= mentions(expression, name));
+    found
+}
+
+fn expression_mentions(expression: &Expression, name: &str) -> bool {
+    let mut found = false;
+    visit_expression(expression, &mut |expression| found |= mentions(expression, name));
+    found
+}
+
+/// Whether an expression itself, not counting what it contains, refers to `name`
+fn mentions(expression: &Expression, name: &str) -> bool {
+    match expression {
+        Expression::Identifier(identifier) | Expression::Call(identifier, _) => identifier == name,
+        // `@python{...}` and friends can reach variables by name, so count them as uses
+        Expression::MultiLangCall(_, code) => code.contains(name),
+        _ => false,
+    }
+}
+
+/// Calls `visit` on every expression in a statement, outermost first
+pub(crate) fn visit_statement(statement: &Statement, visit: &mut dyn FnMut(&Expression)) {
+    match statement {
+        Statement::Expression(expression)
+        | Statement::LetBinding { value: expression, .. }
+        | Statement::ConstBinding { value: expression, .. }
+        | Statement::Return(Some(expression)) => visit_expression(expression, visit),
+        Statement::While { condition, body, .. } => {
+            visit_expression(condition, visit);
+            body.iter().for_each(|statement| visit_statement(statement, visit));
+        },
+        Statement::Defer(statement) => visit_statement(statement, visit),
+        Statement::Assert { actual, expected, message, .. } => {
+            visit_expression(actual, visit);
+            expected.iter().chain(message).for_each(|expression| visit_expression(expression, visit));
+        },
+        Statement::Block(statements) => statements.iter().for_each(|statement| visit_statement(statement, visit)),
+        Statement::Function(function) => {
+            let defaults = function.parameters.iter().filter_map(|parameter| parameter.default_value.as_ref());
+            defaults.for_each(|default| visit_expression(default, visit));
+            function.body.iter().for_each(|statement| visit_statement(statement, visit));
+        },
+        _ => {},
+    }
+}
+
+fn visit_expression(expression: &Expression, visit: &mut dyn FnMut(&Expression)) {
+    visit(expression);
+    let statements = |statements: &[Statement], visit: &mut dyn FnMut(&Expression)| {
+        statements.iter().for_each(|statement| visit_statement(statement, visit))
+    };
+    match expression {
+        Expression::Array(items)
+        | Expression::Tuple(items)
+        | Expression::Race(items)
+        | Expression::Call(_, items)
+        | Expression::MacroInvocation(_, items)
+        | Expression::Spawn(_, items) => items.iter().for_each(|item| visit_expression(item, visit)),
+        Expression::Struct(_, fields) => fields.iter().for_each(|(_, value)| visit_expression(value, visit)),
+        Expression::ArrayRepeat(first, second)
+        | Expression::BinaryOp(first, _, second, _)
+        | Expression::Index(first, second)
+        | Expression::Send(first, second)
+        | Expression::ChannelSend(first, second)
+        | Expression::Timeout(first, second) => {
+            visit_expression(first, visit);
+            visit_expression(second, visit);
+        },
+        Expression::Slice(value, start, end) => {
+            visit_expression(value, visit);
+            visit_expression(start, visit);
+            visit_expression(end, visit);
+        },
+        Expression::UnaryOp(_, value)
+        | Expression::FieldAccess(value, _)
+        | Expression::Propagate(value)
+        | Expression::ChannelReceive(value)
+        | Expression::ChannelClose(value)
+        | Expression::Await(value)
+        | Expression::Future(value)
+        | Expression::SpawnTask(value)
+        | Expression::Join(value)
+        | Expression::LambdaSimple(_, value) => visit_expression(value, visit),
+        Expression::MethodCall(receiver, _, arguments) => {
+            visit_expression(receiver, visit);
+            arguments.iter().for_each(|argument| visit_expression(argument, visit));
+        },
+        Expression::Pipeline(value, stages) | Expression::BackPipeline(value, stages) => {
+            visit_expression(value, visit);
+            stages.iter().for_each(|stage| visit_expression(stage, visit));
+        },
+        Expression::If(condition, then_branch, else_branch) => {
+            visit_expression(condition, visit);
+            statements(then_branch, visit);
+            statements(else_branch, visit);
+        },
+        Expression::Match(subject, arms) => {
+            visit_expression(subject, visit);
+            for (_, guard, body) in arms {
+                guard.iter().for_each(|guard| visit_expression(guard, visit));
+                statements(body, visit);
+            }
+        },
+        Expression::Lambda(_, body)
+        | Expression::BlockExpr(body)
+        | Expression::Block(body)
+        | Expression::AsyncBlock(body) => statements(body, visit),
+        Expression::TryCatch(body, _, handler) => {
+            statements(body, visit);
+            statements(handler, visit);
+        },
+        Expression::DestructureAssignment(_, value, body) => {
+            visit_expression(value, visit);
+            visit_statement(body, visit);
+        },
+        Expression::InterpolatedString(parts) => {
+            for part in parts {
+                if let StringPart::Interpolated(value, _) = part {
+                    visit_expression(value, visit);
+                }
+            }
+        },
+        Expression::Select(arms) => arms.iter().for_each(|arm| statements(&arm.body, visit)),
+        _ => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> Vec<PerfHint> {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/perf_hints").join(name);
+        performance_hints_for_source(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_len_in_loop_condition() {
+        let hints = fixture("len_in_loop_condition.logos");
+        assert_eq!(hints.len(), 1);
+        assert_eq!(hints[0].kind, HintKind::LenInLoopCondition);
+        assert_eq!(hints[0].span, Some((3, 11)));
+        assert!(hints[0].message.starts_with("`len(items)` is recomputed"));
+
+        // A loop that grows the array really does need the fresh length
+        let growing = "fn fill(items: [Int]) {\n    while len(items) < 10 {\n        items.push(0)\n    }\n}\n";
+        assert!(performance_hints_for_source(growing).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_string_concat_in_loop() {
+        let hints = fixture("string_concat_in_loop.logos");
+        let kinds: Vec<HintKind> = hints.iter().map(|hint| hint.kind).collect();
+        assert_eq!(kinds, vec![HintKind::StringConcatInLoop]);
+        assert!(hints[0].message.starts_with("`row` is grown with `+`"));
+
+        // Adding numbers in a loop is fine
+        let sum = "fn f() {\n    mut n = 0\n    while n < 3 {\n        n = n + 1\n    }\n}\n";
+        assert!(performance_hints_for_source(sum).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_copying_pattern_bindings() {
+        let hints = fixture("pattern_binding_copies.logos");
+        let messages: Vec<(&str, Option<(usize, usize)>)> = hints.iter().map(|hint| (hint.message.as_str(), hint.span)).collect();
+        assert_eq!(messages, vec![
+            ("`w` is copied out of the matched value but never used; match it with `_` instead", Some((2, 6))),
+            ("`h` is copied out of the matched value but never used; match it with `_` instead", Some((2, 6))),
+            ("the arm binding `copy` copies all of `points`; use `points` itself, or `_` if the arm doesn't need it", Some((8, 12))),
+        ]);
+        assert!(hints.iter().all(|hint| hint.kind == HintKind::CopyingPatternBinding));
+
+        // Without the source the same hints are found, just not placed
+        let program = Parser::new("match p {\n    (a, b) => a,\n}").parse_program().unwrap();
+        let hints = performance_hints(&program);
+        assert_eq!(hints.len(), 1);
+        assert_eq!(hints[0].to_string(), "hint: `b` is copied out of the matched value but never used; match it with `_` instead");
+    }
+}
@@ -324,23 +324,9 @@ license = "MIT"
 "#, name, version, description)
     }
 
-    /// Validates a package specification
-    pub fn validate_spec(spec: &str) -> Result<(), String> {
-        // In a real implementation, this would validate the TOML structure
-        // For now, we'll just check if it contains basic required fields
-        if !spec.contains("[package]") {
-            return Err("Missing [package] section".to_string());
-        }
-        
-        if !spec.contains("name =") {
-            return Err("Missing package name".to_string());
-        }
-        
-        if !spec.contains("version =") {
-            return Err("Missing package version".to_string());
-        }
-        
-        Ok(())
+    /// Validates a package specification, returning it as a typed `Manifest`
+    pub fn validate_spec(spec: &str) -> Result<crate::package_manager::Manifest, String> {
+        crate::package_manager::Manifest::parse(spec)
     }
 }
 
@@ -0,0 +1,144 @@
+// Logos Call-Stack Profiler
+// Records where a running program spends its time as a stream of call-stack samples,
+// and writes them in the folded-stacks format read by flamegraph.pl and inferno:
+// one line per distinct stack, frames joined with `;`, followed by a sample count.
+//
+//     main;render;draw_line 42
+//
+// Sampling is driven by the interpreter rather than a timer: the runtime ticks the
+// profiler once per evaluated expression and every `interval`-th tick records the
+// current stack. That keeps the overhead to a counter increment on most ticks, and
+// makes profiles of the same program reproducible.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Default number of evaluation ticks between samples
+pub const DEFAULT_SAMPLE_INTERVAL: u64 = 100;
+
+/// Default file `logos run --profile` writes its folded stacks to
+pub const DEFAULT_OUTPUT: &str = "logos-profile.folded";
+
+/// Frame recorded for code running outside any function
+pub const TOP_LEVEL_FRAME: &str = "<top-level>";
+
+/// Collects call-stack samples from a running program
+#[derive(Debug, Clone)]
+pub struct Profiler {
+    stack: Vec<String>,
+    samples: HashMap<String, u64>, // Folded stack -> number of samples
+    interval: u64,
+    ticks: u64,
+}
+
+/// A profiler shared by a runtime and the child runtimes it creates for calls
+pub type SharedProfiler = Arc<Mutex<Profiler>>;
+
+impl Profiler {
+    /// Creates a profiler taking one sample every `interval` ticks
+    pub fn new(interval: u64) -> Self {
+        Profiler {
+            stack: Vec::new(),
+            samples: HashMap::new(),
+            interval: interval.max(1),
+            ticks: 0,
+        }
+    }
+
+    /// Creates a profiler ready to be attached to a runtime
+    pub fn shared(interval: u64) -> SharedProfiler {
+        Arc::new(Mutex::new(Self::new(interval)))
+    }
+
+    /// Records entry into a function
+    pub fn enter(&mut self, frame: &str) {
+        self.stack.push(frame.to_string());
+    }
+
+    /// Records return from the innermost function
+    pub fn exit(&mut self) {
+        self.stack.pop();
+    }
+
+    /// Advances the sample clock by one evaluation step
+    pub fn tick(&mut self) {
+        self.ticks += 1;
+        if self.ticks % self.interval == 0 {
+            let stack = if self.stack.is_empty() {
+                TOP_LEVEL_FRAME.to_string()
+            } else {
+                self.stack.join(";")
+            };
+            *self.samples.entry(stack).or_insert(0) += 1;
+        }
+    }
+
+    /// Returns the total number of samples taken
+    pub fn sample_count(&self) -> u64 {
+        self.samples.values().sum()
+    }
+
+    /// Renders the samples as folded stacks, one line per stack, sorted by stack
+    pub fn folded(&self) -> String {
+        let mut stacks: Vec<(&String, &u64)> = self.samples.iter().collect();
+        stacks.sort();
+        stacks.into_iter()
+            .map(|(stack, count)| format!("{} {}\n", stack, count))
+            .collect()
+    }
+
+    /// Writes the folded stacks to `path`
+    pub fn write_folded(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.folded())
+            .map_err(|e| format!("Could not write profile '{}': {}", path.display(), e))
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new(DEFAULT_SAMPLE_INTERVAL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::runtime::Runtime;
+
+    #[test]
+    fn test_samples_are_folded_by_stack() {
+        let mut profiler = Profiler::new(2);
+        profiler.enter("main");
+        profiler.tick();
+        profiler.tick();
+        profiler.enter("work");
+        profiler.tick();
+        profiler.tick();
+        profiler.exit();
+        profiler.exit();
+        profiler.tick();
+        profiler.tick();
+
+        assert_eq!(profiler.folded(), "<top-level> 1\nmain 1\nmain;work 1\n");
+        assert_eq!(profiler.sample_count(), 3);
+    }
+
+    #[test]
+    fn test_two_level_call_produces_folded_stack() {
+        let source = "fn inner() { 1 }\nfn outer() { inner() }\nouter()";
+        let program = Parser::new(source).parse_program().unwrap();
+
+        let profiler = Profiler::shared(1);
+        let mut runtime = Runtime::new();
+        runtime.set_profiler(profiler.clone());
+        runtime.eval_program(&program).unwrap();
+
+        let folded = profiler.lock().unwrap().folded();
+        let lines: Vec<&str> = folded.lines().collect();
+        assert!(lines.contains(&"outer;inner 1"), "{}", folded);
+        assert!(lines.contains(&"outer 1"), "{}", folded);
+    }
+}
@@ -0,0 +1,138 @@
+// Logos Project Scaffolding
+// This module holds the rules and templates used by `logos init` to create new
+// projects, kept separate from the CLI so they can be validated and tested.
+
+/// Kinds of project `logos init` can create
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectType {
+    Binary,
+    Library,
+    Web,
+    Wasm,
+}
+
+impl ProjectType {
+    /// All supported project types, in the order they are listed to users
+    pub const ALL: [ProjectType; 4] = [ProjectType::Binary, ProjectType::Library, ProjectType::Web, ProjectType::Wasm];
+
+    /// Parses a project type name as given on the command line
+    ///
+    /// # Returns
+    /// * `Ok(ProjectType)` for one of `binary`, `library`, `web`, `wasm`
+    /// * `Err` naming the supported types otherwise
+    pub fn parse(name: &str) -> Result<ProjectType, String> {
+        ProjectType::ALL.iter()
+            .copied()
+            .find(|ty| ty.as_str() == name)
+            .ok_or_else(|| {
+                let supported: Vec<&str> = ProjectType::ALL.iter().map(|ty| ty.as_str()).collect();
+                format!("Unknown project type '{}' (supported: {})", name, supported.join(", "))
+            })
+    }
+
+    /// The name of this project type as used on the command line
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProjectType::Binary => "binary",
+            ProjectType::Library => "library",
+            ProjectType::Web => "web",
+            ProjectType::Wasm => "wasm",
+        }
+    }
+
+    /// The source file created under `src/` for this project type
+    pub fn entry_file(&self) -> &'static str {
+        match self {
+            ProjectType::Library => "lib.logos",
+            _ => "main.logos",
+        }
+    }
+
+    /// Initial contents of the entry file
+    pub fn entry_template(&self) -> &'static str {
+        match self {
+            ProjectType::Binary => r#"// Main function for your Logos program
+fn main() {
+    print("Hello, Logos!")
+}
+"#,
+            ProjectType::Library => r#"// Library module for your Logos project
+fn greet(name: String) -> String {
+    "Hello, " + name + "!"
+}
+"#,
+            ProjectType::Web | ProjectType::Wasm => r#"// Logos Web Application
+// Entry point for web application
+fn main() {
+    print("Logos Web App Started")
+}
+"#,
+        }
+    }
+}
+
+impl std::fmt::Display for ProjectType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Checks that a project name can be used both as a directory name and as a package name
+///
+/// Names must start with an ASCII letter or underscore and contain only ASCII
+/// letters, digits, `_` and `-`. This rules out path separators, spaces, `.`/`..`
+/// and other names that would create a surprising directory.
+pub fn validate_project_name(name: &str) -> Result<(), String> {
+    let mut chars = name.chars();
+
+    match chars.next() {
+        None => return Err("Project name must not be empty".to_string()),
+        Some(first) if !(first.is_ascii_alphabetic() || first == '_') => {
+            return Err(format!(
+                "Invalid project name '{}': must start with a letter or underscore",
+                name
+            ));
+        },
+        Some(_) => {},
+    }
+
+    if let Some(bad) = chars.find(|c| !(c.is_ascii_alphanumeric() || *c == '_' || *c == '-')) {
+        return Err(format!(
+            "Invalid project name '{}': character {:?} is not allowed (use letters, digits, '_' or '-')",
+            name, bad
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_project_names() {
+        for name in ["hello", "my_app", "my-app", "_private", "App2"] {
+            assert!(validate_project_name(name).is_ok(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_rejected_project_names() {
+        for name in ["", "my app", "../escape", "a/b", "a\\b", ".", "..", "2fast", "-flag", "naïve"] {
+            assert!(validate_project_name(name).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_project_type_parsing() {
+        assert_eq!(ProjectType::parse("binary"), Ok(ProjectType::Binary));
+        assert_eq!(ProjectType::parse("library"), Ok(ProjectType::Library));
+        assert_eq!(ProjectType::parse("web"), Ok(ProjectType::Web));
+        assert_eq!(ProjectType::parse("wasm"), Ok(ProjectType::Wasm));
+
+        let err = ProjectType::parse("desktop").unwrap_err();
+        assert!(err.contains("desktop"));
+        assert!(err.contains("binary, library, web, wasm"));
+    }
+}
@@ -0,0 +1,97 @@
+// Logos Structured Run Results
+// Backs `logos run --output=json`: runs a program with its printed output captured
+// and reports the outcome as a single JSON object, so automation can read the result
+// instead of scraping the terminal. Only the program's stdout is captured; warnings
+// and status messages keep going to stderr.
+
+use std::time::Instant;
+
+use serde::Serialize;
+
+/// The outcome of running a program
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunReport {
+    pub success: bool,
+    pub stdout: String,        // Everything the program printed
+    pub error: Option<String>, // Why the run failed, if it did
+    pub duration_ms: u128,
+    pub memory_kb: u64,        // Peak resident memory of the process; 0 where unavailable
+}
+
+impl RunReport {
+    /// Renders the report as a JSON object, with keys in field order
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("a run report always serializes")
+    }
+}
+
+/// Runs a program, capturing what it prints, and reports how it went
+pub fn run_captured(source: &str) -> RunReport {
+    let start = Instant::now();
+    let (result, stdout) = crate::runtime::capture_output(|| crate::execute(source));
+
+    RunReport {
+        success: result.is_ok(),
+        stdout,
+        error: result.err().map(|e| e.to_string()),
+        duration_ms: start.elapsed().as_millis(),
+        memory_kb: peak_memory_kb(),
+    }
+}
+
+/// Reads the process's peak resident set size, where the platform exposes it
+fn peak_memory_kb() -> u64 {
+    std::fs::read_to_string("/proc/self/status").ok()
+        .and_then(|status| {
+            status.lines()
+                .find(|line| line.starts_with("VmHWM:"))
+                .and_then(|line| line.split_whitespace().nth(1))
+                .and_then(|kb| kb.parse().ok())
+        })
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_successful_run_report() {
+        let report = run_captured("print(\"hello\")\nprint(1, 2)");
+        assert!(report.success);
+        assert_eq!(report.stdout, "hello\n1 2\n");
+        assert_eq!(report.error, None);
+
+        let json = report.to_json();
+        assert!(json.starts_with("{\"success\":true,\"stdout\":\"hello\\n1 2\\n\",\"error\":null,\"duration_ms\":"), "{}", json);
+        assert!(json.contains(",\"memory_kb\":"));
+        assert!(json.ends_with('}'));
+    }
+
+    #[test]
+    fn test_failing_run_report() {
+        let report = run_captured("print(\"before\")\n10 / 0");
+        assert!(!report.success);
+        assert_eq!(report.stdout, "before\n");
+
+        let json = report.to_json();
+        assert!(json.starts_with("{\"success\":false,\"stdout\":\"before\\n\",\"error\":\"Division by zero: 10 / 0 at line 2, column 4\","), "{}", json);
+    }
+
+    #[test]
+    fn test_a_script_shows_the_value_it_ends_with() {
+        let report = run_captured("let x = 6\nx * 7");
+        assert_eq!(report.stdout, "42\n");
+
+        // Nothing extra is shown when the script ends with a statement or a Unit value
+        assert_eq!(run_captured("let x = 6").stdout, "");
+        assert_eq!(run_captured("print(\"done\")").stdout, "done\n");
+    }
+
+    #[test]
+    fn test_report_json_escapes_output() {
+        let report = run_captured("print(\"a \\\"quoted\\\" path\")");
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["stdout"], "a \"quoted\" path\n");
+    }
+}
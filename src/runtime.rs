@@ -3,8 +3,15 @@
 // It handles value representation, evaluation, and execution of the AST.
 
 use crate::ast::*;
+use crate::bytecode_generator::{Constant, Instruction};
+use crate::codegen::Bytecode;
+use crate::profiler::SharedProfiler;
+use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
 
 /// Represents different types of values in the Logos runtime
 #[derive(Debug, Clone)]
@@ -12,6 +19,7 @@ pub enum Value {
     Integer(i64),
     Float(f64),
     String(String),
+    Bytes(Vec<u8>),                       // Raw bytes, not necessarily UTF-8 (b"...", bytes())
     Boolean(bool),
     Unit,
     Char(char),                           // Character value
@@ -20,9 +28,21 @@ pub enum Value {
     Struct(String, HashMap<String, Value>), // For struct values
     Function(String, Vec<Parameter>, Vec<Statement>, Environment), // Function with closure environment
     BuiltinFunction(fn(&[Value]) -> Result<Value, String>), // Built-in functions
+    ForeignFunction(Arc<ExternDef>),      // Function declared with `extern`, called through the FFI
     Future(Box<Value>),                   // For async/await futures
     Task(Box<Value>),                     // For spawned tasks
     Closure(Vec<Parameter>, Vec<Statement>, Environment), // For closures/anonymous functions
+    Box(Box<Value>),                      // Uniquely owned heap value (Box::new)
+    Rc(Arc<Value>),                       // Shared, reference-counted value (Rc::new)
+    Cell(Arc<Mutex<Value>>),              // Mutable slot shared by every copy (Cell::new)
+    RefCell(Arc<RefCellSlot>),            // Mutable slot with runtime borrow checking (RefCell::new)
+    Borrow(Arc<BorrowGuard>),             // Live borrow of a RefCell, released when the last copy is dropped
+    TraitObject(String, Box<Value>, Arc<Vtable>), // dyn Trait: trait name, concrete value, its method table
+    Map(Arc<Mutex<HashMap<HashKey, Value>>>), // Hash map shared by every copy (Map::new)
+    Set(Arc<Mutex<HashSet<HashKey>>>),        // Hash set shared by every copy (Set::new)
+    Channel(Arc<ChannelSlot>),            // Typed channel shared by every copy (chan T)
+    Option(Option<Box<Value>>),           // Some(value) or None
+    Result(Result<Box<Value>, Box<Value>>), // Ok(value) or Err(error)
     // Add more value types as needed
 }
 
@@ -30,8 +50,9 @@ impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Value::Integer(i) => write!(f, "{}", i),
-            Value::Float(fl) => write!(f, "{}", fl),
+            Value::Float(fl) => write!(f, "{}", format_float(*fl)),
             Value::String(s) => write!(f, "\"{}\"", s),  // Properly quote strings
+            Value::Bytes(bytes) => write!(f, "b\"{}\"", crate::lexer::escape_bytes(bytes)),
             Value::Boolean(b) => write!(f, "{}", b),
             Value::Unit => write!(f, "()"),
             Value::Char(c) => write!(f, "'{}'", c),      // Properly quote characters
@@ -44,16 +65,42 @@ impl fmt::Display for Value {
                 write!(f, "({})", elements.join(", "))
             },
             Value::Struct(name, fields) => {
-                let field_strs: Vec<String> = fields.iter()
+                let field_strs: Vec<String> = sorted_fields(fields).into_iter()
                     .map(|(field_name, field_val)| format!("{}: {}", field_name, field_val))
                     .collect();
                 write!(f, "{} {{ {} }}", name, field_strs.join(", "))
             },
             Value::Function(name, _, _, _) => write!(f, "<function {}>", name),
             Value::BuiltinFunction(_) => write!(f, "<builtin function>"),
+            Value::ForeignFunction(def) => write!(f, "<extern \"{}\" fn {}>", def.abi, def.name),
             Value::Future(_) => write!(f, "<future>"),
             Value::Task(_) => write!(f, "<task>"),
             Value::Closure(_, _, _) => write!(f, "<closure>"),
+            Value::Box(inner) => write!(f, "Box({})", inner),
+            Value::Rc(inner) => write!(f, "Rc({})", inner),
+            Value::Cell(cell) => write!(f, "Cell({})", lock_cell(cell)),
+            Value::RefCell(cell) => write!(f, "RefCell({})", cell.get()),
+            Value::Borrow(guard) => write!(f, "{}", guard.cell.get()),
+            Value::TraitObject(_, inner, _) => write!(f, "{}", inner),
+            Value::Map(map) => {
+                let map = lock_cell(map);
+                let mut entries: Vec<(&HashKey, &Value)> = map.iter().collect();
+                entries.sort_by(|a, b| a.0.cmp(b.0));
+                let entry_strs: Vec<String> = entries.into_iter()
+                    .map(|(key, value)| format!("{}: {}", key.value(), value))
+                    .collect();
+                write!(f, "{{{}}}", entry_strs.join(", "))
+            },
+            Value::Set(set) => {
+                let set = lock_cell(set);
+                let elements: Vec<String> = sorted_keys(set.iter()).into_iter().map(|v| v.to_string()).collect();
+                write!(f, "{{{}}}", elements.join(", "))
+            },
+            Value::Channel(channel) => write!(f, "<channel of {}>", channel.element_type()),
+            Value::Option(Some(inner)) => write!(f, "Some({})", inner),
+            Value::Option(None) => write!(f, "None"),
+            Value::Result(Ok(inner)) => write!(f, "Ok({})", inner),
+            Value::Result(Err(error)) => write!(f, "Err({})", error),
         }
     }
 }
@@ -62,23 +109,559 @@ impl PartialEq for Value {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (Value::Integer(a), Value::Integer(b)) => a == b,
-            (Value::Float(a), Value::Float(b)) => (a - b).abs() < f64::EPSILON,
+            (Value::Float(a), Value::Float(b)) => floats_equal(*a, *b),
             (Value::String(a), Value::String(b)) => a == b,
+            (Value::Bytes(a), Value::Bytes(b)) => a == b,
             (Value::Boolean(a), Value::Boolean(b)) => a == b,
             (Value::Unit, Value::Unit) => true,
             (Value::Array(a), Value::Array(b)) => a == b,
             (Value::Tuple(a), Value::Tuple(b)) => a == b,
+            (Value::Struct(na, a), Value::Struct(nb, b)) => na == nb && a == b,
             (Value::Future(a), Value::Future(b)) => a == b,  // Compare the wrapped values
             (Value::Task(a), Value::Task(b)) => a == b,      // Compare the wrapped values
+            (Value::Box(a), Value::Box(b)) => a == b,
+            (Value::Rc(a), Value::Rc(b)) => a == b,
+            (Value::Cell(a), Value::Cell(b)) => Arc::ptr_eq(a, b) || *lock_cell(a) == *lock_cell(b),
+            (Value::RefCell(a), Value::RefCell(b)) => Arc::ptr_eq(a, b) || a.get() == b.get(),
+            (Value::Borrow(a), Value::Borrow(b)) => a.cell.get() == b.cell.get(),
+            (Value::TraitObject(ta, a, _), Value::TraitObject(tb, b, _)) => ta == tb && a == b,
+            (Value::Map(a), Value::Map(b)) => Arc::ptr_eq(a, b) || *lock_cell(a) == *lock_cell(b),
+            (Value::Set(a), Value::Set(b)) => Arc::ptr_eq(a, b) || *lock_cell(a) == *lock_cell(b),
+            (Value::Channel(a), Value::Channel(b)) => Arc::ptr_eq(a, b),
+            (Value::ForeignFunction(a), Value::ForeignFunction(b)) => Arc::ptr_eq(a, b),
+            (Value::Option(a), Value::Option(b)) => a == b,
+            (Value::Result(a), Value::Result(b)) => a == b,
             _ => false, // Different types or functions are not equal
         }
     }
 }
 
+/// Default limit on the elements a single array allocation may hold
+pub const DEFAULT_MAX_ALLOCATION: usize = 16 * 1024 * 1024;
+
+/// Default size, in bytes, of the stack `logos run` executes programs on
+pub const DEFAULT_STACK_SIZE: usize = 16 * 1024 * 1024;
+
+/// Size of the stack a runtime assumes it has when nothing says otherwise: the
+/// main thread's usual 8 MiB
+const ASSUMED_STACK_SIZE: usize = 8 * 1024 * 1024;
+
+/// Native stack one nested Logos call may take: a simple recursive call measures
+/// about 13 KiB optimized and 164 KiB unoptimized, and calls that nest blocks and
+/// matches take more
+const STACK_PER_CALL: usize = if cfg!(debug_assertions) { 256 * 1024 } else { 32 * 1024 };
+
+/// How many nested Logos calls fit in a stack of `stack_size` bytes
+///
+/// The runtime fails with a recursion error at this depth, before the native stack
+/// can overflow and abort the process.
+pub fn max_call_depth_for(stack_size: usize) -> usize {
+    (stack_size / STACK_PER_CALL).max(1)
+}
+
+/// Runs `f` on a new thread with a stack of `stack_size` bytes, returning its result
+///
+/// A panic in `f` is resumed on the calling thread.
+pub fn with_stack_size<T: Send>(stack_size: usize, f: impl FnOnce() -> T + Send) -> T {
+    std::thread::scope(|scope| {
+        let worker = std::thread::Builder::new()
+            .name("logos-main".to_string())
+            .stack_size(stack_size)
+            .spawn_scoped(scope, f)
+            .expect("failed to spawn the thread to run the program on");
+        worker.join().unwrap_or_else(|payload| std::panic::resume_unwind(payload))
+    })
+}
+
+/// Counts the elements allocated for one copy of a value: itself plus nested arrays and tuples
+fn element_count(value: &Value) -> u128 {
+    match value {
+        Value::Array(items) | Value::Tuple(items) => 1 + items.iter().map(element_count).sum::<u128>(),
+        _ => 1,
+    }
+}
+
+/// Methods of one trait implementation, by method name, used to dispatch `dyn Trait` calls
+pub type Vtable = HashMap<String, Value>;
+
+/// Float equality as `==` sees it: within an epsilon, except that NaN equals nothing,
+/// itself included, and each infinity equals only itself
+pub(crate) fn floats_equal(a: f64, b: f64) -> bool {
+    a == b || (a - b).abs() < f64::EPSILON
+}
+
+/// Significant digits a printed Float keeps; every decimal this long survives a round trip
+/// through f64, so literals print back as written
+const FLOAT_DIGITS: usize = f64::DIGITS as usize;
+
+/// The canonical text of a Float, shared by printing, interpolation and `{}` formatting
+///
+/// Values are rounded to 15 significant digits, which hides the error arithmetic leaves in the
+/// last bit (`0.1 + 0.2` prints `0.3`). Integral values keep a `.0` so they never read as
+/// Ints. Magnitudes from 1e-5 up to 1e16 are written out in full; outside that range, and for
+/// subnormals, scientific notation (`1e300`, `4.94065645841247e-324`) keeps the text short.
+pub fn format_float(value: f64) -> String {
+    if !value.is_finite() {
+        return value.to_string();
+    }
+    if value == 0.0 {
+        return if value.is_sign_negative() { "-0.0" } else { "0.0" }.to_string();
+    }
+
+    // Rust's scientific formatting does the rounding: "3.00000000000000e-1"
+    let scientific = format!("{:.*e}", FLOAT_DIGITS - 1, value);
+    let (mantissa, exponent) = scientific.split_once('e').expect("`{:e}` always writes an exponent");
+    let exponent: i32 = exponent.parse().expect("`{:e}` writes an integer exponent");
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(magnitude) => ("-", magnitude),
+        None => ("", mantissa),
+    };
+    let mantissa = mantissa.trim_end_matches('0').trim_end_matches('.');
+
+    if !(-5..16).contains(&exponent) {
+        return format!("{}{}e{}", sign, mantissa, exponent);
+    }
+    let digits = mantissa.replace('.', "");
+    let point = exponent + 1; // Digits before the decimal point
+    if point <= 0 {
+        format!("{}0.{}{}", sign, "0".repeat(point.unsigned_abs() as usize), digits)
+    } else if point as usize >= digits.len() {
+        format!("{}{}{}.0", sign, digits, "0".repeat(point as usize - digits.len()))
+    } else {
+        let (whole, fraction) = digits.split_at(point as usize);
+        format!("{}{}.{}", sign, whole, fraction)
+    }
+}
+
+/// Encodes a value as JSON text
+///
+/// Structs and maps become objects with their keys sorted, arrays, tuples, sets and
+/// bytes become arrays, and `()` and None become null. Some, Box, Rc and cells stand
+/// for what they hold. JSON has no NaN or infinity, and writing `null` in their place
+/// would lose the value without a word, so they are an error; so are values with no
+/// JSON form at all, such as functions or Results.
+pub fn to_json(value: &Value) -> Result<String, String> {
+    let list = |items: &[Value]| -> Result<String, String> {
+        Ok(format!("[{}]", items.iter().map(to_json).collect::<Result<Vec<_>, _>>()?.join(",")))
+    };
+    let object = |entries: Vec<(String, &Value)>| -> Result<String, String> {
+        let members = entries.into_iter()
+            .map(|(key, value)| Ok(format!("{}:{}", json_string(&key)?, to_json(value)?)))
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(format!("{{{}}}", members.join(",")))
+    };
+
+    match value {
+        Value::Integer(i) => Ok(i.to_string()),
+        Value::Float(f) if f.is_finite() => Ok(format_float(*f)),
+        Value::Float(f) => Err(format!("{} has no JSON representation", format_float(*f))),
+        Value::String(s) => json_string(s),
+        Value::Char(c) => json_string(&c.to_string()),
+        Value::Boolean(b) => Ok(b.to_string()),
+        Value::Unit | Value::Option(None) => Ok("null".to_string()),
+        Value::Bytes(bytes) => Ok(format!("[{}]", bytes.iter().map(u8::to_string).collect::<Vec<_>>().join(","))),
+        Value::Array(items) | Value::Tuple(items) => list(items),
+        Value::Set(set) => list(&sorted_keys(lock_cell(set).iter())),
+        Value::Struct(_, fields) => object(sorted_fields(fields).into_iter().map(|(name, value)| (name.clone(), value)).collect()),
+        Value::Map(map) => {
+            let map = lock_cell(map);
+            let mut entries: Vec<(&HashKey, &Value)> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            object(entries.into_iter()
+                .map(|(key, value)| match key.value() {
+                    Value::String(key) => (key.clone(), value),
+                    key => (key.to_string(), value),
+                })
+                .collect())
+        },
+        Value::Option(Some(inner)) | Value::Box(inner) | Value::TraitObject(_, inner, _) => to_json(inner),
+        Value::Rc(inner) => to_json(inner),
+        Value::Cell(cell) => to_json(&lock_cell(cell)),
+        Value::RefCell(cell) => to_json(&cell.get()),
+        Value::Borrow(guard) => to_json(&guard.cell.get()),
+        other => Err(format!("{} has no JSON representation", runtime_type_name(other))),
+    }
+}
+
+/// Encodes a string as a JSON string literal
+fn json_string(text: &str) -> Result<String, String> {
+    serde_json::to_string(text).map_err(|e| e.to_string())
+}
+
+/// Returns the type name a value is known by in `impl Trait for Type` blocks
+pub fn runtime_type_name(value: &Value) -> String {
+    match value {
+        Value::Integer(_) => "Int".to_string(),
+        Value::Float(_) => "Float".to_string(),
+        Value::String(_) => "String".to_string(),
+        Value::Bytes(_) => "Bytes".to_string(),
+        Value::Boolean(_) => "Bool".to_string(),
+        Value::Unit => "Unit".to_string(),
+        Value::Char(_) => "Char".to_string(),
+        Value::Array(_) => "Array".to_string(),
+        Value::Tuple(_) => "Tuple".to_string(),
+        Value::Struct(name, _) => name.clone(),
+        Value::Function(_, _, _, _) | Value::BuiltinFunction(_) | Value::ForeignFunction(_) | Value::Closure(_, _, _) => {
+            "Function".to_string()
+        },
+        Value::Future(_) => "Future".to_string(),
+        Value::Task(_) => "Task".to_string(),
+        Value::Box(_) => "Box".to_string(),
+        Value::Rc(_) => "Rc".to_string(),
+        Value::Cell(_) => "Cell".to_string(),
+        Value::RefCell(_) => "RefCell".to_string(),
+        Value::Borrow(_) => "Ref".to_string(),
+        Value::TraitObject(trait_name, _, _) => format!("dyn {}", trait_name),
+        Value::Map(_) => "Map".to_string(),
+        Value::Set(_) => "Set".to_string(),
+        Value::Channel(_) => "Channel".to_string(),
+        Value::Option(_) => "Option".to_string(),
+        Value::Result(_) => "Result".to_string(),
+    }
+}
+
+/// A value used as a `Map` key or `Set` element
+///
+/// Only values with a stable structural identity can be keys: integers, strings,
+/// booleans, chars, unit, and tuples, arrays, structs and boxes built from them.
+/// Floats are rejected because NaN is unequal to itself, and functions and shared
+/// cells because they have no structural identity. Hashing and ordering visit
+/// struct fields sorted by name, so they don't depend on how the fields were stored.
+#[derive(Debug, Clone)]
+pub struct HashKey(Value);
+
+impl HashKey {
+    /// Wraps a value as a key, or explains why it isn't hashable
+    pub fn new(value: Value) -> Result<Self, String> {
+        check_key_value(&value)?;
+        Ok(HashKey(value))
+    }
+
+    pub fn value(&self) -> &Value {
+        &self.0
+    }
+
+    pub fn into_value(self) -> Value {
+        self.0
+    }
+}
+
+fn check_key_value(value: &Value) -> Result<(), String> {
+    match value {
+        Value::Integer(_) | Value::String(_) | Value::Bytes(_) | Value::Boolean(_) | Value::Char(_) | Value::Unit => Ok(()),
+        Value::Array(items) | Value::Tuple(items) => items.iter().try_for_each(check_key_value),
+        Value::Struct(_, fields) => fields.values().try_for_each(check_key_value),
+        Value::Box(inner) => check_key_value(inner),
+        Value::Rc(inner) => check_key_value(inner),
+        Value::Float(_) => Err("Float values cannot be used as map keys: floats are not hashable".to_string()),
+        other => Err(format!("{} values cannot be used as map keys: they are not hashable", runtime_type_name(other))),
+    }
+}
+
+/// Orders values of different kinds when comparing keys
+fn key_rank(value: &Value) -> u8 {
+    match value {
+        Value::Unit => 0,
+        Value::Boolean(_) => 1,
+        Value::Integer(_) => 2,
+        Value::Char(_) => 3,
+        Value::String(_) => 4,
+        Value::Tuple(_) => 5,
+        Value::Array(_) => 6,
+        Value::Struct(_, _) => 7,
+        Value::Box(_) => 8,
+        Value::Rc(_) => 9,
+        Value::Bytes(_) => 10,
+        _ => u8::MAX, // Not hashable; HashKey::new rejects these
+    }
+}
+
+/// Returns struct fields sorted by name, the order used for hashing, comparison and display
+fn sorted_fields(fields: &HashMap<String, Value>) -> Vec<(&String, &Value)> {
+    let mut sorted: Vec<(&String, &Value)> = fields.iter().collect();
+    sorted.sort_by(|a, b| a.0.cmp(b.0));
+    sorted
+}
+
+/// Returns the values of a set of keys in key order
+fn sorted_keys<'k>(keys: impl Iterator<Item = &'k HashKey>) -> Vec<Value> {
+    let mut sorted: Vec<&HashKey> = keys.collect();
+    sorted.sort();
+    sorted.into_iter().map(|key| key.value().clone()).collect()
+}
+
+fn hash_key_value<H: Hasher>(value: &Value, state: &mut H) {
+    key_rank(value).hash(state);
+    match value {
+        Value::Integer(i) => i.hash(state),
+        Value::String(s) => s.hash(state),
+        Value::Bytes(bytes) => bytes.hash(state),
+        Value::Boolean(b) => b.hash(state),
+        Value::Char(c) => c.hash(state),
+        Value::Array(items) | Value::Tuple(items) => {
+            items.len().hash(state);
+            for item in items {
+                hash_key_value(item, state);
+            }
+        },
+        Value::Struct(name, fields) => {
+            name.hash(state);
+            for (field, field_value) in sorted_fields(fields) {
+                field.hash(state);
+                hash_key_value(field_value, state);
+            }
+        },
+        Value::Box(inner) => hash_key_value(inner, state),
+        Value::Rc(inner) => hash_key_value(inner, state),
+        _ => {},
+    }
+}
+
+fn compare_key_values(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Integer(x), Value::Integer(y)) => x.cmp(y),
+        (Value::String(x), Value::String(y)) => x.cmp(y),
+        (Value::Bytes(x), Value::Bytes(y)) => x.cmp(y),
+        (Value::Boolean(x), Value::Boolean(y)) => x.cmp(y),
+        (Value::Char(x), Value::Char(y)) => x.cmp(y),
+        (Value::Array(x), Value::Array(y)) | (Value::Tuple(x), Value::Tuple(y)) => {
+            x.iter().zip(y)
+                .map(|(p, q)| compare_key_values(p, q))
+                .find(|ordering| *ordering != Ordering::Equal)
+                .unwrap_or_else(|| x.len().cmp(&y.len()))
+        },
+        (Value::Struct(na, fa), Value::Struct(nb, fb)) => {
+            let (fa, fb) = (sorted_fields(fa), sorted_fields(fb));
+            na.cmp(nb).then_with(|| {
+                fa.iter().zip(&fb)
+                    .map(|((ka, va), (kb, vb))| ka.cmp(kb).then_with(|| compare_key_values(va, vb)))
+                    .find(|ordering| *ordering != Ordering::Equal)
+                    .unwrap_or_else(|| fa.len().cmp(&fb.len()))
+            })
+        },
+        (Value::Box(x), Value::Box(y)) => compare_key_values(x, y),
+        (Value::Rc(x), Value::Rc(y)) => compare_key_values(x, y),
+        _ => key_rank(a).cmp(&key_rank(b)),
+    }
+}
+
+impl Hash for HashKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_key_value(&self.0, state);
+    }
+}
+
+impl PartialEq for HashKey {
+    fn eq(&self, other: &Self) -> bool {
+        compare_key_values(&self.0, &other.0) == Ordering::Equal
+    }
+}
+
+impl Eq for HashKey {}
+
+impl PartialOrd for HashKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for HashKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare_key_values(&self.0, &other.0)
+    }
+}
+
+/// Storage behind a `RefCell` value
+///
+/// Borrows are tracked at runtime, for sharing patterns the static borrow checker
+/// in `memory_safety` can't prove safe. A conflicting borrow is reported as an
+/// error rather than aborting the program.
+#[derive(Debug)]
+pub struct RefCellSlot {
+    value: Mutex<Value>,
+    borrows: Mutex<BorrowState>,
+}
+
+/// Outstanding borrows of a `RefCellSlot`
+#[derive(Debug, Default)]
+struct BorrowState {
+    shared: usize,
+    mutable: bool,
+}
+
+impl RefCellSlot {
+    /// Creates an unborrowed slot holding `value`
+    pub fn new(value: Value) -> Self {
+        RefCellSlot {
+            value: Mutex::new(value),
+            borrows: Mutex::new(BorrowState::default()),
+        }
+    }
+
+    /// Returns a copy of the current contents
+    pub fn get(&self) -> Value {
+        lock_cell(&self.value).clone()
+    }
+
+    /// Starts a shared or mutable borrow
+    ///
+    /// # Returns
+    /// * `Ok(BorrowGuard)` that ends the borrow when dropped
+    /// * `Err` if the borrow conflicts with one already outstanding
+    pub fn borrow(self: &Arc<Self>, mutable: bool) -> Result<BorrowGuard, String> {
+        let mut state = lock_cell(&self.borrows);
+
+        if state.mutable {
+            return Err("RefCell already mutably borrowed".to_string());
+        }
+        if mutable {
+            if state.shared > 0 {
+                return Err("RefCell already borrowed".to_string());
+            }
+            state.mutable = true;
+        } else {
+            state.shared += 1;
+        }
+
+        Ok(BorrowGuard { cell: Arc::clone(self), mutable })
+    }
+}
+
+/// A live borrow of a `RefCellSlot`
+#[derive(Debug)]
+pub struct BorrowGuard {
+    cell: Arc<RefCellSlot>,
+    mutable: bool,
+}
+
+impl BorrowGuard {
+    /// Replaces the borrowed value; only allowed through a mutable borrow
+    pub fn set(&self, value: Value) -> Result<(), String> {
+        if !self.mutable {
+            return Err("Cannot assign through a shared borrow".to_string());
+        }
+        *lock_cell(&self.cell.value) = value;
+        Ok(())
+    }
+}
+
+impl Drop for BorrowGuard {
+    fn drop(&mut self) {
+        let mut state = lock_cell(&self.cell.borrows);
+        if self.mutable {
+            state.mutable = false;
+        } else {
+            state.shared -= 1;
+        }
+    }
+}
+
+/// Storage behind a `Channel` value: an `mpsc` queue and the type of what it carries
+///
+/// The interpreter runs on one thread, so `recv` never waits: receiving from an
+/// empty channel is an error instead of a deadlock. Closing drops the sender, after
+/// which queued values can still be received.
+#[derive(Debug)]
+pub struct ChannelSlot {
+    element_type: Type,
+    sender: Mutex<Option<Sender<Value>>>,
+    receiver: Mutex<Receiver<Value>>,
+}
+
+impl ChannelSlot {
+    /// Creates an open, empty channel carrying values of `element_type`
+    pub fn new(element_type: Type) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        ChannelSlot {
+            element_type,
+            sender: Mutex::new(Some(sender)),
+            receiver: Mutex::new(receiver),
+        }
+    }
+
+    /// The type every value sent on the channel must have
+    pub fn element_type(&self) -> &Type {
+        &self.element_type
+    }
+
+    /// Queues `value`, rejecting values of the wrong type and sends after `close`
+    pub fn send(&self, value: Value) -> Result<(), String> {
+        // Ints are promoted on a Float channel, as the type checker allows
+        let value = match (&self.element_type, value) {
+            (Type::Float, Value::Integer(i)) => Value::Float(i as f64),
+            (_, value) => value,
+        };
+        if !value_has_type(&value, &self.element_type) {
+            return Err(format!(
+                "Channel send: expected {}, found {}",
+                self.element_type,
+                runtime_type_name(&value)
+            ));
+        }
+        match lock_cell(&self.sender).as_ref() {
+            Some(sender) => sender.send(value).map_err(|_| "Send on a closed channel".to_string()),
+            None => Err("Send on a closed channel".to_string()),
+        }
+    }
+
+    /// Takes the oldest queued value
+    pub fn recv(&self) -> Result<Value, String> {
+        self.try_recv()?.ok_or_else(|| "Receive on an empty channel would block forever".to_string())
+    }
+
+    /// Takes the oldest queued value if there is one; `None` means the channel is empty but open
+    pub fn try_recv(&self) -> Result<Option<Value>, String> {
+        match lock_cell(&self.receiver).try_recv() {
+            Ok(value) => Ok(Some(value)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err("Receive on a closed channel".to_string()),
+        }
+    }
+
+    /// Stops further sends; values already queued stay receivable
+    pub fn close(&self) {
+        lock_cell(&self.sender).take();
+    }
+}
+
+/// Whether `value` can stand where `ty` is expected
+///
+/// Types the runtime can't see through, such as named types, generics and
+/// functions, accept any value; the type checker has already vetted those.
+pub(crate) fn value_has_type(value: &Value, ty: &Type) -> bool {
+    match (ty, value) {
+        (Type::Int, Value::Integer(_))
+        | (Type::Float, Value::Float(_))
+        | (Type::Bool, Value::Boolean(_))
+        | (Type::String, Value::String(_))
+        | (Type::Unit, Value::Unit)
+        | (Type::Map(_, _), Value::Map(_))
+        | (Type::Set(_), Value::Set(_))
+        | (Type::Option(_), Value::Option(None)) => true,
+        (Type::Array(inner), Value::Array(items)) => items.iter().all(|item| value_has_type(item, inner)),
+        (Type::Tuple(types), Value::Tuple(items)) => {
+            types.len() == items.len() && items.iter().zip(types).all(|(item, ty)| value_has_type(item, ty))
+        },
+        (Type::Channel(inner), Value::Channel(channel)) => channel.element_type() == inner.as_ref(),
+        (Type::Option(inner), Value::Option(Some(value))) => value_has_type(value, inner),
+        (Type::Result(ok, _), Value::Result(Ok(value))) => value_has_type(value, ok),
+        (Type::Result(_, err), Value::Result(Err(value))) => value_has_type(value, err),
+        (Type::Named(name), Value::Struct(struct_name, _)) => name == struct_name,
+        (
+            Type::Int | Type::Float | Type::Bool | Type::String | Type::Unit | Type::Array(_) | Type::Tuple(_)
+            | Type::Channel(_) | Type::Map(_, _) | Type::Set(_) | Type::Option(_) | Type::Result(_, _),
+            _,
+        ) => false,
+        _ => true,
+    }
+}
+
 /// Environment for variable bindings during execution
 #[derive(Debug, Clone)]
 pub struct Environment {
     values: HashMap<String, Value>,
+    moved: HashSet<String>,  // Bindings in this scope whose Box was moved out
+    mutable: HashSet<String>,  // Bindings in this scope declared with `mut`, which may be assigned to
     parent: Option<Box<Environment>>,
 }
 
@@ -87,6 +670,8 @@ impl Environment {
     pub fn new(parent: Option<Environment>) -> Self {
         Environment {
             values: HashMap::new(),
+            moved: HashSet::new(),
+            mutable: HashSet::new(),
             parent: parent.map(Box::new),
         }
     }
@@ -95,6 +680,7 @@ impl Environment {
     pub fn get(&self, name: &str) -> Option<Value> {
         match self.values.get(name) {
             Some(value) => Some(value.clone()),
+            None if self.moved.contains(name) => None,
             None => {
                 if let Some(ref parent) = self.parent {
                     parent.get(name)
@@ -105,11 +691,74 @@ impl Environment {
         }
     }
 
-    /// Sets a value in the environment
+    /// Sets a value in the environment, as an immutable binding
     pub fn set(&mut self, name: String, value: Value) {
+        self.moved.remove(&name);
+        self.mutable.remove(&name);
+        self.values.insert(name, value);
+    }
+
+    /// Sets a value in the environment as a binding that `assign` may change
+    pub fn set_mutable(&mut self, name: String, value: Value) {
+        self.mutable.insert(name.clone());
+        self.moved.remove(&name);
         self.values.insert(name, value);
     }
 
+    /// Assigns to the nearest existing binding of a name, in whichever scope holds it
+    ///
+    /// Only bindings declared with `mut` can be assigned to. Assigning to a binding
+    /// that was moved out of makes it readable again.
+    pub fn assign(&mut self, name: &str, value: Value) -> Result<(), String> {
+        if self.values.contains_key(name) || self.moved.contains(name) {
+            if !self.mutable.contains(name) {
+                return Err(format!("Cannot assign to immutable variable: {} (declare it with `mut`)", name));
+            }
+            self.moved.remove(name);
+            self.values.insert(name.to_string(), value);
+            Ok(())
+        } else if let Some(ref mut parent) = self.parent {
+            parent.assign(name, value)
+        } else {
+            Err(format!("Cannot assign to undeclared variable: {}", name))
+        }
+    }
+
+    /// Marks a binding as moved out of; it is unreadable until assigned again
+    pub fn mark_moved(&mut self, name: &str) {
+        if self.values.remove(name).is_some() {
+            self.moved.insert(name.to_string());
+        } else if let Some(ref mut parent) = self.parent {
+            parent.mark_moved(name);
+        }
+    }
+
+    /// Checks if the nearest binding of a name has been moved out of
+    pub fn is_moved(&self, name: &str) -> bool {
+        if self.values.contains_key(name) {
+            false
+        } else if self.moved.contains(name) {
+            true
+        } else if let Some(ref parent) = self.parent {
+            parent.is_moved(name)
+        } else {
+            false
+        }
+    }
+
+    /// Enters a new innermost scope; bindings made until `pop_scope` shadow outer ones
+    pub fn push_scope(&mut self) {
+        let outer = std::mem::replace(self, Environment::new(None));
+        self.parent = Some(Box::new(outer));
+    }
+
+    /// Leaves the innermost scope, discarding its bindings and restoring any shadowed ones
+    pub fn pop_scope(&mut self) {
+        if let Some(parent) = self.parent.take() {
+            *self = *parent;
+        }
+    }
+
     /// Checks if a variable exists in the environment
     pub fn contains(&self, name: &str) -> bool {
         if self.values.contains_key(name) {
@@ -120,13 +769,38 @@ impl Environment {
             false
         }
     }
+
+    /// Every name visible from this scope, sorted, including builtins
+    pub fn names(&self) -> Vec<String> {
+        let mut names = std::collections::BTreeSet::new();
+        let mut scope = Some(self);
+        while let Some(env) = scope {
+            names.extend(env.values.keys().cloned());
+            scope = env.parent.as_deref();
+        }
+        names.into_iter().collect()
+    }
+}
+
+/// A function defined by `DEFINE_FN` in bytecode, with the constant pool its body loads from
+#[derive(Debug)]
+struct BytecodeFunction {
+    params: Vec<String>,
+    body: Vec<Instruction>,
+    constants: Arc<Vec<Constant>>,
+    env: Environment,  // Bindings visible where the function was defined
 }
 
 /// The Runtime struct manages program execution
 pub struct Runtime {
     pub env: Environment,
+    trait_impls: Arc<Mutex<HashMap<(String, String), Arc<Vtable>>>>,  // (type, trait) -> methods, shared with child runtimes
     recursion_depth: usize,  // Track recursion depth to prevent stack overflow
     max_recursion_depth: usize,  // Maximum allowed recursion depth
+    max_allocation: usize,  // Maximum number of elements a single array allocation may hold
+    max_call_depth: usize,  // Maximum number of nested Logos function calls
+    profiler: Option<SharedProfiler>,  // Call-stack sampler, shared with child runtimes, when profiling
+    bytecode_functions: Arc<Mutex<HashMap<String, Arc<BytecodeFunction>>>>,  // Functions defined by eval_bytecode, shared with child runtimes
 }
 
 impl Runtime {
@@ -140,23 +814,274 @@ impl Runtime {
         env.set("str".to_string(), Value::BuiltinFunction(runtime_str));
         env.set("int".to_string(), Value::BuiltinFunction(runtime_int));
         env.set("float".to_string(), Value::BuiltinFunction(runtime_float));
+        env.set("bytes".to_string(), Value::BuiltinFunction(runtime_bytes));
+
+        // Explicit numeric conversions, since Int and Float never mix implicitly
+        env.set("to_float".to_string(), Value::BuiltinFunction(runtime_to_float));
+        env.set("to_int".to_string(), Value::BuiltinFunction(runtime_to_int));
+        env.set("parse_int".to_string(), Value::BuiltinFunction(runtime_parse_int));
+        env.set("parse_float".to_string(), Value::BuiltinFunction(runtime_parse_float));
+        env.set("panic".to_string(), Value::BuiltinFunction(runtime_panic));
+        env.set("debug::assert".to_string(), Value::BuiltinFunction(runtime_debug_assert));
+
+        // Files; relative paths resolve against the script's directory, see std_lib::file_io
+        env.set("script_dir".to_string(), Value::BuiltinFunction(runtime_script_dir));
+        env.set("read_file".to_string(), Value::BuiltinFunction(runtime_read_file));
+        env.set("write_file".to_string(), Value::BuiltinFunction(runtime_write_file));
+
+        // The program's arguments (what followed `--` on `logos run`) and environment, see std_lib::env
+        env.set("env::args".to_string(), Value::BuiltinFunction(runtime_env_args));
+        env.set("env::arg".to_string(), Value::BuiltinFunction(runtime_env_arg));
+        env.set("env::vars".to_string(), Value::BuiltinFunction(runtime_env_vars));
+        env.set("env::get_var".to_string(), Value::BuiltinFunction(runtime_env_get_var));
+        env.set("env::set_var".to_string(), Value::BuiltinFunction(runtime_env_set_var));
+
+        // JSON text of a value, see to_json
+        env.set("json::stringify".to_string(), Value::BuiltinFunction(runtime_json_stringify));
+
+        // Smart pointer constructors
+        env.set("Box::new".to_string(), Value::BuiltinFunction(runtime_box_new));
+        env.set("Rc::new".to_string(), Value::BuiltinFunction(runtime_rc_new));
+        env.set("Rc::clone".to_string(), Value::BuiltinFunction(runtime_rc_clone));
+        env.set("Cell::new".to_string(), Value::BuiltinFunction(runtime_cell_new));
+        env.set("RefCell::new".to_string(), Value::BuiltinFunction(runtime_refcell_new));
+
+        // Hashed collections
+        env.set("Map::new".to_string(), Value::BuiltinFunction(runtime_map_new));
+        env.set("Set::new".to_string(), Value::BuiltinFunction(runtime_set_new));
+
+        // Option and Result constructors
+        env.set("Some".to_string(), Value::BuiltinFunction(runtime_some));
+        env.set("None".to_string(), Value::Option(None));
+        env.set("Ok".to_string(), Value::BuiltinFunction(runtime_ok));
+        env.set("Err".to_string(), Value::BuiltinFunction(runtime_err));
         
         Runtime {
             env,
+            trait_impls: Arc::new(Mutex::new(HashMap::new())),
             recursion_depth: 0,
             max_recursion_depth: 100,  // Reasonable default to prevent stack overflow
+            max_allocation: DEFAULT_MAX_ALLOCATION,
+            max_call_depth: max_call_depth_for(ASSUMED_STACK_SIZE),
+            profiler: None,
+            bytecode_functions: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
+    /// Creates a runtime for a nested body (function call, branch, match arm)
+    ///
+    /// The child sees `env` and shares this runtime's trait implementations, which
+    /// are global like in Rust rather than scoped to where the `impl` appeared.
+    fn child_runtime(&self, env: Environment) -> Runtime {
+        let mut child = Runtime::new();
+        child.env = env;
+        child.trait_impls = Arc::clone(&self.trait_impls);
+        child.max_allocation = self.max_allocation;
+        child.max_call_depth = self.max_call_depth;
+        child.profiler = self.profiler.clone();
+        child.bytecode_functions = Arc::clone(&self.bytecode_functions);
+        child
+    }
+
+    /// Attaches a profiler that samples the call stack while the program runs
+    pub fn set_profiler(&mut self, profiler: SharedProfiler) {
+        self.profiler = Some(profiler);
+    }
+
+    /// Returns the attached profiler, if any
+    pub fn profiler(&self) -> Option<SharedProfiler> {
+        self.profiler.clone()
+    }
+
+    /// Sets the largest array, in elements, the program may allocate at once
+    ///
+    /// Nested arrays count every element they contain, so `[[0; 1000]; 1000]` needs
+    /// a limit of about a million.
+    pub fn set_max_allocation(&mut self, elements: usize) {
+        self.max_allocation = elements;
+    }
+
+    /// Returns the current allocation limit, in elements
+    pub fn max_allocation(&self) -> usize {
+        self.max_allocation
+    }
+
+    /// Sets how many Logos function calls may be in progress at once
+    ///
+    /// The default suits an 8 MiB stack. A program run on a bigger stack (see
+    /// `with_stack_size`) can go deeper; `max_call_depth_for` gives the matching limit.
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+
+    /// Returns the current limit on nested function calls
+    pub fn max_call_depth(&self) -> usize {
+        self.max_call_depth
+    }
+
     /// Evaluates a program (sequence of statements)
     pub fn eval_program(&mut self, program: &Program) -> Result<Value, String> {
-        let mut result = Value::Unit;
+        self.eval_statements(&program.statements).map_err(|e| match take_exit(&e) {
+            Some(exit) => exit.escaped_message(),
+            None => e,
+        })
+    }
+
+    /// Runs bytecode from `codegen::compile_to_bytecode` in this runtime's environment
+    ///
+    /// Returns the value of the program's final expression, like `eval_program`.
+    /// Functions the bytecode defines can be called afterwards with `call_by_name`.
+    pub fn eval_bytecode(&mut self, bytecode: &Bytecode) -> Result<Value, String> {
+        self.run_instructions(&bytecode.instructions, &Arc::new(bytecode.constants.clone()))
+    }
+
+    /// Whether bytecode run by this runtime defined a function called `name`
+    pub fn has_bytecode_function(&self, name: &str) -> bool {
+        lock_cell(&self.bytecode_functions).contains_key(name)
+    }
+
+    /// Calls the function named `name`: one defined by bytecode, or else one bound in the environment
+    pub fn call_by_name(&mut self, name: &str, args: Vec<Value>) -> Result<Value, String> {
+        let defined = lock_cell(&self.bytecode_functions).get(name).cloned();
+        let Some(function) = defined else {
+            let func = self.env.get(name).ok_or_else(|| format!("Undefined function: {}", name))?;
+            return self.call_function(func, args);
+        };
+
+        if function.params.len() != args.len() {
+            return Err(format!("Argument count mismatch for function {}", name));
+        }
+        if CALL_STACK.with(|stack| stack.borrow().len()) >= self.max_call_depth {
+            return Err(format!(
+                "Recursion depth exceeded: more than {} nested calls reaching {}; a larger --stack-size allows deeper recursion",
+                self.max_call_depth, name
+            ));
+        }
+
+        // As with closures, the body sees the bindings from where it was defined; other
+        // bytecode functions are found by name, so they may be defined later
+        let mut frame_env = Environment::new(Some(function.env.clone()));
+        for (param, arg) in function.params.iter().zip(args) {
+            frame_env.set(param.clone(), arg);
+        }
+        let mut frame = self.child_runtime(frame_env);
+
+        if let Some(profiler) = &self.profiler {
+            lock_cell(profiler).enter(name);
+        }
+        CALL_STACK.with(|stack| stack.borrow_mut().push(name.to_string()));
+        let result = frame.run_instructions(&function.body, &function.constants);
+        CALL_STACK.with(|stack| stack.borrow_mut().pop());
+        if let Some(profiler) = &self.profiler {
+            lock_cell(profiler).exit();
+        }
+        result
+    }
 
-        for statement in &program.statements {
-            result = self.eval_statement(statement)?;
+    /// Executes one instruction sequence on its own operand stack
+    ///
+    /// `RETURN`, or running off the end, yields the top of the stack, or unit when
+    /// the sequence left nothing there.
+    fn run_instructions(&mut self, code: &[Instruction], constants: &Arc<Vec<Constant>>) -> Result<Value, String> {
+        fn pop(stack: &mut Vec<Value>) -> Result<Value, String> {
+            stack.pop().ok_or_else(|| "Bytecode stack underflow".to_string())
         }
 
-        Ok(result)
+        let mut stack: Vec<Value> = Vec::new();
+        let mut pc = 0;
+        let mut location = ParsedAt::default(); // Set by `Located` for the instruction after it
+        while let Some(instruction) = code.get(pc) {
+            pc += 1;
+            let parsed_at = std::mem::take(&mut location);
+            match instruction {
+                Instruction::LoadConstant(index) => {
+                    let constant = constants.get(*index)
+                        .ok_or_else(|| format!("Bytecode refers to missing constant #{}", index))?;
+                    stack.push(match constant {
+                        Constant::Integer(i) => Value::Integer(*i),
+                        Constant::Float(f) => Value::Float(*f),
+                        Constant::String(s) => Value::String(s.clone()),
+                        Constant::Boolean(b) => Value::Boolean(*b),
+                        Constant::Unit => Value::Unit,
+                    });
+                },
+                Instruction::LoadVar(name) => {
+                    stack.push(self.env.get(name).ok_or_else(|| format!("Undefined variable: {}", name))?);
+                },
+                Instruction::StoreVar(name) => {
+                    let value = pop(&mut stack)?;
+                    self.env.set(name.clone(), value);
+                },
+                Instruction::Add | Instruction::Sub | Instruction::Mul | Instruction::Div | Instruction::Mod
+                | Instruction::Eq | Instruction::Ne | Instruction::Lt | Instruction::Gt | Instruction::Le
+                | Instruction::Ge | Instruction::And | Instruction::Or => {
+                    let right = pop(&mut stack)?;
+                    let left = pop(&mut stack)?;
+                    stack.push(match instruction {
+                        Instruction::Add => binary_op_add(left, right)?,
+                        Instruction::Sub => binary_op_sub(left, right)?,
+                        Instruction::Mul => binary_op_mul(left, right)?,
+                        Instruction::Div => binary_op_div(left, right).map_err(|e| located(e, &parsed_at))?,
+                        Instruction::Mod => binary_op_mod(left, right).map_err(|e| located(e, &parsed_at))?,
+                        Instruction::Eq => Value::Boolean(self.values_equal(left, right)?),
+                        Instruction::Ne => Value::Boolean(!self.values_equal(left, right)?),
+                        Instruction::Lt => binary_op_lt(left, right)?,
+                        Instruction::Gt => binary_op_gt(left, right)?,
+                        Instruction::Le => binary_op_le(left, right)?,
+                        Instruction::Ge => binary_op_ge(left, right)?,
+                        Instruction::And => binary_op_and(left, right)?,
+                        _ => binary_op_or(left, right)?,
+                    });
+                },
+                Instruction::Not => {
+                    let value = pop(&mut stack)?;
+                    stack.push(unary_op_not(value)?);
+                },
+                Instruction::Neg => {
+                    let value = pop(&mut stack)?;
+                    stack.push(unary_op_neg(value)?);
+                },
+                Instruction::Jump(target) => pc = *target,
+                Instruction::JumpIfTrue(target) => {
+                    if is_truthy(&pop(&mut stack)?) {
+                        pc = *target;
+                    }
+                },
+                Instruction::JumpIfFalse(target) => {
+                    if !is_truthy(&pop(&mut stack)?) {
+                        pc = *target;
+                    }
+                },
+                Instruction::Call(name, argc) => {
+                    let first_arg = stack.len().checked_sub(*argc)
+                        .ok_or_else(|| "Bytecode stack underflow".to_string())?;
+                    let args = stack.split_off(first_arg);
+                    stack.push(self.call_by_name(name, args)?);
+                },
+                Instruction::Return => break,
+                Instruction::DefineFunction(name, params, body) => {
+                    let function = BytecodeFunction {
+                        params: params.clone(),
+                        body: body.clone(),
+                        constants: Arc::clone(constants),
+                        env: self.env.clone(),
+                    };
+                    lock_cell(&self.bytecode_functions).insert(name.clone(), Arc::new(function));
+                },
+                Instruction::Pop => {
+                    pop(&mut stack)?;
+                },
+                Instruction::Dup => {
+                    let top = stack.last().cloned().ok_or_else(|| "Bytecode stack underflow".to_string())?;
+                    stack.push(top);
+                },
+                Instruction::EnterScope => self.env.push_scope(),
+                Instruction::ExitScope => self.env.pop_scope(),
+                Instruction::Print => print_value(&pop(&mut stack)?),
+                Instruction::Located(at) => location = ParsedAt(Some(*at)),
+            }
+        }
+        Ok(stack.pop().unwrap_or(Value::Unit))
     }
 
     /// Executes a program (same as eval_program)
@@ -168,11 +1093,17 @@ impl Runtime {
     pub fn eval_statement(&mut self, statement: &Statement) -> Result<Value, String> {
         match statement {
             Statement::Expression(expr) => self.eval_expression(expr),
-            Statement::LetBinding { mutable, name, type_annotation: _, value, ownership_modifier: _, lifetime_annotation: _ } => {
-                let value = self.eval_expression(value)?;
-                
-                // In a real implementation, we'd check mutability and type annotations
-                self.env.set(name.clone(), value);
+            Statement::LetBinding { mutable, name, type_annotation, value, ownership_modifier: _, lifetime_annotation: _ } => {
+                let mut value = self.eval_moving(value)?;
+                if let Some(ty) = type_annotation {
+                    value = self.coerce_to_annotation(value, ty)?;
+                }
+
+                if *mutable {
+                    self.env.set_mutable(name.clone(), value);
+                } else {
+                    self.env.set(name.clone(), value);
+                }
                 Ok(Value::Unit)
             },
             Statement::ConstBinding { name, type_annotation: _, value } => {
@@ -196,73 +1127,445 @@ impl Runtime {
                     None => Ok(Value::Unit),
                 }
             },
-            Statement::Break => Err("Break outside loop".to_string()),
-            Statement::Continue => Err("Continue outside loop".to_string()),
-            Statement::Block(statements) => {
-                // Create a new environment scope for the block
-                let mut block_runtime = Runtime::new();
-                block_runtime.env = Environment::new(Some(self.env.clone()));
-
-                let mut result = Value::Unit;
-                for stmt in statements {
-                    result = block_runtime.eval_statement(stmt)?;
-                }
-
-                Ok(result)
+            Statement::While { label, condition, body } => self.eval_while(label.as_ref(), condition, body),
+            Statement::Defer(_) => Err("defer can only be used inside a block or function body".to_string()),
+            Statement::Assert { actual, expected, message, location } => {
+                self.eval_assert(actual, expected.as_ref(), message.as_ref(), *location)
             },
+            Statement::Break(label) => Err(begin_exit(NonLocalExit::Break(label.clone()))),
+            Statement::Continue(label) => Err(begin_exit(NonLocalExit::Continue(label.clone()))),
+            Statement::Block(statements) => self.eval_block(statements),
             Statement::Trait(_) => {
                 // Traits are compile-time constructs, so at runtime we just acknowledge them
                 Ok(Value::Unit)
             },
-            Statement::Implementation(_) => {
-                // Implementations are compile-time constructs, so at runtime we just acknowledge them
+            Statement::Class(_) | Statement::Enum(_) => {
+                // Type declarations only describe values built elsewhere (e.g. struct literals)
+                Ok(Value::Unit)
+            },
+            Statement::Implementation(impl_def) => {
+                // Record the methods so values of this type can be used as `dyn Trait`
+                let vtable: Vtable = impl_def.methods.iter()
+                    .map(|method| {
+                        let func = Value::Function(
+                            method.name.clone(),
+                            method.parameters.clone(),
+                            method.body.clone(),
+                            self.env.clone(),
+                        );
+                        (method.name.clone(), func)
+                    })
+                    .collect();
+                lock_cell(&self.trait_impls).insert(
+                    (impl_def.for_type.clone(), impl_def.trait_name.clone()),
+                    Arc::new(vtable),
+                );
                 Ok(Value::Unit)
             },
             Statement::MacroDefinition(_) => {
                 // Macro definitions are compile-time constructs, so at runtime we just acknowledge them
                 Ok(Value::Unit)
             },
+            Statement::Extern(extern_def) => {
+                self.env.set(extern_def.name.clone(), Value::ForeignFunction(Arc::new(extern_def.clone())));
+                Ok(Value::Unit)
+            },
             // Handle other statement types as needed
             _ => Err("Unsupported statement type".to_string()),
         }
     }
 
-    /// Evaluates an expression
-    fn eval_expression(&mut self, expr: &Expression) -> Result<Value, String> {
-        match expr {
-            Expression::Integer(val) => Ok(Value::Integer(*val)),
-            Expression::Float(val) => Ok(Value::Float(*val)),
-            Expression::String(val) => Ok(Value::String(val.clone())),
-            Expression::Boolean(val) => Ok(Value::Boolean(*val)),
-            Expression::Nil => Ok(Value::Unit),
-            Expression::Identifier(name) => {
-                match self.env.get(name) {
-                    Some(value) => Ok(value),
-                    None => Err(format!("Undefined variable: {}", name)),
+    /// Evaluates a block of statements in its own scope
+    ///
+    /// The scope is popped on every exit path, including errors, so bindings made
+    /// inside the block never leak out and shadowed outer bindings are restored.
+    fn eval_block(&mut self, statements: &[Statement]) -> Result<Value, String> {
+        self.env.push_scope();
+        let result = self.eval_statements(statements);
+        self.env.pop_scope();
+        result
+    }
+
+    /// Evaluates statements in order, then runs the statements they deferred
+    ///
+    /// Evaluation stops at the first error. Deferred statements run last-deferred-first
+    /// on every exit path: normal completion, an error, or a `?`, break or continue
+    /// unwinding through. They run after the value of the sequence (including a
+    /// `return` value) has been evaluated, so they can't change it. If the sequence
+    /// failed, its error is reported rather than any error from a deferred statement.
+    fn eval_statements(&mut self, statements: &[Statement]) -> Result<Value, String> {
+        let mut deferred = Vec::new();
+        let mut result = Ok(Value::Unit);
+
+        for stmt in statements {
+            result = match stmt {
+                Statement::Defer(deferred_stmt) => {
+                    deferred.push(deferred_stmt.as_ref());
+                    Ok(Value::Unit)
+                },
+                stmt => self.eval_statement(stmt),
+            };
+            if result.is_err() {
+                break;
+            }
+        }
+
+        if deferred.is_empty() {
+            return result;
+        }
+
+        // Set aside an exit that is unwinding, so deferred statements can't disturb it
+        let pending_exit = result.as_ref().err().and_then(|e| take_exit(e));
+        let mut deferred_error = None;
+        for stmt in deferred.into_iter().rev() {
+            if let Err(e) = self.eval_statement(stmt) {
+                // A deferred break, continue or ? has nowhere to go
+                let e = take_exit(&e).map_or(e, |exit| exit.escaped_message());
+                deferred_error.get_or_insert(e);
+            }
+        }
+
+        match (pending_exit, result, deferred_error) {
+            (Some(exit), _, _) => Err(begin_exit(exit)),
+            (None, Ok(_), Some(e)) => Err(e),
+            (None, result, _) => result,
+        }
+    }
+
+    /// Evaluates an assert or assert_eq, failing with an error that says where and why
+    ///
+    /// The failure is an ordinary runtime error, so `logos test` reports it as a failed
+    /// test and other code can handle it like any other error.
+    fn eval_assert(
+        &mut self,
+        actual: &Expression,
+        expected: Option<&Expression>,
+        message: Option<&Expression>,
+        location: SourceLocation,
+    ) -> Result<Value, String> {
+        let actual_value = self.eval_expression(actual)?;
+        let comparison = match expected {
+            Some(expected) => {
+                let expected_value = self.eval_expression(expected)?;
+                if actual_value == expected_value {
+                    return Ok(Value::Unit);
                 }
+                Some(format!("expected {}, actual {}", expected_value, actual_value))
             },
-            Expression::BinaryOp(left, op, right) => {
-                let left_val = self.eval_expression(left)?;
-                let right_val = self.eval_expression(right)?;
-                
-                match op {
-                    BinaryOp::Add => binary_op_add(left_val, right_val),
-                    BinaryOp::Sub => binary_op_sub(left_val, right_val),
-                    BinaryOp::Mul => binary_op_mul(left_val, right_val),
-                    BinaryOp::Div => binary_op_div(left_val, right_val),
-                    BinaryOp::Mod => binary_op_mod(left_val, right_val),
-                    BinaryOp::Eq => Ok(Value::Boolean(left_val == right_val)),
-                    BinaryOp::Ne => Ok(Value::Boolean(left_val != right_val)),
-                    BinaryOp::Lt => binary_op_lt(left_val, right_val),
-                    BinaryOp::Gt => binary_op_gt(left_val, right_val),
-                    BinaryOp::Le => binary_op_le(left_val, right_val),
-                    BinaryOp::Ge => binary_op_ge(left_val, right_val),
-                    BinaryOp::And => binary_op_and(left_val, right_val),
-                    BinaryOp::Or => binary_op_or(left_val, right_val),
-                    BinaryOp::PipeForward => {
-                        // Implement pipe forward operator (value |> function)
-                        // This passes the left value as the first argument to the right function
-                        match (&left_val, &right_val) {
+            None => match actual_value {
+                Value::Boolean(true) => return Ok(Value::Unit),
+                Value::Boolean(false) => None,
+                other => return Err(format!(
+                    "assert() expects a Bool condition, found {} at {}",
+                    runtime_type_name(&other), location
+                )),
+            },
+        };
+
+        let mut error = format!("Assertion failed at {}", location);
+        if let Some(message) = message {
+            // A string message is reported as written, without quotes
+            let message = match self.eval_expression(message)? {
+                Value::String(text) => text,
+                other => other.to_string(),
+            };
+            error.push_str(": ");
+            error.push_str(&message);
+        }
+        if let Some(comparison) = comparison {
+            error.push_str(": ");
+            error.push_str(&comparison);
+        }
+        Err(error)
+    }
+
+    /// Runs a while loop, stopping at the breaks and continues that target it
+    ///
+    /// Each iteration gets a fresh scope. An unlabeled break or continue targets the
+    /// innermost loop; a labeled one unwinds through inner loops until it reaches the
+    /// loop with that label.
+    fn eval_while(&mut self, label: Option<&Label>, condition: &Expression, body: &[Statement]) -> Result<Value, String> {
+        let targets_this_loop = |target: &Option<Label>| target.is_none() || target.as_ref() == label;
+
+        while is_truthy(&self.eval_expression(condition)?) {
+            if let Err(e) = self.eval_block(body) {
+                match take_exit(&e) {
+                    Some(NonLocalExit::Break(target)) if targets_this_loop(&target) => break,
+                    Some(NonLocalExit::Continue(target)) if targets_this_loop(&target) => continue,
+                    // Not ours: keep unwinding to an outer loop or the function body
+                    Some(exit) => return Err(begin_exit(exit)),
+                    None => return Err(e),
+                }
+            }
+        }
+
+        Ok(Value::Unit)
+    }
+
+    /// Converts a value to the type it is annotated with, where that needs runtime work
+    ///
+    /// Currently this wraps values annotated `dyn Trait` (or `[dyn Trait]`) into trait
+    /// objects carrying the method table of their type's implementation.
+    fn coerce_to_annotation(&self, value: Value, ty: &Type) -> Result<Value, String> {
+        match (ty, value) {
+            (Type::Dyn(_), value @ Value::TraitObject(_, _, _)) => Ok(value),
+            (Type::Dyn(trait_name), value) => {
+                let type_name = runtime_type_name(&value);
+                let vtable = lock_cell(&self.trait_impls)
+                    .get(&(type_name.clone(), trait_name.clone()))
+                    .cloned()
+                    .ok_or_else(|| format!("Type '{}' does not implement trait '{}'", type_name, trait_name))?;
+                Ok(Value::TraitObject(trait_name.clone(), Box::new(value), vtable))
+            },
+            (Type::Array(inner), Value::Array(items)) => {
+                let items: Result<Vec<Value>, String> = items.into_iter()
+                    .map(|item| self.coerce_to_annotation(item, inner))
+                    .collect();
+                Ok(Value::Array(items?))
+            },
+            (_, value) => Ok(value),
+        }
+    }
+
+    /// Calls a function value with already-evaluated arguments
+    pub fn call_function(&mut self, func: Value, args: Vec<Value>) -> Result<Value, String> {
+        match func {
+            Value::BuiltinFunction(func) => call_builtin(func, &args),
+            Value::ForeignFunction(def) => call_foreign(&def, &args),
+            Value::Function(name, params, body, closure_env) => {
+                if params.len() != args.len() {
+                    return Err(format!("Argument count mismatch for function {}", name));
+                }
+                if CALL_STACK.with(|stack| stack.borrow().len()) >= self.max_call_depth {
+                    return Err(format!(
+                        "Recursion depth exceeded: more than {} nested calls reaching {}; a larger --stack-size allows deeper recursion",
+                        self.max_call_depth, name
+                    ));
+                }
+
+                // The closure was captured before the function was bound, so the function
+                // is bound again in its own call to let it recurse
+                let this = Value::Function(name.clone(), params.clone(), body.clone(), closure_env.clone());
+
+                // Bind parameters to arguments in a new environment for the call
+                let mut func_env = Environment::new(Some(closure_env));
+                func_env.set(name.clone(), this);
+                for (param, arg_val) in params.iter().zip(args) {
+                    func_env.set(param.name.clone(), arg_val);
+                }
+
+                let mut func_runtime = self.child_runtime(func_env);
+
+                if let Some(profiler) = &self.profiler {
+                    lock_cell(profiler).enter(&name);
+                }
+                CALL_STACK.with(|stack| stack.borrow_mut().push(name));
+                let result = func_runtime.eval_body(&body);
+                CALL_STACK.with(|stack| stack.borrow_mut().pop());
+                if let Some(profiler) = &self.profiler {
+                    lock_cell(profiler).exit();
+                }
+
+                result
+            },
+            other => Err(format!("Not a function: {}", other)),
+        }
+    }
+
+    /// Evaluates a function body, stopping at the first return statement
+    ///
+    /// A `?` that propagates a `None` or `Err` anywhere in the body ends it early with
+    /// that value.
+    pub fn eval_body(&mut self, body: &[Statement]) -> Result<Value, String> {
+        // Statements after the first top-level return are never reached
+        let end = body.iter()
+            .position(|stmt| matches!(stmt, Statement::Return(_)))
+            .map_or(body.len(), |i| i + 1);
+
+        self.eval_statements(&body[..end]).or_else(|e| match take_exit(&e) {
+            Some(NonLocalExit::Return(value)) => Ok(value),
+            Some(exit) => Err(exit.escaped_message()),
+            None => Err(e),
+        })
+    }
+
+    /// Calls a method, dispatching through the vtable when the receiver is a trait object
+    pub fn call_method(&mut self, receiver: Value, method: &str, args: Vec<Value>) -> Result<Value, String> {
+        match receiver {
+            Value::TraitObject(trait_name, inner, vtable) => {
+                let func = vtable.get(method)
+                    .cloned()
+                    .ok_or_else(|| format!("Trait '{}' has no method '{}'", trait_name, method))?;
+
+                // The concrete value is passed as the method's first (self) parameter
+                let mut call_args = Vec::with_capacity(args.len() + 1);
+                call_args.push(*inner);
+                call_args.extend(args);
+                self.call_function(func, call_args)
+            },
+            receiver => {
+                // Every Map/Set method that takes an argument takes the key first
+                if let (Value::Map(_) | Value::Set(_), Some(key)) = (&receiver, args.first()) {
+                    self.check_key_traits(key)?;
+                }
+                if let Some(func) = self.find_impl_method(&runtime_type_name(&receiver), method)? {
+                    let mut call_args = Vec::with_capacity(args.len() + 1);
+                    call_args.push(receiver);
+                    call_args.extend(args);
+                    return self.call_function(func, call_args);
+                }
+                call_builtin_method(receiver, method, &args)
+            },
+        }
+    }
+
+    /// Finds a method among the trait implementations for a type
+    ///
+    /// Returns an error if more than one implemented trait provides the method, since
+    /// the call would be ambiguous.
+    fn find_impl_method(&self, type_name: &str, method: &str) -> Result<Option<Value>, String> {
+        let impls = lock_cell(&self.trait_impls);
+        let mut providers: Vec<(&String, &Value)> = impls.iter()
+            .filter(|((impl_type, _), _)| impl_type == type_name)
+            .filter_map(|((_, trait_name), vtable)| vtable.get(method).map(|func| (trait_name, func)))
+            .collect();
+        // The table is a HashMap, so sort the traits to name them in a stable order
+        providers.sort_by(|a, b| a.0.cmp(b.0));
+
+        match providers.as_slice() {
+            [] => Ok(None),
+            [(_, func)] => Ok(Some((*func).clone())),
+            [(first, _), (second, _)] => Err(format!(
+                "Method '{}' on '{}' is ambiguous: provided by both '{}' and '{}'",
+                method, type_name, first, second
+            )),
+            many => {
+                let names: Vec<String> = many.iter().map(|(trait_name, _)| format!("'{}'", trait_name)).collect();
+                Err(format!(
+                    "Method '{}' on '{}' is ambiguous: provided by {}",
+                    method, type_name, names.join(", ")
+                ))
+            },
+        }
+    }
+
+    /// Checks that struct values used as map keys implement Eq and Hash
+    ///
+    /// Whether the value itself is hashable (no floats, functions...) is checked by
+    /// `HashKey::new`; this adds the trait requirement, which only the runtime's
+    /// impl table can answer.
+    fn check_key_traits(&self, key: &Value) -> Result<(), String> {
+        match key {
+            Value::Struct(name, fields) => {
+                let impls = lock_cell(&self.trait_impls);
+                let missing: Vec<&str> = ["Eq", "Hash"].into_iter()
+                    .filter(|t| !impls.contains_key(&(name.clone(), t.to_string())))
+                    .collect();
+                drop(impls);
+
+                if !missing.is_empty() {
+                    return Err(format!(
+                        "Type '{}' cannot be used as a map key: it does not implement {} (add #[derive(Eq, Hash)])",
+                        name,
+                        missing.join(" or ")
+                    ));
+                }
+                fields.values().try_for_each(|field| self.check_key_traits(field))
+            },
+            Value::Array(items) | Value::Tuple(items) => items.iter().try_for_each(|item| self.check_key_traits(item)),
+            Value::Box(inner) => self.check_key_traits(inner),
+            Value::Rc(inner) => self.check_key_traits(inner),
+            _ => Ok(()),
+        }
+    }
+
+    /// Compares two values with `==`
+    ///
+    /// Structs compare through their `Eq` implementation (usually `#[derive(Eq)]`);
+    /// comparing structs of a type without one is an error rather than silently false.
+    /// Other values must have the same type, as `check_equatable` describes.
+    fn values_equal(&mut self, left: Value, right: Value) -> Result<bool, String> {
+        let type_name = match (&left, &right) {
+            (Value::Struct(left_name, _), Value::Struct(right_name, _)) if left_name == right_name => left_name.clone(),
+            (Value::Struct(_, _), Value::Struct(_, _)) => return Ok(false),
+            _ => {
+                check_equatable(&left, &right)?;
+                return Ok(left == right);
+            },
+        };
+
+        let eq = lock_cell(&self.trait_impls)
+            .get(&(type_name.clone(), "Eq".to_string()))
+            .and_then(|vtable| vtable.get("eq").cloned())
+            .ok_or_else(|| format!("Type '{}' does not implement Eq; add #[derive(Eq)] to compare it with ==", type_name))?;
+
+        match self.call_function(eq, vec![left, right])? {
+            Value::Boolean(result) => Ok(result),
+            other => Err(format!("{}::eq returned {} instead of a Bool", type_name, other)),
+        }
+    }
+
+    /// Evaluates an expression whose result is bound to a new owner
+    ///
+    /// Binding a variable that holds a `Box` moves it: the source variable can't be
+    /// read again until it is reassigned. Other values are copied as before.
+    fn eval_moving(&mut self, expr: &Expression) -> Result<Value, String> {
+        let value = self.eval_expression(expr)?;
+        if let (Expression::Identifier(name), Value::Box(_)) = (expr, &value) {
+            self.env.mark_moved(name);
+        }
+        Ok(value)
+    }
+
+    /// Evaluates an expression in the current environment, returning its value
+    pub fn eval_expression(&mut self, expr: &Expression) -> Result<Value, String> {
+        if let Some(profiler) = &self.profiler {
+            lock_cell(profiler).tick();
+        }
+
+        match expr {
+            Expression::Integer(val) => Ok(Value::Integer(*val)),
+            Expression::Float(val) => Ok(Value::Float(*val)),
+            Expression::String(val) => Ok(Value::String(val.clone())),
+            Expression::Bytes(bytes) => Ok(Value::Bytes(bytes.clone())),
+            Expression::Boolean(val) => Ok(Value::Boolean(*val)),
+            Expression::Nil => Ok(Value::Unit),
+            Expression::Identifier(name) => {
+                match self.env.get(name) {
+                    Some(value) => Ok(value),
+                    None if self.env.is_moved(name) => Err(format!("Use of moved value: {}", name)),
+                    None => Err(format!("Undefined variable: {}", name)),
+                }
+            },
+            Expression::BinaryOp(left, op, right, parsed_at) => {
+                let left_val = self.eval_expression(left)?;
+
+                // `&&` and `||` only evaluate their right operand when the left doesn't decide
+                match op {
+                    BinaryOp::And if !is_truthy(&left_val) => return Ok(Value::Boolean(false)),
+                    BinaryOp::Or if is_truthy(&left_val) => return Ok(Value::Boolean(true)),
+                    _ => {},
+                }
+                let right_val = self.eval_expression(right)?;
+                
+                match op {
+                    BinaryOp::Add => binary_op_add(left_val, right_val),
+                    BinaryOp::Sub => binary_op_sub(left_val, right_val),
+                    BinaryOp::Mul => binary_op_mul(left_val, right_val),
+                    BinaryOp::Div => binary_op_div(left_val, right_val).map_err(|e| located(e, parsed_at)),
+                    BinaryOp::Mod => binary_op_mod(left_val, right_val).map_err(|e| located(e, parsed_at)),
+                    BinaryOp::Eq => Ok(Value::Boolean(self.values_equal(left_val, right_val)?)),
+                    BinaryOp::Ne => Ok(Value::Boolean(!self.values_equal(left_val, right_val)?)),
+                    BinaryOp::Lt => binary_op_lt(left_val, right_val),
+                    BinaryOp::Gt => binary_op_gt(left_val, right_val),
+                    BinaryOp::Le => binary_op_le(left_val, right_val),
+                    BinaryOp::Ge => binary_op_ge(left_val, right_val),
+                    BinaryOp::And => binary_op_and(left_val, right_val),
+                    BinaryOp::Or => binary_op_or(left_val, right_val),
+                    BinaryOp::PipeForward => {
+                        // Implement pipe forward operator (value |> function)
+                        // This passes the left value as the first argument to the right function
+                        match (&left_val, &right_val) {
                             (value, Value::Function(func_name, params, body, closure_env)) => {
                                 // Create a new environment for the function call
                                 let mut func_env = Environment::new(Some(closure_env.clone()));
@@ -273,18 +1576,13 @@ impl Runtime {
                                 }
                                 
                                 // Evaluate the function body in the new environment
-                                let mut func_runtime = Runtime::new();
-                                func_runtime.env = func_env;
+                                let mut func_runtime = self.child_runtime(func_env);
                                 
-                                let mut result = Value::Unit;
-                                for stmt in body {
-                                    result = func_runtime.eval_statement(stmt)?;
-                                }
-                                Ok(result)
+                                func_runtime.eval_body(body)
                             },
                             (value, Value::BuiltinFunction(func)) => {
                                 // Apply the builtin function to the value
-                                func(&[value.clone()])
+                                call_builtin(*func, &[value.clone()])
                             },
                             (_, _) => Err("Pipe forward expects a function on the right side".to_string()),
                         }
@@ -303,18 +1601,13 @@ impl Runtime {
                                 }
                                 
                                 // Evaluate the function body in the new environment
-                                let mut func_runtime = Runtime::new();
-                                func_runtime.env = func_env;
+                                let mut func_runtime = self.child_runtime(func_env);
                                 
-                                let mut result = Value::Unit;
-                                for stmt in body {
-                                    result = func_runtime.eval_statement(stmt)?;
-                                }
-                                Ok(result)
+                                func_runtime.eval_body(body)
                             },
                             (Value::BuiltinFunction(func), value) => {
                                 // Apply the builtin function to the value
-                                func(&[value.clone()])
+                                call_builtin(*func, &[value.clone()])
                             },
                             (_, _) => Err("Pipe backward expects a function on the left side".to_string()),
                         }
@@ -353,9 +1646,19 @@ impl Runtime {
                 match op {
                     UnaryOp::Neg => unary_op_neg(val),
                     UnaryOp::Not => unary_op_not(val),
+                    UnaryOp::Deref => unary_op_deref(val),
                     _ => Err("Unsupported unary operation".to_string()),
                 }
             },
+            // The parser reads `x = value` as a call to `assign` with the target's name
+            Expression::Call(name, args) if name == "assign" && !self.env.contains("assign") => match args.as_slice() {
+                [Expression::String(target), value] => {
+                    let value = self.eval_moving(value)?;
+                    self.env.assign(target, value)?;
+                    Ok(Value::Unit)
+                },
+                _ => Err("Invalid assignment".to_string()),
+            },
             Expression::Call(name, args) => {
                 let func_val = self.env.get(name).ok_or_else(|| format!("Function not found: {}", name))?;
                 
@@ -364,36 +1667,22 @@ impl Runtime {
                         let evaluated_args: Result<Vec<Value>, String> = 
                             args.iter().map(|arg| self.eval_expression(arg)).collect();
                         let args = evaluated_args?;
-                        func(&args)
+                        call_builtin(func, &args)
+                    },
+                    Value::ForeignFunction(def) => {
+                        let args = args.iter().map(|arg| self.eval_expression(arg)).collect::<Result<Vec<_>, _>>()?;
+                        call_foreign(&def, &args)
                     },
-                    Value::Function(_, params, body, closure_env) => {
+                    Value::Function(_, ref params, _, _) => {
                         if params.len() != args.len() {
                             return Err(format!("Argument count mismatch for function {}", name));
                         }
-                        
-                        // Create new environment for function execution
-                        let mut func_env = Environment::new(Some(closure_env));
-                        
-                        // Bind parameters to arguments
-                        for (param, arg) in params.iter().zip(args.iter()) {
-                            let arg_val = self.eval_expression(arg)?;
-                            func_env.set(param.name.clone(), arg_val);
-                        }
-                        
-                        // Create a new environment for function execution
-                        let mut func_runtime = Runtime::new();
-                        func_runtime.env = func_env;
-
-                        let mut result = Value::Unit;
-                        for stmt in body {
-                            result = func_runtime.eval_statement(&stmt)?;
-                            // If we encounter a return, we should return that value
-                            if matches!(stmt, Statement::Return(_)) {
-                                break;
-                            }
-                        }
 
-                        Ok(result)
+                        let mut arg_values = Vec::with_capacity(args.len());
+                        for arg in args {
+                            arg_values.push(self.eval_moving(arg)?);
+                        }
+                        self.call_function(func_val, arg_values)
                     },
                     _ => Err(format!("Not a function: {}", name)),
                 }
@@ -401,38 +1690,34 @@ impl Runtime {
             Expression::If(condition, then_stmts, else_stmts) => {
                 let cond_val = self.eval_expression(condition)?;
 
+                // Branches get their own scope, but assignments reach bindings outside it
                 if is_truthy(&cond_val) {
-                    // Evaluate then branch with a new runtime to avoid borrowing issues
-                    let mut then_runtime = Runtime::new();
-                    then_runtime.env = Environment::new(Some(self.env.clone()));
-
-                    let mut result = Value::Unit;
-                    for stmt in then_stmts {
-                        result = then_runtime.eval_statement(stmt)?;
-                    }
-
-                    Ok(result)
+                    self.eval_block(then_stmts)
                 } else {
-                    // Evaluate else branch with a new runtime to avoid borrowing issues
-                    let mut else_runtime = Runtime::new();
-                    else_runtime.env = Environment::new(Some(self.env.clone()));
-
-                    let mut result = Value::Unit;
-                    for stmt in else_stmts {
-                        result = else_runtime.eval_statement(stmt)?;
-                    }
-
-                    Ok(result)
+                    self.eval_block(else_stmts)
                 }
             },
             Expression::Char(c) => Ok(Value::Char(*c)),
             Expression::Array(items) => {
+                crate::std_lib::collections::check_allocation(items.len() as u128, self.max_allocation)?;
                 let mut values = Vec::new();
                 for item in items {
                     values.push(self.eval_expression(item)?);
                 }
                 Ok(Value::Array(values))
             },
+            Expression::ArrayRepeat(value, length) => {
+                let value = self.eval_expression(value)?;
+                let length = match self.eval_expression(length)? {
+                    Value::Integer(n) if n >= 0 => n as u128,
+                    Value::Integer(n) => return Err(format!("Array length cannot be negative: {}", n)),
+                    other => return Err(format!("Array length must be an integer, found {}", other)),
+                };
+
+                // Every copy of the value is allocated, including arrays nested inside it
+                crate::std_lib::collections::check_allocation(length * element_count(&value), self.max_allocation)?;
+                Ok(Value::Array(vec![value; length as usize]))
+            },
             Expression::Tuple(items) => {
                 let mut values = Vec::new();
                 for item in items {
@@ -454,10 +1739,7 @@ impl Runtime {
             Expression::AsyncBlock(statements) => {
                 // Create a future that represents the async block
                 // In a real implementation, this would create a proper future and schedule it
-                let mut result = Value::Unit;
-                for stmt in statements {
-                    result = self.eval_statement(stmt)?;
-                }
+                let result = self.eval_statements(statements)?;
                 // Wrap the result in a Future value
                 Ok(Value::Future(Box::new(result)))
             },
@@ -506,8 +1788,7 @@ impl Runtime {
                 for (pattern, guard, body) in arms {
                     // Create a new environment for this match arm
                     let mut arm_env = Environment::new(Some(self.env.clone()));
-                    let mut runtime_for_arm = Runtime::new();
-                    runtime_for_arm.env = arm_env;
+                    let mut runtime_for_arm = self.child_runtime(arm_env);
 
                     // Check if the pattern matches the value
                     if self.pattern_matches(&match_value, pattern, &mut runtime_for_arm)? {
@@ -516,19 +1797,11 @@ impl Runtime {
                             let guard_result = runtime_for_arm.eval_expression(guard_expr)?;
                             if is_truthy(&guard_result) {
                                 // Guard passed, execute the body
-                                let mut result = Value::Unit;
-                                for stmt in body {
-                                    result = runtime_for_arm.eval_statement(stmt)?;
-                                }
-                                return Ok(result);
+                                return runtime_for_arm.eval_statements(body);
                             }
                         } else {
                             // No guard, execute the body
-                            let mut result = Value::Unit;
-                            for stmt in body {
-                                result = runtime_for_arm.eval_statement(stmt)?;
-                            }
-                            return Ok(result);
+                            return runtime_for_arm.eval_statements(body);
                         }
                     }
                 }
@@ -588,24 +1861,174 @@ impl Runtime {
                 // For now, we'll simulate it
                 Ok(Value::String(format!("Indexed {} using {} indexer", resource, indexer)))
             },
-            Expression::Block(statements) => {
-                // Create a new environment for the block
-                let mut block_env = Environment::new(Some(self.env.clone()));
-                let mut block_runtime = Runtime::new();
-                block_runtime.env = block_env;
-
-                let mut result = Value::Unit;
-                for stmt in statements {
-                    result = block_runtime.eval_statement(stmt)?;
+            Expression::Block(statements) => self.eval_block(statements),
+            Expression::TryCatch(body, name, handler) => {
+                match self.eval_block(body) {
+                    // `?`, break and continue are passing through, not failing
+                    Err(e) if e != NON_LOCAL_EXIT && !is_fatal(&e) => {
+                        self.env.push_scope();
+                        self.env.set(name.clone(), error_value(&e));
+                        let result = self.eval_statements(handler);
+                        self.env.pop_scope();
+                        result
+                    },
+                    result => result,
                 }
-
-                Ok(result)
             },
+            Expression::Index(target, index) => {
+                let target = self.eval_expression(target)?;
+                let index = self.eval_expression(index)?;
+                index_value(&target, &index)
+            },
+            Expression::Slice(target, start, end) => {
+                let target = self.eval_expression(target)?;
+                let start = self.eval_expression(start)?;
+                let end = self.eval_expression(end)?;
+                slice_value(&target, &start, &end)
+            },
+            Expression::InterpolatedString(parts) => {
+                let mut text = String::new();
+                for part in parts {
+                    match part {
+                        StringPart::Literal(literal) => text.push_str(literal),
+                        StringPart::Interpolated(expr, spec) => {
+                            let value = self.eval_expression(expr)?;
+                            match (spec, value) {
+                                (Some(spec), value) => text.push_str(&spec.apply(&value)?),
+                                // Interpolated strings are spliced in without their quotes
+                                (None, Value::String(s)) => text.push_str(&s),
+                                (None, value) => text.push_str(&value.to_string()),
+                            }
+                        },
+                    }
+                }
+                Ok(Value::String(text))
+            },
+            Expression::Propagate(inner) => {
+                match self.eval_expression(inner)? {
+                    Value::Option(Some(value)) | Value::Result(Ok(value)) => Ok(*value),
+                    early @ (Value::Option(None) | Value::Result(Err(_))) => {
+                        Err(begin_exit(NonLocalExit::Return(early)))
+                    },
+                    other => Err(format!("The ? operator expects an Option or Result, found {}", runtime_type_name(&other))),
+                }
+            },
+            Expression::FieldAccess(receiver, field) => {
+                match self.eval_expression(receiver)? {
+                    Value::Struct(name, fields) => fields.get(field)
+                        .cloned()
+                        .ok_or_else(|| format!("Struct '{}' has no field '{}'", name, field)),
+                    other => Err(format!("Cannot access field '{}' on {}", field, other)),
+                }
+            },
+            Expression::MethodCall(receiver, method, args) => {
+                let receiver = self.eval_expression(receiver)?;
+                let mut arg_values = Vec::with_capacity(args.len());
+                for arg in args {
+                    arg_values.push(self.eval_expression(arg)?);
+                }
+                self.call_method(receiver, method, arg_values)
+            },
+            Expression::ChannelCreate(element_type) => {
+                Ok(Value::Channel(Arc::new(ChannelSlot::new(element_type.as_ref().clone()))))
+            },
+            Expression::ChannelSend(channel, value) => {
+                let channel = expect_channel(self.eval_expression(channel)?)?;
+                channel.send(self.eval_expression(value)?)?;
+                Ok(Value::Unit)
+            },
+            Expression::ChannelReceive(channel) => expect_channel(self.eval_expression(channel)?)?.recv(),
+            Expression::ChannelClose(channel) => {
+                expect_channel(self.eval_expression(channel)?)?.close();
+                Ok(Value::Unit)
+            },
+            Expression::Select(arms) => self.eval_select(arms),
             // Handle other expression types as needed
             _ => Err("Unsupported expression type".to_string()),
         }
     }
 
+    /// Runs the first ready arm of a select, in the order the arms are written
+    ///
+    /// Channels and sent values are evaluated once, before anything is polled. A send
+    /// or close is always ready, and a receive is ready when its channel has a value
+    /// queued. With nothing ready the `default` arm runs straight away, and a
+    /// `timeout(ms)` arm keeps polling until its deadline passes. Without either, no
+    /// other code could ever make a channel ready, so the select is an error.
+    fn eval_select(&mut self, arms: &[SelectArm]) -> Result<Value, String> {
+        enum Ready {
+            Send(Arc<ChannelSlot>, Value),
+            Receive(Arc<ChannelSlot>),
+            Close(Arc<ChannelSlot>),
+            Fallback,
+        }
+
+        let mut operations = Vec::with_capacity(arms.len());
+        let mut deadline = None;
+        for arm in arms {
+            operations.push(match &arm.channel_operation {
+                ChannelOperation::Send { channel, value } => {
+                    let channel = expect_channel(self.eval_expression(channel)?)?;
+                    Ready::Send(channel, self.eval_expression(value)?)
+                },
+                ChannelOperation::Receive { channel } => Ready::Receive(expect_channel(self.eval_expression(channel)?)?),
+                ChannelOperation::Close { channel } => Ready::Close(expect_channel(self.eval_expression(channel)?)?),
+                ChannelOperation::Timeout { millis } => {
+                    let millis = match self.eval_expression(millis)? {
+                        Value::Integer(ms) if ms >= 0 => ms as u64,
+                        other => return Err(format!("Select timeout must be a non-negative Int, found {}", other)),
+                    };
+                    deadline = Some(std::time::Instant::now() + std::time::Duration::from_millis(millis));
+                    Ready::Fallback
+                },
+                ChannelOperation::Default => Ready::Fallback,
+            });
+        }
+
+        loop {
+            for (arm, operation) in arms.iter().zip(&operations) {
+                let received = match operation {
+                    Ready::Send(channel, value) => {
+                        channel.send(value.clone())?;
+                        None
+                    },
+                    Ready::Receive(channel) => match channel.try_recv() {
+                        Ok(Some(value)) => Some(value),
+                        // A closed or empty channel isn't ready; another arm may be
+                        Ok(None) | Err(_) => continue,
+                    },
+                    Ready::Close(channel) => {
+                        channel.close();
+                        None
+                    },
+                    Ready::Fallback => continue,
+                };
+                return self.eval_select_arm(arm, received);
+            }
+
+            let fallback = arms.iter().zip(&operations).find(|(_, operation)| matches!(operation, Ready::Fallback));
+            match (fallback, deadline) {
+                (Some((arm, _)), None) => return self.eval_select_arm(arm, None),
+                (Some((arm, _)), Some(deadline)) if std::time::Instant::now() >= deadline => {
+                    return self.eval_select_arm(arm, None);
+                },
+                (Some(_), Some(_)) => std::thread::sleep(std::time::Duration::from_millis(1)),
+                (None, _) => {
+                    return Err("select would block forever: no channel is ready and there is no default or timeout arm".to_string());
+                },
+            }
+        }
+    }
+
+    /// Runs a chosen select arm, binding the received value to the arm's name if it has one
+    fn eval_select_arm(&mut self, arm: &SelectArm, received: Option<Value>) -> Result<Value, String> {
+        let mut arm_env = Environment::new(Some(self.env.clone()));
+        if let (Some(Pattern::Identifier(name)), Some(value)) = (&arm.pattern, received) {
+            arm_env.set(name.clone(), value);
+        }
+        self.child_runtime(arm_env).eval_statements(&arm.body)
+    }
+
     /// Executes Python code from within Logos
     fn execute_python_code(&mut self, code: &str) -> Result<Value, String> {
         #[cfg(feature = "python")]
@@ -834,32 +2257,43 @@ fn binary_op_mul(left: Value, right: Value) -> Result<Value, String> {
     }
 }
 
+/// Appends where the failing expression is to a runtime error, when it was parsed
+fn located(error: String, parsed_at: &ParsedAt) -> String {
+    match parsed_at.0 {
+        Some(location) => format!("{} at {}", error, location),
+        None => error,
+    }
+}
+
 fn binary_op_div(left: Value, right: Value) -> Result<Value, String> {
     match (left, right) {
         (Value::Integer(a), Value::Integer(b)) => {
             if b == 0 {
-                Err("Division by zero".to_string())
+                Err(format!("Division by zero: {} / {}", a, b))
             } else {
-                Ok(Value::Integer(a / b))
+                // checked_div also catches i64::MIN / -1, which would otherwise panic
+                a.checked_div(b)
+                    .map(Value::Integer)
+                    .ok_or_else(|| format!("Integer overflow in division: {} / {}", a, b))
             }
         },
         (Value::Float(a), Value::Float(b)) => {
             if b == 0.0 {
-                Err("Division by zero".to_string())
+                Err(format!("Division by zero: {} / {}", a, b))
             } else {
                 Ok(Value::Float(a / b))
             }
         },
         (Value::Float(a), Value::Integer(b)) => {
             if b == 0 {
-                Err("Division by zero".to_string())
+                Err(format!("Division by zero: {} / {}", a, b))
             } else {
                 Ok(Value::Float(a / b as f64))
             }
         },
         (Value::Integer(a), Value::Float(b)) => {
             if b == 0.0 {
-                Err("Division by zero".to_string())
+                Err(format!("Division by zero: {} / {}", a, b))
             } else {
                 Ok(Value::Float(a as f64 / b))
             }
@@ -872,55 +2306,109 @@ fn binary_op_mod(left: Value, right: Value) -> Result<Value, String> {
     match (left, right) {
         (Value::Integer(a), Value::Integer(b)) => {
             if b == 0 {
-                Err("Modulo by zero".to_string())
+                Err(format!("Modulo by zero: {} % {}", a, b))
             } else {
-                Ok(Value::Integer(a % b))
+                a.checked_rem(b)
+                    .map(Value::Integer)
+                    .ok_or_else(|| format!("Integer overflow in modulo: {} % {}", a, b))
             }
         },
         (Value::Float(a), Value::Float(b)) => {
             if b == 0.0 {
-                Err("Modulo by zero".to_string())
+                Err(format!("Modulo by zero: {} % {}", a, b))
             } else {
                 Ok(Value::Float(a % b))
             }
         },
+        (Value::Float(a), Value::Integer(b)) => {
+            if b == 0 {
+                Err(format!("Modulo by zero: {} % {}", a, b))
+            } else {
+                Ok(Value::Float(a % b as f64))
+            }
+        },
+        (Value::Integer(a), Value::Float(b)) => {
+            if b == 0.0 {
+                Err(format!("Modulo by zero: {} % {}", a, b))
+            } else {
+                Ok(Value::Float(a as f64 % b))
+            }
+        },
         _ => Err("Cannot perform modulo on values of different types".to_string()),
     }
 }
 
 fn binary_op_lt(left: Value, right: Value) -> Result<Value, String> {
-    match (left, right) {
-        (Value::Integer(a), Value::Integer(b)) => Ok(Value::Boolean(a < b)),
-        (Value::Float(a), Value::Float(b)) => Ok(Value::Boolean(a < b)),
-        (Value::String(a), Value::String(b)) => Ok(Value::Boolean(a < b)),
-        _ => Err("Cannot compare values of different types".to_string()),
-    }
+    Ok(Value::Boolean(compare_values(&left, &right)? == Ordering::Less))
 }
 
 fn binary_op_gt(left: Value, right: Value) -> Result<Value, String> {
-    match (left, right) {
-        (Value::Integer(a), Value::Integer(b)) => Ok(Value::Boolean(a > b)),
-        (Value::Float(a), Value::Float(b)) => Ok(Value::Boolean(a > b)),
-        (Value::String(a), Value::String(b)) => Ok(Value::Boolean(a > b)),
-        _ => Err("Cannot compare values of different types".to_string()),
-    }
+    Ok(Value::Boolean(compare_values(&left, &right)? == Ordering::Greater))
 }
 
 fn binary_op_le(left: Value, right: Value) -> Result<Value, String> {
+    Ok(Value::Boolean(compare_values(&left, &right)? != Ordering::Greater))
+}
+
+fn binary_op_ge(left: Value, right: Value) -> Result<Value, String> {
+    Ok(Value::Boolean(compare_values(&left, &right)? != Ordering::Less))
+}
+
+/// Orders two values for `<`, `<=`, `>`, `>=` and `<>`
+///
+/// Only values of the same type are ordered: an Int and a Float need an explicit
+/// conversion first. Arrays and tuples compare element by element, with a prefix
+/// sorting before anything longer. Values with no natural order, such as structs or
+/// functions, are an error. So is NaN: rather than quietly sorting it somewhere, any
+/// ordering that reaches a NaN fails. The infinities order as usual.
+fn compare_values(left: &Value, right: &Value) -> Result<Ordering, String> {
     match (left, right) {
-        (Value::Integer(a), Value::Integer(b)) => Ok(Value::Boolean(a <= b)),
-        (Value::Float(a), Value::Float(b)) => Ok(Value::Boolean(a <= b)),
-        (Value::String(a), Value::String(b)) => Ok(Value::Boolean(a <= b)),
-        _ => Err("Cannot compare values of different types".to_string()),
+        (Value::Integer(a), Value::Integer(b)) => Ok(a.cmp(b)),
+        (Value::Float(a), Value::Float(b)) => a.partial_cmp(b)
+            .ok_or_else(|| format!("Cannot order NaN: {} and {} are unordered", format_float(*a), format_float(*b))),
+        (Value::String(a), Value::String(b)) => Ok(a.cmp(b)),
+        (Value::Char(a), Value::Char(b)) => Ok(a.cmp(b)),
+        (Value::Boolean(a), Value::Boolean(b)) => Ok(a.cmp(b)),
+        (Value::Bytes(a), Value::Bytes(b)) => Ok(a.cmp(b)),
+        (Value::Array(a), Value::Array(b)) | (Value::Tuple(a), Value::Tuple(b)) => {
+            for (x, y) in a.iter().zip(b) {
+                let ordering = compare_values(x, y)?;
+                if ordering != Ordering::Equal {
+                    return Ok(ordering);
+                }
+            }
+            Ok(a.len().cmp(&b.len()))
+        },
+        (Value::Box(a), Value::Box(b)) => compare_values(a, b),
+        (Value::Rc(a), Value::Rc(b)) => compare_values(a, b),
+        _ if runtime_type_name(left) == runtime_type_name(right) => {
+            Err(format!("Values of type {} can't be ordered", runtime_type_name(left)))
+        },
+        _ => Err(format!("Cannot compare {} with {}", runtime_type_name(left), runtime_type_name(right))),
     }
 }
 
-fn binary_op_ge(left: Value, right: Value) -> Result<Value, String> {
+/// Rejects `==` between values that can't be compared
+///
+/// Equality is structural, but only between values of the same type, all the way
+/// down: `[1, 2] == [1.0, 2.0]` is an error rather than false. Functions have no
+/// meaningful equality and are always an error.
+fn check_equatable(left: &Value, right: &Value) -> Result<(), String> {
+    let is_function = |value: &Value| matches!(value, Value::Function(..) | Value::BuiltinFunction(_) | Value::Closure(..));
     match (left, right) {
-        (Value::Integer(a), Value::Integer(b)) => Ok(Value::Boolean(a >= b)),
-        (Value::Float(a), Value::Float(b)) => Ok(Value::Boolean(a >= b)),
-        (Value::String(a), Value::String(b)) => Ok(Value::Boolean(a >= b)),
-        _ => Err("Cannot compare values of different types".to_string()),
+        _ if is_function(left) || is_function(right) => Err("Functions can't be compared".to_string()),
+        (Value::Array(a), Value::Array(b)) | (Value::Tuple(a), Value::Tuple(b)) => {
+            a.iter().zip(b).try_for_each(|(x, y)| check_equatable(x, y))
+        },
+        (Value::Option(Some(a)), Value::Option(Some(b)))
+        | (Value::Result(Ok(a)), Value::Result(Ok(b)))
+        | (Value::Result(Err(a)), Value::Result(Err(b)))
+        | (Value::Box(a), Value::Box(b)) => check_equatable(a, b),
+        (Value::Rc(a), Value::Rc(b)) => check_equatable(a, b),
+        _ if runtime_type_name(left) != runtime_type_name(right) => {
+            Err(format!("Cannot compare {} with {}", runtime_type_name(left), runtime_type_name(right)))
+        },
+        _ => Ok(()),
     }
 }
 
@@ -943,24 +2431,7 @@ fn binary_op_power(left: Value, right: Value) -> Result<Value, String> {
 }
 
 fn binary_op_spaceship(left: Value, right: Value) -> Result<Value, String> {
-    match (left, right) {
-        (Value::Integer(a), Value::Integer(b)) => {
-            if a < b { Ok(Value::Integer(-1)) }
-            else if a > b { Ok(Value::Integer(1)) }
-            else { Ok(Value::Integer(0)) }
-        },
-        (Value::Float(a), Value::Float(b)) => {
-            if a < b { Ok(Value::Integer(-1)) }
-            else if a > b { Ok(Value::Integer(1)) }
-            else { Ok(Value::Integer(0)) }
-        },
-        (Value::String(a), Value::String(b)) => {
-            if a < b { Ok(Value::Integer(-1)) }
-            else if a > b { Ok(Value::Integer(1)) }
-            else { Ok(Value::Integer(0)) }
-        },
-        _ => Err("Cannot compare values of different types for spaceship operator".to_string()),
-    }
+    Ok(Value::Integer(compare_values(&left, &right)? as i64))
 }
 
 // Helper functions for unary operations
@@ -972,6 +2443,15 @@ fn unary_op_neg(val: Value) -> Result<Value, String> {
     }
 }
 
+fn unary_op_deref(val: Value) -> Result<Value, String> {
+    match val {
+        Value::Box(inner) => Ok(*inner),
+        Value::Rc(inner) => Ok((*inner).clone()),
+        Value::Borrow(guard) => Ok(guard.cell.get()),
+        other => Err(format!("Cannot dereference {}", other)),
+    }
+}
+
 fn unary_op_not(val: Value) -> Result<Value, String> {
     Ok(Value::Boolean(!is_truthy(&val)))
 }
@@ -990,16 +2470,270 @@ fn is_truthy(val: &Value) -> bool {
     }
 }
 
+/// The text print() shows for a value: strings and characters as they are, anything
+/// else as it would be written in source
+fn printed_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Char(c) => c.to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Prints a value on its own line, as print() would
+///
+/// `logos run` uses this to show the value a script ends with.
+pub fn print_value(value: &Value) {
+    write_output(&format!("{}\n", printed_text(value)));
+}
+
 // Built-in function implementations
 fn runtime_print(args: &[Value]) -> Result<Value, String> {
-    for (i, arg) in args.iter().enumerate() {
-        if i > 0 {
-            print!(" ");
+    let line: Vec<String> = args.iter().map(printed_text).collect();
+    write_output(&format!("{}\n", line.join(" ")));
+    Ok(Value::Unit)
+}
+
+thread_local! {
+    /// Program output collected by `capture_output` instead of being printed
+    static CAPTURED_OUTPUT: std::cell::RefCell<Option<String>> = std::cell::RefCell::new(None);
+
+    /// Exit being unwound while a `NON_LOCAL_EXIT` error propagates
+    static PENDING_EXIT: std::cell::RefCell<Option<NonLocalExit>> = std::cell::RefCell::new(None);
+
+    /// Names of the Logos functions being called, outermost first, for panic reports
+    static CALL_STACK: std::cell::RefCell<Vec<String>> = std::cell::RefCell::new(Vec::new());
+
+    /// Whether a builtin is running, so the panic hook stays quiet about panics
+    /// `call_builtin` turns into Logos errors
+    static IN_BUILTIN: std::cell::Cell<bool> = std::cell::Cell::new(false);
+}
+
+/// Start of the error a Logos panic raises
+const PANIC_PREFIX: &str = "panic: ";
+
+/// Separates a panic's message from each function it unwound through
+const PANIC_FRAME: &str = "\n    in ";
+
+/// Builds the error for a panic raised now: the message, then the functions being
+/// called, innermost first
+fn panic_error(message: &str) -> String {
+    CALL_STACK.with(|stack| {
+        let mut error = format!("{}{}", PANIC_PREFIX, message);
+        for name in stack.borrow().iter().rev() {
+            error.push_str(PANIC_FRAME);
+            error.push_str(name);
         }
-        print!("{}", arg);
+        error
+    })
+}
+
+/// Splits a panic error back into its message and call stack
+fn parse_panic(error: &str) -> Option<(&str, Vec<&str>)> {
+    let rest = error.strip_prefix(PANIC_PREFIX)?;
+    let mut parts = rest.split(PANIC_FRAME);
+    let message = parts.next().unwrap_or_default();
+    Some((message, parts.collect()))
+}
+
+/// Calls a builtin, turning a Rust panic inside it into a Logos panic
+///
+/// A builtin that panics has failed, but the program that called it hasn't: the
+/// panic becomes a runtime error that `try`/`catch` can handle like any other.
+fn call_builtin(func: fn(&[Value]) -> Result<Value, String>, args: &[Value]) -> Result<Value, String> {
+    install_panic_hook();
+    let was_in_builtin = IN_BUILTIN.with(|flag| flag.replace(true));
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| func(args)));
+    IN_BUILTIN.with(|flag| flag.set(was_in_builtin));
+
+    result.unwrap_or_else(|payload| {
+        let message = payload.downcast_ref::<&str>().map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        Err(panic_error(&format!("builtin panicked: {}", message)))
+    })
+}
+
+/// Calls a function declared with `extern`, converting each argument to the type
+/// its parameter declares and the foreign result back to a Logos value
+fn call_foreign(def: &ExternDef, args: &[Value]) -> Result<Value, String> {
+    use crate::enhanced_ffi::{call_extern, ffi_type_of, FFICall, FFICallType, FFISafetyLevel, FFIType, FFIValue};
+
+    if args.len() != def.parameters.len() {
+        return Err(format!("Argument count mismatch for function {}", def.name));
     }
-    println!();
-    Ok(Value::Unit)
+    let mut parameters = Vec::with_capacity(args.len());
+    for (param, arg) in def.parameters.iter().zip(args) {
+        let value = match (&param.type_annotation, arg) {
+            (Type::Int, Value::Integer(i)) => FFIValue::Int(*i),
+            (Type::Float, Value::Float(f)) => FFIValue::Double(*f),
+            (Type::Float, Value::Integer(i)) => FFIValue::Double(*i as f64),
+            (Type::Bool, Value::Boolean(b)) => FFIValue::Bool(*b),
+            (Type::String, Value::String(s)) => FFIValue::String(s.clone()),
+            (expected, value) => {
+                return Err(format!(
+                    "Argument '{}' of {}: expected {}, found {}",
+                    param.name, def.name, expected, runtime_type_name(value)
+                ))
+            },
+        };
+        parameters.push(value);
+    }
+
+    let return_type = match &def.return_type {
+        Some(ty) => ffi_type_of(ty).ok_or_else(|| format!("extern fn {} can't return {}", def.name, ty))?,
+        None => FFIType::Void,
+    };
+    let language = match def.abi.as_str() {
+        "c" => FFICallType::C,
+        "rust" => FFICallType::Rust,
+        "go" => FFICallType::Go,
+        "python" => FFICallType::Python,
+        "java" => FFICallType::Java,
+        "wasm" => FFICallType::WASM,
+        _ => FFICallType::JavaScript,
+    };
+    let call = FFICall {
+        language,
+        function_name: def.name.clone(),
+        library_path: def.library.clone(),
+        parameters,
+        return_type,
+        safety_level: FFISafetyLevel::Unsafe,
+        timeout: None,
+    };
+
+    Ok(match call_extern(call)? {
+        FFIValue::Void => Value::Unit,
+        FFIValue::Int(i) => Value::Integer(i),
+        FFIValue::Float(f) => Value::Float(f as f64),
+        FFIValue::Double(f) => Value::Float(f),
+        FFIValue::Bool(b) => Value::Boolean(b),
+        FFIValue::Char(c) => Value::Char(c as char),
+        FFIValue::String(s) => Value::String(s),
+        other => return Err(format!("extern fn {} returned {:?}, which Logos has no value for", def.name, other)),
+    })
+}
+
+/// Keeps Rust's default panic report off stderr for panics inside builtins, which
+/// are reported as Logos errors instead
+fn install_panic_hook() {
+    static INSTALL: std::sync::Once = std::sync::Once::new();
+    INSTALL.call_once(|| {
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if !IN_BUILTIN.try_with(|flag| flag.get()).unwrap_or(false) {
+                default_hook(info);
+            }
+        }));
+    });
+}
+
+/// panic(message) - stops the program with a runtime error `try`/`catch` can recover from
+fn runtime_panic(args: &[Value]) -> Result<Value, String> {
+    let message = match expect_one_arg("panic", args)? {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    };
+    Err(panic_error(&message))
+}
+
+/// debug::assert(condition, message) - the host's assertion, which panics when it fails
+fn runtime_debug_assert(args: &[Value]) -> Result<Value, String> {
+    match args {
+        [Value::Boolean(condition), Value::String(message)] => {
+            crate::std_lib::debug::assert(*condition, message);
+            Ok(Value::Unit)
+        },
+        _ => Err("debug::assert() expects a Bool and a String".to_string()),
+    }
+}
+
+/// Returns whether an error ends the program even inside `try`
+///
+/// Exceeding a resource limit (the allocation limit, the recursion depth) is fatal:
+/// the program has already lost control of its resources and can't meaningfully
+/// recover. Every other runtime error, such as division by zero, a failed assertion or
+/// an index out of bounds, can be caught.
+fn is_fatal(error: &str) -> bool {
+    crate::explain::code_for_message(error) == Some("E0007") || error.starts_with("Recursion depth exceeded")
+}
+
+/// The value a `catch` binds a caught error to: `Error { message, code, stack }`
+///
+/// `code` is the diagnostic code `logos explain` knows the error by, if it has one.
+/// For a panic, `stack` names the functions it unwound through, innermost first;
+/// other errors have an empty stack.
+fn error_value(error: &str) -> Value {
+    let code = crate::explain::code_for_message(error)
+        .map(|code| Box::new(Value::String(code.to_string())));
+    let (message, stack) = parse_panic(error).unwrap_or((error, Vec::new()));
+    let stack = stack.into_iter().map(|name| Value::String(name.to_string())).collect();
+
+    let mut fields = HashMap::new();
+    fields.insert("message".to_string(), Value::String(message.to_string()));
+    fields.insert("code".to_string(), Value::Option(code));
+    fields.insert("stack".to_string(), Value::Array(stack));
+    Value::Struct("Error".to_string(), fields)
+}
+
+/// Control flow that leaves nested blocks, unwinding through them as a `NON_LOCAL_EXIT` error
+#[derive(Debug, Clone)]
+enum NonLocalExit {
+    Return(Value),           // `?` returning early from the enclosing function
+    Break(Option<Label>),    // break out of the innermost or the labeled loop
+    Continue(Option<Label>), // continue the innermost or the labeled loop
+}
+
+impl NonLocalExit {
+    /// The error reported when nothing handled the exit
+    fn escaped_message(&self) -> String {
+        match self {
+            NonLocalExit::Return(_) => "The ? operator can only be used inside a function".to_string(),
+            NonLocalExit::Break(None) => "Break outside loop".to_string(),
+            NonLocalExit::Continue(None) => "Continue outside loop".to_string(),
+            NonLocalExit::Break(Some(label)) | NonLocalExit::Continue(Some(label)) => {
+                format!("Undefined loop label '{}", label)
+            },
+        }
+    }
+}
+
+/// Error that unwinds a `NonLocalExit` to the loop or function body that handles it
+const NON_LOCAL_EXIT: &str = "<non-local exit>";
+
+/// Starts unwinding `exit`, returning the error to propagate
+fn begin_exit(exit: NonLocalExit) -> String {
+    PENDING_EXIT.with(|slot| *slot.borrow_mut() = Some(exit));
+    NON_LOCAL_EXIT.to_string()
+}
+
+/// Takes the exit being unwound, if `error` is the one carrying it
+fn take_exit(error: &str) -> Option<NonLocalExit> {
+    if error == NON_LOCAL_EXIT {
+        PENDING_EXIT.with(|slot| slot.borrow_mut().take())
+    } else {
+        None
+    }
+}
+
+/// Runs `f`, collecting what the program prints on this thread instead of writing it to stdout
+///
+/// Returns the result of `f` and the captured output. Captures nest: an inner capture
+/// takes the output until it finishes.
+pub fn capture_output<T>(f: impl FnOnce() -> T) -> (T, String) {
+    let previous = CAPTURED_OUTPUT.with(|captured| captured.replace(Some(String::new())));
+    let result = f();
+    let output = CAPTURED_OUTPUT.with(|captured| captured.replace(previous)).unwrap_or_default();
+    (result, output)
+}
+
+/// Writes program output to stdout, or to the active capture
+fn write_output(text: &str) {
+    CAPTURED_OUTPUT.with(|captured| match captured.borrow_mut().as_mut() {
+        Some(buffer) => buffer.push_str(text),
+        None => print!("{}", text),
+    });
 }
 
 fn runtime_len(args: &[Value]) -> Result<Value, String> {
@@ -1011,7 +2745,8 @@ fn runtime_len(args: &[Value]) -> Result<Value, String> {
         Value::String(s) => Ok(Value::Integer(s.len() as i64)),
         Value::Array(arr) => Ok(Value::Integer(arr.len() as i64)),
         Value::Tuple(tup) => Ok(Value::Integer(tup.len() as i64)),
-        _ => Err("len() expects a string, array, or tuple".to_string()),
+        Value::Bytes(bytes) => Ok(Value::Integer(bytes.len() as i64)),
+        _ => Err("len() expects a string, bytes, array, or tuple".to_string()),
     }
 }
 
@@ -1020,10 +2755,80 @@ fn runtime_str(args: &[Value]) -> Result<Value, String> {
         return Err("str() expects exactly one argument".to_string());
     }
     
-    Ok(Value::String(args[0].to_string()))
+    match &args[0] {
+        // Bytes are decoded rather than displayed, so they must hold valid UTF-8
+        Value::Bytes(bytes) => String::from_utf8(bytes.clone())
+            .map(Value::String)
+            .map_err(|e| format!("str() expects valid UTF-8 bytes: {}", e.utf8_error())),
+        other => Ok(Value::String(other.to_string())),
+    }
 }
 
-fn runtime_int(args: &[Value]) -> Result<Value, String> {
+/// Converts a String (as UTF-8), an array of integers in 0..=255, or Bytes to Bytes
+fn runtime_bytes(args: &[Value]) -> Result<Value, String> {
+    match args {
+        [Value::String(s)] => Ok(Value::Bytes(s.as_bytes().to_vec())),
+        [Value::Bytes(bytes)] => Ok(Value::Bytes(bytes.clone())),
+        [Value::Array(items)] => items.iter().enumerate()
+            .map(|(i, item)| match item {
+                Value::Integer(n) => u8::try_from(*n)
+                    .map_err(|_| format!("bytes() element {} is {}, which is not a byte (0 to 255)", i, n)),
+                other => Err(format!("bytes() element {} is {}, expected Int", i, runtime_type_name(other))),
+            })
+            .collect::<Result<Vec<u8>, String>>()
+            .map(Value::Bytes),
+        [other] => Err(format!("bytes() expects a String, Bytes or array of Int, found {}", runtime_type_name(other))),
+        _ => Err("bytes() expects exactly one argument".to_string()),
+    }
+}
+
+/// Evaluates `target[index]` for arrays and bytes
+fn index_value(target: &Value, index: &Value) -> Result<Value, String> {
+    let index = match index {
+        Value::Integer(i) => *i,
+        other => return Err(format!("Index must be an Int, found {}", runtime_type_name(other))),
+    };
+    let len = match target {
+        Value::Array(items) => items.len(),
+        Value::Bytes(bytes) => bytes.len(),
+        other => return Err(format!("Cannot index into {}", runtime_type_name(other))),
+    };
+    let i = usize::try_from(index).ok().filter(|&i| i < len)
+        .ok_or_else(|| format!("Index {} out of bounds for {} of length {}", index, runtime_type_name(target), len))?;
+
+    Ok(match target {
+        Value::Bytes(bytes) => Value::Integer(bytes[i] as i64),
+        Value::Array(items) => items[i].clone(),
+        _ => unreachable!("checked above"),
+    })
+}
+
+/// Evaluates `target[start..end]` for arrays and bytes; the end is exclusive
+fn slice_value(target: &Value, start: &Value, end: &Value) -> Result<Value, String> {
+    let (start, end) = match (start, end) {
+        (Value::Integer(start), Value::Integer(end)) => (*start, *end),
+        (Value::Integer(_), other) | (other, _) => {
+            return Err(format!("Slice bounds must be Int, found {}", runtime_type_name(other)));
+        },
+    };
+    let len = match target {
+        Value::Array(items) => items.len(),
+        Value::Bytes(bytes) => bytes.len(),
+        other => return Err(format!("Cannot slice {}", runtime_type_name(other))),
+    };
+    let range = match (usize::try_from(start), usize::try_from(end)) {
+        (Ok(s), Ok(e)) if s <= e && e <= len => s..e,
+        _ => return Err(format!("Slice {}..{} out of bounds for {} of length {}", start, end, runtime_type_name(target), len)),
+    };
+
+    Ok(match target {
+        Value::Bytes(bytes) => Value::Bytes(bytes[range].to_vec()),
+        Value::Array(items) => Value::Array(items[range].to_vec()),
+        _ => unreachable!("checked above"),
+    })
+}
+
+fn runtime_int(args: &[Value]) -> Result<Value, String> {
     if args.len() != 1 {
         return Err("int() expects exactly one argument".to_string());
     }
@@ -1042,6 +2847,172 @@ fn runtime_int(args: &[Value]) -> Result<Value, String> {
     }
 }
 
+// Smart pointer built-ins
+//
+// Reference counting is delegated to `Arc`: `Rc::clone` and copying an `Rc` value
+// bump the count, and dropping a binding (e.g. when its scope is popped) releases it.
+
+fn lock_cell<T>(cell: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    // A poisoned cell still holds a valid value; the panic was reported where it happened
+    cell.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn expect_one_arg<'v>(name: &str, args: &'v [Value]) -> Result<&'v Value, String> {
+    match args {
+        [arg] => Ok(arg),
+        _ => Err(format!("{}() expects exactly one argument", name)),
+    }
+}
+
+fn expect_channel(value: Value) -> Result<Arc<ChannelSlot>, String> {
+    match value {
+        Value::Channel(channel) => Ok(channel),
+        other => Err(format!("Expected a channel, found {}", runtime_type_name(&other))),
+    }
+}
+
+fn runtime_box_new(args: &[Value]) -> Result<Value, String> {
+    let value = expect_one_arg("Box::new", args)?;
+    Ok(Value::Box(Box::new(value.clone())))
+}
+
+fn runtime_some(args: &[Value]) -> Result<Value, String> {
+    let value = expect_one_arg("Some", args)?;
+    Ok(Value::Option(Some(Box::new(value.clone()))))
+}
+
+fn runtime_ok(args: &[Value]) -> Result<Value, String> {
+    let value = expect_one_arg("Ok", args)?;
+    Ok(Value::Result(Ok(Box::new(value.clone()))))
+}
+
+fn runtime_err(args: &[Value]) -> Result<Value, String> {
+    let error = expect_one_arg("Err", args)?;
+    Ok(Value::Result(Err(Box::new(error.clone()))))
+}
+
+fn runtime_rc_new(args: &[Value]) -> Result<Value, String> {
+    let value = expect_one_arg("Rc::new", args)?;
+    Ok(Value::Rc(Arc::new(value.clone())))
+}
+
+fn runtime_rc_clone(args: &[Value]) -> Result<Value, String> {
+    match expect_one_arg("Rc::clone", args)? {
+        Value::Rc(inner) => Ok(Value::Rc(Arc::clone(inner))),
+        other => Err(format!("Rc::clone() expects an Rc, got {}", other)),
+    }
+}
+
+fn runtime_cell_new(args: &[Value]) -> Result<Value, String> {
+    let value = expect_one_arg("Cell::new", args)?;
+    Ok(Value::Cell(Arc::new(Mutex::new(value.clone()))))
+}
+
+fn runtime_refcell_new(args: &[Value]) -> Result<Value, String> {
+    let value = expect_one_arg("RefCell::new", args)?;
+    Ok(Value::RefCell(Arc::new(RefCellSlot::new(value.clone()))))
+}
+
+fn runtime_map_new(args: &[Value]) -> Result<Value, String> {
+    if !args.is_empty() {
+        return Err("Map::new() takes no arguments".to_string());
+    }
+    Ok(Value::Map(Arc::new(Mutex::new(HashMap::new()))))
+}
+
+fn runtime_set_new(args: &[Value]) -> Result<Value, String> {
+    if !args.is_empty() {
+        return Err("Set::new() takes no arguments".to_string());
+    }
+    Ok(Value::Set(Arc::new(Mutex::new(HashSet::new()))))
+}
+
+/// Calls a `Map` or `Set` method; keys are validated by `HashKey::new`
+fn call_collection_method(receiver: &Value, method: &str, args: &[Value]) -> Result<Value, String> {
+    match (receiver, method, args) {
+        (Value::Map(map), "insert", [key, value]) => {
+            let key = HashKey::new(key.clone())?;
+            Ok(lock_cell(map).insert(key, value.clone()).unwrap_or(Value::Unit))
+        },
+        (Value::Map(map), "get", [key]) => {
+            let key = HashKey::new(key.clone())?;
+            Ok(lock_cell(map).get(&key).cloned().unwrap_or(Value::Unit))
+        },
+        (Value::Map(map), "contains_key", [key]) => {
+            let key = HashKey::new(key.clone())?;
+            Ok(Value::Boolean(lock_cell(map).contains_key(&key)))
+        },
+        (Value::Map(map), "remove", [key]) => {
+            let key = HashKey::new(key.clone())?;
+            Ok(lock_cell(map).remove(&key).unwrap_or(Value::Unit))
+        },
+        (Value::Map(map), "len", []) => Ok(Value::Integer(lock_cell(map).len() as i64)),
+        (Value::Map(map), "keys", []) => Ok(Value::Array(sorted_keys(lock_cell(map).keys()))),
+        (Value::Set(set), "insert", [element]) => {
+            let element = HashKey::new(element.clone())?;
+            Ok(Value::Boolean(lock_cell(set).insert(element)))
+        },
+        (Value::Set(set), "contains", [element]) => {
+            let element = HashKey::new(element.clone())?;
+            Ok(Value::Boolean(lock_cell(set).contains(&element)))
+        },
+        (Value::Set(set), "remove", [element]) => {
+            let element = HashKey::new(element.clone())?;
+            Ok(Value::Boolean(lock_cell(set).remove(&element)))
+        },
+        (Value::Set(set), "len", []) => Ok(Value::Integer(lock_cell(set).len() as i64)),
+        (Value::Set(set), "values", []) => Ok(Value::Array(sorted_keys(lock_cell(set).iter()))),
+        (receiver, method, _) => Err(format!("No method '{}' with {} argument(s) on {}", method, args.len(), runtime_type_name(receiver))),
+    }
+}
+
+/// Calls a method implemented by the runtime itself
+///
+/// Pointer receivers are dereferenced automatically, so `rc.set(1)` reaches the
+/// `Cell` inside an `Rc`. `clone()` applies to the pointer itself. Borrows taken
+/// with `borrow()`/`borrow_mut()` last until the returned value is dropped.
+fn call_builtin_method(receiver: Value, method: &str, args: &[Value]) -> Result<Value, String> {
+    match (receiver, method) {
+        // Collections and cells are shared handles, so cloning one copies its contents
+        (Value::Map(map), "clone") if args.is_empty() => Ok(Value::Map(Arc::new(Mutex::new(lock_cell(&map).clone())))),
+        (Value::Set(set), "clone") if args.is_empty() => Ok(Value::Set(Arc::new(Mutex::new(lock_cell(&set).clone())))),
+        (Value::Cell(cell), "clone") if args.is_empty() => Ok(Value::Cell(Arc::new(Mutex::new(lock_cell(&cell).clone())))),
+        (Value::RefCell(cell), "clone") if args.is_empty() => Ok(Value::RefCell(Arc::new(RefCellSlot::new(cell.get())))),
+        (receiver, "clone") if args.is_empty() => Ok(receiver),
+        (receiver @ (Value::Map(_) | Value::Set(_)), method) => call_collection_method(&receiver, method, args),
+        (Value::Channel(channel), "send") => {
+            let value = expect_one_arg("send", args)?;
+            channel.send(value.clone())?;
+            Ok(Value::Unit)
+        },
+        (Value::Channel(channel), "recv") if args.is_empty() => channel.recv(),
+        (Value::Channel(channel), "close") if args.is_empty() => {
+            channel.close();
+            Ok(Value::Unit)
+        },
+        (Value::Bytes(bytes), "to_array") if args.is_empty() => {
+            Ok(Value::Array(bytes.into_iter().map(|byte| Value::Integer(byte as i64)).collect()))
+        },
+        (Value::Cell(cell), "get") if args.is_empty() => Ok(lock_cell(&cell).clone()),
+        (Value::Cell(cell), "set") => {
+            let value = expect_one_arg("Cell::set", args)?;
+            *lock_cell(&cell) = value.clone();
+            Ok(Value::Unit)
+        },
+        (Value::RefCell(cell), "borrow") if args.is_empty() => Ok(Value::Borrow(Arc::new(cell.borrow(false)?))),
+        (Value::RefCell(cell), "borrow_mut") if args.is_empty() => Ok(Value::Borrow(Arc::new(cell.borrow(true)?))),
+        (Value::Borrow(guard), "set") => {
+            let value = expect_one_arg("set", args)?;
+            guard.set(value.clone())?;
+            Ok(Value::Unit)
+        },
+        (Value::Borrow(guard), method) => call_builtin_method(guard.cell.get(), method, args),
+        (Value::Box(inner), method) => call_builtin_method(*inner, method, args),
+        (Value::Rc(inner), method) => call_builtin_method((*inner).clone(), method, args),
+        (receiver, method) => Err(format!("No method '{}' on {}", method, receiver)),
+    }
+}
+
 fn runtime_float(args: &[Value]) -> Result<Value, String> {
     if args.len() != 1 {
         return Err("float() expects exactly one argument".to_string());
@@ -1061,9 +3032,963 @@ fn runtime_float(args: &[Value]) -> Result<Value, String> {
     }
 }
 
+/// Converts an Int to a Float; Floats are returned unchanged
+fn runtime_to_float(args: &[Value]) -> Result<Value, String> {
+    match expect_one_arg("to_float", args)? {
+        Value::Integer(i) => Ok(Value::Float(*i as f64)),
+        Value::Float(f) => Ok(Value::Float(*f)),
+        other => Err(format!("to_float() expects an Int or Float, found {}", runtime_type_name(other))),
+    }
+}
+
+/// Converts a Float to an Int by truncating toward zero
+///
+/// NaN, infinities and floats beyond the range of Int are an error rather than being
+/// clamped, so a conversion never silently gives a wrong number.
+fn runtime_to_int(args: &[Value]) -> Result<Value, String> {
+    match expect_one_arg("to_int", args)? {
+        Value::Integer(i) => Ok(Value::Integer(*i)),
+        // i64::MIN is a power of two, so it and its negation are exact as floats
+        Value::Float(f) if f.trunc() >= i64::MIN as f64 && f.trunc() < -(i64::MIN as f64) => Ok(Value::Integer(f.trunc() as i64)),
+        Value::Float(f) => Err(format!("to_int() can't convert {} to Int: it is out of range", format_float(*f))),
+        other => Err(format!("to_int() expects an Int or Float, found {}", runtime_type_name(other))),
+    }
+}
+
+/// Parses a String holding a decimal integer, giving `Ok(n)` or `Err(message)`
+fn runtime_parse_int(args: &[Value]) -> Result<Value, String> {
+    match expect_one_arg("parse_int", args)? {
+        Value::String(s) => Ok(Value::Result(match s.parse::<i64>() {
+            Ok(n) => Ok(Box::new(Value::Integer(n))),
+            Err(e) => Err(Box::new(Value::String(format!("'{}' is not an Int: {}", s, e)))),
+        })),
+        other => Err(format!("parse_int() expects a String, found {}", runtime_type_name(other))),
+    }
+}
+
+/// Parses a String holding a decimal number, giving `Ok(x)` or `Err(message)`
+fn runtime_parse_float(args: &[Value]) -> Result<Value, String> {
+    match expect_one_arg("parse_float", args)? {
+        Value::String(s) => Ok(Value::Result(match s.parse::<f64>() {
+            Ok(f) => Ok(Box::new(Value::Float(f))),
+            Err(e) => Err(Box::new(Value::String(format!("'{}' is not a Float: {}", s, e)))),
+        })),
+        other => Err(format!("parse_float() expects a String, found {}", runtime_type_name(other))),
+    }
+}
+
+/// script_dir() - the directory of the running script, or the working directory without one
+fn runtime_script_dir(args: &[Value]) -> Result<Value, String> {
+    if !args.is_empty() {
+        return Err("script_dir() takes no arguments".to_string());
+    }
+    let dir = match crate::std_lib::file_io::script_dir() {
+        Some(dir) => dir,
+        None => std::env::current_dir().map_err(|e| format!("script_dir(): {}", e))?,
+    };
+    Ok(Value::String(dir.display().to_string()))
+}
+
+/// read_file(path) - the file's text, or an error message, as a Result
+fn runtime_read_file(args: &[Value]) -> Result<Value, String> {
+    match expect_one_arg("read_file", args)? {
+        Value::String(path) => Ok(Value::Result(match crate::std_lib::file_io::read_file(path) {
+            Ok(text) => Ok(Box::new(Value::String(text))),
+            Err(e) => Err(Box::new(Value::String(e))),
+        })),
+        other => Err(format!("read_file() expects a String path, found {}", runtime_type_name(other))),
+    }
+}
+
+/// json::stringify(value) - the value's JSON text, or why it has none, as a Result
+fn runtime_json_stringify(args: &[Value]) -> Result<Value, String> {
+    let value = expect_one_arg("json::stringify", args)?;
+    Ok(Value::Result(match to_json(value) {
+        Ok(json) => Ok(Box::new(Value::String(json))),
+        Err(e) => Err(Box::new(Value::String(format!("json::stringify: {}", e)))),
+    }))
+}
+
+/// env::args() - the program's arguments as an array of strings
+fn runtime_env_args(args: &[Value]) -> Result<Value, String> {
+    if !args.is_empty() {
+        return Err("env::args() takes no arguments".to_string());
+    }
+    Ok(Value::Array(crate::std_lib::env::args().into_iter().map(Value::String).collect()))
+}
+
+/// env::arg(n) - Some(the nth argument), or None past the last one
+fn runtime_env_arg(args: &[Value]) -> Result<Value, String> {
+    match expect_one_arg("env::arg", args)? {
+        Value::Integer(n) => {
+            let arg = usize::try_from(*n).ok().and_then(crate::std_lib::env::arg);
+            Ok(Value::Option(arg.map(|arg| Box::new(Value::String(arg)))))
+        },
+        other => Err(format!("env::arg() expects an Int index, found {}", runtime_type_name(other))),
+    }
+}
+
+/// env::vars() - a Map from each environment variable's name to its value
+fn runtime_env_vars(args: &[Value]) -> Result<Value, String> {
+    if !args.is_empty() {
+        return Err("env::vars() takes no arguments".to_string());
+    }
+    let map = Arc::new(Mutex::new(HashMap::new()));
+    for (name, value) in crate::std_lib::env::vars() {
+        lock_cell(&map).insert(HashKey::new(Value::String(name))?, Value::String(value));
+    }
+    Ok(Value::Map(map))
+}
+
+/// env::get_var(name) - Some(the variable's value), or None when it isn't set
+fn runtime_env_get_var(args: &[Value]) -> Result<Value, String> {
+    match expect_one_arg("env::get_var", args)? {
+        Value::String(name) => Ok(Value::Option(crate::std_lib::env::get_env_var(name).map(|value| Box::new(Value::String(value))))),
+        other => Err(format!("env::get_var() expects a String name, found {}", runtime_type_name(other))),
+    }
+}
+
+/// env::set_var(name, value) - sets an environment variable for the rest of the run
+fn runtime_env_set_var(args: &[Value]) -> Result<Value, String> {
+    match args {
+        [Value::String(name), Value::String(value)] => {
+            crate::std_lib::env::set_env_var(name, value);
+            Ok(Value::Unit)
+        },
+        [_, _] => Err("env::set_var() expects a String name and String value".to_string()),
+        _ => Err("env::set_var() expects exactly two arguments".to_string()),
+    }
+}
+
+/// write_file(path, text) - replaces the file's contents, returning Ok(()) or an error message
+fn runtime_write_file(args: &[Value]) -> Result<Value, String> {
+    match args {
+        [Value::String(path), Value::String(text)] => Ok(Value::Result(match crate::std_lib::file_io::write_file(path, text) {
+            Ok(()) => Ok(Box::new(Value::Unit)),
+            Err(e) => Err(Box::new(Value::String(e))),
+        })),
+        [_, _] => Err("write_file() expects a String path and String contents".to_string()),
+        _ => Err("write_file() expects exactly two arguments".to_string()),
+    }
+}
+
     /// Checks if a value matches a pattern, binding variables to the environment if it does
 /// Executes a Logos program
 pub fn execute_program(program: &Program) -> Result<Value, String> {
     let mut runtime = Runtime::new();
     runtime.eval_program(program)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn eval(source: &str) -> Result<Value, String> {
+        let program = Parser::new(source).parse_program()?;
+        Runtime::new().eval_program(&program)
+    }
+
+    #[test]
+    fn test_propagate_unwraps_and_returns_early() {
+        let source = "fn good() -> Result<Int, String> { Ok(20) }\n\
+                      fn bad() -> Result<Int, String> { Err(\"boom\") }\n\
+                      fn sum_good() -> Result<Int, String> { let a = good()?\n let b = good()?\n Ok(a + b) }\n\
+                      fn sum_bad() -> Result<Int, String> { let a = good()?\n let b = bad()?\n Err(\"unreachable\") }\n";
+        assert_eq!(eval(&format!("{}sum_good()", source)).unwrap().to_string(), "Ok(40)");
+        assert_eq!(eval(&format!("{}sum_bad()", source)).unwrap().to_string(), "Err(\"boom\")");
+
+        // None propagates out of nested blocks, skipping the rest of the function
+        let nested = "fn find() -> Option<Int> { if true { let x = None? }\n Some(1) }\nfind()";
+        assert_eq!(eval(nested).unwrap(), Value::Option(None));
+    }
+
+    #[test]
+    fn test_propagate_errors() {
+        let err = eval("fn f() -> Int { 1? }\nf()").unwrap_err();
+        assert!(err.contains("The ? operator expects an Option or Result, found Int"), "{}", err);
+
+        let err = eval("None?").unwrap_err();
+        assert!(err.contains("can only be used inside a function"), "{}", err);
+    }
+
+    #[test]
+    fn test_byte_literals_index_and_slice() {
+        let source = "let header = b\"\\x89PNG\\r\\n\"\n";
+        assert_eq!(
+            eval(&format!("{}header", source)).unwrap(),
+            Value::Bytes(vec![0x89, b'P', b'N', b'G', b'\r', b'\n'])
+        );
+        assert_eq!(eval(&format!("{}header", source)).unwrap().to_string(), "b\"\\x89PNG\\r\\n\"");
+        assert_eq!(eval(&format!("{}(header[0], len(header))", source)).unwrap().to_string(), "(137, 6)");
+        assert_eq!(eval(&format!("{}header[1..4]", source)).unwrap(), Value::Bytes(b"PNG".to_vec()));
+        assert_eq!(eval(&format!("{}header[4..6].to_array()", source)).unwrap().to_string(), "[13, 10]");
+
+        let err = eval(&format!("{}header[6]", source)).unwrap_err();
+        assert_eq!(err, "Index 6 out of bounds for Bytes of length 6");
+        let err = eval(&format!("{}header[2..9]", source)).unwrap_err();
+        assert_eq!(err, "Slice 2..9 out of bounds for Bytes of length 6");
+    }
+
+    #[test]
+    fn test_bytes_utf8_round_trip() {
+        assert_eq!(eval("bytes(\"héllo\")").unwrap(), Value::Bytes("héllo".as_bytes().to_vec()));
+        assert_eq!(eval("str(bytes(\"héllo\"))").unwrap(), Value::String("héllo".to_string()));
+        assert_eq!(eval("str(bytes([104, 105]))").unwrap(), Value::String("hi".to_string()));
+
+        let err = eval("bytes([1, 256])").unwrap_err();
+        assert!(err.contains("element 1 is 256, which is not a byte"), "{}", err);
+    }
+
+    #[test]
+    fn test_invalid_utf8_bytes_to_string_is_error() {
+        let err = eval("str(b\"ok\\xff\")").unwrap_err();
+        assert_eq!(err, "str() expects valid UTF-8 bytes: invalid utf-8 sequence of 1 bytes from index 2");
+    }
+
+    #[test]
+    fn test_labeled_break_exits_outer_loop() {
+        let source = "let i = Cell::new(0)\nlet hits = Cell::new(0)\n\
+                      'outer: while i.get() < 3 {\n\
+                          i.set(i.get() + 1)\n\
+                          let j = Cell::new(0)\n\
+                          while j.get() < 3 {\n\
+                              j.set(j.get() + 1)\n\
+                              if j.get() == 2 { break 'outer }\n\
+                              hits.set(hits.get() + 1)\n\
+                          }\n\
+                          hits.set(hits.get() + 100)\n\
+                      }\n";
+        assert_eq!(eval(&format!("{}(i.get(), hits.get())", source)).unwrap().to_string(), "(1, 1)");
+
+        // Without the label only the inner loop ends
+        let unlabeled = source.replace("break 'outer", "break");
+        assert_eq!(eval(&format!("{}(i.get(), hits.get())", unlabeled)).unwrap().to_string(), "(3, 303)");
+    }
+
+    #[test]
+    fn test_labeled_continue_skips_rest_of_outer_iteration() {
+        let source = "let i = Cell::new(0)\nlet hits = Cell::new(0)\n\
+                      'outer: while i.get() < 3 {\n\
+                          i.set(i.get() + 1)\n\
+                          let j = Cell::new(0)\n\
+                          while j.get() < 3 {\n\
+                              j.set(j.get() + 1)\n\
+                              if j.get() == 2 { continue 'outer }\n\
+                              hits.set(hits.get() + 1)\n\
+                          }\n\
+                          hits.set(hits.get() + 100)\n\
+                      }\n\
+                      (i.get(), hits.get())";
+        assert_eq!(eval(source).unwrap().to_string(), "(3, 3)");
+
+        let err = eval("while true { break 'missing }").unwrap_err();
+        assert!(err.contains("Undefined loop label 'missing"), "{}", err);
+    }
+
+    #[test]
+    fn test_defers_run_in_reverse_on_normal_exit() {
+        let source = "let log = Cell::new(\"\")\n\
+                      fn work(log: Cell) -> Int {\n\
+                          defer log.set(log.get() + \"1\")\n\
+                          defer log.set(log.get() + \"2\")\n\
+                          log.set(log.get() + \"body \")\n\
+                          7\n\
+                      }\n\
+                      (work(log), log.get())";
+        assert_eq!(eval(source).unwrap().to_string(), "(7, \"body 21\")");
+
+        // A block runs its defers when it ends, before the statements after it
+        let block = "let log = Cell::new(\"\")\n{ defer log.set(log.get() + \"inner \") }\nlog.set(log.get() + \"after\")\nlog.get()";
+        assert_eq!(eval(block).unwrap().to_string(), "\"inner after\"");
+    }
+
+    #[test]
+    fn test_defers_run_after_return_value_is_evaluated() {
+        let source = "let log = Cell::new(\"\")\n\
+                      fn work(log: Cell) -> Int {\n\
+                          defer log.set(log.get() + \"deferred\")\n\
+                          return len(log.get())\n\
+                          log.set(\"unreachable\")\n\
+                      }\n\
+                      (work(log), log.get())";
+        assert_eq!(eval(source).unwrap().to_string(), "(0, \"deferred\")");
+    }
+
+    #[test]
+    fn test_defers_run_on_error_propagation() {
+        let source = "let log = Cell::new(\"\")\n\
+                      fn fails() -> Result<Int, String> { Err(\"boom\") }\n\
+                      fn work(log: Cell) -> Result<Int, String> {\n\
+                          defer log.set(log.get() + \"cleanup\")\n\
+                          let x = fails()?\n\
+                          Ok(x)\n\
+                      }\n\
+                      (work(log), log.get())";
+        assert_eq!(eval(source).unwrap().to_string(), "(Err(\"boom\"), \"cleanup\")");
+
+        // Runtime errors run defers too, and the original error is reported
+        let crash = "let log = Cell::new(\"\")\nfn crash(log: Cell) { defer log.set(\"ran\")\n 1 / 0 }\ncrash(log)";
+        let program = Parser::new(crash).parse_program().unwrap();
+        let mut runtime = Runtime::new();
+        let err = runtime.eval_program(&program).unwrap_err();
+        assert!(err.contains("Division by zero"), "{}", err);
+        assert_eq!(runtime.env.get("log").unwrap().to_string(), "Cell(\"ran\")");
+    }
+
+    #[test]
+    fn test_assertions_report_location_and_values() {
+        assert!(eval("assert(1 < 2)\nassert_eq(str(12), \"12\", \"str renders digits\")").is_ok());
+
+        let err = eval("let x = 5\n  assert(x > 10)").unwrap_err();
+        assert_eq!(err, "Assertion failed at line 2, column 3");
+
+        let err = eval("fn f() {\n    assert(false, \"unreachable branch\")\n}\nf()").unwrap_err();
+        assert_eq!(err, "Assertion failed at line 2, column 5: unreachable branch");
+
+        let err = eval("assert_eq([1, 2], [1, 3])").unwrap_err();
+        assert_eq!(err, "Assertion failed at line 1, column 1: expected [1, 3], actual [1, 2]");
+
+        let err = eval("assert(1)").unwrap_err();
+        assert!(err.contains("assert() expects a Bool condition, found Int"), "{}", err);
+    }
+
+    #[test]
+    fn test_interpolation_format_specs() {
+        let source = "let pi = 3.14159\n\
+                      let n = 42\n\
+                      let name = \"ab\"\n\
+                      \"[${pi:0.2}] [${n:05}] [${name:<5}] [${name:>5}] [${n}] [${name}]\"";
+        assert_eq!(
+            eval(source).unwrap(),
+            Value::String("[3.14] [00042] [ab   ] [   ab] [42] [ab]".to_string())
+        );
+
+        let err = eval("let flag = true\n\"${flag:.2}\"").unwrap_err();
+        assert!(err.contains("can't be applied to Bool"), "{}", err);
+    }
+
+    #[test]
+    fn test_block_expressions_yield_their_trailing_value() {
+        assert_eq!(eval("let x = { let a = 1; a + 2 }\nx").unwrap(), Value::Integer(3));
+        assert_eq!(eval("let x = { let a = 1 }\nx").unwrap(), Value::Unit);
+        assert_eq!(eval("let a = 10\nlet b = { let a = 1\n a * 2 }\na + b").unwrap(), Value::Integer(12));
+
+        // The block's bindings go out of scope when it ends
+        let err = eval("let x = { let inner = 1; inner }\ninner").unwrap_err();
+        assert!(err.contains("inner"), "{}", err);
+    }
+
+    #[test]
+    fn test_if_expressions_yield_the_taken_branch() {
+        assert_eq!(eval("let a = 3\nlet b = 7\nlet max = if a > b { a } else { b }\nmax").unwrap(), Value::Integer(7));
+        let sign = |n: i64| eval(&format!("let n = {}\nif n < 0 {{ -1 }} else if n == 0 {{ 0 }} else {{ 1 }}", n)).unwrap();
+        assert_eq!((sign(-5), sign(0), sign(9)), (Value::Integer(-1), Value::Integer(0), Value::Integer(1)));
+        assert_eq!(eval("if 1 > 2 { 5 }").unwrap(), Value::Unit);
+    }
+
+    #[test]
+    fn test_match_expressions_yield_the_matched_arm() {
+        let label = |n: i64| eval(&format!("let n = {}\nlet label = match n {{ 0 => \"zero\", _ => \"other\" }}\nlabel", n)).unwrap();
+        assert_eq!((label(0), label(4)), (Value::String("zero".to_string()), Value::String("other".to_string())));
+        assert_eq!(eval("let n = 3\nmatch n { 0 => 0, m => { let twice = m * 2\n twice + 1 } }").unwrap(), Value::Integer(7));
+    }
+
+    #[test]
+    fn test_equality_and_ordering_within_one_type() {
+        assert_eq!(eval("[[1, 2], [3]] == [[1, 2], [3]]").unwrap(), Value::Boolean(true));
+        assert_eq!(eval("[1, 2] != [1, 3]").unwrap(), Value::Boolean(true));
+
+        // Tuples and arrays order element by element, and a prefix comes first
+        assert_eq!(eval("(1, \"b\") < (1, \"c\")").unwrap(), Value::Boolean(true));
+        assert_eq!(eval("(2, \"a\") <> (1, \"z\")").unwrap(), Value::Integer(1));
+        assert_eq!(eval("[1, 2] >= [1, 2, 0]").unwrap(), Value::Boolean(false));
+
+        // Int and Float don't mix without a conversion, even inside arrays
+        assert_eq!(eval("1 == 1.0").unwrap_err(), "Cannot compare Int with Float");
+        assert_eq!(eval("[1] < [1.5]").unwrap_err(), "Cannot compare Int with Float");
+        assert_eq!(eval("fn f() { 1 }\nf == f").unwrap_err(), "Functions can't be compared");
+    }
+
+    #[test]
+    fn test_numeric_conversions() {
+        assert_eq!(eval("to_float(3) + 0.5").unwrap(), Value::Float(3.5));
+        assert_eq!(eval("(to_int(2.9), to_int(-2.9))").unwrap(), Value::Tuple(vec![Value::Integer(2), Value::Integer(-2)]));
+
+        // Floats past the range of Int are an error, not a clamped value
+        assert_eq!(eval("to_int(10000000000000000000.0)").unwrap_err(), "to_int() can't convert 1e19 to Int: it is out of range");
+        assert!(eval("to_int(-9223372036854775808.0)").is_ok());
+        assert!(eval("to_int(0.0 / 0.0)").is_err());
+
+        assert_eq!(eval("parse_int(\"42\")").unwrap().to_string(), "Ok(42)");
+        assert_eq!(eval("parse_float(\"2.5\")").unwrap().to_string(), "Ok(2.5)");
+        assert_eq!(eval("parse_int(\"4.2\")").unwrap().to_string(), "Err(\"'4.2' is not an Int: invalid digit found in string\")");
+        assert!(eval("parse_float(\"abc\")").unwrap().to_string().starts_with("Err("));
+    }
+
+    #[test]
+    fn test_relative_reads_resolve_against_the_script_directory() {
+        use crate::std_lib::file_io::use_script;
+        let dir = std::env::temp_dir().join(format!("logos-script-dir-test-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("other")).unwrap();
+        std::fs::write(dir.join("data.txt"), "beside the script").unwrap();
+        std::fs::write(dir.join("other").join("data.txt"), "under --cwd").unwrap();
+        let script = dir.join("main.logos");
+
+        // The tests run from the crate root, which has no data.txt of its own
+        use_script(Some(&script), None).unwrap();
+        assert_eq!(eval("read_file(\"data.txt\")").unwrap().to_string(), "Ok(\"beside the script\")");
+        assert!(matches!(eval("script_dir()").unwrap(), Value::String(path) if path == dir.display().to_string()));
+        assert!(eval("write_file(\"out.txt\", \"written\")").is_ok());
+        assert_eq!(std::fs::read_to_string(dir.join("out.txt")).unwrap(), "written");
+
+        // An explicit base wins, and a missing file is an Err value rather than a crash
+        use_script(Some(&script), Some(&dir.join("other"))).unwrap();
+        assert_eq!(eval("read_file(\"data.txt\")").unwrap().to_string(), "Ok(\"under --cwd\")");
+        assert!(eval("read_file(\"out.txt\")").unwrap().to_string().starts_with("Err(\"Failed to read file 'out.txt'"));
+        assert!(use_script(Some(&script), Some(&dir.join("missing"))).unwrap_err().contains("is not a directory"));
+
+        use_script(None, None).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_floats_render_canonically() {
+        // Integral floats keep their `.0`, so they read differently from Ints
+        assert_eq!(format_float(1.0), "1.0");
+        assert_eq!(format_float(-42.0), "-42.0");
+        assert_eq!(format_float(-0.0), "-0.0");
+        assert_eq!(eval("print(2.0)\n3.0 * 2.0").unwrap().to_string(), "6.0");
+
+        // Error in the last bit is rounded away, while literals print back as written
+        assert_eq!(eval("0.1 + 0.2").unwrap().to_string(), "0.3");
+        assert_eq!(eval("let sum = 0.1 + 0.2\n\"${sum}\"").unwrap().to_string(), "\"0.3\"");
+        assert_eq!(format_float(3.14159), "3.14159");
+        assert_eq!(format_float(0.000015), "0.000015");
+        assert_eq!(format_float(123456789.125), "123456789.125");
+
+        // Very large and very small values switch to scientific notation
+        assert_eq!(format_float(1e300), "1e300");
+        assert_eq!(format_float(-1.5e16), "-1.5e16");
+        assert_eq!(format_float(1e15), "1000000000000000.0");
+        assert_eq!(format_float(f64::MAX), "1.79769313486232e308");
+        assert_eq!(format_float(0.000001), "1e-6");
+        assert_eq!(format_float(f64::MIN_POSITIVE), "2.2250738585072e-308");
+        assert_eq!(format_float(5e-324), "4.94065645841247e-324");
+
+        // Every rendering parses back to a float that renders the same way
+        for value in [0.1 + 0.2, 1e300, 5e-324, -7.25, 1.0 / 3.0] {
+            let text = format_float(value);
+            assert_eq!(format_float(text.parse().unwrap()), text);
+        }
+    }
+
+    /// Evaluates `source` with `nan`, `inf` and `ninf` bound, since arithmetic can't produce them
+    fn eval_special(source: &str) -> Result<Value, String> {
+        let mut runtime = Runtime::new();
+        runtime.env.set("nan".to_string(), Value::Float(f64::NAN));
+        runtime.env.set("inf".to_string(), Value::Float(f64::INFINITY));
+        runtime.env.set("ninf".to_string(), Value::Float(f64::NEG_INFINITY));
+        runtime.eval_program(&Parser::new(source).parse_program()?)
+    }
+
+    #[test]
+    fn test_nan_is_unequal_to_everything_and_infinities_equal_themselves() {
+        let eval_special = |source: &str| eval_special(source).unwrap().to_string();
+        assert_eq!(eval_special("nan == nan"), "false");
+        assert_eq!(eval_special("nan != nan"), "true");
+        assert_eq!(eval_special("[nan] == [nan]"), "false");
+        assert_eq!(eval_special("nan == 1.0"), "false");
+        assert_eq!(eval_special("inf == inf"), "true");
+        assert_eq!(eval_special("inf == ninf"), "false");
+        assert_eq!(eval_special("ninf < inf"), "true");
+        assert_eq!(eval_special("ninf < -1000000.0"), "true");
+    }
+
+    #[test]
+    fn test_ordering_nan_is_an_error() {
+        let err = eval_special("nan < 1.0").unwrap_err();
+        assert!(err.contains("Cannot order NaN: NaN and 1.0 are unordered"), "{}", err);
+        let err = eval_special("[1.0, nan] > [1.0, 2.0]").unwrap_err();
+        assert!(err.contains("Cannot order NaN"), "{}", err);
+        assert!(eval_special("inf <=> nan").is_err());
+    }
+
+    #[test]
+    fn test_nan_matches_no_literal_pattern() {
+        let source = "fn describe(x: Float) -> String { match x { 0.0 => \"zero\", 1.5 => \"one and a half\", _ => \"other\" } }\n";
+        assert_eq!(eval_special(&format!("{}describe(nan)", source)).unwrap().to_string(), "\"other\"");
+        assert_eq!(eval_special(&format!("{}describe(inf)", source)).unwrap().to_string(), "\"other\"");
+        assert_eq!(eval_special(&format!("{}describe(1.5)", source)).unwrap().to_string(), "\"one and a half\"");
+    }
+
+    #[test]
+    fn test_json_stringify_rejects_nan_and_infinity() {
+        let json = |source: &str| eval_special(&format!("json::stringify({})", source)).unwrap().to_string();
+        assert_eq!(json("[1, 2.5, 3.0]"), "Ok(\"[1,2.5,3.0]\")");
+        assert_eq!(json("nan"), "Err(\"json::stringify: NaN has no JSON representation\")");
+        assert_eq!(json("[1.0, inf]"), "Err(\"json::stringify: inf has no JSON representation\")");
+        assert_eq!(json("Some(ninf)"), "Err(\"json::stringify: -inf has no JSON representation\")");
+
+        let mut runtime = Runtime::new();
+        let program = Parser::new("let m = Map::new()\nm.insert(\"b\", [true, None])\nm.insert(\"a\", \"q\\\"uote\")\nm").parse_program().unwrap();
+        let map = runtime.eval_program(&program).unwrap();
+        assert_eq!(to_json(&map).unwrap(), "{\"a\":\"q\\\"uote\",\"b\":[true,null]}");
+        assert_eq!(to_json(&Value::Float(f64::NAN)).unwrap_err(), "NaN has no JSON representation");
+    }
+
+    #[test]
+    fn test_program_arguments_are_visible() {
+        crate::std_lib::env::set_args(vec!["foo".to_string(), "bar".to_string()]);
+        assert_eq!(eval("env::args()").unwrap().to_string(), "[\"foo\", \"bar\"]");
+        assert_eq!(eval("env::arg(1)").unwrap().to_string(), "Some(\"bar\")");
+        assert_eq!(eval("env::arg(2)").unwrap().to_string(), "None");
+        assert_eq!(eval("len(env::args())").unwrap().to_string(), "2");
+        crate::std_lib::env::set_args(Vec::new());
+
+        assert_eq!(eval("env::set_var(\"LOGOS_ENV_TEST\", \"on\")\nenv::get_var(\"LOGOS_ENV_TEST\")").unwrap().to_string(), "Some(\"on\")");
+        assert_eq!(eval("env::vars().get(\"LOGOS_ENV_TEST\")").unwrap().to_string(), "\"on\"");
+        assert_eq!(eval("env::get_var(\"LOGOS_ENV_TEST_UNSET\")").unwrap().to_string(), "None");
+    }
+
+    #[test]
+    fn test_try_catch_recovers_from_division_by_zero() {
+        let source = "let result = try { 10 / 0 } catch (e) { e }\n\
+                      let after = \"still running\"\n\
+                      (result.message, result.code, after)";
+        assert_eq!(
+            eval(source).unwrap().to_string(),
+            "(\"Division by zero: 10 / 0 at line 1, column 23\", Some(\"E0003\"), \"still running\")"
+        );
+
+        // Without an error the try block's value is used and the handler doesn't run
+        assert_eq!(eval("try { 10 / 2 } catch (e) { 0 }").unwrap(), Value::Integer(5));
+    }
+
+    #[test]
+    fn test_try_catch_leaves_fatal_errors_and_exits_alone() {
+        let err = eval("try { [0; 10000000000] } catch (e) { 0 }").unwrap_err();
+        assert!(err.contains("exceeds limit"), "{}", err);
+
+        // `?` inside try still returns from the function instead of being caught
+        let source = "fn first() -> Option<Int> {\n\
+                          try { let x = None? } catch (e) { print(\"caught\") }\n\
+                          Some(1)\n\
+                      }\n\
+                      first()";
+        let (result, output) = capture_output(|| eval(source));
+        assert_eq!(result.unwrap(), Value::Option(None));
+        assert_eq!(output, "");
+    }
+
+    #[test]
+    fn test_deep_recursion_needs_a_bigger_stack() {
+        let depth = max_call_depth_for(ASSUMED_STACK_SIZE) + 10;
+        let source = format!("fn down(n: Int) -> Int {{ if n == 0 {{ 0 }} else {{ down(n - 1) + 1 }} }}\ndown({})", depth);
+        let program = Parser::new(&source).parse_program().unwrap();
+
+        // On the default stack the depth limit stops the program before the stack overflows
+        let err = with_stack_size(ASSUMED_STACK_SIZE, || Runtime::new().eval_program(&program)).unwrap_err();
+        assert!(err.starts_with("Recursion depth exceeded"), "{}", err);
+
+        let stack_size = 8 * ASSUMED_STACK_SIZE;
+        let result = with_stack_size(stack_size, || {
+            let mut runtime = Runtime::new();
+            runtime.set_max_call_depth(max_call_depth_for(stack_size));
+            runtime.eval_program(&program)
+        });
+        assert_eq!(result, Ok(Value::Integer(depth as i64)));
+    }
+
+    #[test]
+    fn test_panic_unwinds_to_the_nearest_catch() {
+        let source = "fn inner(n: Int) -> Int { panic(\"bad input ${n}\") }\n\
+                      fn outer(n: Int) -> Int { inner(n) + 1 }\n\
+                      let caught = try { outer(7) } catch (e) { e }\n\
+                      let summary = (caught.message, caught.stack)\n\
+                      summary";
+        assert_eq!(eval(source).unwrap().to_string(), "(\"bad input 7\", [\"inner\", \"outer\"])");
+
+        // Uncaught, the panic ends the program with its message and where it happened
+        let err = eval("fn fail() -> Int { panic(\"giving up\") }\nfail()").unwrap_err();
+        assert_eq!(err, "panic: giving up\n    in fail");
+        // The stack only holds calls still in progress
+        let err = eval("fn ok() -> Int { 1 }\nok()\npanic(\"top level\")").unwrap_err();
+        assert_eq!(err, "panic: top level");
+    }
+
+    #[test]
+    fn test_a_panicking_builtin_becomes_a_runtime_error() {
+        let source = "let caught = try { debug::assert(1 > 2, \"one is not more than two\") } catch (e) { e }\n\
+                      caught.message";
+        assert_eq!(
+            eval(source).unwrap().to_string(),
+            "\"builtin panicked: Assertion failed: one is not more than two\""
+        );
+        assert_eq!(eval("debug::assert(true, \"fine\")").unwrap(), Value::Unit);
+    }
+
+    #[test]
+    fn test_print_writes_strings_unquoted() {
+        let source = "let name = \"Ada\"\nprint(\"hello\", name, 'x', 2)\nprint([\"a\"], 1.5)";
+        let (result, output) = capture_output(|| eval(source));
+        assert_eq!(result.unwrap(), Value::Unit);
+        assert_eq!(output, "hello Ada x 2\n[\"a\"] 1.5\n");
+    }
+
+    #[test]
+    fn test_arithmetic_promotes_int_to_float() {
+        assert_eq!(eval("1 + 2.5").unwrap(), Value::Float(3.5));
+        assert_eq!(eval("2.0 * 3").unwrap(), Value::Float(6.0));
+        assert_eq!(eval("7 / 2").unwrap(), Value::Integer(3));
+        assert_eq!(eval("let x = 4\nif x > 3 { x * 10 } else { 0 }").unwrap(), Value::Integer(40));
+        assert!(eval("1.5 / 0").unwrap_err().contains("Division by zero"));
+    }
+
+    #[test]
+    fn test_integer_division_by_zero_is_error() {
+        let result = eval("10 / 0");
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("Division by zero"));
+
+        // The error says where the division is
+        let source = "fn ratio(a: Int, b: Int) -> Int {\n    a / b\n}\nratio(1, 0)";
+        assert_eq!(eval(source).unwrap_err(), "Division by zero: 1 / 0 at line 2, column 7");
+        assert_eq!(eval("let n = 0\n5 % n").unwrap_err(), "Modulo by zero: 5 % 0 at line 2, column 3");
+    }
+
+    #[test]
+    fn test_modulo_by_zero_is_error() {
+        let result = eval("10 % 0");
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("Modulo by zero"));
+    }
+
+    #[test]
+    fn test_block_shadowing_is_restored() {
+        let program = Parser::new("let x = 1\n{\n let x = 2\n x\n}").parse_program().unwrap();
+        let mut runtime = Runtime::new();
+
+        // The block sees its own shadowing binding
+        assert_eq!(runtime.eval_program(&program).unwrap(), Value::Integer(2));
+        // ...and the outer binding is back once the block exits
+        assert_eq!(runtime.env.get("x"), Some(Value::Integer(1)));
+    }
+
+    #[test]
+    fn test_nested_block_scopes() {
+        let source = "let x = 1\nlet y = 10\n{\n let x = 2\n {\n let x = 3\n x + y\n }\n}";
+        let program = Parser::new(source).parse_program().unwrap();
+        let mut runtime = Runtime::new();
+
+        assert_eq!(runtime.eval_program(&program).unwrap(), Value::Integer(13));
+        assert_eq!(runtime.env.get("x"), Some(Value::Integer(1)));
+        assert_eq!(runtime.env.get("y"), Some(Value::Integer(10)));
+    }
+
+    #[test]
+    fn test_box_deref_and_move() {
+        assert_eq!(eval("let a = Box::new(5)\nlet b = *a + 1\nb").unwrap(), Value::Integer(6));
+
+        let err = eval("let a = Box::new(5)\nlet b = a\nlet c = *a").unwrap_err();
+        assert!(err.contains("Use of moved value: a"), "{}", err);
+
+        // The new owner is usable, and rebinding the old name revives it
+        assert_eq!(eval("let a = Box::new(5)\nlet b = a\nlet c = *b\nc").unwrap(), Value::Integer(5));
+        assert_eq!(eval("let a = Box::new(5)\nlet b = a\nlet a = 7\na").unwrap(), Value::Integer(7));
+    }
+
+    #[test]
+    fn test_rc_shared_mutation_via_cell() {
+        let source = "let shared = Rc::new(Cell::new(1))\n\
+                      let other = Rc::clone(shared)\n\
+                      other.set(42)\n\
+                      shared.get()";
+        assert_eq!(eval(source).unwrap(), Value::Integer(42));
+    }
+
+    #[test]
+    fn test_rc_count_released_with_scope() {
+        let source = "let a = Rc::new(1)\n{\n let b = Rc::clone(a)\n}";
+        let program = Parser::new(source).parse_program().unwrap();
+        let mut runtime = Runtime::new();
+
+        runtime.eval_program(&program).unwrap();
+        match runtime.env.get("a") {
+            // The binding plus the copy returned by `get`
+            Some(Value::Rc(inner)) => assert_eq!(Arc::strong_count(&inner), 2),
+            other => panic!("expected an Rc, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_assignment_updates_the_enclosing_binding() {
+        let program = Parser::new("mut i = 0\nwhile i < 3 { i = i + 1 }").parse_program().unwrap();
+        let mut runtime = Runtime::new();
+        runtime.eval_program(&program).unwrap();
+        assert_eq!(runtime.env.get("i"), Some(Value::Integer(3)));
+
+        // Through an `if` inside the loop, and leaving a shadowing `let` in the body alone
+        let source = "mut total = 0\nmut i = 0\nwhile i < 5 {\n i = i + 1\n if i % 2 == 0 { total = total + i }\n let i = 100\n}\ntotal";
+        assert_eq!(eval(source).unwrap(), Value::Integer(6));
+
+        assert_eq!(eval("x = 1").unwrap_err(), "Cannot assign to undeclared variable: x");
+    }
+
+    #[test]
+    fn test_assignment_needs_a_mut_binding() {
+        let err = eval("let i = 0\nwhile i < 3 { i = i + 1 }").unwrap_err();
+        assert_eq!(err, "Cannot assign to immutable variable: i (declare it with `mut`)");
+
+        // Shadowing with `let` makes the name immutable again, and with `mut` mutable again
+        assert!(eval("mut x = 1\nlet x = 2\nx = 3").unwrap_err().contains("immutable variable: x"));
+        assert_eq!(eval("let x = 1\nmut x = 2\nx = 3\nx").unwrap(), Value::Integer(3));
+    }
+
+    #[test]
+    fn test_cell_clone_is_independent() {
+        let source = "let a = Cell::new(1)\nlet b = a.clone()\nb.set(2)\na.get()";
+        assert_eq!(eval(source).unwrap(), Value::Integer(1));
+
+        let source = "let a = RefCell::new(1)\nlet b = a.clone()\n{\n let w = b.borrow_mut()\n w.set(2)\n}\nlet r = a.borrow()\nlet v = *r\nv";
+        assert_eq!(eval(source).unwrap(), Value::Integer(1));
+    }
+
+    #[test]
+    fn test_refcell_borrows() {
+        // Any number of shared borrows may coexist
+        let source = "let c = RefCell::new(1)\n{\n let a = c.borrow()\n let b = c.borrow()\n let sum = *a + *b\n sum\n}";
+        assert_eq!(eval(source).unwrap(), Value::Integer(2));
+
+        // Borrows end with their scope, so a later mutable borrow succeeds
+        let source = "let c = RefCell::new(1)\n\
+                      {\n let a = c.borrow()\n}\n\
+                      {\n let w = c.borrow_mut()\n w.set(10)\n}\n\
+                      let r = c.borrow()\n\
+                      let v = *r\n\
+                      v";
+        assert_eq!(eval(source).unwrap(), Value::Integer(10));
+    }
+
+    #[test]
+    fn test_refcell_conflicting_borrows_are_errors() {
+        let err = eval("let c = RefCell::new(1)\nlet a = c.borrow_mut()\nlet b = c.borrow_mut()").unwrap_err();
+        assert!(err.contains("already mutably borrowed"), "{}", err);
+
+        let err = eval("let c = RefCell::new(1)\nlet a = c.borrow()\nlet b = c.borrow_mut()").unwrap_err();
+        assert!(err.contains("already borrowed"), "{}", err);
+
+        let err = eval("let c = RefCell::new(1)\nlet a = c.borrow()\na.set(2)").unwrap_err();
+        assert!(err.contains("shared borrow"), "{}", err);
+    }
+
+    const DRAWABLE: &str = "trait Drawable {\n fn draw(self: Drawable) -> String {}\n}\n\
+                            impl Drawable for Int {\n fn draw(self: Int) -> String { \"int \" + str(self) }\n}\n\
+                            impl Drawable for String {\n fn draw(self: String) -> String { \"text \" + self }\n}\n";
+
+    #[test]
+    fn test_trait_objects_dispatch_per_type() {
+        let source = "trait Shape {\n fn area(self: Shape) -> Int {}\n fn name(self: Shape) -> String {}\n}\n\
+                      struct Square { side: Int }\n\
+                      struct Rect { width: Int, height: Int }\n\
+                      impl Shape for Square {\n\
+                       fn area(self: Square) -> Int { self.side * self.side }\n\
+                       fn name(self: Square) -> String { \"square\" }\n\
+                      }\n\
+                      impl Shape for Rect {\n\
+                       fn area(self: Rect) -> Int { self.width * self.height }\n\
+                       fn name(self: Rect) -> String { \"rect\" }\n\
+                      }\n\
+                      let shapes: [dyn Shape] = [Square { side: 3 }, Rect { width: 2, height: 5 }, Square { side: 1 }]\n\
+                      mut total = 0\n\
+                      mut i = 0\n\
+                      while i < len(shapes) {\n\
+                       let shape = shapes[i]\n\
+                       print(shape.name(), shape.area())\n\
+                       total = total + shape.area()\n\
+                       i = i + 1\n\
+                      }\n\
+                      total";
+        let (result, output) = capture_output(|| eval(source));
+
+        assert_eq!(result.unwrap(), Value::Integer(20));
+        assert_eq!(output, "square 9\nrect 10\nsquare 1\n");
+    }
+
+    #[test]
+    fn test_trait_object_method_call_and_missing_impl() {
+        let source = format!("{}let shape: dyn Drawable = 7\nshape.draw()", DRAWABLE);
+        assert_eq!(eval(&source).unwrap(), Value::String("int 7".to_string()));
+
+        let source = format!("{}let shape: dyn Drawable = true", DRAWABLE);
+        let err = eval(&source).unwrap_err();
+        assert!(err.contains("Type 'Bool' does not implement trait 'Drawable'"), "{}", err);
+    }
+
+    #[test]
+    fn test_array_repeat_respects_allocation_limit() {
+        assert_eq!(eval("[7; 3]").unwrap().to_string(), "[7, 7, 7]");
+
+        // Far past the default limit; must fail before anything is allocated
+        let err = eval("[0; 10000000000]").unwrap_err();
+        assert!(err.contains("allocation of 10000000000 elements exceeds limit"), "{}", err);
+
+        let err = eval("[0; -1]").unwrap_err();
+        assert!(err.contains("cannot be negative"), "{}", err);
+    }
+
+    #[test]
+    fn test_max_allocation_setter_counts_nested_arrays() {
+        let program = Parser::new("[[0; 10]; 10]").parse_program().unwrap();
+        let mut runtime = Runtime::new();
+        runtime.set_max_allocation(100);
+        assert_eq!(runtime.max_allocation(), 100);
+
+        // 10 inner arrays of 10 elements, plus the inner arrays themselves
+        let err = runtime.eval_program(&program).unwrap_err();
+        assert!(err.contains("allocation of 110 elements exceeds limit of 100"), "{}", err);
+
+        runtime.set_max_allocation(110);
+        assert!(runtime.eval_program(&program).is_ok());
+    }
+
+    #[test]
+    fn test_integer_division_overflow_is_error() {
+        let result = binary_op_div(Value::Integer(i64::MIN), Value::Integer(-1));
+        assert!(result.is_err());
+    }
+
+    fn eval_derived(source: &str) -> Result<Value, String> {
+        let program = Parser::new(source).parse_program()?;
+        let program = crate::derive::expand_derives(program)?;
+        Runtime::new().eval_program(&program)
+    }
+
+    const POINT_KEY: &str = "#[derive(Eq, Hash)]\nstruct Point { x: Int, y: Int }\n";
+
+    #[test]
+    fn test_struct_as_map_key() {
+        let source = format!(
+            "{}let counts = Map::new()\ncounts.insert(Point {{ x: 1, y: 2 }}, 10)\ncounts.insert(Point {{ x: 1, y: 2 }}, 20)\n\
+             counts.insert(Point {{ x: 3, y: 4 }}, 30)\nlet n = counts.len()\nlet total = n + counts.get(Point {{ x: 1, y: 2 }})\ntotal",
+            POINT_KEY
+        );
+        // Equal structs are the same key, so the second insert replaced the first
+        assert_eq!(eval_derived(&source).unwrap(), Value::Integer(22));
+    }
+
+    #[test]
+    fn test_struct_in_set_and_sorted_keys() {
+        let source = format!(
+            "{}let seen = Set::new()\nseen.insert(Point {{ x: 2, y: 0 }})\nseen.insert(Point {{ x: 1, y: 5 }})\nseen.insert(Point {{ x: 2, y: 0 }})\nseen.values()",
+            POINT_KEY
+        );
+        let values = eval_derived(&source).unwrap();
+        assert_eq!(values.to_string(), "[Point { x: 1, y: 5 }, Point { x: 2, y: 0 }]");
+    }
+
+    #[test]
+    fn test_channels_send_and_receive_typed_values() {
+        let source = "let ch = chan Int\nch <- 1\nch.send(2)\nlet first = <-ch\nfirst * 10 + ch.recv()";
+        assert_eq!(eval(source).unwrap(), Value::Integer(12));
+
+        // Values queued before close are still delivered, then the channel reports it is closed
+        let source = "let names: Channel<String> = chan String\nnames <- \"ada\"\nnames.close()\nlet name = <-names\nname";
+        assert_eq!(eval(source).unwrap(), Value::String("ada".to_string()));
+        let err = eval("let ch = chan Int\nch <- 1\nch.close()\nlet a = <-ch\nlet b = <-ch").unwrap_err();
+        assert!(err.contains("Receive on a closed channel"), "{}", err);
+        let err = eval("let ch = chan Int\nch.close()\nch <- 1").unwrap_err();
+        assert!(err.contains("Send on a closed channel"), "{}", err);
+    }
+
+    #[test]
+    fn test_channel_rejects_values_of_the_wrong_type() {
+        let err = eval("let ch = chan Int\nch.send(\"seven\")").unwrap_err();
+        assert!(err.contains("Channel send: expected Int, found String"), "{}", err);
+        let err = eval("let ch = chan [Int]\nch <- [1, 2.5]").unwrap_err();
+        assert!(err.contains("Channel send: expected [Int], found Array"), "{}", err);
+
+        let err = eval("let ch = chan Int\nlet n = <-ch").unwrap_err();
+        assert!(err.contains("empty channel"), "{}", err);
+    }
+
+    #[test]
+    fn test_select_runs_the_arm_of_the_ready_channel() {
+        let source = "let a = chan Int\nlet b = chan String\nb <- \"hi\"\nselect {\n    n = a.recv() => n * 2,\n    s = b.recv() => s + \"!\",\n}";
+        assert_eq!(eval(source).unwrap(), Value::String("hi!".to_string()));
+
+        // When several are ready, the first arm written wins
+        let source = "let a = chan Int\nlet b = chan Int\na <- 1\nb <- 2\nlet got = select { n = b.recv() => n, n = a.recv() => n }\ngot * 10 + <-a";
+        assert_eq!(eval(source).unwrap(), Value::Integer(21));
+
+        let err = eval("let a = chan Int\nselect { n = a.recv() => n }").unwrap_err();
+        assert!(err.contains("select would block forever"), "{}", err);
+    }
+
+    #[test]
+    fn test_select_default_arm_runs_when_nothing_is_ready() {
+        let source = "let a = chan Int\nselect {\n    n = a.recv() => n,\n    default => -1,\n}";
+        assert_eq!(eval(source).unwrap(), Value::Integer(-1));
+
+        // A closed, drained channel is never ready
+        let source = "let a = chan Int\na.close()\nselect { n = a.recv() => n, default => 0 }";
+        assert_eq!(eval(source).unwrap(), Value::Integer(0));
+    }
+
+    #[test]
+    fn test_select_timeout_arm_runs_after_the_deadline() {
+        let started = std::time::Instant::now();
+        let source = "let a = chan Int\nselect {\n    n = a.recv() => n,\n    timeout(20) => 99,\n}";
+        assert_eq!(eval(source).unwrap(), Value::Integer(99));
+        assert!(started.elapsed() >= std::time::Duration::from_millis(20));
+
+        let source = "let a = chan Int\na <- 5\nselect { n = a.recv() => n, timeout(10000) => 99 }";
+        assert_eq!(eval(source).unwrap(), Value::Integer(5));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_extern_functions_call_into_c() {
+        let source = "extern \"c\" fn c_sqrt(x: Float) -> Float from \"libm\"\nextern \"c\" fn labs(n: Int) -> Int from \"libc\"\nc_sqrt(16) + to_float(labs(-2))";
+        assert_eq!(eval(source).unwrap(), Value::Float(6.0));
+
+        let source = "extern \"c\" fn c_sqrt(x: Float) -> Float from \"libm\"\nc_sqrt";
+        assert_eq!(eval(source).unwrap().to_string(), "<extern \"c\" fn c_sqrt>");
+
+        let err = eval("extern \"c\" fn no_such_function(x: Float) -> Float from \"libm\"\nno_such_function(1.0)").unwrap_err();
+        assert!(err.contains("C library 'libm' has no function 'no_such_function'"), "{}", err);
+    }
+
+    #[test]
+    fn test_unhashable_keys_are_rejected() {
+        let err = eval("let m = Map::new()\nm.insert(1.5, 1)").unwrap_err();
+        assert!(err.contains("floats are not hashable"), "{}", err);
+
+        let err = eval_derived("#[derive(Eq)]\nstruct P { x: Int }\nlet m = Map::new()\nm.insert(P { x: 1 }, 1)").unwrap_err();
+        assert!(err.contains("does not implement Hash"), "{}", err);
+    }
+
+    #[test]
+    fn test_hash_key_ignores_field_storage_order() {
+        use std::collections::hash_map::DefaultHasher;
+
+        let mut a = HashMap::new();
+        a.insert("x".to_string(), Value::Integer(1));
+        a.insert("y".to_string(), Value::Tuple(vec![Value::String("s".to_string()), Value::Boolean(true)]));
+        let mut b = HashMap::new();
+        b.insert("y".to_string(), Value::Tuple(vec![Value::String("s".to_string()), Value::Boolean(true)]));
+        b.insert("x".to_string(), Value::Integer(1));
+
+        let a = HashKey::new(Value::Struct("P".to_string(), a)).unwrap();
+        let b = HashKey::new(Value::Struct("P".to_string(), b)).unwrap();
+        let hash = |key: &HashKey| {
+            let mut hasher = DefaultHasher::new();
+            key.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        assert_eq!(a, b);
+        assert_eq!(hash(&a), hash(&b));
+        assert!(HashKey::new(Value::Integer(1)).unwrap() < HashKey::new(Value::Integer(2)).unwrap());
+    }
+}
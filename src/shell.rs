@@ -13,6 +13,12 @@ use ratatui::{
 };
 use std::io::{self, stdout};
 
+use crate::ast::{Program, Statement, Type};
+use crate::lexer::{Lexer, Token, KEYWORDS};
+use crate::parser::Parser;
+use crate::runtime::{capture_output, Runtime, Value};
+use crate::type_checker::TypeChecker;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum ShellMode {
     Editing,
@@ -64,6 +70,160 @@ impl Default for FileBrowserState {
     }
 }
 
+/// Whether `buffer` reads as finished input, rather than the start of a longer one
+///
+/// Input is unfinished while a `(`, `[` or `{` is still open, a string literal is
+/// unterminated, or the last token is an operator or separator that needs a right
+/// hand side. Extra closing brackets count as finished, so the parser can report them.
+pub fn is_input_complete(buffer: &str) -> bool {
+    let mut lexer = Lexer::new(buffer);
+    let mut depth: i64 = 0;
+    let mut last = Token::Eof;
+    loop {
+        let token = lexer.next_token();
+        match &token {
+            Token::Eof => break,
+            Token::LeftParen | Token::LeftBracket | Token::LeftBrace => depth += 1,
+            Token::RightParen | Token::RightBracket | Token::RightBrace => depth -= 1,
+            Token::String(_) | Token::ByteString(_) => {
+                let text = &buffer[lexer.token_span()];
+                let quote = text.chars().last();
+                if text.len() < 2 || (quote != Some('"') && quote != Some('\'')) {
+                    return false;
+                }
+            },
+            _ => {},
+        }
+        last = token;
+    }
+
+    let needs_more = matches!(
+        last,
+        Token::Plus | Token::Minus | Token::Multiply | Token::Divide | Token::Modulo | Token::Power
+            | Token::Equal | Token::NotEqual | Token::Less | Token::Greater | Token::LessEqual | Token::GreaterEqual
+            | Token::And | Token::Or | Token::Not | Token::Assign | Token::PlusAssign | Token::MinusAssign
+            | Token::PipeForward | Token::PipeBackward | Token::Spaceship | Token::Range | Token::LeftArrow
+            | Token::Comma | Token::Dot | Token::DoubleColon | Token::Arrow | Token::FatArrow
+    );
+    depth <= 0 && !needs_more
+}
+
+/// Splits one line of code into pieces, each with the color it is drawn in
+///
+/// Keywords, string and character literals, numbers and `//` comments get their
+/// own colors; everything else, whitespace included, keeps the terminal's. The
+/// pieces cover the line exactly, so a redraw after an insert or a backspace
+/// replaces every character and leaves nothing stale behind.
+pub fn highlight_line(input: &str) -> Vec<(&str, Color)> {
+    let mut lexer = Lexer::new(input);
+    let mut pieces = Vec::new();
+    let mut end = 0;
+    loop {
+        let token = lexer.next_token();
+        if matches!(token, Token::Eof) {
+            break;
+        }
+        let span = lexer.token_span();
+        push_gap(&mut pieces, &input[end..span.start]);
+        let text = &input[span.clone()];
+        pieces.push((text, token_color(&token, text)));
+        end = span.end;
+    }
+    push_gap(&mut pieces, &input[end..]);
+    pieces
+}
+
+/// Text the lexer skipped between two tokens: whitespace, or a comment to the end of the line
+fn push_gap<'a>(pieces: &mut Vec<(&'a str, Color)>, gap: &'a str) {
+    if gap.is_empty() {
+        return;
+    }
+    match gap.find("//") {
+        Some(start) => {
+            if start > 0 {
+                pieces.push((&gap[..start], Color::Reset));
+            }
+            pieces.push((&gap[start..], Color::DarkGray));
+        },
+        None => pieces.push((gap, Color::Reset)),
+    }
+}
+
+fn token_color(token: &Token, text: &str) -> Color {
+    match token {
+        Token::String(_) | Token::Char(_) | Token::ByteString(_) => Color::Green,
+        Token::Integer(_) | Token::Float(_) => Color::Cyan,
+        Token::Label(_) => Color::Yellow,
+        Token::Error(_) => Color::Red,
+        _ if KEYWORDS.contains(&text) => Color::Magenta,
+        _ => Color::Reset,
+    }
+}
+
+/// Completes the word before the cursor from keywords and the names a runtime has bound
+///
+/// The names include the builtins every runtime starts with (`print`, `len`,
+/// `debug::assert`, ...), which is the standard library as Logos code sees it.
+pub struct Completer {
+    candidates: Vec<String>,
+}
+
+impl Completer {
+    /// Collects candidates from the keywords and everything bound in `runtime`
+    pub fn new(runtime: &Runtime) -> Self {
+        let mut candidates = runtime.env.names();
+        candidates.extend(KEYWORDS.iter().map(|keyword| keyword.to_string()));
+        candidates.sort();
+        candidates.dedup();
+        Completer { candidates }
+    }
+
+    /// Returns the candidates that extend the word ending at byte offset `cursor` of `buffer`
+    pub fn complete(&self, buffer: &str, cursor: usize) -> Vec<String> {
+        let prefix = Self::word_before(buffer, cursor);
+        if prefix.is_empty() {
+            return Vec::new();
+        }
+        self.candidates.iter().filter(|candidate| candidate.starts_with(prefix)).cloned().collect()
+    }
+
+    /// The partial identifier, possibly with `::` paths, that ends at `cursor`
+    pub fn word_before(buffer: &str, cursor: usize) -> &str {
+        let mut cursor = cursor.min(buffer.len());
+        while !buffer.is_char_boundary(cursor) {
+            cursor -= 1;
+        }
+        let before = &buffer[..cursor];
+        let start = before
+            .char_indices()
+            .rev()
+            .take_while(|&(_, ch)| ch.is_alphanumeric() || ch == '_' || ch == ':')
+            .last()
+            .map_or(cursor, |(index, _)| index);
+        &before[start..]
+    }
+}
+
+/// The longest prefix every candidate shares
+fn common_prefix(candidates: &[String]) -> &str {
+    let first = match candidates.first() {
+        Some(first) => first.as_str(),
+        None => return "",
+    };
+    let mut len = first.len();
+    for candidate in &candidates[1..] {
+        len = first
+            .char_indices()
+            .zip(candidate.chars())
+            .take_while(|((_, a), b)| a == b)
+            .map(|((index, ch), _)| index + ch.len_utf8())
+            .last()
+            .unwrap_or(0)
+            .min(len);
+    }
+    &first[..len]
+}
+
 pub struct ShellState {
     pub lines: Vec<String>,
     pub cursor_x: usize,
@@ -76,6 +236,18 @@ pub struct ShellState {
     pub file_browser_state: FileBrowserState,
     pub command_buffer: String,
     pub command_cursor: usize,
+    /// Keeps bindings from one evaluation to the next until `:reset`
+    pub runtime: Runtime,
+    /// The types of what the runtime's bindings hold, for `:type`
+    pub checker: TypeChecker,
+    /// Whether Tab completes names while typing code or commands
+    pub completion: bool,
+    /// Whether the editor colors code as it is typed
+    pub highlight: bool,
+    /// Whether Enter at the command prompt waits for more lines while the input is incomplete
+    pub multiline: bool,
+    /// Lines already entered at the command prompt for input that isn't complete yet
+    pub pending_lines: Vec<String>,
 }
 
 impl ShellState {
@@ -92,6 +264,12 @@ impl ShellState {
             file_browser_state: FileBrowserState::default(),
             command_buffer: String::new(),
             command_cursor: 0,
+            runtime: Runtime::new(),
+            checker: TypeChecker::new(),
+            completion: true,
+            highlight: true,
+            multiline: true,
+            pending_lines: Vec::new(),
         }
     }
 
@@ -175,16 +353,53 @@ impl ShellState {
     }
 
     pub fn execute_input(&mut self) {
-        let input = self.lines.join("\n");
         let code = self.lines.join("\n");
         self.output.push(format!("> Executing code..."));
 
-        // For now, just add a placeholder result
-        // In a real implementation, this would parse and execute the code
-        self.output.push("Code executed successfully".to_string());
+        let results = self.evaluate_logos_code(&code);
+        self.output.extend(results);
         self.status_message = "Code executed".to_string();
     }
 
+    /// Completes the word before the cursor in the editor or the command prompt
+    ///
+    /// A single match is filled in. Several matches are filled in up to the part
+    /// they share and listed in the output pane. Returns false when there is
+    /// nothing to complete here, so the caller can treat Tab as usual.
+    pub fn complete_at_cursor(&mut self) -> bool {
+        let (buffer, cursor) = match self.mode {
+            ShellMode::Insert => match self.lines.get(self.cursor_y) {
+                Some(line) => (line.clone(), self.cursor_x),
+                None => return false,
+            },
+            ShellMode::CommandPrompt => (self.command_buffer.clone(), self.command_cursor),
+            _ => return false,
+        };
+
+        let candidates = Completer::new(&self.runtime).complete(&buffer, cursor);
+        if candidates.is_empty() {
+            return false;
+        }
+        let typed = Completer::word_before(&buffer, cursor).len();
+        let completion = if candidates.len() == 1 { candidates[0].as_str() } else { common_prefix(&candidates) };
+        let insert = completion[typed..].to_string();
+
+        match self.mode {
+            ShellMode::Insert => {
+                self.lines[self.cursor_y].insert_str(self.cursor_x, &insert);
+                self.cursor_x += insert.len();
+            },
+            _ => {
+                self.command_buffer.insert_str(self.command_cursor, &insert);
+                self.command_cursor += insert.len();
+            },
+        }
+        if candidates.len() > 1 {
+            self.output.push(candidates.join("  "));
+        }
+        true
+    }
+
     pub fn enter_command_prompt(&mut self) {
         self.mode = ShellMode::CommandPrompt;
         self.command_buffer.clear();
@@ -196,10 +411,27 @@ impl ShellState {
         self.mode = ShellMode::Editing;
         self.command_buffer.clear();
         self.command_cursor = 0;
+        self.pending_lines.clear();
+    }
+
+    /// The prompt shown before the command buffer: `...` while continuing unfinished input
+    pub fn prompt(&self) -> &'static str {
+        if self.pending_lines.is_empty() { ">" } else { "..." }
     }
 
     pub fn handle_command_in_prompt(&mut self) {
-        let input = self.command_buffer.clone();
+        let mut lines = self.pending_lines.clone();
+        lines.push(self.command_buffer.clone());
+        let input = lines.join("\n");
+        if self.multiline && !input.trim().is_empty() && !is_input_complete(&input) {
+            // Keep the line and wait for the rest at a `...` prompt
+            self.pending_lines = lines;
+            self.command_buffer.clear();
+            self.command_cursor = 0;
+            return;
+        }
+        self.pending_lines.clear();
+
         let parts: Vec<&str> = input.split_whitespace().collect();
 
         if parts.is_empty() {
@@ -232,9 +464,18 @@ impl ShellState {
                 self.output.push("  pwd - Print working directory".to_string());
                 self.output.push("  clear - Clear output".to_string());
                 self.output.push("  help - Show this help".to_string());
+                self.output.push("  :help - Shell commands; anything else is evaluated as Logos code".to_string());
             },
             _ => {
-                self.status_message = format!("Unknown command: {}", parts[0]);
+                let results = self.process_command(&input);
+                if input.trim() == ":clear" {
+                    self.output.clear();
+                } else {
+                    for (index, line) in input.trim().lines().enumerate() {
+                        self.output.push(format!("{} {}", if index == 0 { ">" } else { "..." }, line));
+                    }
+                    self.output.extend(results);
+                }
             }
         }
 
@@ -262,6 +503,10 @@ impl ShellState {
         if self.command_cursor > 0 {
             self.command_buffer.remove(self.command_cursor - 1);
             self.command_cursor -= 1;
+        } else if let Some(previous) = self.pending_lines.pop() {
+            // Backspace at the start of a continuation line joins it to the line before
+            self.command_cursor = previous.len();
+            self.command_buffer.insert_str(0, &previous);
         }
     }
 
@@ -315,6 +560,10 @@ impl ShellState {
                     ":quit - Exit the shell".to_string(),
                     ":eval <expression> - Evaluate an expression".to_string(),
                     ":load <file> - Load a file".to_string(),
+                    ":reset - Forget every variable and function defined so far".to_string(),
+                    ":type <expression> (or :t) - Show an expression's type without running it".to_string(),
+                    ":ast <code> - Show the syntax tree code parses to, without running it".to_string(),
+                    ":tokens <code> - Show the tokens code lexes to, without running it".to_string(),
                 ],
                 ":clear" => {
                     vec!["Screen cleared".to_string()]  // We'll handle clearing in the caller
@@ -323,10 +572,24 @@ impl ShellState {
                     // This would normally trigger an exit, but we'll just return a message
                     vec!["Use Ctrl+C to exit".to_string()]
                 },
+                ":reset" => {
+                    self.runtime = Runtime::new();
+                    self.checker = TypeChecker::new();
+                    vec!["Runtime environment cleared".to_string()]
+                },
                 cmd if cmd.starts_with(":eval ") => {
                     let expr = cmd.strip_prefix(":eval ").unwrap_or("");
                     self.evaluate_expression(expr)
                 },
+                cmd if cmd.starts_with(":type ") || cmd.starts_with(":t ") => {
+                    let expr = cmd.split_once(' ').map_or("", |(_, expr)| expr.trim());
+                    match self.type_of(expr) {
+                        Ok(ty) => vec![format!("{}: {}", expr, ty)],
+                        Err(e) => vec![e],
+                    }
+                },
+                cmd if cmd.starts_with(":ast ") => ast_lines(cmd.strip_prefix(":ast ").unwrap_or("")),
+                cmd if cmd.starts_with(":tokens ") => token_lines(cmd.strip_prefix(":tokens ").unwrap_or("")),
                 cmd if cmd.starts_with(":load ") => {
                     let filename = cmd.strip_prefix(":load ").unwrap_or("");
                     self.load_file(filename)
@@ -340,9 +603,16 @@ impl ShellState {
     }
 
     pub fn evaluate_expression(&mut self, expr: &str) -> Vec<String> {
-        // For now, just return the expression as-is
-        // In a real implementation, this would parse and evaluate the expression
-        vec![format!("Result: {}", expr)]
+        self.evaluate_logos_code(expr)
+    }
+
+    /// Infers the type of `expr` from the bindings made so far, without running it
+    pub fn type_of(&self, expr: &str) -> Result<Type, String> {
+        let program = Parser::new(expr).parse_program().map_err(|e| format!("Parse error: {}", e))?;
+        match program.statements.as_slice() {
+            [Statement::Expression(expr)] => self.checker.clone().type_of(expr).map_err(|e| format!("Type error: {}", e)),
+            _ => Err("Type error: expected a single expression".to_string()),
+        }
     }
 
     pub fn load_file(&mut self, filename: &str) -> Vec<String> {
@@ -356,12 +626,47 @@ impl ShellState {
         }
     }
 
-    pub fn evaluate_logos_code(&self, code: &str) -> Vec<String> {
-        // For now, just return the code as-is since the actual modules may not exist
-        vec![format!("Evaluated: {}", code)]
+    /// Runs `code` in the shell's runtime and returns what it printed, followed by
+    /// its value when the input ends with an expression that produces one
+    pub fn evaluate_logos_code(&mut self, code: &str) -> Vec<String> {
+        let program = match Parser::new(code).parse_program() {
+            Ok(program) => program,
+            Err(e) => return vec![format!("Parse error: {}", e)],
+        };
+        let ends_with_expression = matches!(program.statements.last(), Some(Statement::Expression(_)));
+
+        let runtime = &mut self.runtime;
+        let (result, printed) = capture_output(|| runtime.eval_program(&program));
+        // Keep the types of whatever each statement binds; one that doesn't check binds nothing
+        for statement in &program.statements {
+            let _ = self.checker.check_program(&Program::new(vec![statement.clone()]));
+        }
+        let mut lines: Vec<String> = printed.lines().map(str::to_string).collect();
+        match result {
+            Ok(Value::Unit) => {},
+            Ok(value) if ends_with_expression => lines.push(value.to_string()),
+            Ok(_) => {},
+            Err(e) => lines.push(format!("Runtime error: {}", e)),
+        }
+        lines
+    }
+}
+
+/// The syntax tree of `code`, one statement after another, for `:ast`
+pub fn ast_lines(code: &str) -> Vec<String> {
+    match Parser::new(code).parse_program() {
+        Ok(program) => program.statements.iter()
+            .flat_map(|statement| format!("{:#?}", statement).lines().map(str::to_string).collect::<Vec<_>>())
+            .collect(),
+        Err(e) => vec![format!("Parse error: {}", e)],
     }
 }
 
+/// The token dump of `code`, one token per line, for `:tokens`
+pub fn token_lines(code: &str) -> Vec<String> {
+    crate::debug_tokens::dump(code).lines().map(String::from).collect()
+}
+
 /// Run the Logos shell
 pub fn run_shell() -> Result<(), Box<dyn std::error::Error>> {
     // Setup terminal
@@ -477,6 +782,7 @@ pub fn run_shell() -> Result<(), Box<dyn std::error::Error>> {
                                 state.move_cursor_down();
                             }
                         }
+                        KeyCode::Tab if state.completion && state.complete_at_cursor() => {}
                         KeyCode::Tab => {
                             // Switch between menu and editor modes
                             state.mode = match state.mode {
@@ -761,7 +1067,11 @@ fn ui(f: &mut Frame, state: &mut ShellState) {
             ));
             
             // Add line content
-            line_spans.push(Span::raw(line));
+            if state.highlight {
+                line_spans.extend(highlight_line(line).into_iter().map(|(text, color)| Span::styled(text, Style::default().fg(color))));
+            } else {
+                line_spans.push(Span::raw(line));
+            }
             
             spans.push(Line::from(line_spans));
         }
@@ -783,9 +1093,14 @@ fn ui(f: &mut Frame, state: &mut ShellState) {
             _ => Span::styled("NORMAL", Style::default().bg(Color::Gray).fg(Color::White)),
         };
 
+        let status_line = if state.mode == ShellMode::CommandPrompt {
+            format!("  {} {}", state.prompt(), state.command_buffer)
+        } else {
+            format!("  {}", state.status_message)
+        };
         let status_bar = Paragraph::new(Line::from(vec![
             status_text,
-            Span::raw(format!("  {}", state.status_message)),
+            Span::raw(status_line),
         ]))
         .block(Block::default().borders(Borders::ALL));
         f.render_widget(status_bar, editor_chunks[1]);
@@ -951,4 +1266,159 @@ impl ShellState {
             }
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bindings_persist_between_inputs() {
+        let mut state = ShellState::new();
+        assert!(state.process_command("let x = 5").is_empty());
+        assert_eq!(state.process_command("x + 1"), vec!["6"]);
+        assert!(state.process_command("fn double(n: Int) -> Int { n * 2 }").is_empty());
+        assert_eq!(state.process_command("print(\"twice\")\ndouble(x)"), vec!["twice", "10"]);
+    }
+
+    #[test]
+    fn test_reset_clears_the_runtime_environment() {
+        let mut state = ShellState::new();
+        state.process_command("let x = 5");
+        assert_eq!(state.process_command(":reset"), vec!["Runtime environment cleared"]);
+
+        let after_reset = state.process_command("x");
+        assert_eq!(after_reset.len(), 1);
+        assert!(after_reset[0].starts_with("Runtime error:"), "{:?}", after_reset);
+    }
+
+    #[test]
+    fn test_type_of_uses_the_session_bindings() {
+        let mut state = ShellState::new();
+        state.process_command("let xs = [1, 2, 3]\nlet scale = 2.5");
+        assert_eq!(state.type_of("xs"), Ok(Type::Array(Box::new(Type::Int))));
+        assert_eq!(state.type_of("len(xs) > 1"), Ok(Type::Bool));
+        assert_eq!(state.type_of("scale * 2.0"), Ok(Type::Float));
+        assert_eq!(state.process_command(":t (xs, \"a\")"), vec!["(xs, \"a\"): ([Int], String)"]);
+
+        assert_eq!(state.process_command(":type xs + \"a\"").len(), 1);
+        assert!(state.type_of("xs + \"a\"").unwrap_err().starts_with("Type error: "));
+        assert!(state.type_of("(1 +").unwrap_err().starts_with("Parse error: "));
+        assert_eq!(state.type_of("missing"), Err("Type error: Undefined variable: missing".to_string()));
+
+        state.process_command(":reset");
+        assert!(state.type_of("xs").is_err());
+    }
+
+    #[test]
+    fn test_ast_and_tokens_show_input_without_running_it() {
+        let mut state = ShellState::new();
+        assert_eq!(state.process_command(":tokens print(x + 1)"), vec![
+            "1:1 0..5 Identifier \"print\"", "1:6 5..6 LeftParen \"(\"", "1:7 6..7 Identifier \"x\"",
+            "1:9 8..9 Plus \"+\"", "1:11 10..11 Integer \"1\"", "1:12 11..12 RightParen \")\"", "1:13 12..12 Eof \"\"",
+        ]);
+        assert_eq!(state.process_command(":ast -x").join("\n"), "Expression(\n    UnaryOp(\n        Neg,\n        Identifier(\n            \"x\",\n        ),\n    ),\n)");
+        assert!(state.process_command(":ast let = 1")[0].starts_with("Parse error: "));
+
+        // Nothing is run, so nothing is bound
+        state.process_command(":ast let y = 1");
+        assert!(state.runtime.env.get("y").is_none());
+    }
+
+    #[test]
+    fn test_is_input_complete() {
+        for complete in ["", "let x = 5", "fn f() { 1 }", "print(\"a { b\")", "x)", "let s = 'c'"] {
+            assert!(is_input_complete(complete), "{:?} should be complete", complete);
+        }
+        for incomplete in ["fn f() {", "print(1,", "[1, 2", "let total = 1 +", "x |>", "print(\"unfinished", "if a { b } else {\n  c"] {
+            assert!(!is_input_complete(incomplete), "{:?} should be incomplete", incomplete);
+        }
+    }
+
+    #[test]
+    fn test_prompt_collects_lines_until_input_is_complete() {
+        let mut state = ShellState::new();
+        state.mode = ShellMode::CommandPrompt;
+        for ch in "fn triple(n: Int) -> Int {".chars() {
+            state.add_char_to_command(ch);
+        }
+        state.handle_command_in_prompt();
+        assert_eq!(state.prompt(), "...");
+        assert_eq!(state.mode, ShellMode::CommandPrompt);
+
+        // Backspace at the start of a continuation line goes back to the previous line
+        state.remove_char_from_command();
+        assert_eq!(state.command_buffer, "fn triple(n: Int) -> Int {");
+        assert_eq!(state.prompt(), ">");
+        state.handle_command_in_prompt();
+
+        for line in ["n * 3", "}"] {
+            state.command_buffer = line.to_string();
+            state.handle_command_in_prompt();
+        }
+        assert_eq!(state.prompt(), ">");
+        assert_eq!(state.output.last().map(String::as_str), Some("... }"));
+        assert_eq!(state.process_command("triple(4)"), vec!["12"]);
+    }
+
+    #[test]
+    fn test_highlight_line_colors_each_kind_of_token() {
+        let line = "let name = \"Ada\"  // greet 2 of them";
+        assert_eq!(
+            highlight_line(line),
+            vec![
+                ("let", Color::Magenta),
+                (" ", Color::Reset),
+                ("name", Color::Reset),
+                (" ", Color::Reset),
+                ("=", Color::Reset),
+                (" ", Color::Reset),
+                ("\"Ada\"", Color::Green),
+                ("  ", Color::Reset),
+                ("// greet 2 of them", Color::DarkGray),
+            ]
+        );
+        assert_eq!(highlight_line("x + 3.5")[4], ("3.5", Color::Cyan));
+    }
+
+    #[test]
+    fn test_highlighted_pieces_cover_the_line_while_editing() {
+        // Typing, then backspacing out of a string literal
+        for line in ["print(\"héllo", "print(\"héllo\")", "print(\"h", "print(", "", "  if x { 'c' }"] {
+            let pieces = highlight_line(line);
+            assert_eq!(pieces.iter().map(|(text, _)| *text).collect::<String>(), line);
+        }
+    }
+
+    #[test]
+    fn test_completer_offers_bindings_builtins_and_keywords() {
+        let mut state = ShellState::new();
+        state.process_command("let total_count = 3");
+        let completer = Completer::new(&state.runtime);
+
+        assert_eq!(completer.complete("print(total_c", 13), vec!["total_count"]);
+        assert_eq!(completer.complete("le", 2), vec!["len", "let"]);
+        assert_eq!(completer.complete("debug::as", 9), vec!["debug::assert"]);
+        assert!(completer.complete("x + ", 4).is_empty());
+        // Only the text before the cursor counts
+        assert_eq!(completer.complete("whi + 1", 3), vec!["while"]);
+    }
+
+    #[test]
+    fn test_tab_fills_in_the_shared_prefix_and_lists_choices() {
+        let mut state = ShellState::new();
+        state.process_command("let counter = 1");
+        state.process_command("let country = 2");
+        state.mode = ShellMode::CommandPrompt;
+        state.command_buffer = "cou".to_string();
+        state.command_cursor = 3;
+
+        assert!(state.complete_at_cursor());
+        assert_eq!(state.command_buffer, "count");
+        assert_eq!(state.output.last().map(String::as_str), Some("counter  country"));
+
+        state.command_buffer.push('e');
+        state.command_cursor = 6;
+        assert!(state.complete_at_cursor());
+        assert_eq!(state.command_buffer, "counter");
+    }
+}
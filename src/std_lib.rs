@@ -12,8 +12,12 @@ pub mod collections {
     use std::collections::{HashMap, HashSet, VecDeque};
 
     /// Creates a new vector with the given elements
-    pub fn vec_new<T>(elements: Vec<T>) -> Vec<T> {
-        elements
+    ///
+    /// Fails when there are more than `max_elements` of them, the same limit the
+    /// runtime applies to array literals.
+    pub fn vec_new<T>(elements: Vec<T>, max_elements: usize) -> Result<Vec<T>, String> {
+        check_allocation(elements.len() as u128, max_elements)?;
+        Ok(elements)
     }
 
     /// Creates a new hash map with the given key-value pairs
@@ -32,6 +36,18 @@ pub mod collections {
         elements.into_iter().collect()
     }
 
+    /// Checks a requested allocation size against a limit, in elements
+    ///
+    /// Sizes are computed in `u128` by callers, so a request can't overflow before
+    /// it's compared against the limit.
+    pub fn check_allocation(elements: u128, max_elements: usize) -> Result<usize, String> {
+        if elements > max_elements as u128 {
+            Err(format!("allocation of {} elements exceeds limit of {}", elements, max_elements))
+        } else {
+            Ok(elements as usize)
+        }
+    }
+
     /// Creates a new queue with the given elements
     pub fn queue_new<T>(elements: Vec<T>) -> VecDeque<T> {
         elements.into()
@@ -177,20 +193,73 @@ pub mod string_utils {
 }
 
 /// File I/O operations
+///
+/// Relative paths don't resolve against the process's working directory but against
+/// a base: the directory given with `--cwd` if there was one, otherwise the directory
+/// of the script being run. So `read_file("data.txt")` finds the file next to the
+/// script wherever `logos run` was started from. Code run without a script, such as
+/// the REPL, resolves against the working directory as usual.
 pub mod file_io {
     use std::fs::File;
     use std::io::{self, BufRead, BufReader, Write};
-    use std::path::Path;
+    use std::path::{Path, PathBuf};
+    use std::sync::RwLock;
+
+    /// The directories relative paths may resolve against
+    struct Roots {
+        base: Option<PathBuf>,
+        script: Option<PathBuf>,
+    }
+
+    static ROOTS: RwLock<Roots> = RwLock::new(Roots { base: None, script: None });
+
+    /// Sets up path resolution for running `script`, with `cwd` overriding its directory;
+    /// passing neither goes back to resolving against the working directory
+    ///
+    /// Both are made absolute against the current working directory first, so later
+    /// changes to it don't move them.
+    pub fn use_script(script: Option<&Path>, cwd: Option<&Path>) -> Result<(), String> {
+        let absolute = |path: &Path| std::path::absolute(path)
+            .map_err(|e| format!("Could not resolve '{}': {}", path.display(), e));
+        let script_dir = match script {
+            Some(script) => absolute(script)?.parent().map(Path::to_path_buf),
+            None => None,
+        };
+        let base = cwd.map(absolute).transpose()?;
+        if let Some(base) = &base {
+            if !base.is_dir() {
+                return Err(format!("--cwd '{}' is not a directory", base.display()));
+            }
+        }
+        let mut roots = ROOTS.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+        *roots = Roots { base, script: script_dir };
+        Ok(())
+    }
+
+    /// The directory of the script being run, if code is running from one
+    pub fn script_dir() -> Option<PathBuf> {
+        ROOTS.read().unwrap_or_else(|poisoned| poisoned.into_inner()).script.clone()
+    }
+
+    /// Where a path a program names actually is; absolute paths are left alone
+    pub fn resolve(path: &str) -> PathBuf {
+        let path = Path::new(path);
+        let roots = ROOTS.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        match roots.base.as_ref().or(roots.script.as_ref()) {
+            Some(root) if path.is_relative() => root.join(path),
+            _ => path.to_path_buf(),
+        }
+    }
     
     /// Reads the entire contents of a file as a string
     pub fn read_file(path: &str) -> Result<String, String> {
-        std::fs::read_to_string(path)
+        std::fs::read_to_string(resolve(path))
             .map_err(|e| format!("Failed to read file '{}': {}", path, e))
     }
     
     /// Writes a string to a file
     pub fn write_file(path: &str, content: &str) -> Result<(), String> {
-        std::fs::write(path, content)
+        std::fs::write(resolve(path), content)
             .map_err(|e| format!("Failed to write to file '{}': {}", path, e))
     }
     
@@ -199,7 +268,7 @@ pub mod file_io {
         let mut file = std::fs::OpenOptions::new()
             .append(true)
             .create(true)
-            .open(path)
+            .open(resolve(path))
             .map_err(|e| format!("Failed to open file '{}': {}", path, e))?;
         
         file.write_all(content.as_bytes())
@@ -208,22 +277,22 @@ pub mod file_io {
     
     /// Checks if a file exists
     pub fn file_exists(path: &str) -> bool {
-        Path::new(path).exists()
+        resolve(path).exists()
     }
     
     /// Checks if a path is a directory
     pub fn is_directory(path: &str) -> bool {
-        Path::new(path).is_dir()
+        resolve(path).is_dir()
     }
     
     /// Checks if a path is a file
     pub fn is_file(path: &str) -> bool {
-        Path::new(path).is_file()
+        resolve(path).is_file()
     }
     
     /// Reads lines from a file
     pub fn read_lines(path: &str) -> Result<Vec<String>, String> {
-        let file = File::open(path)
+        let file = File::open(resolve(path))
             .map_err(|e| format!("Failed to open file '{}': {}", path, e))?;
         let reader = BufReader::new(file);
         
@@ -237,19 +306,19 @@ pub mod file_io {
     
     /// Creates a directory and all its parent directories
     pub fn create_dir(path: &str) -> Result<(), String> {
-        std::fs::create_dir_all(path)
+        std::fs::create_dir_all(resolve(path))
             .map_err(|e| format!("Failed to create directory '{}': {}", path, e))
     }
     
     /// Removes a file
     pub fn remove_file(path: &str) -> Result<(), String> {
-        std::fs::remove_file(path)
+        std::fs::remove_file(resolve(path))
             .map_err(|e| format!("Failed to remove file '{}': {}", path, e))
     }
     
     /// Copies a file from source to destination
     pub fn copy_file(src: &str, dst: &str) -> Result<(), String> {
-        std::fs::copy(src, dst)
+        std::fs::copy(resolve(src), resolve(dst))
             .map_err(|e| format!("Failed to copy file from '{}' to '{}': {}", src, dst, e))
             .map(|_| ())
     }
@@ -410,6 +479,57 @@ pub mod concurrency {
     pub fn num_cpus() -> usize {
         num_cpus::get()
     }
+
+    /// Applies `f` to every item on up to `num_threads` threads (default: one per
+    /// core), returning the results in input order
+    ///
+    /// The items are split into one contiguous run per thread. `f` only gets shared
+    /// access to what it captures, so workers can't race on state. If any worker
+    /// panics the whole map fails with the panic message instead of unwinding into
+    /// the caller.
+    pub fn parallel_map<T, R, F>(items: Vec<T>, f: F, num_threads: Option<usize>) -> Result<Vec<R>, String>
+    where
+        T: Send,
+        R: Send,
+        F: Fn(T) -> R + Sync,
+    {
+        let threads = num_threads.unwrap_or_else(num_cpus).clamp(1, items.len().max(1));
+        let chunk_size = items.len().div_ceil(threads).max(1);
+
+        let mut chunks: Vec<Vec<T>> = Vec::with_capacity(threads);
+        let mut items = items.into_iter().peekable();
+        while items.peek().is_some() {
+            chunks.push(items.by_ref().take(chunk_size).collect());
+        }
+
+        let f = &f;
+        thread::scope(|scope| {
+            let workers: Vec<_> = chunks.into_iter()
+                .map(|chunk| scope.spawn(move || chunk.into_iter().map(f).collect::<Vec<R>>()))
+                .collect();
+
+            // Every worker is joined before returning; one left unjoined would make the
+            // scope re-raise its panic. The first failure is the one reported.
+            let mut results = Vec::new();
+            let mut failure = None;
+            for (index, worker) in workers.into_iter().enumerate() {
+                match worker.join() {
+                    Ok(chunk) => results.extend(chunk),
+                    Err(payload) if failure.is_none() => {
+                        let message = payload.downcast_ref::<&str>().map(|s| s.to_string())
+                            .or_else(|| payload.downcast_ref::<String>().cloned())
+                            .unwrap_or_else(|| "unknown panic".to_string());
+                        failure = Some(format!("parallel_map worker {} panicked: {}", index, message));
+                    },
+                    Err(_) => {},
+                }
+            }
+            match failure {
+                Some(message) => Err(message),
+                None => Ok(results),
+            }
+        })
+    }
 }
 
 /// Utility functions for debugging and development
@@ -546,6 +666,47 @@ pub mod time {
     }
 }
 
+/// Command-line arguments and environment variables of the running program
+pub mod env {
+    use std::collections::HashMap;
+    use std::sync::RwLock;
+
+    /// What followed `--` on the `logos run` command line
+    static ARGS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+    /// Sets the arguments the program sees; `logos run` passes everything after `--`
+    pub fn set_args(args: Vec<String>) {
+        *ARGS.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = args;
+    }
+
+    /// The program's arguments, without the script's own path
+    pub fn args() -> Vec<String> {
+        ARGS.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    /// The `n`th argument (0-based), if there are that many
+    pub fn arg(n: usize) -> Option<String> {
+        ARGS.read().unwrap_or_else(|poisoned| poisoned.into_inner()).get(n).cloned()
+    }
+
+    /// Every environment variable whose name and value are valid Unicode
+    pub fn vars() -> HashMap<String, String> {
+        std::env::vars_os()
+            .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+            .collect()
+    }
+
+    /// Gets an environment variable
+    pub fn get_env_var(name: &str) -> Option<String> {
+        std::env::var(name).ok()
+    }
+
+    /// Sets an environment variable
+    pub fn set_env_var(name: &str, value: &str) {
+        std::env::set_var(name, value);
+    }
+}
+
 /// Process and system utilities
 pub mod system {
     use std::process::{Command, Stdio};
@@ -567,16 +728,6 @@ pub mod system {
         }
     }
     
-    /// Gets an environment variable
-    pub fn get_env_var(name: &str) -> Option<String> {
-        std::env::var(name).ok()
-    }
-    
-    /// Sets an environment variable
-    pub fn set_env_var(name: &str, value: &str) {
-        std::env::set_var(name, value);
-    }
-    
     /// Gets the current working directory
     pub fn current_dir() -> Result<String, String> {
         std::env::current_dir()
@@ -605,7 +756,43 @@ pub use debug::*;
 pub use error_handling::*;
 pub use memory::*;
 pub use time::*;
+pub use env::*;
 pub use system::*;
 
 #[cfg(feature = "networking")]
-pub use net::*;
\ No newline at end of file
+pub use net::*;
+
+#[cfg(test)]
+mod tests {
+    use super::collections::vec_new;
+    use super::concurrency::parallel_map;
+
+    #[test]
+    fn test_parallel_map_keeps_input_order() {
+        let squares = parallel_map((0..100).collect(), |n: i64| n * n, Some(4)).unwrap();
+        assert_eq!(squares, (0..100).map(|n| n * n).collect::<Vec<_>>());
+
+        // More threads than items, and no items at all
+        assert_eq!(parallel_map(vec![1, 2], |n: i32| n + 1, Some(8)).unwrap(), vec![2, 3]);
+        assert_eq!(parallel_map(Vec::<i32>::new(), |n| n, None).unwrap(), Vec::<i32>::new());
+    }
+
+    #[test]
+    fn test_parallel_map_reports_a_worker_panic() {
+        let err = parallel_map((0..10).collect(), |n: i32| if n == 7 { panic!("bad item {}", n) } else { n }, Some(2)).unwrap_err();
+        assert_eq!(err, "parallel_map worker 1 panicked: bad item 7");
+    }
+
+    #[test]
+    fn test_parallel_map_reports_the_first_of_several_panics() {
+        // Every chunk but the second panics; the error names the first, and the call returns
+        let err = parallel_map((0..8).collect(), |n: i32| if !(2..=3).contains(&n) { panic!("bad item {}", n) } else { n }, Some(4)).unwrap_err();
+        assert_eq!(err, "parallel_map worker 0 panicked: bad item 0");
+    }
+
+    #[test]
+    fn test_vec_new_respects_allocation_limit() {
+        assert_eq!(vec_new(vec![1, 2, 3], 3).unwrap(), vec![1, 2, 3]);
+        assert_eq!(vec_new(vec![0; 4], 3).unwrap_err(), "allocation of 4 elements exceeds limit of 3");
+    }
+}
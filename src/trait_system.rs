@@ -4,6 +4,9 @@
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 use crate::ast::*;
+use crate::additional_core_features::trait_system_enhancements::{
+    EnhancedImplDef, EnhancedTraitDef, EnhancedTraitResolver, TypeParam,
+};
 
 /// Represents a trait definition with methods and associated types
 #[derive(Debug, Clone)]
@@ -218,6 +221,8 @@ impl TraitSolver {
             }
         }
 
+        // The implementations are keyed by a HashMap; sort so callers see a stable order
+        implemented.sort();
         implemented
     }
 
@@ -277,14 +282,22 @@ pub fn validate_trait_impl(impl_def: &ImplDef, trait_resolver: &TraitResolver) -
     let trait_data = trait_resolver.get_trait(&impl_def.trait_name)
         .ok_or_else(|| format!("Trait '{}' not found", impl_def.trait_name))?;
 
-    // Check that all required methods are implemented
-    for required_method in &trait_data.required_methods {
-        if !impl_def.methods.iter().any(|m| m.name == required_method.name) {
-            return Err(format!(
-                "Missing implementation for required method '{}' in trait '{}'",
-                required_method.name, impl_def.trait_name
-            ));
-        }
+    // Check that all required methods are implemented, reporting every missing one by name
+    let mut missing: Vec<&str> = trait_data.required_methods.iter()
+        .filter(|required| !impl_def.methods.iter().any(|m| m.name == required.name))
+        .map(|required| required.name.as_str())
+        .collect();
+    missing.sort_unstable();
+    match missing.as_slice() {
+        [] => {},
+        [method] => return Err(format!(
+            "Missing implementation for required method '{}' in trait '{}'",
+            method, impl_def.trait_name
+        )),
+        methods => return Err(format!(
+            "Missing implementations for required methods {} in trait '{}'",
+            methods.iter().map(|m| format!("'{}'", m)).collect::<Vec<_>>().join(", "), impl_def.trait_name
+        )),
     }
 
     // Check that implemented methods match the signature in the trait
@@ -308,6 +321,99 @@ pub fn validate_trait_impl(impl_def: &ImplDef, trait_resolver: &TraitResolver) -
     Ok(())
 }
 
+/// Mirrors everything registered with a `TraitResolver` into an `EnhancedTraitResolver`
+///
+/// Trait methods declared with a body are defaults, so they land in `provided_methods`;
+/// bodiless signatures stay required.
+impl From<&TraitResolver> for EnhancedTraitResolver {
+    fn from(resolver: &TraitResolver) -> Self {
+        let mut enhanced = EnhancedTraitResolver::new();
+        for (name, data) in &resolver.traits {
+            let (provided_methods, required_methods) = data.required_methods.iter()
+                .chain(&data.provided_methods)
+                .cloned()
+                .partition(|method| !method.body.is_empty());
+            enhanced.traits.insert(name.clone(), EnhancedTraitDef {
+                name: name.clone(),
+                type_params: data.type_params.iter()
+                    .map(|param| TypeParam { name: param.clone(), bounds: Vec::new() })
+                    .collect(),
+                required_methods,
+                provided_methods,
+                associated_types: data.associated_types.clone(),
+                super_traits: data.super_traits.clone(),
+                trait_constraints: Vec::new(),
+            });
+        }
+        for (trait_name, implementations) in &resolver.implementations {
+            let converted = implementations.iter()
+                .map(|imp| EnhancedImplDef {
+                    trait_name: imp.trait_name.clone(),
+                    for_type: imp.for_type.clone(),
+                    type_params: imp.type_params.clone(),
+                    methods: imp.methods.clone(),
+                    associated_types: imp.associated_types.clone(),
+                    trait_constraints: Vec::new(),
+                })
+                .collect();
+            enhanced.implementations.insert(trait_name.clone(), converted);
+        }
+        enhanced
+    }
+}
+
+/// The name trait implementations use for a receiver of this type
+fn receiver_type_name(receiver_ty: &Type) -> String {
+    match receiver_ty {
+        Type::Linear(inner) => receiver_type_name(inner),
+        other => other.to_string(),
+    }
+}
+
+/// Every trait that supplies `method` for the receiver's type, sorted by trait name
+///
+/// An implementation's own method is preferred over the default its trait provides.
+pub fn method_candidates<'a>(
+    receiver_ty: &Type,
+    method: &str,
+    resolver: &'a EnhancedTraitResolver,
+) -> Vec<(&'a str, &'a FunctionDef)> {
+    let type_name = receiver_type_name(receiver_ty);
+    let mut candidates: Vec<(&str, &FunctionDef)> = resolver.implementations.iter()
+        .filter_map(|(trait_name, implementations)| {
+            let implementation = implementations.iter().find(|imp| imp.for_type == type_name)?;
+            implementation.methods.iter()
+                .find(|m| m.name == method)
+                .or_else(|| resolver.traits.get(trait_name)?.provided_methods.iter().find(|m| m.name == method))
+                .map(|def| (trait_name.as_str(), def))
+        })
+        .collect();
+    candidates.sort_by_key(|(trait_name, _)| *trait_name);
+    candidates
+}
+
+/// Resolves `receiver.method(...)` to the one trait method it calls
+///
+/// Fails when no implemented trait has the method, or when several do and the call
+/// can't pick between them.
+pub fn resolve_method_call<'a>(
+    receiver_ty: &Type,
+    method: &str,
+    resolver: &'a EnhancedTraitResolver,
+) -> Result<&'a FunctionDef, String> {
+    let candidates = method_candidates(receiver_ty, method, resolver);
+    match candidates.as_slice() {
+        [] => Err(format!("No method '{}' found for type '{}'", method, receiver_type_name(receiver_ty))),
+        [(_, def)] => Ok(def),
+        several => Err(format!(
+            "Method '{}' on type '{}' is ambiguous: it is provided by traits {}",
+            method,
+            receiver_type_name(receiver_ty),
+            several.iter().map(|(trait_name, _)| format!("'{}'", trait_name)).collect::<Vec<_>>().join(", ")
+        )),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -2,33 +2,66 @@
 // This module performs static type checking on the AST to ensure type safety.
 
 use crate::ast::*;
-use crate::trait_system::{TraitResolver, validate_trait_impl};
+use crate::additional_core_features::type_system::{LinearTypeSystem, ResourceStatus, Unifier};
+use crate::monomorphize::substitute;
+use crate::additional_core_features::trait_system_enhancements::EnhancedTraitResolver;
+use crate::trait_system::{method_candidates, resolve_method_call, TraitResolver, validate_trait_impl};
 use crate::effects::{Effect, EffectSet};
-use std::collections::HashMap;
-
-/// Type environment for tracking variable types during type checking
+use crate::interner::{Interner, Symbol};
+use crate::limits::CompileLimits;
+use crate::macros::ExpandedStatement;
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+/// Type environment for tracking variable types during type checking; names are
+/// interned, so walking the scope chain compares symbols rather than strings
 #[derive(Debug, Clone)]
 pub struct TypeEnv {
-    types: HashMap<String, Type>,
+    types: HashMap<Symbol, Type>,
+    interner: Rc<RefCell<Interner>>,  // Shared with the parent; a root environment starts a new one
     parent: Option<Box<TypeEnv>>,
 }
 
 impl TypeEnv {
     /// Creates a new type environment with optional parent
     pub fn new(parent: Option<TypeEnv>) -> Self {
+        let interner = match &parent {
+            Some(parent) => Rc::clone(&parent.interner),
+            None => Rc::default(),
+        };
         TypeEnv {
             types: HashMap::new(),
+            interner,
             parent: parent.map(Box::new),
         }
     }
 
+    /// The symbol for a name in this environment's interner, adding it if it's new
+    pub fn intern(&self, name: &str) -> Symbol {
+        self.interner.borrow_mut().intern(name)
+    }
+
+    /// The name a symbol from this environment's interner stands for
+    pub fn name(&self, symbol: Symbol) -> String {
+        self.interner.borrow().resolve(symbol).to_string()
+    }
+
     /// Gets the type of a variable from the environment
     pub fn get_type(&self, name: &str) -> Option<Type> {
-        match self.types.get(name) {
+        // A name that was never interned can't be bound anywhere
+        let symbol = self.interner.borrow().get(name)?;
+        self.get_symbol_type(symbol)
+    }
+
+    fn get_symbol_type(&self, symbol: Symbol) -> Option<Type> {
+        match self.types.get(&symbol) {
             Some(ty) => Some(ty.clone()),
             None => {
                 if let Some(ref parent) = self.parent {
-                    parent.get_type(name)
+                    parent.get_symbol_type(symbol)
                 } else {
                     None
                 }
@@ -38,15 +71,24 @@ impl TypeEnv {
 
     /// Sets the type of a variable in the environment
     pub fn set_type(&mut self, name: String, ty: Type) {
-        self.types.insert(name, ty);
+        let symbol = self.intern(&name);
+        self.types.insert(symbol, ty);
     }
 
     /// Checks if a variable exists in the environment
     pub fn contains(&self, name: &str) -> bool {
-        if self.types.contains_key(name) {
+        let symbol = self.interner.borrow().get(name);
+        match symbol {
+            Some(symbol) => self.contains_symbol(symbol),
+            None => false,
+        }
+    }
+
+    fn contains_symbol(&self, symbol: Symbol) -> bool {
+        if self.types.contains_key(&symbol) {
             true
         } else if let Some(ref parent) = self.parent {
-            parent.contains(name)
+            parent.contains_symbol(symbol)
         } else {
             false
         }
@@ -58,11 +100,21 @@ impl TypeEnv {
 pub struct TypeChecker {
     env: TypeEnv,
     /// Linear resource tracking (resource -> usage count)
-    linear_resources: HashMap<String, u32>,
+    linear_resources: HashMap<Symbol, u32>,
     /// Trait resolver for handling trait-related type checking
     trait_resolver: TraitResolver,
+    /// Variant names of each enum declared so far, for checking that matches cover them
+    enums: HashMap<String, Vec<String>>,
     /// Effect tracking for algebraic effects
     effects: EffectSet,
+    /// Return type of the enclosing function, which `?` propagates into; None outside functions
+    return_type: Option<Type>,
+    /// Labels of the loops enclosing the code being checked, innermost last
+    loop_labels: Vec<Label>,
+    /// Bounds on how deep alias expansion may recurse
+    limits: CompileLimits,
+    /// Where `infer_program` collects expression types; shared with every nested checker
+    recording: Option<Rc<RefCell<Recording>>>,
 }
 
 impl TypeChecker {
@@ -76,15 +128,41 @@ impl TypeChecker {
         env.set_type("Bool".to_string(), Type::Bool);
         env.set_type("String".to_string(), Type::String);
         env.set_type("Unit".to_string(), Type::Unit);
+        env.set_type("None".to_string(), Type::Option(Box::new(Type::Infer)));
+
+        // Register the built-in traits that #[derive(...)] generates impls for
+        let mut trait_resolver = TraitResolver::new();
+        for name in crate::derive::DERIVABLE_TRAITS {
+            let builtin = TraitDef {
+                name: name.to_string(),
+                type_params: Vec::new(),
+                methods: Vec::new(),
+                associated_types: Vec::new(),
+                super_traits: Vec::new(),
+                attributes: Vec::new(),
+                is_public: true,
+            };
+            trait_resolver.register_trait(builtin).expect("built-in traits are registered once");
+        }
 
         TypeChecker {
             env,
             linear_resources: HashMap::new(),
-            trait_resolver: TraitResolver::new(),
+            trait_resolver,
+            enums: HashMap::new(),
             effects: EffectSet::new(),
+            return_type: None,
+            loop_labels: Vec::new(),
+            limits: CompileLimits::default(),
+            recording: None,
         }
     }
 
+    /// Creates a type checker that enforces the given compile-time limits
+    pub fn with_limits(limits: CompileLimits) -> Self {
+        TypeChecker { limits, ..TypeChecker::new() }
+    }
+
     /// Checks if a type is a linear type
     fn is_linear_type(&self, ty: &Type) -> bool {
         matches!(ty, Type::Linear(_))
@@ -95,7 +173,7 @@ impl TypeChecker {
         // Check if the variable has a linear type
         if let Some(var_type) = self.env.get_type(var_name) {
             if self.is_linear_type(&var_type) {
-                let count = self.linear_resources.entry(var_name.to_string()).or_insert(0);
+                let count = self.linear_resources.entry(self.env.intern(var_name)).or_insert(0);
                 *count += 1;
 
                 // Linear types should be used exactly once
@@ -111,9 +189,13 @@ impl TypeChecker {
     fn validate_linear_usage(&self) -> Result<(), String> {
         for (resource, count) in &self.linear_resources {
             if *count == 0 {
-                return Err(format!("Linear resource '{}' was not used", resource));
+                return Err(format!("Linear resource '{}' was not used", self.env.name(*resource)));
             } else if *count > 1 {
-                return Err(format!("Linear resource '{}' was used {} times (should be exactly once)", resource, count));
+                return Err(format!(
+                    "Linear resource '{}' was used {} times (should be exactly once)",
+                    self.env.name(*resource),
+                    count
+                ));
             }
         }
         Ok(())
@@ -126,7 +208,8 @@ impl TypeChecker {
             if self.is_linear_type(&var_type) {
                 // Mark the resource as moved by setting its count to a special value
                 // In a more sophisticated system, we'd track ownership differently
-                self.linear_resources.insert(var_name.to_string(), 2); // Mark as moved (more than once to trigger error)
+                let symbol = self.env.intern(var_name);
+                self.linear_resources.insert(symbol, 2); // Mark as moved (more than once to trigger error)
 
                 Ok(())
             } else {
@@ -143,14 +226,45 @@ impl TypeChecker {
             self.check_statement(statement)?;
         }
 
+        // Each function has to declare the effects its body performs
+        let resolver = crate::effects::effect_resolver(&program.statements);
+        for statement in &program.statements {
+            if let Statement::Function(func) = statement {
+                crate::effects::check_function_effects(func, &resolver)?;
+            }
+        }
+
         // Validate linear type usage after checking all statements
         self.validate_linear_usage()?;
         Ok(())
     }
 
+    /// Infers the type of an expression in the scope left by the programs checked so far
+    ///
+    /// Checking can use up linear resources, so ask a clone to leave this checker as it was.
+    pub fn type_of(&mut self, expr: &Expression) -> Result<Type, String> {
+        self.check_expression(expr)
+    }
+
     /// Checks the types in a statement
     fn check_statement(&mut self, statement: &Statement) -> Result<(), String> {
         match statement {
+            // An if used as a statement has no value, so its branches needn't agree
+            Statement::Expression(if_expr @ Expression::If(condition, then_stmts, else_stmts)) => {
+                let if_type = self.check_if(condition, then_stmts, else_stmts, false)?;
+                self.record(if_expr, if_type);
+                Ok(())
+            },
+            Statement::Expression(match_expr @ Expression::Match(expr, arms)) => {
+                let match_type = self.check_match(expr, arms, false)?;
+                self.record(match_expr, match_type);
+                Ok(())
+            },
+            Statement::Expression(select @ Expression::Select(arms)) => {
+                let select_type = self.check_select(arms, false)?;
+                self.record(select, select_type);
+                Ok(())
+            },
             Statement::Expression(expr) => {
                 self.check_expression(expr)?;
                 Ok(())
@@ -159,21 +273,28 @@ impl TypeChecker {
                 let value_type = self.check_expression(value)?;
 
                 if let Some(expected_type) = type_annotation {
+                    self.check_collection_types(expected_type)?;
+                    self.check_trait_object_value(value, expected_type)?;
                     if !self.types_compatible(&value_type, expected_type) {
-                        return Err(format!(
-                            "Type mismatch: expected {:?}, found {:?} for variable '{}'",
+                        return Err(self.mismatch(expected_type, &value_type, format!(
+                            "Type mismatch: expected {}, found {} for variable '{}'",
                             expected_type, value_type, name
-                        ));
+                        )));
                     }
                 }
 
-                // Add the variable to the environment
-                let final_type = type_annotation.clone().unwrap_or(value_type.clone());
+                // Add the variable to the environment; without an annotation, or with one
+                // left to inference, the variable takes the value's type
+                let final_type = match type_annotation {
+                    Some(annotation) if !mentions_infer(annotation) || mentions_infer(&value_type) => annotation.clone(),
+                    _ => value_type,
+                };
                 self.env.set_type(name.clone(), final_type.clone());
 
                 // If it's a linear type, initialize its usage count to 0
                 if self.is_linear_type(&final_type) {
-                    self.linear_resources.insert(name.clone(), 0);
+                    let symbol = self.env.intern(name);
+                    self.linear_resources.insert(symbol, 0);
                 }
 
                 Ok(())
@@ -183,10 +304,10 @@ impl TypeChecker {
                 
                 if let Some(expected_type) = type_annotation {
                     if !self.types_compatible(&value_type, expected_type) {
-                        return Err(format!(
-                            "Type mismatch: expected {:?}, found {:?} for constant '{}'",
+                        return Err(self.mismatch(expected_type, &value_type, format!(
+                            "Type mismatch: expected {}, found {} for constant '{}'",
                             expected_type, value_type, name
-                        ));
+                        )));
                     }
                 }
                 
@@ -197,27 +318,15 @@ impl TypeChecker {
                 Ok(())
             },
             Statement::Function(func_def) => {
+                // Calls to a generic function instantiate its signature afresh, see check_generic_call
+                if !func_def.generic_params.is_empty() {
+                    self.env.set_type(func_def.name.clone(), Self::generic_signature(func_def));
+                }
                 self.check_function(func_def)?;
                 Ok(())
             },
             Statement::Block(statements) => {
-                // Check the block with a new environment
-                let mut checker = TypeChecker {
-                    env: TypeEnv::new(Some(self.env.clone())),
-                    linear_resources: self.linear_resources.clone(), // Inherit linear resources
-                    trait_resolver: self.trait_resolver.clone(), // Inherit trait resolver
-                    effects: self.effects.clone(), // Inherit effects
-                };
-
-                for stmt in statements {
-                    checker.check_statement(stmt)?;
-                }
-
-                // Copy back the linear resource usage from the block
-                for (resource, count) in &checker.linear_resources {
-                    self.linear_resources.insert(resource.clone(), *count);
-                }
-
+                self.check_block(statements)?;
                 Ok(())
             },
             Statement::Trait(trait_def) => {
@@ -239,6 +348,63 @@ impl TypeChecker {
                 self.check_class(class_def)?;
                 Ok(())
             },
+            Statement::While { label, condition, body } => {
+                let cond_type = self.check_expression(condition)?;
+                if !self.is_boolean_type(&cond_type) {
+                    return Err(format!("While condition must be boolean, found {}", cond_type));
+                }
+
+                let mut loop_labels = self.loop_labels.clone();
+                loop_labels.extend(label.clone());
+                let mut body_checker = TypeChecker {
+                    env: TypeEnv::new(Some(self.env.clone())),
+                    linear_resources: self.linear_resources.clone(),
+                    trait_resolver: self.trait_resolver.clone(),
+                    enums: self.enums.clone(),
+                    effects: self.effects.clone(),
+                    return_type: self.return_type.clone(),
+                    loop_labels,
+                    limits: self.limits,
+                    recording: self.recording.clone(),
+                };
+                for stmt in body {
+                    body_checker.check_statement(stmt)?;
+                }
+                body_checker.validate_linear_usage()?;
+
+                Ok(())
+            },
+            Statement::Defer(deferred) => self.check_statement(deferred),
+            Statement::Assert { actual, expected, message, .. } => {
+                let actual_type = self.check_expression(actual)?;
+                match expected {
+                    Some(expected) => {
+                        let expected_type = self.check_expression(expected)?;
+                        if !self.types_compatible(&actual_type, &expected_type) {
+                            return Err(format!(
+                                "assert_eq compares values of different types: {} and {}",
+                                actual_type, expected_type
+                            ));
+                        }
+                    },
+                    None => {
+                        if !matches!(actual_type, Type::Bool | Type::Infer) {
+                            return Err(format!("Assert condition must be boolean, found {}", actual_type));
+                        }
+                    },
+                }
+                if let Some(message) = message {
+                    self.check_expression(message)?;
+                }
+                Ok(())
+            },
+            Statement::Break(Some(label)) | Statement::Continue(Some(label)) => {
+                if self.loop_labels.contains(label) {
+                    Ok(())
+                } else {
+                    Err(format!("Undefined loop label '{}", label))
+                }
+            },
             Statement::Return(expr) => {
                 match expr {
                     Some(e) => {
@@ -259,6 +425,7 @@ impl TypeChecker {
                 // Register the enum with the type environment
                 // For now, we just add the enum name as a type
                 self.env.set_type(enum_def.name.clone(), Type::Named(enum_def.name.clone()));
+                self.enums.insert(enum_def.name.clone(), enum_def.variants.iter().map(|variant| variant.name.clone()).collect());
 
                 // Check each variant
                 for variant in &enum_def.variants {
@@ -270,7 +437,7 @@ impl TypeChecker {
                                     // Check that each type in the tuple is valid
                                     // For now, we just ensure the type is known
                                     if !self.is_known_type(ty) {
-                                        return Err(format!("Unknown type in enum variant: {:?}", ty));
+                                        return Err(format!("Unknown type in enum variant: {}", ty));
                                     }
                                 }
                             },
@@ -278,7 +445,7 @@ impl TypeChecker {
                                 for field in fields {
                                     // Check that each field type is valid
                                     if !self.is_known_type(&field.type_annotation) {
-                                        return Err(format!("Unknown type in enum field: {:?}", field.type_annotation));
+                                        return Err(format!("Unknown type in enum field: {}", field.type_annotation));
                                     }
                                 }
                             },
@@ -292,21 +459,369 @@ impl TypeChecker {
                 Ok(())
             },
             Statement::TypeAlias(alias_def) => {
+                // The alias is in scope in its own definition, so `type Loop = Option<Loop>`
+                // is caught by the recursion limit rather than reported as an unknown type
+                self.env.set_type(alias_def.name.clone(), Type::Named(alias_def.name.clone()));
+
                 // Check that the aliased type is valid
                 if !self.is_known_type(&alias_def.aliased_type) {
-                    return Err(format!("Unknown type in type alias: {:?}", alias_def.aliased_type));
+                    return Err(format!("Unknown type in type alias: {}", alias_def.aliased_type));
                 }
 
                 // Register the alias in the environment
                 self.env.set_type(alias_def.name.clone(), alias_def.aliased_type.clone());
+                self.expand_alias(&alias_def.aliased_type, &alias_def.name, 0)?;
 
                 Ok(())
             },
+            Statement::Extern(extern_def) => {
+                // Every type in the signature has to cross the FFI boundary
+                let declared = extern_def.parameters.iter().map(|param| &param.type_annotation);
+                for ty in declared.chain(&extern_def.return_type) {
+                    if crate::enhanced_ffi::ffi_type_of(ty).is_none() {
+                        return Err(format!("extern fn {} can't pass {} to foreign code", extern_def.name, ty));
+                    }
+                }
+
+                let params = extern_def.parameters.iter().map(|param| param.type_annotation.clone()).collect();
+                let ret = extern_def.return_type.clone().unwrap_or(Type::Unit);
+                self.env.set_type(extern_def.name.clone(), Type::Function(params, Box::new(ret)));
+                Ok(())
+            },
             // Handle other statement types as needed
             _ => Ok(()), // For now, accept other statements without strict checking
         }
     }
 
+    /// Expands the aliases a type refers to, failing if that nests deeper than the type depth
+    fn expand_alias(&self, ty: &Type, alias: &str, depth: usize) -> Result<(), String> {
+        if depth > self.limits.type_depth {
+            return Err(format!(
+                "type recursion limit reached while expanding alias '{}' (limit is {} levels)",
+                alias, self.limits.type_depth
+            ));
+        }
+        let depth = depth + 1;
+        match ty {
+            Type::Named(name) => match self.env.get_type(name) {
+                Some(Type::Named(target)) if &target == name => Ok(()),
+                Some(target) => self.expand_alias(&target, alias, depth),
+                None => Ok(()),
+            },
+            Type::Array(inner) | Type::Option(inner) | Type::Set(inner)
+                | Type::Channel(inner) | Type::Linear(inner) => self.expand_alias(inner, alias, depth),
+            Type::Result(first, second) | Type::Map(first, second) => {
+                self.expand_alias(first, alias, depth)?;
+                self.expand_alias(second, alias, depth)
+            },
+            Type::Tuple(types) => types.iter().try_for_each(|t| self.expand_alias(t, alias, depth)),
+            Type::Function(params, ret) => {
+                params.iter().try_for_each(|t| self.expand_alias(t, alias, depth))?;
+                self.expand_alias(ret, alias, depth)
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Creates a checker for a nested block: a new scope that inherits everything else
+    fn block_checker(&self) -> TypeChecker {
+        TypeChecker {
+            env: TypeEnv::new(Some(self.env.clone())),
+            linear_resources: self.linear_resources.clone(), // Inherit linear resources
+            trait_resolver: self.trait_resolver.clone(), // Inherit trait resolver
+            enums: self.enums.clone(),
+            effects: self.effects.clone(), // Inherit effects
+            return_type: self.return_type.clone(),
+            loop_labels: self.loop_labels.clone(),
+            limits: self.limits,
+            recording: self.recording.clone(),
+        }
+    }
+
+    /// Checks a block in a new scope and returns the type of its value
+    fn check_block(&mut self, statements: &[Statement]) -> Result<Type, String> {
+        let mut checker = self.block_checker();
+        let block_type = checker.check_block_value(statements)?;
+
+        // Copy back the linear resource usage from the block
+        for (resource, count) in &checker.linear_resources {
+            self.linear_resources.insert(*resource, *count);
+        }
+
+        Ok(block_type)
+    }
+
+    /// Checks a block's statements in the current scope and returns the type of its value
+    ///
+    /// A block's value is its trailing expression, so its type is that expression's
+    /// type, or Unit when the block is empty or ends in any other statement. A block
+    /// that ends by leaving (`return`, `break`, `continue`) never produces a value, so
+    /// its type is left open to agree with whatever the other branches produce.
+    fn check_block_value(&mut self, statements: &[Statement]) -> Result<Type, String> {
+        let mut block_type = Type::Unit;
+        for stmt in statements {
+            block_type = match stmt {
+                Statement::Expression(expr) => self.check_expression(expr)?,
+                Statement::Block(inner) => self.check_block(inner)?,
+                Statement::Return(_) | Statement::Break(_) | Statement::Continue(_) => {
+                    self.check_statement(stmt)?;
+                    Type::Infer
+                },
+                stmt => {
+                    self.check_statement(stmt)?;
+                    Type::Unit
+                },
+            };
+        }
+        Ok(block_type)
+    }
+
+    /// Checks an if expression and returns its type
+    ///
+    /// When the value is used, both branches must have agreeing types, which become the
+    /// if's type. Without an else branch there is nothing to use when the condition is
+    /// false, so the if's type is Unit. An unused if whose branches happen to agree still
+    /// has their type, and one whose branches don't is Unit.
+    fn check_if(&mut self, condition: &Expression, then_stmts: &[Statement], else_stmts: &[Statement], value_used: bool) -> Result<Type, String> {
+        // `else if` nests each if in the else branch of the one before. The chain is
+        // walked in a loop rather than by recursion, so a long one can't exhaust the
+        // stack, but each link is checked as if nested: its condition and branches in
+        // a scope inside the previous link's else branch.
+        let mut links = vec![(None, condition, then_stmts)];
+        let mut else_stmts = else_stmts;
+        while let [Statement::Expression(inner @ Expression::If(condition, then_stmts, rest))] = else_stmts {
+            links.push((Some(inner), condition, then_stmts));
+            else_stmts = rest;
+        }
+
+        // Each link's then-branch checker and type, and the else scope that follows it
+        let mut branches: Vec<(TypeChecker, Type, TypeChecker)> = Vec::with_capacity(links.len());
+        for (_, condition, then_stmts) in &links {
+            let scope = match branches.last_mut() {
+                Some((_, _, else_scope)) => else_scope,
+                None => &mut *self,
+            };
+            let cond_type = scope.check_expression(condition)?;
+            if !scope.is_boolean_type(&cond_type) {
+                return Err(format!("If condition must be boolean, found {}", cond_type));
+            }
+
+            // Each branch is checked in its own scope
+            let mut then_checker = scope.block_checker();
+            let then_type = then_checker.check_block_value(then_stmts)?;
+            let else_scope = scope.block_checker();
+            branches.push((then_checker, then_type, else_scope));
+        }
+        let mut if_type = match branches.last_mut() {
+            Some((_, _, else_scope)) => else_scope.check_block_value(else_stmts)?,
+            None => Type::Unit,
+        };
+
+        // From the innermost if outwards, as the nested checks would finish
+        for (index, ((inner, _, _), (then_checker, then_type, else_scope))) in links.iter().zip(branches).enumerate().rev() {
+            // For linear resources that exist in both branches, ensure they're used the same way
+            // In a full implementation, we'd merge the linear resource usage
+            // For now, we'll just validate both branches separately
+            then_checker.validate_linear_usage()?;
+            else_scope.validate_linear_usage()?;
+
+            let innermost = index == links.len() - 1;
+            let else_type = std::mem::replace(&mut if_type, Type::Unit);
+            let result = if innermost && else_stmts.is_empty() {
+                Ok(Type::Unit)
+            } else {
+                // An inner if is the value of the else branch around it, so it is always used
+                match self.unify_branch_types(&then_type, &else_type) {
+                    Some(unified) => Ok(unified),
+                    None if index == 0 && !value_used => Ok(Type::Unit),
+                    None => Err(format!("If and else branches have different types: {} and {}", then_type, else_type)),
+                }
+            };
+
+            // The outermost if is recorded by check_expression
+            if let Some(inner) = inner {
+                self.record_result(inner, &result);
+            }
+            if_type = result?;
+        }
+
+        Ok(if_type)
+    }
+
+    /// Checks a match expression and returns its type
+    ///
+    /// Each arm is checked in its own scope with its pattern's bindings. When the value
+    /// is used, every arm must produce an agreeing type, and some arm must match
+    /// whatever the scrutinee turns out to be, since otherwise there'd be no value. An
+    /// unused match is Unit unless its arms agree and cover everything, as with `check_if`.
+    fn check_match(&mut self, expr: &Expression, arms: &[MatchArm], value_used: bool) -> Result<Type, String> {
+        let scrutinee_type = self.check_expression(expr)?;
+        self.check_match_exhaustiveness(&scrutinee_type, arms).map_err(|problems| problems.join("; "))?;
+
+        let mut match_type: Option<Type> = None;
+        let mut arms_agree = true;
+        for (pattern, guard, body) in arms {
+            let mut arm_checker = self.block_checker();
+            self.bind_pattern_variables_to_env(pattern, &mut arm_checker.env)?;
+
+            if let Some(guard_expr) = guard {
+                let guard_type = arm_checker.check_expression(guard_expr)?;
+                if !self.is_boolean_type(&guard_type) {
+                    return Err("Guard expression must be of boolean type".to_string());
+                }
+            }
+
+            let arm_type = arm_checker.check_block_value(body)?;
+            arm_checker.validate_linear_usage()?;
+
+            match_type = Some(match match_type {
+                Some(previous) => match self.unify_branch_types(&previous, &arm_type) {
+                    Some(unified) => unified,
+                    None if !value_used => {
+                        arms_agree = false;
+                        arm_type
+                    },
+                    None => return Err(format!("Match arms have different types: {} and {}", previous, arm_type)),
+                },
+                None => arm_type,
+            });
+        }
+
+        if !value_used {
+            let settled = arms_agree && self.covers_every_case(&scrutinee_type, arms);
+            return Ok(match match_type {
+                Some(match_type) if settled => match_type,
+                _ => Type::Unit,
+            });
+        }
+        if !self.covers_every_case(&scrutinee_type, arms) {
+            return Err("Non-exhaustive match used as a value: add a `_` arm".to_string());
+        }
+        Ok(match_type.unwrap_or(Type::Unit))
+    }
+
+    /// Checks that a match handles every value of `scrutinee_ty` and that each arm can be taken
+    ///
+    /// A match on a `Bool` needs both `true` and `false`, and one on an enum needs every
+    /// variant, unless an arm matches anything. Guarded arms don't count towards this, as
+    /// their guard may fail, and a variant only counts as handled by an arm whose
+    /// sub-patterns match anything. Other types are left to the `_` rule for matches used
+    /// as values. Arms after an unguarded catch-all are reported as unreachable.
+    pub fn check_match_exhaustiveness(&self, scrutinee_ty: &Type, arms: &[MatchArm]) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        let catch_all = arms.iter().position(|(pattern, guard, _)| guard.is_none() && matches_anything(pattern));
+        if let Some(position) = catch_all {
+            for (pattern, _, _) in &arms[position + 1..] {
+                problems.push(format!("Unreachable match arm `{}`: the `{}` arm before it matches everything", pattern, arms[position].0));
+            }
+        } else if let Some(missing) = self.unhandled_cases(scrutinee_ty, arms) {
+            if !missing.is_empty() {
+                problems.push(format!("Non-exhaustive match on {}: {} not handled", scrutinee_ty, missing.join(", ")));
+            }
+        }
+        if problems.is_empty() { Ok(()) } else { Err(problems) }
+    }
+
+    /// Whether a match's arms handle every value, counting enum variants one by one
+    fn covers_every_case(&self, scrutinee_ty: &Type, arms: &[MatchArm]) -> bool {
+        is_exhaustive(arms) || self.unhandled_cases(scrutinee_ty, arms).is_some_and(|missing| missing.is_empty())
+    }
+
+    /// The values of a boolean or the variants of an enum that no unguarded arm handles;
+    /// None for types whose values can't be listed
+    fn unhandled_cases(&self, scrutinee_ty: &Type, arms: &[MatchArm]) -> Option<Vec<String>> {
+        let unguarded: Vec<&Pattern> = arms.iter()
+            .filter(|(_, guard, _)| guard.is_none())
+            .map(|(pattern, _, _)| pattern)
+            .collect();
+        match scrutinee_ty {
+            Type::Bool => Some([true, false].iter()
+                .filter(|value| !unguarded.iter().any(|pattern| matches_bool(pattern, **value)))
+                .map(|value| value.to_string())
+                .collect()),
+            Type::Named(name) => Some(self.enums.get(name)?.iter()
+                .filter(|variant| !unguarded.iter().any(|pattern| matches_variant(pattern, name, variant)))
+                .map(|variant| format!("{}::{}", name, variant))
+                .collect()),
+            _ => None,
+        }
+    }
+
+    /// Checks a select; as with `check_match`, arms only need to agree on a type when
+    /// the select's value is used
+    ///
+    /// A receive arm's binding has its channel's element type, and a send arm's value
+    /// must have it.
+    fn check_select(&mut self, arms: &[SelectArm], value_used: bool) -> Result<Type, String> {
+        let mut select_type: Option<Type> = None;
+        for arm in arms {
+            let mut arm_checker = self.block_checker();
+            match &arm.channel_operation {
+                ChannelOperation::Send { channel, value } => {
+                    let element_type = self.channel_element_type(channel)?;
+                    let value_type = self.check_expression(value)?;
+                    if !self.types_compatible(&value_type, &element_type) {
+                        return Err(format!("Select send: expected {}, found {}", element_type, value_type));
+                    }
+                },
+                ChannelOperation::Receive { channel } => {
+                    let element_type = self.channel_element_type(channel)?;
+                    match &arm.pattern {
+                        Some(Pattern::Identifier(name)) => arm_checker.env.set_type(name.clone(), element_type),
+                        Some(pattern) => self.bind_pattern_variables_to_env(pattern, &mut arm_checker.env)?,
+                        None => {},
+                    }
+                },
+                ChannelOperation::Close { channel } => {
+                    self.channel_element_type(channel)?;
+                },
+                ChannelOperation::Timeout { millis } => {
+                    let millis_type = self.check_expression(millis)?;
+                    if !matches!(millis_type, Type::Int | Type::Infer) {
+                        return Err(format!("Select timeout: expected Int milliseconds, found {}", millis_type));
+                    }
+                },
+                ChannelOperation::Default => {},
+            }
+
+            let arm_type = arm_checker.check_block_value(&arm.body)?;
+            arm_checker.validate_linear_usage()?;
+
+            select_type = Some(match select_type {
+                Some(previous) if value_used => self.unify_branch_types(&previous, &arm_type).ok_or_else(|| format!(
+                    "Select arms have different types: {} and {}",
+                    previous, arm_type
+                ))?,
+                _ => arm_type,
+            });
+        }
+
+        if !value_used {
+            return Ok(Type::Unit);
+        }
+        Ok(select_type.unwrap_or(Type::Unit))
+    }
+
+    /// The element type of the channel `channel` evaluates to
+    fn channel_element_type(&mut self, channel: &Expression) -> Result<Type, String> {
+        match self.check_expression(channel)? {
+            Type::Channel(element_type) => Ok(*element_type),
+            Type::Infer => Ok(Type::Infer),
+            other => Err(format!("Expected channel type in select, found {}", other)),
+        }
+    }
+
+    /// The type of a value that may come from either of two branches, if they agree
+    ///
+    /// Where one branch's type is only partly known, as with `None` against `Some(1)`,
+    /// the better-known type is used.
+    fn unify_branch_types(&self, first: &Type, second: &Type) -> Option<Type> {
+        if !self.types_compatible(first, second) || !self.types_compatible(second, first) {
+            return None;
+        }
+        Some(if mentions_infer(first) { second.clone() } else { first.clone() })
+    }
+
     /// Checks if a type is known (defined in the current environment)
     fn is_known_type(&self, ty: &Type) -> bool {
         match ty {
@@ -335,6 +850,110 @@ impl TypeChecker {
             Type::Option(inner) => self.is_known_type(inner),
             Type::Result(ok, err) => self.is_known_type(ok) && self.is_known_type(err),
             Type::Infer => true, // Infer is always considered known
+            Type::Dyn(trait_name) => self.trait_resolver.get_trait(trait_name).is_some(),
+            Type::Map(key, value) => self.is_known_type(key) && self.is_known_type(value),
+            Type::Set(element) => self.is_known_type(element),
+        }
+    }
+
+    /// Checks that every Map and Set mentioned in a type has a hashable key type
+    fn check_collection_types(&self, ty: &Type) -> Result<(), String> {
+        match ty {
+            Type::Map(key, value) => {
+                self.check_key_type(key)?;
+                self.check_collection_types(key)?;
+                self.check_collection_types(value)
+            },
+            Type::Set(element) => {
+                self.check_key_type(element)?;
+                self.check_collection_types(element)
+            },
+            Type::Array(inner) | Type::Channel(inner) | Type::Linear(inner) | Type::Option(inner) => {
+                self.check_collection_types(inner)
+            },
+            Type::Tuple(types) => types.iter().try_for_each(|t| self.check_collection_types(t)),
+            Type::Function(params, ret) => {
+                params.iter().try_for_each(|t| self.check_collection_types(t))?;
+                self.check_collection_types(ret)
+            },
+            Type::Result(ok, err) => {
+                self.check_collection_types(ok)?;
+                self.check_collection_types(err)
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that a type can be used as a Map key or Set element
+    ///
+    /// Keys must have a stable equality and hash: floats (NaN is unequal to itself),
+    /// functions and closures are rejected, and user-defined types must implement
+    /// both Eq and Hash, typically through #[derive(Eq, Hash)].
+    fn check_key_type(&self, ty: &Type) -> Result<(), String> {
+        match ty {
+            Type::Int | Type::Bool | Type::String | Type::Unit => Ok(()),
+            Type::Generic(_) | Type::GenericWithBounds { .. } | Type::Infer => Ok(()),
+            Type::Array(inner) => self.check_key_type(inner),
+            Type::Tuple(types) => types.iter().try_for_each(|t| self.check_key_type(t)),
+            Type::Float => Err("Type 'Float' cannot be used as a map key: floats are not hashable".to_string()),
+            Type::Function(_, _) => {
+                Err(format!("Type '{}' cannot be used as a map key: functions and closures are not hashable", ty))
+            },
+            Type::Named(name) => {
+                let missing: Vec<&str> = ["Eq", "Hash"].into_iter()
+                    .filter(|t| !self.trait_resolver.implements_trait(name, t))
+                    .collect();
+                if missing.is_empty() {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "Type '{}' cannot be used as a map key: it does not implement {} (add #[derive(Eq, Hash)])",
+                        name,
+                        missing.join(" or ")
+                    ))
+                }
+            },
+            other => Err(format!("Type '{}' cannot be used as a map key: it is not hashable", other)),
+        }
+    }
+
+    /// Checks that values stored as `dyn Trait` have types implementing the trait
+    ///
+    /// Array literals annotated `[dyn Trait]` are checked element by element, since
+    /// each element may have a different concrete type.
+    fn check_trait_object_value(&mut self, value: &Expression, expected: &Type) -> Result<(), String> {
+        match (expected, value) {
+            (Type::Dyn(trait_name), _) => {
+                let value_type = self.check_expression(value)?;
+                self.check_implements_trait(&value_type, trait_name)
+            },
+            (Type::Array(inner), Expression::Array(items)) => {
+                for item in items {
+                    self.check_trait_object_value(item, inner)?;
+                }
+                Ok(())
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that a concrete type implements a trait, for use as `dyn Trait`
+    fn check_implements_trait(&self, ty: &Type, trait_name: &str) -> Result<(), String> {
+        if self.trait_resolver.get_trait(trait_name).is_none() {
+            return Err(format!("Unknown trait '{}' in dyn type", trait_name));
+        }
+
+        let type_name = match ty {
+            Type::Infer => return Ok(()), // Not known statically; the runtime checks it
+            Type::Dyn(other) if other == trait_name => return Ok(()),
+            Type::Named(name) => name.clone(),
+            other => other.to_string(),
+        };
+
+        if self.trait_resolver.implements_trait(&type_name, trait_name) {
+            Ok(())
+        } else {
+            Err(format!("Type '{}' does not implement trait '{}'", type_name, trait_name))
         }
     }
 
@@ -356,8 +975,9 @@ impl TypeChecker {
         // Check each field
         for field in &class_def.fields {
             if !self.is_known_type(&field.type_annotation) {
-                return Err(format!("Unknown type in field '{}': {:?}", field.name, field.type_annotation));
+                return Err(format!("Unknown type in field '{}': {}", field.name, field.type_annotation));
             }
+            self.check_collection_types(&field.type_annotation)?;
         }
 
         // Check each method
@@ -383,9 +1003,15 @@ impl TypeChecker {
                 env: method_env,
                 linear_resources: HashMap::new(), // Each method gets its own linear resource tracker
                 trait_resolver: self.trait_resolver.clone(), // Inherit trait resolver
+                enums: self.enums.clone(),
                 effects: self.effects.clone(), // Inherit effects
+                return_type: Self::function_return_type(method),
+                loop_labels: Vec::new(),
+                limits: self.limits,
+                recording: self.recording.clone(),
             };
 
+            Self::check_linear_consumption(method)?;
             for stmt in &method.body {
                 method_checker.check_statement(stmt)?;
             }
@@ -410,17 +1036,27 @@ impl TypeChecker {
 
         // Add parameters to the function environment
         for param in &func_def.parameters {
+            self.check_collection_types(&param.type_annotation)?;
             func_env.set_type(param.name.clone(), param.type_annotation.clone());
         }
+        if let Some(return_type) = &func_def.return_type {
+            self.check_collection_types(return_type)?;
+        }
 
         // Check the function body with the new environment
         let mut checker = TypeChecker {
             env: func_env,
             linear_resources: HashMap::new(), // Each function gets its own linear resource tracker
             trait_resolver: self.trait_resolver.clone(), // Inherit trait resolver
+            enums: self.enums.clone(),
             effects: self.effects.clone(), // Inherit effects
+            return_type: Self::function_return_type(func_def),
+            loop_labels: Vec::new(), // Loops outside a function body can't be targeted from it
+            limits: self.limits,
+            recording: self.recording.clone(),
         };
 
+        Self::check_linear_consumption(func_def)?;
         for stmt in &func_def.body {
             checker.check_statement(stmt)?;
         }
@@ -428,6 +1064,19 @@ impl TypeChecker {
         // Validate linear type usage in the function
         checker.validate_linear_usage()?;
 
+        // The body's trailing value is what an unannotated function returns
+        if let Some(recording) = &self.recording {
+            let returned = match (&func_def.return_type, func_def.body.last()) {
+                (Some(declared), _) => Some(declared.clone()),
+                (None, Some(Statement::Expression(value))) | (None, Some(Statement::Return(Some(value)))) => {
+                    recording.borrow().types.get(&(value as *const Expression)).cloned()
+                },
+                (None, _) => None,
+            };
+            let returned = returned.unwrap_or(Type::Unit);
+            recording.borrow_mut().returns.push((func_def.name.clone(), returned));
+        }
+
         // Update the function's effect annotations based on what was detected
         // In a full implementation, we'd match the detected effects to the declared ones
         // For now, we'll just note the effects that were used
@@ -435,6 +1084,31 @@ impl TypeChecker {
         Ok(())
     }
 
+    /// Follows a function's linear parameters and bindings through its body in order
+    ///
+    /// Each must be used exactly once: a second use is an error where it happens, and
+    /// one still unused when the body ends was dropped without being consumed.
+    fn check_linear_consumption(func_def: &FunctionDef) -> Result<(), String> {
+        let mut linear = LinearTypeSystem::new();
+        for param in &func_def.parameters {
+            if matches!(param.type_annotation, Type::Linear(_)) {
+                linear.mark_linear(&param.name);
+                linear.track_resource(&param.name, ResourceStatus::Owned);
+            }
+        }
+        for stmt in &func_def.body {
+            linear.check_linear_statement(stmt)?;
+        }
+        linear.validate_consumption().map_err(|e| format!("{} by the end of {}", e, func_def.name))
+    }
+
+    /// Returns the type `?` propagates into inside a function's body
+    ///
+    /// Functions without a return annotation aren't checked, so anything may propagate out of them.
+    fn function_return_type(func_def: &FunctionDef) -> Option<Type> {
+        Some(func_def.return_type.clone().unwrap_or(Type::Infer))
+    }
+
     /// Checks a trait definition
     fn check_trait(&mut self, trait_def: &TraitDef) -> Result<(), String> {
         // Register the trait with the trait resolver
@@ -456,9 +1130,15 @@ impl TypeChecker {
                     env: trait_env.clone(),
                     linear_resources: HashMap::new(),
                     trait_resolver: TraitResolver::new(), // Fresh resolver for method checking
+                    enums: self.enums.clone(),
                     effects: self.effects.clone(), // Inherit effects
+                    return_type: Self::function_return_type(method),
+                    loop_labels: Vec::new(),
+                    limits: self.limits,
+                    recording: self.recording.clone(),
                 };
 
+                Self::check_linear_consumption(method)?;
                 for stmt in &method.body {
                     method_checker.check_statement(stmt)?;
                 }
@@ -497,9 +1177,15 @@ impl TypeChecker {
                 env: impl_env.clone(),
                 linear_resources: HashMap::new(),
                 trait_resolver: TraitResolver::new(), // Fresh resolver for method checking
+                enums: self.enums.clone(),
                 effects: self.effects.clone(), // Inherit effects
+                return_type: Self::function_return_type(method),
+                loop_labels: Vec::new(),
+                limits: self.limits,
+                recording: self.recording.clone(),
             };
 
+            Self::check_linear_consumption(method)?;
             for stmt in &method.body {
                 method_checker.check_statement(stmt)?;
             }
@@ -511,12 +1197,292 @@ impl TypeChecker {
         Ok(())
     }
 
-    /// Checks the types in an expression
+    /// Checks the types in an expression, noting its type when `infer_program` is recording
     fn check_expression(&mut self, expr: &Expression) -> Result<Type, String> {
+        let result = self.expression_type(expr);
+        self.record_result(expr, &result);
+        result
+    }
+
+    /// Notes the outcome of checking an expression, if anything is recording
+    fn record_result(&self, expr: &Expression, result: &Result<Type, String>) {
+        match result {
+            Ok(ty) => self.record(expr, ty.clone()),
+            Err(_) => {
+                if let Some(recording) = &self.recording {
+                    // Errors propagate straight out, so the first expression to fail is the innermost
+                    recording.borrow_mut().failed_at.get_or_insert(expr as *const Expression);
+                }
+            },
+        }
+    }
+
+    /// Types a binary operation, walking its chain of left operands in a loop
+    ///
+    /// `a + b + c + ...` nests one level per operator on the left, so recursing down
+    /// that side would run out of stack on long but flat expressions.
+    fn binary_chain_type(&mut self, expr: &Expression) -> Result<Type, String> {
+        let mut spine = Vec::new();
+        let mut leftmost = expr;
+        while let Expression::BinaryOp(left, op, right, _) = leftmost {
+            spine.push((leftmost, op, right));
+            leftmost = left;
+        }
+
+        let mut left_type = self.check_expression(leftmost)?;
+        for (node, op, right) in spine.into_iter().rev() {
+            let right_type = self.check_expression(right)?;
+            let result = self.binary_op_type(op, left_type, right_type);
+            // Inner operations are recorded as check_expression would have; the outermost
+            // one is recorded by the caller
+            if !std::ptr::eq(node, expr) {
+                self.record_result(node, &result);
+            }
+            left_type = result?;
+        }
+
+        Ok(left_type)
+    }
+
+    /// The type of `left op right`, given the types of its operands
+    fn binary_op_type(&self, op: &BinaryOp, left_type: Type, right_type: Type) -> Result<Type, String> {
+        // Check if the operation is valid for the types
+        match op {
+            BinaryOp::Add if matches!(
+                (&left_type, &right_type),
+                (Type::String, Type::String | Type::Infer) | (Type::Infer, Type::String)
+            ) => {
+                // String concatenation
+                Ok(Type::String)
+            },
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => {
+                // These operations require numeric types
+                if self.is_numeric_type(&left_type) && self.is_numeric_type(&right_type) {
+                    // Return the wider type (Float if either is Float)
+                    if self.is_float_type(&left_type) || self.is_float_type(&right_type) {
+                        Ok(Type::Float)
+                    } else {
+                        Ok(Type::Int)
+                    }
+                } else {
+                    Err(format!(
+                        "Operator {:?} requires numeric operands, found {} and {}",
+                        op, left_type, right_type
+                    ))
+                }
+            },
+            BinaryOp::PipeForward => {
+                // Pipeline operator: left |> right
+                // This applies the function 'right' to the value 'left'
+                // 'right' should be a function that accepts 'left' as its first argument
+
+                // Check if 'right' is a function type
+                if let Type::Function(params, return_type) = &right_type {
+                    if !params.is_empty() {
+                        // Check if the left type matches the first parameter of the function
+                        if self.types_compatible(&left_type, &params[0]) {
+                            // Return the return type of the function
+                            Ok(*return_type.clone())
+                        } else {
+                            Err(format!(
+                                "Pipeline operator: left operand type {} does not match function's first parameter type {}",
+                                left_type, params[0]
+                            ))
+                        }
+                    } else {
+                        Err("Pipeline operator: function has no parameters".to_string())
+                    }
+                } else {
+                    Err(format!(
+                        "Pipeline operator: right operand must be a function, found {}",
+                        right_type
+                    ))
+                }
+            },
+            BinaryOp::PipeBackward => {
+                // Backward pipeline operator: left <| right
+                // This is similar to |> but with reversed arguments
+                // 'left' should be a function that accepts 'right' as its first argument
+
+                // Check if 'left' is a function type
+                if let Type::Function(params, return_type) = &left_type {
+                    if !params.is_empty() {
+                        // Check if the right type matches the first parameter of the function
+                        if self.types_compatible(&right_type, &params[0]) {
+                            // Return the return type of the function
+                            Ok(*return_type.clone())
+                        } else {
+                            Err(format!(
+                                "Backward pipeline operator: right operand type {} does not match function's first parameter type {}",
+                                right_type, params[0]
+                            ))
+                        }
+                    } else {
+                        Err("Backward pipeline operator: function has no parameters".to_string())
+                    }
+                } else {
+                    Err(format!(
+                        "Backward pipeline operator: left operand must be a function, found {}",
+                        left_type
+                    ))
+                }
+            },
+            BinaryOp::Eq | BinaryOp::Ne => {
+                // Equality operations return Bool, and only compare values of one type
+                if self.types_comparable(&left_type, &right_type, false) {
+                    Ok(Type::Bool)
+                } else {
+                    Err(format!(
+                        "Cannot compare {} with {}{}", 
+                        left_type, right_type, conversion_hint(&left_type, &right_type)
+                    ))
+                }
+            },
+            BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Le | BinaryOp::Ge | BinaryOp::Spaceship => {
+                // Comparison operations require an ordered type on both sides;
+                // `<>` gives -1, 0 or 1 instead of a Bool
+                if self.types_comparable(&left_type, &right_type, true) {
+                    Ok(if matches!(op, BinaryOp::Spaceship) { Type::Int } else { Type::Bool })
+                } else {
+                    Err(format!(
+                        "Cannot compare {} with {} using {:?}{}", 
+                        left_type, right_type, op, conversion_hint(&left_type, &right_type)
+                    ))
+                }
+            },
+            BinaryOp::And | BinaryOp::Or => {
+                // Logical operations require Bool types
+                if self.is_boolean_type(&left_type) && self.is_boolean_type(&right_type) {
+                    Ok(Type::Bool)
+                } else {
+                    Err(format!(
+                        "Operator {:?} requires boolean operands, found {} and {}",
+                        op, left_type, right_type
+                    ))
+                }
+            },
+            _ => {
+                // For other operations, return a general type or handle specifically
+                Ok(Type::Infer) // Using Infer as a placeholder
+            }
+        }
+    }
+
+    /// Notes the type found for an expression, if anything is recording
+    fn record(&self, expr: &Expression, ty: Type) {
+        if let Some(recording) = &self.recording {
+            recording.borrow_mut().types.insert(expr as *const Expression, ty);
+        }
+    }
+
+    /// Keeps the two sides of a type mismatch for `infer_program`, passing its message through
+    fn mismatch(&self, expected: &Type, found: &Type, message: String) -> String {
+        if let Some(recording) = &self.recording {
+            recording.borrow_mut().mismatch.get_or_insert_with(|| (expected.clone(), found.clone()));
+        }
+        message
+    }
+
+    /// A generic function's type, in which each type parameter is a `GenericWithBounds`
+    /// carrying the bounds its calls have to meet
+    fn generic_signature(func_def: &FunctionDef) -> Type {
+        let type_params: HashMap<String, Type> = func_def.generic_params.iter()
+            .map(|param| (param.name.clone(), Type::GenericWithBounds { name: param.name.clone(), bounds: param.bounds.clone() }))
+            .collect();
+        let mut params: Vec<Type> = func_def.parameters.iter().map(|param| param.type_annotation.clone()).collect();
+        let mut ret = func_def.return_type.clone().unwrap_or(Type::Infer);
+        params.iter_mut().for_each(|param| substitute(param, &type_params));
+        substitute(&mut ret, &type_params);
+        Type::Function(params, Box::new(ret))
+    }
+
+    /// Checks a call to a generic function
+    ///
+    /// Each call gets its own inference variable for every type parameter, solved from
+    /// the arguments, so `id(5)` is an Int even where `id("a")` is a String. What the
+    /// parameters are solved to must then meet their bounds.
+    fn check_generic_call(
+        &mut self,
+        name: &str,
+        args: &[Expression],
+        params: &[Type],
+        ret: &Type,
+        type_params: &[(String, Vec<String>)],
+    ) -> Result<Type, String> {
+        if args.len() != params.len() {
+            return Err(format!("Function {} expects {} arguments, found {}", name, params.len(), args.len()));
+        }
+
+        let mut unifier = Unifier::new();
+        let variables: HashMap<String, Type> = type_params.iter()
+            .map(|(param, _)| (param.clone(), unifier.fresh()))
+            .collect();
+        let instantiate = |ty: &Type| {
+            let mut ty = ty.clone();
+            substitute(&mut ty, &variables);
+            ty
+        };
+
+        for (index, (arg, param)) in args.iter().zip(params).enumerate() {
+            let expected = instantiate(param);
+            let actual = self.check_expression(arg)?;
+            if unifier.unify(&expected, &actual).is_err() {
+                let expected = unifier.resolve(&expected);
+                return Err(self.mismatch(&expected, &actual, format!(
+                    "Argument {} of {}: expected {}, found {}",
+                    index + 1, name, expected, actual
+                )));
+            }
+        }
+
+        for (param, bounds) in type_params {
+            // A parameter no argument pinned down has nothing to check yet
+            let solved = unifier.resolve(&variables[param]);
+            if Unifier::is_variable(&solved) {
+                continue;
+            }
+            if let Some(bound) = bounds.iter().find(|bound| !self.satisfies_bound(&solved, bound)) {
+                return Err(format!(
+                    "{} does not implement {}, which {}'s type parameter {} requires",
+                    solved, bound, name, param
+                ));
+            }
+        }
+
+        // Variables left unsolved are still open to inference
+        let mut returned = unifier.resolve(&instantiate(ret));
+        let unsolved: HashMap<String, Type> = variables.values()
+            .map(|variable| unifier.resolve(variable))
+            .filter(Unifier::is_variable)
+            .map(|variable| (variable.to_string(), Type::Infer))
+            .collect();
+        substitute(&mut returned, &unsolved);
+        Ok(returned)
+    }
+
+    /// Whether a type meets a trait bound
+    ///
+    /// Primitives have every derivable trait except that floats can't be hashed; any
+    /// type also meets a bound it has an impl for. Type parameters in scope are taken
+    /// on trust, since their own bounds were checked where they were instantiated.
+    fn satisfies_bound(&self, ty: &Type, bound: &str) -> bool {
+        let builtin = match ty {
+            Type::Int | Type::Bool | Type::String | Type::Unit => crate::derive::DERIVABLE_TRAITS.contains(&bound),
+            Type::Float => crate::derive::DERIVABLE_TRAITS.contains(&bound) && bound != "Hash",
+            Type::Generic(_) | Type::GenericWithBounds { .. } => true,
+            Type::Named(name) => matches!(self.env.get_type(name), Some(Type::Generic(_))),
+            _ => false,
+        };
+        builtin || self.trait_resolver.implements_trait(&ty.to_string(), bound)
+    }
+
+    /// Works out the type of an expression, checking its parts along the way
+    fn expression_type(&mut self, expr: &Expression) -> Result<Type, String> {
         match expr {
             Expression::Integer(_) => Ok(Type::Int),
             Expression::Float(_) => Ok(Type::Float),
             Expression::String(_) => Ok(Type::String),
+            Expression::Bytes(_) => Ok(Type::Named("Bytes".to_string())),
             Expression::Boolean(_) => Ok(Type::Bool),
             Expression::Nil => Ok(Type::Unit),
             Expression::Identifier(name) => {
@@ -555,8 +1521,20 @@ impl TypeChecker {
                 let obj_type = self.check_expression(obj_expr)?;
 
                 // Check all arguments
+                let mut arg_types = Vec::with_capacity(args.len());
                 for arg in args {
-                    self.check_expression(arg)?;
+                    arg_types.push(self.check_expression(arg)?);
+                }
+
+                // Channels carry one element type: sends must match it and receives produce it
+                if let Type::Channel(element_type) = &obj_type {
+                    return match (method_name.as_str(), arg_types.as_slice()) {
+                        ("send", [value_type]) if self.types_compatible(value_type, element_type) => Ok(Type::Unit),
+                        ("send", [value_type]) => Err(format!("Channel send: expected {}, found {}", element_type, value_type)),
+                        ("recv", []) => Ok(element_type.as_ref().clone()),
+                        ("close", []) => Ok(Type::Unit),
+                        _ => Err(format!("No method '{}' with {} argument(s) on {}", method_name, args.len(), obj_type)),
+                    };
                 }
 
                 // For linear types, method calls might consume the resource
@@ -566,128 +1544,23 @@ impl TypeChecker {
                     }
                 }
 
-                // For now, return a placeholder type
-                // In a full implementation, we'd look up the method signature
-                Ok(Type::Infer)
-            },
-            Expression::BinaryOp(left, op, right) => {
-                let left_type = self.check_expression(left)?;
-                let right_type = self.check_expression(right)?;
-
-                // Check if the operation is valid for the types
-                match op {
-                    BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => {
-                        // These operations require numeric types
-                        if self.is_numeric_type(&left_type) && self.is_numeric_type(&right_type) {
-                            // Return the wider type (Float if either is Float)
-                            if self.is_float_type(&left_type) || self.is_float_type(&right_type) {
-                                Ok(Type::Float)
-                            } else {
-                                Ok(Type::Int)
-                            }
-                        } else {
-                            Err(format!(
-                                "Operator {:?} requires numeric operands, found {:?} and {:?}",
-                                op, left_type, right_type
-                            ))
-                        }
-                    },
-                    BinaryOp::PipeForward => {
-                        // Pipeline operator: left |> right
-                        // This applies the function 'right' to the value 'left'
-                        // 'right' should be a function that accepts 'left' as its first argument
-
-                        // Check if 'right' is a function type
-                        if let Type::Function(params, return_type) = &right_type {
-                            if !params.is_empty() {
-                                // Check if the left type matches the first parameter of the function
-                                if self.types_compatible(&left_type, &params[0]) {
-                                    // Return the return type of the function
-                                    Ok(*return_type.clone())
-                                } else {
-                                    Err(format!(
-                                        "Pipeline operator: left operand type {:?} does not match function's first parameter type {:?}",
-                                        left_type, params[0]
-                                    ))
-                                }
-                            } else {
-                                Err("Pipeline operator: function has no parameters".to_string())
-                            }
-                        } else {
-                            Err(format!(
-                                "Pipeline operator: right operand must be a function, found {:?}",
-                                right_type
-                            ))
-                        }
-                    },
-                    BinaryOp::PipeBackward => {
-                        // Backward pipeline operator: left <| right
-                        // This is similar to |> but with reversed arguments
-                        // 'left' should be a function that accepts 'right' as its first argument
-
-                        // Check if 'left' is a function type
-                        if let Type::Function(params, return_type) = &left_type {
-                            if !params.is_empty() {
-                                // Check if the right type matches the first parameter of the function
-                                if self.types_compatible(&right_type, &params[0]) {
-                                    // Return the return type of the function
-                                    Ok(*return_type.clone())
-                                } else {
-                                    Err(format!(
-                                        "Backward pipeline operator: right operand type {:?} does not match function's first parameter type {:?}",
-                                        right_type, params[0]
-                                    ))
-                                }
-                            } else {
-                                Err("Backward pipeline operator: function has no parameters".to_string())
-                            }
-                        } else {
-                            Err(format!(
-                                "Backward pipeline operator: left operand must be a function, found {:?}",
-                                left_type
-                            ))
-                        }
-                    },
-                    BinaryOp::Eq | BinaryOp::Ne => {
-                        // Equality operations return Bool
-                        if self.types_compatible(&left_type, &right_type) {
-                            Ok(Type::Bool)
-                        } else {
-                            Err(format!(
-                                "Cannot compare {:?} with {:?}", 
-                                left_type, right_type
-                            ))
-                        }
-                    },
-                    BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Le | BinaryOp::Ge => {
-                        // Comparison operations return Bool and require comparable types
-                        if self.is_comparable_type(&left_type) && self.is_comparable_type(&right_type) &&
-                           self.types_compatible(&left_type, &right_type) {
-                            Ok(Type::Bool)
-                        } else {
-                            Err(format!(
-                                "Cannot compare {:?} with {:?} using {:?}", 
-                                left_type, right_type, op
-                            ))
-                        }
-                    },
-                    BinaryOp::And | BinaryOp::Or => {
-                        // Logical operations require Bool types
-                        if self.is_boolean_type(&left_type) && self.is_boolean_type(&right_type) {
-                            Ok(Type::Bool)
-                        } else {
-                            Err(format!(
-                                "Operator {:?} requires boolean operands, found {:?} and {:?}",
-                                op, left_type, right_type
-                            ))
-                        }
-                    },
-                    _ => {
-                        // For other operations, return a general type or handle specifically
-                        Ok(Type::Infer) // Using Infer as a placeholder
-                    }
+                // Trait methods resolve across every trait the receiver's type implements;
+                // anything else (built-in methods on strings, arrays, cells) stays unchecked
+                let methods = EnhancedTraitResolver::from(&self.trait_resolver);
+                if method_candidates(&obj_type, method_name, &methods).is_empty() {
+                    return Ok(Type::Infer);
+                }
+                let method = resolve_method_call(&obj_type, method_name, &methods)?;
+                let expected = method.parameters.iter().filter(|p| p.name != "self").count();
+                if expected != args.len() {
+                    return Err(format!(
+                        "Method '{}' takes {} argument(s) but {} were supplied",
+                        method_name, expected, args.len()
+                    ));
                 }
+                Ok(method.return_type.clone().unwrap_or(Type::Infer))
             },
+            Expression::BinaryOp(..) => self.binary_chain_type(expr),
             Expression::UnaryOp(op, expr) => {
                 let expr_type = self.check_expression(expr)?;
                 
@@ -696,14 +1569,14 @@ impl TypeChecker {
                         if self.is_numeric_type(&expr_type) {
                             Ok(expr_type) // Return the same numeric type
                         } else {
-                            Err(format!("Unary minus requires numeric operand, found {:?}", expr_type))
+                            Err(format!("Unary minus requires numeric operand, found {}", expr_type))
                         }
                     },
                     UnaryOp::Not => {
                         if self.is_boolean_type(&expr_type) {
                             Ok(Type::Bool)
                         } else {
-                            Err(format!("Unary not requires boolean operand, found {:?}", expr_type))
+                            Err(format!("Unary not requires boolean operand, found {}", expr_type))
                         }
                     },
                     _ => Ok(Type::Infer), // Placeholder for other unary ops
@@ -739,6 +1612,14 @@ impl TypeChecker {
                         self.check_expression(&args[0])?;
                         Ok(Type::String)
                     },
+                    "bytes" => {
+                        // bytes accepts a String, an array of byte values or Bytes
+                        if args.len() != 1 {
+                            return Err("bytes() expects exactly one argument".to_string());
+                        }
+                        self.check_expression(&args[0])?;
+                        Ok(Type::Named("Bytes".to_string()))
+                    },
                     "int" => {
                         // int can convert compatible types to integer
                         if args.len() != 1 {
@@ -747,6 +1628,29 @@ impl TypeChecker {
                         self.check_expression(&args[0])?;
                         Ok(Type::Int)
                     },
+                    "Map::new" | "Set::new" => {
+                        if !args.is_empty() {
+                            return Err(format!("{}() takes no arguments", name));
+                        }
+                        // Element types come from the annotation the value is bound to
+                        if name == "Map::new" {
+                            Ok(Type::Map(Box::new(Type::Infer), Box::new(Type::Infer)))
+                        } else {
+                            Ok(Type::Set(Box::new(Type::Infer)))
+                        }
+                    },
+                    "Some" | "Ok" | "Err" => {
+                        if args.len() != 1 {
+                            return Err(format!("{}() expects exactly one argument", name));
+                        }
+                        let arg_type = Box::new(self.check_expression(&args[0])?);
+                        // The other half of a Result comes from the context it is used in
+                        Ok(match name.as_str() {
+                            "Some" => Type::Option(arg_type),
+                            "Ok" => Type::Result(arg_type, Box::new(Type::Infer)),
+                            _ => Type::Result(Box::new(Type::Infer), arg_type),
+                        })
+                    },
                     "float" => {
                         // float can convert compatible types to float
                         if args.len() != 1 {
@@ -755,52 +1659,118 @@ impl TypeChecker {
                         self.check_expression(&args[0])?;
                         Ok(Type::Float)
                     },
+                    "to_float" | "to_int" => {
+                        // Explicit conversions between the two numeric types
+                        if args.len() != 1 {
+                            return Err(format!("{}() expects exactly one argument", name));
+                        }
+                        let arg_type = self.check_expression(&args[0])?;
+                        if !matches!(arg_type, Type::Int | Type::Float | Type::Infer) {
+                            return Err(format!("{}() expects an Int or Float, found {}", name, arg_type));
+                        }
+                        Ok(if name == "to_float" { Type::Float } else { Type::Int })
+                    },
+                    "parse_int" | "parse_float" => {
+                        // Parsing can fail, so the number comes wrapped in a Result
+                        if args.len() != 1 {
+                            return Err(format!("{}() expects exactly one argument", name));
+                        }
+                        let arg_type = self.check_expression(&args[0])?;
+                        if !matches!(arg_type, Type::String | Type::Infer) {
+                            return Err(format!("{}() expects a String, found {}", name, arg_type));
+                        }
+                        let number = if name == "parse_int" { Type::Int } else { Type::Float };
+                        Ok(Type::Result(Box::new(number), Box::new(Type::String)))
+                    },
+                    "script_dir" => {
+                        if !args.is_empty() {
+                            return Err("script_dir() takes no arguments".to_string());
+                        }
+                        Ok(Type::String)
+                    },
+                    "json::stringify" => {
+                        // NaN, the infinities and values JSON can't hold come back as an Err
+                        if args.len() != 1 {
+                            return Err("json::stringify() expects exactly one argument".to_string());
+                        }
+                        self.check_expression(&args[0])?;
+                        Ok(Type::Result(Box::new(Type::String), Box::new(Type::String)))
+                    },
+                    "env::args" | "env::vars" => {
+                        if !args.is_empty() {
+                            return Err(format!("{}() takes no arguments", name));
+                        }
+                        Ok(if name == "env::args" {
+                            Type::Array(Box::new(Type::String))
+                        } else {
+                            Type::Map(Box::new(Type::String), Box::new(Type::String))
+                        })
+                    },
+                    "env::arg" | "env::get_var" | "env::set_var" => {
+                        // Arguments and variables may be missing, so lookups come back as Options
+                        let expected: &[Type] = match name.as_str() {
+                            "env::arg" => &[Type::Int],
+                            "env::get_var" => &[Type::String],
+                            _ => &[Type::String, Type::String],
+                        };
+                        if args.len() != expected.len() {
+                            return Err(format!("{}() expects {} argument(s), found {}", name, expected.len(), args.len()));
+                        }
+                        for (index, (arg, expected)) in args.iter().zip(expected).enumerate() {
+                            let arg_type = self.check_expression(arg)?;
+                            if !self.types_compatible(&arg_type, expected) {
+                                return Err(format!("Argument {} of {}: expected {}, found {}", index + 1, name, expected, arg_type));
+                            }
+                        }
+                        Ok(if name == "env::set_var" { Type::Unit } else { Type::Option(Box::new(Type::String)) })
+                    },
+                    "read_file" | "write_file" => {
+                        // File access can fail, so the outcome comes wrapped in a Result
+                        let arity = if name == "read_file" { 1 } else { 2 };
+                        if args.len() != arity {
+                            return Err(format!("{}() expects {} argument(s), found {}", name, arity, args.len()));
+                        }
+                        for arg in args {
+                            let arg_type = self.check_expression(arg)?;
+                            if !matches!(arg_type, Type::String | Type::Infer) {
+                                return Err(format!("{}() expects String arguments, found {}", name, arg_type));
+                            }
+                        }
+                        let value = if name == "read_file" { Type::String } else { Type::Unit };
+                        Ok(Type::Result(Box::new(value), Box::new(Type::String)))
+                    },
                     _ => {
+                        // Names bound to a known signature, such as extern functions, have their
+                        // arguments checked against it
+                        if let Some(Type::Function(params, ret)) = self.env.get_type(name) {
+                            let mut type_params = Vec::new();
+                            params.iter().chain([ret.as_ref()]).for_each(|ty| collect_type_params(ty, &mut type_params));
+                            if !type_params.is_empty() {
+                                return self.check_generic_call(name, args, &params, &ret, &type_params);
+                            }
+                            if args.len() != params.len() {
+                                return Err(format!("Function {} expects {} arguments, found {}", name, params.len(), args.len()));
+                            }
+                            for (index, (arg, expected)) in args.iter().zip(&params).enumerate() {
+                                let actual = self.check_expression(arg)?;
+                                if !self.types_compatible(&actual, expected) {
+                                    return Err(self.mismatch(expected, &actual, format!(
+                                        "Argument {} of {}: expected {}, found {}",
+                                        index + 1, name, expected, actual
+                                    )));
+                                }
+                            }
+                            return Ok(*ret);
+                        }
+
                         // For user-defined functions, we'd need to look up the function signature
                         // For now, return a placeholder type
                         Ok(Type::Infer)
                     }
                 }
             },
-            Expression::If(condition, then_stmts, else_stmts) => {
-                // Check the condition
-                let cond_type = self.check_expression(condition)?;
-                if !self.is_boolean_type(&cond_type) {
-                    return Err(format!("If condition must be boolean, found {:?}", cond_type));
-                }
-
-                // Check then branch with a new environment
-                let mut then_checker = TypeChecker {
-                    env: self.env.clone(),
-                    linear_resources: self.linear_resources.clone(),
-                    trait_resolver: self.trait_resolver.clone(),
-                    effects: self.effects.clone(), // Inherit effects
-                };
-                for stmt in then_stmts {
-                    then_checker.check_statement(stmt)?;
-                }
-
-                // Check else branch with a new environment
-                let mut else_checker = TypeChecker {
-                    env: self.env.clone(),
-                    linear_resources: self.linear_resources.clone(),
-                    trait_resolver: self.trait_resolver.clone(),
-                    effects: self.effects.clone(), // Inherit effects
-                };
-                for stmt in else_stmts {
-                    else_checker.check_statement(stmt)?;
-                }
-
-                // For linear resources that exist in both branches, ensure they're used the same way
-                // In a full implementation, we'd merge the linear resource usage
-                // For now, we'll just validate both branches separately
-                then_checker.validate_linear_usage()?;
-                else_checker.validate_linear_usage()?;
-
-                // For now, return a placeholder type for if expressions
-                // In a full implementation, we'd need to determine the unified type of both branches
-                Ok(Type::Infer)
-            },
+            Expression::If(condition, then_stmts, else_stmts) => self.check_if(condition, then_stmts, else_stmts, true),
+            Expression::Block(statements) | Expression::BlockExpr(statements) => self.check_block(statements),
             Expression::Tuple(items) => {
                 let mut item_types = Vec::new();
                 for item in items {
@@ -808,45 +1778,95 @@ impl TypeChecker {
                 }
                 Ok(Type::Tuple(item_types))
             },
-            Expression::Match(expr, arms) => {
-                // Check the expression being matched
-                let match_expr_type = self.check_expression(expr)?;
+            Expression::Array(items) => {
+                // Arrays are checked loosely: elements that disagree just leave the element type open
+                let mut element_type: Option<Type> = None;
+                for item in items {
+                    let item_type = self.check_expression(item)?;
+                    element_type = Some(match element_type {
+                        Some(previous) => self.unify_branch_types(&previous, &item_type).unwrap_or(Type::Infer),
+                        None => item_type,
+                    });
+                }
+                Ok(Type::Array(Box::new(element_type.unwrap_or(Type::Infer))))
+            },
+            Expression::TryCatch(body, name, handler) => {
+                // The handler sees the caught error as an `Error { message, code }` struct
+                let mut handler_env = TypeEnv::new(Some(self.env.clone()));
+                handler_env.set_type(name.clone(), Type::Named("Error".to_string()));
 
-                // Check each arm
-                for (pattern, guard, body) in arms {
-                    // We need to validate that the pattern is compatible with the match expression type
-                    // For now, we'll just check the body statements
-                    let mut arm_checker = TypeChecker {
-                        env: self.env.clone(),
+                for (env, statements) in [(TypeEnv::new(Some(self.env.clone())), body), (handler_env, handler)] {
+                    let mut checker = TypeChecker {
+                        env,
                         linear_resources: self.linear_resources.clone(),
                         trait_resolver: self.trait_resolver.clone(),
-                        effects: self.effects.clone(), // Inherit effects
+                        enums: self.enums.clone(),
+                        effects: self.effects.clone(),
+                        return_type: self.return_type.clone(),
+                        loop_labels: self.loop_labels.clone(),
+                        limits: self.limits,
+                        recording: self.recording.clone(),
                     };
-
-                    // Bind pattern variables to the arm's environment
-                    self.bind_pattern_variables_to_env(pattern, &mut arm_checker.env)?;
-
-                    // Check the guard if it exists
-                    if let Some(guard_expr) = guard {
-                        let guard_type = arm_checker.check_expression(guard_expr)?;
-                        if !self.is_boolean_type(&guard_type) {
-                            return Err("Guard expression must be of boolean type".to_string());
-                        }
+                    for stmt in statements {
+                        checker.check_statement(stmt)?;
                     }
-
-                    // Check the body statements
-                    for stmt in body {
-                        arm_checker.check_statement(stmt)?;
-                    }
-
-                    // Validate linear usage in this arm
-                    arm_checker.validate_linear_usage()?;
+                    checker.validate_linear_usage()?;
                 }
 
-                // For now, return a placeholder type
-                // In a full implementation, we'd need to determine the unified type of all arms
+                // The value comes from whichever block finishes, so it isn't known statically
                 Ok(Type::Infer)
             },
+            Expression::Index(target, index) => {
+                let target_type = self.check_expression(target)?;
+                self.check_index_type(index)?;
+                Ok(match target_type {
+                    Type::Array(element) => *element,
+                    Type::Named(name) if name == "Bytes" => Type::Int,
+                    _ => Type::Infer,
+                })
+            },
+            Expression::Slice(target, start, end) => {
+                let target_type = self.check_expression(target)?;
+                self.check_index_type(start)?;
+                self.check_index_type(end)?;
+                // A slice has the type of what it was cut from
+                Ok(target_type)
+            },
+            Expression::InterpolatedString(parts) => {
+                for part in parts {
+                    if let StringPart::Interpolated(expr, _) = part {
+                        self.check_expression(expr)?;
+                    }
+                }
+                Ok(Type::String)
+            },
+            Expression::Propagate(inner) => {
+                let inner_type = self.check_expression(inner)?;
+                let return_type = self.return_type.clone()
+                    .ok_or_else(|| "The ? operator can only be used inside a function".to_string())?;
+
+                match (inner_type, return_type) {
+                    (Type::Option(value), Type::Option(_) | Type::Infer) => Ok(*value),
+                    (Type::Result(value, _), Type::Infer) => Ok(*value),
+                    (Type::Result(value, error), Type::Result(_, return_error))
+                        if self.types_compatible(&error, &return_error) => Ok(*value),
+                    (Type::Infer, _) => Ok(Type::Infer),
+                    (inner_type @ (Type::Option(_) | Type::Result(_, _)), return_type) => Err(format!(
+                        "The ? operator cannot propagate {} out of a function returning {}",
+                        inner_type, return_type
+                    )),
+                    (other, _) => Err(format!("The ? operator expects an Option or Result, found {}", other)),
+                }
+            },
+            Expression::ArrayRepeat(value, length) => {
+                let value_type = self.check_expression(value)?;
+                let length_type = self.check_expression(length)?;
+                if !matches!(length_type, Type::Int | Type::Infer) {
+                    return Err(format!("Array length must be Int, found {}", length_type));
+                }
+                Ok(Type::Array(Box::new(value_type)))
+            },
+            Expression::Match(expr, arms) => self.check_match(expr, arms, true),
             Expression::Lambda(params, body) => {
                 // Create a new environment for the lambda
                 let mut lambda_env = TypeEnv::new(Some(self.env.clone()));
@@ -861,7 +1881,12 @@ impl TypeChecker {
                     env: lambda_env,
                     linear_resources: self.linear_resources.clone(), // Inherit linear resources
                     trait_resolver: self.trait_resolver.clone(), // Inherit trait resolver
+                    enums: self.enums.clone(),
                     effects: self.effects.clone(), // Inherit effects
+                    return_type: Some(Type::Infer), // A lambda's return type is not declared
+                    loop_labels: Vec::new(), // A lambda body can't break out of loops around it
+                    limits: self.limits,
+                    recording: self.recording.clone(),
                 };
 
                 for stmt in body {
@@ -890,70 +1915,28 @@ impl TypeChecker {
                     // Verify that the value type matches the channel's element type
                     if !self.types_compatible(&value_type, expected_elem_type.as_ref()) {
                         return Err(format!(
-                            "Channel send: expected {:?}, found {:?}",
+                            "Channel send: expected {}, found {}",
                             expected_elem_type.as_ref(),
                             value_type
                         ));
                     }
                     Ok(Type::Unit) // Channel send returns Unit
                 } else {
-                    Err(format!("Expected channel type, found {:?}", channel_type))
+                    Err(format!("Expected channel type, found {}", channel_type))
                 }
             },
             Expression::ChannelReceive(channel_expr) => {
                 // Check the channel expression
                 let channel_type = self.check_expression(channel_expr)?;
 
-                // Verify that the channel type is indeed a channel type
-                if let Type::Channel(elem_type) = channel_type {
-                    Ok(*elem_type) // Channel receive returns the element type
-                } else {
-                    Err(format!("Expected channel type, found {:?}", channel_type))
-                }
-            },
-            Expression::Select(select_arms) => {
-                // Check each select arm
-                for arm in select_arms {
-                    match &arm.channel_operation {
-                        ChannelOperation::Send { channel, value } => {
-                            let channel_type = self.check_expression(channel)?;
-                            let value_type = self.check_expression(value)?;
-
-                            if let Type::Channel(expected_elem_type) = channel_type {
-                                if !self.types_compatible(&value_type, expected_elem_type.as_ref()) {
-                                    return Err(format!(
-                                        "Select send: expected {:?}, found {:?}",
-                                        expected_elem_type.as_ref(),
-                                        value_type
-                                    ));
-                                }
-                            } else {
-                                return Err(format!("Expected channel type in select, found {:?}", channel_type));
-                            }
-                        },
-                        ChannelOperation::Receive { channel } => {
-                            let channel_type = self.check_expression(channel)?;
-                            if !matches!(channel_type, Type::Channel(_)) {
-                                return Err(format!("Expected channel type in select, found {:?}", channel_type));
-                            }
-                        },
-                        ChannelOperation::Close { channel } => {
-                            let channel_type = self.check_expression(channel)?;
-                            if !matches!(channel_type, Type::Channel(_)) {
-                                return Err(format!("Expected channel type in select close, found {:?}", channel_type));
-                            }
-                        },
-                    }
-
-                    // Check the body of each arm
-                    for stmt in &arm.body {
-                        self.check_statement(stmt)?;
-                    }
+                // Verify that the channel type is indeed a channel type
+                if let Type::Channel(elem_type) = channel_type {
+                    Ok(*elem_type) // Channel receive returns the element type
+                } else {
+                    Err(format!("Expected channel type, found {}", channel_type))
                 }
-
-                // For now, return a placeholder type
-                Ok(Type::Infer)
             },
+            Expression::Select(arms) => self.check_select(arms, true),
             Expression::DestructureAssignment(pattern, value, statement) => {
                 // Check the value being destructured
                 let value_type = self.check_expression(value)?;
@@ -981,14 +1964,19 @@ impl TypeChecker {
                     env: destructure_env,
                     linear_resources: self.linear_resources.clone(),
                     trait_resolver: self.trait_resolver.clone(),
+                    enums: self.enums.clone(),
                     effects: self.effects.clone(),
+                    return_type: self.return_type.clone(),
+                    loop_labels: self.loop_labels.clone(),
+                    limits: self.limits,
+                    recording: self.recording.clone(),
                 };
 
                 stmt_checker.check_statement(statement)?;
 
                 // Copy back any linear resource usage from the statement checker
                 for (resource, count) in &stmt_checker.linear_resources {
-                    self.linear_resources.insert(resource.clone(), *count);
+                    self.linear_resources.insert(*resource, *count);
                 }
 
                 // Destructuring assignment evaluates to Unit
@@ -1052,6 +2040,14 @@ impl TypeChecker {
             },
             (Type::Named(n1), Type::Named(n2)) => n1 == n2,
             (Type::Infer, _) | (_, Type::Infer) => true,  // Infer is compatible with everything
+            (Type::Dyn(t1), Type::Dyn(t2)) => t1 == t2,
+            (Type::Map(k1, v1), Type::Map(k2, v2)) => self.types_compatible(k1, k2) && self.types_compatible(v1, v2),
+            (Type::Set(t1), Type::Set(t2)) => self.types_compatible(t1, t2),
+            (Type::Channel(t1), Type::Channel(t2)) => self.types_compatible(t1, t2),
+            (Type::Option(t1), Type::Option(t2)) => self.types_compatible(t1, t2),
+            (Type::Result(ok1, err1), Type::Result(ok2, err2)) => self.types_compatible(ok1, ok2) && self.types_compatible(err1, err2),
+            (Type::Named(name), Type::Dyn(trait_name)) => self.trait_resolver.implements_trait(name, trait_name),
+            (concrete, Type::Dyn(trait_name)) => self.trait_resolver.implements_trait(&concrete.to_string(), trait_name),
             // Linear type compatibility
             (Type::Linear(t1), Type::Linear(t2)) => self.types_compatible(t1, t2),
             (Type::Linear(t1), other) => self.types_compatible(t1, other),
@@ -1094,9 +2090,44 @@ impl TypeChecker {
         matches!(ty, Type::Int | Type::Float | Type::String | Type::Bool)
     }
 
+    /// Whether values of two types can be compared with `==`, or ordered with `<` when
+    /// `ordered` is set
+    ///
+    /// Both sides need the same type, element by element for arrays, tuples and
+    /// options: unlike arithmetic, comparison never promotes an Int to a Float.
+    /// Functions can't be compared at all.
+    fn types_comparable(&self, left: &Type, right: &Type, ordered: bool) -> bool {
+        match (left, right) {
+            (Type::Function(..), _) | (_, Type::Function(..)) => false,
+            (Type::Array(a), Type::Array(b)) => self.types_comparable(a, b, ordered),
+            (Type::Tuple(a), Type::Tuple(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(x, y)| self.types_comparable(x, y, ordered))
+            },
+            (Type::Int, Type::Float) | (Type::Float, Type::Int) => false,
+            _ if ordered => self.is_comparable_type(left) && left == right,
+            (Type::Option(a), Type::Option(b)) => self.types_comparable(a, b, false),
+            (Type::Result(ok1, err1), Type::Result(ok2, err2)) => {
+                self.types_comparable(ok1, ok2, false) && self.types_comparable(err1, err2, false)
+            },
+            _ => self.types_compatible(left, right),
+        }
+    }
+
     /// Checks if a type is a collection
     fn is_collection_type(&self, ty: &Type) -> bool {
-        matches!(ty, Type::String | Type::Array(_))
+        match ty {
+            Type::String | Type::Array(_) => true,
+            Type::Named(name) => name == "Bytes",
+            _ => false,
+        }
+    }
+
+    /// Checks that an index or slice bound is an integer
+    fn check_index_type(&mut self, index: &Expression) -> Result<(), String> {
+        match self.check_expression(index)? {
+            Type::Int | Type::Infer => Ok(()),
+            other => Err(format!("Index must be an Int, found {}", other)),
+        }
     }
 
     /// Checks if a type is a dependent type
@@ -1167,7 +2198,7 @@ impl TypeChecker {
 
                     Ok(())
                 } else {
-                    Err(format!("Tuple pattern cannot match non-tuple type {:?}", expected_type))
+                    Err(format!("Tuple pattern cannot match non-tuple type {}", expected_type))
                 }
             },
             Pattern::Array(pattern_items) => {
@@ -1180,7 +2211,7 @@ impl TypeChecker {
 
                     Ok(())
                 } else {
-                    Err(format!("Array pattern cannot match non-array type {:?}", expected_type))
+                    Err(format!("Array pattern cannot match non-array type {}", expected_type))
                 }
             },
             Pattern::Struct(name, fields) => {
@@ -1193,7 +2224,7 @@ impl TypeChecker {
                     // In a full implementation, we'd check each field against the struct's field types
                     Ok(())
                 } else {
-                    Err(format!("Struct pattern cannot match non-named type {:?}", expected_type))
+                    Err(format!("Struct pattern cannot match non-named type {}", expected_type))
                 }
             },
             Pattern::Or(left, right) => {
@@ -1219,7 +2250,7 @@ impl TypeChecker {
             Pattern::Range(_, _) => {
                 // Range patterns are typically used in match expressions with integer types
                 if !matches!(expected_type, Type::Int) {
-                    return Err(format!("Range pattern should match integer type, found {:?}", expected_type));
+                    return Err(format!("Range pattern should match integer type, found {}", expected_type));
                 }
                 Ok(())
             },
@@ -1312,7 +2343,8 @@ impl TypeChecker {
                 // For now, bind with Infer type - in a full implementation, we'd infer the type from context
                 checker.env.set_type(name.clone(), Type::Infer);
                 // Initialize linear resource tracking for linear types
-                checker.linear_resources.insert(name.clone(), 0);
+                let symbol = checker.env.intern(name);
+                checker.linear_resources.insert(symbol, 0);
                 Ok(())
             },
             Pattern::Literal(_) => {
@@ -1434,8 +2466,1022 @@ impl TypeChecker {
     }
 }
 
+/// Whether some arm of a match is sure to be taken
+///
+/// This is deliberately simple: an unguarded arm whose pattern matches anything, or
+/// unguarded arms covering both `true` and `false`. Enum variants aren't counted.
+fn is_exhaustive(arms: &[MatchArm]) -> bool {
+    let unguarded: Vec<&Pattern> = arms.iter()
+        .filter(|(_, guard, _)| guard.is_none())
+        .map(|(pattern, _, _)| pattern)
+        .collect();
+
+    unguarded.iter().any(|pattern| matches_anything(pattern))
+        || [true, false].iter().all(|value| unguarded.iter().any(|pattern| matches_bool(pattern, *value)))
+}
+
+/// Whether a pattern matches every value
+fn matches_anything(pattern: &Pattern) -> bool {
+    match pattern {
+        Pattern::Wildcard | Pattern::Identifier(_) => true,
+        Pattern::Irrefutable(inner) => matches_anything(inner),
+        Pattern::Tuple(items) => items.iter().all(matches_anything),
+        Pattern::Or(left, right) => matches_anything(left) || matches_anything(right),
+        _ => false,
+    }
+}
+
+/// Whether a pattern matches every value of an enum's `variant`
+fn matches_variant(pattern: &Pattern, enum_name: &str, variant: &str) -> bool {
+    match pattern {
+        Pattern::Enum(name, matched, sub_patterns) => {
+            name == enum_name && matched == variant && sub_patterns.iter().flatten().all(matches_anything)
+        },
+        Pattern::Or(left, right) => matches_variant(left, enum_name, variant) || matches_variant(right, enum_name, variant),
+        Pattern::Irrefutable(inner) => matches_variant(inner, enum_name, variant),
+        pattern => matches_anything(pattern),
+    }
+}
+
+/// Whether a pattern matches the boolean `value`
+fn matches_bool(pattern: &Pattern, value: bool) -> bool {
+    match pattern {
+        Pattern::Literal(Expression::Boolean(literal)) => *literal == value,
+        Pattern::Or(left, right) => matches_bool(left, value) || matches_bool(right, value),
+        pattern => matches_anything(pattern),
+    }
+}
+
+/// The type parameters of a generic signature, each with its bounds, in order of appearance
+fn collect_type_params(ty: &Type, found: &mut Vec<(String, Vec<String>)>) {
+    match ty {
+        Type::GenericWithBounds { name, bounds } if !found.iter().any(|(seen, _)| seen == name) => {
+            found.push((name.clone(), bounds.clone()));
+        },
+        Type::Array(inner) | Type::Option(inner) | Type::Set(inner) | Type::Channel(inner) | Type::Linear(inner) => collect_type_params(inner, found),
+        Type::Result(first, second) | Type::Map(first, second) => {
+            collect_type_params(first, found);
+            collect_type_params(second, found);
+        },
+        Type::Tuple(types) => types.iter().for_each(|ty| collect_type_params(ty, found)),
+        Type::Function(params, ret) => {
+            params.iter().for_each(|param| collect_type_params(param, found));
+            collect_type_params(ret, found);
+        },
+        _ => {},
+    }
+}
+
+/// Whether any part of a type is still to be inferred
+fn mentions_infer(ty: &Type) -> bool {
+    match ty {
+        Type::Infer => true,
+        Type::Array(inner) | Type::Option(inner) | Type::Set(inner) | Type::Channel(inner) | Type::Linear(inner) => mentions_infer(inner),
+        Type::Result(first, second) | Type::Map(first, second) => mentions_infer(first) || mentions_infer(second),
+        Type::Tuple(types) => types.iter().any(mentions_infer),
+        Type::Function(params, ret) => params.iter().any(mentions_infer) || mentions_infer(ret),
+        _ => false,
+    }
+}
+
 /// Checks the types in a program
 pub fn check_types(program: &Program) -> Result<(), String> {
     let mut checker = TypeChecker::new();
     checker.check_program(program)
-}
\ No newline at end of file
+}
+
+/// Checks the types in a program under the given compile-time limits
+pub fn check_types_with_limits(program: &Program, limits: &CompileLimits) -> Result<(), String> {
+    let mut checker = TypeChecker::with_limits(*limits);
+    checker.check_program(program)
+}
+
+/// Checks macro-expanded statements, saying which expansion an error came out of
+pub fn check_expanded_with_limits(statements: &[ExpandedStatement], limits: &CompileLimits) -> Result<(), String> {
+    let mut checker = TypeChecker::with_limits(*limits);
+    for item in statements {
+        checker.check_statement(&item.statement).map_err(|e| item.annotate(e))?;
+    }
+    checker.validate_linear_usage()
+}
+
+/// What a checker notes down for `infer_program`, keyed by node address since the AST
+/// keeps no positions of its own
+#[derive(Debug, Default)]
+struct Recording {
+    types: HashMap<*const Expression, Type>,
+    /// The innermost expression an error came out of
+    failed_at: Option<*const Expression>,
+    /// The expected and found types of the mismatch being reported, if that's what it is
+    mismatch: Option<(Type, Type)>,
+    /// Each checked function's declared return type, or the type its body ends with
+    returns: Vec<(String, Type)>,
+}
+
+/// Where an expression sits in a program
+///
+/// The AST carries no source positions, so a span names the top-level statement the
+/// expression is in and its place among that statement's expressions, counted from 0
+/// outermost first. Callers with line numbers map `statement` through
+/// `Parser::parse_program_with_lines`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Span {
+    pub statement: usize,
+    pub expression: usize,
+}
+
+/// The types `infer_program` found in a program
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TypeMap {
+    types: BTreeMap<Span, Type>,
+    returns: Vec<(String, Type)>,
+}
+
+impl TypeMap {
+    /// The type inferred for the expression at `span`
+    pub fn get(&self, span: Span) -> Option<&Type> {
+        self.types.get(&span)
+    }
+
+    /// Every expression's type, in source order
+    pub fn iter(&self) -> impl Iterator<Item = (Span, &Type)> {
+        self.types.iter().map(|(span, ty)| (*span, ty))
+    }
+
+    /// How many expressions have a type
+    pub fn len(&self) -> usize {
+        self.types.len()
+    }
+
+    /// Whether no expression has a type
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+    }
+
+    /// What a function returns: its annotation, or else the type of the value its body ends with
+    pub fn return_type(&self, function: &str) -> Option<&Type> {
+        self.returns.iter().find(|(name, _)| name == function).map(|(_, ty)| ty)
+    }
+
+    /// Every checked function with its return type, in the order they were checked
+    pub fn return_types(&self) -> &[(String, Type)] {
+        &self.returns
+    }
+}
+
+/// A type error from `infer_program`, with the types that conflicted where there were two
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeError {
+    pub message: String,
+    pub expected: Option<Type>,
+    pub found: Option<Type>,
+    /// The top-level statement being checked; None for the whole-program linear usage check
+    pub statement: Option<usize>,
+    /// The innermost expression the error came out of, when it came out of one
+    pub span: Option<Span>,
+}
+
+impl std::fmt::Display for TypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match (self.span, self.statement) {
+            (Some(span), _) => write!(f, "{} (statement {}, expression {})", self.message, span.statement + 1, span.expression + 1),
+            (None, Some(statement)) => write!(f, "{} (statement {})", self.message, statement + 1),
+            (None, None) => f.write_str(&self.message),
+        }
+    }
+}
+
+impl std::error::Error for TypeError {}
+
+/// Type-checks a program, keeping the type inferred for every expression
+///
+/// Checks exactly what `check_types` does; the difference is what comes back. Nodes the
+/// checker only sees in desugared copies, and the bodies of class and impl methods,
+/// have no span and are left out of the map.
+#[allow(clippy::result_large_err)]
+pub fn infer_program(program: &Program) -> Result<TypeMap, TypeError> {
+    let recording = Rc::new(RefCell::new(Recording::default()));
+    let mut checker = TypeChecker { recording: Some(recording.clone()), ..TypeChecker::new() };
+    let mut map = TypeMap::default();
+
+    for (index, statement) in program.statements.iter().enumerate() {
+        let result = checker.check_statement(statement);
+        let spans = expression_spans(index, statement);
+        let mut recording = recording.borrow_mut();
+        for (address, ty) in recording.types.drain() {
+            if let Some(span) = spans.get(&address) {
+                map.types.insert(*span, ty);
+            }
+        }
+        if let Err(message) = result {
+            let (expected, found) = recording.mismatch.take().map_or((None, None), |(expected, found)| (Some(expected), Some(found)));
+            let span = recording.failed_at.and_then(|address| spans.get(&address).copied());
+            return Err(TypeError { message, expected, found, statement: Some(index), span });
+        }
+    }
+
+    checker.validate_linear_usage().map_err(|message| TypeError {
+        message,
+        expected: None,
+        found: None,
+        statement: None,
+        span: None,
+    })?;
+    map.returns = std::mem::take(&mut recording.borrow_mut().returns);
+    Ok(map)
+}
+
+/// Gives each expression in a top-level statement its span, by address
+fn expression_spans(statement_index: usize, statement: &Statement) -> HashMap<*const Expression, Span> {
+    let mut spans = HashMap::new();
+    crate::perf_hints::visit_statement(statement, &mut |expression| {
+        let span = Span { statement: statement_index, expression: spans.len() };
+        spans.entry(expression as *const Expression).or_insert(span);
+    });
+    spans
+}
+
+/// Type-checking results carried from one `check_incremental` call to the next
+#[derive(Debug, Clone, Default)]
+pub struct IncrementalState {
+    /// Fingerprint of every named top-level item, and the names that change with it
+    items: HashMap<String, (u64, Vec<String>)>,
+    /// Cached result of checking each top-level function body
+    functions: HashMap<String, Result<(), String>>,
+    /// How many times each function body has been checked
+    check_counts: HashMap<String, usize>,
+}
+
+impl IncrementalState {
+    /// Creates an empty state; the first check then covers the whole program
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns how many times a function's body has been type-checked
+    pub fn check_count(&self, function: &str) -> usize {
+        self.check_counts.get(function).copied().unwrap_or(0)
+    }
+}
+
+/// Errors reported by `check_incremental`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Diagnostics {
+    pub errors: Vec<String>,
+    pub rechecked: Vec<String>, // Functions checked in this run rather than reused from the cache
+}
+
+impl Diagnostics {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Type-checks an edited program, reusing cached results for functions the edit can't affect
+///
+/// A function body is rechecked when it changed, or when anything it refers to (a
+/// function it calls, a type or global it uses) changed, directly or through other
+/// functions. Everything else at the top level is cheap and is always rechecked, since
+/// it builds the environment function bodies are checked in. Unlike `check_types`,
+/// checking continues past an error, so every failing item is reported.
+pub fn check_incremental(state: &mut IncrementalState, edited_source: &str) -> Diagnostics {
+    let mut diagnostics = Diagnostics::default();
+    let program = match crate::parser::Parser::new(edited_source).parse_program() {
+        Ok(program) => program,
+        Err(e) => {
+            diagnostics.errors.push(e.to_string());
+            return diagnostics;
+        },
+    };
+
+    // Find the items whose definition differs from the previous check, including removed ones
+    let mut items = HashMap::new();
+    let mut changed = HashSet::new();
+    for statement in &program.statements {
+        if let Some((key, names)) = item_names(statement) {
+            let fingerprint = fingerprint(statement);
+            if state.items.get(&key).map(|(previous, _)| *previous) != Some(fingerprint) {
+                changed.extend(names.iter().cloned());
+            }
+            items.insert(key, (fingerprint, names));
+        }
+    }
+    for (key, (_, names)) in &state.items {
+        if !items.contains_key(key) {
+            changed.extend(names.iter().cloned());
+        }
+    }
+
+    // Follow the dependency graph backwards so dependents of a changed item are rechecked too
+    let dependencies: Vec<(&str, Dependencies)> = program.statements.iter()
+        .filter_map(|statement| match statement {
+            Statement::Function(def) => Some((def.name.as_str(), Dependencies::of_function(def))),
+            _ => None,
+        })
+        .collect();
+    let mut dirty = changed;
+    loop {
+        let newly_dirty: Vec<String> = dependencies.iter()
+            .filter(|(name, deps)| !dirty.contains(*name) && deps.touches(&dirty))
+            .map(|(name, _)| name.to_string())
+            .collect();
+        if newly_dirty.is_empty() {
+            break;
+        }
+        dirty.extend(newly_dirty);
+    }
+
+    let mut checker = TypeChecker::new();
+    let resolver = crate::effects::effect_resolver(&program.statements);
+    let mut functions = HashMap::new();
+    for statement in &program.statements {
+        match statement {
+            Statement::Function(def) => {
+                // As in check_statement, so calls check against the signature whether or not it's cached
+                if !def.generic_params.is_empty() {
+                    checker.env.set_type(def.name.clone(), TypeChecker::generic_signature(def));
+                }
+                let cached = state.functions.get(&def.name).filter(|_| !dirty.contains(&def.name));
+                let result = match cached {
+                    Some(result) => result.clone(),
+                    None => {
+                        *state.check_counts.entry(def.name.clone()).or_insert(0) += 1;
+                        diagnostics.rechecked.push(def.name.clone());
+                        checker.check_function(def)
+                            .and_then(|()| crate::effects::check_function_effects(def, &resolver))
+                    },
+                };
+                if let Err(e) = &result {
+                    diagnostics.errors.push(e.clone());
+                }
+                functions.insert(def.name.clone(), result);
+            },
+            other => {
+                if let Err(e) = checker.check_statement(other) {
+                    diagnostics.errors.push(e);
+                }
+            },
+        }
+    }
+    if let Err(e) = checker.validate_linear_usage() {
+        diagnostics.errors.push(e);
+    }
+
+    state.items = items;
+    state.functions = functions;
+    diagnostics
+}
+
+/// Returns the key a top-level item is cached under, and the names that change with it
+///
+/// Changing an impl changes what its trait and type support, so both count as changed.
+fn item_names(statement: &Statement) -> Option<(String, Vec<String>)> {
+    let name = match statement {
+        Statement::Function(def) => def.name.clone(),
+        Statement::Class(def) => def.name.clone(),
+        Statement::Trait(def) => def.name.clone(),
+        Statement::Enum(def) => def.name.clone(),
+        Statement::Actor(def) => def.name.clone(),
+        Statement::Effect(def) => def.name.clone(),
+        Statement::TypeAlias(def) => def.name.clone(),
+        Statement::Extern(def) => def.name.clone(),
+        Statement::LetBinding { name, .. } | Statement::ConstBinding { name, .. } => name.clone(),
+        Statement::Implementation(def) => {
+            let key = format!("impl {} for {}", def.trait_name, def.for_type);
+            return Some((key.clone(), vec![key, def.trait_name.clone(), def.for_type.clone()]));
+        },
+        _ => return None,
+    };
+    Some((name.clone(), vec![name]))
+}
+
+fn fingerprint(statement: &Statement) -> u64 {
+    // The AST holds floats, so it can't derive Hash; its Debug form identifies it just as well.
+    // Locations are left out, or an edit would change every item below it.
+    let mut hasher = DefaultHasher::new();
+    debug_without_locations(statement).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The names a function refers to: functions it calls, types and globals it uses
+///
+/// Constructs the walk doesn't follow set `all`, so the function is rechecked after any change.
+#[derive(Debug, Clone, Default)]
+struct Dependencies {
+    names: HashSet<String>,
+    all: bool,
+}
+
+impl Dependencies {
+    fn of_function(def: &FunctionDef) -> Self {
+        let mut deps = Dependencies::default();
+        deps.add_function(def);
+        deps
+    }
+
+    /// Whether any of the given changed names affects this function
+    fn touches(&self, changed: &HashSet<String>) -> bool {
+        (self.all && !changed.is_empty()) || self.names.iter().any(|name| changed.contains(name))
+    }
+
+    fn add_function(&mut self, def: &FunctionDef) {
+        for param in &def.parameters {
+            self.add_type(&param.type_annotation);
+            if let Some(default) = &param.default_value {
+                self.add_expression(default);
+            }
+        }
+        if let Some(return_type) = &def.return_type {
+            self.add_type(return_type);
+        }
+        self.add_statements(&def.body);
+    }
+
+    fn add_statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.add_statement(statement);
+        }
+    }
+
+    fn add_statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Expression(expr) | Statement::Return(Some(expr)) => self.add_expression(expr),
+            Statement::LetBinding { type_annotation, value, .. } | Statement::ConstBinding { type_annotation, value, .. } => {
+                if let Some(ty) = type_annotation {
+                    self.add_type(ty);
+                }
+                self.add_expression(value);
+            },
+            Statement::Block(statements) => self.add_statements(statements),
+            Statement::Defer(deferred) => self.add_statement(deferred),
+            Statement::Assert { actual, expected, message, .. } => {
+                self.add_expression(actual);
+                expected.iter().chain(message).for_each(|expr| self.add_expression(expr));
+            },
+            Statement::Function(def) => self.add_function(def),
+            Statement::While { condition, body, .. } => {
+                self.add_expression(condition);
+                self.add_statements(body);
+            },
+            Statement::Return(None) | Statement::Break(_) | Statement::Continue(_) => {},
+            _ => self.all = true,
+        }
+    }
+
+    fn add_expression(&mut self, expr: &Expression) {
+        match expr {
+            Expression::Integer(_) | Expression::Float(_) | Expression::String(_) | Expression::Bytes(_)
+                | Expression::Boolean(_) | Expression::Nil | Expression::Char(_) => {},
+            Expression::Index(target, index) => {
+                self.add_expression(target);
+                self.add_expression(index);
+            },
+            Expression::Slice(target, start, end) => {
+                self.add_expression(target);
+                self.add_expression(start);
+                self.add_expression(end);
+            },
+            Expression::Identifier(name) => {
+                self.names.insert(name.clone());
+            },
+            Expression::Call(name, args) => {
+                self.names.insert(name.clone());
+                args.iter().for_each(|arg| self.add_expression(arg));
+            },
+            Expression::Struct(name, fields) => {
+                self.names.insert(name.clone());
+                fields.iter().for_each(|(_, value)| self.add_expression(value));
+            },
+            Expression::Array(items) | Expression::Tuple(items) => items.iter().for_each(|item| self.add_expression(item)),
+            Expression::ArrayRepeat(left, right) | Expression::BinaryOp(left, _, right, _) => {
+                self.add_expression(left);
+                self.add_expression(right);
+            },
+            Expression::UnaryOp(_, operand) | Expression::FieldAccess(operand, _) | Expression::Propagate(operand) => self.add_expression(operand),
+            Expression::MethodCall(receiver, _, args) => {
+                self.add_expression(receiver);
+                args.iter().for_each(|arg| self.add_expression(arg));
+            },
+            Expression::If(condition, then_branch, else_branch) => {
+                self.add_expression(condition);
+                self.add_statements(then_branch);
+                self.add_statements(else_branch);
+            },
+            Expression::Block(statements) | Expression::BlockExpr(statements) => self.add_statements(statements),
+            Expression::TryCatch(body, _, handler) => {
+                self.add_statements(body);
+                self.add_statements(handler);
+            },
+            Expression::InterpolatedString(parts) => parts.iter().for_each(|part| {
+                if let StringPart::Interpolated(expr, _) = part {
+                    self.add_expression(expr);
+                }
+            }),
+            Expression::Lambda(params, body) => {
+                params.iter().for_each(|param| self.add_type(&param.type_annotation));
+                self.add_statements(body);
+            },
+            _ => self.all = true,
+        }
+    }
+
+    fn add_type(&mut self, ty: &Type) {
+        match ty {
+            Type::Named(name) | Type::Dyn(name) => {
+                self.names.insert(name.clone());
+            },
+            Type::Array(inner) | Type::Channel(inner) | Type::Linear(inner) | Type::Set(inner) | Type::Option(inner) => {
+                self.add_type(inner)
+            },
+            Type::Map(key, value) | Type::Result(key, value) => {
+                self.add_type(key);
+                self.add_type(value);
+            },
+            Type::Tuple(types) => types.iter().for_each(|ty| self.add_type(ty)),
+            Type::Function(params, ret) => {
+                params.iter().for_each(|ty| self.add_type(ty));
+                self.add_type(ret);
+            },
+            Type::GenericWithBounds { bounds, .. } => self.names.extend(bounds.iter().cloned()),
+            // Dependent types embed expressions
+            Type::Pi(_, _) | Type::Sigma(_, _) | Type::Equality(_, _, _) => self.all = true,
+            _ => {},
+        }
+    }
+}
+/// Suggests converting explicitly when a comparison mixes Int and Float
+fn conversion_hint(left: &Type, right: &Type) -> &'static str {
+    match (left, right) {
+        (Type::Int, Type::Float) | (Type::Float, Type::Int) => "; convert one side with to_float() or to_int()",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    const DRAWABLE: &str = "trait Drawable {\n fn draw(self: Drawable) -> String {}\n}\n\
+                            impl Drawable for Int {\n fn draw(self: Int) -> String { \"int\" }\n}\n\
+                            impl Drawable for String {\n fn draw(self: String) -> String { \"text\" }\n}\n";
+
+    fn check(source: &str) -> Result<(), String> {
+        let program = Parser::new(source).parse_program()?;
+        check_types(&program)
+    }
+
+    #[test]
+    fn test_dyn_accepts_implementing_types() {
+        assert!(check(&format!("{}let shapes: [dyn Drawable] = [1, \"two\"]", DRAWABLE)).is_ok());
+        assert!(check(&format!("{}let shape: dyn Drawable = 1", DRAWABLE)).is_ok());
+    }
+
+    #[test]
+    fn test_dyn_rejects_non_implementing_types() {
+        let err = check(&format!("{}let shapes: [dyn Drawable] = [1, true]", DRAWABLE)).unwrap_err();
+        assert!(err.contains("Type 'Bool' does not implement trait 'Drawable'"), "{}", err);
+    }
+
+    #[test]
+    fn test_trait_method_calls_resolve_to_their_return_type() {
+        assert!(check(&format!("{}let n = 1\nlet label: String = n.draw()", DRAWABLE)).is_ok());
+        let err = check(&format!("{}let label: Int = \"x\".draw()", DRAWABLE)).unwrap_err();
+        assert!(err.contains("expected Int, found String"), "{}", err);
+    }
+
+    #[test]
+    fn test_method_provided_by_two_traits_is_ambiguous() {
+        let source = format!("{}trait Sketch {{\n fn draw(self: Sketch) -> String {{}}\n}}\n\
+                              impl Sketch for Int {{\n fn draw(self: Int) -> String {{ \"sketch\" }}\n}}\n\
+                              let n = 1\n\
+                              let label = n.draw()", DRAWABLE);
+        let err = check(&source).unwrap_err();
+        assert_eq!(err, "Method 'draw' on type 'Int' is ambiguous: it is provided by traits 'Drawable', 'Sketch'");
+
+        // Only Drawable is implemented for String, so the same call there is fine
+        assert!(check(&format!("{}let label = \"x\".draw()", DRAWABLE)).is_ok());
+    }
+
+    #[test]
+    fn test_env_builtins_are_typed() {
+        assert!(check("let first: Option<String> = env::arg(0)\nlet all: [String] = env::args()").is_ok());
+        let err = check("let first = env::arg(\"0\")").unwrap_err();
+        assert!(err.contains("Argument 1 of env::arg: expected Int, found String"), "{}", err);
+    }
+
+    #[test]
+    fn test_missing_trait_methods_render_deterministically() {
+        let source = "trait Shape {\n fn perimeter(self: Int) -> Int {}\n fn area(self: Int) -> Int {}\n fn name(self: Int) -> String {}\n}\n\
+                      impl Shape for Int {}";
+        let first = check(source).unwrap_err();
+        assert_eq!(first, "Missing implementations for required methods 'area', 'name', 'perimeter' in trait 'Shape'");
+        for _ in 0..5 {
+            assert_eq!(check(source).unwrap_err(), first);
+        }
+
+        let err = check("let flag: Bool = Some(1)").unwrap_err();
+        assert!(err.contains("expected Bool, found Option<Int>"), "{}", err);
+    }
+
+    #[test]
+    fn test_self_referential_alias_hits_type_depth_limit() {
+        let err = check("type Loop = Option<Loop>").unwrap_err();
+        assert_eq!(err, "type recursion limit reached while expanding alias 'Loop' (limit is 128 levels)");
+
+        // Aliases that only refer to other aliases expand fine within the limit
+        assert!(check("type Id = Int\ntype Ids = [Id]\ntype Lookup = Map<String, Ids>").is_ok());
+
+        let program = Parser::new("type A = Int\ntype B = Option<A>\ntype C = [B]").parse_program().unwrap();
+        let shallow = CompileLimits { type_depth: 2, ..CompileLimits::default() };
+        let err = check_types_with_limits(&program, &shallow).unwrap_err();
+        assert!(err.contains("while expanding alias 'C' (limit is 2 levels)"), "{}", err);
+    }
+
+    #[test]
+    fn test_long_flat_programs_are_within_the_type_depth() {
+        // Nesting that comes from a long list of operands or arms is not type depth
+        let sum = format!("let x = 1{}", " + 1".repeat(140));
+        assert!(crate::check_syntax_and_types(&sum).is_ok());
+
+        let mut chain = String::from("let n = 3\nlet label = if n == 0 { \"0\" }");
+        for arm in 1..70 {
+            chain.push_str(&format!(" else if n == {} {{ \"{}\" }}", arm, arm));
+        }
+        chain.push_str(" else { \"many\" }");
+        assert!(crate::check_syntax_and_types(&chain).is_ok());
+    }
+
+    #[test]
+    fn test_block_expression_types() {
+        assert!(check("let x: Int = { let a = 1; a + 2 }").is_ok());
+        assert!(check("let nested: String = { let a = 1\n { \"inner\" } }").is_ok());
+
+        let err = check("let s: String = { let a = 1; a + 2 }").unwrap_err();
+        assert_eq!(err, "Type mismatch: expected String, found Int for variable 's'");
+
+        // A block ending in a statement has no value
+        let err = check("let x: Int = { let a = 1 }").unwrap_err();
+        assert_eq!(err, "Type mismatch: expected Int, found Unit for variable 'x'");
+
+        // Bindings made in a block end with it
+        let err = check("let x = { let a = 1; a }\nlet y = a").unwrap_err();
+        assert_eq!(err, "Undefined variable: a");
+    }
+
+    #[test]
+    fn test_if_expression_types() {
+        assert!(check("let a = 3\nlet b = 4\nlet max: Int = if a > b { a } else { b }").is_ok());
+        assert!(check("let n = 2\nlet label: String = if n > 1 { \"many\" } else if n == 1 { \"one\" } else { \"none\" }").is_ok());
+        assert!(check("let n = 2\nlet found: Option<Int> = if n > 1 { Some(n) } else { None }").is_ok());
+
+        let err = check("let n = 2\nlet x = if n > 1 { 1 } else { \"one\" }").unwrap_err();
+        assert_eq!(err, "If and else branches have different types: Int and String");
+
+        // Without an else there is no value
+        let err = check("let n = 2\nlet x: Int = if n > 1 { 1 }").unwrap_err();
+        assert_eq!(err, "Type mismatch: expected Int, found Unit for variable 'x'");
+
+        // Branches that leave don't need a value, and statement ifs needn't agree
+        assert!(check("fn clamp(n: Int) -> Int {\n let x = if n < 0 { return 0 } else { n }\n x\n}").is_ok());
+        assert!(check("let n = 2\nif n > 1 { print(\"big\") } else { n + 1 }").is_ok());
+    }
+
+    #[test]
+    fn test_match_expression_types() {
+        assert!(check("let n = 0\nlet label: String = match n { 0 => \"zero\", _ => \"other\" }").is_ok());
+        assert!(check("let ok = true\nlet code: Int = match ok { true => 0, false => 1 }").is_ok());
+        assert!(check("let n = 3\nlet m: Int = match n { 0 => { 0 } x => { x } }").is_ok());
+
+        let err = check("let n = 0\nlet x = match n { 0 => 1, _ => \"one\" }").unwrap_err();
+        assert_eq!(err, "Match arms have different types: Int and String");
+
+        let err = check("let n = 0\nlet x = match n { 0 => 1, 1 => 2 }").unwrap_err();
+        assert_eq!(err, "Non-exhaustive match used as a value: add a `_` arm");
+        let err = check("let n = 0\nlet big = true\nlet x = match n { y if big => 1 }").unwrap_err();
+        assert_eq!(err, "Non-exhaustive match used as a value: add a `_` arm");
+
+        // A match used as a statement has no value to agree on
+        assert!(check("let n = 0\nmatch n { 0 => print(\"zero\"), 1 => 2 }").is_ok());
+    }
+
+    fn linear_fixture(name: &str) -> Result<(), String> {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/linear").join(name);
+        check(&std::fs::read_to_string(path).unwrap())
+    }
+
+    #[test]
+    fn test_linear_values_are_used_exactly_once() {
+        assert_eq!(linear_fixture("moved_twice.logos"), Err("Linear variable 'log' used more than once".to_string()));
+        assert_eq!(linear_fixture("unconsumed.logos"), Err("Linear resource 'log' was not consumed by the end of size".to_string()));
+
+        // A use in a branch still counts against an earlier one
+        let file = "struct File { fd: Int }\nfn keep(f: !File) -> !File { f }\n";
+        assert!(check(&format!("{}fn pass(f: !File) -> !File {{ keep(f) }}", file)).is_ok());
+        let err = check(&format!("{}fn twice(f: !File) -> !File {{\n let g = keep(f)\n if true {{ keep(f) }} else {{ g }}\n}}", file)).unwrap_err();
+        assert_eq!(err, "Linear variable 'f' used more than once");
+    }
+
+    #[test]
+    fn test_matches_must_cover_booleans_and_enum_variants() {
+        let err = check("let ok = true\nmatch ok { true => print(\"yes\") }").unwrap_err();
+        assert_eq!(err, "Non-exhaustive match on Bool: false not handled");
+        // A guarded arm may not be taken
+        let err = check("let ok = true\nlet n = 1\nmatch ok { true => 1, false if n > 0 => 2 }").unwrap_err();
+        assert_eq!(err, "Non-exhaustive match on Bool: false not handled");
+
+        let shape = "enum Shape { Circle(Float), Square(Float), Empty }\nfn area(s: Shape) -> Float {\n";
+        let err = check(&format!("{}match s {{ Shape::Circle(_) => 1.0, Shape::Empty => 0.0 }}\n}}", shape)).unwrap_err();
+        assert_eq!(err, "Non-exhaustive match on Shape: Shape::Square not handled");
+        assert!(check(&format!("{}match s {{ Shape::Circle(_) | Shape::Square(_) => 1.0, Shape::Empty => 0.0 }}\n}}", shape)).is_ok());
+        assert!(check(&format!("{}match s {{ Shape::Empty => 0.0, _ => 1.0 }}\n}}", shape)).is_ok());
+
+        // Arms after a catch-all can never be taken
+        let checker = TypeChecker::new();
+        let program = Parser::new("match 1 { 0 => 1, n => n, 2 => 3, _ => 0 }").parse_program().unwrap();
+        let Statement::Expression(Expression::Match(_, arms)) = &program.statements[0] else { panic!("expected a match") };
+        assert_eq!(checker.check_match_exhaustiveness(&Type::Int, arms), Err(vec![
+            "Unreachable match arm `2`: the `n` arm before it matches everything".to_string(),
+            "Unreachable match arm `_`: the `n` arm before it matches everything".to_string(),
+        ]));
+    }
+
+    #[test]
+    fn test_comparisons_need_matching_types() {
+        assert!(check("let same = [1, 2] == [1, 2]").is_ok());
+        assert!(check("let before = (1, \"a\") < (2, \"b\")").is_ok());
+        assert!(check("let order: Int = 1 <> 2").is_ok());
+
+        let err = check("let x = 1 == 1.0").unwrap_err();
+        assert_eq!(err, "Cannot compare Int with Float; convert one side with to_float() or to_int()");
+        assert!(check("let x = [1] < [2.0]").is_err());
+    }
+
+    #[test]
+    fn test_numeric_conversion_types() {
+        assert!(check("let x: Float = to_float(1) + 0.5").is_ok());
+        assert!(check("let n: Int = to_int(2.5) + 1").is_ok());
+        assert!(check("let parsed: Result<Int, String> = parse_int(\"7\")").is_ok());
+        assert_eq!(check("let x = to_int(\"7\")").unwrap_err(), "to_int() expects an Int or Float, found String");
+    }
+
+    fn check_derived(source: &str) -> Result<(), String> {
+        let program = Parser::new(source).parse_program()?;
+        check_types(&crate::derive::expand_derives(program)?)
+    }
+
+    #[test]
+    fn test_struct_map_keys_require_eq_and_hash() {
+        let ok = "#[derive(Eq, Hash)]\nstruct Point { x: Int, y: Int }\nlet seen: Map<Point, Int> = Map::new()";
+        assert!(check_derived(ok).is_ok(), "{:?}", check_derived(ok));
+
+        let missing = "#[derive(Eq)]\nstruct Point { x: Int }\nlet seen: Set<Point> = Set::new()";
+        let err = check_derived(missing).unwrap_err();
+        assert!(err.contains("Type 'Point' cannot be used as a map key: it does not implement Hash"), "{}", err);
+    }
+
+    #[test]
+    fn test_channel_sends_must_match_the_element_type() {
+        let ok = "let ch: Channel<Int> = chan Int\nch <- 1\nch.send(2)\nlet n: Int = ch.recv() + <-ch";
+        assert!(check(ok).is_ok(), "{:?}", check(ok));
+
+        let err = check("let ch = chan Int\nch.send(\"seven\")").unwrap_err();
+        assert!(err.contains("Channel send: expected Int, found String"), "{}", err);
+        let err = check("let ch = chan Int\nch <- true").unwrap_err();
+        assert!(err.contains("Channel send: expected Int, found Bool"), "{}", err);
+        let err = check("let ch = chan String\nlet n: Int = ch.recv()").unwrap_err();
+        assert!(err.contains("String"), "{}", err);
+    }
+
+    #[test]
+    fn test_select_bindings_take_the_channel_element_type() {
+        let ok = "let a = chan Int\nlet n: Int = select { v = a.recv() => v + 1, timeout(5) => 0 }";
+        assert!(check(ok).is_ok(), "{:?}", check(ok));
+
+        let err = check("let a = chan String\nlet n: Int = select { v = a.recv() => v, default => 0 }").unwrap_err();
+        assert!(err.contains("String"), "{}", err);
+        let err = check("let a = chan Int\nselect { a.send(\"x\") => 1, default => 0 }").unwrap_err();
+        assert!(err.contains("Select send: expected Int, found String"), "{}", err);
+        let err = check("let a = chan Int\nselect { v = a.recv() => v, timeout(\"soon\") => 0 }").unwrap_err();
+        assert!(err.contains("Select timeout: expected Int milliseconds"), "{}", err);
+    }
+
+    #[test]
+    fn test_extern_calls_are_checked_against_the_signature() {
+        let decl = "extern \"c\" fn c_sqrt(x: Float) -> Float from \"libm\"\n";
+        let ok = format!("{}let r: Float = c_sqrt(16.0)\nlet s: Float = c_sqrt(9)", decl);
+        assert!(check(&ok).is_ok(), "{:?}", check(&ok));
+
+        let err = check(&format!("{}c_sqrt(\"16\")", decl)).unwrap_err();
+        assert!(err.contains("Argument 1 of c_sqrt: expected Float, found String"), "{}", err);
+        let err = check(&format!("{}c_sqrt(1.0, 2.0)", decl)).unwrap_err();
+        assert!(err.contains("Function c_sqrt expects 1 arguments, found 2"), "{}", err);
+        let err = check(&format!("{}let r: String = c_sqrt(4.0)", decl)).unwrap_err();
+        assert!(err.contains("String"), "{}", err);
+
+        let err = check("extern \"c\" fn f(xs: [Int]) from \"libc\"").unwrap_err();
+        assert!(err.contains("extern fn f can't pass [Int] to foreign code"), "{}", err);
+    }
+
+    #[test]
+    fn test_unhashable_key_types_are_rejected() {
+        let err = check("let m: Map<Float, Int> = Map::new()").unwrap_err();
+        assert!(err.contains("floats are not hashable"), "{}", err);
+
+        let err = check("fn f(m: Map<[Float], Int>) {}").unwrap_err();
+        assert!(err.contains("floats are not hashable"), "{}", err);
+    }
+
+    #[test]
+    fn test_propagate_requires_compatible_return_type() {
+        assert!(check("fn first() -> Option<Int> { let x = Some(1)?\n Some(x) }").is_ok());
+        assert!(check("fn read() -> Result<Int, String> { let x = Ok(1)?\n Ok(x) }").is_ok());
+
+        let err = check("fn first() -> Int { let x = Some(1)?\n x }").unwrap_err();
+        assert!(err.contains("cannot propagate Option<Int> out of a function returning Int"), "{}", err);
+
+        let err = check("fn read() -> Result<Int, String> { let x = Err(1)?\n Ok(x) }").unwrap_err();
+        assert!(err.contains("out of a function returning Result<Int, String>"), "{}", err);
+
+        let err = check("let x = Some(1)?").unwrap_err();
+        assert!(err.contains("can only be used inside a function"), "{}", err);
+    }
+
+    #[test]
+    fn test_break_to_undefined_label_is_rejected() {
+        assert!(check("'outer: while true { while true { break 'outer } }").is_ok());
+
+        let err = check("'outer: while true { while true { continue 'inner } }").unwrap_err();
+        assert!(err.contains("Undefined loop label 'inner"), "{}", err);
+
+        // Labels don't reach into functions defined inside the loop
+        let err = check("'outer: while true { fn f() { break 'outer } }").unwrap_err();
+        assert!(err.contains("Undefined loop label 'outer"), "{}", err);
+    }
+
+    #[test]
+    fn test_incremental_check_skips_unrelated_functions() {
+        let source = "fn helper(x: Int) -> Int { x + 1 }\n\
+                      fn uses_helper(y: Int) -> Int { helper(y) }\n\
+                      fn unrelated(s: String) -> String { s }";
+        let mut state = IncrementalState::new();
+        assert!(check_incremental(&mut state, source).is_ok());
+        assert_eq!(state.check_count("unrelated"), 1);
+
+        // Editing helper rechecks it and its caller, but not the unrelated function
+        let edited = source.replace("x + 1", "x + 2");
+        let diagnostics = check_incremental(&mut state, &edited);
+        assert!(diagnostics.is_ok(), "{:?}", diagnostics);
+        assert_eq!(diagnostics.rechecked, vec!["helper", "uses_helper"]);
+        assert_eq!(state.check_count("helper"), 2);
+        assert_eq!(state.check_count("unrelated"), 1);
+
+        // Moving items down the file isn't a change to them, even where operators record their location
+        let shifted = format!("\n\n{}", edited.replace("{ s }", "{ s + \"\" }"));
+        let diagnostics = check_incremental(&mut state, &shifted);
+        assert_eq!(diagnostics.rechecked, vec!["unrelated"]);
+        assert!(check_incremental(&mut state, &format!("\n{}", shifted)).rechecked.is_empty());
+
+        // A cached failure is still reported after an unrelated edit
+        let broken = edited.replace("{ s }", "{ missing }");
+        assert!(check_incremental(&mut state, &broken).errors[0].contains("Undefined variable: missing"));
+        let diagnostics = check_incremental(&mut state, &broken.replace("x + 2", "x + 3"));
+        assert_eq!(diagnostics.rechecked, vec!["helper", "uses_helper"]);
+        assert_eq!(diagnostics.errors.len(), 1);
+    }
+
+    #[test]
+    fn test_incremental_check_reports_undeclared_effects() {
+        let source = "effect Log { fn log(message: String) {} }\n\
+                      fn greet(name: String) with Log { log(name) }\n\
+                      fn welcome() { greet(\"you\") }";
+        let expected = "function 'welcome' performs effect 'Log' but does not declare it";
+        assert_eq!(check(source).unwrap_err(), expected);
+
+        let mut state = IncrementalState::new();
+        assert_eq!(check_incremental(&mut state, source).errors, vec![expected]);
+
+        // Declaring the effect fixes it; dropping it from greet again breaks its caller
+        let declared = source.replace("fn welcome()", "fn welcome() with Log");
+        assert!(check_incremental(&mut state, &declared).is_ok());
+        let undeclared = source.replace("with Log { log", "{ log").replace("fn welcome()", "fn welcome() with Log");
+        let diagnostics = check_incremental(&mut state, &undeclared);
+        assert_eq!(diagnostics.rechecked, vec!["greet", "welcome"]);
+        assert_eq!(diagnostics.errors, vec!["function 'greet' performs effect 'Log' but does not declare it"]);
+    }
+
+    #[test]
+    fn test_incremental_check_matches_check_types_on_generic_calls() {
+        let source = "struct Point { x: Int }\n\
+                      fn key<K: Eq + Hash>(k: K) -> Int { 0 }\n\
+                      fn lookup(p: Point) -> Int { key(p) }\n\
+                      fn other() -> Int { 1 }";
+        let expected = "Point does not implement Eq, which key's type parameter K requires";
+        assert_eq!(check(source).unwrap_err(), expected);
+
+        let mut state = IncrementalState::new();
+        assert_eq!(check_incremental(&mut state, source).errors, vec![expected]);
+
+        // The caller is rechecked against key's signature even while key itself is cached
+        let edited = source.replace("key(p)", "key(p) + 0");
+        let diagnostics = check_incremental(&mut state, &edited);
+        assert_eq!(diagnostics.rechecked, vec!["lookup"]);
+        assert_eq!(diagnostics.errors, vec![expected]);
+    }
+
+    #[test]
+    fn test_infer_program_types_expressions_and_returns() {
+        let source = "let xs = [1, 2]\nlet first = xs[0]\n\
+                      fn double(n: Int) { n * 2 }\n\
+                      fn label(n: Int) -> String { \"n\" }\n\
+                      fn pick(flag: Bool) { if flag { 1.5 } else { 2.5 } }";
+        let program = Parser::new(source).parse_program().unwrap();
+        let types = infer_program(&program).unwrap();
+
+        let at = |statement, expression| types.get(Span { statement, expression });
+        assert_eq!(at(0, 0), Some(&Type::Array(Box::new(Type::Int))));
+        assert_eq!(at(0, 1), Some(&Type::Int));
+        // `xs[0]`, then `xs` itself: an unannotated binding has its value's type
+        assert_eq!(at(1, 0), Some(&Type::Int));
+        assert_eq!(at(1, 1), Some(&Type::Array(Box::new(Type::Int))));
+
+        assert_eq!(types.return_type("double"), Some(&Type::Int));
+        assert_eq!(types.return_type("label"), Some(&Type::String));
+        assert_eq!(types.return_type("pick"), Some(&Type::Float));
+        assert_eq!(types.return_types().len(), 3);
+    }
+
+    #[test]
+    fn test_binding_left_to_inference_takes_the_value_type() {
+        let program = Program::new(vec![
+            Statement::LetBinding {
+                mutable: false,
+                name: "x".to_string(),
+                type_annotation: Some(Type::Infer),
+                value: Expression::string("hi".to_string()),
+                ownership_modifier: None,
+                lifetime_annotation: None,
+            },
+            Statement::expr(Expression::ident("x".to_string())),
+        ]);
+        let types = infer_program(&program).unwrap();
+        assert_eq!(types.get(Span { statement: 1, expression: 0 }), Some(&Type::String));
+    }
+
+    #[test]
+    fn test_type_errors_carry_the_conflict_and_location() {
+        let program = Parser::new("let ok = 1\nlet n: Int = \"text\"").parse_program().unwrap();
+        let error = infer_program(&program).unwrap_err();
+        assert_eq!(error.expected, Some(Type::Int));
+        assert_eq!(error.found, Some(Type::String));
+        assert_eq!(error.statement, Some(1));
+        assert_eq!(error.to_string(), "Type mismatch: expected Int, found String for variable 'n' (statement 2)");
+
+        // An error inside an expression points at the innermost part that failed
+        let program = Parser::new("let a = 1\nlet b = a + missing").parse_program().unwrap();
+        let error = infer_program(&program).unwrap_err();
+        assert_eq!(error.span, Some(Span { statement: 1, expression: 2 }));
+        assert_eq!((error.expected, error.found), (None, None));
+        assert!(check_types(&program).is_err());
+    }
+
+    #[test]
+    fn test_generic_functions_are_instantiated_per_call() {
+        let source = "struct Point { x: Int }\n\
+                      fn id<T>(x: T) -> T { x }\n\
+                      fn key<K: Eq + Hash>(k: K) -> Int { 0 }\n\
+                      fn second<A, B>(a: A, b: [B]) -> B { b[0] }";
+        let program = Parser::new(source).parse_program().unwrap();
+        match &program.statements[2] {
+            Statement::Function(def) => {
+                assert_eq!(def.generic_params[0].name, "K");
+                assert_eq!(def.generic_params[0].bounds, vec!["Eq", "Hash"]);
+            },
+            other => panic!("expected a function, found {:?}", other),
+        }
+
+        let mut checker = TypeChecker::new();
+        checker.check_program(&program).unwrap();
+        let mut type_of = |expr: &str| {
+            let program = Parser::new(expr).parse_program()?;
+            match &program.statements[..] {
+                [Statement::Expression(expr)] => checker.type_of(expr),
+                _ => Err(format!("expected one expression: {}", expr)),
+            }
+        };
+        assert_eq!(type_of("id(5)"), Ok(Type::Int));
+        assert_eq!(type_of("id(\"a\")"), Ok(Type::String));
+        assert_eq!(type_of("second(1, [true])"), Ok(Type::Bool));
+        assert_eq!(type_of("key(\"k\")"), Ok(Type::Int));
+
+        // A bound the inferred type doesn't meet is an error at the call
+        assert_eq!(type_of("key(1.5)").unwrap_err(), "Float does not implement Hash, which key's type parameter K requires");
+        assert_eq!(
+            check(&format!("{}\nfn lookup(p: Point) -> Int {{ key(p) }}", source)).unwrap_err(),
+            "Point does not implement Eq, which key's type parameter K requires"
+        );
+        assert!(check(&format!("{}\nfn twice<T>(a: T, b: T) -> T {{ a }}\ntwice(1, \"two\")", source))
+            .unwrap_err()
+            .contains("Argument 2 of twice: expected Int, found String"));
+    }
+}
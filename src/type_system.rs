@@ -285,7 +285,7 @@ impl AdvancedTypeChecker {
                     return Err(format!("Variable '{}' not found in environment", name));
                 }
             },
-            ast::Expression::BinaryOp(left, op, right) => {
+            ast::Expression::BinaryOp(left, _, right, _) => {
                 let left_type = self.infer_type(left)?;
                 let right_type = self.infer_type(right)?;
 
@@ -0,0 +1,262 @@
+// Logos Watch Mode
+// Re-runs a command whenever its input file, or a local file it imports with
+// `@import("...")` directly or through other imports, changes on disk. Files are polled for their modification time and
+// size, which works the same on every platform and needs no OS notification API.
+// Editors often save in bursts of events (truncate, write, rename over the old file),
+// so changes are debounced: the command runs once the files have been quiet for a
+// short delay. A file that disappears is waited for rather than treated as an error,
+// since saving by delete-and-recreate briefly leaves it missing.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often watched files are checked for changes
+pub const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long files must be quiet after a change before the command re-runs
+pub const DEBOUNCE_DELAY: Duration = Duration::from_millis(200);
+
+/// Coalesces bursts of change events into a single run
+#[derive(Debug, Clone)]
+pub struct Debouncer {
+    delay: Duration,
+    last_event: Option<Instant>,
+}
+
+impl Debouncer {
+    pub fn new(delay: Duration) -> Self {
+        Debouncer { delay, last_event: None }
+    }
+
+    /// Records a change event; each event restarts the quiet period
+    pub fn record(&mut self, now: Instant) {
+        self.last_event = Some(now);
+    }
+
+    /// Returns true, once, when the quiet period after the last event has passed
+    pub fn ready(&mut self, now: Instant) -> bool {
+        match self.last_event {
+            Some(last) if now.duration_since(last) >= self.delay => {
+                self.last_event = None;
+                true
+            },
+            _ => false,
+        }
+    }
+}
+
+/// What a watched file looked like when it was last polled; `None` if it was missing
+type Stamp = Option<(SystemTime, u64)>;
+
+/// Polls a set of files and reports the ones that changed since the last poll
+#[derive(Debug, Clone, Default)]
+pub struct FileWatcher {
+    stamps: HashMap<PathBuf, Stamp>,
+}
+
+impl FileWatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the watched files, keeping what is known about files watched before
+    pub fn set_paths(&mut self, paths: Vec<PathBuf>) {
+        let mut stamps = HashMap::new();
+        for path in paths {
+            let stamp = self.stamps.remove(&path).unwrap_or_else(|| stamp(&path));
+            stamps.insert(path, stamp);
+        }
+        self.stamps = stamps;
+    }
+
+    /// Returns the files that were modified, deleted or recreated since the last poll
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        for (path, previous) in self.stamps.iter_mut() {
+            let current = stamp(path);
+            if current != *previous {
+                *previous = current;
+                changed.push(path.clone());
+            }
+        }
+        changed.sort();
+        changed
+    }
+}
+
+fn stamp(path: &Path) -> Stamp {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Returns the local files a program imports with `@import("...")`, resolved against `base_dir`
+///
+/// The source is scanned as text rather than parsed, so imports are still found while
+/// the file is mid-edit and doesn't parse. URLs and GitHub repositories are skipped.
+pub fn imported_files(source: &str, base_dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut rest = source;
+
+    while let Some(start) = rest.find("@import(\"") {
+        rest = &rest[start + "@import(\"".len()..];
+        let end = match rest.find('"') {
+            Some(end) => end,
+            None => break,
+        };
+        let resource = &rest[..end];
+        rest = &rest[end..];
+
+        if !resource.contains("://") {
+            let path = base_dir.join(resource);
+            if !files.contains(&path) {
+                files.push(path);
+            }
+        }
+    }
+
+    files
+}
+
+/// Returns `path` and every local file it imports, following imports of imports
+///
+/// Each import is resolved against the directory of the file containing it, as
+/// modules are loaded. `source` is the contents of `path`; imported files are read
+/// from disk, and one that can't be read is still listed so it is picked up once it
+/// appears. Import cycles are followed only once.
+pub fn watched_files(path: &Path, source: &str) -> Vec<PathBuf> {
+    let base_dir = |file: &Path| file.parent().map(Path::to_path_buf).unwrap_or_default();
+    // `lib/../a.logos` and `a.logos` are the same file
+    let identity = |file: &Path| file.canonicalize().unwrap_or_else(|_| file.to_path_buf());
+    let mut files = vec![path.to_path_buf()];
+    let mut visited = HashSet::from([identity(path)]);
+    let mut pending = imported_files(source, &base_dir(path));
+
+    while let Some(file) = pending.pop() {
+        if !visited.insert(identity(&file)) {
+            continue;
+        }
+        if let Ok(source) = fs::read_to_string(&file) {
+            pending.extend(imported_files(&source, &base_dir(&file)));
+        }
+        files.push(file);
+    }
+
+    files
+}
+
+/// Runs `run` with the contents of `path` now and again after every change to it or its imports
+///
+/// Never returns unless reading the file fails for a reason other than it being missing.
+pub fn watch_file<F>(path: &Path, mut run: F) -> Result<(), String>
+where
+    F: FnMut(&str),
+{
+    let mut watcher = FileWatcher::new();
+    let mut debouncer = Debouncer::new(DEBOUNCE_DELAY);
+    let mut waiting_for_file = false;
+    let mut first_run = true;
+
+    loop {
+        if !watcher.poll().is_empty() {
+            debouncer.record(Instant::now());
+        }
+
+        if std::mem::take(&mut first_run) || debouncer.ready(Instant::now()) {
+            match fs::read_to_string(path) {
+                Ok(source) => {
+                    waiting_for_file = false;
+                    watcher.set_paths(watched_files(path, &source));
+                    run(&source);
+                },
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    // Keep watching the path so the file is picked up when it comes back
+                    watcher.set_paths(vec![path.to_path_buf()]);
+                    if !waiting_for_file {
+                        eprintln!("{} was removed; waiting for it to reappear...", path.display());
+                        waiting_for_file = true;
+                    }
+                },
+                Err(e) => return Err(format!("Could not read file '{}': {}", path.display(), e)),
+            }
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debouncer_coalesces_bursts() {
+        let start = Instant::now();
+        let ms = |n| start + Duration::from_millis(n);
+        let mut debouncer = Debouncer::new(Duration::from_millis(50));
+
+        assert!(!debouncer.ready(ms(0)));
+
+        // Three events in quick succession produce one run, 50ms after the last of them
+        debouncer.record(ms(0));
+        debouncer.record(ms(20));
+        assert!(!debouncer.ready(ms(60)));
+        debouncer.record(ms(60));
+        assert!(!debouncer.ready(ms(100)));
+        assert!(debouncer.ready(ms(110)));
+        assert!(!debouncer.ready(ms(500)));
+    }
+
+    #[test]
+    fn test_watcher_reports_modified_deleted_and_recreated_files() {
+        let dir = std::env::temp_dir().join(format!("logos-watch-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("main.logos");
+        fs::write(&file, "print(1)").unwrap();
+
+        let mut watcher = FileWatcher::new();
+        watcher.set_paths(vec![file.clone()]);
+        assert!(watcher.poll().is_empty());
+
+        fs::write(&file, "print(12)").unwrap();
+        assert_eq!(watcher.poll(), vec![file.clone()]);
+        assert!(watcher.poll().is_empty());
+
+        fs::remove_file(&file).unwrap();
+        assert_eq!(watcher.poll(), vec![file.clone()]);
+        fs::write(&file, "print(123)").unwrap();
+        assert_eq!(watcher.poll(), vec![file.clone()]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_imported_files_are_watched() {
+        let source = "let a = @import(\"util.logos\")\nlet b = @import(\"https://example.com/lib\")\nlet c = @import(\"util.logos\")";
+        assert_eq!(imported_files(source, Path::new("src")), vec![PathBuf::from("src/util.logos")]);
+    }
+
+    #[test]
+    fn test_imports_are_watched_transitively() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b, c) = (dir.path().join("a.logos"), dir.path().join("lib").join("b.logos"), dir.path().join("lib").join("c.logos"));
+        fs::create_dir_all(dir.path().join("lib")).unwrap();
+        let a_source = "let b = @import(\"lib/b.logos\")";
+        fs::write(&a, a_source).unwrap();
+        fs::write(&b, "let c = @import(\"c.logos\")").unwrap();
+        // An import cycle back to the start is followed once
+        fs::write(&c, "let a = @import(\"../a.logos\")\nlet x = 1").unwrap();
+
+        let files = watched_files(&a, a_source);
+        assert_eq!(files.len(), 3, "{:?}", files);
+        assert!(files.contains(&c));
+
+        // Editing only c, two imports away, is seen as a change
+        let mut watcher = FileWatcher::new();
+        watcher.set_paths(files);
+        fs::write(&c, "let a = @import(\"../a.logos\")\nlet x = 12").unwrap();
+        assert_eq!(watcher.poll(), vec![c]);
+    }
+}